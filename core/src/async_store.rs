@@ -0,0 +1,106 @@
+use std::{
+	marker::PhantomData,
+	sync::{Arc, RwLock},
+};
+
+use tokio::task::{spawn_blocking, JoinError};
+
+use crate::store_interface::{StoreRead, StoreWrite};
+
+#[derive(Debug)]
+pub enum AsyncStoreError<E> {
+	Store(E),
+	Join(JoinError),
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for AsyncStoreError<E> {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			AsyncStoreError::Store(err) => write!(f, "store error: {err}"),
+			AsyncStoreError::Join(err) => write!(f, "blocking task failed: {err}"),
+		}
+	}
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for AsyncStoreError<E> {}
+
+pub type AsyncStoreResult<T, E> = Result<T, AsyncStoreError<E>>;
+
+/// Runs a synchronous store on tokio's blocking pool. Reads share the store, commits take it
+/// exclusively; clones refer to the same store.
+pub struct AsyncStore<S, K, V> {
+	inner: Arc<RwLock<S>>,
+	_ph: PhantomData<fn(K, V)>,
+}
+
+impl<S, K, V> Clone for AsyncStore<S, K, V> {
+	fn clone(&self) -> Self {
+		Self { inner: self.inner.clone(), _ph: PhantomData }
+	}
+}
+
+impl<S, K, V> AsyncStore<S, K, V>
+where
+	S: StoreRead<K, V> + Send + Sync + 'static,
+	S::Error: Send + 'static,
+	K: Send + 'static,
+	V: Send + 'static,
+{
+	pub fn new(store: S) -> Self {
+		Self { inner: Arc::new(RwLock::new(store)), _ph: PhantomData }
+	}
+
+	pub async fn get_value(&self, key: K) -> AsyncStoreResult<Option<V>, S::Error> {
+		let inner = self.inner.clone();
+		join(spawn_blocking(move || inner.read().unwrap().get_value(&key)).await)
+	}
+
+	pub async fn get_key_for_value(&self, value: V) -> AsyncStoreResult<Option<K>, S::Error> {
+		let inner = self.inner.clone();
+		join(spawn_blocking(move || inner.read().unwrap().get_key_for_value(&value)).await)
+	}
+
+	pub async fn get_keys_for_value(&self, value: V) -> AsyncStoreResult<Vec<K>, S::Error> {
+		let inner = self.inner.clone();
+		join(spawn_blocking(move || inner.read().unwrap().get_keys_for_value(&value)).await)
+	}
+}
+
+impl<S, K, V> AsyncStore<S, K, V>
+where
+	S: StoreWrite<K, V> + Send + Sync + 'static,
+	S::Error: Send + 'static,
+	K: Send + 'static,
+	V: Send + 'static,
+{
+	pub async fn commit(&self, items: Vec<(K, V)>) -> AsyncStoreResult<(), S::Error> {
+		let inner = self.inner.clone();
+		join(spawn_blocking(move || inner.write().unwrap().commit(items.iter().map(|(k, v)| (k, v)))).await)
+	}
+
+	pub async fn flush(&self) -> AsyncStoreResult<(), S::Error> {
+		let inner = self.inner.clone();
+		join(spawn_blocking(move || inner.write().unwrap().flush()).await)
+	}
+}
+
+fn join<T, E>(res: Result<Result<T, E>, JoinError>) -> AsyncStoreResult<T, E> {
+	res.map_err(AsyncStoreError::Join)?.map_err(AsyncStoreError::Store)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::store_tests::MemStore;
+
+	#[tokio::test]
+	async fn commit_then_get() {
+		let store = AsyncStore::new(MemStore::<String>::default());
+		let items = vec![(b"a".to_vec(), b"x".to_vec()), (b"b".to_vec(), b"x".to_vec()), (b"c".to_vec(), b"y".to_vec())];
+		store.commit(items).await.unwrap();
+		store.flush().await.unwrap();
+		assert_eq!(store.get_value(b"c".to_vec()).await.unwrap(), Some(b"y".to_vec()));
+		assert_eq!(store.get_value(b"d".to_vec()).await.unwrap(), None);
+		assert_eq!(store.get_keys_for_value(b"x".to_vec()).await.unwrap(), vec![b"a".to_vec(), b"b".to_vec()]);
+	}
+}
@@ -1,13 +1,46 @@
-use std::marker::PhantomData;
+use std::{borrow::Cow, marker::PhantomData};
 
 use crate::{
-	bench_common::{Address, Amount, Key, Timestamp, TxHash},
-	store_interface::StoreCodec,
+	bench_common::{Address, Amount, Key, Timestamp, TxHash, VarKey},
+	store_interface::{BorrowDecode, StoreCodec},
 };
 
-/// Supplies an error value for invalid input in codecs.
+/// Supplies an error value for input a codec cannot decode.
 pub trait InvalidInput<E> {
-	fn invalid_input(msg: &'static str) -> E;
+	fn invalid_input(err: DecodeError) -> E;
+}
+
+/// Leading input bytes a `DecodeError` keeps for its message.
+const DECODE_SNIPPET_BYTES: usize = 16;
+
+/// Input of the wrong length for a fixed-width codec, with what was actually read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodeError {
+	pub what: &'static str,
+	pub expected: usize,
+	pub got: usize,
+	/// The first `DECODE_SNIPPET_BYTES` bytes of the input.
+	pub head: Vec<u8>,
+}
+
+impl DecodeError {
+	pub fn new(what: &'static str, expected: usize, bytes: &[u8]) -> Self {
+		let head = bytes[..bytes.len().min(DECODE_SNIPPET_BYTES)].to_vec();
+		Self { what, expected, got: bytes.len(), head }
+	}
+}
+
+impl std::fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}: expected {} bytes, got {} (", self.what, self.expected, self.got)?;
+		for byte in &self.head {
+			write!(f, "{byte:02x}")?;
+		}
+		if self.got > self.head.len() {
+			write!(f, "..")?;
+		}
+		write!(f, ")")
+	}
 }
 
 pub struct KeyCodec<E, I>(PhantomData<(E, I)>);
@@ -15,15 +48,46 @@ pub struct AmountCodec<E, I>(PhantomData<(E, I)>);
 pub struct TxCodec<E, I>(PhantomData<(E, I)>);
 pub struct TimestampCodec<E, I>(PhantomData<(E, I)>);
 pub struct AddressCodec<E>(PhantomData<E>);
+pub struct VarKeyCodec<E>(PhantomData<E>);
+/// Raw bytes as they are, for stores driven through `DynStore`.
+pub struct BytesCodec<E>(PhantomData<E>);
+/// Tuple keys such as `(height, tx_index, output_index)`; components are big-endian and
+/// concatenated, so byte order matches tuple order.
+pub struct TupleCodec<E, I>(PhantomData<(E, I)>);
+
+/// Fixed-width unsigned integer usable as a `TupleCodec` component.
+pub trait TupleComponent: Sized {
+	const WIDTH: usize;
+	fn put_be(&self, out: &mut Vec<u8>);
+	fn from_be(bytes: &[u8]) -> Self;
+}
+
+macro_rules! tuple_component {
+	($($t:ty),*) => {$(
+		impl TupleComponent for $t {
+			const WIDTH: usize = std::mem::size_of::<$t>();
+			fn put_be(&self, out: &mut Vec<u8>) {
+				out.extend_from_slice(&self.to_be_bytes());
+			}
+			fn from_be(bytes: &[u8]) -> Self {
+				<$t>::from_be_bytes(bytes.try_into().expect("component width"))
+			}
+		}
+	)*};
+}
+
+tuple_component!(u8, u16, u32, u64);
 
 impl<E: 'static, I: InvalidInput<E> + 'static> StoreCodec<Key> for KeyCodec<E, I> {
 	type Error = E;
 	type Enc<'a> = [u8; 8] where E: 'a, I: 'a;
+	const EXPECTED_LEN: Option<usize> = Some(8);
 	fn encode<'a>(value: &'a Key) -> Self::Enc<'a> {
 		value.0.to_be_bytes()
 	}
 	fn decode(bytes: &[u8]) -> Result<Key, Self::Error> {
-		let arr: [u8; 8] = bytes.try_into().map_err(|_| I::invalid_input("bad key length"))?;
+		let arr: [u8; 8] =
+			bytes.try_into().map_err(|_| I::invalid_input(DecodeError::new("bad key length", 8, bytes)))?;
 		Ok(Key(u64::from_be_bytes(arr)))
 	}
 }
@@ -31,11 +95,12 @@ impl<E: 'static, I: InvalidInput<E> + 'static> StoreCodec<Key> for KeyCodec<E, I
 impl<E: 'static, I: InvalidInput<E> + 'static> StoreCodec<Amount> for AmountCodec<E, I> {
 	type Error = E;
 	type Enc<'a> = [u8; 8] where E: 'a, I: 'a;
+	const EXPECTED_LEN: Option<usize> = Some(8);
 	fn encode<'a>(value: &'a Amount) -> Self::Enc<'a> {
 		value.0.to_le_bytes()
 	}
 	fn decode(bytes: &[u8]) -> Result<Amount, Self::Error> {
-		let arr: [u8; 8] = bytes.try_into().map_err(|_| I::invalid_input("bad amount"))?;
+		let arr: [u8; 8] = bytes.try_into().map_err(|_| I::invalid_input(DecodeError::new("bad amount", 8, bytes)))?;
 		Ok(Amount(u64::from_le_bytes(arr)))
 	}
 }
@@ -43,11 +108,12 @@ impl<E: 'static, I: InvalidInput<E> + 'static> StoreCodec<Amount> for AmountCode
 impl<E: 'static, I: InvalidInput<E> + 'static> StoreCodec<TxHash> for TxCodec<E, I> {
 	type Error = E;
 	type Enc<'a> = &'a [u8] where E: 'a, I: 'a;
+	const EXPECTED_LEN: Option<usize> = Some(32);
 	fn encode<'a>(value: &'a TxHash) -> Self::Enc<'a> {
 		value.as_ref()
 	}
 	fn decode(bytes: &[u8]) -> Result<TxHash, Self::Error> {
-		let arr: [u8; 32] = bytes.try_into().map_err(|_| I::invalid_input("bad tx hash"))?;
+		let arr: [u8; 32] = bytes.try_into().map_err(|_| I::invalid_input(DecodeError::new("bad tx hash", 32, bytes)))?;
 		Ok(TxHash(arr))
 	}
 }
@@ -55,11 +121,12 @@ impl<E: 'static, I: InvalidInput<E> + 'static> StoreCodec<TxHash> for TxCodec<E,
 impl<E: 'static, I: InvalidInput<E> + 'static> StoreCodec<Timestamp> for TimestampCodec<E, I> {
 	type Error = E;
 	type Enc<'a> = [u8; 8] where E: 'a, I: 'a;
+	const EXPECTED_LEN: Option<usize> = Some(8);
 	fn encode<'a>(value: &'a Timestamp) -> Self::Enc<'a> {
 		value.0.to_le_bytes()
 	}
 	fn decode(bytes: &[u8]) -> Result<Timestamp, Self::Error> {
-		let arr: [u8; 8] = bytes.try_into().map_err(|_| I::invalid_input("bad timestamp"))?;
+		let arr: [u8; 8] = bytes.try_into().map_err(|_| I::invalid_input(DecodeError::new("bad timestamp", 8, bytes)))?;
 		Ok(Timestamp(u64::from_le_bytes(arr)))
 	}
 }
@@ -74,3 +141,176 @@ impl<E: 'static> StoreCodec<Address> for AddressCodec<E> {
 		Ok(Address(bytes.to_vec()))
 	}
 }
+
+impl<E: 'static> StoreCodec<VarKey> for VarKeyCodec<E> {
+	type Error = E;
+	type Enc<'a> = &'a [u8] where E: 'a;
+	fn encode<'a>(value: &'a VarKey) -> Self::Enc<'a> {
+		value.as_ref()
+	}
+	fn decode(bytes: &[u8]) -> Result<VarKey, Self::Error> {
+		Ok(VarKey(bytes.to_vec()))
+	}
+}
+
+impl<E: 'static> StoreCodec<Vec<u8>> for BytesCodec<E> {
+	type Error = E;
+	type Enc<'a> = &'a [u8] where E: 'a;
+	fn encode<'a>(value: &'a Vec<u8>) -> Self::Enc<'a> {
+		value.as_slice()
+	}
+	fn decode(bytes: &[u8]) -> Result<Vec<u8>, Self::Error> {
+		Ok(bytes.to_vec())
+	}
+}
+
+/// Address bytes are used as stored, so reads can borrow them from the backend's buffer.
+impl<E: 'static> BorrowDecode<Address> for AddressCodec<E> {
+	type Ref<'a> = Cow<'a, [u8]>;
+	fn decode_borrowed<'a>(bytes: &'a [u8]) -> Result<Self::Ref<'a>, E> {
+		Ok(Cow::Borrowed(bytes))
+	}
+}
+
+impl<E: 'static> BorrowDecode<VarKey> for VarKeyCodec<E> {
+	type Ref<'a> = Cow<'a, [u8]>;
+	fn decode_borrowed<'a>(bytes: &'a [u8]) -> Result<Self::Ref<'a>, E> {
+		Ok(Cow::Borrowed(bytes))
+	}
+}
+
+/// Fixed-width values are cheaper to copy out than to borrow, so they decode owned.
+macro_rules! decode_owned {
+	($($codec:ident => $t:ty),*) => {$(
+		impl<E: 'static, I: InvalidInput<E> + 'static> BorrowDecode<$t> for $codec<E, I> {
+			type Ref<'a> = $t;
+			fn decode_borrowed<'a>(bytes: &'a [u8]) -> Result<Self::Ref<'a>, E> {
+				Self::decode(bytes)
+			}
+		}
+	)*};
+}
+
+decode_owned!(KeyCodec => Key, AmountCodec => Amount, TxCodec => TxHash, TimestampCodec => Timestamp);
+
+impl<E: 'static, I: InvalidInput<E> + 'static, A: TupleComponent, B: TupleComponent> StoreCodec<(A, B)> for TupleCodec<E, I> {
+	type Error = E;
+	type Enc<'a> = Vec<u8> where E: 'a, I: 'a, A: 'a, B: 'a;
+	const EXPECTED_LEN: Option<usize> = Some(A::WIDTH + B::WIDTH);
+	fn encode<'a>(value: &'a (A, B)) -> Self::Enc<'a> {
+		let mut out = Vec::with_capacity(A::WIDTH + B::WIDTH);
+		value.0.put_be(&mut out);
+		value.1.put_be(&mut out);
+		out
+	}
+	fn decode(bytes: &[u8]) -> Result<(A, B), Self::Error> {
+		if bytes.len() != A::WIDTH + B::WIDTH {
+			return Err(I::invalid_input(DecodeError::new("bad tuple key length", A::WIDTH + B::WIDTH, bytes)))
+		}
+		let (a, b) = bytes.split_at(A::WIDTH);
+		Ok((A::from_be(a), B::from_be(b)))
+	}
+}
+
+impl<E: 'static, I: InvalidInput<E> + 'static, A: TupleComponent, B: TupleComponent, C: TupleComponent>
+	StoreCodec<(A, B, C)> for TupleCodec<E, I>
+{
+	type Error = E;
+	type Enc<'a> = Vec<u8> where E: 'a, I: 'a, A: 'a, B: 'a, C: 'a;
+	const EXPECTED_LEN: Option<usize> = Some(A::WIDTH + B::WIDTH + C::WIDTH);
+	fn encode<'a>(value: &'a (A, B, C)) -> Self::Enc<'a> {
+		let mut out = Vec::with_capacity(A::WIDTH + B::WIDTH + C::WIDTH);
+		value.0.put_be(&mut out);
+		value.1.put_be(&mut out);
+		value.2.put_be(&mut out);
+		out
+	}
+	fn decode(bytes: &[u8]) -> Result<(A, B, C), Self::Error> {
+		let width = A::WIDTH + B::WIDTH + C::WIDTH;
+		if bytes.len() != width {
+			return Err(I::invalid_input(DecodeError::new("bad tuple key length", width, bytes)))
+		}
+		let (a, rest) = bytes.split_at(A::WIDTH);
+		let (b, c) = rest.split_at(B::WIDTH);
+		Ok((A::from_be(a), B::from_be(b), C::from_be(c)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::store_interface::decode_exact;
+
+	struct Invalid;
+
+	impl InvalidInput<String> for Invalid {
+		fn invalid_input(err: DecodeError) -> String {
+			err.to_string()
+		}
+	}
+
+	type OutputCodec = TupleCodec<String, Invalid>;
+
+	#[test]
+	fn tuple_byte_order_matches_tuple_order() {
+		let mut keys: Vec<(u64, u32, u32)> = vec![
+			(2, 0, 0),
+			(1, u32::MAX, u32::MAX),
+			(1, 256, 0),
+			(1, 255, 7),
+			(0, 0, 1),
+			(1, 255, 256),
+			(u64::MAX, 0, 0),
+			(256, 0, 0),
+		];
+		let mut encoded: Vec<Vec<u8>> = keys.iter().map(OutputCodec::encode).collect();
+		keys.sort();
+		encoded.sort();
+		let decoded: Vec<(u64, u32, u32)> = encoded.iter().map(|b| OutputCodec::decode(b).unwrap()).collect();
+		assert_eq!(decoded, keys);
+	}
+
+	#[test]
+	fn borrowed_decode_points_into_the_read_buffer() {
+		let buf = b"bc1qaddress".to_vec();
+		let address = <AddressCodec<&'static str> as BorrowDecode<Address>>::decode_borrowed(&buf).unwrap();
+		assert!(matches!(address, Cow::Borrowed(view) if std::ptr::eq(view, buf.as_slice())));
+		let key_bytes = 42u64.to_be_bytes();
+		let key = <KeyCodec<String, Invalid> as BorrowDecode<Key>>::decode_borrowed(&key_bytes).unwrap();
+		assert_eq!(key.0, 42);
+		assert!(<KeyCodec<String, Invalid> as BorrowDecode<Key>>::decode_borrowed(&buf).is_err());
+	}
+
+	#[test]
+	fn tuple_roundtrip_and_length_check() {
+		let pair: (u32, u16) = (7, 9);
+		let bytes = <OutputCodec as StoreCodec<(u32, u16)>>::encode(&pair);
+		assert_eq!(bytes, [0, 0, 0, 7, 0, 9]);
+		assert_eq!(<OutputCodec as StoreCodec<(u32, u16)>>::decode(&bytes), Ok(pair));
+		assert!(<OutputCodec as StoreCodec<(u64, u32, u32)>>::decode(&bytes).is_err());
+	}
+
+	#[test]
+	fn decode_errors_show_the_bytes_read() {
+		let err = KeyCodec::<String, Invalid>::decode(&[0xde, 0xad, 0xbe]).unwrap_err();
+		assert_eq!(err, "bad key length: expected 8 bytes, got 3 (deadbe)");
+		let long = [0xabu8; 40];
+		let err = <TxCodec<String, Invalid> as StoreCodec<TxHash>>::decode(&long).unwrap_err();
+		assert!(err.starts_with("bad tx hash: expected 32 bytes, got 40 (abab"), "{err}");
+		assert!(err.ends_with("..)"), "{err}");
+	}
+
+	#[test]
+	fn exact_decode_rejects_bytes_of_another_width() {
+		let tx = TxHash([7; 32]);
+		let tx_bytes = <TxCodec<String, Invalid> as StoreCodec<TxHash>>::encode(&tx);
+		let err = decode_exact::<Key, KeyCodec<String, Invalid>>(tx_bytes, |msg| msg).unwrap_err();
+		assert!(err.starts_with("32 bytes where "), "{err}");
+		assert!(err.contains("Key expects 8"), "{err}");
+		let amount = AmountCodec::<String, Invalid>::encode(&Amount(5));
+		assert!(decode_exact::<TxHash, TxCodec<String, Invalid>>(&amount, |msg| msg).is_err());
+		assert_eq!(decode_exact::<Timestamp, TimestampCodec<String, Invalid>>(&amount, |msg| msg).unwrap().0, 5);
+		let address = decode_exact::<Address, AddressCodec<String>>(&amount, |msg| msg).unwrap();
+		assert_eq!(address.as_ref(), amount.as_slice(), "variable-length codecs take any length");
+	}
+}
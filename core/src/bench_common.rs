@@ -1,28 +1,121 @@
-use crate::store_interface::StoreWrite;
+use crate::store_interface::{ProgressEvent, ProgressTracker, Retryable, StoreRead, StoreWrite};
 use bech32::{ToBase32, Variant};
 use bs58;
 use crossbeam_channel::bounded;
-use rand::{rngs::StdRng, RngCore, SeedableRng};
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
 use std::{
+	collections::{BTreeMap, BTreeSet},
 	num::NonZeroUsize,
-	path::Path,
+	path::{Path, PathBuf},
 	sync::{
-		atomic::{AtomicU64, Ordering},
-		Arc,
+		atomic::{AtomicBool, AtomicU64, Ordering},
+		Arc, Mutex, RwLock,
 	},
-	thread,
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
 };
 
 pub const BATCH: usize = 20_000;
 
+/// Version bytes of the base58 addresses `AddressStream` generates, and `inspect` decodes.
+pub const P2PKH_VERSION: u8 = 0x00;
+pub const P2SH_VERSION: u8 = 0x05;
+/// Human-readable part of the generated bech32 addresses.
+pub const BECH32_HRP: &str = "bc";
+
+/// Batches `ingest_resumable` commits between two checkpoints.
+pub const CHECKPOINT_BATCHES: u64 = 50;
+
+/// Retries of a `run_mixed` commit that fails with a retryable error, e.g. a busy database.
+pub const MIXED_COMMIT_RETRIES: u32 = 5;
+
+/// A job handed the board of the `run_all_parallel` call running it, to report its progress to.
+pub type ProgressJob<E> = Box<dyn FnOnce(&ProgressBoard) -> Result<(), E> + Send>;
+
 pub struct NamedJob<E> {
-	pub name: &'static str,
-	pub job: Box<dyn FnOnce() -> Result<(), E> + Send>,
+	pub layout: BenchLayout,
+	pub job: ProgressJob<E>,
 }
 
-impl<E> NamedJob<E> {
-	pub fn new(name: &'static str, job: Box<dyn FnOnce() -> Result<(), E> + Send>) -> Self {
-		Self { name, job }
+impl<E: 'static> NamedJob<E> {
+	/// A job that reports no progress.
+	pub fn new(layout: BenchLayout, job: Box<dyn FnOnce() -> Result<(), E> + Send>) -> Self {
+		Self { layout, job: Box::new(move |_| job()) }
+	}
+
+	pub fn with_progress(layout: BenchLayout, job: ProgressJob<E>) -> Self {
+		Self { layout, job }
+	}
+}
+
+/// One of the stores a bench binary writes, each under its own directory of the bench base.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BenchLayout {
+	Plain,
+	PlainVarkey,
+	Index,
+	Range,
+	Dictionary,
+}
+
+impl BenchLayout {
+	pub const ALL: [BenchLayout; 5] =
+		[BenchLayout::Plain, BenchLayout::PlainVarkey, BenchLayout::Index, BenchLayout::Range, BenchLayout::Dictionary];
+
+	/// The job's name and its store's directory under the bench base.
+	pub fn name(self) -> &'static str {
+		match self {
+			BenchLayout::Plain => "plain",
+			BenchLayout::PlainVarkey => "plain_varkey",
+			BenchLayout::Index => "index",
+			BenchLayout::Range => "range",
+			BenchLayout::Dictionary => "dictionary",
+		}
+	}
+
+	pub fn parse(name: &str) -> Option<Self> {
+		Self::ALL.into_iter().find(|layout| layout.name() == name)
+	}
+}
+
+/// The layouts a run is limited to, from `--layouts`; all of them by default. Jobs of the others
+/// never run and their directories are left alone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayoutSet(BTreeSet<BenchLayout>);
+
+impl Default for LayoutSet {
+	fn default() -> Self {
+		Self(BenchLayout::ALL.into_iter().collect())
+	}
+}
+
+impl LayoutSet {
+	/// Parses a comma-separated list of layout names, case-insensitively; `all` selects every
+	/// layout. Unknown names are an error rather than silently selecting nothing.
+	pub fn parse(list: &str) -> Result<Self, String> {
+		let mut layouts = BTreeSet::new();
+		for name in list.split(',').map(|name| name.trim().to_ascii_lowercase()).filter(|name| !name.is_empty()) {
+			if name == "all" || name == "all_in_par" {
+				return Ok(Self::default())
+			}
+			let layout = BenchLayout::parse(&name).ok_or_else(|| {
+				let known: Vec<&str> = BenchLayout::ALL.iter().map(|layout| layout.name()).collect();
+				format!("unknown layout {name:?}, expected one of {} or all", known.join(", "))
+			})?;
+			layouts.insert(layout);
+		}
+		if layouts.is_empty() {
+			return Err("no layout selected".to_string())
+		}
+		Ok(Self(layouts))
+	}
+
+	pub fn contains(&self, layout: BenchLayout) -> bool {
+		self.0.contains(&layout)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = BenchLayout> + '_ {
+		self.0.iter().copied()
 	}
 }
 
@@ -35,6 +128,29 @@ impl Key {
 	}
 }
 
+/// Variable-length key, e.g. a script or an address used as a key.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct VarKey(pub Vec<u8>);
+impl AsRef<[u8]> for VarKey {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+/// Length range of generated `VarKey`s; each length is drawn uniformly from `min..=max`.
+#[derive(Clone, Copy, Debug)]
+pub struct KeyLengths {
+	pub min: usize,
+	pub max: usize,
+}
+
+impl Default for KeyLengths {
+	fn default() -> Self {
+		// P2PKH/P2WPKH scripts up to P2TR and short multisig ones.
+		Self { min: 22, max: 71 }
+	}
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct Amount(pub u64);
 
@@ -57,161 +173,1054 @@ impl AsRef<[u8]> for Address {
 	}
 }
 
-pub fn run_plain<S, F>(base: &Path, total: u64, factory: F) -> Result<(), S::Error>
+/// Bounds on the rows `ingest` holds while the store commits.
+#[derive(Clone, Copy, Debug)]
+pub struct IngestConfig {
+	/// Full batches that may queue for the store besides the one it is committing and the one
+	/// being filled; generation blocks once they are all taken.
+	pub max_in_flight: usize,
+	/// Stops at the first batch boundary this long after the start, rows left or not.
+	pub stop_after: Option<Duration>,
+}
+
+impl Default for IngestConfig {
+	fn default() -> Self {
+		Self { max_in_flight: 1, stop_after: None }
+	}
+}
+
+/// How much a `run_*` bench ingests: `--total` rows, or as many as `--duration` of wall-clock
+/// time allows, which shows sustained throughput with compaction included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RunLength {
+	Rows(u64),
+	Duration(Duration),
+	/// `Rows` that checkpoints as it goes and picks up after the checkpoint an interrupted run
+	/// left, from `--resume`; see `ingest_resumable`.
+	Resumable(u64),
+}
+
+impl RunLength {
+	/// `Rows(total)` unless a `duration` is given.
+	pub fn new(total: u64, duration: Option<Duration>) -> Self {
+		duration.map_or(RunLength::Rows(total), RunLength::Duration)
+	}
+
+	/// Rows to generate; a duration run never runs out of them.
+	fn rows(self) -> u64 {
+		match self {
+			RunLength::Rows(total) | RunLength::Resumable(total) => total,
+			RunLength::Duration(_) => u64::MAX,
+		}
+	}
+
+	/// The total progress reports count towards, 0 when unknown.
+	fn progress_total(self) -> u64 {
+		match self {
+			RunLength::Rows(total) | RunLength::Resumable(total) => total,
+			RunLength::Duration(_) => 0,
+		}
+	}
+
+	fn ingest_config(self) -> IngestConfig {
+		match self {
+			RunLength::Rows(_) | RunLength::Resumable(_) => IngestConfig::default(),
+			RunLength::Duration(duration) => IngestConfig { stop_after: Some(duration), ..IngestConfig::default() },
+		}
+	}
+}
+
+/// Parses the `--duration` flag: whole milliseconds, seconds, minutes or hours, e.g. `500ms` or `60s`.
+pub fn parse_duration(text: &str) -> Option<Duration> {
+	let split = text.find(|c: char| !c.is_ascii_digit())?;
+	let value: u64 = text[..split].parse().ok()?;
+	let secs = match &text[split..] {
+		"ms" => return Some(Duration::from_millis(value)),
+		"s" => 1,
+		"m" => 60,
+		"h" => 3600,
+		_ => return None,
+	};
+	Some(Duration::from_secs(value.checked_mul(secs)?))
+}
+
+/// Generates `rows` on a separate thread while committing them to `store` in batches of `batch`
+/// rows, so at most `(config.max_in_flight + 2) * batch` rows are held at once however slow the
+/// store is. Returns the number of rows committed, which with `config.stop_after` may be fewer
+/// than `rows` holds.
+pub fn ingest<K, V, S, I>(rows: I, store: &mut S, batch: usize, config: IngestConfig) -> Result<u64, S::Error>
+where
+	S: StoreWrite<K, V>,
+	I: IntoIterator<Item = (K, V)>,
+	I::IntoIter: Send,
+	K: Send,
+	V: Send,
+{
+	ingest_batches(rows, store, batch, config, |_| (), |_, _, ()| Ok(()))
+}
+
+/// `ingest`, handing `committed` the store, the rows committed so far and the `mark` of the last
+/// key after every batch.
+fn ingest_batches<K, V, S, I, M>(
+	rows: I,
+	store: &mut S,
+	batch: usize,
+	config: IngestConfig,
+	mark: impl Fn(&K) -> M,
+	mut committed: impl FnMut(&mut S, u64, M) -> Result<(), S::Error>,
+) -> Result<u64, S::Error>
+where
+	S: StoreWrite<K, V>,
+	I: IntoIterator<Item = (K, V)>,
+	I::IntoIter: Send,
+	K: Send,
+	V: Send,
+{
+	let batch = batch.max(1);
+	let (tx, rx) = bounded::<Vec<(K, V)>>(config.max_in_flight);
+	let mut rows = rows.into_iter();
+	thread::scope(|scope| {
+		scope.spawn(move || {
+			loop {
+				let chunk: Vec<(K, V)> = rows.by_ref().take(batch).collect();
+				// An empty chunk means the rows ran out; a failed send, that the store failed.
+				if chunk.is_empty() || tx.send(chunk).is_err() {
+					break
+				}
+			}
+		});
+		// Owned here so an early return drops it and unblocks the generator before the join.
+		let rx = rx;
+		let start = Instant::now();
+		let mut inserted = 0;
+		for chunk in rx.iter() {
+			if config.stop_after.is_some_and(|limit| start.elapsed() >= limit) {
+				break
+			}
+			inserted += chunk.len() as u64;
+			let last = chunk.last().map(|(key, _)| mark(key));
+			store.commit_owned(chunk)?;
+			if let Some(last) = last {
+				committed(store, inserted, last)?;
+			}
+		}
+		Ok(inserted)
+	})
+}
+
+/// How far an `ingest_resumable` got, saved in a small file beside the store: the rows committed
+/// and flushed, and the encoding of the last of their keys.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+	pub rows_committed: u64,
+	pub last_key: Vec<u8>,
+}
+
+impl Checkpoint {
+	/// The checkpoint file of the store at `store_path`, e.g. `plain.checkpoint` beside `plain`.
+	pub fn path(store_path: &Path) -> PathBuf {
+		store_path.with_extension("checkpoint")
+	}
+
+	/// The rows as a decimal line, then the key as a hex line.
+	pub fn save(&self, path: &Path) -> std::io::Result<()> {
+		let key: String = self.last_key.iter().map(|b| format!("{b:02x}")).collect();
+		// Written aside and renamed over the old one, so a crash never leaves half a checkpoint.
+		let tmp = path.with_extension("checkpoint.tmp");
+		let mut file = std::fs::File::create(&tmp)?;
+		std::io::Write::write_all(&mut file, format!("{}\n{key}\n", self.rows_committed).as_bytes())?;
+		file.sync_all()?;
+		std::fs::rename(&tmp, path)
+	}
+
+	/// The checkpoint at `path`, `None` if there is none or it does not parse.
+	pub fn load(path: &Path) -> Option<Self> {
+		let text = std::fs::read_to_string(path).ok()?;
+		let mut lines = text.lines();
+		let rows_committed = lines.next()?.parse().ok()?;
+		let key = lines.next()?;
+		if key.len() % 2 != 0 {
+			return None
+		}
+		let last_key = (0..key.len())
+			.step_by(2)
+			.map(|i| u8::from_str_radix(key.get(i..i + 2)?, 16).ok())
+			.collect::<Option<Vec<u8>>>()?;
+		Some(Self { rows_committed, last_key })
+	}
+}
+
+/// `ingest` of `rows` that a crash does not send back to the start. Every `every` batches it
+/// flushes the store and saves a `Checkpoint` at `checkpoint`; on start it skips the rows of the
+/// checkpoint found there, once the store's `last_key` shows it holds them. `rows` must be the
+/// same sequence on every run and ascending in the order `key_bytes`, the key codec's encoding,
+/// gives. Rows committed after the last checkpoint are committed again, so a backend that hashes
+/// its keys, and does not `orders_keys`, must take a re-commit as an overwrite. Returns the rows
+/// committed, those skipped included, or the error of a failed `last_key`; the checkpoint stays
+/// until `finish_checkpoint`.
+pub fn ingest_resumable<K, V, S, I>(
+	rows: I,
+	store: &mut S,
+	batch: usize,
+	checkpoint: &Path,
+	every: u64,
+	key_bytes: impl Fn(&K) -> Vec<u8>,
+) -> Result<u64, S::Error>
+where
+	S: StoreWrite<K, V>,
+	I: IntoIterator<Item = (K, V)>,
+	I::IntoIter: Send,
+	K: Send,
+	V: Send,
+{
+	let resumed = resume_point(store, checkpoint, &key_bytes)?;
+	let every = every.max(1);
+	let mut batches = 0u64;
+	let rows = rows.into_iter().skip(resumed as usize);
+	let inserted = ingest_batches(rows, store, batch, IngestConfig::default(), key_bytes, |store, inserted, last_key| {
+		batches += 1;
+		if batches.is_multiple_of(every) {
+			// Flushed first, so the checkpoint never claims rows a crash could still lose.
+			store.flush()?;
+			let saved = Checkpoint { rows_committed: resumed + inserted, last_key }.save(checkpoint);
+			if let Err(err) = saved {
+				// A resume then starts from the previous checkpoint, which is slower but as correct.
+				eprintln!("{}: checkpoint not saved: {err}", checkpoint.display());
+			}
+		}
+		Ok(())
+	})?;
+	Ok(resumed + inserted)
+}
+
+/// Rows of the checkpoint at `checkpoint` the store provably holds, 0 without one. A failed
+/// `last_key` of a store that orders its keys is returned rather than taken for a match.
+fn resume_point<K, V, S>(store: &S, checkpoint: &Path, key_bytes: impl Fn(&K) -> Vec<u8>) -> Result<u64, S::Error>
+where
+	S: StoreRead<K, V>,
+{
+	let Some(saved) = Checkpoint::load(checkpoint) else { return Ok(0) };
+	// Hashing backends cannot order their keys; the flush before every checkpoint vouches for it.
+	if store.orders_keys() {
+		match store.last_key()? {
+			Some(last) if key_bytes(&last) >= saved.last_key => {},
+			_ => {
+				println!("{}: the store lacks rows its checkpoint counts, starting over", checkpoint.display());
+				return Ok(0)
+			},
+		}
+	}
+	println!("{}: resuming after {} rows", checkpoint.display(), saved.rows_committed);
+	Ok(saved.rows_committed)
+}
+
+/// Removes the checkpoint of a run that got all its rows in and flushed.
+pub fn finish_checkpoint(checkpoint: &Path) {
+	std::fs::remove_file(checkpoint).ok();
+}
+
+pub fn run_plain<S, F>(base: &Path, length: RunLength, board: &ProgressBoard, factory: F) -> Result<(), S::Error>
 where
 	S: StoreWrite<Key, Amount>,
 	F: Fn(&Path) -> Result<S, S::Error>,
 {
 	let path = base.join("plain");
 	let mut store = factory(&path)?;
-	store.set_progress("plain", total);
-	let mut _inserted: u64 = 0;
-	let mut batch: Vec<(Key, Amount)> = Vec::with_capacity(BATCH);
-	for i in 0..total {
-		batch.push((make_key(i), Amount(i)));
-		if batch.len() >= BATCH {
-			store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-			batch.clear();
-			_inserted += BATCH as u64;
-		}
-	}
-	if !batch.is_empty() {
-		store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-		_inserted += batch.len() as u64;
-	}
-	store.flush()?;
+	store.set_progress_tracker(board.tracker("plain", length.progress_total()));
+	timed_ingest(&path, (0..length.rows()).map(|i| (make_key(i), Amount(i))), &mut store, length, key_bytes)?;
+	board.finish("plain");
+	final_flush(&path, &mut store, length)?;
+	report_disk_usage(&path);
+	Ok(())
+}
+
+pub fn run_plain_varkey<S, F>(
+	base: &Path,
+	length: RunLength,
+	lengths: KeyLengths,
+	board: &ProgressBoard,
+	factory: F,
+) -> Result<(), S::Error>
+where
+	S: StoreWrite<VarKey, Amount>,
+	F: Fn(&Path) -> Result<S, S::Error>,
+{
+	let path = base.join("plain_varkey");
+	let mut store = factory(&path)?;
+	store.set_progress_tracker(board.tracker("plain_varkey", length.progress_total()));
+	let mut rng = StdRng::seed_from_u64(3);
+	let rows = (0..length.rows()).map(move |i| (make_var_key(i, lengths, &mut rng), Amount(i)));
+	timed_ingest(&path, rows, &mut store, length, |key: &VarKey| key.0.clone())?;
+	board.finish("plain_varkey");
+	final_flush(&path, &mut store, length)?;
+	report_disk_usage(&path);
 	Ok(())
 }
 
-pub fn run_index<S, F>(base: &Path, total: u64, factory: F) -> Result<(), S::Error>
+pub fn run_index<S, F>(base: &Path, length: RunLength, board: &ProgressBoard, factory: F) -> Result<(), S::Error>
 where
 	S: StoreWrite<Key, TxHash>,
 	F: Fn(&Path) -> Result<S, S::Error>,
 {
 	let path = base.join("index");
 	let mut store = factory(&path)?;
-	store.set_progress("index", total);
+	store.set_progress_tracker(board.tracker("index", length.progress_total()));
 	let mut rng = StdRng::seed_from_u64(1);
-	let mut _inserted: u64 = 0;
-	let mut batch: Vec<(Key, TxHash)> = Vec::with_capacity(BATCH);
-	for i in 0..total {
-		let k = make_key(i);
+	let rows = (0..length.rows()).map(move |i| {
 		let mut h = [0u8; 32];
 		rng.fill_bytes(&mut h);
-		batch.push((k, TxHash(h)));
-		if batch.len() >= BATCH {
-			store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-			batch.clear();
-			_inserted += BATCH as u64;
-		}
-	}
-	if !batch.is_empty() {
-		store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-		_inserted += batch.len() as u64;
-	}
-	store.flush()?;
+		(make_key(i), TxHash(h))
+	});
+	timed_ingest(&path, rows, &mut store, length, key_bytes)?;
+	board.finish("index");
+	final_flush(&path, &mut store, length)?;
+	report_disk_usage(&path);
 	Ok(())
 }
 
-pub fn run_range<S, F>(base: &Path, total: u64, factory: F) -> Result<(), S::Error>
+pub fn run_range<S, F>(base: &Path, length: RunLength, board: &ProgressBoard, factory: F) -> Result<(), S::Error>
 where
 	S: StoreWrite<Key, Timestamp>,
 	F: Fn(&Path) -> Result<S, S::Error>,
 {
 	let path = base.join("range");
 	let mut store = factory(&path)?;
-	store.set_progress("range", total);
-	let mut _inserted: u64 = 0;
-	let mut batch: Vec<(Key, Timestamp)> = Vec::with_capacity(BATCH);
-	for i in 0..total {
-		batch.push((make_key(i), Timestamp(i)));
-		if batch.len() >= BATCH {
-			store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-			batch.clear();
-			_inserted += BATCH as u64;
-		}
-	}
-	if !batch.is_empty() {
-		store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-		_inserted += batch.len() as u64;
-	}
-	store.flush()?;
+	store.set_progress_tracker(board.tracker("range", length.progress_total()));
+	timed_ingest(&path, (0..length.rows()).map(|i| (make_key(i), Timestamp(i))), &mut store, length, key_bytes)?;
+	board.finish("range");
+	final_flush(&path, &mut store, length)?;
+	report_disk_usage(&path);
 	Ok(())
 }
 
-pub fn run_dictionary<S, F>(base: &Path, total: u64, factory: F) -> Result<(), S::Error>
+pub fn run_dictionary<S, F>(base: &Path, length: RunLength, board: &ProgressBoard, factory: F) -> Result<(), S::Error>
 where
 	S: StoreWrite<Key, Address>,
 	F: Fn(&Path) -> Result<S, S::Error>,
 {
 	let path = base.join("dictionary");
 	let mut store = factory(&path)?;
-	store.set_progress("dictionary", total);
-	let mut stream = AddressStream::new(total, 2);
-	let mut _inserted: u64 = 0;
-	let mut batch: Vec<(Key, Address)> = Vec::with_capacity(BATCH);
-	for i in 0..total {
-		if let Some(v) = stream.next() {
-			let k = make_key(i);
-			batch.push((k, v));
+	store.set_progress_tracker(board.tracker("dictionary", length.progress_total()));
+	// Generated addresses differ from run to run, so rows committed again after a checkpoint would
+	// not match the first ones; a resumed dictionary run starts over instead.
+	let length = match length {
+		RunLength::Resumable(total) => {
+			store.clear()?;
+			RunLength::Rows(total)
+		},
+		length => length,
+	};
+	let mut stream = AddressStream::new(length.rows(), 2);
+	let rows = (0..length.rows()).zip(stream.by_ref()).map(|(i, v)| (make_key(i), v));
+	timed_ingest(&path, rows, &mut store, length, key_bytes)?;
+	board.finish("dictionary");
+	stream.join();
+	final_flush(&path, &mut store, length)?;
+	report_disk_usage(&path);
+	Ok(())
+}
+
+/// `ingest` of `rows` per `length`, reporting the rows committed and their rate over the whole run.
+/// A `Resumable` run goes through `ingest_resumable`, with `key_bytes` as its key encoding.
+fn timed_ingest<K, V, S, I>(
+	path: &Path,
+	rows: I,
+	store: &mut S,
+	length: RunLength,
+	key_bytes: impl Fn(&K) -> Vec<u8>,
+) -> Result<u64, S::Error>
+where
+	S: StoreWrite<K, V>,
+	I: IntoIterator<Item = (K, V)>,
+	I::IntoIter: Send,
+	K: Send,
+	V: Send,
+{
+	let start = Instant::now();
+	let inserted = match length {
+		RunLength::Resumable(_) => {
+			ingest_resumable(rows, store, BATCH, &Checkpoint::path(path), CHECKPOINT_BATCHES, key_bytes)?
+		},
+		_ => ingest(rows, store, BATCH, length.ingest_config())?,
+	};
+	let elapsed = start.elapsed();
+	println!("{}: ingested {inserted} rows in {elapsed:.2?}, {:.0} rows/s", path.display(), ops_per_sec(inserted, elapsed));
+	Ok(inserted)
+}
+
+/// Big-endian, as `KeyCodec` encodes bench keys.
+fn key_bytes(key: &Key) -> Vec<u8> {
+	key.to_be_bytes().to_vec()
+}
+
+/// Flushes what the ingest left in memory, reporting it apart from the commit loop; a `Resumable`
+/// run is complete then and drops its checkpoint.
+fn final_flush<K, V, S: StoreWrite<K, V>>(path: &Path, store: &mut S, length: RunLength) -> Result<(), S::Error> {
+	let start = Instant::now();
+	let stats = store.flush_reporting()?;
+	println!(
+		"{}: final flush of {} rows into {} segments ({:.1} MiB) in {:.2?}",
+		path.display(),
+		stats.rows_flushed,
+		stats.segments_written,
+		stats.bytes_flushed as f64 / (1024.0 * 1024.0),
+		start.elapsed()
+	);
+	if let RunLength::Resumable(_) = length {
+		finish_checkpoint(&Checkpoint::path(path));
+	}
+	Ok(())
+}
+
+/// Reopens the store a `run_*` bench wrote under `base/name` and settles it for reads, reporting
+/// the time taken and the settled size.
+pub fn run_settle<K, V, S, F>(base: &Path, name: &str, factory: F) -> Result<(), S::Error>
+where
+	S: StoreWrite<K, V>,
+	F: Fn(&Path) -> Result<S, S::Error>,
+{
+	let path = base.join(name);
+	let mut store = factory(&path)?;
+	let start = Instant::now();
+	store.settle_for_reads()?;
+	println!("{name}: settled for reads in {:.2?}", start.elapsed());
+	report_disk_usage(&path);
+	Ok(())
+}
+
+/// Which of the committed keys a read benchmark looks up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyDistribution {
+	#[default]
+	Uniform,
+	/// The `i`-th key is drawn with probability proportional to `1 / (i + 1)^theta`, so a few of
+	/// the first keys written take most of the reads.
+	Zipfian,
+	/// Zipfian over recency: the last keys written are the hot ones.
+	Latest,
+}
+
+impl KeyDistribution {
+	/// Parses the `--distribution` flag: `uniform`, `zipfian` or `latest`.
+	pub fn parse(name: &str) -> Option<Self> {
+		match name {
+			"uniform" => Some(KeyDistribution::Uniform),
+			"zipfian" => Some(KeyDistribution::Zipfian),
+			"latest" => Some(KeyDistribution::Latest),
+			_ => None,
 		}
-		if batch.len() >= BATCH {
-			store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-			batch.clear();
-			_inserted += BATCH as u64;
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			KeyDistribution::Uniform => "uniform",
+			KeyDistribution::Zipfian => "zipfian",
+			KeyDistribution::Latest => "latest",
 		}
 	}
-	if !batch.is_empty() {
-		store.commit(batch.iter().map(|(k, v)| (k, v)))?;
-		_inserted += batch.len() as u64;
+}
+
+/// Settings of `run_reads`, set from the `--reads`, `--distribution` and `--theta` flags.
+#[derive(Clone, Copy, Debug)]
+pub struct ReadLoad {
+	/// Lookups to time; 0 skips the read benchmark.
+	pub reads: u64,
+	pub distribution: KeyDistribution,
+	/// Skew of the zipfian and latest distributions, in (0, 1); YCSB uses 0.99.
+	pub theta: f64,
+}
+
+impl Default for ReadLoad {
+	fn default() -> Self {
+		Self { reads: 1_000_000, distribution: KeyDistribution::default(), theta: 0.99 }
 	}
-	stream.join();
-	store.flush()?;
-	Ok(())
 }
 
-pub fn run_all_parallel<E>(jobs: Vec<NamedJob<E>>, selected: &[String]) -> Result<(), E>
+/// Draws key indices in `0..n` per a `KeyDistribution`. Skewed draws use the YCSB zipfian
+/// generator (Gray et al., "Quickly generating billion-record synthetic databases"): O(n) to set
+/// up, O(1) per draw.
+pub struct KeyChooser {
+	n: u64,
+	distribution: KeyDistribution,
+	rng: StdRng,
+	zipf: Option<Zipf>,
+}
+
+struct Zipf {
+	theta: f64,
+	alpha: f64,
+	zeta_n: f64,
+	eta: f64,
+}
+
+impl KeyChooser {
+	pub fn new(n: u64, distribution: KeyDistribution, theta: f64, seed: u64) -> Self {
+		assert!(n > 0, "no keys to choose from");
+		let zipf = (distribution != KeyDistribution::Uniform).then(|| {
+			assert!(theta > 0.0 && theta < 1.0, "theta {theta} is outside (0, 1)");
+			let zeta = |count: u64| (1..=count).map(|i| 1.0 / (i as f64).powf(theta)).sum::<f64>();
+			let zeta_n = zeta(n);
+			let eta = (1.0 - (2.0 / n as f64).powf(1.0 - theta)) / (1.0 - zeta(2) / zeta_n);
+			Zipf { theta, alpha: 1.0 / (1.0 - theta), zeta_n, eta }
+		});
+		Self { n, distribution, rng: StdRng::seed_from_u64(seed), zipf }
+	}
+
+	pub fn next_index(&mut self) -> u64 {
+		let Some(zipf) = &self.zipf else {
+			return self.rng.random_range(0..self.n)
+		};
+		let u: f64 = self.rng.random();
+		let uz = u * zipf.zeta_n;
+		let rank = if uz < 1.0 {
+			0
+		} else if uz < 1.0 + 0.5f64.powf(zipf.theta) {
+			1
+		} else {
+			((self.n as f64 * (zipf.eta * u - zipf.eta + 1.0).powf(zipf.alpha)) as u64).min(self.n - 1)
+		};
+		match self.distribution {
+			KeyDistribution::Latest => self.n - 1 - rank,
+			_ => rank,
+		}
+	}
+}
+
+/// Looks up keys of the `plain` store a `run_plain` of `length` wrote, drawn per `load`,
+/// timing each lookup, and reports throughput and latency percentiles.
+pub fn run_reads<S, F>(base: &Path, length: RunLength, load: ReadLoad, factory: F) -> Result<(), S::Error>
 where
-	E: Send + 'static,
+	S: StoreWrite<Key, Amount>,
+	F: Fn(&Path) -> Result<S, S::Error>,
 {
-	let selected: Vec<String> = selected.iter().map(|s| s.to_ascii_lowercase()).collect();
-	let run_all = selected.is_empty()
-		|| selected.iter().any(|s| s == "all" || s == "all_in_par");
+	if load.reads == 0 {
+		return Ok(())
+	}
+	let store = factory(&base.join("plain"))?;
+	let total = match length {
+		RunLength::Rows(total) | RunLength::Resumable(total) => total,
+		RunLength::Duration(_) => count_rows(&store)?,
+	};
+	if total == 0 {
+		return Ok(())
+	}
+	let mut chooser = KeyChooser::new(total, load.distribution, load.theta, 4);
+	let mut latencies = Vec::with_capacity(load.reads as usize);
+	let mut hits = 0u64;
+	let start = Instant::now();
+	for _ in 0..load.reads {
+		let key = make_key(chooser.next_index());
+		let read = Instant::now();
+		hits += store.get_value(&key)?.is_some() as u64;
+		latencies.push(read.elapsed());
+	}
+	let elapsed = start.elapsed();
+	println!(
+		"reads ({}): {} in {elapsed:.2?}, {:.0} ops/s, {hits} hits, {}",
+		key_label(load),
+		load.reads,
+		ops_per_sec(load.reads, elapsed),
+		percentiles(&mut latencies),
+	);
+	Ok(())
+}
 
-	let filtered = if run_all {
-		jobs
-	} else {
-		jobs
-			.into_iter()
-			.filter(|j| selected.iter().any(|s| s == j.name))
-			.collect()
+/// What a `run_dictionary_reads` did: point lookups and the values they found, then reverse
+/// lookups of those values and how many listed the key the value was found under.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DictionaryReadReport {
+	pub reads: u64,
+	pub hits: u64,
+	pub reverse_reads: u64,
+	pub reverse_hits: u64,
+}
+
+/// Looks up keys of the `dictionary` store a `run_dictionary` of `length` wrote, drawn per `load`,
+/// then the keys of every value found, and reports each pass on its own. A point lookup takes two
+/// reads, key to birth key and birth key to value, where `run_reads` of the plain store takes one;
+/// a reverse lookup resolves the value's birth key and lists the keys under it.
+pub fn run_dictionary_reads<S, F>(
+	base: &Path,
+	length: RunLength,
+	load: ReadLoad,
+	factory: F,
+) -> Result<DictionaryReadReport, S::Error>
+where
+	S: StoreWrite<Key, Address>,
+	F: Fn(&Path) -> Result<S, S::Error>,
+{
+	if load.reads == 0 {
+		return Ok(DictionaryReadReport::default())
+	}
+	let path = base.join("dictionary");
+	let store = factory(&path)?;
+	let total = match length {
+		RunLength::Rows(total) | RunLength::Resumable(total) => total,
+		RunLength::Duration(_) => count_rows(&store)?,
 	};
+	if total == 0 {
+		return Ok(DictionaryReadReport::default())
+	}
+	let mut chooser = KeyChooser::new(total, load.distribution, load.theta, 6);
+	let mut latencies = Vec::with_capacity(load.reads as usize);
+	let mut found = Vec::new();
+	let start = Instant::now();
+	for _ in 0..load.reads {
+		let key = make_key(chooser.next_index());
+		let read = Instant::now();
+		let value = store.get_value(&key)?;
+		latencies.push(read.elapsed());
+		found.extend(value.map(|value| (key, value)));
+	}
+	let elapsed = start.elapsed();
+	let hits = found.len() as u64;
+	println!(
+		"{}: get_value ({}): {} in {elapsed:.2?}, {:.0} ops/s, {hits} hits, {}",
+		path.display(),
+		key_label(load),
+		load.reads,
+		ops_per_sec(load.reads, elapsed),
+		percentiles(&mut latencies),
+	);
+
+	latencies.clear();
+	let mut reverse_hits = 0u64;
+	let start = Instant::now();
+	for (key, value) in &found {
+		let read = Instant::now();
+		let keys = store.get_keys_for_value(value)?;
+		latencies.push(read.elapsed());
+		reverse_hits += keys.contains(key) as u64;
+	}
+	let elapsed = start.elapsed();
+	if hits > 0 {
+		println!(
+			"{}: get_keys_for_value: {hits} in {elapsed:.2?}, {:.0} ops/s, {reverse_hits} listed their key, {}",
+			path.display(),
+			ops_per_sec(hits, elapsed),
+			percentiles(&mut latencies),
+		);
+	}
+	Ok(DictionaryReadReport { reads: load.reads, hits, reverse_reads: hits, reverse_hits })
+}
+
+/// The keys a read benchmark draws, for its report.
+fn key_label(load: ReadLoad) -> String {
+	match load.distribution {
+		KeyDistribution::Uniform => "uniform".to_string(),
+		skewed => format!("{}, theta {}", skewed.name(), load.theta),
+	}
+}
+
+/// Median and tail of `latencies`, which it sorts; there must be at least one.
+fn percentiles(latencies: &mut [Duration]) -> String {
+	latencies.sort_unstable();
+	let at = |q: f64| latencies[((latencies.len() - 1) as f64 * q) as usize];
+	format!("p50 {:.2?} p99 {:.2?} p99.9 {:.2?}", at(0.5), at(0.99), at(0.999))
+}
 
-	let handles = filtered.into_iter().map(|j| thread::spawn(j.job)).collect::<Vec<_>>();
+/// Settings of `run_mixed`, set from the `--mixed <writers>:<readers>` flag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MixedLoad {
+	/// Threads committing batches of `BATCH` rows; each batch takes the store exclusively.
+	pub writers: usize,
+	/// Threads looking up keys already committed, sharing the store between commits.
+	pub readers: usize,
+}
+
+impl MixedLoad {
+	/// Parses `<writers>:<readers>`, e.g. `1:4`; at least one writer, any number of readers.
+	pub fn parse(text: &str) -> Option<Self> {
+		let (writers, readers) = text.split_once(':')?;
+		let load = MixedLoad { writers: writers.trim().parse().ok()?, readers: readers.trim().parse().ok()? };
+		(load.writers > 0).then_some(load)
+	}
+}
+
+/// What a `run_mixed` did: rows committed and lookups made while they were.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MixedReport {
+	pub rows: u64,
+	pub reads: u64,
+	pub hits: u64,
+}
+
+/// Writes a fresh `mixed` plain store per `length` from `load.writers` threads while
+/// `load.readers` threads look up uniformly drawn keys among those committed so far, and reports
+/// both throughputs. The store sits behind an `RwLock`, so lookups run alongside one another and
+/// the store's own background work but wait out every commit; that contention is the point. A
+/// commit failing with a retryable error is tried again up to `MIXED_COMMIT_RETRIES` times.
+pub fn run_mixed<S, F>(base: &Path, length: RunLength, load: MixedLoad, factory: F) -> Result<MixedReport, S::Error>
+where
+	S: StoreWrite<Key, Amount> + Send + Sync,
+	S::Error: Retryable + Send,
+	F: Fn(&Path) -> Result<S, S::Error>,
+{
+	let path = base.join("mixed");
+	std::fs::remove_dir_all(&path).ok();
+	let store = RwLock::new(factory(&path)?);
+	let rows = length.rows();
+	let stop_after = length.ingest_config().stop_after;
+	// Rows handed to writers, and rows committed, which readers draw from.
+	let claimed = AtomicU64::new(0);
+	let committed = AtomicU64::new(0);
+	let writing = AtomicU64::new(load.writers as u64);
+	let start = Instant::now();
+	let (reads, hits) = thread::scope(|scope| {
+		let writers: Vec<_> = (0..load.writers)
+			.map(|_| {
+				scope.spawn(|| {
+					// Readers stop once every writer did, whether it ran out of rows or failed.
+					let _done = DecrementOnDrop(&writing);
+					loop {
+						if stop_after.is_some_and(|limit| start.elapsed() >= limit) {
+							return Ok(())
+						}
+						let from = claimed.fetch_add(BATCH as u64, Ordering::Relaxed);
+						if from >= rows {
+							return Ok(())
+						}
+						let batch: Vec<_> = (from..rows.min(from + BATCH as u64)).map(|i| (make_key(i), Amount(i))).collect();
+						store.write().unwrap().commit_retry(batch.iter().map(|(k, v)| (k, v)), MIXED_COMMIT_RETRIES)?;
+						committed.fetch_add(rows.min(from + BATCH as u64) - from, Ordering::Relaxed);
+					}
+				})
+			})
+			.collect();
+		let readers: Vec<_> = (0..load.readers)
+			.map(|seed| {
+				let mut rng = StdRng::seed_from_u64(seed as u64 + 5);
+				let (store, committed, writing) = (&store, &committed, &writing);
+				scope.spawn(move || {
+					let (mut reads, mut hits) = (0u64, 0u64);
+					while writing.load(Ordering::Relaxed) > 0 {
+						let n = committed.load(Ordering::Relaxed);
+						if n == 0 {
+							thread::yield_now();
+							continue
+						}
+						// Writers commit out of order, so a key below `n` may still be in flight.
+						let key = make_key(rng.random_range(0..n));
+						hits += store.read().unwrap().get_value(&key)?.is_some() as u64;
+						reads += 1;
+					}
+					Ok((reads, hits))
+				})
+			})
+			.collect();
+		for writer in writers {
+			writer.join().unwrap()?;
+		}
+		readers.into_iter().try_fold((0, 0), |(reads, hits), reader| {
+			let (r, h) = reader.join().unwrap()?;
+			Ok((reads + r, hits + h))
+		})
+	})?;
+	let elapsed = start.elapsed();
+	let rows = committed.into_inner();
+	println!(
+		"{}: mixed ({} writers, {} readers) in {elapsed:.2?}: {rows} rows, {:.0} rows/s; {reads} reads, {:.0} ops/s, {hits} hits",
+		path.display(),
+		load.writers,
+		load.readers,
+		ops_per_sec(rows, elapsed),
+		ops_per_sec(reads, elapsed),
+	);
+	let mut store = store.into_inner().unwrap();
+	// A fresh store each run, so there is no checkpoint to finish.
+	final_flush(&path, &mut store, RunLength::Rows(rows))?;
+	report_disk_usage(&path);
+	Ok(MixedReport { rows, reads, hits })
+}
+
+/// Counts a writer of `run_mixed` out when it returns, even by an error.
+struct DecrementOnDrop<'a>(&'a AtomicU64);
+
+impl Drop for DecrementOnDrop<'_> {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+/// Rows a `run_plain` or `run_dictionary` wrote, found by lookups alone as their keys are `0..n`:
+/// doubling until a key is missing, then bisecting. Works on backends that cannot walk keys in order.
+fn count_rows<V, S: StoreRead<Key, V>>(store: &S) -> Result<u64, S::Error> {
+	let present = |i: u64| store.get_value(&make_key(i)).map(|v| v.is_some());
+	if !present(0)? {
+		return Ok(0)
+	}
+	// Key `lo` is present and key `hi` missing.
+	let mut lo = 0;
+	let mut hi = 1;
+	while present(hi)? {
+		lo = hi;
+		hi = hi.saturating_mul(2);
+	}
+	while hi - lo > 1 {
+		let mid = lo + (hi - lo) / 2;
+		if present(mid)? {
+			lo = mid;
+		} else {
+			hi = mid;
+		}
+	}
+	Ok(hi)
+}
+
+/// Runs the jobs of the selected `layouts` in parallel, one thread each.
+pub fn run_all_parallel<E>(jobs: Vec<NamedJob<E>>, layouts: &LayoutSet) -> Result<(), E>
+where
+	E: Send + 'static,
+{
+	let filtered: Vec<NamedJob<E>> = jobs.into_iter().filter(|j| layouts.contains(j.layout)).collect();
+
+	// Jobs share the process, so a job's peak is the process peak up to the moment it finished.
+	let sampler = RssSampler::start(Duration::from_millis(100));
+	let board = ProgressBoard::default();
+	let handles = filtered
+		.into_iter()
+		.map(|j| {
+			let peak = sampler.peak.clone();
+			let board = board.clone();
+			thread::spawn(move || {
+				let start = Instant::now();
+				let res = (j.job)(&board);
+				let peak_mib = peak.load(Ordering::Relaxed) as f64 / (1024.0 * 1024.0);
+				println!("{}: finished in {:.2?}, peak RSS {peak_mib:.1} MiB", j.layout.name(), start.elapsed());
+				res
+			})
+		})
+		.collect::<Vec<_>>();
 	for h in handles {
 		h.join().unwrap()?;
 	}
 	Ok(())
 }
 
-pub fn cleanup_dirs(base: &Path, dirs: &[&str]) {
-	for dir in dirs {
-		let path = base.join(dir);
+/// Spacing of the lines a `ProgressBoard` prints.
+pub const PROGRESS_BOARD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Progress of the jobs `run_all_parallel` runs side by side, printed as one line with each job's
+/// rows and rate and their totals instead of interleaved lines per job. Clones share the board.
+#[derive(Clone, Default)]
+pub struct ProgressBoard {
+	state: Arc<Mutex<BoardState>>,
+}
+
+#[derive(Default)]
+struct BoardState {
+	/// Latest report of each job by label.
+	jobs: BTreeMap<String, ProgressEvent>,
+	last_print: Option<Instant>,
+}
+
+impl ProgressBoard {
+	/// Takes `event` as the latest of its job, printing the board when a line is due.
+	pub fn report(&self, event: &ProgressEvent) {
+		if let Some(line) = self.update(event, Instant::now()) {
+			println!("{line}");
+		}
+	}
+
+	/// `report` against an explicit clock, returning the line due instead of printing it.
+	fn update(&self, event: &ProgressEvent, now: Instant) -> Option<String> {
+		let mut state = self.state.lock().unwrap();
+		state.jobs.insert(event.label.clone(), event.clone());
+		if state.last_print.is_some_and(|at| now.duration_since(at) < PROGRESS_BOARD_INTERVAL) {
+			return None
+		}
+		state.last_print = Some(now);
+		Some(render_board(&state.jobs))
+	}
+
+	/// Progress tracker of a `run_*` job, reporting to this board under `label`.
+	pub fn tracker(&self, label: &str, total: u64) -> ProgressTracker {
+		let board = self.clone();
+		ProgressTracker::new(label.to_string(), total).with_sink(move |event: &ProgressEvent| board.report(event))
+	}
+
+	/// Marks the job under `label` done: its rows stay on the board, its rate drops out of the total.
+	pub fn finish(&self, label: &str) {
+		if let Some(event) = self.state.lock().unwrap().jobs.get_mut(label) {
+			event.rate = 0.0;
+			event.eta = None;
+		}
+	}
+
+	/// Rows reported by all jobs so far.
+	pub fn inserted(&self) -> u64 {
+		self.state.lock().unwrap().jobs.values().map(|e| e.inserted).sum()
+	}
+
+	/// Combined rows per second of all jobs.
+	pub fn rate(&self) -> f64 {
+		self.state.lock().unwrap().jobs.values().map(|e| e.rate).sum()
+	}
+}
+
+fn render_board(jobs: &BTreeMap<String, ProgressEvent>) -> String {
+	let mut line = String::from("progress:");
+	for event in jobs.values() {
+		line.push_str(&format!(" {} {}/{} (~{:.1} rows/s),", event.label, event.inserted, event.total, event.rate));
+	}
+	let inserted: u64 = jobs.values().map(|e| e.inserted).sum();
+	let total: u64 = jobs.values().map(|e| e.total).sum();
+	let rate: f64 = jobs.values().map(|e| e.rate).sum();
+	line.push_str(&format!(" total {inserted}/{total} (~{rate:.1} rows/s)"));
+	line
+}
+
+/// Samples the resident set size of this process on a background thread and keeps the peak.
+pub struct RssSampler {
+	peak: Arc<AtomicU64>,
+	stop: Arc<AtomicBool>,
+	handle: Option<JoinHandle<()>>,
+}
+
+impl RssSampler {
+	pub fn start(interval: Duration) -> Self {
+		let peak = Arc::new(AtomicU64::new(current_rss_bytes().unwrap_or(0)));
+		let stop = Arc::new(AtomicBool::new(false));
+		let handle = {
+			let (peak, stop) = (peak.clone(), stop.clone());
+			thread::spawn(move || {
+				while !stop.load(Ordering::Relaxed) {
+					if let Some(rss) = current_rss_bytes() {
+						peak.fetch_max(rss, Ordering::Relaxed);
+					}
+					thread::sleep(interval);
+				}
+			})
+		};
+		Self { peak, stop, handle: Some(handle) }
+	}
+
+	/// Highest RSS seen so far, including a fresh sample; 0 where RSS can't be read.
+	pub fn peak_bytes(&self) -> u64 {
+		if let Some(rss) = current_rss_bytes() {
+			self.peak.fetch_max(rss, Ordering::Relaxed);
+		}
+		self.peak.load(Ordering::Relaxed)
+	}
+}
+
+impl Drop for RssSampler {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+		if let Some(h) = self.handle.take() {
+			let _ = h.join();
+		}
+	}
+}
+
+/// Current RSS from `/proc/self/status`.
+#[cfg(target_os = "linux")]
+pub fn current_rss_bytes() -> Option<u64> {
+	let status = std::fs::read_to_string("/proc/self/status").ok()?;
+	let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+	let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+	Some(kib * 1024)
+}
+
+/// Peak RSS from `getrusage`; other unixes expose no cheap current RSS, and the peak is what
+/// the sampler keeps anyway. macOS reports bytes, the BSDs kilobytes.
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn current_rss_bytes() -> Option<u64> {
+	let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+	if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } != 0 {
+		return None
+	}
+	let max_rss = unsafe { usage.assume_init() }.ru_maxrss as u64;
+	if cfg!(target_os = "macos") { Some(max_rss) } else { Some(max_rss * 1024) }
+}
+
+#[cfg(not(unix))]
+pub fn current_rss_bytes() -> Option<u64> {
+	None
+}
+
+/// Total bytes of all files under `path`, recursively; unreadable entries count as zero.
+pub fn dir_size(path: &Path) -> u64 {
+	dir_size_by_kind(path).values().sum()
+}
+
+/// Bytes under `path` per file extension (`sst`, `fst`, `val`, ...); files without one, like
+/// RocksDB's `MANIFEST-*` or `LOG`, are grouped as `other`.
+pub fn dir_size_by_kind(path: &Path) -> BTreeMap<String, u64> {
+	let mut sizes = BTreeMap::new();
+	let mut pending = vec![path.to_path_buf()];
+	while let Some(dir) = pending.pop() {
+		let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+		for entry in entries.flatten() {
+			let Ok(meta) = entry.metadata() else { continue };
+			let path = entry.path();
+			if meta.is_dir() {
+				pending.push(path);
+				continue
+			}
+			let kind = path.extension().and_then(|e| e.to_str()).unwrap_or("other").to_string();
+			*sizes.entry(kind).or_insert(0) += meta.len();
+		}
+	}
+	sizes
+}
+
+/// Prints the on-disk size of a bench directory, largest file kinds first.
+pub fn report_disk_usage(path: &Path) {
+	let mut kinds: Vec<(String, u64)> = dir_size_by_kind(path).into_iter().collect();
+	kinds.sort_by_key(|kind| std::cmp::Reverse(kind.1));
+	let total: u64 = kinds.iter().map(|(_, bytes)| bytes).sum();
+	let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+	let breakdown: Vec<String> = kinds.iter().map(|(kind, bytes)| format!("{kind} {:.1} MiB", mib(*bytes))).collect();
+	println!("{}: {:.1} MiB on disk ({})", path.display(), mib(total), breakdown.join(", "));
+}
+
+/// Directory under the `--wal-dir` base for the log of the store at `path`, named after the store's
+/// own directory, so benches running side by side never share a log.
+pub fn store_wal_dir(wal_base: &Path, path: &Path) -> PathBuf {
+	wal_base.join(path.file_name().unwrap_or(path.as_os_str()))
+}
+
+/// Removes what an earlier run left in the directories of the selected `layouts`.
+pub fn cleanup_dirs(base: &Path, layouts: &LayoutSet) {
+	for layout in layouts.iter() {
+		let path = base.join(layout.name());
 		if path.exists() {
 			std::fs::remove_dir_all(&path).ok();
 		}
+		finish_checkpoint(&Checkpoint::path(&path));
 	}
 }
 
+/// Removes what an earlier run left in the `--wal-dir` logs of the selected `layouts` and of the
+/// mixed job, whose fresh stores would otherwise replay them.
+pub fn cleanup_wal_dirs(wal_base: &Path, layouts: &LayoutSet) {
+	cleanup_dirs(wal_base, layouts);
+	std::fs::remove_dir_all(wal_base.join("mixed")).ok();
+}
+
 pub fn make_key(i: u64) -> Key {
 	Key(i)
 }
 
+/// Big-endian `i` followed by random padding, so keys stay unique whatever length is drawn.
+/// Lengths below 8 bytes are raised to 8.
+pub fn make_var_key(i: u64, lengths: KeyLengths, rng: &mut StdRng) -> VarKey {
+	let min = lengths.min.max(8);
+	let max = lengths.max.max(min);
+	let len = min + (rng.next_u64() % (max - min + 1) as u64) as usize;
+	let mut bytes = vec![0u8; len];
+	bytes[..8].copy_from_slice(&i.to_be_bytes());
+	rng.fill_bytes(&mut bytes[8..]);
+	VarKey(bytes)
+}
+
 pub fn ops_per_sec(total: u64, elapsed: std::time::Duration) -> f64 {
 	total as f64 / elapsed.as_secs_f64()
 }
 
-fn random_address(rng: &mut StdRng) -> Address {
+/// A base58 or bech32 address drawn from `rng`, as `AddressStream` generates them.
+pub fn random_address(rng: &mut StdRng) -> Address {
 	if rng.next_u32() & 1 == 0 {
 		base58_address(rng)
 	} else {
@@ -220,7 +1229,7 @@ fn random_address(rng: &mut StdRng) -> Address {
 }
 
 fn base58_address(rng: &mut StdRng) -> Address {
-	let version = if rng.next_u32() & 1 == 0 { 0x00 } else { 0x05 }; // P2PKH / P2SH
+	let version = if rng.next_u32() & 1 == 0 { P2PKH_VERSION } else { P2SH_VERSION };
 	let mut payload = [0u8; 20];
 	rng.fill_bytes(&mut payload);
 	let mut data = Vec::with_capacity(1 + payload.len());
@@ -240,7 +1249,7 @@ fn bech32_address(rng: &mut StdRng) -> Address {
 	data.push(bech32::u5::try_from_u8(version).expect("valid witness version"));
 	data.extend(program.to_base32());
 
-	let addr = bech32::encode("bc", data, variant).expect("encode succeeds");
+	let addr = bech32::encode(BECH32_HRP, data, variant).expect("encode succeeds");
 	Address(addr.into_bytes())
 }
 
@@ -250,6 +1259,7 @@ pub struct AddressStream {
 }
 
 impl AddressStream {
+	/// Addresses for `total` rows; `u64::MAX` streams until `join`.
 	pub fn new(total: u64, seed: u64) -> Self {
 		let (tx, rx) = bounded(1024);
 		let counter = Arc::new(AtomicU64::new(0));
@@ -266,7 +1276,7 @@ impl AddressStream {
 					if idx >= total {
 						break
 					}
-					let addr = if idx % 5 == 0 {
+					let addr = if idx.is_multiple_of(5) {
 						let a = random_address(&mut rng);
 						last = Some(a.clone());
 						a
@@ -287,7 +1297,10 @@ impl AddressStream {
 		Self { rx, handles }
 	}
 
+	/// Stops the generator threads, which an unbounded stream needs, and waits for them.
 	pub fn join(self) {
+		// Dropping the receiver fails their pending sends.
+		drop(self.rx);
 		for h in self.handles {
 			let _ = h.join();
 		}
@@ -300,3 +1313,637 @@ impl Iterator for AddressStream {
 		self.rx.recv().ok()
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Mutex;
+	use crate::{
+		store_interface::{CommitStats, ConsistencyReport, OpenMode, StoreRead},
+		store_tests::MemStore,
+	};
+
+	#[test]
+	fn progress_board_sums_the_latest_report_of_each_job() {
+		let board = ProgressBoard::default();
+		let event = |label: &str, inserted: u64, rate: f64| ProgressEvent {
+			label: label.to_string(),
+			inserted,
+			total: 1000,
+			rate,
+			eta: None,
+		};
+		let t0 = Instant::now();
+		let first = board.update(&event("plain", 100, 20.0), t0).unwrap();
+		assert_eq!(first, "progress: plain 100/1000 (~20.0 rows/s), total 100/1000 (~20.0 rows/s)");
+		assert_eq!(board.update(&event("index", 50, 10.0), t0 + Duration::from_secs(1)), None);
+		// A job's later report replaces its earlier one rather than adding to it.
+		assert_eq!(board.update(&event("plain", 300, 30.0), t0 + Duration::from_secs(2)), None);
+		assert_eq!(board.inserted(), 350);
+		assert!((board.rate() - 40.0).abs() < 1e-9);
+		let line = board.update(&event("range", 10, 2.0), t0 + PROGRESS_BOARD_INTERVAL).unwrap();
+		assert_eq!(
+			line,
+			"progress: index 50/1000 (~10.0 rows/s), plain 300/1000 (~30.0 rows/s), range 10/1000 (~2.0 rows/s), \
+			 total 360/3000 (~42.0 rows/s)"
+		);
+		// A finished job keeps its rows but no longer counts towards the total rate.
+		board.finish("plain");
+		assert_eq!(board.inserted(), 360);
+		assert!((board.rate() - 12.0).abs() < 1e-9);
+	}
+
+	#[test]
+	fn only_selected_layouts_run_and_get_cleaned() {
+		let base = std::env::temp_dir().join(format!("bench_common_layouts_{}", std::process::id()));
+		for layout in BenchLayout::ALL {
+			std::fs::create_dir_all(base.join(layout.name()).join("stale")).unwrap();
+		}
+		let layouts = LayoutSet::parse("Plain, range").unwrap();
+		cleanup_dirs(&base, &layouts);
+		let jobs = BenchLayout::ALL
+			.into_iter()
+			.map(|layout| {
+				let out = base.join(format!("{}.out", layout.name()));
+				NamedJob::new(layout, Box::new(move || std::fs::write(out, b"done")))
+			})
+			.collect();
+		run_all_parallel(jobs, &layouts).unwrap();
+		for layout in BenchLayout::ALL {
+			let selected = matches!(layout, BenchLayout::Plain | BenchLayout::Range);
+			assert_eq!(base.join(format!("{}.out", layout.name())).exists(), selected, "{layout:?} output");
+			assert_eq!(base.join(layout.name()).exists(), !selected, "{layout:?} directory");
+		}
+		std::fs::remove_dir_all(&base).unwrap();
+
+		assert_eq!(LayoutSet::parse("all").unwrap(), LayoutSet::default());
+		assert!(LayoutSet::parse("plain,indx").unwrap_err().contains("\"indx\""));
+		assert!(LayoutSet::parse(" , ").is_err());
+	}
+
+	/// `MemStore` that takes its time over every commit and checks how far generation ran ahead.
+	struct SlowStore {
+		inner: MemStore,
+		produced: Arc<AtomicU64>,
+		committed: u64,
+		max_ahead: u64,
+	}
+
+	impl StoreRead<Vec<u8>, Vec<u8>> for SlowStore {
+		type Error = String;
+
+		fn get_value(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+			self.inner.get_value(key)
+		}
+
+		fn get_value_raw(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+			self.inner.get_value_raw(key)
+		}
+
+		fn get_key_for_value(&self, value: &Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+			self.inner.get_key_for_value(value)
+		}
+
+		fn get_keys_for_value(&self, value: &Vec<u8>) -> Result<Vec<Vec<u8>>, String> {
+			self.inner.get_keys_for_value(value)
+		}
+
+		fn distinct_values(&self) -> Result<impl Iterator<Item = Result<Vec<u8>, String>>, String> {
+			self.inner.distinct_values()
+		}
+
+		fn verify_consistency(&self) -> Result<ConsistencyReport, String> {
+			self.inner.verify_consistency()
+		}
+
+		fn first_key(&self) -> Result<Option<Vec<u8>>, String> {
+			self.inner.first_key()
+		}
+
+		fn last_key(&self) -> Result<Option<Vec<u8>>, String> {
+			self.inner.last_key()
+		}
+
+		fn approx_key_count(&self) -> Result<u64, String> {
+			self.inner.approx_key_count()
+		}
+	}
+
+	impl StoreWrite<Vec<u8>, Vec<u8>> for SlowStore {
+		type Options = ();
+		type Layout = ();
+
+		fn open_with_options(_path: &Path, _layout: (), _options: ()) -> Result<Self, String> {
+			Err("open SlowStore directly".into())
+		}
+
+		fn open_with_mode(_path: &Path, _layout: (), _options: (), _mode: OpenMode) -> Result<Self, String> {
+			Err("open SlowStore directly".into())
+		}
+
+		fn open_read_only(_path: &Path, _layout: ()) -> Result<Self, String> {
+			Err("open SlowStore directly".into())
+		}
+
+		fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, String>
+		where
+			I: IntoIterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>,
+		{
+			thread::sleep(Duration::from_millis(2));
+			let ahead = self.produced.load(Ordering::Relaxed) - self.committed;
+			self.max_ahead = self.max_ahead.max(ahead);
+			let items: Vec<_> = items.into_iter().collect();
+			self.committed += items.len() as u64;
+			self.inner.commit_counted(items)
+		}
+
+		fn flush(&mut self) -> Result<(), String> {
+			self.inner.flush()
+		}
+
+		fn clear(&mut self) -> Result<(), String> {
+			self.inner.clear()
+		}
+	}
+
+	#[test]
+	fn ingest_into_a_slow_store_holds_a_bounded_number_of_rows() {
+		let produced = Arc::new(AtomicU64::new(0));
+		let mut store =
+			SlowStore { inner: MemStore::default(), produced: produced.clone(), committed: 0, max_ahead: 0 };
+		let rows = (0..10_000u32).map(|i| {
+			produced.fetch_add(1, Ordering::Relaxed);
+			(i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec())
+		});
+		let config = IngestConfig { max_in_flight: 2, ..IngestConfig::default() };
+		assert_eq!(ingest(rows, &mut store, 100, config), Ok(10_000));
+		// Generation is cheap next to a commit, so it runs as far ahead as the bound lets it.
+		assert!(store.max_ahead <= (config.max_in_flight as u64 + 2) * 100, "ran {} rows ahead", store.max_ahead);
+		assert!(store.max_ahead >= 100, "ran {} rows ahead", store.max_ahead);
+		assert_eq!(store.get_value(&9_999u32.to_be_bytes().to_vec()), Ok(Some(9_999u32.to_le_bytes().to_vec())));
+	}
+
+	#[test]
+	fn duration_bounded_ingest_stops_promptly() {
+		let mut store = MemStore::<String>::default();
+		let rows = (0u64..).map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()));
+		let config = RunLength::Duration(Duration::from_millis(200)).ingest_config();
+		let start = Instant::now();
+		let inserted = ingest(rows, &mut store, 1_000, config).unwrap();
+		assert!(start.elapsed() < Duration::from_secs(5), "ran for {:.2?}", start.elapsed());
+		assert!(inserted >= 1_000, "ingested {inserted} rows");
+		assert_eq!(inserted % 1_000, 0);
+		let last = (inserted - 1).to_be_bytes().to_vec();
+		assert_eq!(store.get_value(&last), Ok(Some((inserted - 1).to_le_bytes().to_vec())));
+		assert_eq!(store.get_value(&inserted.to_be_bytes().to_vec()), Ok(None));
+	}
+
+	/// A store whose commits sit in memory until `flush` writes them to `disk`, which outlives the
+	/// store like files do, and which fails every commit after `commits_left` like a crashed process.
+	struct CrashStore {
+		disk: Arc<Mutex<MemStore>>,
+		memtable: Vec<(Vec<u8>, Vec<u8>)>,
+		commits_left: u64,
+		rows_committed: u64,
+		/// `last_key` fails, as it does on a backend that hashes its keys when `orders_keys` is off.
+		last_key_fails: bool,
+		orders_keys: bool,
+	}
+
+	impl CrashStore {
+		fn new(disk: &Arc<Mutex<MemStore>>, commits_left: u64) -> Self {
+			Self {
+				disk: disk.clone(),
+				memtable: Vec::new(),
+				commits_left,
+				rows_committed: 0,
+				last_key_fails: false,
+				orders_keys: true,
+			}
+		}
+	}
+
+	impl StoreRead<Vec<u8>, Vec<u8>> for CrashStore {
+		type Error = String;
+
+		fn get_value(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+			match self.memtable.iter().rev().find(|(k, _)| k == key) {
+				Some((_, value)) => Ok(Some(value.clone())),
+				None => self.disk.lock().unwrap().get_value(key),
+			}
+		}
+
+		fn get_value_raw(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+			self.get_value(key)
+		}
+
+		fn get_key_for_value(&self, value: &Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+			self.disk.lock().unwrap().get_key_for_value(value)
+		}
+
+		fn get_keys_for_value(&self, value: &Vec<u8>) -> Result<Vec<Vec<u8>>, String> {
+			self.disk.lock().unwrap().get_keys_for_value(value)
+		}
+
+		fn distinct_values(&self) -> Result<impl Iterator<Item = Result<Vec<u8>, String>>, String> {
+			let values: Vec<_> = self.disk.lock().unwrap().distinct_values()?.collect();
+			Ok(values.into_iter())
+		}
+
+		fn verify_consistency(&self) -> Result<ConsistencyReport, String> {
+			self.disk.lock().unwrap().verify_consistency()
+		}
+
+		fn first_key(&self) -> Result<Option<Vec<u8>>, String> {
+			self.disk.lock().unwrap().first_key()
+		}
+
+		fn last_key(&self) -> Result<Option<Vec<u8>>, String> {
+			if self.last_key_fails {
+				return Err("last_key failed".into())
+			}
+			let buffered = self.memtable.iter().map(|(k, _)| k.clone()).max();
+			Ok(self.disk.lock().unwrap().last_key()?.max(buffered))
+		}
+
+		fn orders_keys(&self) -> bool {
+			self.orders_keys
+		}
+
+		fn approx_key_count(&self) -> Result<u64, String> {
+			Ok(self.disk.lock().unwrap().approx_key_count()? + self.memtable.len() as u64)
+		}
+	}
+
+	impl StoreWrite<Vec<u8>, Vec<u8>> for CrashStore {
+		type Options = ();
+		type Layout = ();
+
+		fn open_with_options(_path: &Path, _layout: (), _options: ()) -> Result<Self, String> {
+			Err("open CrashStore directly".into())
+		}
+
+		fn open_with_mode(_path: &Path, _layout: (), _options: (), _mode: OpenMode) -> Result<Self, String> {
+			Err("open CrashStore directly".into())
+		}
+
+		fn open_read_only(_path: &Path, _layout: ()) -> Result<Self, String> {
+			Err("open CrashStore directly".into())
+		}
+
+		fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, String>
+		where
+			I: IntoIterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>,
+		{
+			if self.commits_left == 0 {
+				return Err("crashed".into())
+			}
+			self.commits_left -= 1;
+			let before = self.memtable.len();
+			self.memtable.extend(items.into_iter().map(|(k, v)| (k.clone(), v.clone())));
+			self.rows_committed += (self.memtable.len() - before) as u64;
+			Ok(CommitStats::default())
+		}
+
+		fn flush(&mut self) -> Result<(), String> {
+			let rows = std::mem::take(&mut self.memtable);
+			self.disk.lock().unwrap().commit(rows.iter().map(|(k, v)| (k, v)))
+		}
+
+		fn clear(&mut self) -> Result<(), String> {
+			self.memtable.clear();
+			self.disk.lock().unwrap().clear()
+		}
+	}
+
+	#[test]
+	fn resumed_ingest_holds_every_row_exactly_once() {
+		const ROWS: u64 = 10_000;
+		let dir = std::env::temp_dir().join(format!("bench_common_resume_{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let checkpoint = Checkpoint::path(&dir.join("plain"));
+		finish_checkpoint(&checkpoint);
+		let rows = || (0..ROWS).map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec()));
+		let key_bytes = |key: &Vec<u8>| key.clone();
+		let disk = Arc::new(Mutex::new(MemStore::default()));
+
+		// Batches of 100 with a checkpoint every 10: the crash at the 56th batch loses the 500 rows
+		// committed since the checkpoint after 5_000.
+		let mut store = CrashStore::new(&disk, 55);
+		assert!(ingest_resumable(rows(), &mut store, 100, &checkpoint, 10, key_bytes).is_err());
+		drop(store);
+		assert_eq!(Checkpoint::load(&checkpoint).unwrap().rows_committed, 5_000);
+		assert_eq!(disk.lock().unwrap().last_key().unwrap(), Some(4_999u64.to_be_bytes().to_vec()));
+
+		let mut store = CrashStore::new(&disk, u64::MAX);
+		assert_eq!(ingest_resumable(rows(), &mut store, 100, &checkpoint, 10, key_bytes).unwrap(), ROWS);
+		assert_eq!(store.rows_committed, ROWS - 5_000);
+		store.flush().unwrap();
+		finish_checkpoint(&checkpoint);
+		let disk = std::mem::take(&mut *disk.lock().unwrap());
+		assert_eq!(disk.first_key().unwrap(), Some(0u64.to_be_bytes().to_vec()));
+		assert_eq!(disk.last_key().unwrap(), Some((ROWS - 1).to_be_bytes().to_vec()));
+		for (key, value) in rows() {
+			assert_eq!(disk.get_value(&key).unwrap(), Some(value));
+		}
+
+		// A checkpoint the store does not back, e.g. of a store since removed, is not trusted.
+		Checkpoint { rows_committed: 5_000, last_key: 4_999u64.to_be_bytes().to_vec() }.save(&checkpoint).unwrap();
+		let mut store = CrashStore::new(&Arc::default(), u64::MAX);
+		assert_eq!(ingest_resumable(rows(), &mut store, 100, &checkpoint, 10, key_bytes).unwrap(), ROWS);
+		assert_eq!(store.rows_committed, ROWS);
+
+		// A backend that cannot order its keys takes the checkpoint on trust; one whose `last_key`
+		// fails for another reason does not.
+		Checkpoint { rows_committed: 5_000, last_key: 4_999u64.to_be_bytes().to_vec() }.save(&checkpoint).unwrap();
+		let empty = CrashStore::new(&Arc::default(), u64::MAX);
+		let mut store = CrashStore { last_key_fails: true, orders_keys: false, ..empty };
+		assert_eq!(ingest_resumable(rows(), &mut store, 100, &checkpoint, 10, key_bytes).unwrap(), ROWS);
+		assert_eq!(store.rows_committed, ROWS - 5_000);
+		Checkpoint { rows_committed: 5_000, last_key: 4_999u64.to_be_bytes().to_vec() }.save(&checkpoint).unwrap();
+		let mut store = CrashStore { last_key_fails: true, ..CrashStore::new(&Arc::default(), u64::MAX) };
+		let err = ingest_resumable(rows(), &mut store, 100, &checkpoint, 10, key_bytes).unwrap_err();
+		assert_eq!(err, "last_key failed");
+		assert_eq!(store.rows_committed, 0);
+
+		std::fs::write(&checkpoint, "5000\nnot hex\n").unwrap();
+		assert_eq!(Checkpoint::load(&checkpoint), None);
+		std::fs::remove_dir_all(&dir).ok();
+	}
+
+	/// `MemStore` under the `Key`/`Amount` types of the plain benches.
+	#[derive(Default)]
+	struct PlainMemStore(MemStore);
+
+	fn amount(bytes: Vec<u8>) -> Amount {
+		Amount(u64::from_be_bytes(bytes.try_into().unwrap()))
+	}
+
+	impl StoreRead<Key, Amount> for PlainMemStore {
+		type Error = String;
+
+		fn get_value(&self, key: &Key) -> Result<Option<Amount>, String> {
+			Ok(self.0.get_value(&key.to_be_bytes().to_vec())?.map(amount))
+		}
+
+		fn get_value_raw(&self, key: &Key) -> Result<Option<Vec<u8>>, String> {
+			self.0.get_value_raw(&key.to_be_bytes().to_vec())
+		}
+
+		fn get_key_for_value(&self, value: &Amount) -> Result<Option<Key>, String> {
+			Ok(self.0.get_key_for_value(&value.0.to_be_bytes().to_vec())?.map(|k| Key(amount(k).0)))
+		}
+
+		fn get_keys_for_value(&self, value: &Amount) -> Result<Vec<Key>, String> {
+			Ok(self.0.get_keys_for_value(&value.0.to_be_bytes().to_vec())?.into_iter().map(|k| Key(amount(k).0)).collect())
+		}
+
+		fn distinct_values(&self) -> Result<impl Iterator<Item = Result<Amount, String>>, String> {
+			Ok(self.0.distinct_values()?.map(|v| v.map(amount)))
+		}
+
+		fn verify_consistency(&self) -> Result<ConsistencyReport, String> {
+			self.0.verify_consistency()
+		}
+
+		fn first_key(&self) -> Result<Option<Key>, String> {
+			Ok(self.0.first_key()?.map(|k| Key(amount(k).0)))
+		}
+
+		fn last_key(&self) -> Result<Option<Key>, String> {
+			Ok(self.0.last_key()?.map(|k| Key(amount(k).0)))
+		}
+
+		fn approx_key_count(&self) -> Result<u64, String> {
+			self.0.approx_key_count()
+		}
+	}
+
+	impl StoreWrite<Key, Amount> for PlainMemStore {
+		type Options = ();
+		type Layout = ();
+
+		fn open_with_options(_path: &Path, _layout: (), _options: ()) -> Result<Self, String> {
+			Ok(Self::default())
+		}
+
+		fn open_with_mode(_path: &Path, _layout: (), _options: (), _mode: OpenMode) -> Result<Self, String> {
+			Ok(Self::default())
+		}
+
+		fn open_read_only(_path: &Path, _layout: ()) -> Result<Self, String> {
+			Ok(Self::default())
+		}
+
+		fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, String>
+		where
+			I: IntoIterator<Item = (&'a Key, &'a Amount)>,
+		{
+			let rows: Vec<_> = items.into_iter().map(|(k, v)| (k.to_be_bytes().to_vec(), v.0.to_be_bytes().to_vec())).collect();
+			self.0.commit_counted(rows.iter().map(|(k, v)| (k, v)))
+		}
+
+		fn flush(&mut self) -> Result<(), String> {
+			self.0.flush()
+		}
+
+		fn clear(&mut self) -> Result<(), String> {
+			self.0.clear()
+		}
+	}
+
+	#[test]
+	fn mixed_run_advances_writes_and_reads() {
+		let base = std::env::temp_dir().join(format!("bench_common_mixed_{}", std::process::id()));
+		let load = MixedLoad::parse("2:3").unwrap();
+		let report = run_mixed(&base, RunLength::Rows(200_000), load, |_| Ok(PlainMemStore::default())).unwrap();
+		assert_eq!(report.rows, 200_000);
+		assert!(report.reads > 0, "no lookups ran during the writes");
+		assert!(report.hits > 0 && report.hits <= report.reads, "{report:?}");
+
+		let report =
+			run_mixed(&base, RunLength::Duration(Duration::from_millis(100)), load, |_| Ok(PlainMemStore::default())).unwrap();
+		assert!(report.rows > 0 && report.reads > 0, "{report:?}");
+		std::fs::remove_dir_all(&base).ok();
+
+		assert_eq!(MixedLoad::parse("1:0"), Some(MixedLoad { writers: 1, readers: 0 }));
+		assert_eq!(MixedLoad::parse("0:4"), None);
+		assert_eq!(MixedLoad::parse("4"), None);
+	}
+
+	/// Dictionary layout in memory: every key points at the birth key of its value, the first key
+	/// written with it, under which the value itself is kept.
+	#[derive(Default)]
+	struct DictionaryMemStore {
+		birth_keys: BTreeMap<u64, u64>,
+		values: BTreeMap<u64, Vec<u8>>,
+		births: BTreeMap<Vec<u8>, u64>,
+		members: BTreeSet<(u64, u64)>,
+	}
+
+	impl StoreRead<Key, Address> for DictionaryMemStore {
+		type Error = String;
+
+		fn get_value(&self, key: &Key) -> Result<Option<Address>, String> {
+			let Some(birth) = self.birth_keys.get(&key.0) else { return Ok(None) };
+			Ok(self.values.get(birth).cloned().map(Address))
+		}
+
+		fn get_value_raw(&self, key: &Key) -> Result<Option<Vec<u8>>, String> {
+			Ok(self.get_value(key)?.map(|address| address.0))
+		}
+
+		fn get_key_for_value(&self, value: &Address) -> Result<Option<Key>, String> {
+			Ok(self.births.get(&value.0).map(|birth| Key(*birth)))
+		}
+
+		fn get_keys_for_value(&self, value: &Address) -> Result<Vec<Key>, String> {
+			let Some(&birth) = self.births.get(&value.0) else { return Ok(Vec::new()) };
+			Ok(self.members.range((birth, 0)..=(birth, u64::MAX)).map(|(_, key)| Key(*key)).collect())
+		}
+
+		fn distinct_values(&self) -> Result<impl Iterator<Item = Result<Address, String>>, String> {
+			Ok(self.values.values().cloned().map(|v| Ok(Address(v))).collect::<Vec<_>>().into_iter())
+		}
+
+		fn verify_consistency(&self) -> Result<ConsistencyReport, String> {
+			Ok(ConsistencyReport { checked: 0, mismatches: Vec::new() })
+		}
+
+		fn first_key(&self) -> Result<Option<Key>, String> {
+			Ok(self.birth_keys.keys().next().map(|k| Key(*k)))
+		}
+
+		fn last_key(&self) -> Result<Option<Key>, String> {
+			Ok(self.birth_keys.keys().next_back().map(|k| Key(*k)))
+		}
+
+		fn approx_key_count(&self) -> Result<u64, String> {
+			Ok(self.birth_keys.len() as u64)
+		}
+	}
+
+	impl StoreWrite<Key, Address> for DictionaryMemStore {
+		type Options = ();
+		type Layout = ();
+
+		fn open_with_options(_path: &Path, _layout: (), _options: ()) -> Result<Self, String> {
+			Ok(Self::default())
+		}
+
+		fn open_with_mode(_path: &Path, _layout: (), _options: (), _mode: OpenMode) -> Result<Self, String> {
+			Ok(Self::default())
+		}
+
+		fn open_read_only(_path: &Path, _layout: ()) -> Result<Self, String> {
+			Ok(Self::default())
+		}
+
+		fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, String>
+		where
+			I: IntoIterator<Item = (&'a Key, &'a Address)>,
+		{
+			let mut rows = 0;
+			for (key, value) in items {
+				let birth = *self.births.entry(value.0.clone()).or_insert(key.0);
+				self.values.entry(birth).or_insert_with(|| value.0.clone());
+				if let Some(old) = self.birth_keys.insert(key.0, birth) {
+					self.members.remove(&(old, key.0));
+				}
+				self.members.insert((birth, key.0));
+				rows += 1;
+			}
+			Ok(CommitStats { rows, ..CommitStats::default() })
+		}
+
+		fn flush(&mut self) -> Result<(), String> {
+			Ok(())
+		}
+
+		fn clear(&mut self) -> Result<(), String> {
+			*self = Self::default();
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn dictionary_reads_resolve_values_through_birth_keys() {
+		let rows: Vec<(Key, Address)> =
+			(0..1_000).map(|i| (make_key(i), Address(format!("addr{}", i / 5).into_bytes()))).collect();
+		let factory = |_: &Path| {
+			let mut store = DictionaryMemStore::default();
+			store.commit_owned(rows.clone())?;
+			Ok(store)
+		};
+		// Key 7 shares its value with keys 5..10 and reaches it through birth key 5.
+		let store = factory(Path::new("")).unwrap();
+		assert_eq!(store.birth_keys[&7], 5);
+		assert_eq!(store.get_value(&make_key(7)).unwrap().unwrap().0, b"addr1");
+		assert_eq!(store.get_keys_for_value(&Address(b"addr1".to_vec())).unwrap(), (5..10).map(make_key).collect::<Vec<_>>());
+
+		let base = Path::new("dictionary_reads");
+		let load = ReadLoad { reads: 2_000, ..ReadLoad::default() };
+		let report = run_dictionary_reads(base, RunLength::Rows(1_000), load, factory).unwrap();
+		assert_eq!(report, DictionaryReadReport { reads: 2_000, hits: 2_000, reverse_reads: 2_000, reverse_hits: 2_000 });
+		// A timed run finds the rows written by lookups alone.
+		let load = ReadLoad { reads: 500, distribution: KeyDistribution::Latest, ..ReadLoad::default() };
+		let report = run_dictionary_reads(base, RunLength::Duration(Duration::from_secs(1)), load, factory).unwrap();
+		assert_eq!((report.hits, report.reverse_hits), (500, 500));
+		let none = ReadLoad { reads: 0, ..ReadLoad::default() };
+		assert_eq!(run_dictionary_reads(base, RunLength::Rows(1_000), none, factory).unwrap(), DictionaryReadReport::default());
+	}
+
+	#[test]
+	fn unbounded_address_stream_joins_after_partial_reads() {
+		let mut stream = AddressStream::new(u64::MAX, 7);
+		assert_eq!(stream.by_ref().take(5_000).count(), 5_000);
+		stream.join();
+	}
+
+	#[test]
+	fn parses_duration_flags() {
+		assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+		assert_eq!(parse_duration("60s"), Some(Duration::from_secs(60)));
+		assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+		assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+		assert_eq!(parse_duration("60"), None);
+		assert_eq!(parse_duration("s"), None);
+		assert_eq!(parse_duration("1.5s"), None);
+	}
+
+	#[test]
+	fn zipfian_draws_concentrate_on_low_indices() {
+		let n = 100_000;
+		let draws = 100_000;
+		let share_below = |distribution, theta, bound| {
+			let mut chooser = KeyChooser::new(n, distribution, theta, 1);
+			let hits = (0..draws).filter(|_| chooser.next_index() < bound).count();
+			hits as f64 / draws as f64
+		};
+		// The first 1% of the keys draw 1% of uniform reads but most zipfian ones at high theta.
+		let uniform = share_below(KeyDistribution::Uniform, 0.99, n / 100);
+		assert!((uniform - 0.01).abs() < 0.005, "uniform share {uniform}");
+		let skewed = share_below(KeyDistribution::Zipfian, 0.99, n / 100);
+		assert!(skewed > 0.5, "zipfian share {skewed}");
+		let mild = share_below(KeyDistribution::Zipfian, 0.5, n / 100);
+		assert!(mild < skewed && mild > 0.05, "theta 0.5 share {mild}");
+		// `Latest` puts the same skew on the last keys.
+		let latest = share_below(KeyDistribution::Latest, 0.99, n - n / 100);
+		assert!(latest < 0.5, "latest share below the last 1% {latest}");
+		let mut chooser = KeyChooser::new(n, KeyDistribution::Zipfian, 0.99, 1);
+		assert!((0..draws).all(|_| chooser.next_index() < n));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn rss_sampler_peak_is_nonzero_and_never_drops() {
+		let sampler = RssSampler::start(Duration::from_millis(5));
+		let first = sampler.peak_bytes();
+		assert!(first > 0);
+		let buf = vec![1u8; 64 * 1024 * 1024];
+		let during = sampler.peak_bytes();
+		assert!(during >= first + 32 * 1024 * 1024, "peak {during} did not see the 64 MiB buffer");
+		drop(std::hint::black_box(buf));
+		assert!(sampler.peak_bytes() >= during);
+	}
+}
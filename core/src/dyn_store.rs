@@ -0,0 +1,115 @@
+use crate::store_interface::StoreWrite;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Backend-independent error, so one tool can drive several backends. Backends with their own
+/// error type provide `From<StoreError>`; anything else goes through `BenchError::store`.
+#[derive(Debug)]
+pub enum BenchError {
+	Store(BoxError),
+	Other(BoxError),
+}
+
+impl BenchError {
+	pub fn store(err: impl Into<BoxError>) -> Self {
+		BenchError::Store(err.into())
+	}
+}
+
+impl std::fmt::Display for BenchError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BenchError::Store(err) => write!(f, "store error: {err}"),
+			BenchError::Other(err) => write!(f, "{err}"),
+		}
+	}
+}
+
+impl std::error::Error for BenchError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			BenchError::Store(err) | BenchError::Other(err) => Some(err.as_ref()),
+		}
+	}
+}
+
+impl From<BoxError> for BenchError {
+	fn from(err: BoxError) -> Self {
+		BenchError::Other(err)
+	}
+}
+
+impl From<std::io::Error> for BenchError {
+	fn from(err: std::io::Error) -> Self {
+		BenchError::Other(Box::new(err))
+	}
+}
+
+pub type BenchResult<T> = Result<T, BenchError>;
+
+/// Object-safe form of `StoreRead` + `StoreWrite` over raw bytes, implemented for every store
+/// with `Vec<u8>` keys and values.
+pub trait DynStore: Send {
+	fn get_value(&self, key: &[u8]) -> BenchResult<Option<Vec<u8>>>;
+	fn get_key_for_value(&self, value: &[u8]) -> BenchResult<Option<Vec<u8>>>;
+	fn get_keys_for_value(&self, value: &[u8]) -> BenchResult<Vec<Vec<u8>>>;
+	fn commit(&mut self, items: &[(Vec<u8>, Vec<u8>)]) -> BenchResult<()>;
+	fn flush(&mut self) -> BenchResult<()>;
+	fn settle_for_reads(&mut self) -> BenchResult<()>;
+	fn clear(&mut self) -> BenchResult<()>;
+}
+
+impl<S> DynStore for S
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>> + Send,
+	S::Error: Into<BoxError>,
+{
+	fn get_value(&self, key: &[u8]) -> BenchResult<Option<Vec<u8>>> {
+		S::get_value(self, &key.to_vec()).map_err(BenchError::store)
+	}
+
+	fn get_key_for_value(&self, value: &[u8]) -> BenchResult<Option<Vec<u8>>> {
+		S::get_key_for_value(self, &value.to_vec()).map_err(BenchError::store)
+	}
+
+	fn get_keys_for_value(&self, value: &[u8]) -> BenchResult<Vec<Vec<u8>>> {
+		S::get_keys_for_value(self, &value.to_vec()).map_err(BenchError::store)
+	}
+
+	fn commit(&mut self, items: &[(Vec<u8>, Vec<u8>)]) -> BenchResult<()> {
+		S::commit(self, items.iter().map(|(k, v)| (k, v))).map_err(BenchError::store)
+	}
+
+	fn flush(&mut self) -> BenchResult<()> {
+		S::flush(self).map_err(BenchError::store)
+	}
+
+	fn settle_for_reads(&mut self) -> BenchResult<()> {
+		S::settle_for_reads(self).map_err(BenchError::store)
+	}
+
+	fn clear(&mut self) -> BenchResult<()> {
+		S::clear(self).map_err(BenchError::store)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::store_tests::MemStore;
+
+	#[test]
+	fn boxed_stores_dispatch_polymorphically() {
+		let mut stores: Vec<Box<dyn DynStore>> =
+			vec![Box::new(MemStore::<String>::default()), Box::new(MemStore::<std::io::Error>::default())];
+		for store in &mut stores {
+			store.commit(&[(b"k".to_vec(), b"v".to_vec())]).unwrap();
+			store.flush().unwrap();
+		}
+		for store in &stores {
+			assert_eq!(store.get_value(b"k").unwrap(), Some(b"v".to_vec()));
+			assert_eq!(store.get_key_for_value(b"v").unwrap(), Some(b"k".to_vec()));
+			assert_eq!(store.get_value(b"missing").unwrap(), None);
+		}
+	}
+}
@@ -0,0 +1,76 @@
+use std::marker::PhantomData;
+
+use crate::{
+	bench_codecs::{DecodeError, InvalidInput},
+	store_interface::{StoreCodec, WriteOnlyKey},
+};
+
+/// Width of every `HashedKeyCodec` encoding.
+pub const HASHED_KEY_BYTES: usize = 32;
+
+/// One-way hash of an encoded key into `HASHED_KEY_BYTES` bytes.
+pub trait KeyHasher {
+	fn hash(bytes: &[u8]) -> [u8; HASHED_KEY_BYTES];
+}
+
+/// BLAKE3, fast enough to hash every key of a commit.
+pub struct Blake3;
+
+impl KeyHasher for Blake3 {
+	fn hash(bytes: &[u8]) -> [u8; HASHED_KEY_BYTES] {
+		*blake3::hash(bytes).as_bytes()
+	}
+}
+
+/// Keys encoded by `Inner` and then hashed by `H`, so keys of any size, large blobs among them,
+/// are indexed at a fixed width; a value that needs the full key has to carry it itself. Hashes
+/// do not sort like the keys, and a hash cannot be turned back into its key: `decode` always
+/// fails through `I`, which makes the codec `WriteOnlyKey`.
+pub struct HashedKeyCodec<H, Inner, I>(PhantomData<(H, Inner, I)>);
+
+impl<T, H, Inner, I> StoreCodec<T> for HashedKeyCodec<H, Inner, I>
+where
+	H: KeyHasher + 'static,
+	Inner: StoreCodec<T> + 'static,
+	I: InvalidInput<Inner::Error> + 'static,
+{
+	type Error = Inner::Error;
+	type Enc<'a> = [u8; HASHED_KEY_BYTES] where T: 'a;
+	const EXPECTED_LEN: Option<usize> = Some(HASHED_KEY_BYTES);
+	fn encode<'a>(value: &'a T) -> Self::Enc<'a> {
+		H::hash(Inner::encode(value).as_ref())
+	}
+	fn decode(bytes: &[u8]) -> Result<T, Self::Error> {
+		Err(I::invalid_input(DecodeError::new("hashed key cannot be decoded back", HASHED_KEY_BYTES, bytes)))
+	}
+}
+
+impl<H, Inner, I> WriteOnlyKey for HashedKeyCodec<H, Inner, I> {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::bench_codecs::BytesCodec;
+
+	struct Invalid;
+
+	impl InvalidInput<String> for Invalid {
+		fn invalid_input(err: DecodeError) -> String {
+			err.to_string()
+		}
+	}
+
+	type Codec = HashedKeyCodec<Blake3, BytesCodec<String>, Invalid>;
+
+	#[test]
+	fn long_keys_hash_to_distinct_fixed_width_keys() {
+		let (blob, other) = (vec![7u8; 10_000], [vec![7u8; 9_999], vec![8]].concat());
+		let (hash, other_hash) = (Codec::encode(&blob), Codec::encode(&other));
+		assert_eq!(hash.len(), HASHED_KEY_BYTES);
+		assert_ne!(hash, other_hash);
+		assert_eq!(Codec::encode(&blob), hash, "the same key always hashes alike");
+		assert_eq!(Codec::encode(&Vec::new()).len(), HASHED_KEY_BYTES);
+		let err = Codec::decode(&hash).unwrap_err();
+		assert!(err.starts_with("hashed key cannot be decoded back"), "{err}");
+	}
+}
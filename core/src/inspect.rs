@@ -0,0 +1,173 @@
+//! Looking at what a store actually holds for a key, for lookups that return something
+//! unexpected: the key as its codec encodes it and the value's bytes, as hex dumps.
+
+use std::fmt;
+
+use bech32::{FromBase32, Variant};
+
+use crate::{
+	bench_common::{BECH32_HRP, P2PKH_VERSION, P2SH_VERSION},
+	store_interface::StoreCodec,
+};
+
+/// Bytes on one line of a `hexdump`.
+const HEXDUMP_WIDTH: usize = 16;
+
+/// `bytes` in the layout of `hexdump -C`: the offset, 16 bytes in hex, then those of them that are
+/// printable ASCII. Empty input gives an empty string.
+pub fn hexdump(bytes: &[u8]) -> String {
+	let mut out = String::new();
+	for (line, chunk) in bytes.chunks(HEXDUMP_WIDTH).enumerate() {
+		out.push_str(&format!("{:08x} ", line * HEXDUMP_WIDTH));
+		for (i, byte) in chunk.iter().enumerate() {
+			if i == HEXDUMP_WIDTH / 2 {
+				out.push(' ');
+			}
+			out.push_str(&format!(" {byte:02x}"));
+		}
+		// Pads a short last line, so its ASCII column lines up with the others.
+		let missing = HEXDUMP_WIDTH - chunk.len();
+		out.push_str(&" ".repeat(missing * 3 + usize::from(chunk.len() <= HEXDUMP_WIDTH / 2)));
+		out.push_str("  |");
+		out.extend(chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+		out.push_str("|\n");
+	}
+	out
+}
+
+/// The bytes of a `hexdump`, e.g. one pasted from a log; `None` if a line is not in its layout.
+pub fn parse_hexdump(dump: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::new();
+	for line in dump.lines().filter(|line| !line.trim().is_empty()) {
+		let hex = line.split_once('|').map_or(line, |(hex, _)| hex);
+		let mut fields = hex.split_whitespace();
+		usize::from_str_radix(fields.next()?, 16).ok()?;
+		for field in fields {
+			bytes.push(u8::from_str_radix(field, 16).ok()?);
+		}
+	}
+	Some(bytes)
+}
+
+/// What `bytes` decode to as one of the addresses `AddressStream` generates, which are stored as
+/// their string: a base58 P2PKH/P2SH address or a bech32(m) segwit one. `None` for anything else.
+pub fn describe_address(bytes: &[u8]) -> Option<String> {
+	let text = std::str::from_utf8(bytes).ok()?;
+	if let Ok((hrp, data, variant)) = bech32::decode(text) {
+		let (version, program) = data.split_first()?;
+		let program = Vec::<u8>::from_base32(program).ok()?;
+		if hrp == BECH32_HRP {
+			let kind = match variant {
+				Variant::Bech32 => "bech32",
+				Variant::Bech32m => "bech32m",
+			};
+			return Some(format!(
+				"{kind} address {text}: witness v{}, {}-byte program {}",
+				version.to_u8(),
+				program.len(),
+				hex(&program)
+			))
+		}
+	}
+	let decoded = bs58::decode(text).into_vec().ok()?;
+	let (&version, payload) = decoded.split_first()?;
+	let kind = match version {
+		P2PKH_VERSION => "P2PKH",
+		P2SH_VERSION => "P2SH",
+		_ => return None,
+	};
+	(payload.len() == 20).then(|| format!("base58 {kind} address {text}: version 0x{version:02x}, hash {}", hex(payload)))
+}
+
+/// `bytes` as lowercase hex digits, two per byte.
+pub fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// A key as stored and the value a store returned for it; `Display` prints both as hex dumps,
+/// with the address a value decodes to. Stores hand one out from `debug_dump`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyDump {
+	/// The key's encoding, the bytes the store looked up.
+	pub key: Vec<u8>,
+	/// The value's encoding, `None` if the key is not in the store.
+	pub value: Option<Vec<u8>>,
+}
+
+impl KeyDump {
+	pub fn new<K, V, KC: StoreCodec<K>, VC: StoreCodec<V>>(key: &K, value: Option<&V>) -> Self {
+		Self { key: KC::encode(key).as_ref().to_vec(), value: value.map(|v| VC::encode(v).as_ref().to_vec()) }
+	}
+
+	/// `describe_address` of the value.
+	pub fn value_address(&self) -> Option<String> {
+		self.value.as_deref().and_then(describe_address)
+	}
+}
+
+impl fmt::Display for KeyDump {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "key, {} bytes:", self.key.len())?;
+		write!(f, "{}", hexdump(&self.key))?;
+		let Some(value) = &self.value else { return writeln!(f, "value: none") };
+		match self.value_address() {
+			Some(address) => writeln!(f, "value, {} bytes, {address}:", value.len())?,
+			None => writeln!(f, "value, {} bytes:", value.len())?,
+		}
+		write!(f, "{}", hexdump(value))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::bench_codecs::{AddressCodec, KeyCodec};
+	use crate::bench_common::{make_key, Address, AddressStream, Key};
+
+	struct Invalid;
+	impl crate::bench_codecs::InvalidInput<String> for Invalid {
+		fn invalid_input(err: crate::bench_codecs::DecodeError) -> String {
+			err.to_string()
+		}
+	}
+
+	#[test]
+	fn key_round_trips_through_the_hex_dump() {
+		let address = bs58::encode([[P2PKH_VERSION].as_slice(), &[0xab; 20]].concat()).into_string();
+		let dump = KeyDump::new::<Key, Address, KeyCodec<String, Invalid>, AddressCodec<String>>(
+			&make_key(0x0102_0304_0506_0708),
+			Some(&Address(address.clone().into_bytes())),
+		);
+		assert_eq!(dump.key, [1, 2, 3, 4, 5, 6, 7, 8]);
+		assert_eq!(hexdump(&dump.key), "00000000  01 02 03 04 05 06 07 08                           |........|\n");
+		assert_eq!(parse_hexdump(&hexdump(&dump.key)).unwrap(), dump.key);
+		let long: Vec<u8> = (0..=255).collect();
+		assert_eq!(parse_hexdump(&hexdump(&long)).unwrap(), long);
+		assert_eq!(parse_hexdump(&hexdump(&long[..17])).unwrap(), &long[..17]);
+		assert_eq!(parse_hexdump("").unwrap(), Vec::<u8>::new());
+		assert_eq!(parse_hexdump("00000000  zz"), None);
+
+		let printed = dump.to_string();
+		assert!(printed.starts_with("key, 8 bytes:\n00000000  01 02"));
+		let described = format!("base58 P2PKH address {address}: version 0x00, hash {}", "ab".repeat(20));
+		assert_eq!(dump.value_address().unwrap(), described);
+		assert!(printed.contains(&format!("value, {} bytes, {described}:\n", address.len())));
+		let missing = KeyDump { value: None, ..dump };
+		assert!(missing.to_string().ends_with("value: none\n"));
+	}
+
+	#[test]
+	fn generated_addresses_decode() {
+		let mut stream = AddressStream::new(200, 3);
+		let mut kinds = std::collections::BTreeSet::new();
+		for address in stream.by_ref() {
+			let described = describe_address(&address.0).unwrap();
+			kinds.insert(described.split(" address").next().unwrap().to_string());
+		}
+		stream.join();
+		assert!(kinds.len() > 1, "{kinds:?}");
+		assert!(kinds.iter().all(|k| ["base58 P2PKH", "base58 P2SH", "bech32", "bech32m"].contains(&k.as_str())));
+		assert_eq!(describe_address(b"addr1"), None);
+		assert_eq!(describe_address(&[0xff, 0x00]), None);
+	}
+}
@@ -1,4 +1,14 @@
 pub mod store_interface;
+pub mod store_builder;
 pub mod store_tests;
 pub mod bench_common;
 pub mod bench_codecs;
+pub mod dyn_store;
+pub mod null_store;
+pub mod inspect;
+#[cfg(feature = "async-store")]
+pub mod async_store;
+#[cfg(feature = "serde-codec")]
+pub mod serde_codec;
+#[cfg(feature = "hashed-keys")]
+pub mod hashed_codec;
@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use core::bench_common::{
+	self, parse_duration, run_all_parallel, run_dictionary, run_index, run_plain, run_range, BenchLayout, LayoutSet,
+	NamedJob, RunLength,
+};
+use core::null_store::NullStore;
+
+/// Runs the ingest benches against `NullStore`, which discards every row, so the rows/s each
+/// reports is the harness alone: what generating and batching the rows costs.
+fn main() -> Result<(), String> {
+	let mut args = std::env::args().skip(1);
+	let mut total = 10_000_000u64;
+	let mut duration = None;
+	let mut base: Option<PathBuf> = None;
+	let mut layouts = LayoutSet::default();
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--total" => {
+				if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+					total = v;
+				}
+			},
+			"--duration" => {
+				if let Some(d) = args.next().and_then(|s| parse_duration(&s)) {
+					duration = Some(d);
+				}
+			},
+			"--dir" => {
+				if let Some(p) = args.next() {
+					base = Some(PathBuf::from(p));
+				}
+			},
+			"--layouts" | "--benches" => {
+				if let Some(list) = args.next() {
+					layouts = LayoutSet::parse(&list).unwrap_or_else(|err| panic!("--layouts: {err}"));
+				}
+			},
+			_ => {},
+		}
+	}
+
+	// Nothing is written under it; the runs only name their output after it.
+	let base = base.unwrap_or_else(|| std::env::temp_dir().join(Path::new("null_bench")));
+	let length = RunLength::new(total, duration);
+
+	bench_common::cleanup_dirs(&base, &layouts);
+
+	let jobs: Vec<NamedJob<String>> = vec![
+		{
+			let base = base.clone();
+			NamedJob::with_progress(
+				BenchLayout::Plain,
+				Box::new(move |board| run_plain(&base, length, board, null_factory)),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::with_progress(
+				BenchLayout::Index,
+				Box::new(move |board| run_index(&base, length, board, null_factory)),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::with_progress(
+				BenchLayout::Range,
+				Box::new(move |board| run_range(&base, length, board, null_factory)),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::with_progress(
+				BenchLayout::Dictionary,
+				Box::new(move |board| run_dictionary(&base, length, board, null_factory)),
+			)
+		},
+	];
+
+	run_all_parallel(jobs, &layouts)
+}
+
+fn null_factory<K, V>(_path: &Path) -> Result<NullStore<K, V>, String> {
+	Ok(NullStore::default())
+}
@@ -0,0 +1,116 @@
+use crate::store_interface::{CommitStats, ConsistencyReport, OpenMode, StoreRead, StoreWrite};
+use std::{marker::PhantomData, path::Path};
+
+/// Store that discards every write and finds nothing, so a bench run against it times the
+/// harness alone: key and value generation, batching and the ingest channel. Its throughput is
+/// the ceiling any backend can reach. `E` only sets its error type.
+pub struct NullStore<K, V, E = String> {
+	_ph: PhantomData<fn(K, V) -> E>,
+}
+
+impl<K, V, E> Default for NullStore<K, V, E> {
+	fn default() -> Self {
+		Self { _ph: PhantomData }
+	}
+}
+
+impl<K, V, E> StoreRead<K, V> for NullStore<K, V, E> {
+	type Error = E;
+
+	fn get_value(&self, _key: &K) -> Result<Option<V>, E> {
+		Ok(None)
+	}
+
+	fn get_value_raw(&self, _key: &K) -> Result<Option<Vec<u8>>, E> {
+		Ok(None)
+	}
+
+	fn get_key_for_value(&self, _value: &V) -> Result<Option<K>, E> {
+		Ok(None)
+	}
+
+	fn get_keys_for_value(&self, _value: &V) -> Result<Vec<K>, E> {
+		Ok(Vec::new())
+	}
+
+	fn distinct_values(&self) -> Result<impl Iterator<Item = Result<V, E>>, E> {
+		Ok(std::iter::empty())
+	}
+
+	fn verify_consistency(&self) -> Result<ConsistencyReport, E> {
+		Ok(ConsistencyReport { checked: 0, mismatches: Vec::new() })
+	}
+
+	fn first_key(&self) -> Result<Option<K>, E> {
+		Ok(None)
+	}
+
+	fn last_key(&self) -> Result<Option<K>, E> {
+		Ok(None)
+	}
+
+	fn approx_key_count(&self) -> Result<u64, E> {
+		Ok(0)
+	}
+}
+
+impl<K, V, E> StoreWrite<K, V> for NullStore<K, V, E> {
+	type Options = ();
+	type Layout = ();
+
+	fn open_with_options(_path: &Path, _layout: (), _options: ()) -> Result<Self, E> {
+		Ok(Self::default())
+	}
+
+	/// Nothing is ever stored, so every mode opens an empty one.
+	fn open_with_mode(_path: &Path, _layout: (), _options: (), _mode: OpenMode) -> Result<Self, E> {
+		Ok(Self::default())
+	}
+
+	fn open_read_only(_path: &Path, _layout: ()) -> Result<Self, E> {
+		Ok(Self::default())
+	}
+
+	/// Counts the rows of the batch; there is no codec to size them with, so bytes stay zero.
+	fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, E>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		let rows = items.into_iter().map(std::hint::black_box).count() as u64;
+		Ok(CommitStats { rows, ..CommitStats::default() })
+	}
+
+	fn flush(&mut self) -> Result<(), E> {
+		Ok(())
+	}
+
+	fn clear(&mut self) -> Result<(), E> {
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn commits_succeed_and_reads_find_nothing() {
+		let mut store = NullStore::<Vec<u8>, Vec<u8>>::default();
+		let rows: Vec<(Vec<u8>, Vec<u8>)> = (0..100u8).map(|i| (vec![i], vec![i, i])).collect();
+		let stats = store.commit_counted(rows.iter().map(|(k, v)| (k, v))).unwrap();
+		assert_eq!(stats.rows, 100);
+		store.commit_owned(rows.clone()).unwrap();
+		store.flush().unwrap();
+		for (k, v) in &rows {
+			assert_eq!(store.get_value(k), Ok(None));
+			assert_eq!(store.get_key_for_value(v), Ok(None));
+			assert_eq!(store.get_keys_for_value(v), Ok(Vec::new()));
+		}
+		assert_eq!(store.first_key(), Ok(None));
+		assert_eq!(store.last_key(), Ok(None));
+		assert_eq!(store.distinct_values().unwrap().count(), 0);
+		assert!(store.verify_consistency().unwrap().is_consistent());
+	}
+}
@@ -0,0 +1,71 @@
+use std::marker::PhantomData;
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::store_interface::StoreCodec;
+
+/// What deserializing failed with, re-exported so users need no bincode dependency of their own.
+pub use bincode::Error as BincodeError;
+
+/// Supplies an error value for bytes `SerdeCodec` cannot deserialize.
+pub trait InvalidValue<E> {
+	fn invalid_value(err: BincodeError) -> E;
+}
+
+/// Codec of any `Serialize + DeserializeOwned` type, encoded with bincode, so a struct can be a
+/// value without a hand-written codec. Encodings are variable-width and do not sort like the
+/// values: bincode writes integers little-endian and strings after their length. Lookups by
+/// value, reverse ones included, match encodings exactly and work; the order of a `Range`
+/// layout's btree column follows the bytes, not the values, and `EXPECTED_LEN` stays `None`.
+pub struct SerdeCodec<E, I>(PhantomData<(E, I)>);
+
+impl<T, E: 'static, I: InvalidValue<E> + 'static> StoreCodec<T> for SerdeCodec<E, I>
+where
+	T: Serialize + DeserializeOwned,
+{
+	type Error = E;
+	type Enc<'a> = Vec<u8> where T: 'a, E: 'a, I: 'a;
+	fn encode<'a>(value: &'a T) -> Self::Enc<'a> {
+		bincode::serialize(value).expect("bincode serializes any Serialize value into a Vec")
+	}
+	fn decode(bytes: &[u8]) -> Result<T, Self::Error> {
+		bincode::deserialize(bytes).map_err(I::invalid_value)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::Deserialize;
+
+	struct Invalid;
+
+	impl InvalidValue<String> for Invalid {
+		fn invalid_value(err: BincodeError) -> String {
+			err.to_string()
+		}
+	}
+
+	type Codec = SerdeCodec<String, Invalid>;
+
+	#[derive(Debug, PartialEq, Serialize, Deserialize)]
+	struct Output {
+		address: String,
+		amount: u64,
+	}
+
+	#[test]
+	fn struct_roundtrip_and_truncated_input() {
+		let output = Output { address: "bc1qaddress".into(), amount: 42 };
+		let bytes = <Codec as StoreCodec<Output>>::encode(&output);
+		assert_eq!(<Codec as StoreCodec<Output>>::decode(&bytes), Ok(output));
+		assert!(<Codec as StoreCodec<Output>>::decode(&bytes[..bytes.len() - 1]).is_err());
+		assert_eq!(<Codec as StoreCodec<Output>>::EXPECTED_LEN, None);
+	}
+
+	#[test]
+	fn encodings_do_not_sort_like_values() {
+		let (one, big) = (<Codec as StoreCodec<u32>>::encode(&1), <Codec as StoreCodec<u32>>::encode(&256));
+		assert!(big < one, "little-endian 256 sorts before 1");
+	}
+}
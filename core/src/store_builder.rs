@@ -0,0 +1,174 @@
+use std::{
+	ffi::OsStr,
+	fs, io,
+	marker::PhantomData,
+	path::{Path, PathBuf},
+};
+
+use crate::store_interface::{OpenMode, StoreWrite};
+
+/// How eagerly a store gets its writes to disk; each backend maps it to its nearest setting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Durability {
+	/// Writes reach disk when the backend gets to it, at the latest on `flush`.
+	#[default]
+	Relaxed,
+	/// `flush` does not return before the written data is synced.
+	Synced,
+}
+
+/// Knobs `StoreBuilder` sets on a backend's options. A backend without an equivalent for a knob
+/// leaves the default setter, which ignores it.
+pub trait BuilderOptions: Default {
+	fn set_coalesce_duplicates(&mut self, coalesce: bool);
+
+	/// Rows per segment or write buffer, for backends that size them by row count.
+	fn set_segment_size(&mut self, _rows: usize) {}
+
+	fn set_durability(&mut self, _durability: Durability) {}
+
+	/// Keeps the write-ahead log or journal in `dir` instead of under the store's path, e.g. on a
+	/// faster device. Backends without a log of their own ignore it.
+	fn set_wal_dir(&mut self, _dir: &Path) {}
+
+	/// Whether dropping the store flushes what commits left in memory. Backends whose commits
+	/// reach their files without a `flush` ignore it.
+	fn set_flush_on_drop(&mut self, _flush: bool) {}
+}
+
+/// Opens any backend from a path, a layout and backend-neutral settings:
+///
+/// `StoreBuilder::new(dir, Layout::plain()).durability(Durability::Synced).open()`
+///
+/// The path may name a directory or, for single-file backends, the database file itself; see
+/// `db_file`.
+pub struct StoreBuilder<K, V, S: StoreWrite<K, V>> {
+	path: PathBuf,
+	layout: S::Layout,
+	options: S::Options,
+	mode: OpenMode,
+	_marker: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V, S: StoreWrite<K, V>> StoreBuilder<K, V, S> {
+	pub fn new(path: impl AsRef<Path>, layout: S::Layout) -> Self {
+		Self {
+			path: path.as_ref().to_path_buf(),
+			layout,
+			options: S::Options::default(),
+			mode: OpenMode::default(),
+			_marker: PhantomData,
+		}
+	}
+
+	pub fn path(self, path: impl AsRef<Path>) -> Self {
+		Self { path: path.as_ref().to_path_buf(), ..self }
+	}
+
+	pub fn layout(self, layout: S::Layout) -> Self {
+		Self { layout, ..self }
+	}
+
+	pub fn mode(self, mode: OpenMode) -> Self {
+		Self { mode, ..self }
+	}
+
+	/// Replaces the backend-specific options wholesale.
+	pub fn options(self, options: S::Options) -> Self {
+		Self { options, ..self }
+	}
+
+	/// Adjusts backend-specific options the neutral setters do not cover.
+	pub fn configure(mut self, f: impl FnOnce(&mut S::Options)) -> Self {
+		f(&mut self.options);
+		self
+	}
+
+	pub fn open(self) -> Result<S, S::Error>
+	where
+		S: Sized,
+	{
+		S::open_with_mode(&self.path, self.layout, self.options, self.mode)
+	}
+
+	/// Opens the store at the path for reads only; options and mode do not apply.
+	pub fn open_read_only(self) -> Result<S, S::Error>
+	where
+		S: Sized,
+	{
+		S::open_read_only(&self.path, self.layout)
+	}
+}
+
+impl<K, V, S> StoreBuilder<K, V, S>
+where
+	S: StoreWrite<K, V>,
+	S::Options: BuilderOptions,
+{
+	pub fn coalesce_duplicates(self, coalesce: bool) -> Self {
+		self.configure(|options| options.set_coalesce_duplicates(coalesce))
+	}
+
+	pub fn segment_size(self, rows: usize) -> Self {
+		self.configure(|options| options.set_segment_size(rows))
+	}
+
+	pub fn durability(self, durability: Durability) -> Self {
+		self.configure(|options| options.set_durability(durability))
+	}
+
+	pub fn wal_dir(self, dir: impl AsRef<Path>) -> Self {
+		self.configure(|options| options.set_wal_dir(dir.as_ref()))
+	}
+
+	pub fn flush_on_drop(self, flush: bool) -> Self {
+		self.configure(|options| options.set_flush_on_drop(flush))
+	}
+}
+
+impl<K, V, S> Clone for StoreBuilder<K, V, S>
+where
+	S: StoreWrite<K, V>,
+	S::Options: Clone,
+{
+	fn clone(&self) -> Self {
+		Self {
+			path: self.path.clone(),
+			layout: self.layout,
+			options: self.options.clone(),
+			mode: self.mode,
+			_marker: PhantomData,
+		}
+	}
+}
+
+/// The database file of a single-file backend: `path` itself if it carries `extension`,
+/// otherwise `db.<extension>` inside the directory `path`.
+pub fn db_file(path: &Path, extension: &str) -> PathBuf {
+	if path.extension() == Some(OsStr::new(extension)) {
+		path.to_path_buf()
+	} else {
+		path.join(format!("db.{extension}"))
+	}
+}
+
+/// `db_file`, creating the directory it lives in.
+pub fn db_file_path(path: &Path, extension: &str) -> io::Result<PathBuf> {
+	let file = db_file(path, extension);
+	if let Some(parent) = file.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+		fs::create_dir_all(parent)?;
+	}
+	Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn db_file_takes_a_directory_or_the_file_itself() {
+		assert_eq!(db_file(Path::new("runs/plain"), "redb"), Path::new("runs/plain/db.redb"));
+		assert_eq!(db_file(Path::new("runs/plain.redb"), "redb"), Path::new("runs/plain.redb"));
+		assert_eq!(db_file(Path::new("runs/plain.mdbx"), "redb"), Path::new("runs/plain.mdbx/db.redb"));
+	}
+}
@@ -1,20 +1,204 @@
-use std::path::Path;
+use std::{
+	borrow::Cow,
+	collections::{BTreeSet, HashSet, VecDeque},
+	fmt,
+	io,
+	path::Path,
+	thread,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 /// Borrow-friendly codec shared by store implementations.
 pub trait StoreCodec<T> {
 	type Error;
 	type Enc<'a>: AsRef<[u8]> where T: 'a, Self: 'a;
+	/// Length of every encoding of a fixed-width codec, `None` for variable-length ones.
+	const EXPECTED_LEN: Option<usize> = None;
 	fn encode<'a>(value: &'a T) -> Self::Enc<'a>;
 	fn decode(bytes: &[u8]) -> Result<T, Self::Error>;
 }
 
+/// Marks a key codec whose `decode` cannot recover a key from its encoding, e.g. because it is a
+/// hash of it. Stores write and look up such keys like any other, while every read handing keys
+/// back, reverse lookups by value and key bounds among them, fails with the codec's decode error.
+pub trait WriteOnlyKey {}
+
+/// Decoding into a view of the read buffer instead of an owned `T`, for value types that have a
+/// borrowed form; codecs of the others decode owned into `Ref<'a> = T`.
+pub trait BorrowDecode<T>: StoreCodec<T> {
+	type Ref<'a> where Self: 'a;
+	fn decode_borrowed<'a>(bytes: &'a [u8]) -> Result<Self::Ref<'a>, Self::Error>;
+}
+
+/// What a commit wrote across all columns of the layout, secondary ones included, so a value
+/// the `Dictionary` layout already knows adds no birth-key mappings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommitStats {
+	/// Column entries written.
+	pub rows: u64,
+	pub key_bytes: u64,
+	pub value_bytes: u64,
+}
+
+impl CommitStats {
+	pub fn record(&mut self, key: &[u8], value: &[u8]) {
+		self.rows += 1;
+		self.key_bytes += key.len() as u64;
+		self.value_bytes += value.len() as u64;
+	}
+
+	pub fn add(&mut self, other: CommitStats) {
+		self.rows += other.rows;
+		self.key_bytes += other.key_bytes;
+		self.value_bytes += other.value_bytes;
+	}
+}
+
+/// What a flush moved from memory to disk. Backends that keep no memtable of their own write
+/// through on commit and report zero.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FlushStats {
+	pub segments_written: u64,
+	pub rows_flushed: u64,
+	/// On-disk size of the written segments.
+	pub bytes_flushed: u64,
+}
+
+impl FlushStats {
+	pub fn record(&mut self, rows: u64, bytes: u64) {
+		self.segments_written += 1;
+		self.rows_flushed += rows;
+		self.bytes_flushed += bytes;
+	}
+
+	pub fn add(&mut self, other: FlushStats) {
+		self.segments_written += other.segments_written;
+		self.rows_flushed += other.rows_flushed;
+		self.bytes_flushed += other.bytes_flushed;
+	}
+}
+
 pub trait StoreRead<K, V> {
 	type Error;
 	fn get_value(&self, key: &K) -> Result<Option<V>, Self::Error>;
+	/// `key`'s value as stored, the `VC::encode` of what `get_value` returns, for callers that
+	/// hash or forward it and need neither the decode nor the value type.
+	fn get_value_raw(&self, key: &K) -> Result<Option<Vec<u8>>, Self::Error>;
+	/// The key of a unique index; for layouts that map one value to many keys this is a key,
+	/// the lexicographically smallest encoded one, not the only one.
 	fn get_key_for_value(&self, value: &V) -> Result<Option<K>, Self::Error>;
+	/// Every key of `value`, in ascending encoded order, the order of the `value||key` btree; every
+	/// backend returns the same order for the same data. It is the keys' own order only for codecs
+	/// that encode order-preserving, e.g. big-endian; see `get_keys_for_value_sorted`.
 	fn get_keys_for_value(&self, value: &V) -> Result<Vec<K>, Self::Error>;
+	/// `get_keys_for_value` in ascending decoded order, for key codecs whose encoding does not sort
+	/// like the keys, e.g. little-endian integers.
+	fn get_keys_for_value_sorted(&self, value: &V) -> Result<Vec<K>, Self::Error>
+	where
+		K: Ord,
+	{
+		let mut keys = self.get_keys_for_value(value)?;
+		keys.sort();
+		Ok(keys)
+	}
+	/// The keys holding `value`, in `get_keys_for_value` order, each paired with it. Keys since
+	/// overwritten with another value keep their btree entry under this one and are left out, so
+	/// every pair is what `get_value` returns.
+	fn get_entries_for_value(&self, value: &V) -> Result<Vec<(K, V)>, Self::Error>
+	where
+		V: PartialEq,
+	{
+		let mut entries = Vec::new();
+		for key in self.get_keys_for_value(value)? {
+			if let Some(current) = self.get_value(&key)?
+				&& current == *value
+			{
+				entries.push((key, current));
+			}
+		}
+		Ok(entries)
+	}
+	/// `get_keys_for_value` of each value, index-aligned with `values`. Backends answer the whole
+	/// batch from one read view, walking the btree in encoded value order.
+	fn get_keys_for_values(&self, values: &[V]) -> Result<Vec<Vec<K>>, Self::Error> {
+		values.iter().map(|value| self.get_keys_for_value(value)).collect()
+	}
+	/// Every value of a `Dictionary` store once, read from `birth_key_to_value`; the other
+	/// layouts keep no per-value column and return an error.
+	fn distinct_values(&self) -> Result<impl Iterator<Item = Result<V, Self::Error>>, Self::Error>;
+	/// Walks the primary column and checks every entry against the secondary columns of the
+	/// layout, collecting each mismatch instead of stopping at the first; `Plain` has nothing to check.
+	fn verify_consistency(&self) -> Result<ConsistencyReport, Self::Error>;
+	/// The smallest key of the primary column in encoded order, `None` for an empty store. Lets a
+	/// resumed ingest pick up after `last_key` instead of starting over. Backends that hash the
+	/// primary column cannot order its keys and return an error.
+	fn first_key(&self) -> Result<Option<K>, Self::Error>;
+	/// The largest key of the primary column in encoded order, `None` for an empty store.
+	fn last_key(&self) -> Result<Option<K>, Self::Error>;
+	/// Whether `first_key` and `last_key` can answer at all; `false` for backends that hash the
+	/// primary column, so a caller can tell their error from a failed read.
+	fn orders_keys(&self) -> bool {
+		true
+	}
+	/// A cheap estimate of the keys in the primary column, from the backend's statistics rather
+	/// than a scan, for progress and ETA reporting. Overwrites not yet compacted away may count
+	/// more than once and statistics may lag behind recent writes, so it can be off either way.
+	fn approx_key_count(&self) -> Result<u64, Self::Error>;
 }
 
+/// What `verify_consistency` found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConsistencyReport {
+	/// Primary entries checked: `key_to_value`, or `key_to_birth_key` for `Dictionary`.
+	pub checked: u64,
+	pub mismatches: Vec<Mismatch>,
+}
+
+impl ConsistencyReport {
+	pub fn is_consistent(&self) -> bool {
+		self.mismatches.is_empty()
+	}
+}
+
+/// A primary entry whose secondary counterpart is missing or points elsewhere, in encoded bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+	/// `UniqueIndex`: `value_to_key` lacks `value` or maps it to another key.
+	MissingBackEdge { key: Vec<u8>, value: Vec<u8> },
+	/// `Range` and `Dictionary`: the btree lacks the composite of `head` (value or birth key) and `key`.
+	MissingBtreeEntry { head: Vec<u8>, key: Vec<u8> },
+	/// `Dictionary`: `key_to_birth_key` points to a birth key `birth_key_to_value` does not hold.
+	DanglingBirthKey { key: Vec<u8>, birth_key: Vec<u8> },
+}
+
+/// Whether opening a path may create a store there or must find one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpenMode {
+	/// Opens the store at the path, creating an empty one if there is none.
+	#[default]
+	CreateIfMissing,
+	/// Fails unless the path holds a store, so a mistyped path cannot pass for an empty store.
+	MustExist,
+	/// Fails if the path already holds a store.
+	CreateNew,
+}
+
+impl OpenMode {
+	/// Rejects a path that does or, per `exists`, does not hold a store, as `mode` requires.
+	pub fn check(self, path: &Path, exists: bool) -> Result<(), String> {
+		match (self, exists) {
+			(OpenMode::MustExist, false) => Err(format!("no store at {}", path.display())),
+			(OpenMode::CreateNew, true) => Err(format!("a store already exists at {}", path.display())),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// There is no per-key delete here, only `clear`; the redb, fjall, rocksdb and mdbx stores have an
+/// inherent `delete`, which drops a `Dictionary` birth key's `value_to_birth_key` and
+/// `birth_key_to_value` entries once its `birth_key||key` prefix in `birth_key_key_btree` holds no
+/// live key. Overwrites never prune reverse mappings, so a key overwritten with a new value is still
+/// listed under its old one until it is deleted.
 pub trait StoreWrite<K, V>: StoreRead<K, V> {
 	type Options: Default;
 	type Layout: Copy;
@@ -23,40 +207,640 @@ pub trait StoreWrite<K, V>: StoreRead<K, V> {
 	where
 		Self: Sized;
 
-	fn commit<'a, I>(&mut self, items: I) -> Result<(), Self::Error>
+	/// `open_with_options` after checking `mode` against whether `path` already holds a store.
+	fn open_with_mode(
+		path: &Path,
+		layout: Self::Layout,
+		options: Self::Options,
+		mode: OpenMode,
+	) -> Result<Self, Self::Error>
+	where
+		Self: Sized;
+
+	/// Opens an existing store for reads only; `commit`, `flush` and `clear` return an error.
+	fn open_read_only(path: &Path, layout: Self::Layout) -> Result<Self, Self::Error>
+	where
+		Self: Sized;
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, Self::Error>
 	where
 		I: IntoIterator<Item = (&'a K, &'a V)>,
 		K: 'a,
 		V: 'a;
 
+	fn commit<'a, I>(&mut self, items: I) -> Result<(), Self::Error>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.commit_counted(items).map(|_| ())
+	}
+
+	/// `commit` for owned pairs, e.g. a batch moved out of a channel, so the caller need not keep
+	/// it alive to lend it out; the pairs are dropped once written.
+	fn commit_owned<I>(&mut self, items: I) -> Result<(), Self::Error>
+	where
+		I: IntoIterator<Item = (K, V)>,
+	{
+		let items: Vec<(K, V)> = items.into_iter().collect();
+		self.commit(items.iter().map(|(k, v)| (k, v)))
+	}
+
+	/// Commits the batch in encoded-key order; of duplicate keys the last one wins. Backends that
+	/// benefit from ordered inserts sort first, the rest commit the batch as given.
+	fn commit_sorted<'a, I>(&mut self, items: I) -> Result<(), Self::Error>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.commit(items)
+	}
+
+	/// `commit` that tries the batch again, up to `max_retries` more times, while it fails with a
+	/// retryable error, waiting `RETRY_BACKOFF` and twice as long after every further failure. A
+	/// commit is an upsert, so replaying a batch that partly landed writes the same rows.
+	fn commit_retry<'a, I>(&mut self, items: I, max_retries: u32) -> Result<(), Self::Error>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+		Self::Error: Retryable,
+	{
+		let batch: Vec<(&K, &V)> = items.into_iter().collect();
+		let mut backoff = RETRY_BACKOFF;
+		for _ in 0..max_retries {
+			match self.commit(batch.iter().copied()) {
+				Err(err) if err.is_retryable() => {
+					thread::sleep(backoff);
+					backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+				},
+				done => return done,
+			}
+		}
+		self.commit(batch)
+	}
+
 	fn flush(&mut self) -> Result<(), Self::Error>;
 
-	fn set_progress(&mut self, _label: &str, _total: u64) {}
+	/// `flush`, reporting what it wrote, so an empty flush can be told from one that wrote
+	/// segments. Defaults to zero stats.
+	fn flush_reporting(&mut self) -> Result<FlushStats, Self::Error> {
+		self.flush().map(|_| FlushStats::default())
+	}
+
+	/// Brings the store into its read-optimized on-disk state, typically a full compaction, so
+	/// read benchmarks of different backends start from comparable layouts. Defaults to `flush`.
+	fn settle_for_reads(&mut self) -> Result<(), Self::Error> {
+		self.flush()
+	}
+
+	/// Removes every entry from all columns while keeping the store open and its directory intact.
+	fn clear(&mut self) -> Result<(), Self::Error>;
+
+	/// Reports the rows of the following commits and merges to stdout under `label`.
+	fn set_progress(&mut self, label: &str, total: u64) {
+		self.set_progress_tracker(ProgressTracker::new(label.to_string(), total));
+	}
+
+	/// Reports the rows of the following commits and merges to `tracker`, e.g. one built
+	/// `with_sink` to route them elsewhere. Stores without progress reporting ignore it.
+	fn set_progress_tracker(&mut self, _tracker: ProgressTracker) {}
+}
+
+/// First wait of `StoreWrite::commit_retry` after a failed attempt.
+pub const RETRY_BACKOFF: Duration = Duration::from_millis(1);
+/// Longest wait of `StoreWrite::commit_retry` between two attempts.
+pub const MAX_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Store errors that tell a transient failure, such as a busy or conflicting transaction under
+/// contention, which the same operation may get past when tried again, from a fatal one.
+pub trait Retryable {
+	fn is_retryable(&self) -> bool;
+}
+
+/// Stores of string errors, like the in-memory test ones, have nothing to retry.
+impl Retryable for String {
+	fn is_retryable(&self) -> bool {
+		false
+	}
+}
+
+/// Whether an io error is worth retrying: an interrupted, would-block or timed out call.
+pub fn is_transient_io(err: &io::Error) -> bool {
+	matches!(err.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+}
+
+/// `Plain` stores opened with expiring entries, an option of each backend. Every value is kept
+/// behind its expiry (`stored_value`), `get_value` returns `None` once that has passed and
+/// `purge_expired` deletes such entries; until then they still take space and count as keys.
+pub trait ExpiringStore<K, V>: StoreWrite<K, V> {
+	/// `commit` of entries that expire `ttl` from now; a plain `commit` writes entries that never do.
+	fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> Result<(), Self::Error>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a;
+
+	/// Deletes the expired entries, returning how many.
+	fn purge_expired(&mut self) -> Result<u64, Self::Error>;
+}
+
+/// Rejects expiring entries in a layout other than `Plain`, the only one that keeps them.
+pub fn check_expiring(expiring: bool, plain: bool) -> Result<(), String> {
+	if expiring && !plain {
+		return Err("expiring entries need the Plain layout".into())
+	}
+	Ok(())
+}
+
+/// Rejects opening a store with a layout other than the `stored` one its backend recorded at
+/// creation, which would read its columns as something they are not or add empty ones next to them.
+pub fn check_layout(stored: &str, requested: &str) -> Result<(), String> {
+	if stored != requested {
+		return Err(format!("store was created with the {stored} layout, not {requested}"))
+	}
+	Ok(())
+}
+
+/// For a store from before its layout was recorded: rejects a `layout` of the `expected` column
+/// ids unless the ids `found` in the store, by `column_id`, are the same ones, or there are none
+/// yet to tell it by. Ids are compared as sets, so a layout `from` a nonzero base matches the
+/// store it wrote and a column split over several files counts once.
+pub fn check_columns<F, E>(found: F, layout: &str, expected: E) -> Result<(), String>
+where
+	F: IntoIterator<Item = usize>,
+	E: IntoIterator<Item = usize>,
+{
+	let found: BTreeSet<usize> = found.into_iter().collect();
+	let expected: BTreeSet<usize> = expected.into_iter().collect();
+	if !found.is_empty() && found != expected {
+		return Err(format!("store has columns {found:?}, the {layout} layout has {expected:?}"))
+	}
+	Ok(())
+}
+
+/// The id in a `col<id>` column, table, file or directory name, e.g. `col2`, `col2.manifest` or
+/// `col2_shard0`; `None` for any other name.
+pub fn column_id(name: &str) -> Option<usize> {
+	let rest = name.strip_prefix("col")?;
+	let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+	if end == 0 || !matches!(rest[end..].chars().next(), None | Some('.' | '_')) {
+		return None
+	}
+	rest[..end].parse().ok()
+}
+
+/// Rejects an empty value encoding in `UniqueIndex` and `Dictionary`, which key a reverse column by
+/// the value, where an empty key is invalid. `Plain` and `Range` store empty values like any other.
+pub fn check_indexed_value(value: &[u8]) -> Result<(), String> {
+	if value.is_empty() {
+		return Err("empty value cannot be indexed; UniqueIndex and Dictionary key by the value".into())
+	}
+	Ok(())
+}
+
+/// `C::decode` of `bytes` read back from a column, after checking them against `C::EXPECTED_LEN`.
+/// Bytes written by another codec, e.g. with the key and value codecs swapped, fail with their
+/// length and the decoded type through `invalid` instead of decoding into garbage.
+pub fn decode_exact<T, C: StoreCodec<T>>(bytes: &[u8], invalid: impl FnOnce(String) -> C::Error) -> Result<T, C::Error> {
+	match C::EXPECTED_LEN {
+		Some(len) if bytes.len() != len => Err(invalid(format!(
+			"{} bytes where {} expects {len}; are the key and value codecs swapped?",
+			bytes.len(),
+			std::any::type_name::<T>()
+		))),
+		_ => C::decode(bytes),
+	}
+}
+
+/// Expiry of an entry committed without a TTL.
+pub const NEVER_EXPIRES: u64 = u64::MAX;
+
+/// Unix time in milliseconds, the unit of entry expiries.
+pub fn unix_millis() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Expiry of an entry committed now with `ttl`.
+pub fn expiry_after(ttl: Duration) -> u64 {
+	unix_millis().saturating_add(ttl.as_millis().try_into().unwrap_or(u64::MAX))
+}
+
+/// The bytes a `Plain` store keeps for `value`: prefixed by `expires_at` as 8 big-endian bytes if
+/// the store has expiring entries, `value` itself otherwise.
+pub fn stored_value(value: &[u8], expires_at: Option<u64>) -> Cow<'_, [u8]> {
+	match expires_at {
+		Some(expires_at) => Cow::Owned([&expires_at.to_be_bytes()[..], value].concat()),
+		None => Cow::Borrowed(value),
+	}
+}
+
+/// The value behind the expiry of `stored`, `None` if it expired at or before `now`; errors if
+/// `stored` is too short to hold an expiry.
+pub fn unexpired(stored: &[u8], now: u64) -> Result<Option<&[u8]>, String> {
+	let Some((expiry, value)) = stored.split_first_chunk::<8>() else {
+		return Err(format!("stored value of {} bytes lacks its expiry", stored.len()))
+	};
+	Ok((u64::from_be_bytes(*expiry) > now).then_some(value))
+}
+
+/// Orders a batch by encoded key, keeping only the last write of each key.
+pub fn sort_by_encoded_key<'a, K, V, C, I>(items: I) -> Vec<(&'a K, &'a V)>
+where
+	C: StoreCodec<K>,
+	I: IntoIterator<Item = (&'a K, &'a V)>,
+{
+	let mut batch: Vec<(Vec<u8>, (&'a K, &'a V))> =
+		items.into_iter().map(|(k, v)| (C::encode(k).as_ref().to_vec(), (k, v))).collect();
+	// Stable sort keeps writes of one key in arrival order; reversing lets `dedup_by` keep the newest.
+	batch.sort_by(|a, b| a.0.cmp(&b.0));
+	batch.reverse();
+	batch.dedup_by(|a, b| a.0 == b.0);
+	batch.reverse();
+	batch.into_iter().map(|(_, item)| item).collect()
+}
+
+/// Encodes `values` and orders them by encoding, each paired with its index in `values`.
+pub fn sort_by_encoding<V, C: StoreCodec<V>>(values: &[V]) -> Vec<(usize, Vec<u8>)> {
+	let mut sorted: Vec<(usize, Vec<u8>)> =
+		values.iter().enumerate().map(|(idx, value)| (idx, C::encode(value).as_ref().to_vec())).collect();
+	sorted.sort_by(|a, b| a.1.cmp(&b.1));
+	sorted
+}
+
+/// Drops every write a later one in the batch overwrites, by encoded key, and keeps the rest in
+/// batch order.
+pub fn coalesce_by_encoded_key<'a, K, V, C, I>(items: I) -> Vec<(&'a K, &'a V)>
+where
+	C: StoreCodec<K>,
+	I: IntoIterator<Item = (&'a K, &'a V)>,
+{
+	let batch: Vec<(&'a K, &'a V)> = items.into_iter().collect();
+	let mut seen: HashSet<Vec<u8>> = HashSet::with_capacity(batch.len());
+	let mut out: Vec<(&'a K, &'a V)> =
+		batch.into_iter().rev().filter(|(k, _)| seen.insert(C::encode(k).as_ref().to_vec())).collect();
+	out.reverse();
+	out
+}
+
+/// Seek prefix of the btree keys under `head`: its length as a LEB128 varint, then its bytes.
+/// The length makes the boundary explicit, so a scan for `v` never reaches `vv||key`.
+pub fn composite_prefix(head: &[u8]) -> Vec<u8> {
+	let mut out = Vec::with_capacity(head.len() + 2);
+	let mut len = head.len();
+	while len >= 0x80 {
+		out.push(len as u8 | 0x80);
+		len >>= 7;
+	}
+	out.push(len as u8);
+	out.extend_from_slice(head);
+	out
+}
+
+/// Btree key `head||tail` of the `Range` and `Dictionary` layouts; `split_composite` undoes it.
+pub fn composite_key(head: &[u8], tail: &[u8]) -> Vec<u8> {
+	let mut out = composite_prefix(head);
+	out.extend_from_slice(tail);
+	out
+}
+
+/// Splits a `composite_key` into `(head, tail)`, or `None` if the length prefix is truncated.
+pub fn split_composite(key: &[u8]) -> Option<(&[u8], &[u8])> {
+	let mut len = 0usize;
+	for (idx, byte) in key.iter().enumerate().take(10) {
+		len |= ((byte & 0x7f) as usize) << (7 * idx);
+		if byte & 0x80 == 0 {
+			let rest = &key[idx + 1..];
+			return (len <= rest.len()).then(|| rest.split_at(len))
+		}
+	}
+	None
 }
 
+/// The ETA uses the insert rate over this trailing window, which tracks slowdowns from growing
+/// data and compaction that the all-time average hides.
+const PROGRESS_RATE_WINDOW: Duration = Duration::from_secs(30);
+/// Minimum spacing of the rate samples, which bounds the ring to about 30 entries.
+const PROGRESS_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Spacing of the reports a `ProgressTracker` hands to its sink.
+const PROGRESS_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// One report of a `ProgressTracker`; `Display` renders the line `StdoutSink` prints.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgressEvent {
+    pub label: String,
+    pub inserted: u64,
+    /// Rows expected in total, 0 if unknown.
+    pub total: u64,
+    /// Rows per second since the tracker started.
+    pub rate: f64,
+    /// Time left at the rate of the trailing window; `None` without a total or recent progress.
+    pub eta: Option<Duration>,
+}
+
+impl fmt::Display for ProgressEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: progress {}/{} (~{:.1} rows/s", self.label, self.inserted, self.total, self.rate)?;
+        if let Some(eta) = self.eta {
+            write!(f, ", ETA {}m{:02}s", eta.as_secs() / 60, eta.as_secs() % 60)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Receives the reports of a `ProgressTracker`, e.g. to route them to a log, a channel or a
+/// metrics system. Closures taking a `&ProgressEvent` are sinks too. `Sync` keeps the stores that
+/// own a tracker shareable across threads.
+pub trait ProgressSink: Send + Sync {
+    fn report(&mut self, event: &ProgressEvent);
+}
+
+/// Prints each report as a line on stdout; the sink of `ProgressTracker::new`.
+pub struct StdoutSink;
+
+impl ProgressSink for StdoutSink {
+    fn report(&mut self, event: &ProgressEvent) {
+        println!("{event}");
+    }
+}
+
+impl<F: FnMut(&ProgressEvent) + Send + Sync> ProgressSink for F {
+    fn report(&mut self, event: &ProgressEvent) {
+        self(event)
+    }
+}
 
 pub struct ProgressTracker {
     label: String,
     total: u64,
     inserted: u64,
-    start: std::time::Instant,
-    last_report: std::time::Instant,
+    start: Instant,
+    last_report: Instant,
+    /// `(time, inserted)` samples, oldest first; the first one is at or before the window start.
+    samples: VecDeque<(Instant, u64)>,
+    sink: Box<dyn ProgressSink>,
 }
 
 impl ProgressTracker {
     pub fn new(label: String, total: u64) -> Self {
-        let now = std::time::Instant::now();
-        Self { label, total, inserted: 0, start: now, last_report: now }
+        let now = Instant::now();
+        Self {
+            label,
+            total,
+            inserted: 0,
+            start: now,
+            last_report: now,
+            samples: VecDeque::from([(now, 0)]),
+            sink: Box::new(StdoutSink),
+        }
+    }
+
+    pub fn with_sink(self, sink: impl ProgressSink + 'static) -> Self {
+        Self { sink: Box::new(sink), ..self }
     }
 
     pub fn record(&mut self, delta: u64) {
+        self.record_at(delta, Instant::now());
+    }
+
+    /// `record` against an explicit clock; reports to the sink when a report is due.
+    fn record_at(&mut self, delta: u64, now: Instant) {
         self.inserted = self.inserted.saturating_add(delta);
-        let now = std::time::Instant::now();
-        if now.duration_since(self.last_report).as_secs() >= 5 {
-            let elapsed = now.duration_since(self.start);
-            let speed = self.inserted as f64 / elapsed.as_secs_f64();
-            println!("{}: progress {}/{} (~{:.1} rows/s)", self.label, self.inserted, self.total, speed);
-            self.last_report = now;
+        if self.samples.back().is_none_or(|(at, _)| now.duration_since(*at) >= PROGRESS_SAMPLE_INTERVAL) {
+            self.samples.push_back((now, self.inserted));
+        }
+        while self.samples.len() > 1 && now.duration_since(self.samples[1].0) >= PROGRESS_RATE_WINDOW {
+            self.samples.pop_front();
+        }
+        if now.duration_since(self.last_report) < PROGRESS_REPORT_INTERVAL {
+            return
+        }
+        self.last_report = now;
+        let event = ProgressEvent {
+            label: self.label.clone(),
+            inserted: self.inserted,
+            total: self.total,
+            rate: self.inserted as f64 / now.duration_since(self.start).as_secs_f64(),
+            eta: self.eta_at(now),
+        };
+        self.sink.report(&event);
+    }
+
+    /// Time left at the windowed rate; `None` without a known total or before any progress.
+    fn eta_at(&self, now: Instant) -> Option<Duration> {
+        let &(since, base) = self.samples.front()?;
+        let progressed = self.inserted.saturating_sub(base);
+        if self.total == 0 || progressed == 0 || since >= now {
+            return None
         }
+        let rate = progressed as f64 / now.duration_since(since).as_secs_f64();
+        Some(Duration::from_secs_f64(self.total.saturating_sub(self.inserted) as f64 / rate))
     }
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::{Arc, Mutex};
+
+	#[test]
+	fn commit_owned_takes_a_moved_batch() {
+		let mut store = crate::store_tests::MemStore::<String>::default();
+		let batch: Vec<(Vec<u8>, Vec<u8>)> = (0..3u8).map(|i| (vec![i], vec![b'v', i])).collect();
+		store.commit_owned(batch).unwrap();
+		let mut streamed = vec![(vec![1], b"again".to_vec()), (vec![9], b"new".to_vec())];
+		store.commit_owned(streamed.drain(..)).unwrap();
+		assert_eq!(store.get_value(&vec![0]).unwrap(), Some(b"v\0".to_vec()));
+		assert_eq!(store.get_value(&vec![1]).unwrap(), Some(b"again".to_vec()));
+		assert_eq!(store.get_value(&vec![2]).unwrap(), Some(b"v\x02".to_vec()));
+		assert_eq!(store.get_value(&vec![9]).unwrap(), Some(b"new".to_vec()));
+	}
+
+	#[test]
+	fn columns_compare_as_sets_of_ids() {
+		assert!(check_columns([], "range", [0, 1]).is_ok());
+		assert!(check_columns([1, 0, 1], "range", [0, 1]).is_ok());
+		assert!(check_columns([2, 3], "range", [2, 3]).is_ok());
+		assert!(check_columns([0, 1], "range", [2, 3]).is_err());
+		assert!(check_columns([0, 1, 2, 3], "range", [0, 1]).is_err());
+		let err = check_columns([0], "range", [0, 1]).unwrap_err();
+		assert_eq!(err, "store has columns {0}, the range layout has {0, 1}");
+	}
+
+	/// A tracker whose reports are collected in the returned vector.
+	fn capturing(total: u64) -> (ProgressTracker, Arc<Mutex<Vec<ProgressEvent>>>) {
+		let events = Arc::new(Mutex::new(Vec::new()));
+		let sink = Arc::clone(&events);
+		let tracker = ProgressTracker::new("rows".to_string(), total)
+			.with_sink(move |event: &ProgressEvent| sink.lock().unwrap().push(event.clone()));
+		(tracker, events)
+	}
+
+	#[test]
+	fn sink_receives_a_report_every_five_seconds() {
+		let (mut tracker, events) = capturing(0);
+		let t0 = tracker.start;
+		for millis in (500..=23_000).step_by(500) {
+			tracker.record_at(50, t0 + Duration::from_millis(millis));
+		}
+		let events = events.lock().unwrap();
+		let inserted: Vec<u64> = events.iter().map(|e| e.inserted).collect();
+		assert_eq!(inserted, [500, 1000, 1500, 2000]);
+		assert!(events.iter().all(|e| e.label == "rows" && (e.rate - 100.0).abs() < 1e-9));
+		assert_eq!(events[0].to_string(), "rows: progress 500/0 (~100.0 rows/s)");
+	}
+
+	#[test]
+	fn eta_follows_the_recent_rate() {
+		let (mut tracker, events) = capturing(100_000);
+		let t0 = tracker.start;
+		let at = |secs: u64| t0 + Duration::from_secs(secs);
+		// 1000 rows/s for 10s leaves 90_000 rows, 90s.
+		for secs in 1..=10 {
+			tracker.record_at(1000, at(secs));
+		}
+		assert_eq!(events.lock().unwrap().len(), 2);
+		assert!(events.lock().unwrap()[1].to_string().ends_with(", ETA 1m30s)"));
+		let eta = tracker.eta_at(at(10)).unwrap().as_secs_f64();
+		assert!((eta - 90.0).abs() < 1.0, "eta {eta}");
+		// 100 rows/s for 40s: the window only sees the slow rate, 86_000 rows left is 860s where
+		// the all-time average of 280 rows/s would say ~307s.
+		for secs in 11..=50 {
+			tracker.record_at(100, at(secs));
+		}
+		let eta = tracker.eta_at(at(50)).unwrap().as_secs_f64();
+		assert!((eta - 860.0).abs() < 860.0 * 0.05, "eta {eta}");
+		assert!(tracker.samples.len() <= 32, "{} samples", tracker.samples.len());
+	}
+
+	#[test]
+	fn no_eta_without_total() {
+		let (mut tracker, events) = capturing(0);
+		let t0 = tracker.start;
+		tracker.record_at(500, t0 + Duration::from_secs(6));
+		let event = events.lock().unwrap()[0].clone();
+		assert_eq!(event.eta, None);
+		assert!(!event.to_string().contains("ETA"), "{event}");
+	}
+
+	#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+	enum Failure {
+		Conflict,
+		Fatal,
+	}
+
+	impl Retryable for Failure {
+		fn is_retryable(&self) -> bool {
+			*self == Failure::Conflict
+		}
+	}
+
+	/// `MemStore` whose next commits fail with the queued failures, one each, before any lands.
+	#[derive(Default)]
+	struct FlakyStore {
+		inner: crate::store_tests::MemStore<Failure>,
+		failures: VecDeque<Failure>,
+		attempts: u32,
+	}
+
+	impl StoreRead<Vec<u8>, Vec<u8>> for FlakyStore {
+		type Error = Failure;
+
+		fn get_value(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, Failure> {
+			self.inner.get_value(key)
+		}
+
+		fn get_value_raw(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, Failure> {
+			self.inner.get_value_raw(key)
+		}
+
+		fn get_key_for_value(&self, value: &Vec<u8>) -> Result<Option<Vec<u8>>, Failure> {
+			self.inner.get_key_for_value(value)
+		}
+
+		fn get_keys_for_value(&self, value: &Vec<u8>) -> Result<Vec<Vec<u8>>, Failure> {
+			self.inner.get_keys_for_value(value)
+		}
+
+		fn distinct_values(&self) -> Result<impl Iterator<Item = Result<Vec<u8>, Failure>>, Failure> {
+			self.inner.distinct_values()
+		}
+
+		fn verify_consistency(&self) -> Result<ConsistencyReport, Failure> {
+			self.inner.verify_consistency()
+		}
+
+		fn first_key(&self) -> Result<Option<Vec<u8>>, Failure> {
+			self.inner.first_key()
+		}
+
+		fn last_key(&self) -> Result<Option<Vec<u8>>, Failure> {
+			self.inner.last_key()
+		}
+
+		fn approx_key_count(&self) -> Result<u64, Failure> {
+			self.inner.approx_key_count()
+		}
+	}
+
+	impl StoreWrite<Vec<u8>, Vec<u8>> for FlakyStore {
+		type Options = ();
+		type Layout = ();
+
+		fn open_with_options(_path: &Path, _layout: (), _options: ()) -> Result<Self, Failure> {
+			Ok(Self::default())
+		}
+
+		fn open_with_mode(_path: &Path, _layout: (), _options: (), _mode: OpenMode) -> Result<Self, Failure> {
+			Ok(Self::default())
+		}
+
+		fn open_read_only(_path: &Path, _layout: ()) -> Result<Self, Failure> {
+			Ok(Self::default())
+		}
+
+		fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, Failure>
+		where
+			I: IntoIterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>,
+		{
+			self.attempts += 1;
+			match self.failures.pop_front() {
+				Some(failure) => Err(failure),
+				None => self.inner.commit_counted(items),
+			}
+		}
+
+		fn flush(&mut self) -> Result<(), Failure> {
+			Ok(())
+		}
+
+		fn clear(&mut self) -> Result<(), Failure> {
+			self.inner.clear()
+		}
+	}
+
+	#[test]
+	fn commit_retry_gets_past_transient_conflicts_only() {
+		let rows: Vec<(Vec<u8>, Vec<u8>)> = (0..10u8).map(|i| (vec![i], vec![b'v', i])).collect();
+		let batch = || rows.iter().map(|(k, v)| (k, v));
+
+		let mut store = FlakyStore { failures: VecDeque::from([Failure::Conflict; 2]), ..FlakyStore::default() };
+		store.commit_retry(batch(), 3).unwrap();
+		assert_eq!(store.attempts, 3);
+		assert_eq!(store.get_value(&vec![9]).unwrap(), Some(b"v\x09".to_vec()));
+
+		// Retries run out before the conflicts do.
+		let mut store = FlakyStore { failures: VecDeque::from([Failure::Conflict; 3]), ..FlakyStore::default() };
+		assert_eq!(store.commit_retry(batch(), 2), Err(Failure::Conflict));
+		assert_eq!(store.attempts, 3);
+		assert_eq!(store.get_value(&vec![0]).unwrap(), None);
+
+		// A fatal error is returned at once.
+		let mut store = FlakyStore { failures: VecDeque::from([Failure::Fatal]), ..FlakyStore::default() };
+		assert_eq!(store.commit_retry(batch(), 5), Err(Failure::Fatal));
+		assert_eq!(store.attempts, 1);
+	}
+}
@@ -1,15 +1,65 @@
-use std::fmt::Debug;
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Debug,
+	fs::{self, OpenOptions},
+	io::Write,
+	marker::PhantomData,
+	path::{Path, PathBuf},
+	process::{Command, Stdio},
+	sync::atomic::{AtomicU64, Ordering},
+	thread,
+	time::{Duration, Instant},
+};
 
-use crate::store_interface::StoreWrite;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+	bench_common::{make_var_key, KeyLengths},
+	store_builder::{BuilderOptions, StoreBuilder},
+	store_interface::{
+		CommitStats, ConsistencyReport, ExpiringStore, Mismatch, OpenMode, StoreCodec, StoreRead, StoreWrite,
+	},
+};
+
+/// A directory of its own under the system temp dir, removed when dropped.
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+	fn create() -> Self {
+		static NEXT: AtomicU64 = AtomicU64::new(0);
+		let name = format!("store_tests_{}_{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed));
+		let path = std::env::temp_dir().join(name);
+		fs::create_dir_all(&path).expect("create scratch dir");
+		Self(path)
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for ScratchDir {
+	fn drop(&mut self) {
+		fs::remove_dir_all(&self.0).ok();
+	}
+}
+
+/// Opens a store in a fresh `ScratchDir`, returned next to it. Bind the directory first, e.g.
+/// `let (_dir, store) = ...`, so the store is dropped before its files are removed.
+pub fn scratch_store<S>(open: impl FnOnce(&Path) -> S) -> (ScratchDir, S) {
+	let dir = ScratchDir::create();
+	let store = open(dir.path());
+	(dir, store)
+}
 
 /// Basic put/get/overwrite cycle for a store using `Vec<u8>` keys and values.
 pub fn basic_value_roundtrip<S, F>(mut factory: F)
 where
 	S: StoreWrite<Vec<u8>, Vec<u8>>,
 	S::Error: Debug,
-	F: FnMut() -> S,
+	F: FnMut(&Path) -> S,
 {
-	let mut store = factory();
+	let (_dir, mut store) = scratch_store(&mut factory);
 	let k = b"key".to_vec();
 	let v1 = b"value1".to_vec();
 	let v2 = b"value2".to_vec();
@@ -30,9 +80,9 @@ pub fn reverse_lookup_unique<S, F>(mut factory: F)
 where
 	S: StoreWrite<Vec<u8>, Vec<u8>>,
 	S::Error: Debug,
-	F: FnMut() -> S,
+	F: FnMut(&Path) -> S,
 {
-	let mut store = factory();
+	let (_dir, mut store) = scratch_store(&mut factory);
 	let k = b"k".to_vec();
 	let v = b"val".to_vec();
 	store.commit([(&k, &v)]).expect("commit");
@@ -41,21 +91,867 @@ where
 	assert_eq!(store.get_key_for_value(&v).expect("reverse after flush"), Some(k));
 }
 
-/// Multi-key lookup for stores supporting range/dictionary style value->keys.
+/// Multi-key lookup for stores supporting range/dictionary style value->keys. Keys come back in
+/// encoded order whatever the commit order and whether they were flushed, so every backend agrees;
+/// the single-key lookup falls back to the smallest one.
 pub fn multiple_keys_for_value<S, F>(mut factory: F)
 where
 	S: StoreWrite<Vec<u8>, Vec<u8>>,
 	S::Error: Debug,
-	F: FnMut() -> S,
+	F: FnMut(&Path) -> S,
 {
-	let mut store = factory();
+	let (_dir, mut store) = scratch_store(&mut factory);
 	let v = b"shared".to_vec();
-	let keys = vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()];
-	for k in &keys {
-		store.commit([ (k, &v) ]).expect("commit");
+	// Little-endian 1, 256, 2, 513 and 3: encoded order is not the numeric one.
+	let keys: Vec<Vec<u8>> = [1u16, 256, 2, 513, 3].iter().map(|k| k.to_le_bytes().to_vec()).collect();
+	for k in &keys[..3] {
+		store.commit([(k, &v)]).expect("commit");
+	}
+	store.flush().expect("flush");
+	for k in keys[3..].iter().rev() {
+		store.commit([(k, &v)]).expect("commit");
 	}
+	let encoded_order = vec![vec![0, 1], vec![1, 0], vec![1, 2], vec![2, 0], vec![3, 0]];
+	assert_eq!(store.get_keys_for_value(&v).expect("get keys"), encoded_order);
+	assert_eq!(store.get_keys_for_value_sorted(&v).expect("sorted keys"), encoded_order);
 	store.flush().expect("flush");
+	assert_eq!(store.get_keys_for_value(&v).expect("get keys after flush"), encoded_order);
+	assert_eq!(store.get_key_for_value(&v).expect("any key"), Some(vec![0, 1]));
+	assert_eq!(store.get_key_for_value(&b"missing".to_vec()).expect("any key of missing"), None);
+	assert!(store.get_keys_for_value(&b"missing".to_vec()).expect("keys of missing").is_empty());
+}
+
+/// Values, and with them `Dictionary` birth keys, that are byte prefixes of one another share the
+/// btree key-space without a reverse lookup of the shorter one picking up the longer one's keys.
+pub fn prefix_values_isolated<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let (v, vv) = (b"v".to_vec(), b"vv".to_vec());
+	let items = [(b"k".to_vec(), &v), (b"kk".to_vec(), &vv), (b"kkk".to_vec(), &v), (b"kx".to_vec(), &vv)];
+	store.commit(items.iter().map(|(k, v)| (k, *v))).expect("commit");
+	store.flush().expect("flush");
+	assert_eq!(store.get_keys_for_value(&v).expect("keys of v"), vec![b"k".to_vec(), b"kkk".to_vec()]);
+	assert_eq!(store.get_keys_for_value(&vv).expect("keys of vv"), vec![b"kk".to_vec(), b"kx".to_vec()]);
+	assert_eq!(store.get_key_for_value(&vv).expect("any key of vv"), Some(b"kk".to_vec()));
+	assert!(store.get_keys_for_value(&b"vvv".to_vec()).expect("keys of vvv").is_empty());
+}
+
+/// A `Dictionary` value keeps the birth key of its first writer across commit and flush
+/// boundaries; a second birth key would re-point the value and orphan the earlier keys.
+pub fn dictionary_birth_key_stable<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let v = b"shared".to_vec();
+	let [a, b, c, d] = [b"a", b"b", b"c", b"d"].map(|k| k.to_vec());
+	store.commit([(&a, &v)]).expect("first commit");
+	store.commit([(&b, &v), (&c, &v)]).expect("second commit");
+	store.flush().expect("flush");
+	store.commit([(&d, &v)]).expect("commit after flush");
+	for k in [&a, &b, &c, &d] {
+		assert_eq!(store.get_value(k).expect("get"), Some(v.clone()));
+	}
 	let mut got = store.get_keys_for_value(&v).expect("get keys");
 	got.sort();
-	assert_eq!(got, keys);
+	assert_eq!(got, vec![a, b, c, d]);
+}
+
+/// Clearing a populated store drops all entries but leaves it usable for new writes.
+pub fn clear_then_reuse<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let k1 = b"k1".to_vec();
+	let k2 = b"k2".to_vec();
+	let v1 = b"v1".to_vec();
+	let v2 = b"v2".to_vec();
+	store.commit([(&k1, &v1), (&k2, &v2)]).expect("commit");
+	store.flush().expect("flush");
+
+	store.clear().expect("clear");
+	assert_eq!(store.get_value(&k1).expect("get after clear"), None);
+	assert_eq!(store.get_value(&k2).expect("get after clear"), None);
+
+	store.commit([(&k2, &v1)]).expect("commit after clear");
+	assert_eq!(store.get_value(&k2).expect("get new write"), Some(v1.clone()));
+	store.flush().expect("flush after clear");
+	assert_eq!(store.get_value(&k1).expect("get cleared key"), None);
+	assert_eq!(store.get_value(&k2).expect("get new write after flush"), Some(v1));
+}
+
+/// Keys of widely varying length, including keys that are prefixes of one another.
+pub fn variable_length_keys_roundtrip<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let mut rng = StdRng::seed_from_u64(7);
+	let lengths = KeyLengths { min: 8, max: 512 };
+	let mut keys: Vec<Vec<u8>> = (0..200u64).map(|i| make_var_key(i, lengths, &mut rng).0).collect();
+	keys.extend([b"p".to_vec(), b"pr".to_vec(), b"prefix".to_vec(), vec![0xff; 1024]]);
+	let items: Vec<(Vec<u8>, Vec<u8>)> =
+		keys.iter().enumerate().map(|(i, k)| (k.clone(), (i as u64).to_le_bytes().to_vec())).collect();
+
+	store.commit(items.iter().map(|(k, v)| (k, v))).expect("commit");
+	for (k, v) in &items {
+		assert_eq!(store.get_value(k).expect("get"), Some(v.clone()), "key of {} bytes", k.len());
+	}
+	store.flush().expect("flush");
+	for (k, v) in &items {
+		assert_eq!(store.get_value(k).expect("get after flush"), Some(v.clone()), "key of {} bytes", k.len());
+	}
+	assert_eq!(store.get_value(&b"pre".to_vec()).expect("get missing prefix"), None);
+}
+
+/// Data written through a writable handle is readable through a read-only one, which rejects writes.
+pub fn read_only_rejects_writes<S, W, R>(mut open: W, mut open_read_only: R)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	W: FnMut() -> S,
+	R: FnMut() -> S,
+{
+	let k = b"key".to_vec();
+	let v = b"value".to_vec();
+	{
+		let mut store = open();
+		store.commit([(&k, &v)]).expect("commit");
+		store.flush().expect("flush");
+	}
+
+	let mut store = open_read_only();
+	assert_eq!(store.get_value(&k).expect("read-only get"), Some(v.clone()));
+	assert!(store.commit([(&k, &v)]).is_err(), "read-only commit must fail");
+	assert!(store.clear().is_err(), "read-only clear must fail");
+	assert_eq!(store.get_value(&k).expect("get after rejected writes"), Some(v));
+}
+
+/// `commit_sorted` of a shuffled batch with duplicate keys reads back like plain commits.
+pub fn sorted_commit_matches_unsorted<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_plain_dir, mut plain) = scratch_store(&mut factory);
+	let (_sorted_dir, mut sorted) = scratch_store(&mut factory);
+	let mut items: Vec<(Vec<u8>, Vec<u8>)> =
+		(0..500u32).map(|i| ((i * 7919 % 500).to_be_bytes().to_vec(), i.to_le_bytes().to_vec())).collect();
+	items.extend((0..50u32).map(|i| (i.to_be_bytes().to_vec(), b"last".to_vec())));
+
+	plain.commit(items.iter().map(|(k, v)| (k, v))).expect("commit");
+	sorted.commit_sorted(items.iter().map(|(k, v)| (k, v))).expect("commit_sorted");
+	plain.flush().expect("flush");
+	sorted.flush().expect("flush sorted");
+	for i in 0..500u32 {
+		let k = i.to_be_bytes().to_vec();
+		let expected = plain.get_value(&k).expect("get");
+		assert!(expected.is_some());
+		assert_eq!(sorted.get_value(&k).expect("get sorted"), expected);
+		if i < 50 {
+			assert_eq!(expected, Some(b"last".to_vec()));
+		}
+	}
+}
+
+/// `commit_counted` on a `Dictionary` store counts every column write, birth-key mappings only
+/// for values not seen before, whether in the same batch or an earlier commit.
+pub fn dictionary_commit_stats<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let (a, b, c, d) = (b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec());
+	let (xx, yyy) = (b"xx".to_vec(), b"yyy".to_vec());
+	// (a, xx): value_to_birth_key xx->a, birth_key_to_value a->xx, key_to_birth_key a->a, btree aa;
+	// (b, xx): b->a and ab; (c, yyy): yyy->c, c->yyy, c->c and cc. Btree keys carry a one-byte
+	// length of the birth key.
+	let stats = store.commit_counted([(&a, &xx), (&b, &xx), (&c, &yyy)]).expect("commit");
+	assert_eq!(stats, CommitStats { rows: 10, key_bytes: 19, value_bytes: 10 });
+	store.flush().expect("flush");
+	// xx already has birth key a: only d->a and ad.
+	let stats = store.commit_counted([(&d, &xx)]).expect("commit known value");
+	assert_eq!(stats, CommitStats { rows: 2, key_bytes: 4, value_bytes: 1 });
+	assert_eq!(store.get_keys_for_value(&xx).expect("keys"), vec![a, b, d]);
+}
+
+/// A batch that writes a key twice leaves the last value either way; with coalescing, opened by
+/// `open(true)`, the overwritten write is skipped, as is its `UniqueIndex` entry for the old value.
+pub fn coalesced_duplicate_keys<S, F, G>(mut plain: F, mut unique_index: G)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path, bool) -> S,
+	G: FnMut(&Path, bool) -> S,
+{
+	let (a, b) = (b"a".to_vec(), b"b".to_vec());
+	let (old, new, other) = (b"old".to_vec(), b"new".to_vec(), b"other".to_vec());
+	let batch = [(&a, &old), (&b, &other), (&a, &new)];
+	for (coalesce, rows) in [(false, 3), (true, 2)] {
+		let (_dir, mut store) = scratch_store(|path| plain(path, coalesce));
+		let stats = store.commit_counted(batch).expect("commit");
+		assert_eq!(stats.rows, rows, "coalesce: {coalesce}");
+		assert_eq!(store.get_value(&a).expect("get"), Some(new.clone()), "coalesce: {coalesce}");
+		assert_eq!(store.get_value(&b).expect("get"), Some(other.clone()), "coalesce: {coalesce}");
+	}
+	let (_dir, mut store) = scratch_store(|path| unique_index(path, true));
+	let stats = store.commit_counted(batch).expect("commit");
+	assert_eq!(stats.rows, 4);
+	assert_eq!(store.get_value(&a).expect("get"), Some(new.clone()));
+	assert_eq!(store.get_key_for_value(&new).expect("key of new"), Some(a));
+	assert_eq!(store.get_key_for_value(&old).expect("key of old"), None);
+}
+
+/// `settle_for_reads` keeps every entry, flushed or not, and leaves the store writable.
+pub fn settle_keeps_contents<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let rows: Vec<(Vec<u8>, Vec<u8>)> =
+		(0..200u32).map(|i| (i.to_be_bytes().to_vec(), format!("v{i}").into_bytes())).collect();
+	store.commit(rows[..100].iter().map(|(k, v)| (k, v))).expect("commit");
+	store.flush().expect("flush");
+	store.commit(rows[100..].iter().map(|(k, v)| (k, v))).expect("unflushed commit");
+	store.settle_for_reads().expect("settle");
+	for (k, v) in &rows {
+		assert_eq!(store.get_value(k).expect("get"), Some(v.clone()));
+	}
+	let (k, v) = (b"after".to_vec(), b"settle".to_vec());
+	store.commit([(&k, &v)]).expect("commit after settle");
+	assert_eq!(store.get_value(&k).expect("get"), Some(v));
+}
+
+/// `first_key` and `last_key` track the encoded key bounds across flushes, out-of-order commits,
+/// overwrites and a clear.
+pub fn key_bounds<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	assert_eq!(store.first_key().expect("first of empty"), None);
+	assert_eq!(store.last_key().expect("last of empty"), None);
+	let key = |i: u32| i.to_be_bytes().to_vec();
+	for chunk in [[50u32, 10, 90], [20, 70, 30], [60, 5, 95]] {
+		let rows: Vec<(Vec<u8>, Vec<u8>)> = chunk.iter().map(|&i| (key(i), format!("v{i}").into_bytes())).collect();
+		store.commit(rows.iter().map(|(k, v)| (k, v))).expect("commit");
+		store.flush().expect("flush");
+	}
+	let (k, v) = (key(99), b"unflushed".to_vec());
+	store.commit([(&k, &v)]).expect("unflushed commit");
+	store.commit([(&key(5), &b"again".to_vec())]).expect("overwrite");
+	assert_eq!(store.first_key().expect("first"), Some(key(5)));
+	assert_eq!(store.last_key().expect("last"), Some(key(99)));
+	store.settle_for_reads().expect("settle");
+	assert_eq!(store.first_key().expect("first after settle"), Some(key(5)));
+	assert_eq!(store.last_key().expect("last after settle"), Some(key(99)));
+	store.clear().expect("clear");
+	assert_eq!(store.first_key().expect("first after clear"), None);
+	assert_eq!(store.last_key().expect("last after clear"), None);
+}
+
+/// Entries committed with a zero TTL read as missing before and after a flush, one overwritten
+/// without a TTL lives again, and `purge_expired` deletes exactly the expired keys once.
+pub fn expiring_entries<S, F>(mut factory: F)
+where
+	S: ExpiringStore<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let key = |i: u32| i.to_be_bytes().to_vec();
+	let mut rows: Vec<(Vec<u8>, Vec<u8>)> = (0..20u32).map(|i| (key(i), format!("v{i}").into_bytes())).collect();
+	// 0..10 expire at once, 10..15 in an hour and 15..20 never.
+	store.commit_with_ttl(rows[..10].iter().map(|(k, v)| (k, v)), Duration::ZERO).expect("commit expired");
+	store.commit_with_ttl(rows[10..15].iter().map(|(k, v)| (k, v)), Duration::from_secs(3600)).expect("commit live");
+	store.commit(rows[15..].iter().map(|(k, v)| (k, v))).expect("commit without ttl");
+	let check = |store: &S, rows: &[(Vec<u8>, Vec<u8>)], expired: &dyn Fn(usize) -> bool| {
+		for (i, (k, v)) in rows.iter().enumerate() {
+			let expected = (!expired(i)).then(|| v.clone());
+			assert_eq!(store.get_value(k).expect("get"), expected, "key {i}");
+			assert_eq!(store.get_value_raw(k).expect("get raw"), expected, "raw key {i}");
+		}
+	};
+	check(&store, &rows, &|i| i < 10);
+	store.flush().expect("flush");
+	check(&store, &rows, &|i| i < 10);
+
+	rows[0].1 = b"back".to_vec();
+	rows[15].1 = b"gone".to_vec();
+	store.commit([(&rows[0].0, &rows[0].1)]).expect("revive");
+	store.commit_with_ttl([(&rows[15].0, &rows[15].1)], Duration::ZERO).expect("expire");
+	let expired = |i: usize| (1..10).contains(&i) || i == 15;
+	check(&store, &rows, &expired);
+	assert_eq!(store.purge_expired().expect("purge"), 10);
+	check(&store, &rows, &expired);
+	assert_eq!(store.purge_expired().expect("purge again"), 0);
+	store.flush().expect("flush after purge");
+	check(&store, &rows, &expired);
+}
+
+/// `get_keys_for_values` over unsorted values, with a repeat and a value no key holds, matches
+/// `get_keys_for_value` of each value at the same index.
+pub fn batched_keys_for_values<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let values: Vec<Vec<u8>> = ["walnut", "ash", "oak", "elm", "birch"].iter().map(|v| v.as_bytes().to_vec()).collect();
+	let rows: Vec<(Vec<u8>, Vec<u8>)> =
+		(0..40u32).map(|i| (format!("k{i:02}").into_bytes(), values[(i * 7 % 5) as usize].clone())).collect();
+	store.commit(rows[..25].iter().map(|(k, v)| (k, v))).expect("commit");
+	store.flush().expect("flush");
+	store.commit(rows[25..].iter().map(|(k, v)| (k, v))).expect("commit after flush");
+	let queries: Vec<Vec<u8>> = vec![
+		values[2].clone(),
+		b"maple".to_vec(),
+		values[0].clone(),
+		values[4].clone(),
+		values[2].clone(),
+		values[1].clone(),
+		values[3].clone(),
+	];
+	let batched = store.get_keys_for_values(&queries).expect("batched lookup");
+	assert_eq!(batched.len(), queries.len());
+	for (value, keys) in queries.iter().zip(&batched) {
+		assert_eq!(keys, &store.get_keys_for_value(value).expect("lookup"), "keys of {value:?}");
+	}
+	assert!(batched[1].is_empty());
+	assert_eq!(batched[0].len(), 8);
+	assert!(store.get_keys_for_values(&[]).expect("empty batch").is_empty());
+}
+
+/// `get_value_raw` of flushed and unflushed rows is the `VC::encode` of what `get_value` decodes,
+/// and `None` for a key never committed.
+pub fn raw_values<VC, S, F>(mut factory: F)
+where
+	VC: StoreCodec<Vec<u8>>,
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let rows: Vec<(Vec<u8>, Vec<u8>)> =
+		(0..30u32).map(|i| (format!("k{i:02}").into_bytes(), format!("v{}", i % 4).into_bytes())).collect();
+	store.commit(rows[..20].iter().map(|(k, v)| (k, v))).expect("commit");
+	store.flush().expect("flush");
+	store.commit(rows[20..].iter().map(|(k, v)| (k, v))).expect("commit after flush");
+	for (k, v) in &rows {
+		let raw = store.get_value_raw(k).expect("get raw");
+		assert_eq!(raw.as_deref(), Some(VC::encode(v).as_ref()), "raw value of {k:?}");
+		assert_eq!(store.get_value(k).expect("get").map(|v| VC::encode(&v).as_ref().to_vec()), raw);
+	}
+	assert_eq!(store.get_value_raw(&b"missing".to_vec()).expect("get raw missing"), None);
+}
+
+/// Compiles only for `T: Send + Sync`, so a backend test fails to build once its `Store` can no
+/// longer be shared with reader threads.
+pub fn assert_send_sync<T: Send + Sync>() {}
+
+/// Reader threads looking up committed and flushed rows through one `Arc` of the store, which
+/// `&self` reads allow without a lock.
+pub fn shared_readers<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>> + Send + Sync,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	const ROWS: u16 = 512;
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let rows: Vec<_> = (0..ROWS).map(|i| (i.to_be_bytes().to_vec(), (i % 7).to_be_bytes().to_vec())).collect();
+	store.commit(rows.iter().map(|(k, v)| (k, v))).expect("commit");
+	store.flush().expect("flush");
+
+	let store = std::sync::Arc::new(store);
+	thread::scope(|scope| {
+		for reader in 0..4u16 {
+			let (store, rows) = (store.clone(), &rows);
+			scope.spawn(move || {
+				for (k, v) in rows.iter().skip(reader as usize).step_by(3) {
+					assert_eq!(store.get_value(k).expect("get"), Some(v.clone()));
+				}
+				assert_eq!(store.get_value(&ROWS.to_be_bytes().to_vec()).expect("get missing"), None);
+			});
+		}
+	});
+}
+
+/// `distinct_values` of a `Dictionary` store lists each value once however many keys share it,
+/// across a flush; a store of another layout rejects the call.
+pub fn dictionary_distinct_values<S, F, G>(mut dictionary: F, mut other: G)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+	G: FnMut(&Path) -> S,
+{
+	let (_dir, mut store) = scratch_store(&mut dictionary);
+	let values = [b"red".to_vec(), b"green".to_vec(), b"blue".to_vec()];
+	let rows: Vec<(Vec<u8>, Vec<u8>)> =
+		(0..30u32).map(|i| (format!("k{i:02}").into_bytes(), values[i as usize % 3].clone())).collect();
+	store.commit(rows[..20].iter().map(|(k, v)| (k, v))).expect("commit");
+	store.flush().expect("flush");
+	store.commit(rows[20..].iter().map(|(k, v)| (k, v))).expect("commit after flush");
+	let mut got: Vec<Vec<u8>> =
+		store.distinct_values().expect("distinct values").collect::<Result<_, _>>().expect("decode");
+	got.sort();
+	let mut expected = values.to_vec();
+	expected.sort();
+	assert_eq!(got, expected);
+	let (_other_dir, other) = scratch_store(&mut other);
+	assert!(other.distinct_values().is_err(), "distinct_values outside the Dictionary layout");
+}
+
+/// `delete` on a `Dictionary` store: two keys share a value, and deleting one keeps the value
+/// while deleting the other takes its `birth_key_to_value` and `value_to_birth_key` mappings
+/// too, which `value_mappings` reports on. A key overwritten with another value leaves a stale
+/// `birth_key||key` entry behind, which must not keep its old value alive.
+pub fn dictionary_delete<S, F, D, M>(dictionary: F, mut delete: D, mut value_mappings: M)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnOnce() -> S,
+	D: FnMut(&mut S, &[Vec<u8>]) -> Result<u64, S::Error>,
+	M: FnMut(&S, &[u8]) -> bool,
+{
+	let mut store = dictionary();
+	let (a, b, c, d) = (b"a".to_vec(), b"b".to_vec(), b"c".to_vec(), b"d".to_vec());
+	let (v, w) = (b"value".to_vec(), b"other".to_vec());
+	store.commit([(&a, &v), (&b, &v), (&c, &w)]).expect("commit");
+
+	assert_eq!(delete(&mut store, std::slice::from_ref(&a)).expect("delete one key of the value"), 1);
+	assert_eq!(store.get_value(&a).expect("get deleted"), None);
+	assert_eq!(store.get_value(&b).expect("get kept"), Some(v.clone()));
+	assert_eq!(store.get_keys_for_value(&v).expect("keys of the value"), vec![b.clone()]);
+	assert!(value_mappings(&store, &v), "a value with a key left keeps its mappings");
+
+	let deleted = delete(&mut store, &[b.clone(), a.clone()]).expect("delete the last key");
+	assert_eq!(deleted, 1, "a is gone already");
+	assert_eq!(store.get_keys_for_value(&v).expect("keys of the deleted value"), Vec::<Vec<u8>>::new());
+	assert_eq!(store.get_key_for_value(&v).expect("key of the deleted value"), None);
+	assert!(!value_mappings(&store, &v), "the last key took the value mappings along");
+	let values: Vec<Vec<u8>> =
+		store.distinct_values().expect("distinct values").collect::<Result<_, _>>().expect("decode");
+	assert_eq!(values, vec![w.clone()]);
+
+	// `c` is the birth key of `w`; overwriting `d` leaves a stale `c||d` entry.
+	store.commit([(&d, &w)]).expect("commit a second key of the other value");
+	store.commit([(&d, &v)]).expect("overwrite it with the deleted value");
+	assert!(value_mappings(&store, &v), "the value came back under a new birth key");
+	delete(&mut store, std::slice::from_ref(&c)).expect("delete the birth key");
+	assert!(!value_mappings(&store, &w), "a stale entry keeps no value alive");
+	assert_eq!(store.get_value(&d).expect("get the overwritten key"), Some(v.clone()));
+	assert_eq!(store.get_keys_for_value(&v).expect("keys of the new value"), vec![d]);
+	assert!(store.verify_consistency().expect("verify").is_consistent());
+}
+
+/// An empty value encoding reads back from `Plain` and `Range`, flushed or not, and a `Range`
+/// reverse lookup of it finds just its keys. `UniqueIndex` and `Dictionary`, which key a column by
+/// the value, reject the commit without writing its other rows.
+pub fn empty_values<S, P, U, R, D>(mut plain: P, mut unique_index: U, mut range: R, mut dictionary: D)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	P: FnMut(&Path) -> S,
+	U: FnMut(&Path) -> S,
+	R: FnMut(&Path) -> S,
+	D: FnMut(&Path) -> S,
+{
+	let empty = Vec::new();
+	let (a, b, c) = (b"a".to_vec(), b"b".to_vec(), b"c".to_vec());
+	let v = b"v".to_vec();
+	for (layout, (_dir, mut store)) in [("Plain", scratch_store(&mut plain)), ("Range", scratch_store(&mut range))] {
+		store.commit([(&a, &empty)]).expect("commit");
+		store.flush().expect("flush");
+		store.commit([(&b, &empty), (&c, &v)]).expect("commit after flush");
+		assert_eq!(store.get_value(&a).expect("get flushed"), Some(empty.clone()), "{layout}");
+		assert_eq!(store.get_value(&b).expect("get unflushed"), Some(empty.clone()), "{layout}");
+		if layout == "Range" {
+			assert_eq!(store.get_keys_for_value(&empty).expect("keys"), vec![a.clone(), b.clone()]);
+			assert_eq!(store.get_keys_for_value(&v).expect("keys"), vec![c.clone()]);
+		}
+	}
+	let stores = [("UniqueIndex", scratch_store(&mut unique_index)), ("Dictionary", scratch_store(&mut dictionary))];
+	for (layout, (_dir, mut store)) in stores {
+		assert!(store.commit([(&a, &empty), (&b, &v)]).is_err(), "{layout} took an empty value");
+		assert_eq!(store.get_value(&a).expect("get"), None, "{layout}");
+		assert_eq!(store.get_value(&b).expect("get"), None, "{layout}");
+		store.commit([(&b, &v)]).expect("commit after the rejected one");
+		assert_eq!(store.get_key_for_value(&v).expect("reverse get"), Some(b.clone()), "{layout}");
+	}
+}
+
+/// `approx_key_count` is 0 for an empty store and, after a bulk load of distinct keys across a
+/// flush, within a factor of 2 of the keys written.
+pub fn approximate_key_count<S, F>(mut factory: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+{
+	const ROWS: u64 = 20_000;
+	let (_dir, mut store) = scratch_store(&mut factory);
+	assert_eq!(store.approx_key_count().expect("empty count"), 0);
+	let rows: Vec<(Vec<u8>, Vec<u8>)> = (0..ROWS).map(|i| (i.to_be_bytes().to_vec(), b"v".to_vec())).collect();
+	for (n, batch) in rows.chunks(1000).enumerate() {
+		store.commit(batch.iter().map(|(k, v)| (k, v))).expect("commit");
+		if n == 9 {
+			store.flush().expect("flush");
+		}
+	}
+	store.flush().expect("final flush");
+	let estimate = store.approx_key_count().expect("count");
+	assert!((ROWS / 2..=ROWS * 2).contains(&estimate), "estimate {estimate} for {ROWS} keys");
+}
+
+/// `verify_consistency` passes a store written across a flush, then reports exactly the
+/// mismatches `corrupt` returns after it damages the columns behind one row.
+pub fn consistency_audit<S, F, C>(mut factory: F, mut corrupt: C)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> S,
+	C: FnMut(&mut S, &[u8], &[u8]) -> Vec<Mismatch>,
+{
+	let (_dir, mut store) = scratch_store(&mut factory);
+	let rows: Vec<(Vec<u8>, Vec<u8>)> =
+		(0..50u32).map(|i| (format!("k{i:02}").into_bytes(), format!("v{i:02}").into_bytes())).collect();
+	store.commit(rows[..30].iter().map(|(k, v)| (k, v))).expect("commit");
+	store.flush().expect("flush");
+	store.commit(rows[30..].iter().map(|(k, v)| (k, v))).expect("commit after flush");
+	let report = store.verify_consistency().expect("verify");
+	assert_eq!(report, ConsistencyReport { checked: 50, mismatches: Vec::new() });
+	let (k, v) = &rows[7];
+	let expected = corrupt(&mut store, k, v);
+	assert!(!expected.is_empty(), "corruption must be detectable");
+	let report = store.verify_consistency().expect("verify corrupted");
+	assert_eq!(report.checked, 50);
+	assert_eq!(report.mismatches, expected);
+}
+
+/// Each `OpenMode` against a missing and an existing store under `root`; a rejected open must
+/// leave nothing behind.
+pub fn open_modes<S, F>(root: &Path, mut open: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path, OpenMode) -> Result<S, S::Error>,
+{
+	let (k, v) = (b"k".to_vec(), b"v".to_vec());
+	let path = root.join("store");
+	assert!(open(&path, OpenMode::MustExist).is_err(), "MustExist opened a missing store");
+	assert!(!path.exists(), "a rejected open created {}", path.display());
+	{
+		let mut store = open(&path, OpenMode::CreateNew).expect("CreateNew of a missing store");
+		store.commit([(&k, &v)]).expect("commit");
+		store.flush().expect("flush");
+	}
+	assert!(open(&path, OpenMode::CreateNew).is_err(), "CreateNew reopened an existing store");
+	for mode in [OpenMode::MustExist, OpenMode::CreateIfMissing] {
+		let store = open(&path, mode).unwrap_or_else(|e| panic!("{mode:?} of an existing store: {e:?}"));
+		assert_eq!(store.get_value(&k).expect("get"), Some(v.clone()), "{mode:?}");
+	}
+	let fresh = open(&root.join("fresh"), OpenMode::CreateIfMissing).expect("CreateIfMissing of a missing store");
+	assert_eq!(fresh.get_value(&k).expect("get from fresh store"), None);
+}
+
+/// A store created by `open` refuses to reopen with another layout, through `open_other` and
+/// `open_other_read_only`, and its own layout still opens it afterwards.
+pub fn mismatched_layout<S, F, W, R>(mut open: F, mut open_other: W, mut open_other_read_only: R)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut() -> S,
+	W: FnMut() -> Result<S, S::Error>,
+	R: FnMut() -> Result<S, S::Error>,
+{
+	let (k, v) = (b"key".to_vec(), b"value".to_vec());
+	{
+		let mut store = open();
+		store.commit([(&k, &v)]).expect("commit");
+		store.flush().expect("flush");
+	}
+	let err = open_other().err().expect("reopened with another layout");
+	assert!(format!("{err:?}").contains("layout"), "{err:?}");
+	let err = open_other_read_only().err().expect("reopened read-only with another layout");
+	assert!(format!("{err:?}").contains("layout"), "{err:?}");
+	let store = open();
+	assert_eq!(store.get_value(&k).expect("get after rejected reopens"), Some(v));
+}
+
+/// `mismatched_layout` for a store from before its layout was recorded: `untag` drops the record
+/// after the first commit, so the reopens must tell the layout by the store's columns. The
+/// accepted reopen records it again.
+pub fn untagged_mismatched_layout<S, F, U, W, R>(mut open: F, untag: U, mut open_other: W, mut open_other_read_only: R)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut() -> S,
+	U: FnOnce(),
+	W: FnMut() -> Result<S, S::Error>,
+	R: FnMut() -> Result<S, S::Error>,
+{
+	let (k, v) = (b"key".to_vec(), b"value".to_vec());
+	{
+		let mut store = open();
+		store.commit([(&k, &v)]).expect("commit");
+		store.flush().expect("flush");
+	}
+	untag();
+	let err = open_other().err().expect("reopened untagged with another layout");
+	assert!(format!("{err:?}").contains("layout"), "{err:?}");
+	let err = open_other_read_only().err().expect("reopened untagged read-only with another layout");
+	assert!(format!("{err:?}").contains("layout"), "{err:?}");
+	let store = open();
+	assert_eq!(store.get_value(&k).expect("get after rejected reopens"), Some(v));
+	drop(store);
+	let err = open_other().err().expect("reopened with another layout once tagged again");
+	assert!(format!("{err:?}").contains("layout"), "{err:?}");
+}
+
+/// Opens through `StoreBuilder` at the directory `root/dir` and at the file path
+/// `root/nested/<file_name>`, then reopens each as an existing store, writable and read-only.
+pub fn builder_paths<S, F>(root: &Path, file_name: &str, mut builder: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: FnMut(&Path) -> StoreBuilder<Vec<u8>, Vec<u8>, S>,
+{
+	let (k, v) = (b"k".to_vec(), b"v".to_vec());
+	for path in [root.join("dir"), root.join("nested").join(file_name)] {
+		{
+			let mut store = builder(&path)
+				.mode(OpenMode::CreateNew)
+				.open()
+				.unwrap_or_else(|e| panic!("open {}: {e:?}", path.display()));
+			store.commit([(&k, &v)]).expect("commit");
+			store.flush().expect("flush");
+		}
+		assert!(path.exists(), "nothing written at {}", path.display());
+		{
+			let store = builder(&path).mode(OpenMode::MustExist).open().expect("reopen");
+			assert_eq!(store.get_value(&k).expect("get"), Some(v.clone()), "{}", path.display());
+		}
+		let store = builder(&path).open_read_only().expect("open read-only");
+		assert_eq!(store.get_value(&k).expect("read-only get"), Some(v.clone()), "{}", path.display());
+	}
+}
+
+/// Opens through `StoreBuilder` at `root/data` with the log in `root/wal`, commits a flushed and an
+/// unflushed batch, drops the store and reopens it with the same directories: both batches must be
+/// back, and the log must have been written to `root/wal`.
+pub fn separate_wal_dir<S, F>(root: &Path, mut builder: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Options: BuilderOptions,
+	S::Error: Debug,
+	F: FnMut(&Path) -> StoreBuilder<Vec<u8>, Vec<u8>, S>,
+{
+	let (data, wal) = (root.join("data"), root.join("wal"));
+	let rows: Vec<(Vec<u8>, Vec<u8>)> = (0..200u32).map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec())).collect();
+	{
+		let mut store = builder(&data).wal_dir(&wal).open().expect("open with a separate log directory");
+		store.commit(rows[..100].iter().map(|(k, v)| (k, v))).expect("commit");
+		store.flush().expect("flush");
+		store.commit(rows[100..].iter().map(|(k, v)| (k, v))).expect("commit");
+	}
+	let logged = fs::read_dir(&wal).map_or(0, |entries| entries.count());
+	assert!(logged > 0, "nothing written to {}", wal.display());
+	let store = builder(&data).wal_dir(&wal).open().expect("reopen");
+	for (k, v) in &rows {
+		assert_eq!(store.get_value(k).expect("get"), Some(v.clone()), "key {k:?}");
+	}
+}
+
+/// Set in the child process of `crash_recovery` to the directory it writes into.
+const CRASH_CHILD_DIR: &str = "STORE_TESTS_CRASH_CHILD_DIR";
+const CRASH_ROUNDS: usize = 3;
+const CRASH_BATCH_ROWS: u32 = 500;
+const CRASH_CHILD_MAX_BATCHES: u32 = 10_000;
+
+fn crash_row(batch: u32, row: u32) -> (Vec<u8>, Vec<u8>) {
+	let key = [batch.to_be_bytes(), row.to_be_bytes()].concat();
+	let value = key.iter().rev().copied().collect();
+	(key, value)
+}
+
+/// Batches the child flushed, counting only lines the kill did not tear.
+fn crash_batches_done(root: &Path) -> u32 {
+	fs::read_to_string(root.join("batches")).map_or(0, |log| log.matches('\n').count() as u32)
+}
+
+/// Re-runs `test_name` (its full path in the calling test binary) as a child that commits and
+/// flushes batches into `root/store` until it is SIGKILLed at a random point, several times over.
+/// Every batch flushed before a kill must survive the reopen, and the one in flight must be
+/// absent or intact. In the child `open` gets the parent's directory, not `root`.
+pub fn crash_recovery<S, F>(test_name: &str, root: &Path, open: F)
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>>,
+	S::Error: Debug,
+	F: Fn(&Path) -> S,
+{
+	if let Some(child_root) = std::env::var_os(CRASH_CHILD_DIR) {
+		let root = PathBuf::from(child_root);
+		let mut store = open(&root.join("store"));
+		let mut log = OpenOptions::new().create(true).append(true).open(root.join("batches")).expect("open batch log");
+		let start = crash_batches_done(&root);
+		for batch in start..start + CRASH_CHILD_MAX_BATCHES {
+			let rows: Vec<_> = (0..CRASH_BATCH_ROWS).map(|row| crash_row(batch, row)).collect();
+			store.commit(rows.iter().map(|(k, v)| (k, v))).expect("child commit");
+			store.flush().expect("child flush");
+			writeln!(log, "{batch}").expect("append batch log");
+		}
+		return
+	}
+
+	let exe = std::env::current_exe().expect("test binary path");
+	let mut rng = rand::rng();
+	for round in 0..CRASH_ROUNDS {
+		let before = crash_batches_done(root);
+		let mut child = Command::new(&exe)
+			.args([test_name, "--exact", "--nocapture", "--test-threads=1"])
+			.env(CRASH_CHILD_DIR, root)
+			.stdout(Stdio::null())
+			.stderr(Stdio::null())
+			.spawn()
+			.expect("spawn writer");
+		let deadline = Instant::now() + Duration::from_secs(60);
+		while crash_batches_done(root) <= before {
+			if let Some(status) = child.try_wait().expect("poll writer") {
+				panic!("writer exited with {status} before flushing in round {round}");
+			}
+			assert!(Instant::now() < deadline, "writer made no progress in round {round}");
+			thread::sleep(Duration::from_millis(5));
+		}
+		thread::sleep(Duration::from_millis(rng.random_range(0..50)));
+		child.kill().expect("kill writer");
+		child.wait().expect("reap writer");
+	}
+
+	let done = crash_batches_done(root);
+	let store = open(&root.join("store"));
+	for batch in 0..done {
+		for row in 0..CRASH_BATCH_ROWS {
+			let (k, v) = crash_row(batch, row);
+			assert_eq!(store.get_value(&k).expect("get flushed"), Some(v), "batch {batch} row {row}");
+		}
+	}
+	for row in 0..CRASH_BATCH_ROWS {
+		let (k, v) = crash_row(done, row);
+		let got = store.get_value(&k).expect("get in flight");
+		assert!(got.is_none() || got == Some(v), "torn value in batch {done} row {row}");
+	}
+}
+
+/// In-memory `Range`-like store that never fails; `E` only sets its error type. Enough to
+/// exercise wrappers without a backend.
+pub struct MemStore<E = String> {
+	kv: BTreeMap<Vec<u8>, Vec<u8>>,
+	_ph: PhantomData<fn() -> E>,
+}
+
+impl<E> Default for MemStore<E> {
+	fn default() -> Self {
+		Self { kv: BTreeMap::new(), _ph: PhantomData }
+	}
+}
+
+impl<E> StoreRead<Vec<u8>, Vec<u8>> for MemStore<E> {
+	type Error = E;
+
+	fn get_value(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, E> {
+		Ok(self.kv.get(key).cloned())
+	}
+
+	fn get_value_raw(&self, key: &Vec<u8>) -> Result<Option<Vec<u8>>, E> {
+		self.get_value(key)
+	}
+
+	fn get_key_for_value(&self, value: &Vec<u8>) -> Result<Option<Vec<u8>>, E> {
+		Ok(self.kv.iter().find(|(_, v)| *v == value).map(|(k, _)| k.clone()))
+	}
+
+	fn get_keys_for_value(&self, value: &Vec<u8>) -> Result<Vec<Vec<u8>>, E> {
+		Ok(self.kv.iter().filter(|(_, v)| *v == value).map(|(k, _)| k.clone()).collect())
+	}
+
+	fn distinct_values(&self) -> Result<impl Iterator<Item = Result<Vec<u8>, E>>, E> {
+		let values: BTreeSet<Vec<u8>> = self.kv.values().cloned().collect();
+		Ok(values.into_iter().map(Ok))
+	}
+
+	fn verify_consistency(&self) -> Result<ConsistencyReport, E> {
+		Ok(ConsistencyReport { checked: self.kv.len() as u64, mismatches: Vec::new() })
+	}
+
+	fn first_key(&self) -> Result<Option<Vec<u8>>, E> {
+		Ok(self.kv.keys().next().cloned())
+	}
+
+	fn last_key(&self) -> Result<Option<Vec<u8>>, E> {
+		Ok(self.kv.keys().next_back().cloned())
+	}
+
+	fn approx_key_count(&self) -> Result<u64, E> {
+		Ok(self.kv.len() as u64)
+	}
+}
+
+impl<E> StoreWrite<Vec<u8>, Vec<u8>> for MemStore<E> {
+	type Options = ();
+	type Layout = ();
+
+	fn open_with_options(_path: &Path, _layout: (), _options: ()) -> Result<Self, E> {
+		Ok(Self::default())
+	}
+
+	/// Nothing outlives a `MemStore`, so every mode opens an empty one.
+	fn open_with_mode(_path: &Path, _layout: (), _options: (), _mode: OpenMode) -> Result<Self, E> {
+		Ok(Self::default())
+	}
+
+	fn open_read_only(_path: &Path, _layout: ()) -> Result<Self, E> {
+		Ok(Self::default())
+	}
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats, E>
+	where
+		I: IntoIterator<Item = (&'a Vec<u8>, &'a Vec<u8>)>,
+	{
+		let mut stats = CommitStats::default();
+		for (k, v) in items {
+			stats.record(k, v);
+			self.kv.insert(k.clone(), v.clone());
+		}
+		Ok(stats)
+	}
+
+	fn flush(&mut self) -> Result<(), E> {
+		Ok(())
+	}
+
+	fn clear(&mut self) -> Result<(), E> {
+		self.kv.clear();
+		Ok(())
+	}
 }
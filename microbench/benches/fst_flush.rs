@@ -0,0 +1,45 @@
+//! `Column::flush` time of the FST store, building each segment's FST on the flushing thread
+//! while a second one writes its values file, against doing both on one thread:
+//!
+//! `cargo bench -p microbench --bench fst_flush`
+//!
+//! Each sample commits `ROWS` rows into a fresh store that cannot flush on its own, then times
+//! the `flush` that builds them into one segment per column.
+
+use core::bench_codecs::BytesCodec;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use fst_bench::store::{Layout, Store, StoreError, StoreOptions};
+use tempfile::tempdir;
+
+const ROWS: u64 = 200_000;
+
+type Bytes = BytesCodec<StoreError>;
+
+fn flushes(c: &mut Criterion) {
+	let rows: Vec<_> = (0..ROWS).map(|i| (i.to_be_bytes().to_vec(), [i.to_le_bytes(); 4].concat())).collect();
+	let mut group = c.benchmark_group("fst");
+	group.sample_size(10);
+	for (name, pipelined) in [("one_thread", false), ("pipelined", true)] {
+		group.bench_function(BenchmarkId::new("flush", name), |b| {
+			b.iter_batched(
+				|| {
+					let dir = tempdir().expect("temp dir");
+					let options = StoreOptions::new(ROWS as usize * 2).with_wal(false).with_pipelined_flush(pipelined);
+					let store = Store::<Vec<u8>, Vec<u8>, Bytes, Bytes>::open_with_options(dir.path(), Layout::plain(0), options)
+						.expect("open");
+					store.commit(rows.iter().map(|(k, v)| (k, v))).expect("commit");
+					(dir, store)
+				},
+				|(dir, mut store)| {
+					store.flush().expect("flush");
+					(dir, store)
+				},
+				BatchSize::PerIteration,
+			)
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, flushes);
+criterion_main!(benches);
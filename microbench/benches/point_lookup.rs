@@ -0,0 +1,201 @@
+//! `get_value` latency on a small settled store, per backend and layout, with criterion's
+//! confidence intervals:
+//!
+//! `cargo bench -p microbench --bench point_lookup [--features rocksdb,mdbx]`
+//!
+//! Each store is populated and settled once before its measurements start.
+
+use std::{fmt::Debug, hint::black_box};
+
+use core::bench_codecs::{AddressCodec, AmountCodec, DecodeError, InvalidInput, KeyCodec, TimestampCodec, TxCodec};
+use core::bench_common::{
+	ingest, make_key, Address, AddressStream, Amount, IngestConfig, Key, KeyChooser, KeyDistribution, Timestamp,
+	TxHash, BATCH,
+};
+use core::store_builder::StoreBuilder;
+use core::store_interface::StoreWrite;
+use criterion::{criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, BenchmarkId, Criterion};
+use tempfile::tempdir;
+
+const ROWS: u64 = 100_000;
+
+/// Fills `store` with `rows`, settles it and measures `get_value` on keys drawn uniformly from them.
+fn lookups<V, S>(
+	group: &mut BenchmarkGroup<'_, WallTime>,
+	layout: &str,
+	store: Result<S, S::Error>,
+	rows: impl Iterator<Item = (Key, V)> + Send,
+) where
+	S: StoreWrite<Key, V>,
+	S::Error: Debug,
+	V: Send,
+{
+	let mut store = store.expect("open");
+	ingest(rows, &mut store, BATCH, IngestConfig::default()).expect("populate");
+	store.flush().expect("flush");
+	store.settle_for_reads().expect("settle");
+	let mut chooser = KeyChooser::new(ROWS, KeyDistribution::Uniform, 0.99, 7);
+	group.bench_function(BenchmarkId::new("get_value", layout), |b| {
+		b.iter(|| black_box(store.get_value(&make_key(chooser.next_index())).expect("get")))
+	});
+}
+
+fn tx_hash(i: u64) -> TxHash {
+	let mut hash = [0u8; 32];
+	hash[..8].copy_from_slice(&i.to_be_bytes());
+	TxHash(hash)
+}
+
+/// Benchmarks the four layouts of one backend. `$invalid` turns a decode error message into the
+/// backend's error; the layouts are given in plain, index, range, dictionary order.
+macro_rules! backend {
+	(
+		$feature:literal, $name:ident, $krate:ident, $error:ty, $invalid:expr,
+		[$plain:expr, $index:expr, $range:expr, $dictionary:expr $(,)?] $(,)?
+	) => {
+		#[cfg(feature = $feature)]
+		fn $name(c: &mut Criterion) {
+			use $krate::store::{Layout, Store};
+
+			struct Invalid;
+
+			impl InvalidInput<$error> for Invalid {
+				fn invalid_input(err: DecodeError) -> $error {
+					($invalid)(err.to_string())
+				}
+			}
+
+			type Kc = KeyCodec<$error, Invalid>;
+			type Plain = Store<Key, Amount, Kc, AmountCodec<$error, Invalid>>;
+			type Index = Store<Key, TxHash, Kc, TxCodec<$error, Invalid>>;
+			type Range = Store<Key, Timestamp, Kc, TimestampCodec<$error, Invalid>>;
+			type Dictionary = Store<Key, Address, Kc, AddressCodec<$error>>;
+
+			let dir = tempdir().expect("temp dir");
+			let path = |layout: &str| dir.path().join(layout);
+			let mut group = c.benchmark_group($feature);
+			lookups(
+				&mut group,
+				"plain",
+				StoreBuilder::<_, _, Plain>::new(path("plain"), $plain).open(),
+				(0..ROWS).map(|i| (make_key(i), Amount(i))),
+			);
+			lookups(
+				&mut group,
+				"index",
+				StoreBuilder::<_, _, Index>::new(path("index"), $index).open(),
+				(0..ROWS).map(|i| (make_key(i), tx_hash(i))),
+			);
+			lookups(
+				&mut group,
+				"range",
+				StoreBuilder::<_, _, Range>::new(path("range"), $range).open(),
+				(0..ROWS).map(|i| (make_key(i), Timestamp(i))),
+			);
+			lookups(
+				&mut group,
+				"dictionary",
+				StoreBuilder::<_, _, Dictionary>::new(path("dictionary"), $dictionary).open(),
+				(0..ROWS).map(make_key).zip(AddressStream::new(ROWS, 2)),
+			);
+			group.finish();
+		}
+	};
+}
+
+backend!(
+	"fjall",
+	fjall,
+	fjall_bench,
+	fjall_bench::store::StoreError,
+	fjall_bench::store::StoreError::InvalidInput,
+	[
+		Layout::plain(0),
+		Layout::unique_index(0),
+		Layout::range(0),
+		Layout::dictionary(0),
+	],
+);
+backend!(
+	"redb",
+	redb,
+	redb_bench,
+	redb_bench::store::StoreError,
+	redb_bench::store::StoreError::InvalidInput,
+	[
+		Layout::plain(),
+		Layout::unique_index(),
+		Layout::range(),
+		Layout::dictionary(),
+	],
+);
+backend!(
+	"rocksdb",
+	rocksdb,
+	rocksdb_bench,
+	rocksdb_bench::store::StoreError,
+	rocksdb_bench::store::StoreError::InvalidInput,
+	[
+		Layout::plain(0),
+		Layout::unique_index(0),
+		Layout::range(0),
+		Layout::dictionary(0),
+	],
+);
+backend!(
+	"mdbx",
+	mdbx,
+	mdbx_bench,
+	mdbx_bench::store::StoreError,
+	mdbx_bench::store::StoreError::InvalidInput,
+	[
+		Layout::plain(0),
+		Layout::unique_index(0),
+		Layout::range(0),
+		Layout::dictionary(0),
+	],
+);
+backend!(
+	"parity",
+	parity,
+	parity_bench,
+	parity_db::Error,
+	parity_db::Error::InvalidInput,
+	[
+		Layout::plain(0),
+		Layout::unique_index(0),
+		Layout::range(0),
+		Layout::dictionary(0),
+	],
+);
+backend!(
+	"fst",
+	fst,
+	fst_bench,
+	fst_bench::store::StoreError,
+	fst_bench::store::StoreError::InvalidInput,
+	[
+		Layout::plain(0),
+		Layout::unique_index(0),
+		Layout::range(0),
+		Layout::dictionary(0),
+	],
+);
+
+fn point_lookups(c: &mut Criterion) {
+	#[cfg(feature = "fjall")]
+	fjall(c);
+	#[cfg(feature = "redb")]
+	redb(c);
+	#[cfg(feature = "rocksdb")]
+	rocksdb(c);
+	#[cfg(feature = "mdbx")]
+	mdbx(c);
+	#[cfg(feature = "parity")]
+	parity(c);
+	#[cfg(feature = "fst")]
+	fst(c);
+}
+
+criterion_group!(benches, point_lookups);
+criterion_main!(benches);
@@ -0,0 +1,321 @@
+//! The backends this build was compiled with, behind one `DynStore` factory, and the workload the
+//! `bench` binary runs on each of them.
+
+use std::{
+	path::Path,
+	time::{Duration, Instant},
+};
+
+use core::bench_codecs::BytesCodec;
+use core::bench_common::{
+	dir_size, make_key, make_var_key, ops_per_sec, AddressStream, BenchLayout, KeyChooser, KeyDistribution, KeyLengths,
+	BATCH,
+};
+use core::dyn_store::{BenchError, BenchResult, BoxError, DynStore};
+use core::store_builder::StoreBuilder;
+use core::store_interface::StoreWrite;
+use rand::{rngs::StdRng, SeedableRng};
+
+/// A storage backend, selected at run time among those enabled by features at compile time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Backend {
+	Fjall,
+	Redb,
+	Rocksdb,
+	Libmdbx,
+	Parity,
+	Fst,
+}
+
+impl Backend {
+	pub const ALL: [Backend; 6] =
+		[Backend::Fjall, Backend::Redb, Backend::Rocksdb, Backend::Libmdbx, Backend::Parity, Backend::Fst];
+
+	/// The name `--backend` takes and the results report.
+	pub fn name(self) -> &'static str {
+		match self {
+			Backend::Fjall => "fjall",
+			Backend::Redb => "redb",
+			Backend::Rocksdb => "rocksdb",
+			Backend::Libmdbx => "libmdbx",
+			Backend::Parity => "parity",
+			Backend::Fst => "fst",
+		}
+	}
+
+	/// The cargo feature that compiles the backend in.
+	pub fn feature(self) -> &'static str {
+		match self {
+			Backend::Libmdbx => "mdbx",
+			_ => self.name(),
+		}
+	}
+
+	/// Case-insensitive; the feature name works too, e.g. `mdbx`.
+	pub fn parse(name: &str) -> Option<Self> {
+		let name = name.trim().to_ascii_lowercase();
+		Self::ALL.into_iter().find(|backend| backend.name() == name || backend.feature() == name)
+	}
+
+	/// Parses a comma-separated list of backends; unknown ones and ones this build lacks are an
+	/// error rather than silently skipped.
+	pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+		let mut backends = Vec::new();
+		for name in list.split(',').filter(|name| !name.trim().is_empty()) {
+			let backend = Self::parse(name).ok_or_else(|| format!("unknown backend `{}`", name.trim()))?;
+			if !backend.enabled() {
+				return Err(format!("{} is not compiled in; build with `--features {}`", backend.name(), backend.feature()))
+			}
+			if !backends.contains(&backend) {
+				backends.push(backend);
+			}
+		}
+		if backends.is_empty() {
+			return Err("no backend selected".to_string())
+		}
+		Ok(backends)
+	}
+
+	pub fn enabled(self) -> bool {
+		match self {
+			Backend::Fjall => cfg!(feature = "fjall"),
+			Backend::Redb => cfg!(feature = "redb"),
+			Backend::Rocksdb => cfg!(feature = "rocksdb"),
+			Backend::Libmdbx => cfg!(feature = "mdbx"),
+			Backend::Parity => cfg!(feature = "parity"),
+			Backend::Fst => cfg!(feature = "fst"),
+		}
+	}
+
+	/// The backends of this build, in `ALL` order.
+	pub fn enabled_all() -> Vec<Self> {
+		Self::ALL.into_iter().filter(|backend| backend.enabled()).collect()
+	}
+
+	/// Opens the store for `layout` at `path` with the backend's default options, over raw bytes.
+	pub fn open(self, path: &Path, layout: BenchLayout) -> BenchResult<Box<dyn DynStore>> {
+		match self {
+			#[cfg(feature = "fjall")]
+			Backend::Fjall => {
+				use fjall_bench::store::{Layout, Store, StoreError};
+				let layout = pick(layout, [Layout::plain(0), Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)]);
+				open_store::<Store<_, _, BytesCodec<StoreError>, BytesCodec<StoreError>>>(path, layout)
+			},
+			#[cfg(feature = "redb")]
+			Backend::Redb => {
+				use redb_bench::store::{Layout, Store, StoreError};
+				let layout = pick(layout, [Layout::plain(), Layout::unique_index(), Layout::range(), Layout::dictionary()]);
+				open_store::<Store<_, _, BytesCodec<StoreError>, BytesCodec<StoreError>>>(path, layout)
+			},
+			#[cfg(feature = "rocksdb")]
+			Backend::Rocksdb => {
+				use rocksdb_bench::store::{Layout, Store, StoreError};
+				let layout = pick(layout, [Layout::plain(0), Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)]);
+				open_store::<Store<_, _, BytesCodec<StoreError>, BytesCodec<StoreError>>>(path, layout)
+			},
+			#[cfg(feature = "mdbx")]
+			Backend::Libmdbx => {
+				use mdbx_bench::store::{Layout, Store, StoreError};
+				let layout = pick(layout, [Layout::plain(0), Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)]);
+				open_store::<Store<_, _, BytesCodec<StoreError>, BytesCodec<StoreError>>>(path, layout)
+			},
+			#[cfg(feature = "parity")]
+			Backend::Parity => {
+				use parity_bench::store::{Layout, Store};
+				let layout = pick(layout, [Layout::plain(0), Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)]);
+				open_store::<Store<_, _, BytesCodec<parity_db::Error>, BytesCodec<parity_db::Error>>>(path, layout)
+			},
+			#[cfg(feature = "fst")]
+			Backend::Fst => {
+				use fst_bench::store::{Layout, Store, StoreError};
+				let layout = pick(layout, [Layout::plain(0), Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)]);
+				open_store::<Store<_, _, BytesCodec<StoreError>, BytesCodec<StoreError>>>(path, layout)
+			},
+			#[allow(unreachable_patterns)]
+			_ => Err(BenchError::Other(
+				format!("{} is not compiled in; build with `--features {}`", self.name(), self.feature()).into(),
+			)),
+		}
+	}
+}
+
+/// The backend layout for a bench layout, given as plain, index, range, dictionary; variable
+/// keys go into a plain store.
+fn pick<L>(layout: BenchLayout, [plain, index, range, dictionary]: [L; 4]) -> L {
+	match layout {
+		BenchLayout::Plain | BenchLayout::PlainVarkey => plain,
+		BenchLayout::Index => index,
+		BenchLayout::Range => range,
+		BenchLayout::Dictionary => dictionary,
+	}
+}
+
+fn open_store<S>(path: &Path, layout: S::Layout) -> BenchResult<Box<dyn DynStore>>
+where
+	S: StoreWrite<Vec<u8>, Vec<u8>> + Send + 'static,
+	S::Error: Into<BoxError>,
+{
+	let store = StoreBuilder::<_, _, S>::new(path, layout).open().map_err(BenchError::store)?;
+	Ok(Box::new(store))
+}
+
+/// One backend and layout of a `run`, a row of the results CSV.
+#[derive(Clone, Debug)]
+pub struct BenchRecord {
+	pub backend: Backend,
+	pub layout: BenchLayout,
+	pub rows: u64,
+	/// Committing every row, then `flush`.
+	pub write: Duration,
+	pub settle: Duration,
+	pub reads: u64,
+	pub hits: u64,
+	pub read: Duration,
+	/// Size of the settled store.
+	pub disk_bytes: u64,
+}
+
+impl BenchRecord {
+	pub const CSV_HEADER: &'static str =
+		"backend,layout,rows,write_secs,rows_per_sec,settle_secs,reads,hits,read_secs,reads_per_sec,disk_bytes";
+
+	pub fn csv_row(&self) -> String {
+		format!(
+			"{},{},{},{:.6},{:.0},{:.6},{},{},{:.6},{:.0},{}",
+			self.backend.name(),
+			self.layout.name(),
+			self.rows,
+			self.write.as_secs_f64(),
+			ops_per_sec(self.rows, self.write),
+			self.settle.as_secs_f64(),
+			self.reads,
+			self.hits,
+			self.read.as_secs_f64(),
+			if self.reads == 0 { 0.0 } else { ops_per_sec(self.reads, self.read) },
+			self.disk_bytes,
+		)
+	}
+}
+
+/// Writes `rows` rows of `layout` into a fresh store of `backend` under `base/<backend>/<layout>`,
+/// flushes and settles it, then looks up `reads` uniformly drawn keys. The rows are the ones the
+/// backend binaries write, as bytes.
+pub fn run(backend: Backend, base: &Path, layout: BenchLayout, rows: u64, reads: u64) -> BenchResult<BenchRecord> {
+	let dir = base.join(backend.name()).join(layout.name());
+	if dir.exists() {
+		std::fs::remove_dir_all(&dir)?;
+	}
+	let mut store = backend.open(&dir, layout)?;
+
+	let start = Instant::now();
+	match layout {
+		BenchLayout::Plain => commit_all(store.as_mut(), (0..rows).map(|i| (i, i.to_be_bytes().to_vec()))),
+		BenchLayout::PlainVarkey => {
+			let mut rng = StdRng::seed_from_u64(1);
+			let rows = (0..rows).map(|i| (make_var_key(i, KeyLengths::default(), &mut rng).0, i.to_be_bytes().to_vec()));
+			commit_rows(store.as_mut(), rows)
+		},
+		BenchLayout::Index => commit_all(store.as_mut(), (0..rows).map(|i| (i, tx_hash(i)))),
+		BenchLayout::Range => commit_all(store.as_mut(), (0..rows).map(|i| (i, i.to_be_bytes().to_vec()))),
+		BenchLayout::Dictionary => {
+			let mut addresses = AddressStream::new(rows, 2);
+			let committed = commit_all(store.as_mut(), (0..rows).zip(addresses.by_ref().map(|address| address.0)));
+			addresses.join();
+			committed
+		},
+	}?;
+	store.flush()?;
+	let write = start.elapsed();
+
+	let start = Instant::now();
+	store.settle_for_reads()?;
+	let settle = start.elapsed();
+	let disk_bytes = dir_size(&dir);
+
+	// Variable keys only start with the row's index, so they are not looked up.
+	let reads = if layout == BenchLayout::PlainVarkey || rows == 0 { 0 } else { reads };
+	let mut chooser = KeyChooser::new(rows.max(1), KeyDistribution::Uniform, 0.99, 7);
+	let mut hits = 0;
+	let start = Instant::now();
+	for _ in 0..reads {
+		if store.get_value(&make_key(chooser.next_index()).to_be_bytes())?.is_some() {
+			hits += 1;
+		}
+	}
+	let read = start.elapsed();
+	Ok(BenchRecord { backend, layout, rows, write, settle, reads, hits, read, disk_bytes })
+}
+
+fn tx_hash(i: u64) -> Vec<u8> {
+	let mut hash = vec![0u8; 32];
+	hash[..8].copy_from_slice(&i.to_be_bytes());
+	hash
+}
+
+/// `commit_rows` of rows keyed by `make_key`.
+fn commit_all(store: &mut dyn DynStore, rows: impl Iterator<Item = (u64, Vec<u8>)>) -> BenchResult<()> {
+	commit_rows(store, rows.map(|(i, value)| (make_key(i).to_be_bytes().to_vec(), value)))
+}
+
+/// Commits `rows` in batches of `BATCH`.
+pub(crate) fn commit_rows(store: &mut dyn DynStore, rows: impl Iterator<Item = (Vec<u8>, Vec<u8>)>) -> BenchResult<()> {
+	let mut batch = Vec::with_capacity(BATCH);
+	for row in rows {
+		batch.push(row);
+		if batch.len() == BATCH {
+			store.commit(&batch)?;
+			batch.clear();
+		}
+	}
+	if !batch.is_empty() {
+		store.commit(&batch)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use core::bench_common::dir_size_by_kind;
+	use tempfile::tempdir;
+
+	use super::*;
+
+	#[test]
+	fn dispatches_to_each_enabled_backend() {
+		assert_eq!(Backend::parse("MDBX"), Some(Backend::Libmdbx));
+		assert_eq!(Backend::parse("libmdbx"), Some(Backend::Libmdbx));
+		assert_eq!(Backend::parse("leveldb"), None);
+		assert!(Backend::parse_list("fst,leveldb").is_err());
+		assert!(Backend::parse_list(" , ").is_err());
+
+		let dir = tempdir().unwrap();
+		for backend in Backend::ALL {
+			if !backend.enabled() {
+				assert!(backend.open(&dir.path().join("disabled"), BenchLayout::Plain).is_err());
+				assert!(Backend::parse_list(backend.name()).is_err());
+				continue
+			}
+			assert_eq!(Backend::parse_list(&format!("{},{}", backend.name(), backend.name())).unwrap(), [backend]);
+			for layout in [BenchLayout::Plain, BenchLayout::Dictionary] {
+				let record = run(backend, dir.path(), layout, 3_000, 200).unwrap();
+				assert_eq!((record.backend, record.layout, record.rows), (backend, layout, 3_000));
+				assert_eq!(record.hits, 200, "{} {}", backend.name(), layout.name());
+				assert!(record.disk_bytes > 0);
+				assert!(record.csv_row().starts_with(&format!("{},{},3000,", backend.name(), layout.name())));
+				assert_eq!(record.csv_row().split(',').count(), BenchRecord::CSV_HEADER.split(',').count());
+			}
+			// The files on disk are the selected backend's own.
+			let kinds = dir_size_by_kind(&dir.path().join(backend.name()).join("plain"));
+			let marker = match backend {
+				Backend::Redb => Some("redb"),
+				Backend::Fst => Some("fst"),
+				Backend::Rocksdb => Some("sst"),
+				Backend::Libmdbx => Some("dat"),
+				Backend::Fjall | Backend::Parity => None,
+			};
+			if let Some(marker) = marker {
+				assert!(kinds.contains_key(marker), "{}: {kinds:?}", backend.name());
+			}
+		}
+	}
+}
@@ -0,0 +1,107 @@
+use std::{
+	fs::File,
+	io::{BufWriter, Write},
+	path::{Path, PathBuf},
+};
+
+use core::bench_common::{LayoutSet, ReadLoad};
+use core::dyn_store::{BenchError, BenchResult};
+use microbench::backend::{run, Backend, BenchRecord};
+use microbench::compare::compare;
+
+fn main() -> BenchResult<()> {
+    let mut args = std::env::args().skip(1);
+    let mut total = 10_000_000u64;
+    let mut reads = ReadLoad::default().reads;
+    let mut base: Option<PathBuf> = None;
+    let mut csv: Option<PathBuf> = None;
+    let mut layouts = LayoutSet::default();
+    let mut backends = Backend::enabled_all();
+    let mut compared: Option<(Backend, Backend)> = None;
+
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"--total" => {
+				if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+					total = v;
+				}
+			},
+			"--reads" => {
+				if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+					reads = v;
+				}
+			},
+			"--backend" | "--backends" => {
+				if let Some(list) = args.next() {
+					backends = Backend::parse_list(&list).unwrap_or_else(|err| panic!("--backend: {err}"));
+				}
+			},
+			"--compare" => {
+				if let Some(list) = args.next() {
+					let pair = Backend::parse_list(&list).unwrap_or_else(|err| panic!("--compare: {err}"));
+					let [left, right] = pair[..] else { panic!("--compare: needs two different backends") };
+					compared = Some((left, right));
+				}
+			},
+			"--dir" => {
+				if let Some(p) = args.next() {
+					base = Some(PathBuf::from(p));
+				}
+			},
+			"--csv" => {
+				if let Some(p) = args.next() {
+					csv = Some(PathBuf::from(p));
+				}
+			},
+			"--layouts" | "--benches" => {
+				if let Some(list) = args.next() {
+					layouts = LayoutSet::parse(&list).unwrap_or_else(|err| panic!("--layouts: {err}"));
+				}
+			},
+			_ => {},
+		}
+	}
+
+	let base = base.unwrap_or_else(|| std::env::temp_dir().join(Path::new("unified_bench")));
+	std::fs::create_dir_all(&base)?;
+	if let Some((left, right)) = compared {
+		// Same rows into both, then `--reads` queries: the run fails at the first differing answer.
+		for layout in layouts.iter() {
+			let pair = format!("{} vs {} {}", left.name(), right.name(), layout.name());
+			match compare(left, right, &base, layout, total, reads)? {
+				Ok(queries) => println!("{pair}: {queries} queries agree"),
+				Err(divergence) => return Err(BenchError::Other(format!("{pair}: {divergence}").into())),
+			}
+		}
+		std::fs::remove_dir_all(base.join("compare")).ok();
+		return Ok(())
+	}
+	let csv = csv.unwrap_or_else(|| base.join("results.csv"));
+	// Rows are written as they come, so an aborted run keeps the results it got.
+	let mut out = BufWriter::new(File::create(&csv)?);
+	writeln!(out, "{}", BenchRecord::CSV_HEADER)?;
+
+	// One store at a time, so no backend competes with another for the disk.
+	for backend in backends {
+		for layout in layouts.iter() {
+			let record = run(backend, &base, layout, total, reads)?;
+			println!(
+				"{} {}: {} rows in {:.2?}, settled in {:.2?}, {}/{} reads in {:.2?}, {:.1} MiB",
+				backend.name(),
+				layout.name(),
+				record.rows,
+				record.write,
+				record.settle,
+				record.hits,
+				record.reads,
+				record.read,
+				record.disk_bytes as f64 / (1024.0 * 1024.0)
+			);
+			writeln!(out, "{}", record.csv_row())?;
+			out.flush()?;
+			std::fs::remove_dir_all(base.join(backend.name()).join(layout.name())).ok();
+		}
+	}
+	println!("results: {}", csv.display());
+	Ok(())
+}
@@ -0,0 +1,244 @@
+//! Differential check of two backends: both get the same rows, then every query must get the same
+//! answer from each, so a backend that drops, reorders or misplaces rows shows up as the first
+//! query it answers differently.
+
+use std::{fmt, path::Path};
+
+use core::bench_common::{make_key, make_var_key, random_address, BenchLayout, KeyLengths};
+use core::dyn_store::{BenchResult, DynStore};
+use core::inspect::hex;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+use crate::backend::{commit_rows, Backend};
+
+/// Seed of the `dataset` that `--compare` writes to both backends.
+pub const COMPARE_SEED: u64 = 11;
+
+/// Rows of `layout` drawn from `seed`, the same on every call: keys are `make_key(i)` as in
+/// `run`, `Range` values repeat every 4 keys and `Dictionary` addresses every 5, so reverse
+/// lookups return several keys.
+pub fn dataset(layout: BenchLayout, rows: u64, seed: u64) -> Vec<(Vec<u8>, Vec<u8>)> {
+	let mut rng = StdRng::seed_from_u64(seed);
+	let mut address = Vec::new();
+	(0..rows)
+		.map(|i| {
+			let key = make_key(i).to_be_bytes().to_vec();
+			match layout {
+				BenchLayout::Plain => (key, i.to_be_bytes().to_vec()),
+				BenchLayout::PlainVarkey => (make_var_key(i, KeyLengths::default(), &mut rng).0, i.to_be_bytes().to_vec()),
+				BenchLayout::Index => {
+					let mut hash = vec![0u8; 32];
+					rng.fill_bytes(&mut hash);
+					(key, hash)
+				},
+				BenchLayout::Range => (key, (i / 4).to_be_bytes().to_vec()),
+				BenchLayout::Dictionary => {
+					if i.is_multiple_of(5) {
+						address = random_address(&mut rng).0;
+					}
+					(key, address.clone())
+				},
+			}
+		})
+		.collect()
+}
+
+/// Commits `rows` in two halves with a flush after each, so answers come from several segments or
+/// pages rather than from a single memtable.
+pub fn populate(store: &mut dyn DynStore, rows: &[(Vec<u8>, Vec<u8>)]) -> BenchResult<()> {
+	let (first, second) = rows.split_at(rows.len() / 2);
+	for half in [first, second] {
+		commit_rows(store, half.iter().cloned())?;
+		store.flush()?;
+	}
+	Ok(())
+}
+
+/// What a store answered to one query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Answer {
+	One(Option<Vec<u8>>),
+	/// Keys of a value, sorted, as backends need not agree on their order.
+	Many(Vec<Vec<u8>>),
+}
+
+impl fmt::Display for Answer {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Answer::One(None) => write!(f, "none"),
+			Answer::One(Some(bytes)) => write!(f, "{}", hex(bytes)),
+			Answer::Many(keys) => write!(f, "[{}]", keys.iter().map(|key| hex(key)).collect::<Vec<_>>().join(", ")),
+		}
+	}
+}
+
+/// The first query two stores answered differently.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+	/// The `DynStore` method asked, e.g. `get_keys_for_value`.
+	pub query: &'static str,
+	/// The key or value it was asked about.
+	pub input: Vec<u8>,
+	pub left: Answer,
+	pub right: Answer,
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}({}): {} vs {}", self.query, hex(&self.input), self.left, self.right)
+	}
+}
+
+/// Asks `left` and `right` about every row of `rows` up to `queries` of them, evenly spaced, and
+/// about a key and a value neither holds: `get_value` of the key, and for the layouts keeping a
+/// reverse index `get_key_for_value` and, where a value has several keys, `get_keys_for_value` of
+/// the value. Returns the queries asked, or the first one answered differently.
+pub fn diff(
+	left: &dyn DynStore,
+	right: &dyn DynStore,
+	layout: BenchLayout,
+	rows: &[(Vec<u8>, Vec<u8>)],
+	queries: u64,
+) -> BenchResult<Result<u64, Divergence>> {
+	let step = (rows.len() as u64 / queries.max(1)).max(1) as usize;
+	let missing = (u64::MAX.to_be_bytes().to_vec(), b"missing".to_vec());
+	let mut asked = 0;
+	for (key, value) in rows.iter().step_by(step).chain([&missing]) {
+		asked += 1;
+		let (l, r) = (left.get_value(key)?, right.get_value(key)?);
+		if l != r {
+			return Ok(Err(Divergence { query: "get_value", input: key.clone(), left: Answer::One(l), right: Answer::One(r) }))
+		}
+		if matches!(layout, BenchLayout::Plain | BenchLayout::PlainVarkey) {
+			continue
+		}
+		asked += 1;
+		let (l, r) = (left.get_key_for_value(value)?, right.get_key_for_value(value)?);
+		if l != r {
+			let (left, right) = (Answer::One(l), Answer::One(r));
+			return Ok(Err(Divergence { query: "get_key_for_value", input: value.clone(), left, right }))
+		}
+		if layout == BenchLayout::Index {
+			continue
+		}
+		asked += 1;
+		let (mut l, mut r) = (left.get_keys_for_value(value)?, right.get_keys_for_value(value)?);
+		l.sort();
+		r.sort();
+		if l != r {
+			let (left, right) = (Answer::Many(l), Answer::Many(r));
+			return Ok(Err(Divergence { query: "get_keys_for_value", input: value.clone(), left, right }))
+		}
+	}
+	Ok(Ok(asked))
+}
+
+/// Writes the `dataset` of `rows` rows of `layout` into fresh stores of `left` and `right` under
+/// `base/compare/<backend>/<layout>` and `diff`s them on up to `queries` rows.
+pub fn compare(
+	left: Backend,
+	right: Backend,
+	base: &Path,
+	layout: BenchLayout,
+	rows: u64,
+	queries: u64,
+) -> BenchResult<Result<u64, Divergence>> {
+	let data = dataset(layout, rows, COMPARE_SEED);
+	let mut stores = Vec::with_capacity(2);
+	for backend in [left, right] {
+		let dir = base.join("compare").join(backend.name()).join(layout.name());
+		if dir.exists() {
+			std::fs::remove_dir_all(&dir)?;
+		}
+		let mut store = backend.open(&dir, layout)?;
+		populate(store.as_mut(), &data)?;
+		stores.push(store);
+	}
+	diff(stores[0].as_ref(), stores[1].as_ref(), layout, &data, queries)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::{BTreeMap, BTreeSet};
+
+	use super::*;
+
+	/// Every layout's answers kept in maps: each key's value, and each value's keys.
+	#[derive(Default)]
+	struct ReferenceStore {
+		values: BTreeMap<Vec<u8>, Vec<u8>>,
+		keys: BTreeMap<Vec<u8>, BTreeSet<Vec<u8>>>,
+	}
+
+	impl DynStore for ReferenceStore {
+		fn get_value(&self, key: &[u8]) -> BenchResult<Option<Vec<u8>>> {
+			Ok(self.values.get(key).cloned())
+		}
+
+		fn get_key_for_value(&self, value: &[u8]) -> BenchResult<Option<Vec<u8>>> {
+			Ok(self.keys.get(value).and_then(|keys| keys.first().cloned()))
+		}
+
+		fn get_keys_for_value(&self, value: &[u8]) -> BenchResult<Vec<Vec<u8>>> {
+			Ok(self.keys.get(value).map(|keys| keys.iter().cloned().collect()).unwrap_or_default())
+		}
+
+		fn commit(&mut self, items: &[(Vec<u8>, Vec<u8>)]) -> BenchResult<()> {
+			for (key, value) in items {
+				if let Some(old) = self.values.insert(key.clone(), value.clone())
+					&& let Some(keys) = self.keys.get_mut(&old)
+				{
+					keys.remove(key);
+				}
+				self.keys.entry(value.clone()).or_default().insert(key.clone());
+			}
+			Ok(())
+		}
+
+		fn flush(&mut self) -> BenchResult<()> {
+			Ok(())
+		}
+
+		fn settle_for_reads(&mut self) -> BenchResult<()> {
+			Ok(())
+		}
+
+		fn clear(&mut self) -> BenchResult<()> {
+			self.values.clear();
+			self.keys.clear();
+			Ok(())
+		}
+	}
+
+	#[cfg(feature = "fst")]
+	#[test]
+	fn fst_answers_like_the_reference_store() {
+		let dir = tempfile::tempdir().unwrap();
+		for layout in BenchLayout::ALL {
+			let rows = dataset(layout, 5_000, COMPARE_SEED);
+			let mut fst = Backend::Fst.open(&dir.path().join(layout.name()), layout).unwrap();
+			let mut reference = ReferenceStore::default();
+			populate(fst.as_mut(), &rows).unwrap();
+			populate(&mut reference, &rows).unwrap();
+			let asked = diff(fst.as_ref(), &reference, layout, &rows, 1_000).unwrap();
+			assert!(asked.as_ref().is_ok_and(|asked| *asked > 1_000), "{}: {asked:?}", layout.name());
+		}
+	}
+
+	#[test]
+	fn the_first_differing_row_is_reported_with_its_key() {
+		let rows = dataset(BenchLayout::Plain, 100, COMPARE_SEED);
+		assert_eq!(rows, dataset(BenchLayout::Plain, 100, COMPARE_SEED));
+		let (mut left, mut right) = (ReferenceStore::default(), ReferenceStore::default());
+		populate(&mut left, &rows).unwrap();
+		populate(&mut right, &rows).unwrap();
+		let (key, value) = rows[42].clone();
+		right.commit(&[(key.clone(), b"other".to_vec())]).unwrap();
+		right.commit(&[(rows[70].0.clone(), b"later".to_vec())]).unwrap();
+		let divergence = diff(&left, &right, BenchLayout::Plain, &rows, 100).unwrap().unwrap_err();
+		assert_eq!(divergence.query, "get_value");
+		assert_eq!(divergence.input, key);
+		assert_eq!(divergence.left, Answer::One(Some(value)));
+		assert_eq!(divergence.to_string(), format!("get_value({}): 000000000000002a vs 6f74686572", hex(&key)));
+	}
+}
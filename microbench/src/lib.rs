@@ -0,0 +1,8 @@
+//! Benchmarks across the backends enabled by features.
+//!
+//! Criterion microbenchmarks live in `benches/`. The `bench` binary runs the same workload on
+//! several backends in one process and collects the results in a single CSV; see `backend`. With
+//! `--compare` it instead checks that two backends answer the same queries alike; see `compare`.
+
+pub mod backend;
+pub mod compare;
@@ -1,6 +1,14 @@
-use core::store_interface::{ProgressTracker, StoreCodec, StoreRead, StoreWrite};
+use core::dyn_store::BenchError;
+use core::inspect::KeyDump;
+use core::store_builder::BuilderOptions;
+use core::store_interface::{
+	CommitStats, ConsistencyReport, ExpiringStore, Mismatch, OpenMode, ProgressTracker, Retryable, StoreCodec,
+	StoreRead, StoreWrite, NEVER_EXPIRES, check_columns, check_expiring, check_indexed_value, check_layout,
+	coalesce_by_encoded_key, column_id, composite_key, composite_prefix, decode_exact, expiry_after, is_transient_io,
+	sort_by_encoding, split_composite, stored_value, unexpired, unix_millis,
+};
 use fjall::{Config, Keyspace, Partition, PartitionCreateOptions, PersistMode};
-use std::{marker::PhantomData, path::Path};
+use std::{fs, marker::PhantomData, path::{Path, PathBuf}, sync::Arc, time::Duration};
 
 #[derive(Debug)]
 pub enum StoreError {
@@ -19,15 +27,36 @@ impl std::fmt::Display for StoreError {
 
 impl std::error::Error for StoreError {}
 
+/// Keyspace writes do not conflict, so only an interrupted or timed out io call is worth trying
+/// again; a failed flush poisons the keyspace for good.
+impl Retryable for StoreError {
+	fn is_retryable(&self) -> bool {
+		matches!(self, StoreError::Fjall(fjall::Error::Io(err)) if is_transient_io(err))
+	}
+}
+
+impl From<StoreError> for BenchError {
+	fn from(err: StoreError) -> Self {
+		BenchError::store(err)
+	}
+}
+
 impl From<fjall::Error> for StoreError {
 	fn from(err: fjall::Error) -> Self {
 		StoreError::Fjall(err)
 	}
 }
 
+/// Snapshot reads surface the LSM tree's own error.
+impl From<fjall::LsmError> for StoreError {
+	fn from(err: fjall::LsmError) -> Self {
+		StoreError::Fjall(err.into())
+	}
+}
+
 pub type StoreResult<T> = Result<T, StoreError>;
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct FjallOptions {
 	pub max_journal_bytes: u64,
 	pub max_write_buffer_bytes: u64,
@@ -35,24 +64,53 @@ pub struct FjallOptions {
 	pub flush_workers: usize,
 	pub compaction_workers: usize,
 	pub manual_journal_persist: bool,
+	/// Skip writes a later one in the same commit overwrites; see `coalesce_by_encoded_key`.
+	pub coalesce_duplicates: bool,
+	/// `Plain` only: keep each value behind its expiry for `ExpiringStore`. A store must always
+	/// be opened with the setting it was created with.
+	pub expiring: bool,
+	/// Directory holding the journals, e.g. on a faster device; `None` keeps them in the store's.
+	/// fjall always reads them from `<path>/journals`, so that becomes a symlink to this directory.
+	pub wal_dir: Option<PathBuf>,
+	/// Persist the journal when the store is dropped, so with `manual_journal_persist` commits a
+	/// caller never flushed are not lost. On by default, at the cost of an fsync per drop.
+	pub flush_on_drop: bool,
 }
 
 impl Default for FjallOptions {
 	fn default() -> Self {
 		let cpus = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
 		Self {
-			max_journal_bytes: 1 * 1024 * 1024 * 1024,     // 4 GiB WAL budget
+			max_journal_bytes: 1024 * 1024 * 1024,         // 1 GiB WAL budget
 			max_write_buffer_bytes: 128 * 1024 * 1024,     // 512 MiB memtables across partitions
 			cache_bytes: 512 * 1024 * 1024,                // 512 MiB cache
 			flush_workers: cpus.max(4),
 			compaction_workers: cpus.max(4),
 			manual_journal_persist: true,                  // favor write throughput over durability
+			coalesce_duplicates: false,
+			expiring: false,
+			wal_dir: None,
+			flush_on_drop: true,
 		}
 	}
 }
 
+impl BuilderOptions for FjallOptions {
+	fn set_coalesce_duplicates(&mut self, coalesce: bool) {
+		self.coalesce_duplicates = coalesce;
+	}
+
+	fn set_wal_dir(&mut self, dir: &Path) {
+		self.wal_dir = Some(dir.to_path_buf());
+	}
+
+	fn set_flush_on_drop(&mut self, flush: bool) {
+		self.flush_on_drop = flush;
+	}
+}
+
 /// Storage layouts supported by the generic store.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub enum Layout {
 	Plain { key_to_value: u8 },
 	UniqueIndex { key_to_value: u8, value_to_key: u8 },
@@ -79,16 +137,84 @@ impl Layout {
 		}
 	}
 
-	fn column_count(&self) -> usize {
+	pub fn name(&self) -> &'static str {
 		match self {
-			Layout::Plain { key_to_value } => (*key_to_value as usize) + 1,
-			Layout::UniqueIndex { value_to_key, .. } => (*value_to_key as usize) + 1,
-			Layout::Range { value_key_btree, .. } => (*value_key_btree as usize) + 1,
-			Layout::Dictionary { birth_key_key_btree, .. } => (*birth_key_key_btree as usize) + 1,
+			Layout::Plain { .. } => "plain",
+			Layout::UniqueIndex { .. } => "unique_index",
+			Layout::Range { .. } => "range",
+			Layout::Dictionary { .. } => "dictionary",
+		}
+	}
+
+	/// What `LAYOUT_PARTITION` records for each of the layout's columns, e.g. `range(1)`, so a
+	/// column cannot be reopened by another layout, or by the same one from another base.
+	fn tag(&self) -> String {
+		format!("{}({})", self.name(), self.columns()[0])
+	}
+
+	/// The column indices the layout uses, each backed by partition `col{idx}`; a layout built
+	/// `from` a nonzero base leaves the partitions below it to other layouts.
+	fn columns(&self) -> Vec<u8> {
+		match *self {
+			Layout::Plain { key_to_value } => vec![key_to_value],
+			Layout::UniqueIndex { key_to_value, value_to_key } => vec![key_to_value, value_to_key],
+			Layout::Range { key_to_value, value_key_btree } => vec![key_to_value, value_key_btree],
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
+				vec![key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree]
+			}
 		}
 	}
 }
 
+/// Layouts sharing one keyspace, e.g. `plain(0)` next to `range(1)`; no two may claim the same
+/// column.
+#[derive(Clone, Debug)]
+pub struct LayoutSet(Vec<Layout>);
+
+impl LayoutSet {
+	pub fn new(layouts: impl IntoIterator<Item = Layout>) -> StoreResult<Self> {
+		let layouts: Vec<Layout> = layouts.into_iter().collect();
+		let mut owners: Vec<Option<usize>> = Vec::new();
+		for (idx, layout) in layouts.iter().enumerate() {
+			for col in layout.columns() {
+				let col = col as usize;
+				if owners.len() <= col {
+					owners.resize(col + 1, None);
+				}
+				if let Some(other) = owners[col] {
+					return Err(StoreError::InvalidInput(format!(
+						"layouts {other} and {idx} both use column {col}"
+					)))
+				}
+				owners[col] = Some(idx);
+			}
+		}
+		Ok(Self(layouts))
+	}
+
+	pub fn layouts(&self) -> &[Layout] {
+		&self.0
+	}
+}
+
+/// Partition recording, under each column's partition name, the `Layout::tag` of the layout it
+/// belongs to.
+const LAYOUT_PARTITION: &str = "layout";
+
+/// A keyspace from before columns were tagged has no tags at all, so nothing tells which layout
+/// each of its partitions belongs to; its `col<id>` partitions must then be exactly the columns of
+/// `layouts`, the ones it is opened with. A new keyspace has none yet.
+fn check_untagged_keyspace(keyspace: &Keyspace, layouts: &[Layout]) -> StoreResult<()> {
+	let tags = keyspace.open_partition(LAYOUT_PARTITION, PartitionCreateOptions::default())?;
+	if !tags.is_empty()? {
+		return Ok(())
+	}
+	let stored = keyspace.list_partitions().iter().filter_map(|name| column_id(name)).collect::<Vec<_>>();
+	let requested = layouts.iter().flat_map(Layout::columns).map(usize::from);
+	let tags: Vec<String> = layouts.iter().map(Layout::tag).collect();
+	check_columns(stored, &tags.join(" and "), requested).map_err(StoreError::InvalidInput)
+}
+
 /// Generic store operating on a chosen layout and codecs.
 pub struct Store<K, V, KC, VC>
 where
@@ -97,11 +223,22 @@ where
 {
 	keyspace: Keyspace,
 	layout: Layout,
-	partitions: Vec<Partition>,
+	// Indexed by column; `None` for indices outside the layout.
+	partitions: Vec<Option<Partition>>,
+	read_only: bool,
+	coalesce_duplicates: bool,
+	expiring: bool,
+	flush_on_drop: bool,
 	progress: Option<ProgressTracker>,
 	_ph: PhantomData<(K, V, KC, VC)>,
 }
 
+/// A `Store` shared across threads: every read takes `&self`, and the store is `Send + Sync` for
+/// `Send + Sync` key and value types. The keyspace and its partitions are thread-safe handles, so
+/// readers do not contend beyond fjall's own locks; commits take `&mut self` and need a lock of the
+/// caller's.
+pub type SharedStore<K, V, KC, VC> = Arc<Store<K, V, KC, VC>>;
+
 impl<K, V, KC, VC> Store<K, V, KC, VC>
 where
 	KC: StoreCodec<K, Error = StoreError>,
@@ -112,21 +249,82 @@ where
 	}
 
 	pub fn open_with_options(path: &Path, layout: Layout, options: FjallOptions) -> StoreResult<Self> {
-		let keyspace = Config::new(path)
-			.manual_journal_persist(options.manual_journal_persist)
-			.max_journaling_size(options.max_journal_bytes)
-			.max_write_buffer_size(options.max_write_buffer_bytes)
-			.cache_size(options.cache_bytes)
-			.flush_workers(options.flush_workers)
-			.compaction_workers(options.compaction_workers)
-			.open()?;
-		let count = layout.column_count();
-		let mut partitions = Vec::with_capacity(count);
-		for idx in 0..count {
+		let keyspace = open_keyspace(path, &options)?;
+		check_untagged_keyspace(&keyspace, &[layout])?;
+		Self::with_keyspace(keyspace, layout, &options)
+	}
+
+	/// Opens one keyspace and a handle per layout of `layouts`, each on its own partitions.
+	pub fn open_set(path: &Path, layouts: &LayoutSet, options: FjallOptions) -> StoreResult<Vec<Self>> {
+		let keyspace = open_keyspace(path, &options)?;
+		check_untagged_keyspace(&keyspace, layouts.layouts())?;
+		layouts.layouts().iter().map(|&layout| Self::with_keyspace(keyspace.clone(), layout, &options)).collect()
+	}
+
+	fn with_keyspace(keyspace: Keyspace, layout: Layout, options: &FjallOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
+		let columns = layout.columns();
+		let tags = keyspace.open_partition(LAYOUT_PARTITION, PartitionCreateOptions::default())?;
+		let tag = layout.tag();
+		let mut untagged = Vec::new();
+		for idx in &columns {
+			// A column without a tag is new, or from before columns were tagged.
+			match tags.get(format!("col{idx}"))? {
+				Some(stored) => check_layout(&String::from_utf8_lossy(&stored), &tag).map_err(StoreError::InvalidInput)?,
+				None => untagged.push(format!("col{idx}")),
+			}
+		}
+		for name in untagged {
+			tags.insert(name, &tag)?;
+		}
+		let mut partitions = vec![None; columns.iter().max().map_or(0, |&idx| idx as usize + 1)];
+		for idx in columns {
 			let name = format!("col{idx}");
-			partitions.push(keyspace.open_partition(&name, PartitionCreateOptions::default())?);
+			partitions[idx as usize] = Some(keyspace.open_partition(&name, PartitionCreateOptions::default())?);
 		}
-		Ok(Self { keyspace, layout, partitions, progress: None, _ph: PhantomData })
+		Ok(Self {
+			keyspace,
+			layout,
+			partitions,
+			read_only: false,
+			coalesce_duplicates: options.coalesce_duplicates,
+			expiring: options.expiring,
+			flush_on_drop: options.flush_on_drop,
+			progress: None,
+			_ph: PhantomData,
+		})
+	}
+
+	/// `open_with_options` after checking `mode` against whether `path` already holds a store.
+	pub fn open_with_mode(path: &Path, layout: Layout, options: FjallOptions, mode: OpenMode) -> StoreResult<Self> {
+		mode.check(path, store_exists(path)).map_err(StoreError::InvalidInput)?;
+		Self::open_with_options(path, layout, options)
+	}
+
+	/// fjall has no read-only keyspace, so this opens it normally and refuses writes on this handle.
+	pub fn open_read_only(path: &Path, layout: Layout) -> StoreResult<Self> {
+		Self::open_read_only_with_options(path, layout, FjallOptions::default())
+	}
+
+	/// `open_read_only` of a store created with `options`, e.g. with expiring entries.
+	pub fn open_read_only_with_options(path: &Path, layout: Layout, options: FjallOptions) -> StoreResult<Self> {
+		if !path.exists() {
+			return Err(StoreError::InvalidInput(format!("no store at {}", path.display())))
+		}
+		let mut store = Self::open_with_options(path, layout, options)?;
+		store.read_only = true;
+		Ok(store)
+	}
+
+	fn partition(&self, col: u8) -> &Partition {
+		self.partitions[col as usize].as_ref().expect("column outside the layout")
+	}
+
+	fn ensure_writable(&self) -> StoreResult<()> {
+		if self.read_only {
+			return Err(StoreError::InvalidInput("store is opened read-only".into()))
+		}
+		Ok(())
 	}
 
 	pub fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
@@ -135,97 +333,171 @@ where
 		K: 'a,
 		V: 'a,
 	{
-		let mut processed = 0u64;
+		self.commit_counted(items).map(|_| ())
+	}
+
+	/// With `coalesce_duplicates` set, a write the batch overwrites later is skipped.
+	pub fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.commit_expiring(items, self.expiring.then_some(NEVER_EXPIRES))
+	}
+
+	/// `commit` of `Plain` entries that `get_value` stops returning once `ttl` has passed.
+	pub fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.ensure_expiring()?;
+		self.commit_expiring(items, Some(expiry_after(ttl))).map(|_| ())
+	}
+
+	/// `expires_at` is the expiry of every entry, `None` for a store without expiring entries.
+	fn commit_expiring<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		if self.coalesce_duplicates {
+			self.write_counted(coalesce_by_encoded_key::<K, V, KC, _>(items), expires_at)
+		} else {
+			self.write_counted(items, expires_at)
+		}
+	}
+
+	fn ensure_expiring(&self) -> StoreResult<()> {
+		if !self.expiring {
+			return Err(StoreError::InvalidInput("store is not opened with expiring entries".into()))
+		}
+		Ok(())
+	}
+
+	fn write_counted<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.ensure_writable()?;
+		let mut stats = CommitStats::default();
 		match self.layout {
 			Layout::Plain { key_to_value } => {
-				let ks = &self.partitions[key_to_value as usize];
+				let ks = self.partition(key_to_value);
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					ks.insert(kbytes.as_ref(), vbytes.as_ref())?;
-					processed += 1;
+					let stored = stored_value(vbytes.as_ref(), expires_at);
+					ks.insert(kbytes.as_ref(), stored.as_ref())?;
+					stats.record(kbytes.as_ref(), &stored);
 				}
 			},
 			Layout::UniqueIndex { key_to_value, value_to_key } => {
-				let ksv = &self.partitions[key_to_value as usize];
-				let ksk = &self.partitions[value_to_key as usize];
+				let ksv = self.partition(key_to_value);
+				let ksk = self.partition(value_to_key);
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
 					ksv.insert(kbytes.as_ref(), vbytes.as_ref())?;
 					ksk.insert(vbytes.as_ref(), kbytes.as_ref())?;
-					processed += 2;
+					stats.record(kbytes.as_ref(), vbytes.as_ref());
+					stats.record(vbytes.as_ref(), kbytes.as_ref());
 				}
 			},
 			Layout::Range { key_to_value, value_key_btree } => {
-				let kv_ks = &self.partitions[key_to_value as usize];
-				let btree_ks = &self.partitions[value_key_btree as usize];
+				let kv_ks = self.partition(key_to_value);
+				let btree_ks = self.partition(value_key_btree);
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
 					let kslice = kbytes.as_ref();
 					kv_ks.insert(kslice, vbytes.as_ref())?;
-					let vk = concat(vbytes.as_ref(), kslice);
-					btree_ks.insert(&vk, &[])?;
-					processed += 2;
+					let vk = composite_key(vbytes.as_ref(), kslice);
+					stats.record(kslice, vbytes.as_ref());
+					stats.record(&vk, &[]);
+					btree_ks.insert(vk, [])?;
 				}
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
 				use std::collections::HashMap;
-				let k2pk = &self.partitions[key_to_birth_key as usize];
-				let pk2v = &self.partitions[birth_key_to_value as usize];
-				let v2pk = &self.partitions[value_to_birth_key as usize];
-				let pk_k_btree = &self.partitions[birth_key_key_btree as usize];
-				let mut value_cache: HashMap<Vec<u8>, (Vec<u8>, bool)> = HashMap::new();
+				let k2pk = self.partition(key_to_birth_key);
+				let pk2v = self.partition(birth_key_to_value);
+				let v2pk = self.partition(value_to_birth_key);
+				let pk_k_btree = self.partition(birth_key_key_btree);
+				let mut value_cache: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					let (pk, is_new) = if let Some(entry) = value_cache.get(vbytes.as_ref()) {
-						entry.clone()
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
+					let (pk, is_new) = if let Some(pk) = value_cache.get(vbytes.as_ref()) {
+						(pk.clone(), false)
 					} else if let Some(pk) = v2pk.get(vbytes.as_ref())? {
 						let pk_vec = pk.as_ref().to_vec();
-						value_cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), false));
+						value_cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, false)
 					} else {
 						let pk_vec = kbytes.as_ref().to_vec();
-						value_cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), true));
+						value_cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, true)
 					};
 
 					if is_new {
 						v2pk.insert(vbytes.as_ref(), &pk)?;
 						pk2v.insert(&pk, vbytes.as_ref())?;
-						processed += 2;
+						stats.record(vbytes.as_ref(), &pk);
+						stats.record(&pk, vbytes.as_ref());
 					}
 					k2pk.insert(kbytes.as_ref(), &pk)?;
-					let pk_key = concat(&pk, kbytes.as_ref());
-					pk_k_btree.insert(&pk_key, &[])?;
-					processed += 2;
+					let pk_key = composite_key(&pk, kbytes.as_ref());
+					stats.record(kbytes.as_ref(), &pk);
+					stats.record(&pk_key, &[]);
+					pk_k_btree.insert(pk_key, [])?;
 				}
 			},
 		}
 		if let Some(p) = self.progress.as_mut() {
-			p.record(processed);
+			p.record(stats.rows);
 		}
-		Ok(())
+		Ok(stats)
 	}
 
 	pub fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		self.read_value(key, VC::decode)
+	}
+
+	/// `key`'s value as stored, `VC::encode` of what `get_value` returns, without decoding it.
+	pub fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		self.read_value(key, |bytes| Ok(bytes.to_vec()))
+	}
+
+	/// Passes the encoded value of `key`, stripped of its expiry, to `read`.
+	fn read_value<T>(&self, key: &K, read: impl FnOnce(&[u8]) -> StoreResult<T>) -> StoreResult<Option<T>> {
 		let kbytes = KC::encode(key);
 		match self.layout {
+			Layout::Plain { key_to_value } if self.expiring => {
+				let Some(stored) = self.partition(key_to_value).get(kbytes.as_ref())? else { return Ok(None) };
+				let value = unexpired(&stored, unix_millis()).map_err(StoreError::InvalidInput)?;
+				value.map(read).transpose()
+			},
 			Layout::Plain { key_to_value }
 			| Layout::UniqueIndex { key_to_value, .. }
 			| Layout::Range { key_to_value, .. } => {
-				self.partitions[key_to_value as usize]
+				self.partition(key_to_value)
 					.get(kbytes.as_ref())?
-					.map(|b| VC::decode(b.as_ref()))
+					.map(|b| read(b.as_ref()))
 					.transpose()
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, .. } => {
-				if let Some(pk) = self.partitions[key_to_birth_key as usize].get(kbytes.as_ref())? {
-					self.partitions[birth_key_to_value as usize]
+				if let Some(pk) = self.partition(key_to_birth_key).get(kbytes.as_ref())? {
+					self.partition(birth_key_to_value)
 						.get(pk.as_ref())?
-						.map(|b| VC::decode(b.as_ref()))
+						.map(|b| read(b.as_ref()))
 						.transpose()
 				} else {
 					Ok(None)
@@ -234,15 +506,23 @@ where
 		}
 	}
 
+	/// What the store holds for `key`: its encoding and that of its value, printing as hex dumps,
+	/// for a lookup that returns something unexpected.
+	pub fn debug_dump(&self, key: &K) -> StoreResult<KeyDump> {
+		let value = self.get_value(key)?;
+		Ok(KeyDump::new::<K, V, KC, VC>(key, value.as_ref()))
+	}
+
 	pub fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
 		let vbytes = VC::encode(value);
 		match self.layout {
 			Layout::UniqueIndex { value_to_key, .. } => {
-				self.partitions[value_to_key as usize]
+				self.partition(value_to_key)
 					.get(vbytes.as_ref())?
-					.map(|b| KC::decode(b.as_ref()))
+					.map(|b| decode_exact::<K, KC>(b.as_ref(), StoreError::InvalidInput))
 					.transpose()
 			},
+			Layout::Range { .. } | Layout::Dictionary { .. } => Ok(self.get_keys_for_value(value)?.into_iter().next()),
 			_ => Err(StoreError::InvalidInput("get_key_for_value not supported for this layout".into())),
 		}
 	}
@@ -251,27 +531,25 @@ where
 		let vbytes = VC::encode(value);
 		match self.layout {
 			Layout::Range { value_key_btree, .. } => {
-				let prefix = vbytes.as_ref().to_vec();
 				let mut out = Vec::new();
-				for kv in self.partitions[value_key_btree as usize].prefix(&prefix) {
+				for kv in self.partition(value_key_btree).prefix(composite_prefix(vbytes.as_ref())) {
 					let (k, _) = kv?;
-					let key_bytes = &k.as_ref()[prefix.len()..];
-					out.push(KC::decode(key_bytes)?);
+					match split_composite(&k) {
+						Some((head, key_bytes)) if head == vbytes.as_ref() => out.push(KC::decode(key_bytes)?),
+						_ => break,
+					}
 				}
 				Ok(out)
 			},
 			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
-				if let Some(pk) = self.partitions[value_to_birth_key as usize].get(vbytes.as_ref())? {
-					let prefix = pk.as_ref().to_vec();
-					let mut iter = self.partitions[birth_key_key_btree as usize].prefix(&prefix);
+				if let Some(pk) = self.partition(value_to_birth_key).get(vbytes.as_ref())? {
 					let mut out = Vec::new();
-					while let Some(kv) = iter.next() {
+					for kv in self.partition(birth_key_key_btree).prefix(composite_prefix(&pk)) {
 						let (k, _) = kv?;
-						if k.len() < prefix.len() || k.as_ref()[..prefix.len()] != prefix[..] {
-							break
+						match split_composite(&k) {
+							Some((head, key_bytes)) if head == pk.as_ref() => out.push(KC::decode(key_bytes)?),
+							_ => break,
 						}
-						let key_bytes = &k.as_ref()[prefix.len()..];
-						out.push(KC::decode(key_bytes)?);
 					}
 					Ok(out)
 				} else {
@@ -282,23 +560,440 @@ where
 		}
 	}
 
+	/// Reads the whole batch from snapshots taken at one instant. Btree scans run in head order;
+	/// `Dictionary` resolves birth keys in value order first.
+	pub fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		let instant = self.keyspace.instant();
+		let (btree, heads) = match self.layout {
+			Layout::Range { value_key_btree, .. } => (value_key_btree, sort_by_encoding::<V, VC>(values)),
+			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
+				let v2pk = self.partition(value_to_birth_key).snapshot_at(instant);
+				let mut pks = Vec::with_capacity(values.len());
+				for (idx, vbytes) in sort_by_encoding::<V, VC>(values) {
+					if let Some(pk) = v2pk.get(&vbytes)? {
+						pks.push((idx, pk.to_vec()));
+					}
+				}
+				pks.sort_by(|a, b| a.1.cmp(&b.1));
+				(birth_key_key_btree, pks)
+			},
+			_ => return Err(StoreError::InvalidInput("get_keys_for_values not supported for this layout".into())),
+		};
+		let btree = self.partition(btree).snapshot_at(instant);
+		let mut out: Vec<Vec<K>> = values.iter().map(|_| Vec::new()).collect();
+		for (idx, head) in heads {
+			for kv in btree.prefix(composite_prefix(&head)) {
+				let (k, _) = kv?;
+				match split_composite(&k) {
+					Some((h, key_bytes)) if h == head.as_slice() => out[idx].push(KC::decode(key_bytes)?),
+					_ => break,
+				}
+			}
+		}
+		Ok(out)
+	}
+
+	pub fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		match self.layout {
+			Layout::Dictionary { birth_key_to_value, .. } => {
+				Ok(self.partition(birth_key_to_value).values().map(|v| VC::decode(&v?)))
+			},
+			_ => Err(StoreError::InvalidInput("distinct_values not supported for this layout".into())),
+		}
+	}
+
+	pub fn first_key(&self) -> StoreResult<Option<K>> {
+		self.partition(self.primary_column()).first_key_value()?.map(|(k, _)| KC::decode(&k)).transpose()
+	}
+
+	pub fn last_key(&self) -> StoreResult<Option<K>> {
+		self.partition(self.primary_column()).last_key_value()?.map(|(k, _)| KC::decode(&k)).transpose()
+	}
+
+	/// The primary partition's item count from its memtables and segment metadata; keys overwritten
+	/// since the last compaction count once per version.
+	pub fn approx_key_count(&self) -> StoreResult<u64> {
+		Ok(self.partition(self.primary_column()).approximate_len() as u64)
+	}
+
+	/// The column holding every key: `key_to_value`, or `key_to_birth_key` for `Dictionary`.
+	fn primary_column(&self) -> u8 {
+		match self.layout {
+			Layout::Plain { key_to_value }
+			| Layout::UniqueIndex { key_to_value, .. }
+			| Layout::Range { key_to_value, .. } => key_to_value,
+			Layout::Dictionary { key_to_birth_key, .. } => key_to_birth_key,
+		}
+	}
+
+	pub fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		let mut report = ConsistencyReport::default();
+		match self.layout {
+			Layout::Plain { .. } => {},
+			Layout::UniqueIndex { key_to_value, value_to_key } => {
+				let v2k = self.partition(value_to_key);
+				for kv in self.partition(key_to_value).iter() {
+					let (k, v) = kv?;
+					report.checked += 1;
+					if v2k.get(&v)?.as_deref() != Some(k.as_ref()) {
+						report.mismatches.push(Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() });
+					}
+				}
+			},
+			Layout::Range { key_to_value, value_key_btree } => {
+				let btree = self.partition(value_key_btree);
+				for kv in self.partition(key_to_value).iter() {
+					let (k, v) = kv?;
+					report.checked += 1;
+					if !btree.contains_key(composite_key(&v, &k))? {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: v.to_vec(), key: k.to_vec() });
+					}
+				}
+			},
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, birth_key_key_btree, .. } => {
+				let pk2v = self.partition(birth_key_to_value);
+				let btree = self.partition(birth_key_key_btree);
+				for kv in self.partition(key_to_birth_key).iter() {
+					let (k, pk) = kv?;
+					report.checked += 1;
+					if !pk2v.contains_key(&pk)? {
+						report.mismatches.push(Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: pk.to_vec() });
+					}
+					if !btree.contains_key(composite_key(&pk, &k))? {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: pk.to_vec(), key: k.to_vec() });
+					}
+				}
+			},
+		}
+		Ok(report)
+	}
+
 	pub fn flush(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
 		self.keyspace.persist(PersistMode::SyncData)?;
 		Ok(())
 	}
+
+	/// Moves every memtable to disk and major-compacts each partition, leaving one sorted run per
+	/// partition for point reads to probe.
+	pub fn settle_for_reads(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		for partition in self.partitions.iter().flatten() {
+			partition.rotate_memtable_and_wait()?;
+			partition.major_compact()?;
+		}
+		self.keyspace.persist(PersistMode::SyncAll)?;
+		Ok(())
+	}
+
+	/// Deletes the expired entries of an expiring `Plain` store in one batch.
+	pub fn purge_expired(&mut self) -> StoreResult<u64> {
+		self.ensure_writable()?;
+		self.ensure_expiring()?;
+		let Layout::Plain { key_to_value } = self.layout else { return Ok(0) };
+		let partition = self.partition(key_to_value);
+		let now = unix_millis();
+		let mut purged = 0;
+		let mut batch = self.keyspace.batch();
+		for kv in partition.iter() {
+			let (k, stored) = kv?;
+			if matches!(unexpired(&stored, now), Ok(None)) {
+				batch.remove(partition, k);
+				purged += 1;
+			}
+		}
+		batch.commit()?;
+		Ok(purged)
+	}
+
+	/// Removes `keys` with their entries in the layout's other columns, returning how many were
+	/// present. A `Dictionary` birth key's `birth_key_to_value` and `value_to_birth_key` entries go
+	/// with the last key holding it, found by scanning its `birth_key||key` prefix in
+	/// `birth_key_key_btree`; entries there of keys since overwritten with another value are stale
+	/// and dropped on the way.
+	pub fn delete<'a, I>(&mut self, keys: I) -> StoreResult<u64>
+	where
+		I: IntoIterator<Item = &'a K>,
+		K: 'a,
+	{
+		self.ensure_writable()?;
+		let mut deleted = 0;
+		match self.layout {
+			Layout::Plain { key_to_value } => {
+				let ks = self.partition(key_to_value);
+				for k in keys {
+					let kbytes = KC::encode(k);
+					if ks.contains_key(kbytes.as_ref())? {
+						ks.remove(kbytes.as_ref())?;
+						deleted += 1;
+					}
+				}
+			},
+			Layout::UniqueIndex { key_to_value, value_to_key } => {
+				let ksv = self.partition(key_to_value);
+				let ksk = self.partition(value_to_key);
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(v) = ksv.get(kbytes.as_ref())? else { continue };
+					ksv.remove(kbytes.as_ref())?;
+					deleted += 1;
+					// The value may have moved to another key since.
+					if ksk.get(&v)?.is_some_and(|owner| owner.as_ref() == kbytes.as_ref()) {
+						ksk.remove(v)?;
+					}
+				}
+			},
+			Layout::Range { key_to_value, value_key_btree } => {
+				let kv_ks = self.partition(key_to_value);
+				let btree_ks = self.partition(value_key_btree);
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(v) = kv_ks.get(kbytes.as_ref())? else { continue };
+					kv_ks.remove(kbytes.as_ref())?;
+					deleted += 1;
+					btree_ks.remove(composite_key(&v, kbytes.as_ref()))?;
+				}
+			},
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
+				let k2pk = self.partition(key_to_birth_key);
+				let pk2v = self.partition(birth_key_to_value);
+				let v2pk = self.partition(value_to_birth_key);
+				let pk_k_btree = self.partition(birth_key_key_btree);
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(pk) = k2pk.get(kbytes.as_ref())? else { continue };
+					k2pk.remove(kbytes.as_ref())?;
+					deleted += 1;
+					pk_k_btree.remove(composite_key(&pk, kbytes.as_ref()))?;
+					let mut live = false;
+					for entry in pk_k_btree.prefix(composite_prefix(&pk)) {
+						let (entry, _) = entry?;
+						let Some((_, key)) = split_composite(&entry) else { continue };
+						if k2pk.get(key)?.is_some_and(|owner| owner == pk) {
+							live = true;
+						} else {
+							pk_k_btree.remove(entry)?;
+						}
+					}
+					if live {
+						continue
+					}
+					if let Some(v) = pk2v.get(&pk)? {
+						pk2v.remove(pk.clone())?;
+						if v2pk.get(&v)?.is_some_and(|owner| owner == pk) {
+							v2pk.remove(v)?;
+						}
+					}
+				}
+			},
+		}
+		Ok(deleted)
+	}
+
+	pub fn clear(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		// Deleting a partition only removes its folder once the last handle drops, so reopening
+		// the same name right away would race that cleanup; remove the keys instead.
+		for partition in self.partitions.iter().flatten() {
+			let mut batch = self.keyspace.batch();
+			for key in partition.keys() {
+				batch.remove(partition, key?);
+			}
+			batch.commit()?;
+		}
+		Ok(())
+	}
+}
+
+fn open_keyspace(path: &Path, options: &FjallOptions) -> StoreResult<Keyspace> {
+	if let Some(wal_dir) = &options.wal_dir {
+		link_journals(path, wal_dir)?;
+	}
+	Ok(Config::new(path)
+		.manual_journal_persist(options.manual_journal_persist)
+		.max_journaling_size(options.max_journal_bytes)
+		.max_write_buffer_size(options.max_write_buffer_bytes)
+		.cache_size(options.cache_bytes)
+		.flush_workers(options.flush_workers)
+		.compaction_workers(options.compaction_workers)
+		.open()?)
+}
+
+/// Points `<path>/journals` at `wal_dir`, creating both, unless it already does. Journals already
+/// in the store's directory, or in another one, are left alone and rejected.
+fn link_journals(path: &Path, wal_dir: &Path) -> StoreResult<()> {
+	fs::create_dir_all(path).map_err(fjall::Error::from)?;
+	fs::create_dir_all(wal_dir).map_err(fjall::Error::from)?;
+	// The link resolves against its own directory, not the working one.
+	let wal_dir = &std::path::absolute(wal_dir).map_err(fjall::Error::from)?;
+	let link = path.join("journals");
+	match fs::read_link(&link) {
+		Ok(target) if target == *wal_dir => Ok(()),
+		Ok(target) => Err(StoreError::InvalidInput(format!("journals of {} are in {}", path.display(), target.display()))),
+		Err(_) if link.exists() => {
+			Err(StoreError::InvalidInput(format!("journals of {} are in the store's directory", path.display())))
+		},
+		Err(_) => symlink_dir(wal_dir, &link),
+	}
+}
+
+#[cfg(unix)]
+fn symlink_dir(target: &Path, link: &Path) -> StoreResult<()> {
+	Ok(std::os::unix::fs::symlink(target, link).map_err(fjall::Error::from)?)
+}
+
+#[cfg(not(unix))]
+fn symlink_dir(_target: &Path, link: &Path) -> StoreResult<()> {
+	Err(StoreError::InvalidInput(format!("cannot link {} to a separate journal directory here", link.display())))
+}
+
+/// Whether `path` holds a store; fjall writes its `version` marker on creation.
+fn store_exists(path: &Path) -> bool {
+	path.join("version").is_file()
 }
 
-fn concat(a: &[u8], b: &[u8]) -> Vec<u8> {
-	let mut out = Vec::with_capacity(a.len() + b.len());
-	out.extend_from_slice(a);
-	out.extend_from_slice(b);
-	out
+impl<K, V, KC, VC> Drop for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	/// Persists the journal per `FjallOptions::flush_on_drop`; every handle of an `open_set`
+	/// persists the shared keyspace, which costs little after the first.
+	fn drop(&mut self) {
+		if self.flush_on_drop
+			&& !self.read_only
+			&& let Err(e) = self.keyspace.persist(PersistMode::SyncData)
+		{
+			eprintln!("fjall persist on drop error: {e}");
+		}
+	}
+}
+
+impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	type Error = StoreError;
+
+	fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		Store::get_value(self, key)
+	}
+
+	fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		Store::get_value_raw(self, key)
+	}
+
+	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
+		Store::get_key_for_value(self, value)
+	}
+
+	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
+		Store::get_keys_for_value(self, value)
+	}
+
+	fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		Store::get_keys_for_values(self, values)
+	}
+
+	fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		Store::distinct_values(self)
+	}
+
+	fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		Store::verify_consistency(self)
+	}
+
+	fn first_key(&self) -> StoreResult<Option<K>> {
+		Store::first_key(self)
+	}
+
+	fn last_key(&self) -> StoreResult<Option<K>> {
+		Store::last_key(self)
+	}
+
+	fn approx_key_count(&self) -> StoreResult<u64> {
+		Store::approx_key_count(self)
+	}
+}
+
+impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	type Options = FjallOptions;
+	type Layout = Layout;
+
+	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
+		Store::open_with_options(path, layout, options)
+	}
+
+	fn open_with_mode(path: &Path, layout: Self::Layout, options: Self::Options, mode: OpenMode) -> StoreResult<Self> {
+		Store::open_with_mode(path, layout, options, mode)
+	}
+
+	fn open_read_only(path: &Path, layout: Self::Layout) -> StoreResult<Self> {
+		Store::open_read_only(path, layout)
+	}
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_counted(self, items)
+	}
+
+	fn flush(&mut self) -> StoreResult<()> {
+		Store::flush(self)
+	}
+
+	fn settle_for_reads(&mut self) -> StoreResult<()> {
+		Store::settle_for_reads(self)
+	}
+
+	fn clear(&mut self) -> StoreResult<()> {
+		Store::clear(self)
+	}
+
+	fn set_progress_tracker(&mut self, tracker: ProgressTracker) {
+		self.progress = Some(tracker);
+	}
+}
+
+impl<K, V, KC, VC> ExpiringStore<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_with_ttl(self, items, ttl)
+	}
+
+	fn purge_expired(&mut self) -> StoreResult<u64> {
+		Store::purge_expired(self)
+	}
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use core::store_tests::{basic_value_roundtrip, multiple_keys_for_value, reverse_lookup_unique};
+	use core::store_tests::{
+		approximate_key_count, assert_send_sync, basic_value_roundtrip, batched_keys_for_values, builder_paths,
+		clear_then_reuse, coalesced_duplicate_keys, consistency_audit, crash_recovery, dictionary_birth_key_stable,
+		dictionary_commit_stats, dictionary_delete, dictionary_distinct_values, empty_values, expiring_entries,
+		key_bounds, mismatched_layout, multiple_keys_for_value, open_modes, prefix_values_isolated, raw_values,
+		read_only_rejects_writes, reverse_lookup_unique, shared_readers, separate_wal_dir, settle_keeps_contents,
+		sorted_commit_matches_unsorted, untagged_mismatched_layout, variable_length_keys_roundtrip,
+	};
+	use core::store_builder::StoreBuilder;
 	use tempfile::tempdir;
 
 	struct BytesCodec;
@@ -316,12 +1011,9 @@ mod tests {
 
 	#[test]
 	fn shared_basic_suite() {
-		basic_value_roundtrip(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
+		basic_value_roundtrip(|path| {
 			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
-				&path,
+				path,
 				Layout::plain(0),
 				FjallOptions::default(),
 			)
@@ -330,14 +1022,11 @@ mod tests {
 	}
 
 	#[test]
-	fn shared_reverse_suite() {
-		reverse_lookup_unique(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
+	fn shared_variable_length_keys_suite() {
+		variable_length_keys_roundtrip(|path| {
 			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
-				&path,
-				Layout::unique_index(0),
+				path,
+				Layout::plain(0),
 				FjallOptions::default(),
 			)
 			.unwrap()
@@ -345,67 +1034,377 @@ mod tests {
 	}
 
 	#[test]
-	fn shared_multiple_keys_suite() {
-		multiple_keys_for_value(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
+	fn shared_sorted_commit_suite() {
+		sorted_commit_matches_unsorted(|path| {
 			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
-				&path,
-				Layout::range(0),
+				path,
+				Layout::plain(0),
 				FjallOptions::default(),
 			)
 			.unwrap()
 		});
 	}
-}
 
-impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
-where
-	KC: StoreCodec<K, Error = StoreError>,
-	VC: StoreCodec<V, Error = StoreError>,
-{
-	type Error = StoreError;
+	#[test]
+	fn shared_crash_recovery_suite() {
+		let dir = tempdir().unwrap();
+		crash_recovery("store::tests::shared_crash_recovery_suite", dir.path(), |path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+				path,
+				Layout::plain(0),
+				FjallOptions::default(),
+			)
+			.unwrap()
+		});
+	}
 
-	fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
-		Store::get_value(self, key)
+	#[test]
+	fn shared_clear_suite() {
+		clear_then_reuse(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+				path,
+				Layout::dictionary(0),
+				FjallOptions::default(),
+			)
+			.unwrap()
+		});
 	}
 
-	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
-		Store::get_key_for_value(self, value)
+	#[test]
+	fn shared_dictionary_birth_key_suite() {
+		dictionary_birth_key_stable(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+				path,
+				Layout::dictionary(0),
+				FjallOptions::default(),
+			)
+			.unwrap()
+		});
 	}
 
-	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
-		Store::get_keys_for_value(self, value)
+	#[test]
+	fn shared_dictionary_commit_stats_suite() {
+		dictionary_commit_stats(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+				path,
+				Layout::dictionary(0),
+				FjallOptions::default(),
+			)
+			.unwrap()
+		});
 	}
-}
 
-impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
-where
-	KC: StoreCodec<K, Error = StoreError>,
-	VC: StoreCodec<V, Error = StoreError>,
-{
-	type Options = FjallOptions;
-	type Layout = Layout;
+	#[test]
+	fn shared_reverse_suite() {
+		reverse_lookup_unique(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+				path,
+				Layout::unique_index(0),
+				FjallOptions::default(),
+			)
+			.unwrap()
+		});
+	}
 
-	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
-		Store::open_with_options(path, layout, options)
+	#[test]
+	fn shared_multiple_keys_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			multiple_keys_for_value(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+					path,
+					layout,
+					FjallOptions::default(),
+				)
+				.unwrap()
+			});
+		}
 	}
 
-	fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
-	where
-		I: IntoIterator<Item = (&'a K, &'a V)>,
-		K: 'a,
-		V: 'a,
-	{
-		Store::commit(self, items)
+	#[test]
+	fn shared_batched_keys_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			batched_keys_for_values(|path| {
+				let options = FjallOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
 	}
 
-	fn flush(&mut self) -> StoreResult<()> {
-		Store::flush(self)
+	#[test]
+	fn shared_readers_suite() {
+		assert_send_sync::<SharedStore<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>();
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			shared_readers(|path| {
+				let options = FjallOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn raw_values_suite() {
+		for layout in [Layout::plain(0), Layout::range(0), Layout::dictionary(0)] {
+			raw_values::<BytesCodec, _, _>(|path| {
+				let options = FjallOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_prefix_values_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			prefix_values_isolated(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+					path,
+					layout,
+					FjallOptions::default(),
+				)
+				.unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_coalesce_suite() {
+		let open = |path: &Path, layout, coalesce_duplicates| {
+			let options = FjallOptions { coalesce_duplicates, ..FjallOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+		};
+		coalesced_duplicate_keys(
+			|path, c| open(path, Layout::plain(0), c),
+			|path, c| open(path, Layout::unique_index(0), c),
+		);
+	}
+
+	#[test]
+	fn shared_expiring_suite() {
+		let open = |path: &Path, layout| {
+			let options = FjallOptions { expiring: true, ..FjallOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options)
+		};
+		expiring_entries(|path| open(path, Layout::plain(0)).unwrap());
+		assert!(open(tempdir().unwrap().path(), Layout::range(0)).is_err());
+	}
+
+	#[test]
+	fn shared_settle_suite() {
+		settle_keeps_contents(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), FjallOptions::default())
+				.unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_key_bounds_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			key_bounds(|path| {
+				let options = FjallOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_approximate_key_count_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			approximate_key_count(|path| {
+				let options = FjallOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_distinct_values_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, FjallOptions::default())
+				.unwrap()
+		};
+		dictionary_distinct_values(|path| open(path, Layout::dictionary(0)), |path| open(path, Layout::range(0)));
+	}
+
+	#[test]
+	fn shared_dictionary_delete_suite() {
+		let dir = tempdir().unwrap();
+		dictionary_delete(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::dictionary(0)).unwrap(),
+			|store, keys| store.delete(keys),
+			|store, value| {
+				store.partition(2).get(value).unwrap().is_some()
+					|| store.partition(1).iter().any(|entry| entry.unwrap().1.as_ref() == value)
+			},
+		);
+	}
+
+	#[test]
+	fn shared_empty_values_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, FjallOptions::default())
+				.unwrap()
+		};
+		empty_values(
+			|path| open(path, Layout::plain(0)),
+			|path| open(path, Layout::unique_index(0)),
+			|path| open(path, Layout::range(0)),
+			|path| open(path, Layout::dictionary(0)),
+		);
+	}
+
+	#[test]
+	fn shared_consistency_audit_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, FjallOptions::default())
+				.unwrap()
+		};
+		consistency_audit(|path| open(path, Layout::unique_index(0)), |store, k, v| {
+			store.partition(1).insert(v, b"elsewhere").unwrap();
+			vec![Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() }]
+		});
+		consistency_audit(|path| open(path, Layout::range(0)), |store, k, v| {
+			store.partition(1).remove(composite_key(v, k)).unwrap();
+			vec![Mismatch::MissingBtreeEntry { head: v.to_vec(), key: k.to_vec() }]
+		});
+		// Every row has its own value, so each key is its own birth key.
+		consistency_audit(|path| open(path, Layout::dictionary(0)), |store, k, _| {
+			store.partition(1).remove(k).unwrap();
+			store.partition(3).remove(composite_key(k, k)).unwrap();
+			vec![
+				Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: k.to_vec() },
+				Mismatch::MissingBtreeEntry { head: k.to_vec(), key: k.to_vec() },
+			]
+		});
+	}
+
+	#[test]
+	fn layout_from_nonzero_base_opens_only_its_own_partitions() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, FjallOptions::default())
+				.unwrap()
+		};
+		let partition_names = |store: &Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>| {
+			let mut names: Vec<String> = store.keyspace.list_partitions().iter().map(|name| name.to_string()).collect();
+			names.sort();
+			names
+		};
+		let dir = tempdir().unwrap();
+		let mut store = open(dir.path(), Layout::plain(2));
+		assert_eq!(partition_names(&store), ["col2", "layout"]);
+		let (k, v) = (b"k".to_vec(), b"v".to_vec());
+		store.commit([(&k, &v)]).unwrap();
+		assert_eq!(store.get_value(&k).unwrap(), Some(v));
+		assert!(store.partition(2).contains_key(&k).unwrap());
+
+		let dir = tempdir().unwrap();
+		let store = open(dir.path(), Layout::dictionary(1));
+		assert_eq!(partition_names(&store), ["col1", "col2", "col3", "col4", "layout"]);
+	}
+
+	#[test]
+	fn shared_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::dictionary(0)).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0)),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::range(0)),
+		);
+	}
+
+	#[test]
+	fn shared_untagged_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		untagged_mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::dictionary(0)).unwrap(),
+			|| {
+				let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::dictionary(0)).unwrap();
+				let tags = store.keyspace.open_partition(LAYOUT_PARTITION, PartitionCreateOptions::default()).unwrap();
+				for idx in store.layout.columns() {
+					tags.remove(format!("col{idx}")).unwrap();
+				}
+			},
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0)),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::plain(0)),
+		);
+	}
+
+	#[test]
+	fn columns_reject_the_same_layout_from_another_base() {
+		let dir = tempdir().unwrap();
+		drop(Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::range(1)).unwrap());
+		let err = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::range(0)).err().unwrap();
+		assert!(matches!(err, StoreError::InvalidInput(ref msg) if msg.contains("range(1)")), "{err:?}");
+		// The rejected open tagged nothing, so `col0` is still free for another layout.
+		Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0)).unwrap();
+	}
+
+	#[test]
+	fn layout_set_rejects_overlap_and_shares_one_keyspace() {
+		assert!(LayoutSet::new([Layout::plain(0), Layout::range(0)]).is_err());
+		assert!(LayoutSet::new([Layout::range(0), Layout::dictionary(1)]).is_err());
+
+		let layouts = LayoutSet::new([Layout::plain(0), Layout::range(1)]).unwrap();
+		let dir = tempdir().unwrap();
+		let mut stores =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_set(dir.path(), &layouts, FjallOptions::default())
+				.unwrap();
+		let mut names: Vec<String> =
+			stores[0].keyspace.list_partitions().iter().map(|name| name.to_string()).collect();
+		names.sort();
+		assert_eq!(names, ["col0", "col1", "col2", "layout"]);
+
+		let (k, v) = (b"k".to_vec(), b"v".to_vec());
+		stores[0].commit([(&k, &v)]).unwrap();
+		stores[1].commit([(&k, &v)]).unwrap();
+		assert_eq!(stores[0].get_value(&k).unwrap(), Some(v.clone()));
+		assert_eq!(stores[1].get_keys_for_value(&v).unwrap(), vec![k.clone()]);
+		assert!(!stores[0].partition(0).is_empty().unwrap());
+		assert!(stores[1].partition(2).contains_key(composite_key(&v, &k)).unwrap());
 	}
 
-	fn set_progress(&mut self, label: &str, total: u64) {
-		self.progress = Some(ProgressTracker::new(label.to_string(), total));
+	#[test]
+	fn shared_open_modes_suite() {
+		let dir = tempdir().unwrap();
+		open_modes(dir.path(), |path, mode| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_mode(
+				path,
+				Layout::plain(0),
+				FjallOptions::default(),
+				mode,
+			)
+		});
+	}
+
+	#[test]
+	fn shared_builder_paths_suite() {
+		let dir = tempdir().unwrap();
+		builder_paths(dir.path(), "store.fjall", |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain(0))
+				.coalesce_duplicates(true)
+		});
+	}
+
+	#[test]
+	fn shared_separate_wal_dir_suite() {
+		let dir = tempdir().unwrap();
+		separate_wal_dir(dir.path(), |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain(0))
+		});
+		assert_eq!(fs::read_link(dir.path().join("data").join("journals")).unwrap(), dir.path().join("wal"));
+	}
+
+	#[test]
+	fn shared_read_only_suite() {
+		let dir = tempdir().unwrap();
+		read_only_rejects_writes(
+			|| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+					dir.path(),
+					Layout::plain(0),
+					FjallOptions::default(),
+				)
+				.unwrap()
+			},
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::plain(0)).unwrap(),
+		);
 	}
 }
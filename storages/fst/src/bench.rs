@@ -1,22 +1,34 @@
-mod compactor;
-mod segment;
-mod store;
-
-use std::path::{Path, PathBuf};
+use std::{
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+};
 
-use core::bench_codecs::{AddressCodec, AmountCodec, InvalidInput, KeyCodec, TimestampCodec, TxCodec};
+use core::bench_codecs::{AddressCodec, AmountCodec, DecodeError, InvalidInput, KeyCodec, TimestampCodec, TxCodec};
 use core::bench_common::{
-	run_all_parallel, run_dictionary, run_index, run_plain, run_range, Address, Amount, Key, NamedJob, Timestamp, TxHash,
+	parse_duration, report_disk_usage, run_all_parallel, run_dictionary, run_dictionary_reads, run_index, run_mixed,
+	run_plain, run_range, run_reads, store_wal_dir, Address, Amount, BenchLayout, Key, KeyDistribution, LayoutSet,
+	MixedLoad, NamedJob, ReadLoad, RunLength, Timestamp, TxHash,
 };
-use crate::store::{Layout, Store, StoreOptions, StoreResult};
+use core::store_builder::StoreBuilder;
+use core::store_interface::{StoreCodec, StoreWrite};
+use fst_bench::compactor::{CompactionStats, SharedCompactionStats};
+use fst_bench::memtable::MemtableKind;
+use fst_bench::segment::{ColumnStats, LengthPrefix, ReadCounters};
+use fst_bench::store::{self, Layout, Store, StoreOptions, StoreResult};
 
 const AVG_ADDRESS_BYTES: usize = 64;
 
+/// Captures the compaction stats handle of the store opened inside a write job.
+type StatsSlot = Arc<Mutex<Option<SharedCompactionStats>>>;
+
+/// Captures the read counters of the store opened inside a read job, per `--count-reads`.
+type ReadsSlot = Arc<Mutex<Option<Vec<Arc<ReadCounters>>>>>;
+
 struct FstInvalid;
 
 impl InvalidInput<store::StoreError> for FstInvalid {
-	fn invalid_input(msg: &'static str) -> store::StoreError {
-		store::StoreError::InvalidInput(msg.into())
+	fn invalid_input(err: DecodeError) -> store::StoreError {
+		store::StoreError::InvalidInput(err.to_string())
 	}
 }
 
@@ -29,9 +41,27 @@ type FAddressCodec = AddressCodec<store::StoreError>;
 fn main() -> StoreResult<()> {
     let mut args = std::env::args().skip(1);
     let mut total = 10_000_000u64;
+    let mut duration = None;
+    let mut resume = false;
     let mut mem_budget_bytes = store::DEFAULT_MEMTABLE_BUDGET_BYTES;
     let mut base: Option<PathBuf> = None;
-    let mut benches: Option<Vec<String>> = None;
+    let mut layouts = LayoutSet::default();
+    let mut read_load = ReadLoad::default();
+    let mut mixed_load = None;
+    let mut memtable = MemtableKind::default();
+    let mut sync_on_flush = false;
+    let mut background_flush = false;
+    let mut merge_threads = 1usize;
+    let mut compaction_workers = 1usize;
+    let mut value_prefix = LengthPrefix::default();
+    let mut mmap_values_max_bytes = 0u64;
+    let mut merge_size_ratio = None;
+    let mut wal_dir: Option<PathBuf> = None;
+    let mut count_reads = false;
+    let mut bloom_bits_per_key = None;
+    let mut adaptive_segments = false;
+    let mut fst_key_prefix = None;
+    let mut segments_per_dir = None;
 
 	while let Some(arg) = args.next() {
 		match arg.as_str() {
@@ -40,19 +70,100 @@ fn main() -> StoreResult<()> {
 					total = v;
 				}
 			},
+			"--resume" => resume = true,
+			"--duration" => {
+				if let Some(d) = args.next().and_then(|s| parse_duration(&s)) {
+					duration = Some(d);
+				}
+			},
 			"--mem-mb" => {
 				if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
 					mem_budget_bytes = (v as usize).saturating_mul(1024 * 1024).max(1);
 				}
 			},
+            "--memtable" => match args.next().as_deref() {
+                Some("btree") => memtable = MemtableKind::BTree,
+                Some("sorted-vec") => memtable = MemtableKind::SortedVec,
+                _ => {},
+            },
+            "--sync" => sync_on_flush = true,
+            "--background-flush" => background_flush = true,
+            "--value-prefix" => match args.next().as_deref() {
+                Some("1") => value_prefix = LengthPrefix::U8,
+                Some("2") => value_prefix = LengthPrefix::U16,
+                Some("4") => value_prefix = LengthPrefix::U32,
+                _ => {},
+            },
+            "--merge-threads" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<usize>().ok()) {
+                    merge_threads = v;
+                }
+            },
+            "--compaction-workers" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<usize>().ok()) {
+                    compaction_workers = v;
+                }
+            },
+            "--mmap-values" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+                    mmap_values_max_bytes = v.saturating_mul(1024 * 1024);
+                }
+            },
+            "--merge-size-ratio" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<f64>().ok()) {
+                    merge_size_ratio = Some(v);
+                }
+            },
+            "--count-reads" => count_reads = true,
+            "--bloom-bits" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<f64>().ok()).filter(|v| *v > 0.0) {
+                    bloom_bits_per_key = Some(v);
+                }
+            },
+            "--adaptive-segments" => adaptive_segments = true,
+            "--fst-key-prefix" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<u8>().ok()).filter(|v| *v > 0) {
+                    fst_key_prefix = Some(v);
+                }
+            },
+            "--segments-per-dir" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()).filter(|v| *v > 0) {
+                    segments_per_dir = Some(v);
+                }
+            },
+            "--wal-dir" => {
+                if let Some(p) = args.next() {
+                    wal_dir = Some(PathBuf::from(p));
+                }
+            },
             "--dir" => {
                 if let Some(p) = args.next() {
                     base = Some(PathBuf::from(p));
                 }
             },
-            "--benches" => {
+            "--reads" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+                    read_load.reads = v;
+                }
+            },
+            "--distribution" => {
+                if let Some(d) = args.next().and_then(|s| KeyDistribution::parse(&s)) {
+                    read_load.distribution = d;
+                }
+            },
+            "--theta" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<f64>().ok()).filter(|t| *t > 0.0 && *t < 1.0) {
+                    read_load.theta = v;
+                }
+            },
+            "--mixed" => {
+                if let Some(load) = args.next().and_then(|s| MixedLoad::parse(&s)) {
+                    mixed_load = Some(load);
+                }
+            },
+            "--layouts" | "--benches" => {
                 if let Some(list) = args.next() {
-                    benches = Some(list.split(',').map(|s| s.to_string()).collect());
+                    layouts = LayoutSet::parse(&list).unwrap_or_else(|err| panic!("--layouts: {err}"));
                 }
             },
             _ => {},
@@ -60,60 +171,299 @@ fn main() -> StoreResult<()> {
     }
 
 	let base = base.unwrap_or_else(|| std::env::temp_dir().join(Path::new("fst_bench")));
+	// `--resume` keeps the stores of an interrupted run and continues each ingest from its checkpoint.
+	let length = if resume { RunLength::Resumable(total) } else { RunLength::new(total, duration) };
 
-	let plain_opts = StoreOptions::from_estimates(total, 16 + 8, mem_budget_bytes);
-	let index_opts = StoreOptions::from_estimates(total, 16 + 32, mem_budget_bytes);
-	let range_opts = StoreOptions::from_estimates(total, 16 + 8, mem_budget_bytes);
-	let dict_opts = StoreOptions::from_estimates(total, 16 + AVG_ADDRESS_BYTES, mem_budget_bytes);
+	let tune = |opts: StoreOptions| {
+		let memtable_budget_bytes = adaptive_segments.then_some(mem_budget_bytes);
+		let opts = StoreOptions {
+			wal_dir: wal_dir.clone(),
+			bloom_bits_per_key,
+			memtable_budget_bytes,
+			fst_key_prefix,
+			segments_per_dir,
+			..opts
+		};
+		let opts = opts
+			.with_memtable(memtable)
+			.with_sync_on_flush(sync_on_flush)
+			.with_background_flush(background_flush)
+			.with_value_prefix(value_prefix)
+			.with_merge_threads(merge_threads)
+			.with_compaction_workers(compaction_workers)
+			.with_mmap_values(mmap_values_max_bytes);
+		match merge_size_ratio {
+			Some(ratio) => opts.with_merge_size_ratio(ratio),
+			None => opts,
+		}
+	};
+	let plain_opts = tune(StoreOptions::from_estimates(total, 16 + 8, mem_budget_bytes));
+	let index_opts = tune(StoreOptions::from_estimates(total, 16 + 32, mem_budget_bytes));
+	let range_opts = tune(StoreOptions::from_estimates(total, 16 + 8, mem_budget_bytes));
+	let dict_opts = tune(StoreOptions::from_estimates(total, 16 + AVG_ADDRESS_BYTES, mem_budget_bytes));
 
-	core::bench_common::cleanup_dirs(&base, &["merge", "plain", "index", "range", "dictionary"]);
+	if !resume {
+		core::bench_common::cleanup_dirs(&base, &layouts);
+		if let Some(wal_dir) = &wal_dir {
+			core::bench_common::cleanup_wal_dirs(wal_dir, &layouts);
+		}
+	}
+
+	let plain_stats = StatsSlot::default();
+	let index_stats = StatsSlot::default();
+	let range_stats = StatsSlot::default();
+	let dictionary_stats = StatsSlot::default();
 
     let jobs: Vec<NamedJob<store::StoreError>> = vec![
         {
             let base = base.clone();
-            NamedJob::new("plain", Box::new(move || run_plain(&base, total, move |path| fst_plain_factory(path, plain_opts))))
+            let plain_opts = plain_opts.clone();
+            let slot = plain_stats.clone();
+            NamedJob::with_progress(BenchLayout::Plain, Box::new(move |board| {
+                run_plain(&base, length, board, move |path| {
+                    let store = fst_plain_factory(path, &plain_opts)?;
+                    *slot.lock().unwrap() = Some(store.compaction_stats_handle());
+                    Ok(store)
+                })
+            }))
         },
         {
             let base = base.clone();
-            NamedJob::new("index", Box::new(move || run_index(&base, total, move |path| fst_index_factory(path, index_opts))))
+            let index_opts = index_opts.clone();
+            let slot = index_stats.clone();
+            NamedJob::with_progress(BenchLayout::Index, Box::new(move |board| {
+                run_index(&base, length, board, move |path| {
+                    let store = fst_index_factory(path, &index_opts)?;
+                    *slot.lock().unwrap() = Some(store.compaction_stats_handle());
+                    Ok(store)
+                })
+            }))
         },
         {
             let base = base.clone();
-            NamedJob::new("range", Box::new(move || run_range(&base, total, move |path| fst_range_factory(path, range_opts))))
+            let range_opts = range_opts.clone();
+            let slot = range_stats.clone();
+            NamedJob::with_progress(BenchLayout::Range, Box::new(move |board| {
+                run_range(&base, length, board, move |path| {
+                    let store = fst_range_factory(path, &range_opts)?;
+                    *slot.lock().unwrap() = Some(store.compaction_stats_handle());
+                    Ok(store)
+                })
+            }))
         },
         {
             let base = base.clone();
-            NamedJob::new("dictionary", Box::new(move || run_dictionary(&base, total, move |path| fst_dictionary_factory(path, dict_opts))))
+            let dict_opts = dict_opts.clone();
+            let slot = dictionary_stats.clone();
+            NamedJob::with_progress(BenchLayout::Dictionary, Box::new(move |board| {
+                run_dictionary(&base, length, board, move |path| {
+                    let store = fst_dictionary_factory(path, &dict_opts)?;
+                    *slot.lock().unwrap() = Some(store.compaction_stats_handle());
+                    Ok(store)
+                })
+            }))
         },
     ];
 
-    run_all_parallel(jobs, benches.as_deref().unwrap_or(&[]))?;
+    run_all_parallel(jobs, &layouts)?;
+
+	// Final compaction into a single segment per column to ease reads, one thread per store.
+	let plain_final = StatsSlot::default();
+	let index_final = StatsSlot::default();
+	let range_final = StatsSlot::default();
+	let dictionary_final = StatsSlot::default();
+
+	let merges: Vec<NamedJob<store::StoreError>> = vec![
+		{
+			let path = base.join("plain");
+			let plain_opts = plain_opts.clone();
+			let slot = plain_final.clone();
+			NamedJob::new(BenchLayout::Plain, Box::new(move || {
+				final_merge(fst_plain_factory(&path, &plain_opts)?, &path, "plain", total, &slot)
+			}))
+		},
+		{
+			let path = base.join("index");
+			let index_opts = index_opts.clone();
+			let slot = index_final.clone();
+			NamedJob::new(BenchLayout::Index, Box::new(move || {
+				final_merge(fst_index_factory(&path, &index_opts)?, &path, "index", 2 * total, &slot)
+			}))
+		},
+		{
+			let path = base.join("range");
+			let range_opts = range_opts.clone();
+			let slot = range_final.clone();
+			NamedJob::new(BenchLayout::Range, Box::new(move || {
+				final_merge(fst_range_factory(&path, &range_opts)?, &path, "range", 2 * total, &slot)
+			}))
+		},
+		{
+			let path = base.join("dictionary");
+			let dict_opts = dict_opts.clone();
+			let slot = dictionary_final.clone();
+			NamedJob::new(BenchLayout::Dictionary, Box::new(move || {
+				final_merge(fst_dictionary_factory(&path, &dict_opts)?, &path, "dictionary", 2 * total, &slot)
+			}))
+		},
+	];
+
+	run_all_parallel(merges, &layouts)?;
+
+	let slots = [
+		(BenchLayout::Plain, &plain_stats, &plain_final),
+		(BenchLayout::Index, &index_stats, &index_final),
+		(BenchLayout::Range, &range_stats, &range_final),
+		(BenchLayout::Dictionary, &dictionary_stats, &dictionary_final),
+	];
+	for (layout, ingest, final_merge) in slots.into_iter().filter(|(layout, ..)| layouts.contains(*layout)) {
+		report_compaction(layout.name(), ingest, final_merge);
+	}
+
+	// Time lookups of the settled plain and dictionary stores, keys drawn per `--distribution`.
+	let read_opts = plain_opts.clone().with_count_reads(count_reads);
+	let dict_read_opts = dict_opts.clone().with_count_reads(count_reads);
+	let reads_slot = ReadsSlot::default();
+	let dict_reads_slot = ReadsSlot::default();
+	let reads: Vec<NamedJob<store::StoreError>> = vec![
+		{
+			let base = base.clone();
+			let read_opts = read_opts.clone();
+			let slot = reads_slot.clone();
+			NamedJob::new(
+				BenchLayout::Plain,
+				Box::new(move || {
+					run_reads(&base, length, read_load, move |path| counted(fst_plain_factory(path, &read_opts)?, &slot))
+				}),
+			)
+		},
+		{
+			let base = base.clone();
+			let slot = dict_reads_slot.clone();
+			NamedJob::new(
+				BenchLayout::Dictionary,
+				Box::new(move || {
+					run_dictionary_reads(&base, length, read_load, move |path| {
+						counted(fst_dictionary_factory(path, &dict_read_opts)?, &slot)
+					})
+					.map(drop)
+				}),
+			)
+		},
+	];
+	run_all_parallel(reads, &layouts)?;
+	if count_reads && layouts.contains(BenchLayout::Plain) {
+		report_reads("plain reads", &reads_slot);
+	}
+	if count_reads && layouts.contains(BenchLayout::Dictionary) {
+		report_reads("dictionary reads", &dict_reads_slot);
+	}
+
+	// Write a fresh plain store while reading it, per `--mixed <writers>:<readers>`.
+	if let Some(load) = mixed_load {
+		let mixed_slot = ReadsSlot::default();
+		let mixed: Vec<NamedJob<store::StoreError>> = vec![{
+			let base = base.clone();
+			let slot = mixed_slot.clone();
+			NamedJob::new(
+				BenchLayout::Plain,
+				Box::new(move || {
+					run_mixed(&base, length, load, move |path| counted(fst_plain_factory(path, &read_opts)?, &slot)).map(drop)
+				}),
+			)
+		}];
+		run_all_parallel(mixed, &layouts)?;
+		if count_reads && layouts.contains(BenchLayout::Plain) {
+			report_reads("plain mixed", &mixed_slot);
+		}
+	}
 
-	// Final compaction into a single segment per column to ease reads.
-	let mut plain_store = fst_plain_factory(&base.join("plain"), plain_opts)?;
-	plain_store.multi_way_merge()?;
-	let mut index_store = fst_index_factory(&base.join("index"), index_opts)?;
-	index_store.multi_way_merge()?;
-	let mut range_store = fst_range_factory(&base.join("range"), range_opts)?;
-	range_store.multi_way_merge()?;
-	let mut dict_store = fst_dictionary_factory(&base.join("dictionary"), dict_opts)?;
-	dict_store.multi_way_merge()?;
+	Ok(())
+}
 
+/// Merges every column of `store` into one segment, printing rows merged per second as it goes
+/// and the directory size once done.
+/// `rows` is only the progress estimate; background merges may already have folded some away.
+fn final_merge<K, V, KC, VC>(
+	mut store: Store<K, V, KC, VC>,
+	path: &Path,
+	label: &str,
+	rows: u64,
+	slot: &StatsSlot,
+) -> StoreResult<()>
+where
+	KC: StoreCodec<K, Error = store::StoreError>,
+	VC: StoreCodec<V, Error = store::StoreError>,
+{
+	store.set_progress(&format!("{label} final merge"), rows);
+	store.settle_for_reads()?;
+	*slot.lock().unwrap() = Some(store.compaction_stats_handle());
+	report_disk_usage(path);
 	Ok(())
 }
 
-fn fst_plain_factory(path: &Path, options: StoreOptions) -> StoreResult<Store<Key, Amount, FKeyCodec, FAmountCodec>> {
-	Store::open(path, Layout::plain(0), options)
+fn slot_total(slot: &StatsSlot) -> CompactionStats {
+	slot.lock().unwrap().as_ref().map(|h| CompactionStats::total(&h.lock().unwrap())).unwrap_or_default()
+}
+
+fn report_compaction(label: &str, ingest: &StatsSlot, final_merge: &StatsSlot) {
+	let background = slot_total(ingest);
+	let settle = slot_total(final_merge);
+	println!(
+		"{label}: background compaction {} merges, {} rows in {:.2?}, reclaimed {} bytes; final merge {} rows in {:.2?}, reclaimed {} bytes",
+		background.merges,
+		background.rows_merged,
+		background.merge_time,
+		background.bytes_reclaimed,
+		settle.rows_merged,
+		settle.merge_time,
+		settle.bytes_reclaimed
+	);
+	// Segments that keep growing during the ingest mean the compactor fell behind the flushes.
+	let max_segments: Vec<u64> =
+		ingest.lock().unwrap().as_ref().map(|h| h.lock().unwrap().iter().map(|s| s.max_segments).collect()).unwrap_or_default();
+	println!("{label}: max segments per column {max_segments:?}, compaction fell behind {} times", background.backlog_warnings);
+}
+
+/// Hands `store` back after keeping its read counters in `slot`, which outlives it.
+fn counted<K, V, KC, VC>(store: Store<K, V, KC, VC>, slot: &ReadsSlot) -> StoreResult<Store<K, V, KC, VC>>
+where
+	KC: StoreCodec<K, Error = store::StoreError>,
+	VC: StoreCodec<V, Error = store::StoreError>,
+{
+	*slot.lock().unwrap() = Some(store.read_counters_handle());
+	Ok(store)
+}
+
+/// Prints the segments a lookup probed on average, over every column of the job's store.
+fn report_reads(label: &str, slot: &ReadsSlot) {
+	let stats: Vec<ColumnStats> = slot.lock().unwrap().iter().flatten().map(|c| c.stats()).collect();
+	let total = ColumnStats::total(&stats);
+	println!(
+		"{label}: read amplification {:.2} segments per get over {} gets",
+		total.read_amplification(),
+		total.gets
+	);
+}
+
+/// `options` with the `--wal-dir` base narrowed to the store at `path`.
+fn for_store(path: &Path, options: &StoreOptions) -> StoreOptions {
+	let wal_dir = options.wal_dir.as_deref().map(|base| store_wal_dir(base, path));
+	StoreOptions { wal_dir, ..options.clone() }
+}
+
+fn fst_plain_factory(path: &Path, options: &StoreOptions) -> StoreResult<Store<Key, Amount, FKeyCodec, FAmountCodec>> {
+	StoreBuilder::new(path, Layout::plain(0)).options(for_store(path, options)).open()
 }
 
-fn fst_index_factory(path: &Path, options: StoreOptions) -> StoreResult<Store<Key, TxHash, FKeyCodec, FTxCodec>> {
-	Store::open(path, Layout::unique_index(0), options)
+fn fst_index_factory(path: &Path, options: &StoreOptions) -> StoreResult<Store<Key, TxHash, FKeyCodec, FTxCodec>> {
+	StoreBuilder::new(path, Layout::unique_index(0)).options(for_store(path, options)).open()
 }
 
-fn fst_range_factory(path: &Path, options: StoreOptions) -> StoreResult<Store<Key, Timestamp, FKeyCodec, FTimestampCodec>> {
-	Store::open(path, Layout::range(0), options)
+fn fst_range_factory(path: &Path, options: &StoreOptions) -> StoreResult<Store<Key, Timestamp, FKeyCodec, FTimestampCodec>> {
+	StoreBuilder::new(path, Layout::range(0)).options(for_store(path, options)).open()
 }
 
-fn fst_dictionary_factory(path: &Path, options: StoreOptions) -> StoreResult<Store<Key, Address, FKeyCodec, FAddressCodec>> {
-	Store::open(path, Layout::dictionary(0), options)
+fn fst_dictionary_factory(path: &Path, options: &StoreOptions) -> StoreResult<Store<Key, Address, FKeyCodec, FAddressCodec>> {
+	StoreBuilder::new(path, Layout::dictionary(0)).options(for_store(path, options)).open()
 }
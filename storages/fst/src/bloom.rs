@@ -0,0 +1,91 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
+use fst::{Map, Streamer};
+use memmap2::Mmap;
+
+use crate::store::StoreResult;
+
+/// Bytes of the hash count and CRC32 in front of the bit array.
+const HEADER_BYTES: usize = 5;
+
+/// Bloom filter over the keys of one segment, kept in a `.bloom` file next to its `.fst`. The
+/// file is the hash count (u8), the CRC32 (u32 LE) of the bit array, then the bit array. It is
+/// advisory: a segment whose file is missing, torn or corrupt loads without one and is probed.
+pub(crate) struct Bloom {
+	bits: Vec<u8>,
+	hashes: u32,
+}
+
+impl Bloom {
+	/// Sizes the filter at `bits_per_key` bits per key of `map`, with the hash count that gives
+	/// the fewest false positives for it; 10 bits per key keep them near 1%.
+	pub(crate) fn build(map: &Map<Mmap>, bits_per_key: f64) -> Self {
+		let bit_len = ((map.len() as f64 * bits_per_key).ceil() as usize).max(64).next_multiple_of(8);
+		let hashes = (bits_per_key * std::f64::consts::LN_2).round().clamp(1.0, 30.0) as u32;
+		let mut bits = vec![0u8; bit_len / 8];
+		let mut stream = map.stream();
+		while let Some((key, _)) = stream.next() {
+			for bit in bit_indexes(key, hashes, bit_len) {
+				bits[bit / 8] |= 1 << (bit % 8);
+			}
+		}
+		Self { bits, hashes }
+	}
+
+	/// False only if `key` is certainly not in the segment.
+	pub(crate) fn may_contain(&self, key: &[u8]) -> bool {
+		bit_indexes(key, self.hashes, self.bits.len() * 8).all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+	}
+
+	/// Written after the segment files without a sync: losing it only costs the skipped probes.
+	pub(crate) fn write(&self, path: &Path) -> StoreResult<()> {
+		let mut bytes = Vec::with_capacity(HEADER_BYTES + self.bits.len());
+		bytes.push(self.hashes as u8);
+		bytes.extend_from_slice(&crc32fast::hash(&self.bits).to_le_bytes());
+		bytes.extend_from_slice(&self.bits);
+		fs::write(path, bytes)?;
+		Ok(())
+	}
+
+	/// The filter stored at `path`, `None` if there is none or it does not check out.
+	pub(crate) fn load(path: &Path) -> Option<Self> {
+		let bytes = fs::read(path).ok()?;
+		let (header, bits) = bytes.split_at_checked(HEADER_BYTES)?;
+		let hashes = header[0] as u32;
+		let crc = u32::from_le_bytes(header[1..].try_into().expect("4 bytes"));
+		if hashes == 0 || bits.is_empty() || crc32fast::hash(bits) != crc {
+			return None
+		}
+		Some(Self { bits: bits.to_vec(), hashes })
+	}
+}
+
+/// The `.bloom` file of the segment whose FST is at `fst_path`.
+pub(crate) fn bloom_path(fst_path: &Path) -> PathBuf {
+	fst_path.with_extension("bloom")
+}
+
+/// The `hashes` bits of `key` in a filter of `bit_len` bits, by double hashing one 64-bit hash:
+/// probe `i` is `h1 + i * h2`.
+fn bit_indexes(key: &[u8], hashes: u32, bit_len: usize) -> impl Iterator<Item = usize> {
+	let h1 = hash(key);
+	let h2 = mix(h1) | 1;
+	(0..hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bit_len as u64) as usize)
+}
+
+/// FNV-1a, finished with `mix`; fixed so filters written by one build still match in the next,
+/// which `std`'s `DefaultHasher` does not promise.
+fn hash(key: &[u8]) -> u64 {
+	let fnv = key.iter().fold(0xcbf2_9ce4_8422_2325u64, |h, &b| (h ^ b as u64).wrapping_mul(0x0100_0000_01b3));
+	mix(fnv)
+}
+
+/// The splitmix64 finalizer, spreading every input bit over the whole word.
+fn mix(mut x: u64) -> u64 {
+	x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+	x ^ (x >> 31)
+}
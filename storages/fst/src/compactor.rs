@@ -3,91 +3,250 @@ use memmap2::Mmap;
 use std::{
 	fs::File,
 	path::Path,
-	sync::{mpsc, Arc, RwLock},
+	sync::{mpsc, Arc, Condvar, Mutex, RwLock, RwLockWriteGuard},
 	thread,
-	time::Instant,
+	time::{Duration, Instant},
 };
 
-use crate::segment::{merge_segments, Column};
+use crate::segment::{merge_segments_parallel, segment_bytes, Column};
 use crate::store::StoreResult;
 
-const MERGE_THRESHOLD: usize = 4;
+/// Cumulative merge statistics for a single column.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+	pub merges: u64,
+	pub rows_merged: u64,
+	pub merge_time: Duration,
+	pub bytes_reclaimed: u64,
+	/// Most segments the column held when the compactor took up one of its flushes.
+	pub max_segments: u64,
+	/// Times the column grew past `StoreOptions::segment_backlog_warning` segments: flushes
+	/// outran the merges, and reads probe ever more segments until they catch up.
+	pub backlog_warnings: u64,
+}
+
+impl CompactionStats {
+	pub(crate) fn record(&mut self, rows: u64, elapsed: Duration, reclaimed: u64) {
+		self.merges += 1;
+		self.rows_merged += rows;
+		self.merge_time += elapsed;
+		self.bytes_reclaimed += reclaimed;
+	}
+
+	/// Sums per-column stats into a single total; `max_segments` is the largest column's.
+	pub fn total(stats: &[CompactionStats]) -> CompactionStats {
+		stats.iter().fold(CompactionStats::default(), |acc, s| CompactionStats {
+			merges: acc.merges + s.merges,
+			rows_merged: acc.rows_merged + s.rows_merged,
+			merge_time: acc.merge_time + s.merge_time,
+			bytes_reclaimed: acc.bytes_reclaimed + s.bytes_reclaimed,
+			max_segments: acc.max_segments.max(s.max_segments),
+			backlog_warnings: acc.backlog_warnings + s.backlog_warnings,
+		})
+	}
+}
 
+/// Per-column compaction stats shared between the store and the compactor worker.
+pub type SharedCompactionStats = Arc<Mutex<Vec<CompactionStats>>>;
+
+/// Background merges on a pool of worker threads taking column requests off one channel. A
+/// column merges on one worker at a time, as a request for a column already merging finds
+/// nothing to snapshot; different columns merge side by side.
 pub struct Compactor {
-	handle: Option<thread::JoinHandle<()>>,
+	handles: Vec<thread::JoinHandle<()>>,
 	sender: Option<mpsc::Sender<usize>>,
+	stats: SharedCompactionStats,
+	state: Arc<WorkerState>,
 }
 
 impl Compactor {
-	pub fn new(columns: Vec<Arc<RwLock<Column>>>) -> Self {
+	/// At least one worker.
+	pub fn new(columns: Vec<Arc<RwLock<Column>>>, workers: usize) -> Self {
 		let (tx, rx) = mpsc::channel::<usize>();
-		let handle = thread::spawn(move || {
-			while let Ok(idx) = rx.recv() {
-				if let Some(col) = columns.get(idx) {
-					let snapshot = {
-						let mut guard = col.write().unwrap();
-						match guard.snapshot_for_merge(MERGE_THRESHOLD) {
-							Ok(Some(s)) => s,
-							Ok(None) => continue,
-							Err(e) => {
-								eprintln!("compaction col {} snapshot error: {}", idx, e);
-								continue
-							},
+		let rx = Arc::new(Mutex::new(rx));
+		let stats: SharedCompactionStats = Arc::new(Mutex::new(vec![CompactionStats::default(); columns.len()]));
+		let state = Arc::new(WorkerState {
+			stats: stats.clone(),
+			behind: Mutex::new(vec![false; columns.len()]),
+			requeued: Mutex::new(vec![false; columns.len()]),
+			pending: Mutex::new(0),
+			idle: Condvar::new(),
+			merges_ended: Mutex::new(0),
+			merge_ended: Condvar::new(),
+		});
+		let handles = (0..workers.max(1))
+			.map(|_| {
+				let (rx, columns, state) = (rx.clone(), columns.clone(), state.clone());
+				thread::spawn(move || {
+					loop {
+						let next = rx.lock().unwrap().recv();
+						let Ok(idx) = next else { break };
+						if let Some(col) = columns.get(idx) {
+							while compact(idx, col, &state) && std::mem::take(&mut state.requeued.lock().unwrap()[idx])
+							{}
 						}
-					};
-
-					let (merge_id, dir, col_id, metas) = snapshot;
-					let before_rows: u64 = metas.iter().map(|m| read_rows(&m.fst_path)).sum();
-					let start = Instant::now();
-					match merge_segments(&dir, col_id, merge_id, metas.clone()) {
-						Ok((merged, metas_back)) => {
-							let dur = start.elapsed();
-							let after_rows = merged.map.len() as u64;
-							let ops = if dur.as_secs_f64() > 0.0 { before_rows as f64 / dur.as_secs_f64() } else { 0.0 };
-							if let Ok(mut guard) = col.write() {
-								guard.finish_merge(merged, &metas_back);
-							}
-							println!(
-								"compaction col {}: segs {}->{} rows {}->{} in {:.2?} (~{:.1} rows/s)",
-								idx,
-								metas_back.len(),
-								1,
-								before_rows,
-								after_rows,
-								dur,
-								ops
-							);
-						},
-						Err(e) => {
-							eprintln!("compaction col {} merge error: {}", idx, e);
-							if let Ok(mut guard) = col.write() {
-								guard.merging = false;
-							}
-						},
+						state.done();
 					}
-				}
-			}
-		});
-		Self { sender: Some(tx), handle: Some(handle) }
+				})
+			})
+			.collect();
+		Self { sender: Some(tx), handles, stats, state }
+	}
+
+	pub fn stats(&self) -> SharedCompactionStats {
+		self.stats.clone()
 	}
 
 	pub fn request(&self, col_idx: usize) -> StoreResult<()> {
 		if let Some(sender) = &self.sender {
-			let _ = sender.send(col_idx);
+			*self.state.pending.lock().unwrap() += 1;
+			if sender.send(col_idx).is_err() {
+				self.state.done();
+			}
 		}
 		Ok(())
 	}
+
+	/// Blocks until every request so far is taken up and the merges it led to are installed,
+	/// including those a request for an already merging column handed to its worker.
+	pub fn drain(&self) {
+		let mut pending = self.state.pending.lock().unwrap();
+		while *pending > 0 {
+			pending = self.state.idle.wait(pending).unwrap();
+		}
+	}
+
+	/// Write access to `col` once no background merge is in flight on it, waiting for the
+	/// worker merging it to install its output or give up.
+	pub fn wait_for_merge<'c>(&self, col: &'c RwLock<Column>) -> RwLockWriteGuard<'c, Column> {
+		loop {
+			// Held across the check so a merge ending in between cannot signal before the wait.
+			let ended = self.state.merges_ended.lock().unwrap();
+			let guard = col.write().unwrap();
+			if !guard.merging {
+				return guard
+			}
+			drop(guard);
+			drop(self.state.merge_ended.wait(ended).unwrap());
+		}
+	}
 }
 
 impl Drop for Compactor {
 	fn drop(&mut self) {
 		self.sender.take();
-		if let Some(h) = self.handle.take() {
+		for h in self.handles.drain(..) {
 			let _ = h.join();
 		}
 	}
 }
 
+/// What the compactor workers share besides the columns.
+struct WorkerState {
+	stats: SharedCompactionStats,
+	/// Whether each column is past its backlog warning, so a backlog warns once, not per flush.
+	behind: Mutex<Vec<bool>>,
+	/// Columns requested while another worker merged them, taken up again by that worker once
+	/// its merge is installed, as a single worker would have after its queued requests.
+	requeued: Mutex<Vec<bool>>,
+	/// Requests not yet taken up to the end, with `idle` signalled whenever it drops to zero.
+	pending: Mutex<usize>,
+	idle: Condvar,
+	/// Merges that ended, installed or not, with `merge_ended` signalled after each, once the
+	/// column's lock is released.
+	merges_ended: Mutex<u64>,
+	merge_ended: Condvar,
+}
+
+impl WorkerState {
+	fn done(&self) {
+		let mut pending = self.pending.lock().unwrap();
+		*pending -= 1;
+		if *pending == 0 {
+			self.idle.notify_all();
+		}
+	}
+
+	fn end_merge(&self) {
+		*self.merges_ended.lock().unwrap() += 1;
+		self.merge_ended.notify_all();
+	}
+}
+
+/// Merges column `idx` if it has segments to merge, recording the merge and the column's
+/// segment backlog in the `state` stats; whether a merge was installed.
+fn compact(idx: usize, col: &RwLock<Column>, state: &WorkerState) -> bool {
+	let (snapshot, write, threads) = {
+		let mut guard = col.write().unwrap();
+		// Only merges shrink a column, so while one runs the flushes pile up and the
+		// sample taken once it is done is the peak.
+		let (segments, limit) = (guard.segments.len(), guard.backlog_warning);
+		let mut behind = state.behind.lock().unwrap();
+		if let Some(s) = state.stats.lock().unwrap().get_mut(idx) {
+			s.max_segments = s.max_segments.max(segments as u64);
+			if segments > limit && !behind[idx] {
+				s.backlog_warnings += 1;
+				eprintln!("compaction col {idx} fell behind: {segments} segments, warning at {limit}");
+			}
+		}
+		behind[idx] = segments > limit;
+		if guard.merging {
+			state.requeued.lock().unwrap()[idx] = true;
+			return false
+		}
+		match guard.snapshot_for_merge() {
+			Ok(Some(s)) => (s, guard.write_options(), guard.merge_threads),
+			Ok(None) => return false,
+			Err(e) => {
+				eprintln!("compaction col {} snapshot error: {}", idx, e);
+				return false
+			},
+		}
+	};
+
+	let (merge_id, dir, col_id, metas) = snapshot;
+	let before_rows: u64 = metas.iter().map(|m| read_rows(&m.fst_path)).sum();
+	let before_bytes: u64 = metas.iter().map(|m| segment_bytes(&m.fst_path, &m.values_path)).sum();
+	let start = Instant::now();
+	match merge_segments_parallel(&dir, col_id, merge_id, metas.clone(), write, threads, &mut |_| {}) {
+		Ok((merged, metas_back)) => {
+			let dur = start.elapsed();
+			let after_rows = merged.map.len() as u64;
+			let ops = if dur.as_secs_f64() > 0.0 { before_rows as f64 / dur.as_secs_f64() } else { 0.0 };
+			let reclaimed = before_bytes.saturating_sub(segment_bytes(&merged.fst_path, &merged.values_path));
+			if let Some(s) = state.stats.lock().unwrap().get_mut(idx) {
+				s.record(before_rows, dur, reclaimed);
+			}
+			let installed = match col.write() {
+				Ok(mut guard) => guard.finish_merge(merged, &metas_back).map_err(|e| {
+					eprintln!("compaction col {} manifest error: {}", idx, e);
+				}),
+				Err(_) => Err(()),
+			};
+			state.end_merge();
+			println!(
+				"compaction col {}: segs {}->{} rows {}->{} in {:.2?} (~{:.1} rows/s)",
+				idx,
+				metas_back.len(),
+				1,
+				before_rows,
+				after_rows,
+				dur,
+				ops
+			);
+			installed.is_ok()
+		},
+		Err(e) => {
+			eprintln!("compaction col {} merge error: {}", idx, e);
+			if let Ok(mut guard) = col.write() {
+				guard.merging = false;
+			}
+			state.end_merge();
+			false
+		},
+	}
+}
+
 fn read_rows(path: &Path) -> u64 {
 	match File::open(path)
 		.ok()
@@ -98,3 +257,61 @@ fn read_rows(path: &Path) -> u64 {
 		None => 0,
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::store::StoreOptions;
+	use tempfile::tempdir;
+
+	#[test]
+	fn workers_merge_other_columns_while_one_is_busy() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(2).with_merge_threshold(2);
+		let columns: Vec<Arc<RwLock<Column>>> = (0..2u8)
+			.map(|id| {
+				let mut col = Column::open(dir.path(), id, &options).unwrap();
+				for i in 0..2u8 {
+					col.insert(vec![b'a', i], vec![i]).unwrap();
+					col.insert(vec![b'b', i], vec![i]).unwrap();
+					col.flush().unwrap();
+				}
+				assert_eq!(col.segments.len(), 2);
+				Arc::new(RwLock::new(col))
+			})
+			.collect();
+		let compactor = Compactor::new(columns.clone(), 2);
+		let stats = compactor.stats();
+		// The worker taking column 0 waits on this lock; a single worker would never get to column 1.
+		let busy = columns[0].write().unwrap();
+		compactor.request(0).unwrap();
+		compactor.request(1).unwrap();
+		let deadline = Instant::now() + Duration::from_secs(10);
+		while columns[1].read().unwrap().segments.len() > 1 {
+			assert!(Instant::now() < deadline, "column 1 did not merge while column 0 was busy");
+			thread::sleep(Duration::from_millis(5));
+		}
+		drop(busy);
+		drop(compactor);
+		assert_eq!(columns[0].read().unwrap().segments.len(), 1);
+		assert_eq!(CompactionStats::total(&stats.lock().unwrap()).merges, 2);
+	}
+
+	#[test]
+	fn wait_for_merge_returns_once_the_merge_ends() {
+		let dir = tempdir().unwrap();
+		let col = Arc::new(RwLock::new(Column::open(dir.path(), 0, &StoreOptions::new(2)).unwrap()));
+		col.write().unwrap().merging = true;
+		let compactor = Compactor::new(vec![col.clone()], 1);
+		let merger = {
+			let (col, state) = (col.clone(), compactor.state.clone());
+			thread::spawn(move || {
+				thread::sleep(Duration::from_millis(50));
+				col.write().unwrap().merging = false;
+				state.end_merge();
+			})
+		};
+		assert!(!compactor.wait_for_merge(&col).merging);
+		merger.join().unwrap();
+	}
+}
@@ -1,3 +1,6 @@
 pub mod compactor;
+pub mod memtable;
 pub mod segment;
 pub mod store;
+mod bloom;
+mod wal;
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+/// In-memory buffer implementation used by each column before it is flushed to a segment.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemtableKind {
+	/// Ordered map; every insert pays for rebalancing but any key order stays cheap to read.
+	#[default]
+	BTree,
+	/// Append-only vector. In-order inserts are a push and reads a binary search; the first
+	/// out-of-order key switches reads to a linear scan until the next flush sorts the buffer.
+	SortedVec,
+}
+
+pub(crate) enum Memtable {
+	BTree(BTreeMap<Vec<u8>, Vec<u8>>),
+	/// `sorted` holds while keys arrived strictly increasing, i.e. entries are sorted and unique.
+	SortedVec { entries: Vec<(Vec<u8>, Vec<u8>)>, sorted: bool },
+}
+
+impl Memtable {
+	pub(crate) fn new(kind: MemtableKind) -> Self {
+		match kind {
+			MemtableKind::BTree => Memtable::BTree(BTreeMap::new()),
+			MemtableKind::SortedVec => Memtable::SortedVec { entries: Vec::new(), sorted: true },
+		}
+	}
+
+	pub(crate) fn kind(&self) -> MemtableKind {
+		match self {
+			Memtable::BTree(_) => MemtableKind::BTree,
+			Memtable::SortedVec { .. } => MemtableKind::SortedVec,
+		}
+	}
+
+	/// Number of buffered writes; for an unsorted `SortedVec` this counts overwritten keys too.
+	pub(crate) fn len(&self) -> usize {
+		match self {
+			Memtable::BTree(map) => map.len(),
+			Memtable::SortedVec { entries, .. } => entries.len(),
+		}
+	}
+
+	pub(crate) fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	pub(crate) fn clear(&mut self) {
+		match self {
+			Memtable::BTree(map) => map.clear(),
+			Memtable::SortedVec { entries, sorted } => {
+				entries.clear();
+				*sorted = true;
+			},
+		}
+	}
+
+	pub(crate) fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+		match self {
+			Memtable::BTree(map) => {
+				map.insert(key, value);
+			},
+			Memtable::SortedVec { entries, sorted } => {
+				if *sorted && let Some(last) = entries.last_mut() {
+					if key == last.0 {
+						last.1 = value;
+						return
+					}
+					*sorted = key > last.0;
+				}
+				entries.push((key, value));
+			},
+		}
+	}
+
+	pub(crate) fn get(&self, key: &[u8]) -> Option<&Vec<u8>> {
+		match self {
+			Memtable::BTree(map) => map.get(key),
+			Memtable::SortedVec { entries, sorted: true } => entries
+				.binary_search_by(|(k, _)| k.as_slice().cmp(key))
+				.ok()
+				.map(|idx| &entries[idx].1),
+			Memtable::SortedVec { entries, sorted: false } => {
+				entries.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v)
+			},
+		}
+	}
+
+	/// Buffered keys starting with `prefix`, in key order and without duplicates.
+	pub(crate) fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<&Vec<u8>> {
+		match self {
+			Memtable::BTree(map) => {
+				map.range(prefix.to_vec()..).map(|(k, _)| k).take_while(|k| k.starts_with(prefix)).collect()
+			},
+			Memtable::SortedVec { entries, sorted: true } => {
+				let start = entries.partition_point(|(k, _)| k.as_slice() < prefix);
+				entries[start..].iter().map(|(k, _)| k).take_while(|k| k.starts_with(prefix)).collect()
+			},
+			Memtable::SortedVec { entries, sorted: false } => {
+				let mut keys: Vec<&Vec<u8>> = entries.iter().map(|(k, _)| k).filter(|k| k.starts_with(prefix)).collect();
+				keys.sort();
+				keys.dedup();
+				keys
+			},
+		}
+	}
+
+	pub(crate) fn first_key(&self) -> Option<&Vec<u8>> {
+		match self {
+			Memtable::BTree(map) => map.keys().next(),
+			Memtable::SortedVec { entries, sorted: true } => entries.first().map(|(k, _)| k),
+			Memtable::SortedVec { entries, sorted: false } => entries.iter().map(|(k, _)| k).min(),
+		}
+	}
+
+	pub(crate) fn last_key(&self) -> Option<&Vec<u8>> {
+		match self {
+			Memtable::BTree(map) => map.keys().next_back(),
+			Memtable::SortedVec { entries, sorted: true } => entries.last().map(|(k, _)| k),
+			Memtable::SortedVec { entries, sorted: false } => entries.iter().map(|(k, _)| k).max(),
+		}
+	}
+
+	/// Sorts and dedupes an unsorted `SortedVec`, keeping the last write of each key.
+	pub(crate) fn normalize(&mut self) {
+		if let Memtable::SortedVec { entries, sorted } = self
+			&& !*sorted
+		{
+			// Stable sort keeps writes of one key in arrival order; reversing lets `dedup_by`
+			// keep the newest.
+			entries.sort_by(|a, b| a.0.cmp(&b.0));
+			entries.reverse();
+			entries.dedup_by(|a, b| a.0 == b.0);
+			entries.reverse();
+			*sorted = true;
+		}
+	}
+
+	/// Entries in key order; call `normalize` first so a `SortedVec` is sorted and unique.
+	pub(crate) fn iter(&self) -> Box<dyn Iterator<Item = (&Vec<u8>, &Vec<u8>)> + '_> {
+		match self {
+			Memtable::BTree(map) => Box::new(map.iter()),
+			Memtable::SortedVec { entries, sorted } => {
+				debug_assert!(*sorted, "iterating an unnormalized memtable");
+				Box::new(entries.iter().map(|(k, v)| (k, v)))
+			},
+		}
+	}
+}
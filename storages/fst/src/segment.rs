@@ -1,20 +1,183 @@
+use core::store_interface::FlushStats;
 use fst::{map::OpBuilder, IntoStreamer, Map, MapBuilder, Streamer};
 use memmap2::Mmap;
 use std::{
-	collections::{BTreeMap, HashSet},
+	collections::HashSet,
 	fs::{self, File},
-	io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+	io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
 	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	thread,
 };
 
-use crate::store::{StoreError, StoreResult};
+use crate::{
+	bloom::{bloom_path, Bloom},
+	memtable::Memtable,
+	store::{RecoveryMode, BACKLOG_WARNING_FACTOR, StoreError, StoreOptions, StoreResult},
+};
 
 pub struct Segment {
 	pub(crate) id: u64,
 	pub(crate) map: Map<Mmap>,
+	pub(crate) fst_path: PathBuf,
 	pub(crate) values_path: PathBuf,
+	pub(crate) prefix: LengthPrefix,
+	/// The `.val` file mapped for reads, when it is within `StoreOptions::mmap_values_max_bytes`.
+	pub(crate) values: Option<Mmap>,
+	/// Filter over the FST's keys, when the segment was written with `StoreOptions::bloom_bits_per_key`.
+	pub(crate) bloom: Option<Bloom>,
+	/// `StoreOptions::fst_key_prefix` the segment was written with: its FST maps the first this
+	/// many bytes of a key to the first record of the keys sharing them, and the records in the
+	/// `.val` file carry their full keys.
+	pub(crate) key_prefix: Option<u8>,
+}
+
+/// Read amplification of a column: its `get`s, and the segments they probed until one held the
+/// key or none was left. Lookups answered by a memtable probe none.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColumnStats {
+	pub gets: u64,
+	pub segments_probed: u64,
+}
+
+impl ColumnStats {
+	/// Segments probed per `get`, 0 before the first one. Well above 1 means too many segments
+	/// pile up between merges; a lower `merge_threshold` keeps them fewer.
+	pub fn read_amplification(&self) -> f64 {
+		if self.gets == 0 { 0.0 } else { self.segments_probed as f64 / self.gets as f64 }
+	}
+
+	/// Sums per-column stats into a single total.
+	pub fn total(stats: &[ColumnStats]) -> ColumnStats {
+		stats.iter().fold(ColumnStats::default(), |acc, s| ColumnStats {
+			gets: acc.gets + s.gets,
+			segments_probed: acc.segments_probed + s.segments_probed,
+		})
+	}
+}
+
+/// The counters behind `ColumnStats`, shared so they outlive the store; they only move with
+/// `StoreOptions::count_reads` set.
+#[derive(Debug, Default)]
+pub struct ReadCounters {
+	gets: AtomicU64,
+	segments_probed: AtomicU64,
+}
+
+impl ReadCounters {
+	pub fn stats(&self) -> ColumnStats {
+		ColumnStats {
+			gets: self.gets.load(Ordering::Relaxed),
+			segments_probed: self.segments_probed.load(Ordering::Relaxed),
+		}
+	}
+
+	pub fn reset(&self) {
+		self.gets.store(0, Ordering::Relaxed);
+		self.segments_probed.store(0, Ordering::Relaxed);
+	}
+}
+
+/// Longest key a column accepts; FST keys have no hard limit, but huge keys bloat every node on
+/// their path and point at a caller bug rather than a real index key.
+pub const MAX_KEY_BYTES: usize = u16::MAX as usize;
+
+/// Rows `merge_segments` writes between two progress callbacks.
+pub(crate) const MERGE_PROGRESS_ROWS: u64 = 65_536;
+
+/// Every `.val` file starts with one byte holding its `LengthPrefix` width, so segments written
+/// with different widths can be read and merged side by side.
+pub(crate) const VALUES_HEADER_BYTES: u64 = 1;
+
+/// Set in the header byte of a `.val` file whose records carry their keys, for a segment whose FST
+/// indexes key prefixes; a second header byte holds the prefix length.
+const KEYED_VALUES: u8 = 0x80;
+
+/// Header of a `.val` file whose records carry their keys.
+const KEYED_HEADER_BYTES: u64 = VALUES_HEADER_BYTES + 1;
+
+/// Rows from which `StoreOptions::pipelined_flush` writes the values file on a second thread;
+/// below it the thread costs more than the overlap saves.
+const PIPELINE_MIN_ROWS: usize = 4096;
+
+/// Buffer of a `ValueWriter`. Values are mostly small, and with the default 8 KiB writing them
+/// took twice as long, in write calls.
+const VALUE_WRITE_BUFFER: usize = 1 << 20;
+
+/// Inserts between two re-tunings of an adaptive column's flush threshold.
+pub(crate) const ADAPT_SAMPLE_ROWS: u64 = 4096;
+
+/// Furthest an adaptive column moves its flush threshold from the configured `segment_size`,
+/// as a factor either way, so a burst of odd rows cannot drive it to 1 or to the whole budget.
+pub const MAX_SEGMENT_SIZE_ADJUST: usize = 16;
+
+/// Width of the length prefix in front of every value in a `.val` file. Narrow prefixes save
+/// bytes per row for columns of small values but cap the value length.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LengthPrefix {
+	U8,
+	U16,
+	#[default]
+	U32,
+}
+
+impl LengthPrefix {
+	pub fn bytes(self) -> usize {
+		match self {
+			LengthPrefix::U8 => 1,
+			LengthPrefix::U16 => 2,
+			LengthPrefix::U32 => 4,
+		}
+	}
+
+	pub fn max_len(self) -> usize {
+		match self {
+			LengthPrefix::U8 => u8::MAX as usize,
+			LengthPrefix::U16 => u16::MAX as usize,
+			LengthPrefix::U32 => u32::MAX as usize,
+		}
+	}
+
+	fn from_header(byte: u8) -> StoreResult<Self> {
+		match byte {
+			1 => Ok(LengthPrefix::U8),
+			2 => Ok(LengthPrefix::U16),
+			4 => Ok(LengthPrefix::U32),
+			other => Err(StoreError::CorruptSegment(format!("unknown value length prefix width {other}"))),
+		}
+	}
+}
+
+/// How new segment files are written, and whether their values are read back through a mmap.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct WriteOptions {
+	pub(crate) sync: bool,
+	pub(crate) prefix: LengthPrefix,
+	pub(crate) mmap_values_max_bytes: u64,
+	pub(crate) bloom_bits_per_key: Option<f64>,
+	/// `StoreOptions::pipelined_flush`.
+	pub(crate) pipelined: bool,
+	/// `StoreOptions::fst_key_prefix`.
+	pub(crate) key_prefix: Option<u8>,
+	/// `StoreOptions::segments_per_dir`.
+	pub(crate) segments_per_dir: Option<u64>,
+}
+
+/// A full memtable handed to a background thread that builds it into a segment.
+pub(crate) struct PendingFlush {
+	memtable: Arc<Memtable>,
+	handle: thread::JoinHandle<StoreResult<Segment>>,
 }
 
+/// Merge work captured under the column lock: new segment id, directory, column id and inputs.
+pub(crate) type MergeSnapshot = (u64, PathBuf, u8, Vec<SegmentMeta>);
+
+/// Whether an entry is expired, given its stored value.
+type ExpiryCheck = dyn Fn(&[u8]) -> bool;
+
 #[derive(Clone)]
 pub(crate) struct SegmentMeta {
 	pub(crate) id: u64,
@@ -25,364 +188,2117 @@ pub(crate) struct SegmentMeta {
 pub struct Column {
 	pub(crate) id: u8,
 	pub(crate) dir: PathBuf,
-	pub(crate) memtable: BTreeMap<Vec<u8>, Vec<u8>>,
+	pub(crate) memtable: Memtable,
 	pub(crate) segments: Vec<Segment>,
 	pub(crate) next_segment_id: u64,
 	pub(crate) segment_size: usize,
+	pub(crate) sync_on_flush: bool,
+	pub(crate) value_prefix: LengthPrefix,
+	pub(crate) merge_threads: usize,
+	pub(crate) merge_threshold: usize,
+	pub(crate) merge_size_ratio: Option<f64>,
 	pub(crate) merging: bool,
+	pub(crate) background_flush: bool,
+	pub(crate) flushing: Option<PendingFlush>,
+	pub(crate) mmap_values_max_bytes: u64,
+	pub(crate) count_reads: bool,
+	pub(crate) read_counters: Arc<ReadCounters>,
+	pub(crate) bloom_bits_per_key: Option<f64>,
+	pub(crate) pipelined_flush: bool,
+	pub(crate) fst_key_prefix: Option<u8>,
+	pub(crate) segments_per_dir: Option<u64>,
+	/// `StoreOptions::segment_backlog_warning`, resolved against `merge_threshold`.
+	pub(crate) backlog_warning: usize,
+	/// `StoreOptions::memtable_budget_bytes`; with it, `segment_size` follows the measured rows.
+	pub(crate) memtable_budget: Option<usize>,
+	pub(crate) configured_segment_size: usize,
+	pub(crate) inserted_rows: u64,
+	pub(crate) inserted_bytes: u64,
 }
 
 impl Column {
-	pub(crate) fn open(dir: &Path, id: u8, segment_size: usize) -> StoreResult<Self> {
-		let mut segments = load_segments(dir, id)?;
+	pub(crate) fn open(dir: &Path, id: u8, options: &StoreOptions) -> StoreResult<Self> {
+		let mut segments = load_segments(dir, id, options.recovery_mode, options.mmap_values_max_bytes)?;
 		segments.sort_by_key(|s| s.id);
-		let next_segment_id = segments.last().map(|s| s.id + 1).unwrap_or(0);
+		// Orphaned files (e.g. an interrupted flush or merge) still reserve their id so a new
+		// segment never lands on a name that already exists on disk.
+		let next_segment_id = max_segment_id_on_disk(dir, id)?.map(|max| max + 1).unwrap_or(0);
 		Ok(Self {
 			id,
 			dir: dir.to_path_buf(),
-			memtable: BTreeMap::new(),
+			memtable: Memtable::new(options.memtable),
 			segments,
 			next_segment_id,
-			segment_size,
+			segment_size: options.segment_size,
+			sync_on_flush: options.sync_on_flush,
+			value_prefix: options.value_prefix,
+			merge_threads: options.merge_threads,
+			merge_threshold: options.merge_threshold,
+			merge_size_ratio: options.merge_size_ratio,
 			merging: false,
+			background_flush: options.background_flush,
+			flushing: None,
+			mmap_values_max_bytes: options.mmap_values_max_bytes,
+			count_reads: options.count_reads,
+			read_counters: Arc::default(),
+			bloom_bits_per_key: options.bloom_bits_per_key,
+			pipelined_flush: options.pipelined_flush,
+			fst_key_prefix: options.fst_key_prefix,
+			segments_per_dir: options.segments_per_dir,
+			backlog_warning: options
+				.segment_backlog_warning
+				.unwrap_or(options.merge_threshold.saturating_mul(BACKLOG_WARNING_FACTOR)),
+			memtable_budget: options.memtable_budget_bytes,
+			configured_segment_size: options.segment_size,
+			inserted_rows: 0,
+			inserted_bytes: 0,
 		})
 	}
 
+	/// Rejects bad keys and values up front, so a flush never fails half way through a segment
+	/// and a commit fails before it is logged rather than when its writes are applied.
+	pub(crate) fn check_entry(&self, key: &[u8], value: &[u8]) -> StoreResult<()> {
+		if key.is_empty() {
+			return Err(StoreError::InvalidInput(format!("col{}: empty key", self.id)))
+		}
+		if key.len() > MAX_KEY_BYTES {
+			return Err(StoreError::InvalidInput(format!(
+				"col{}: key of {} bytes exceeds {MAX_KEY_BYTES}",
+				self.id,
+				key.len()
+			)))
+		}
+		if value.len() > self.value_prefix.max_len() {
+			return Err(StoreError::InvalidInput(format!(
+				"col{}: value of {} bytes exceeds the {}-byte length prefix",
+				self.id,
+				value.len(),
+				self.value_prefix.bytes()
+			)))
+		}
+		Ok(())
+	}
+
 	pub(crate) fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) -> StoreResult<bool> {
+		self.check_entry(&key, &value)?;
+		// A background flush that completed since the last insert is installed here, so the
+		// returned flag tells the caller to request compaction.
+		let mut flushed = self.finish_flush(false)?.is_some();
+		if self.memtable_budget.is_some() {
+			self.inserted_rows += 1;
+			self.inserted_bytes += (key.len() + value.len()) as u64;
+			if self.inserted_rows.is_multiple_of(ADAPT_SAMPLE_ROWS) {
+				self.retune_segment_size();
+			}
+		}
 		self.memtable.insert(key, value);
 		if self.memtable.len() >= self.segment_size {
-			self.flush()?;
-			return Ok(true)
+			if self.background_flush {
+				flushed |= self.flush_in_background()?;
+			} else {
+				self.flush()?;
+				flushed = true;
+			}
+		}
+		Ok(flushed)
+	}
+
+	/// Moves the flush threshold to the rows of the average size inserted so far that fit the
+	/// memtable budget, within `MAX_SEGMENT_SIZE_ADJUST` of the configured size. Moves of less
+	/// than a quarter are skipped, so a running average that wobbles does not keep changing it.
+	fn retune_segment_size(&mut self) {
+		let Some(budget) = self.memtable_budget else { return };
+		let avg_kv_bytes = (self.inserted_bytes / self.inserted_rows.max(1)).max(1);
+		let fitting = (budget as u64 / avg_kv_bytes).clamp(1, usize::MAX as u64) as usize;
+		let target = fitting.clamp(
+			(self.configured_segment_size / MAX_SEGMENT_SIZE_ADJUST).max(1),
+			self.configured_segment_size.saturating_mul(MAX_SEGMENT_SIZE_ADJUST),
+		);
+		if target.abs_diff(self.segment_size) * 4 > self.segment_size {
+			self.segment_size = target;
 		}
-		Ok(false)
 	}
 
-	pub(crate) fn flush(&mut self) -> StoreResult<()> {
+	/// Waits for an in-flight background flush, then writes the memtable synchronously; the stats
+	/// cover both.
+	pub(crate) fn flush(&mut self) -> StoreResult<FlushStats> {
+		let mut stats = self.finish_flush(true)?.unwrap_or_default();
 		if self.memtable.is_empty() {
-			return Ok(())
+			return Ok(stats)
 		}
-		let seg_id = self.next_segment_id;
-		let (fst_path, values_path) = segment_paths(&self.dir, self.id, seg_id);
-		let fst_file = BufWriter::new(File::create(&fst_path)?);
-		let mut map_builder = MapBuilder::new(fst_file)?;
-		let mut val_writer = BufWriter::new(File::create(&values_path)?);
-		let mut offset: u64 = 0;
-		for (key, value) in self.memtable.iter() {
-			map_builder.insert(key, offset)?;
-			write_value(&mut val_writer, value)?;
-			offset = offset.checked_add(4 + value.len() as u64).ok_or_else(|| {
-				StoreError::InvalidInput("value offsets exceeded u64".into())
-			})?;
-		}
-		map_builder.finish()?;
-		val_writer.flush()?;
-		let file = File::open(&fst_path)?;
-		let mmap = unsafe { Mmap::map(&file)? };
-		let map = Map::new(mmap)?;
-		self.segments.push(Segment { id: seg_id, map, values_path });
-		self.next_segment_id += 1;
+		self.memtable.normalize();
+		let seg_id = take_segment_id(&self.dir, self.id, self.segments_per_dir, &mut self.next_segment_id)?;
+		let segment = write_segment(&self.dir, self.id, seg_id, &self.memtable, self.write_options())?;
+		stats.record(self.memtable.len() as u64, segment_bytes(&segment.fst_path, &segment.values_path));
+		self.segments.push(segment);
 		self.memtable.clear();
-		Ok(())
+		self.write_manifest()?;
+		Ok(stats)
+	}
+
+	/// Swaps in an empty memtable and builds the full one into a segment on its own thread. At most
+	/// one flush is in flight: an earlier one is awaited first, which is what the returned flag
+	/// reports.
+	fn flush_in_background(&mut self) -> StoreResult<bool> {
+		let installed = self.finish_flush(true)?.is_some();
+		let seg_id = take_segment_id(&self.dir, self.id, self.segments_per_dir, &mut self.next_segment_id)?;
+		let kind = self.memtable.kind();
+		let mut memtable = std::mem::replace(&mut self.memtable, Memtable::new(kind));
+		memtable.normalize();
+		let memtable = Arc::new(memtable);
+		let (dir, col_id, write, job) = (self.dir.clone(), self.id, self.write_options(), memtable.clone());
+		let handle = thread::spawn(move || write_segment(&dir, col_id, seg_id, &job, write));
+		self.flushing = Some(PendingFlush { memtable, handle });
+		Ok(installed)
+	}
+
+	/// Installs the segment of the in-flight background flush once it is built, or blocks until it
+	/// is with `wait`; returns what the installed one wrote. A failed flush hands its rows back to
+	/// the memtable, below newer writes of the same keys, so the next flush retries them.
+	fn finish_flush(&mut self, wait: bool) -> StoreResult<Option<FlushStats>> {
+		let Some(PendingFlush { memtable, handle }) = self.flushing.take_if(|p| wait || p.handle.is_finished()) else {
+			return Ok(None)
+		};
+		let panicked = || StoreError::Io(io::Error::other(format!("col{}: flush thread panicked", self.id)));
+		let built = handle.join().unwrap_or_else(|_| Err(panicked()));
+		match built {
+			Ok(segment) => {
+				let mut stats = FlushStats::default();
+				stats.record(memtable.len() as u64, segment_bytes(&segment.fst_path, &segment.values_path));
+				self.install_segment(segment);
+				self.write_manifest()?;
+				Ok(Some(stats))
+			},
+			Err(err) => {
+				for (key, value) in memtable.iter() {
+					if self.memtable.get(key).is_none() {
+						self.memtable.insert(key.clone(), value.clone());
+					}
+				}
+				Err(err)
+			},
+		}
 	}
 
 	pub(crate) fn get(&self, key: &[u8]) -> StoreResult<Option<Vec<u8>>> {
+		let counters = self.count_reads.then_some(&*self.read_counters);
+		if let Some(c) = counters {
+			c.gets.fetch_add(1, Ordering::Relaxed);
+		}
 		if let Some(v) = self.memtable.get(key) {
 			return Ok(Some(v.clone()))
 		}
+		if let Some(v) = self.flushing.as_ref().and_then(|p| p.memtable.get(key)) {
+			return Ok(Some(v.clone()))
+		}
 		for seg in self.segments.iter().rev() {
-			if let Some(offset) = seg.map.get(key) {
+			if seg.bloom.as_ref().is_some_and(|bloom| !bloom.may_contain(seg.fst_key(key))) {
+				continue
+			}
+			if let Some(c) = counters {
+				c.segments_probed.fetch_add(1, Ordering::Relaxed);
+			}
+			if let Some(offset) = seg.find(key)? {
 				return Ok(Some(seg.read_value(offset)?))
 			}
 		}
 		Ok(None)
 	}
 
-	pub(crate) fn keys_with_prefix(&self, prefix: &[u8]) -> StoreResult<Vec<Vec<u8>>> {
+	/// Distinct keys starting with `prefix` in key order, at most `limit` of them: the smallest.
+	/// Each memtable and segment yields its keys in order, so only the first `limit` of each can
+	/// make the cut and its stream stops there.
+	pub(crate) fn keys_with_prefix(&self, prefix: &[u8], limit: Option<usize>) -> StoreResult<Vec<Vec<u8>>> {
+		let limit = limit.unwrap_or(usize::MAX);
 		let mut seen: HashSet<Vec<u8>> = HashSet::new();
 		let mut keys: Vec<Vec<u8>> = Vec::new();
-		let range_end = prefix_upper_bound(prefix);
 
-		if let Some(end) = range_end.as_ref() {
-			for (k, _) in self.memtable.range(prefix.to_vec()..end.clone()) {
-				if k.starts_with(prefix) && seen.insert(k.clone()) {
-					keys.push(k.clone());
-				}
-			}
-		} else {
-			for (k, _) in self.memtable.range(prefix.to_vec()..) {
-				if !k.starts_with(prefix) {
-					break;
-				}
-				if seen.insert(k.clone()) {
-					keys.push(k.clone());
-				}
+		let flushing = self.flushing.as_ref().map(|p| p.memtable.keys_with_prefix(prefix)).unwrap_or_default();
+		let buffered = self.memtable.keys_with_prefix(prefix).into_iter().take(limit);
+		for k in buffered.chain(flushing.into_iter().take(limit)) {
+			if seen.insert(k.clone()) {
+				keys.push(k.clone());
 			}
 		}
 
 		for seg in self.segments.iter().rev() {
-			let mut builder = seg.map.range().ge(prefix);
-			if let Some(end) = range_end.as_deref() {
-				builder = builder.lt(end);
-			}
-			let mut stream = builder.into_stream();
-			while let Some((key, _)) = stream.next() {
-				if range_end.is_none() && !key.starts_with(prefix) {
-					break;
-				}
-				if seen.insert(key.to_vec()) {
-					keys.push(key.to_vec());
+			let mut entries = seg.entries(prefix)?;
+			let mut taken = 0;
+			while taken < limit && let Some((key, _)) = entries.next()? {
+				taken += 1;
+				if seen.insert(key.clone()) {
+					keys.push(key);
 				}
 			}
 		}
 
 		keys.sort();
+		keys.truncate(limit);
 		Ok(keys)
 	}
 
-	pub(crate) fn multi_way_merge(&mut self) -> StoreResult<()> {
+	/// The smallest key across the memtables and every segment's first key.
+	pub(crate) fn first_key(&self) -> StoreResult<Option<Vec<u8>>> {
+		let flushing = self.flushing.as_ref().and_then(|p| p.memtable.first_key().cloned());
+		let mut segments = Vec::with_capacity(self.segments.len());
+		for seg in &self.segments {
+			segments.extend(seg.entries(&[])?.next()?.map(|(key, _)| key));
+		}
+		Ok(self.memtable.first_key().cloned().into_iter().chain(flushing).chain(segments).min())
+	}
+
+	/// The largest key across the memtables and every segment's last key.
+	pub(crate) fn last_key(&self) -> StoreResult<Option<Vec<u8>>> {
+		let flushing = self.flushing.as_ref().and_then(|p| p.memtable.last_key().cloned());
+		let mut segments = Vec::with_capacity(self.segments.len());
+		for seg in &self.segments {
+			segments.extend(seg.last_key()?);
+		}
+		Ok(self.memtable.last_key().cloned().into_iter().chain(flushing).chain(segments).max())
+	}
+
+	/// Rows of the memtables and segments, a key once per one of them holding it. A segment
+	/// indexed by `StoreOptions::fst_key_prefix` counts its distinct prefixes instead.
+	pub(crate) fn approx_len(&self) -> u64 {
+		let flushing = self.flushing.as_ref().map_or(0, |p| p.memtable.len());
+		let segments: usize = self.segments.iter().map(|seg| seg.map.len()).sum();
+		(self.memtable.len() + flushing + segments) as u64
+	}
+
+	/// Drops the memtable and every segment file. Ids restart unless a background merge is in
+	/// flight: its output must keep a unique name until `finish_merge` discards it.
+	pub(crate) fn clear(&mut self) -> StoreResult<()> {
+		// Its segment is about to be deleted anyway, so a failed flush is not worth reporting.
+		let _ = self.finish_flush(true);
+		self.memtable.clear();
+		let dropped = std::mem::take(&mut self.segments);
+		self.write_manifest()?;
+		for seg in dropped {
+			fs::remove_file(&seg.fst_path)?;
+			fs::remove_file(&seg.values_path)?;
+			let _ = fs::remove_file(bloom_path(&seg.fst_path));
+		}
+		if !self.merging {
+			self.next_segment_id = max_segment_id_on_disk(&self.dir, self.id)?.map(|max| max + 1).unwrap_or(0);
+		}
+		Ok(())
+	}
+
+	/// Merges all segments into one, returning `(rows_merged, bytes_reclaimed)` if a merge happened.
+	pub(crate) fn multi_way_merge(&mut self, progress: &mut dyn FnMut(u64)) -> StoreResult<Option<(u64, u64)>> {
 		self.flush()?;
 		if self.segments.len() <= 1 {
-			return Ok(())
+			return Ok(None)
 		}
-		let merge_id = self.next_segment_id;
-		self.next_segment_id += 1;
+		let merge_id = take_segment_id(&self.dir, self.id, self.segments_per_dir, &mut self.next_segment_id)?;
 		let snapshot = std::mem::take(&mut self.segments);
-		let metas: Vec<SegmentMeta> = snapshot
-			.iter()
-			.map(|s| {
-				let (fst_path, values_path) = segment_paths(&self.dir, self.id, s.id);
-				SegmentMeta { id: s.id, fst_path, values_path }
-			})
-			.collect();
-		let (merged, old_meta) = merge_segments(&self.dir, self.id, merge_id, metas)?;
+		let metas: Vec<SegmentMeta> = snapshot.iter().map(Segment::meta).collect();
+		let before_rows: u64 = snapshot.iter().map(|s| s.map.len() as u64).sum();
+		let before_bytes: u64 = metas.iter().map(|m| segment_bytes(&m.fst_path, &m.values_path)).sum();
+		let (merged, old_meta) = merge_segments_parallel(
+			&self.dir,
+			self.id,
+			merge_id,
+			metas,
+			self.write_options(),
+			self.merge_threads,
+			progress,
+		)?;
+		let reclaimed = before_bytes.saturating_sub(segment_bytes(&merged.fst_path, &merged.values_path));
 		self.segments.push(merged);
+		self.write_manifest()?;
 		for m in old_meta {
-			let _ = fs::remove_file(m.fst_path);
-			let _ = fs::remove_file(m.values_path);
+			remove_segment_files(&m.fst_path, &m.values_path);
 		}
-		Ok(())
+		Ok(Some((before_rows, reclaimed)))
+	}
+
+	/// Rewrites every segment without the entries a newer segment shadows, returning the bytes
+	/// reclaimed. Rewritten segments take fresh ids; their keys no longer occur in any newer
+	/// segment, so moving them ahead keeps lookups unchanged. Skipped while a merge is in flight.
+	pub(crate) fn gc(&mut self) -> StoreResult<u64> {
+		self.rewrite_segments(None).map(|(reclaimed, _)| reclaimed)
+	}
+
+	/// `gc` after a flush that also drops the entries whose value `expired` holds for, returning
+	/// the bytes reclaimed and the expired keys dropped. The shadowed versions of such a key go
+	/// with it, so no older value resurfaces.
+	pub(crate) fn purge(&mut self, expired: &ExpiryCheck) -> StoreResult<(u64, u64)> {
+		self.flush()?;
+		self.rewrite_segments(Some(expired))
+	}
+
+	fn rewrite_segments(&mut self, expired: Option<&ExpiryCheck>) -> StoreResult<(u64, u64)> {
+		// The in-flight segment is newer than every rewritten one but would keep a lower id.
+		self.finish_flush(true)?;
+		if self.merging || (expired.is_none() && self.segments.len() <= 1) {
+			return Ok((0, 0))
+		}
+		let mut rewritten = Vec::new();
+		let mut dropped = Vec::new();
+		let mut purged = 0;
+		for (idx, seg) in self.segments.iter().enumerate() {
+			let newer = &self.segments[idx + 1..];
+			let shadowed = |key: &[u8]| {
+				for n in newer {
+					if n.find(key)?.is_some() {
+						return Ok::<_, StoreError>(true)
+					}
+				}
+				Ok(false)
+			};
+			let mut entries = seg.entries(&[])?;
+			let mut dead = false;
+			while let Some((key, val_offset)) = entries.next()? {
+				let is_shadowed = shadowed(&key)?;
+				let is_expired = match expired {
+					Some(expired) => !is_shadowed && expired(&seg.read_value(val_offset)?),
+					None => false,
+				};
+				if is_shadowed || is_expired {
+					dead = true;
+					break
+				}
+			}
+			if !dead {
+				continue
+			}
+			let seg_id = take_segment_id(&self.dir, self.id, self.segments_per_dir, &mut self.next_segment_id)?;
+			let (fst_path, values_path) = segment_paths(&self.dir, self.id, seg_id, self.segments_per_dir);
+			let mut writer = SegmentWriter::create(&fst_path, &values_path, self.write_options())?;
+			let mut reader = ValueReader::new(File::open(&seg.values_path)?)?;
+			let mut live = 0usize;
+			let mut entries = seg.entries(&[])?;
+			while let Some((key, val_offset)) = entries.next()? {
+				if shadowed(&key)? {
+					continue
+				}
+				let value = reader.read_at(val_offset)?;
+				if expired.is_some_and(|expired| expired(&value)) {
+					purged += 1;
+					continue
+				}
+				writer.insert(&key, &value)?;
+				live += 1;
+			}
+			writer.finish(self.sync_on_flush)?;
+			dropped.push(seg.id);
+			if live == 0 {
+				remove_segment_files(&fst_path, &values_path);
+				continue
+			}
+			let file = File::open(&fst_path)?;
+			let mmap = unsafe { Mmap::map(&file)? };
+			let map = Map::new(mmap)?;
+			rewritten.push(new_segment(seg_id, map, fst_path, values_path, self.write_options())?);
+		}
+		let (old, kept): (Vec<Segment>, Vec<Segment>) =
+			std::mem::take(&mut self.segments).into_iter().partition(|s| dropped.contains(&s.id));
+		self.segments = kept;
+		let mut reclaimed: u64 = old.iter().map(|seg| segment_bytes(&seg.fst_path, &seg.values_path)).sum();
+		for seg in rewritten {
+			reclaimed = reclaimed.saturating_sub(segment_bytes(&seg.fst_path, &seg.values_path));
+			self.install_segment(seg);
+		}
+		self.write_manifest()?;
+		for seg in old {
+			remove_segment_files(&seg.fst_path, &seg.values_path);
+		}
+		Ok((reclaimed, purged))
 	}
 
-	pub(crate) fn snapshot_for_merge(&mut self, threshold: usize) -> StoreResult<Option<(u64, PathBuf, u8, Vec<SegmentMeta>)>> {
+	pub(crate) fn snapshot_for_merge(&mut self) -> StoreResult<Option<MergeSnapshot>> {
 		if self.merging {
 			return Ok(None)
 		}
+		// The merge output would take an id above the in-flight segment while holding older rows;
+		// wait for its install, which requests compaction again, instead of blocking commits.
+		self.finish_flush(false)?;
+		if self.flushing.is_some() {
+			return Ok(None)
+		}
 		self.flush()?;
-		if self.segments.len() < threshold {
+		let candidates = self.merge_candidates();
+		if candidates.len() < self.merge_threshold {
 			return Ok(None)
 		}
-		let merge_id = self.next_segment_id;
-		self.next_segment_id += 1;
-		let metas: Vec<SegmentMeta> = self
-			.segments
-			.iter()
-			.map(|s| {
-				let (fst_path, values_path) = segment_paths(&self.dir, self.id, s.id);
-				SegmentMeta { id: s.id, fst_path, values_path }
-			})
-			.collect();
+		let metas: Vec<SegmentMeta> = candidates.iter().map(Segment::meta).collect();
+		let merge_id = take_segment_id(&self.dir, self.id, self.segments_per_dir, &mut self.next_segment_id)?;
 		self.merging = true;
 		Ok(Some((merge_id, self.dir.clone(), self.id, metas)))
 	}
 
-	pub(crate) fn finish_merge(&mut self, merged: Segment, old_meta: &[SegmentMeta]) {
-		self.segments.retain(|s| !old_meta.iter().any(|m| m.id == s.id));
-		self.segments.push(merged);
+	/// The segments a background merge takes: all of them or, with `merge_size_ratio`, the longest
+	/// run ending at the newest whose sizes stay within the ratio. The output takes the highest id,
+	/// so a run must reach the newest segment for newer writes to keep shadowing older ones.
+	fn merge_candidates(&self) -> &[Segment] {
+		let Some(ratio) = self.merge_size_ratio else { return &self.segments };
+		let (mut min, mut max) = (u64::MAX, 0u64);
+		let mut start = self.segments.len();
+		for seg in self.segments.iter().rev() {
+			let bytes = segment_bytes(&seg.fst_path, &seg.values_path).max(1);
+			let (lo, hi) = (min.min(bytes), max.max(bytes));
+			if hi as f64 > lo as f64 * ratio {
+				break
+			}
+			(min, max) = (lo, hi);
+			start -= 1;
+		}
+		&self.segments[start..]
+	}
+
+	/// Inputs are removed only once the manifest lists the merged segment instead.
+	pub(crate) fn finish_merge(&mut self, merged: Segment, old_meta: &[SegmentMeta]) -> StoreResult<()> {
 		self.merging = false;
+		// A foreground `multi_way_merge` may have consumed the inputs meanwhile; its output is
+		// newer, so this result is stale and must not be installed.
+		if !old_meta.iter().all(|m| self.segments.iter().any(|s| s.id == m.id)) {
+			remove_segment_files(&merged.fst_path, &merged.values_path);
+			return Ok(())
+		}
+		self.segments.retain(|s| !old_meta.iter().any(|m| m.id == s.id));
+		self.install_segment(merged);
+		self.write_manifest()?;
 		for m in old_meta {
-			let _ = fs::remove_file(&m.fst_path);
-			let _ = fs::remove_file(&m.values_path);
+			remove_segment_files(&m.fst_path, &m.values_path);
+		}
+		Ok(())
+	}
+
+	pub(crate) fn write_options(&self) -> WriteOptions {
+		WriteOptions {
+			sync: self.sync_on_flush,
+			prefix: self.value_prefix,
+			mmap_values_max_bytes: self.mmap_values_max_bytes,
+			bloom_bits_per_key: self.bloom_bits_per_key,
+			pipelined: self.pipelined_flush,
+			key_prefix: self.fst_key_prefix,
+			segments_per_dir: self.segments_per_dir,
 		}
 	}
+
+	fn write_manifest(&self) -> StoreResult<()> {
+		write_manifest(&self.dir, self.id, &self.segments, &self.dir, self.sync_on_flush)
+	}
+
+	/// Inserts a segment keeping `segments` ordered by id. Segments flushed while a merge was in
+	/// flight carry higher ids than the merge output and must stay ahead of it for `get`.
+	fn install_segment(&mut self, segment: Segment) {
+		let pos = self.segments.partition_point(|s| s.id < segment.id);
+		self.segments.insert(pos, segment);
+	}
 }
 
-impl Segment {
-	pub(crate) fn read_value(&self, offset: u64) -> StoreResult<Vec<u8>> {
-		read_value_from_path(&self.values_path, offset)
+impl Drop for Column {
+	/// Lets an in-flight flush finish, so its files are complete before the store is reopened.
+	fn drop(&mut self) {
+		let _ = self.finish_flush(true);
 	}
 }
 
-pub(crate) fn merge_segments(dir: &Path, col_id: u8, new_id: u64, metas: Vec<SegmentMeta>) -> StoreResult<(Segment, Vec<SegmentMeta>)> {
-	let mut holders = Vec::with_capacity(metas.len());
-	for m in &metas {
-		let file = File::open(&m.fst_path)?;
-		let mmap = unsafe { Mmap::map(&file)? };
-		let map = Map::new(mmap)?;
-		holders.push((map, m.values_path.clone(), m.id));
-	}
-
-	// Build union stream; maps are sorted by segment id so later segments override earlier ones.
-	holders.sort_by_key(|(_, _, id)| *id);
-	let maps: Vec<_> = holders.iter().map(|(m, _, _)| m).collect();
-	let mut union = maps.into_iter().collect::<OpBuilder>().union();
-	let mut value_readers: Vec<_> = holders
-		.iter()
-		.map(|(_, val_path, _)| ValueReader::new(File::open(val_path).unwrap()))
-		.collect();
-
-	let (fst_path, values_path) = segment_paths(dir, col_id, new_id);
-	let mut map_builder = MapBuilder::new(BufWriter::new(File::create(&fst_path)?))?;
-	let mut val_writer = BufWriter::new(File::create(&values_path)?);
-	let mut write_offset: u64 = 0;
-
-	while let Some((key, outs)) = union.next() {
-		// outs are ordered by the map input index; take the last to prefer newest segment.
-		if let Some(last) = outs.last() {
-			let reader_idx = last.index;
-			let val_offset = last.value;
-			let val = value_readers[reader_idx].read_at(val_offset)?;
-			map_builder.insert(key, write_offset)?;
-			write_value(&mut val_writer, &val)?;
-			let next_offset = write_offset.checked_add(4 + val.len() as u64).ok_or_else(|| {
-				StoreError::InvalidInput("value offsets exceeded u64".into())
-			})?;
-			write_offset = next_offset;
-		}
-	}
-
-	map_builder.finish()?;
-	val_writer.flush()?;
+/// Builds a normalized memtable into segment `seg_id`.
+fn write_segment(dir: &Path, col_id: u8, seg_id: u64, memtable: &Memtable, write: WriteOptions) -> StoreResult<Segment> {
+	let (fst_path, values_path) = segment_paths(dir, col_id, seg_id, write.segments_per_dir);
+	// On a single core the two threads could only take turns.
+	let cores = thread::available_parallelism().map_or(1, |n| n.get());
+	let pipelined = write.pipelined && write.key_prefix.is_none() && cores > 1 && memtable.len() >= PIPELINE_MIN_ROWS;
+	write_segment_files(&fst_path, &values_path, memtable, write, pipelined)?;
 	let file = File::open(&fst_path)?;
 	let mmap = unsafe { Mmap::map(&file)? };
 	let map = Map::new(mmap)?;
-	let new_seg = Segment { id: new_id, map, values_path };
-
-	Ok((new_seg, metas))
+	new_segment(seg_id, map, fst_path, values_path, write)
 }
 
-pub(crate) fn load_segments(dir: &Path, col_id: u8) -> StoreResult<Vec<Segment>> {
-	let mut segments = Vec::new();
-	let prefix = format!("col{col_id}_seg");
-	for entry in fs::read_dir(dir)? {
-		let entry = entry?;
-		let fname = entry.file_name();
-		let fname = match fname.to_str() {
-			Some(f) => f,
-			None => continue,
-		};
-		if !fname.starts_with(&prefix) || !fname.ends_with(".fst") {
-			continue
+/// Writes the FST and values file of `memtable`, the values on a second thread if `pipelined`.
+fn write_segment_files(
+	fst_path: &Path,
+	values_path: &Path,
+	memtable: &Memtable,
+	write: WriteOptions,
+	pipelined: bool,
+) -> StoreResult<()> {
+	let mut writer = SegmentWriter::create(fst_path, values_path, write)?;
+	if pipelined {
+		writer.values = build_pipelined(&mut writer.map_builder, writer.values, memtable)?;
+	} else {
+		for (key, value) in memtable.iter() {
+			writer.insert(key, value)?;
 		}
-		let id_part = &fname[prefix.len()..fname.len() - 4];
-		let id: u64 = match id_part.parse() {
-			Ok(id) => id,
-			Err(_) => continue,
-		};
-		let fst_path = dir.join(fname);
-		let values_path = dir.join(format!("col{col_id}_seg{id_part}.val"));
-		if !values_path.exists() {
-			return Err(StoreError::CorruptSegment(format!("missing values file for {}", fname)))
-		}
-		let file = File::open(&fst_path)?;
-		let mmap = unsafe { Mmap::map(&file)? };
-		let map = Map::new(mmap)?;
-		segments.push(Segment { id, map, values_path });
 	}
-	Ok(segments)
+	writer.finish(write.sync)
 }
 
-pub(crate) fn segment_paths(dir: &Path, col: u8, id: u64) -> (PathBuf, PathBuf) {
-	let name = format!("col{col}_seg{id:020}");
-	(dir.join(format!("{name}.fst")), dir.join(format!("{name}.val")))
+/// Builds the FST of `memtable` while a thread of its own writes the values file. Each key's
+/// offset is the header plus the records before it, which is what `ValueWriter::append` returns,
+/// so both files come out byte for byte as the one-thread build writes them.
+fn build_pipelined(
+	map_builder: &mut MapBuilder<BufWriter<File>>,
+	mut val_writer: ValueWriter,
+	memtable: &Memtable,
+) -> StoreResult<ValueWriter> {
+	let prefix_bytes = val_writer.prefix.bytes() as u64;
+	thread::scope(|scope| {
+		let values = scope.spawn(move || {
+			for (_, value) in memtable.iter() {
+				val_writer.append(value)?;
+			}
+			Ok::<_, StoreError>(val_writer)
+		});
+		let mut offset = VALUES_HEADER_BYTES;
+		let keys = memtable.iter().try_for_each(|(key, value)| {
+			map_builder.insert(key, offset)?;
+			offset += prefix_bytes + value.len() as u64;
+			Ok::<_, StoreError>(())
+		});
+		// A value the writer rejects fails the build first, as it does in the one-thread loop.
+		let val_writer = values.join().map_err(|_| StoreError::Io(io::Error::other("values writer panicked")))??;
+		keys?;
+		Ok(val_writer)
+	})
 }
 
-pub(crate) fn write_value<W: Write>(writer: &mut W, value: &[u8]) -> StoreResult<()> {
-	let len = u32::try_from(value.len()).map_err(|_| StoreError::InvalidInput("value too large".into()))?;
-	writer.write_all(&len.to_le_bytes())?;
-	writer.write_all(value)?;
-	Ok(())
+/// Wraps freshly written segment files, building and storing their bloom filter if `write` asks.
+fn new_segment(id: u64, map: Map<Mmap>, fst_path: PathBuf, values_path: PathBuf, write: WriteOptions) -> StoreResult<Segment> {
+	let bloom = match write.bloom_bits_per_key {
+		Some(bits_per_key) => {
+			let bloom = Bloom::build(&map, bits_per_key);
+			bloom.write(&bloom_path(&fst_path))?;
+			Some(bloom)
+		},
+		None => None,
+	};
+	let values = map_values(&values_path, write.mmap_values_max_bytes)?;
+	Ok(Segment { id, map, fst_path, values_path, prefix: write.prefix, values, bloom, key_prefix: write.key_prefix })
 }
 
-pub(crate) fn read_value_from_path(path: &Path, offset: u64) -> StoreResult<Vec<u8>> {
-	let mut file = File::open(path)?;
-	file.seek(SeekFrom::Start(offset))?;
-	let mut len_buf = [0u8; 4];
-	file.read_exact(&mut len_buf)?;
-	let len = u32::from_le_bytes(len_buf) as usize;
-	let mut buf = vec![0u8; len];
-	file.read_exact(&mut buf)?;
-	Ok(buf)
+/// Removes a segment's files, its bloom filter included; ones already gone are skipped.
+fn remove_segment_files(fst_path: &Path, values_path: &Path) {
+	let _ = fs::remove_file(fst_path);
+	let _ = fs::remove_file(values_path);
+	let _ = fs::remove_file(bloom_path(fst_path));
 }
 
-struct ValueReader {
-	reader: BufReader<File>,
-	pos: u64,
-}
+impl Segment {
+	/// Paths are taken from the segment itself rather than re-derived from its id, so a file
+	/// loaded under a non-canonical name is still merged and removed correctly.
+	pub(crate) fn meta(&self) -> SegmentMeta {
+		SegmentMeta { id: self.id, fst_path: self.fst_path.clone(), values_path: self.values_path.clone() }
+	}
 
-impl ValueReader {
-	fn new(file: File) -> Self {
-		Self { reader: BufReader::new(file), pos: 0 }
+	pub(crate) fn read_value(&self, offset: u64) -> StoreResult<Vec<u8>> {
+		match &self.values {
+			Some(values) => read_value_from_bytes(values, self.prefix, offset),
+			None => read_value_from_path(&self.values_path, self.prefix, offset),
+		}
 	}
 
-	fn read_at(&mut self, offset: u64) -> StoreResult<Vec<u8>> {
-		if self.pos != offset {
-			self.reader.seek(SeekFrom::Start(offset))?;
-			self.pos = offset;
+	/// The key the FST indexes `key` under: the key itself, or its first `key_prefix` bytes.
+	pub(crate) fn fst_key<'k>(&self, key: &'k [u8]) -> &'k [u8] {
+		match self.key_prefix {
+			Some(len) => &key[..key.len().min(len as usize)],
+			None => key,
 		}
-		let mut len_buf = [0u8; 4];
-		self.reader.read_exact(&mut len_buf)?;
-		let len = u32::from_le_bytes(len_buf) as usize;
-		let mut buf = vec![0u8; len];
-		self.reader.read_exact(&mut buf)?;
-		self.pos = self.pos.checked_add(4 + len as u64).unwrap_or(self.pos);
-		Ok(buf)
 	}
-}
 
-pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
-	if prefix.is_empty() {
-		return None
+	/// Offset of `key`'s value for `read_value`. Under a key prefix the records from the first one
+	/// sharing it are scanned up to `key`; they are sorted, so a larger key ends the scan.
+	pub(crate) fn find(&self, key: &[u8]) -> StoreResult<Option<u64>> {
+		let Some(offset) = self.map.get(self.fst_key(key)) else { return Ok(None) };
+		if self.key_prefix.is_none() {
+			return Ok(Some(offset))
+		}
+		let mut records = self.records_from(offset)?;
+		while let Some((candidate, value_offset)) = records.next_key()? {
+			match candidate.as_slice().cmp(key) {
+				std::cmp::Ordering::Less => continue,
+				std::cmp::Ordering::Equal => return Ok(Some(value_offset)),
+				std::cmp::Ordering::Greater => break,
+			}
+		}
+		Ok(None)
 	}
-	let mut out = prefix.to_vec();
-	for i in (0..out.len()).rev() {
-		if out[i] != u8::MAX {
-			out[i] += 1;
-			out.truncate(i + 1);
-			return Some(out)
+
+	/// The keys starting with `prefix` in order, each with the offset of its value.
+	pub(crate) fn entries<'a>(&'a self, prefix: &'a [u8]) -> StoreResult<Entries<'a>> {
+		let mut builder = self.map.range().ge(self.fst_key(prefix));
+		if self.key_prefix.is_none() {
+			if let Some(end) = prefix_upper_bound(prefix) {
+				builder = builder.lt(end);
+			}
+			return Ok(Entries::Fst { stream: builder.into_stream(), prefix })
+		}
+		// A key starting with `prefix` is indexed at or after the FST key of `prefix`, and the
+		// records are laid out in key order, so the scan starts at that key's first record.
+		match builder.into_stream().next().map(|(_, offset)| offset) {
+			Some(offset) => Ok(Entries::Records { records: self.records_from(offset)?, prefix }),
+			None => Ok(Entries::Empty),
 		}
 	}
-	None
+
+	/// The largest key, read off the last record under a key prefix.
+	fn last_key(&self) -> StoreResult<Option<Vec<u8>>> {
+		let Some(last) = fst_last_key(self.map.as_fst()) else { return Ok(None) };
+		let Some(offset) = self.key_prefix.and(self.map.get(&last)) else { return Ok(Some(last)) };
+		let mut records = self.records_from(offset)?;
+		let mut key = None;
+		while let Some((next, _)) = records.next_key()? {
+			key = Some(next);
+		}
+		Ok(key)
+	}
+
+	fn records_from(&self, offset: u64) -> StoreResult<Records<'_>> {
+		let source: Box<dyn RecordSource + '_> = match &self.values {
+			Some(values) => Box::new(io::Cursor::new(&values[..])),
+			None => Box::new(BufReader::new(File::open(&self.values_path)?)),
+		};
+		Records::new(source, self.prefix, offset)
+	}
 }
 
-#[cfg(test)]
-mod tests {
-	use super::*;
-	use tempfile::tempdir;
+/// A segment's entries under a prefix, from `Segment::entries`.
+pub(crate) enum Entries<'a> {
+	Fst { stream: fst::map::Stream<'a>, prefix: &'a [u8] },
+	Records { records: Records<'a>, prefix: &'a [u8] },
+	Empty,
+}
 
-	#[test]
-	fn flushes_and_reads_single_segment() {
-		let dir = tempdir().unwrap();
-		let mut col = Column::open(dir.path(), 0, 2).unwrap();
-		col.insert(b"a".to_vec(), b"1".to_vec()).unwrap();
-		col.insert(b"b".to_vec(), b"2".to_vec()).unwrap();
-		col.flush().unwrap();
-		assert_eq!(col.segments.len(), 1);
-		assert_eq!(col.get(b"a").unwrap(), Some(b"1".to_vec()));
-		assert_eq!(col.get(b"b").unwrap(), Some(b"2".to_vec()));
+impl Entries<'_> {
+	/// The next key and the offset of its value, `None` past the last key with the prefix.
+	pub(crate) fn next(&mut self) -> StoreResult<Option<(Vec<u8>, u64)>> {
+		match self {
+			Entries::Fst { stream, prefix } => {
+				Ok(stream.next().filter(|(key, _)| key.starts_with(prefix)).map(|(key, offset)| (key.to_vec(), offset)))
+			},
+			Entries::Records { records, prefix } => {
+				while let Some((key, offset)) = records.next_key()? {
+					if key.starts_with(prefix) {
+						return Ok(Some((key, offset)))
+					}
+					if key.as_slice() > *prefix {
+						break
+					}
+				}
+				Ok(None)
+			},
+			Entries::Empty => Ok(None),
+		}
 	}
+}
 
-	#[test]
-	fn multi_way_merge_prefers_newer_segment() {
-		let dir = tempdir().unwrap();
-		let mut col = Column::open(dir.path(), 0, 1).unwrap();
-		col.insert(b"k".to_vec(), b"old".to_vec()).unwrap();
-		col.insert(b"k".to_vec(), b"new".to_vec()).unwrap();
-		col.flush().unwrap();
-		assert!(col.segments.len() >= 2);
-		col.multi_way_merge().unwrap();
-		assert_eq!(col.segments.len(), 1);
-		assert_eq!(col.get(b"k").unwrap(), Some(b"new".to_vec()));
+/// Builds a segment's FST and values file from entries in key order; under a key prefix the FST
+/// gets one entry per run of keys sharing theirs, pointing at the run's first record.
+pub(crate) struct SegmentWriter {
+	map_builder: MapBuilder<BufWriter<File>>,
+	values: ValueWriter,
+	/// Directory holding both files, fsynced with them.
+	dir: PathBuf,
+	key_prefix: Option<u8>,
+	/// FST key of the run the last entry joined.
+	run: Option<Vec<u8>>,
+}
+
+impl SegmentWriter {
+	fn create(fst_path: &Path, values_path: &Path, write: WriteOptions) -> StoreResult<Self> {
+		let dir = fst_path.parent().expect("segment files live in a directory").to_path_buf();
+		if !dir.exists() {
+			// The first segment of a shard; with `sync` the shard's own entry is made durable too.
+			fs::create_dir_all(&dir)?;
+			if let Some(parent) = dir.parent().filter(|_| write.sync) {
+				File::open(parent)?.sync_all()?;
+			}
+		}
+		Ok(Self {
+			map_builder: MapBuilder::new(BufWriter::new(File::create(fst_path)?))?,
+			values: ValueWriter::create(values_path, write.prefix, write.key_prefix)?,
+			dir,
+			key_prefix: write.key_prefix,
+			run: None,
+		})
 	}
 
-	#[test]
-	fn keys_with_prefix_dedupes_from_segments() {
-		let dir = tempdir().unwrap();
-		let mut col = Column::open(dir.path(), 0, 1).unwrap();
-		col.insert(b"p1".to_vec(), vec![]).unwrap();
-		col.insert(b"p2".to_vec(), vec![]).unwrap();
-		col.insert(b"p1".to_vec(), vec![]).unwrap(); // newer duplicate
-		col.flush().unwrap();
-		let keys = col.keys_with_prefix(b"p").unwrap();
-		assert_eq!(keys, vec![b"p1".to_vec(), b"p2".to_vec()]);
+	fn insert(&mut self, key: &[u8], value: &[u8]) -> StoreResult<()> {
+		let Some(len) = self.key_prefix else {
+			self.map_builder.insert(key, self.values.append(value)?)?;
+			return Ok(())
+		};
+		let offset = self.values.append_keyed(key, value)?;
+		// Truncating keeps their order, so runs arrive with ascending FST keys.
+		let run = &key[..key.len().min(len as usize)];
+		if self.run.as_deref() != Some(run) {
+			self.map_builder.insert(run, offset)?;
+			self.run = Some(run.to_vec());
+		}
+		Ok(())
+	}
+
+	fn finish(self, sync: bool) -> StoreResult<()> {
+		finish_segment_files(&self.dir, self.map_builder, self.values, sync)
+	}
+}
+
+/// `progress` receives the number of rows written every `MERGE_PROGRESS_ROWS` rows and once more
+/// for the remainder.
+pub(crate) fn merge_segments(
+	dir: &Path,
+	col_id: u8,
+	new_id: u64,
+	metas: Vec<SegmentMeta>,
+	write: WriteOptions,
+	progress: &mut dyn FnMut(u64),
+) -> StoreResult<(Segment, Vec<SegmentMeta>)> {
+	let (fst_path, values_path) = segment_paths(dir, col_id, new_id, write.segments_per_dir);
+	let map = merge_into(&metas, &fst_path, &values_path, write, progress)?;
+	Ok((new_segment(new_id, map, fst_path, values_path, write)?, metas))
+}
+
+/// Tree merge: contiguous id runs of the inputs are merged on up to `threads` threads into part
+/// files, which are then merged into segment `new_id`. Runs keep id order, so the newest segment
+/// still wins every key and the output is byte-identical to `merge_segments`. Part files are not
+/// named like segments, so a crash never loads them; `progress` only follows the final pass.
+pub(crate) fn merge_segments_parallel(
+	dir: &Path,
+	col_id: u8,
+	new_id: u64,
+	mut metas: Vec<SegmentMeta>,
+	write: WriteOptions,
+	threads: usize,
+	progress: &mut dyn FnMut(u64),
+) -> StoreResult<(Segment, Vec<SegmentMeta>)> {
+	let runs = threads.min(metas.len() / 2);
+	if runs <= 1 {
+		return merge_segments(dir, col_id, new_id, metas, write, progress)
+	}
+	metas.sort_by_key(|m| m.id);
+	let run_len = metas.len().div_ceil(runs);
+	let parts: Vec<StoreResult<SegmentMeta>> = thread::scope(|scope| {
+		let handles: Vec<_> = metas
+			.chunks(run_len)
+			.enumerate()
+			.map(|(idx, run)| scope.spawn(move || merge_part(dir, col_id, new_id, idx, run, write)))
+			.collect();
+		handles.into_iter().map(|h| h.join().expect("merge thread panicked")).collect()
+	});
+	let merged = parts
+		.into_iter()
+		.collect::<StoreResult<Vec<_>>>()
+		.and_then(|inputs| merge_segments(dir, col_id, new_id, inputs, write, progress));
+	for idx in 0..metas.len().div_ceil(run_len) {
+		let (fst_path, values_path) = part_paths(dir, col_id, new_id, idx);
+		let _ = fs::remove_file(fst_path);
+		let _ = fs::remove_file(values_path);
+	}
+	Ok((merged?.0, metas))
+}
+
+/// Merges one run of a tree merge; the part takes the newest id of its run so the final pass
+/// orders parts like their inputs. A single-segment run is passed through as is.
+fn merge_part(
+	dir: &Path,
+	col_id: u8,
+	new_id: u64,
+	idx: usize,
+	run: &[SegmentMeta],
+	write: WriteOptions,
+) -> StoreResult<SegmentMeta> {
+	if let [single] = run {
+		return Ok(single.clone())
+	}
+	let (fst_path, values_path) = part_paths(dir, col_id, new_id, idx);
+	// Parts are merged once more, so a bloom filter over them would go unused.
+	let part = WriteOptions { prefix: write.prefix, key_prefix: write.key_prefix, ..WriteOptions::default() };
+	merge_into(run, &fst_path, &values_path, part, &mut |_| {})?;
+	Ok(SegmentMeta { id: run[run.len() - 1].id, fst_path, values_path })
+}
+
+fn merge_into(
+	metas: &[SegmentMeta],
+	fst_path: &Path,
+	values_path: &Path,
+	write: WriteOptions,
+	progress: &mut dyn FnMut(u64),
+) -> StoreResult<Map<Mmap>> {
+	let mut inputs = Vec::with_capacity(metas.len());
+	for m in metas {
+		let file = File::open(&m.fst_path)?;
+		let mmap = unsafe { Mmap::map(&file)? };
+		let map = Map::new(mmap)?;
+		// Inputs may be removed by a concurrent foreground merge; surface that as an error instead
+		// of panicking the compactor thread.
+		let (prefix, key_prefix) = read_values_header(&mut File::open(&m.values_path)?)?;
+		let (fst_path, values_path) = (m.fst_path.clone(), m.values_path.clone());
+		inputs.push(Segment { id: m.id, map, fst_path, values_path, prefix, values: None, bloom: None, key_prefix });
+	}
+
+	// Inputs are sorted by segment id so later segments override earlier ones.
+	inputs.sort_by_key(|seg| seg.id);
+	let mut value_readers =
+		inputs.iter().map(|seg| ValueReader::new(File::open(&seg.values_path)?)).collect::<StoreResult<Vec<_>>>()?;
+
+	let mut writer = SegmentWriter::create(fst_path, values_path, write)?;
+	let mut unreported: u64 = 0;
+	let mut copy = |input: usize, key: &[u8], val_offset: u64| {
+		let val = value_readers[input].read_at(val_offset)?;
+		writer.insert(key, &val)?;
+		unreported += 1;
+		if unreported == MERGE_PROGRESS_ROWS {
+			progress(unreported);
+			unreported = 0;
+		}
+		Ok::<_, StoreError>(())
+	};
+
+	if inputs.iter().all(|seg| seg.key_prefix.is_none()) {
+		let mut union = inputs.iter().map(|seg| &seg.map).collect::<OpBuilder>().union();
+		while let Some((key, outs)) = union.next() {
+			// outs for a shared key come off fst's heap ordered by value offset, not by input, so
+			// pick the highest input index explicitly to prefer the newest segment.
+			if let Some(newest) = outs.iter().max_by_key(|o| o.index) {
+				copy(newest.index, key, newest.value)?;
+			}
+		}
+	} else {
+		// FSTs over key prefixes cannot be unioned into whole keys; the inputs' entries are merged
+		// by key instead, the newest input holding a key winning it.
+		let mut cursors = inputs.iter().map(|seg| seg.entries(&[])).collect::<StoreResult<Vec<_>>>()?;
+		let mut heads = cursors.iter_mut().map(Entries::next).collect::<StoreResult<Vec<_>>>()?;
+		while let Some(key) = heads.iter().flatten().map(|(key, _)| key).min().cloned() {
+			let mut newest = None;
+			for (input, head) in heads.iter_mut().enumerate() {
+				if let Some((head_key, val_offset)) = head
+					&& *head_key == key
+				{
+					newest = Some((input, *val_offset));
+					*head = cursors[input].next()?;
+				}
+			}
+			let (input, val_offset) = newest.expect("an input holds the smallest key");
+			copy(input, &key, val_offset)?;
+		}
+	}
+	if unreported > 0 {
+		progress(unreported);
+	}
+
+	// Synced before the caller deletes the inputs, so a crash never loses both copies.
+	writer.finish(write.sync)?;
+	let file = File::open(fst_path)?;
+	let mmap = unsafe { Mmap::map(&file)? };
+	Ok(Map::new(mmap)?)
+}
+
+/// Flushes a segment's writers and, with `sync`, fsyncs both files and the directory entries.
+fn finish_segment_files(
+	dir: &Path,
+	map_builder: MapBuilder<BufWriter<File>>,
+	val_writer: ValueWriter,
+	sync: bool,
+) -> StoreResult<()> {
+	let fst_file = map_builder.into_inner()?.into_inner().map_err(|e| e.into_error())?;
+	let val_file = val_writer.writer.into_inner().map_err(|e| e.into_error())?;
+	if sync {
+		fst_file.sync_all()?;
+		val_file.sync_all()?;
+		File::open(dir)?.sync_all()?;
+	}
+	Ok(())
+}
+
+/// Loads the segments the column's manifest lists or, for a store written before manifests, every
+/// `col<id>_seg<n>.fst` in `dir` and its shard directories. With a manifest, files it does not list are leftovers of an
+/// interrupted flush or merge and stay unloaded, while a listed one that is missing is corrupt.
+pub(crate) fn load_segments(
+	dir: &Path,
+	col_id: u8,
+	recovery: RecoveryMode,
+	mmap_values_max_bytes: u64,
+) -> StoreResult<Vec<Segment>> {
+	let listed = match (read_manifest(dir, col_id), recovery) {
+		(Ok(listed), _) => listed,
+		(Err(_), RecoveryMode::SkipCorrupt) => None,
+		(Err(err), RecoveryMode::Strict) => return Err(err),
+	};
+	let from_manifest = listed.is_some();
+	let stems = match listed {
+		Some(listed) => listed,
+		None => scan_segment_stems(dir, col_id)?,
+	};
+	let mut segments = Vec::new();
+	for (id, stem) in stems {
+		let fst_path = dir.join(format!("{stem}.fst"));
+		let values_path = dir.join(format!("{stem}.val"));
+		let loaded = if from_manifest && !fst_path.exists() {
+			Err(StoreError::CorruptSegment(format!("manifest lists missing {}", fst_path.display())))
+		} else {
+			load_segment(id, fst_path, values_path, recovery, mmap_values_max_bytes)
+		};
+		match (loaded, recovery) {
+			(Ok(segment), _) => segments.push(segment),
+			(Err(_), RecoveryMode::SkipCorrupt) => continue,
+			(Err(err), RecoveryMode::Strict) => return Err(err),
+		}
+	}
+	Ok(segments)
+}
+
+/// Id and file stem, relative to `dir`, of every `col<id>_seg<n>.fst` in `dir` and its shard
+/// directories.
+fn scan_segment_stems(dir: &Path, col_id: u8) -> StoreResult<Vec<(u64, String)>> {
+	let mut stems = Vec::new();
+	let prefix = format!("col{col_id}_seg");
+	for (shard, path) in segment_dirs(dir, col_id)? {
+		for entry in fs::read_dir(path)? {
+			let entry = entry?;
+			let fname = entry.file_name();
+			let fname = match fname.to_str() {
+				Some(f) => f,
+				None => continue,
+			};
+			if !fname.starts_with(&prefix) || !fname.ends_with(".fst") {
+				continue
+			}
+			let stem = &fname[..fname.len() - 4];
+			let id: u64 = match stem[prefix.len()..].parse() {
+				Ok(id) => id,
+				Err(_) => continue,
+			};
+			stems.push((id, shard.as_ref().map_or_else(|| stem.to_string(), |shard| format!("{shard}/{stem}"))));
+		}
+	}
+	Ok(stems)
+}
+
+/// `dir` itself, named `None`, and every `col<id>_shard<n>` directory in it, whichever layout the
+/// segments were written with.
+fn segment_dirs(dir: &Path, col_id: u8) -> StoreResult<Vec<(Option<String>, PathBuf)>> {
+	let prefix = format!("col{col_id}_shard");
+	let mut dirs = vec![(None, dir.to_path_buf())];
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let Some(fname) = entry.file_name().to_str().map(str::to_string) else { continue };
+		if fname.starts_with(&prefix) && entry.file_type()?.is_dir() {
+			dirs.push((Some(fname), entry.path()));
+		}
+	}
+	Ok(dirs)
+}
+
+pub(crate) fn manifest_path(dir: &Path, col_id: u8) -> PathBuf {
+	dir.join(format!("col{col_id}.manifest"))
+}
+
+/// Replaces the column's manifest in `dir` with `segments`, one `<id> <file stem>` line each, the
+/// stem relative to `segments_dir`, where they were written, so it names a shard too. Goes through
+/// a rename so a crash leaves either the old or the new list. With `sync` both reach the disk.
+pub(crate) fn write_manifest(
+	dir: &Path,
+	col_id: u8,
+	segments: &[Segment],
+	segments_dir: &Path,
+	sync: bool,
+) -> StoreResult<()> {
+	let mut listing = String::new();
+	for seg in segments {
+		let stem = seg.fst_path.strip_prefix(segments_dir).ok().map(|path| path.with_extension(""));
+		let stem = stem.as_deref().and_then(Path::to_str).expect("segment files live under the store directory");
+		listing.push_str(&format!("{} {stem}\n", seg.id));
+	}
+	let path = manifest_path(dir, col_id);
+	let tmp = path.with_extension("manifest.tmp");
+	let mut file = File::create(&tmp)?;
+	file.write_all(listing.as_bytes())?;
+	if sync {
+		file.sync_all()?;
+	}
+	fs::rename(&tmp, &path)?;
+	if sync {
+		File::open(dir)?.sync_all()?;
+	}
+	Ok(())
+}
+
+/// Hands out a column's next segment id. Flushes, merges and rewrites all take it under the column's
+/// write lock, so a commit's flush and the compactor's snapshot never draw the same one; files
+/// already under an id mean that broke, and writing them would truncate a live segment.
+fn take_segment_id(dir: &Path, col_id: u8, segments_per_dir: Option<u64>, next_segment_id: &mut u64) -> StoreResult<u64> {
+	let id = *next_segment_id;
+	*next_segment_id += 1;
+	let (fst_path, values_path) = segment_paths(dir, col_id, id, segments_per_dir);
+	if fst_path.exists() || values_path.exists() {
+		return Err(StoreError::SegmentIdInUse(format!("col{col_id}: segment {id} already has files")))
+	}
+	Ok(id)
+}
+
+/// The segments the column's manifest lists, or `None` if it has none.
+fn read_manifest(dir: &Path, col_id: u8) -> StoreResult<Option<Vec<(u64, String)>>> {
+	let path = manifest_path(dir, col_id);
+	let listing = match fs::read_to_string(&path) {
+		Ok(listing) => listing,
+		Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+		Err(err) => return Err(err.into()),
+	};
+	let corrupt = || StoreError::CorruptSegment(format!("malformed manifest {}", path.display()));
+	listing
+		.lines()
+		.map(|line| {
+			let (id, stem) = line.split_once(' ').ok_or_else(corrupt)?;
+			Ok((id.parse().map_err(|_| corrupt())?, stem.to_string()))
+		})
+		.collect::<StoreResult<Vec<_>>>()
+		.map(Some)
+}
+
+fn load_segment(
+	id: u64,
+	fst_path: PathBuf,
+	values_path: PathBuf,
+	recovery: RecoveryMode,
+	mmap_values_max_bytes: u64,
+) -> StoreResult<Segment> {
+	if !values_path.exists() {
+		return Err(StoreError::CorruptSegment(format!("missing values file for {}", fst_path.display())))
+	}
+	let file = File::open(&fst_path)?;
+	let mmap = unsafe { Mmap::map(&file)? };
+	let map = Map::new(mmap)?;
+	let (prefix, key_prefix) = read_values_header(&mut File::open(&values_path)?)?;
+	if recovery == RecoveryMode::SkipCorrupt {
+		verify_segment(&map, &values_path, prefix, key_prefix)?;
+	}
+	let values = map_values(&values_path, mmap_values_max_bytes)?;
+	// Loaded even if the store no longer builds filters: it still describes these very keys.
+	let bloom = Bloom::load(&bloom_path(&fst_path));
+	Ok(Segment { id, map, fst_path, values_path, prefix, values, bloom, key_prefix })
+}
+
+/// Checks the FST checksum and that the values file holds the record at the highest offset,
+/// which every writer appends last, or under a key prefix every record. Reads the whole segment.
+fn verify_segment(
+	map: &Map<Mmap>,
+	values_path: &Path,
+	prefix: LengthPrefix,
+	key_prefix: Option<u8>,
+) -> StoreResult<()> {
+	map.as_fst().verify()?;
+	if key_prefix.is_some() {
+		let file = File::open(values_path)?;
+		let len = file.metadata()?.len();
+		let mut records = Records::new(Box::new(BufReader::new(file)), prefix, KEYED_HEADER_BYTES)?;
+		while records.next_key()?.is_some() {}
+		if records.pos != len {
+			return Err(StoreError::CorruptSegment(format!("{} ends inside a record", values_path.display())))
+		}
+		return Ok(())
+	}
+	let mut stream = map.stream();
+	let mut last: Option<u64> = None;
+	while let Some((_, offset)) = stream.next() {
+		last = Some(last.map_or(offset, |max| max.max(offset)));
+	}
+	if let Some(offset) = last {
+		read_value_from_path(values_path, prefix, offset)?;
+	}
+	Ok(())
+}
+
+/// Highest segment id referenced by any `.fst`, `.val` or `.bloom` file of the column, complete
+/// or not and flat or sharded, so a new segment never picks up a stale filter.
+pub(crate) fn max_segment_id_on_disk(dir: &Path, col_id: u8) -> StoreResult<Option<u64>> {
+	let prefix = format!("col{col_id}_seg");
+	let mut max: Option<u64> = None;
+	for (_, path) in segment_dirs(dir, col_id)? {
+		for entry in fs::read_dir(path)? {
+			let entry = entry?;
+			let fname = entry.file_name();
+			let Some(fname) = fname.to_str() else { continue };
+			let Some(rest) = fname.strip_prefix(&prefix) else { continue };
+			let Some(id_part) = [".fst", ".val", ".bloom"].iter().find_map(|suffix| rest.strip_suffix(suffix)) else {
+				continue
+			};
+			if let Ok(id) = id_part.parse::<u64>() {
+				max = Some(max.map_or(id, |m| m.max(id)));
+			}
+		}
+	}
+	Ok(max)
+}
+
+/// Follows the largest transition out of each node; transitions are sorted by input byte, so the
+/// path spells the largest key. `fst` streams only forward, which would walk the whole segment.
+fn fst_last_key(fst: &fst::raw::Fst<Mmap>) -> Option<Vec<u8>> {
+	let mut node = fst.root();
+	let mut key = Vec::new();
+	while !node.is_empty() {
+		let last = node.transition(node.len() - 1);
+		key.push(last.inp);
+		node = fst.node(last.addr);
+	}
+	node.is_final().then_some(key)
+}
+
+/// On-disk size of a segment (`.fst` + `.val` + `.bloom`); missing files count as zero.
+pub(crate) fn segment_bytes(fst_path: &Path, values_path: &Path) -> u64 {
+	let len = |p: &Path| fs::metadata(p).map(|m| m.len()).unwrap_or(0);
+	len(fst_path) + len(values_path) + len(&bloom_path(fst_path))
+}
+
+/// Files of segment `id`; with `segments_per_dir` they go into the column's shard of ids
+/// `n * segments_per_dir ..`, named `col<col>_shard<n>`.
+pub(crate) fn segment_paths(dir: &Path, col: u8, id: u64, segments_per_dir: Option<u64>) -> (PathBuf, PathBuf) {
+	let name = format!("col{col}_seg{id:020}");
+	let dir = match segments_per_dir {
+		Some(per_dir) => dir.join(format!("col{col}_shard{:020}", id / per_dir)),
+		None => dir.to_path_buf(),
+	};
+	(dir.join(format!("{name}.fst")), dir.join(format!("{name}.val")))
+}
+
+/// Files of run `idx` of the tree merge into segment `id`; the suffix keeps them out of
+/// `load_segments` and `max_segment_id_on_disk`.
+fn part_paths(dir: &Path, col: u8, id: u64, idx: usize) -> (PathBuf, PathBuf) {
+	let name = format!("col{col}_seg{id:020}_part{idx}");
+	(dir.join(format!("{name}.fst")), dir.join(format!("{name}.val")))
+}
+
+/// Appends length-prefixed values behind the header and hands out their offsets.
+pub(crate) struct ValueWriter {
+	writer: BufWriter<File>,
+	prefix: LengthPrefix,
+	offset: u64,
+}
+
+impl ValueWriter {
+	/// With `key_prefix`, the header marks the file as one of `append_keyed` records.
+	pub(crate) fn create(path: &Path, prefix: LengthPrefix, key_prefix: Option<u8>) -> StoreResult<Self> {
+		let mut writer = BufWriter::with_capacity(VALUE_WRITE_BUFFER, File::create(path)?);
+		let width = prefix.bytes() as u8;
+		let offset = match key_prefix {
+			Some(len) => {
+				writer.write_all(&[width | KEYED_VALUES, len])?;
+				KEYED_HEADER_BYTES
+			},
+			None => {
+				writer.write_all(&[width])?;
+				VALUES_HEADER_BYTES
+			},
+		};
+		Ok(Self { writer, prefix, offset })
+	}
+
+	/// Writes `value` and returns the offset it was written at. The offset advances by the bytes
+	/// `write_value` reports, so it can never drift from the file contents.
+	pub(crate) fn append(&mut self, value: &[u8]) -> StoreResult<u64> {
+		let offset = self.offset;
+		let written = self.write_value(value)?;
+		self.offset = offset
+			.checked_add(written)
+			.ok_or_else(|| StoreError::InvalidInput("value offsets exceeded u64".into()))?;
+		Ok(offset)
+	}
+
+	/// Writes `key` behind its u16 length, then `value` as `append` does, and returns the offset of
+	/// the record; its value is at the offset plus the key's 2 + `key.len()` bytes.
+	pub(crate) fn append_keyed(&mut self, key: &[u8], value: &[u8]) -> StoreResult<u64> {
+		let offset = self.offset;
+		let Ok(len) = u16::try_from(key.len()) else {
+			return Err(StoreError::InvalidInput(format!("key of {} bytes exceeds {MAX_KEY_BYTES}", key.len())))
+		};
+		self.writer.write_all(&len.to_le_bytes())?;
+		self.writer.write_all(key)?;
+		let written = 2 + key.len() as u64 + self.write_value(value)?;
+		self.offset = offset
+			.checked_add(written)
+			.ok_or_else(|| StoreError::InvalidInput("value offsets exceeded u64".into()))?;
+		Ok(offset)
+	}
+
+	/// Writes the length prefix and `value`, returning how many bytes went to the file.
+	fn write_value(&mut self, value: &[u8]) -> StoreResult<u64> {
+		let len = u32::try_from(value.len()).ok().filter(|len| *len as usize <= self.prefix.max_len());
+		let Some(len) = len else {
+			return Err(StoreError::InvalidInput(format!(
+				"value of {} bytes exceeds the {}-byte length prefix",
+				value.len(),
+				self.prefix.bytes()
+			)))
+		};
+		let prefix = &len.to_le_bytes()[..self.prefix.bytes()];
+		self.writer.write_all(prefix)?;
+		self.writer.write_all(value)?;
+		Ok(prefix.len() as u64 + value.len() as u64)
+	}
+}
+
+/// The value length prefix of a `.val` file and, for one of keyed records, its FST's key prefix.
+fn read_values_header(file: &mut File) -> StoreResult<(LengthPrefix, Option<u8>)> {
+	let mut header = [0u8; 1];
+	file.read_exact(&mut header)?;
+	if header[0] & KEYED_VALUES == 0 {
+		return Ok((LengthPrefix::from_header(header[0])?, None))
+	}
+	let mut key_prefix = [0u8; 1];
+	file.read_exact(&mut key_prefix)?;
+	if key_prefix[0] == 0 {
+		return Err(StoreError::CorruptSegment("values file keyed by an empty prefix".into()))
+	}
+	Ok((LengthPrefix::from_header(header[0] & !KEYED_VALUES)?, Some(key_prefix[0])))
+}
+
+fn read_length<R: Read>(reader: &mut R, prefix: LengthPrefix) -> StoreResult<usize> {
+	let mut len_buf = [0u8; 4];
+	reader.read_exact(&mut len_buf[..prefix.bytes()])?;
+	Ok(u32::from_le_bytes(len_buf) as usize)
+}
+
+pub(crate) fn read_value_from_path(path: &Path, prefix: LengthPrefix, offset: u64) -> StoreResult<Vec<u8>> {
+	let mut file = File::open(path)?;
+	file.seek(SeekFrom::Start(offset))?;
+	let len = read_length(&mut file, prefix)?;
+	let mut buf = vec![0u8; len];
+	file.read_exact(&mut buf)?;
+	Ok(buf)
+}
+
+/// `read_value_from_path` over a mapped values file; a record cut short fails the same way.
+fn read_value_from_bytes(bytes: &[u8], prefix: LengthPrefix, offset: u64) -> StoreResult<Vec<u8>> {
+	let mut reader = usize::try_from(offset).ok().and_then(|offset| bytes.get(offset..)).unwrap_or_default();
+	let len = read_length(&mut reader, prefix)?;
+	let mut buf = vec![0u8; len];
+	reader.read_exact(&mut buf)?;
+	Ok(buf)
+}
+
+/// Maps a values file of at most `max_bytes`; larger files, and all of them with `max_bytes` 0,
+/// are read through a file handle per lookup so huge ones cannot exhaust the address space.
+fn map_values(values_path: &Path, max_bytes: u64) -> StoreResult<Option<Mmap>> {
+	if max_bytes == 0 {
+		return Ok(None)
+	}
+	let file = File::open(values_path)?;
+	if file.metadata()?.len() > max_bytes {
+		return Ok(None)
+	}
+	Ok(Some(unsafe { Mmap::map(&file)? }))
+}
+
+struct ValueReader {
+	reader: BufReader<File>,
+	prefix: LengthPrefix,
+	pos: u64,
+}
+
+impl ValueReader {
+	fn new(mut file: File) -> StoreResult<Self> {
+		let (prefix, key_prefix) = read_values_header(&mut file)?;
+		let pos = if key_prefix.is_some() { KEYED_HEADER_BYTES } else { VALUES_HEADER_BYTES };
+		Ok(Self { reader: BufReader::new(file), prefix, pos })
+	}
+
+	fn read_at(&mut self, offset: u64) -> StoreResult<Vec<u8>> {
+		if self.pos != offset {
+			// Relative, so skipping the keys between keyed records stays within the buffer.
+			self.reader.seek_relative(offset as i64 - self.pos as i64)?;
+			self.pos = offset;
+		}
+		let len = read_length(&mut self.reader, self.prefix)?;
+		let mut buf = vec![0u8; len];
+		self.reader.read_exact(&mut buf)?;
+		self.pos = self.pos.checked_add((self.prefix.bytes() + len) as u64).unwrap_or(self.pos);
+		Ok(buf)
+	}
+}
+
+/// A values file, mapped or read through a buffer, for `Records`.
+trait RecordSource: Read + Seek {}
+
+impl<T: Read + Seek> RecordSource for T {}
+
+/// Reads the records `ValueWriter::append_keyed` writes, in file order, from a given offset.
+pub(crate) struct Records<'a> {
+	source: Box<dyn RecordSource + 'a>,
+	prefix: LengthPrefix,
+	pos: u64,
+}
+
+impl<'a> Records<'a> {
+	fn new(mut source: Box<dyn RecordSource + 'a>, prefix: LengthPrefix, offset: u64) -> StoreResult<Self> {
+		source.seek(SeekFrom::Start(offset))?;
+		Ok(Self { source, prefix, pos: offset })
+	}
+
+	/// The next record's key and the offset of its value, skipping the value; `None` at the end
+	/// of the file.
+	fn next_key(&mut self) -> StoreResult<Option<(Vec<u8>, u64)>> {
+		let mut key_len = [0u8; 2];
+		if self.source.read(&mut key_len[..1])? == 0 {
+			return Ok(None)
+		}
+		self.source.read_exact(&mut key_len[1..])?;
+		let mut key = vec![0u8; u16::from_le_bytes(key_len) as usize];
+		self.source.read_exact(&mut key)?;
+		let value_offset = self.pos + 2 + key.len() as u64;
+		let value_len = read_length(&mut self.source, self.prefix)?;
+		self.source.seek_relative(value_len as i64)?;
+		self.pos = value_offset + (self.prefix.bytes() + value_len) as u64;
+		Ok(Some((key, value_offset)))
+	}
+}
+
+pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+	if prefix.is_empty() {
+		return None
+	}
+	let mut out = prefix.to_vec();
+	for i in (0..out.len()).rev() {
+		if out[i] != u8::MAX {
+			out[i] += 1;
+			out.truncate(i + 1);
+			return Some(out)
+		}
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::memtable::MemtableKind;
+	use tempfile::tempdir;
+
+	#[test]
+	fn flushes_and_reads_single_segment() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(2)).unwrap();
+		col.insert(b"a".to_vec(), b"1".to_vec()).unwrap();
+		col.insert(b"b".to_vec(), b"2".to_vec()).unwrap();
+		col.flush().unwrap();
+		assert_eq!(col.segments.len(), 1);
+		assert_eq!(col.get(b"a").unwrap(), Some(b"1".to_vec()));
+		assert_eq!(col.get(b"b").unwrap(), Some(b"2".to_vec()));
+	}
+
+	#[test]
+	fn gets_count_the_segments_they_probe() {
+		const SEGMENTS: u64 = 5;
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10).with_count_reads(true)).unwrap();
+		for seg in 0..SEGMENTS {
+			col.insert(vec![seg as u8], b"v".to_vec()).unwrap();
+			col.flush().unwrap();
+		}
+		col.insert(b"fresh".to_vec(), b"v".to_vec()).unwrap();
+		assert_eq!(col.segments.len(), SEGMENTS as usize);
+		assert_eq!(col.get(&[0]).unwrap(), Some(b"v".to_vec()));
+		assert_eq!(col.read_counters.stats(), ColumnStats { gets: 1, segments_probed: SEGMENTS });
+		col.get(&[SEGMENTS as u8 - 1]).unwrap();
+		col.get(b"fresh").unwrap();
+		col.get(b"missing").unwrap();
+		let stats = col.read_counters.stats();
+		assert_eq!(stats, ColumnStats { gets: 4, segments_probed: 2 * SEGMENTS + 1 });
+		assert_eq!(stats.read_amplification(), 11.0 / 4.0);
+		col.read_counters.reset();
+		assert_eq!(col.read_counters.stats(), ColumnStats::default());
+		let other_dir = tempdir().unwrap();
+		let uncounted = Column::open(other_dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		uncounted.get(b"a").unwrap();
+		assert_eq!(uncounted.read_counters.stats(), ColumnStats::default());
+	}
+
+	#[test]
+	fn adaptive_segment_size_drops_to_stay_within_the_budget() {
+		const BUDGET: usize = 1 << 20;
+		// Sized for 16-byte rows; the real ones are 256 bytes, 16x the estimate.
+		let options = StoreOptions::from_estimates(10_000_000, 16, BUDGET).with_adaptive_segment_size(BUDGET);
+		assert_eq!(options.segment_size, BUDGET / 16);
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		for i in 0..4 * ADAPT_SAMPLE_ROWS as u32 {
+			col.insert(i.to_be_bytes().to_vec(), vec![7; 252]).unwrap();
+		}
+		assert_eq!(col.segment_size, BUDGET / 256);
+		assert_eq!(col.segments.len(), 4);
+		assert!(col.segments.iter().all(|seg| seg.map.len() * 256 <= BUDGET));
+		assert_eq!(col.get(&5u32.to_be_bytes()).unwrap(), Some(vec![7; 252]));
+
+		// Far off estimates stop at `MAX_SEGMENT_SIZE_ADJUST` of the configured size.
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(64 * 1024).with_adaptive_segment_size(BUDGET);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		for i in 0..ADAPT_SAMPLE_ROWS as u32 {
+			col.insert(i.to_be_bytes().to_vec(), vec![7; 4092]).unwrap();
+		}
+		assert_eq!(col.segment_size, 64 * 1024 / MAX_SEGMENT_SIZE_ADJUST);
+
+		// Without a budget the configured size stays.
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(64 * 1024)).unwrap();
+		for i in 0..ADAPT_SAMPLE_ROWS as u32 {
+			col.insert(i.to_be_bytes().to_vec(), vec![7; 252]).unwrap();
+		}
+		assert_eq!(col.segment_size, 64 * 1024);
+	}
+
+	#[test]
+	fn negative_lookup_skips_segments_whose_bloom_rejects_the_key() {
+		const SEGMENTS: u32 = 8;
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(1000).with_count_reads(true).with_bloom_bits_per_key(10.0);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		for seg in 0..SEGMENTS {
+			for i in 0..100u32 {
+				col.insert((seg * 100 + i).to_be_bytes().to_vec(), b"v".to_vec()).unwrap();
+			}
+			col.flush().unwrap();
+		}
+		assert!(col.segments.iter().all(|seg| bloom_path(&seg.fst_path).exists()));
+		let missing = 1_000_000u32.to_be_bytes();
+		let admitting = col.segments.iter().filter(|seg| seg.bloom.as_ref().unwrap().may_contain(&missing)).count();
+		assert!(admitting < SEGMENTS as usize);
+		assert_eq!(col.get(&missing).unwrap(), None);
+		assert_eq!(col.read_counters.stats(), ColumnStats { gets: 1, segments_probed: admitting as u64 });
+		col.read_counters.reset();
+		assert_eq!(col.get(&0u32.to_be_bytes()).unwrap(), Some(b"v".to_vec()));
+		assert!(col.read_counters.stats().segments_probed <= SEGMENTS as u64);
+		drop(col);
+
+		// The filters come back from their files, even for a store no longer building them.
+		let col = Column::open(dir.path(), 0, &StoreOptions::new(1000).with_count_reads(true)).unwrap();
+		assert!(col.segments.iter().all(|seg| seg.bloom.is_some()));
+		assert_eq!(col.get(&missing).unwrap(), None);
+		assert_eq!(col.read_counters.stats().segments_probed, admitting as u64);
+		for key in [0u32, 450, 799] {
+			assert_eq!(col.get(&key.to_be_bytes()).unwrap(), Some(b"v".to_vec()));
+		}
+		drop(col);
+
+		// A merge filters its output and takes the inputs' filters with them.
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		col.multi_way_merge(&mut |_| {}).unwrap();
+		let blooms = fs::read_dir(dir.path()).unwrap().filter(|e| e.as_ref().unwrap().path().extension() == Some("bloom".as_ref()));
+		assert_eq!(blooms.count(), 1);
+		assert!(col.segments[0].bloom.is_some());
+		for key in [0u32, 450, 799] {
+			assert_eq!(col.get(&key.to_be_bytes()).unwrap(), Some(b"v".to_vec()));
+		}
+	}
+
+	#[test]
+	fn multi_way_merge_prefers_newer_segment() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(1)).unwrap();
+		col.insert(b"k".to_vec(), b"old".to_vec()).unwrap();
+		col.insert(b"k".to_vec(), b"new".to_vec()).unwrap();
+		col.flush().unwrap();
+		assert!(col.segments.len() >= 2);
+		col.multi_way_merge(&mut |_| {}).unwrap();
+		assert_eq!(col.segments.len(), 1);
+		assert_eq!(col.get(b"k").unwrap(), Some(b"new".to_vec()));
+	}
+
+	#[test]
+	fn merge_threshold_gates_background_snapshot() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(1).with_merge_threshold(3)).unwrap();
+		for key in [b"a", b"b"] {
+			col.insert(key.to_vec(), b"v".to_vec()).unwrap();
+		}
+		assert!(col.snapshot_for_merge().unwrap().is_none());
+		col.insert(b"c".to_vec(), b"v".to_vec()).unwrap();
+		let (_, _, _, metas) = col.snapshot_for_merge().unwrap().expect("three segments reach the threshold");
+		assert_eq!(metas.len(), 3);
+		assert!(col.merging);
+	}
+
+	#[test]
+	fn size_ratio_keeps_a_large_segment_out_of_small_merges() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(100_000).with_merge_threshold(3).with_merge_size_ratio(2.0);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		let key = |i: u32| i.to_be_bytes().to_vec();
+		for i in 0..2_000u32 {
+			col.insert(key(i), key(i)).unwrap();
+		}
+		col.flush().unwrap();
+		let large = col.segments[0].id;
+		let mut next = 2_000u32;
+		for round in 0..3 {
+			for _ in 0..3 {
+				for _ in 0..10 {
+					col.insert(key(next), key(next)).unwrap();
+					next += 1;
+				}
+				col.flush().unwrap();
+			}
+			let (merge_id, dir, col_id, metas) = col.snapshot_for_merge().unwrap().expect("three small segments");
+			assert_eq!(metas.len(), 3, "round {round}");
+			assert!(metas.iter().all(|m| m.id != large));
+			let (merged, metas) =
+				merge_segments_parallel(&dir, col_id, merge_id, metas, col.write_options(), 1, &mut |_| {}).unwrap();
+			col.finish_merge(merged, &metas).unwrap();
+			assert_eq!(col.segments[0].id, large);
+		}
+		// The merged small segments now form the next tier, which still leaves the large one out.
+		assert_eq!(col.segments.len(), 4);
+		let (_, _, _, metas) = col.snapshot_for_merge().unwrap().expect("three merged segments");
+		let ids: Vec<u64> = col.segments[1..].iter().map(|s| s.id).collect();
+		assert_eq!(metas.iter().map(|m| m.id).collect::<Vec<_>>(), ids);
+		col.merging = false;
+		for i in (0..next).step_by(97).chain([next - 1]) {
+			assert_eq!(col.get(&key(i)).unwrap(), Some(key(i)));
+		}
+	}
+
+	#[test]
+	fn background_flush_never_hides_keys() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(2).with_background_flush(true);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		let key = |i: u32| i.to_be_bytes().to_vec();
+		for i in 0u32..200 {
+			col.insert(key(i), key(i * 2)).unwrap();
+			if i % 2 == 1 {
+				assert!(col.flushing.is_some(), "a full memtable is handed off");
+				assert!(col.memtable.is_empty());
+			}
+			for j in (0..=i).step_by(7).chain([i]) {
+				assert_eq!(col.get(&key(j)).unwrap(), Some(key(j * 2)), "key {j} after insert {i}");
+			}
+			assert_eq!(col.keys_with_prefix(&[0, 0], None).unwrap().len(), i as usize + 1);
+		}
+		col.flush().unwrap();
+		assert!(col.flushing.is_none());
+		assert_eq!(col.segments.len(), 100);
+		assert!(col.segments.windows(2).all(|w| w[0].id < w[1].id));
+		drop(col);
+		let col = Column::open(dir.path(), 0, &StoreOptions::new(2)).unwrap();
+		assert_eq!(col.get(&key(199)).unwrap(), Some(key(398)));
+	}
+
+	#[test]
+	fn reopen_never_reuses_segment_ids() {
+		let dir = tempdir().unwrap();
+		{
+			let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+			col.insert(b"k".to_vec(), b"old".to_vec()).unwrap();
+			col.flush().unwrap();
+		}
+		// Re-seed the segment under a high, unpadded id plus an orphaned values file above it, as
+		// a store from before manifests, which the scan picks up.
+		fs::remove_file(manifest_path(dir.path(), 0)).unwrap();
+		let (fst_path, values_path) = segment_paths(dir.path(), 0, 0, None);
+		fs::rename(&fst_path, dir.path().join("col0_seg1000.fst")).unwrap();
+		fs::rename(&values_path, dir.path().join("col0_seg1000.val")).unwrap();
+		fs::write(segment_paths(dir.path(), 0, 2000, None).1, b"").unwrap();
+
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		assert_eq!(col.next_segment_id, 2001);
+		col.insert(b"k".to_vec(), b"new".to_vec()).unwrap();
+		col.flush().unwrap();
+		assert_eq!(col.get(b"k").unwrap(), Some(b"new".to_vec()));
+		col.multi_way_merge(&mut |_| {}).unwrap();
+		drop(col);
+
+		let col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		assert_eq!(col.segments.len(), 1);
+		assert!(col.segments[0].id > 2001);
+		assert_eq!(col.get(b"k").unwrap(), Some(b"new".to_vec()));
+		assert!(!dir.path().join("col0_seg1000.fst").exists(), "non-canonical input must be removed after merge");
+	}
+
+	#[test]
+	fn pipelined_build_writes_the_same_files() {
+		let mut memtable = Memtable::new(MemtableKind::default());
+		for i in 0..PIPELINE_MIN_ROWS as u32 * 2 {
+			memtable.insert(i.to_be_bytes().to_vec(), vec![i as u8; (i % 37) as usize]);
+		}
+		let dir = tempdir().unwrap();
+		// Called directly, as `write_segment` builds on one thread on a single core.
+		let files = |memtable: &Memtable, prefix: LengthPrefix, pipelined: bool| {
+			let (fst_path, values_path) = segment_paths(dir.path(), 0, pipelined as u64, None);
+			let write = WriteOptions { prefix, ..WriteOptions::default() };
+			write_segment_files(&fst_path, &values_path, memtable, write, pipelined)?;
+			Ok::<_, StoreError>((fs::read(fst_path)?, fs::read(values_path)?))
+		};
+		for prefix in [LengthPrefix::U8, LengthPrefix::U32] {
+			assert_eq!(files(&memtable, prefix, true).unwrap(), files(&memtable, prefix, false).unwrap());
+		}
+
+		// A value the length prefix cannot hold fails the pipelined build like the serial one.
+		memtable.insert(vec![0xff], vec![0; 300]);
+		assert!(matches!(files(&memtable, LengthPrefix::U8, true), Err(StoreError::InvalidInput(_))));
+	}
+
+	#[test]
+	fn key_prefix_segments_find_long_keys_sharing_prefixes() {
+		// 16 prefixes of 8 bytes, each shared by 64 keys of 112 bytes.
+		let key = |group: u32, i: u32| {
+			[format!("addr/{group:02}/").as_bytes(), &[b'x'; 100], &i.to_be_bytes()].concat()
+		};
+		let rows = || (0..16).flat_map(move |group| (0..64).map(move |i| (group, i)));
+		// Written again in the second segment, shadowing half of the first.
+		let rewritten = |group: u32, i: u32| i % 2 == 1 || group.is_multiple_of(4);
+		let expected = |group: u32, i: u32| {
+			if rewritten(group, i) { format!("{group}/{i}").into_bytes() } else { b"old".to_vec() }
+		};
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(10_000).with_fst_key_prefix(8).with_bloom_bits_per_key(10.0);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		for (group, i) in rows().filter(|(_, i)| i % 2 == 0) {
+			col.insert(key(group, i), b"old".to_vec()).unwrap();
+		}
+		col.flush().unwrap();
+		for (group, i) in rows().filter(|&(group, i)| rewritten(group, i)) {
+			col.insert(key(group, i), expected(group, i)).unwrap();
+		}
+		col.flush().unwrap();
+		assert_eq!(col.segments.len(), 2);
+		assert!(col.segments.iter().all(|seg| seg.key_prefix == Some(8) && seg.map.len() == 16));
+
+		let check = |col: &Column| {
+			for (group, i) in rows() {
+				assert_eq!(col.get(&key(group, i)).unwrap(), Some(expected(group, i)), "key {group}/{i}");
+			}
+			for missing in [key(3, 64), key(16, 0), b"addr/03/".to_vec(), b"addr".to_vec(), key(3, 7)[..50].to_vec()] {
+				assert_eq!(col.get(&missing).unwrap(), None);
+			}
+			assert_eq!(col.keys_with_prefix(b"addr/0", None).unwrap().len(), 10 * 64);
+			let first_five: Vec<_> = (0..5).map(|i| key(3, i)).collect();
+			assert_eq!(col.keys_with_prefix(b"addr/03/", Some(5)).unwrap(), first_five);
+			let longer = &key(3, 7)[..110];
+			assert_eq!(col.keys_with_prefix(longer, None).unwrap().len(), 64);
+			assert_eq!(col.keys_with_prefix(&key(5, 9), None).unwrap(), vec![key(5, 9)]);
+			assert_eq!(col.first_key().unwrap(), Some(key(0, 0)));
+			assert_eq!(col.last_key().unwrap(), Some(key(15, 63)));
+		};
+		check(&col);
+
+		// The FST holds a prefix where whole keys would need a path of their own.
+		let plain_dir = tempdir().unwrap();
+		let mut plain = Column::open(plain_dir.path(), 0, &StoreOptions::new(10_000)).unwrap();
+		for (group, i) in rows() {
+			plain.insert(key(group, i), expected(group, i)).unwrap();
+		}
+		plain.flush().unwrap();
+		let fst_bytes = |col: &Column| fs::metadata(&col.segments[0].fst_path).unwrap().len();
+		assert!(fst_bytes(&col) * 10 < fst_bytes(&plain), "{} vs {}", fst_bytes(&col), fst_bytes(&plain));
+
+		// gc drops the shadowed records of the older segment and keeps its prefixes.
+		col.gc().unwrap();
+		check(&col);
+		drop(col);
+
+		// A column opened without the option still reads the segments and merges them with a plain
+		// one into a plain segment, and back.
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10_000)).unwrap();
+		check(&col);
+		col.insert(key(2, 2), b"new".to_vec()).unwrap();
+		col.flush().unwrap();
+		col.multi_way_merge(&mut |_| {}).unwrap();
+		assert_eq!((col.segments.len(), col.segments[0].key_prefix), (1, None));
+		assert_eq!(col.get(&key(2, 2)).unwrap(), Some(b"new".to_vec()));
+		drop(col);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		col.insert(key(2, 2), expected(2, 2)).unwrap();
+		col.flush().unwrap();
+		col.multi_way_merge(&mut |_| {}).unwrap();
+		assert_eq!(col.segments[0].key_prefix, Some(8));
+		check(&col);
+		drop(col);
+
+		// A keyed values file cut inside a record is caught by the recovery scan.
+		let values_path = fs::read_dir(dir.path())
+			.unwrap()
+			.map(|e| e.unwrap().path())
+			.find(|path| path.extension() == Some("val".as_ref()))
+			.unwrap();
+		let len = fs::metadata(&values_path).unwrap().len();
+		File::options().write(true).open(&values_path).unwrap().set_len(len - 1).unwrap();
+		let options = options.with_recovery_mode(RecoveryMode::SkipCorrupt);
+		assert!(Column::open(dir.path(), 0, &options).unwrap().segments.is_empty());
+	}
+
+	#[test]
+	fn manifest_lists_live_segments_and_leaves_leftovers_unloaded() {
+		let dir = tempdir().unwrap();
+		{
+			let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+			for value in [b"old", b"new"] {
+				col.insert(b"k".to_vec(), value.to_vec()).unwrap();
+				col.flush().unwrap();
+			}
+			let listed = read_manifest(dir.path(), 0).unwrap().unwrap();
+			assert_eq!(listed.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [0, 1]);
+			col.multi_way_merge(&mut |_| {}).unwrap();
+			let listed = read_manifest(dir.path(), 0).unwrap().unwrap();
+			assert_eq!(listed, [(2, "col0_seg00000000000000000002".to_string())]);
+		}
+		// A complete segment the manifest never took in, like the output of an interrupted merge.
+		let mut stale = Memtable::new(MemtableKind::default());
+		stale.insert(b"k".to_vec(), b"stale".to_vec());
+		write_segment(dir.path(), 0, 3, &stale, WriteOptions::default()).unwrap();
+
+		let col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		assert_eq!(col.segments.iter().map(|s| s.id).collect::<Vec<_>>(), [2]);
+		assert_eq!(col.get(b"k").unwrap(), Some(b"new".to_vec()));
+		assert_eq!(col.next_segment_id, 4);
+		drop(col);
+
+		// A listed segment that lost its files is corrupt, not silently dropped.
+		let (fst_path, _) = segment_paths(dir.path(), 0, 2, None);
+		fs::remove_file(&fst_path).unwrap();
+		let err = Column::open(dir.path(), 0, &StoreOptions::new(10)).err().unwrap();
+		assert!(matches!(err, StoreError::CorruptSegment(ref msg) if msg.contains("manifest lists missing")), "{err}");
+		let options = StoreOptions::new(10).with_recovery_mode(RecoveryMode::SkipCorrupt);
+		assert!(Column::open(dir.path(), 0, &options).unwrap().segments.is_empty());
+	}
+
+	#[test]
+	fn flush_refuses_a_segment_id_that_already_has_files() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		let mut stale = Memtable::new(MemtableKind::default());
+		stale.insert(b"k".to_vec(), b"stale".to_vec());
+		write_segment(dir.path(), 0, 0, &stale, WriteOptions::default()).unwrap();
+		let stale_len = fs::metadata(segment_paths(dir.path(), 0, 0, None).1).unwrap().len();
+
+		col.insert(b"k".to_vec(), b"fresh".to_vec()).unwrap();
+		let err = col.flush().err().unwrap();
+		assert!(matches!(err, StoreError::SegmentIdInUse(ref msg) if msg.contains("segment 0")), "{err}");
+		assert_eq!(fs::metadata(segment_paths(dir.path(), 0, 0, None).1).unwrap().len(), stale_len, "left untouched");
+		assert_eq!(col.get(b"k").unwrap(), Some(b"fresh".to_vec()), "rows stay in the memtable");
+
+		// The refused id is burned, so the retry lands on the next one.
+		col.flush().unwrap();
+		assert_eq!(col.segments.iter().map(|s| s.id).collect::<Vec<_>>(), [1]);
+		assert_eq!(col.get(b"k").unwrap(), Some(b"fresh".to_vec()));
+	}
+
+	#[test]
+	fn sharded_segments_reload_alongside_flat_ones() {
+		let dir = tempdir().unwrap();
+		let insert_flush = |col: &mut Column, key: &[u8]| {
+			col.insert(key.to_vec(), b"value".to_vec()).unwrap();
+			col.flush().unwrap();
+		};
+		{
+			let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+			for key in [b"a", b"b"] {
+				insert_flush(&mut col, key);
+			}
+		}
+		let options = StoreOptions::new(10).with_segments_per_dir(2);
+		{
+			let mut col = Column::open(dir.path(), 0, &options).unwrap();
+			for key in [b"c", b"d", b"e"] {
+				insert_flush(&mut col, key);
+			}
+		}
+		let shard = |n: u64| dir.path().join(format!("col0_shard{n:020}"));
+		assert!(shard(1).join("col0_seg00000000000000000002.fst").is_file());
+		assert!(shard(1).join("col0_seg00000000000000000003.val").is_file());
+		assert!(shard(2).join("col0_seg00000000000000000004.fst").is_file());
+		assert!(!segment_paths(dir.path(), 0, 2, None).0.exists());
+		let listed = read_manifest(dir.path(), 0).unwrap().unwrap();
+		assert_eq!(listed[4], (4, format!("col0_shard{:020}/col0_seg{:020}", 2, 4)));
+
+		let keys = [b"a", b"b", b"c", b"d", b"e"];
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		assert_eq!(col.segments.iter().map(|s| s.id).collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+		assert!(keys.iter().all(|key| col.get(*key).unwrap() == Some(b"value".to_vec())));
+		assert_eq!(col.next_segment_id, 5);
+		col.multi_way_merge(&mut |_| {}).unwrap();
+		assert!(shard(2).join("col0_seg00000000000000000005.fst").is_file());
+		assert!(!shard(1).join("col0_seg00000000000000000002.fst").exists());
+		drop(col);
+
+		// Without a manifest the scan finds the merged segment in its shard; a flat open reads it too.
+		fs::remove_file(manifest_path(dir.path(), 0)).unwrap();
+		let col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		assert_eq!(col.segments.iter().map(|s| s.id).collect::<Vec<_>>(), [5]);
+		assert!(keys.iter().all(|key| col.get(*key).unwrap() == Some(b"value".to_vec())));
+		assert_eq!(col.next_segment_id, 6);
+	}
+
+	#[test]
+	fn store_without_manifest_falls_back_to_scanning() {
+		let dir = tempdir().unwrap();
+		{
+			let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+			for value in [b"old", b"new"] {
+				col.insert(b"k".to_vec(), value.to_vec()).unwrap();
+				col.flush().unwrap();
+			}
+		}
+		fs::remove_file(manifest_path(dir.path(), 0)).unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		assert_eq!(col.segments.iter().map(|s| s.id).collect::<Vec<_>>(), [0, 1]);
+		assert_eq!(col.get(b"k").unwrap(), Some(b"new".to_vec()));
+		assert!(read_manifest(dir.path(), 0).unwrap().is_none(), "opening alone writes nothing");
+		col.insert(b"j".to_vec(), b"v".to_vec()).unwrap();
+		col.flush().unwrap();
+		assert_eq!(read_manifest(dir.path(), 0).unwrap().unwrap().len(), 3);
+
+		fs::write(manifest_path(dir.path(), 0), "not a manifest").unwrap();
+		assert!(Column::open(dir.path(), 0, &StoreOptions::new(10)).is_err());
+		let options = StoreOptions::new(10).with_recovery_mode(RecoveryMode::SkipCorrupt);
+		assert_eq!(Column::open(dir.path(), 0, &options).unwrap().segments.len(), 3);
+	}
+
+	#[test]
+	fn skip_corrupt_recovery_ignores_torn_segments() {
+		let dir = tempdir().unwrap();
+		{
+			let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+			for key in [b"a", b"b", b"c"] {
+				col.insert(key.to_vec(), b"value".to_vec()).unwrap();
+				col.flush().unwrap();
+			}
+		}
+		// Segment 1 lost the tail of its values file, segment 2 never got its FST written.
+		let values_path = segment_paths(dir.path(), 0, 1, None).1;
+		File::options().write(true).open(&values_path).unwrap().set_len(3).unwrap();
+		File::create(segment_paths(dir.path(), 0, 2, None).0).unwrap();
+
+		assert!(Column::open(dir.path(), 0, &StoreOptions::new(10)).is_err());
+
+		let options = StoreOptions::new(10).with_recovery_mode(RecoveryMode::SkipCorrupt);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		assert_eq!(col.segments.iter().map(|s| s.id).collect::<Vec<_>>(), vec![0]);
+		assert_eq!(col.get(b"a").unwrap(), Some(b"value".to_vec()));
+		assert_eq!(col.get(b"b").unwrap(), None);
+		assert_eq!(col.get(b"c").unwrap(), None);
+		assert_eq!(col.next_segment_id, 3);
+		col.insert(b"d".to_vec(), b"value".to_vec()).unwrap();
+		col.flush().unwrap();
+		assert_eq!(col.get(b"d").unwrap(), Some(b"value".to_vec()));
+	}
+
+	#[test]
+	fn merge_prefers_highest_segment_id_for_shared_key() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10)).unwrap();
+		for value in [b"old", b"mid", b"new"] {
+			col.insert(b"k".to_vec(), value.to_vec()).unwrap();
+			col.insert(value.to_vec(), value.to_vec()).unwrap();
+			col.flush().unwrap();
+		}
+		let metas: Vec<SegmentMeta> = col.segments.iter().map(Segment::meta).collect();
+		assert_eq!(metas.iter().map(|m| m.id).collect::<Vec<_>>(), vec![0, 1, 2]);
+		let write = WriteOptions::default();
+		// Input order must not matter: the merge orders inputs by id itself.
+		let shuffled = vec![metas[2].clone(), metas[0].clone(), metas[1].clone()];
+		let (merged, _) = merge_segments(dir.path(), 0, 10, shuffled, write, &mut |_| {}).unwrap();
+		assert_eq!(merged.read_value(merged.map.get(b"k").unwrap()).unwrap(), b"new");
+		assert_eq!(merged.map.len(), 4);
+
+		// Re-merging the newer two under an id above both inputs keeps that output ahead of segment 0.
+		let (newer, _) = merge_segments(dir.path(), 0, 11, metas[1..].to_vec(), write, &mut |_| {}).unwrap();
+		let (remerged, _) = merge_segments(dir.path(), 0, 12, vec![newer.meta(), metas[0].clone()], write, &mut |_| {}).unwrap();
+		assert_eq!(remerged.read_value(remerged.map.get(b"k").unwrap()).unwrap(), b"new");
+	}
+
+	#[test]
+	fn parallel_merge_matches_sequential_merge() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10_000)).unwrap();
+		// Nine overlapping segments: key i is rewritten by every segment `seg` with i % (seg + 1) == 0.
+		for seg in 0..9u64 {
+			for i in (0..500u64).filter(|i| i % (seg + 1) == 0) {
+				col.insert(i.to_be_bytes().to_vec(), seg.to_le_bytes().to_vec()).unwrap();
+			}
+			col.flush().unwrap();
+		}
+		let metas: Vec<SegmentMeta> = col.segments.iter().map(Segment::meta).collect();
+		let write = WriteOptions::default();
+		let (sequential, _) = merge_segments(dir.path(), 0, 100, metas.clone(), write, &mut |_| {}).unwrap();
+		for threads in [2, 4, 8] {
+			let new_id = 100 + threads as u64;
+			let (parallel, inputs) =
+				merge_segments_parallel(dir.path(), 0, new_id, metas.clone(), write, threads, &mut |_| {}).unwrap();
+			assert_eq!(inputs.len(), metas.len());
+			assert_eq!(fs::read(&parallel.fst_path).unwrap(), fs::read(&sequential.fst_path).unwrap());
+			assert_eq!(fs::read(&parallel.values_path).unwrap(), fs::read(&sequential.values_path).unwrap());
+		}
+		let newest = sequential.map.get(12u64.to_be_bytes()).unwrap();
+		assert_eq!(sequential.read_value(newest).unwrap(), 5u64.to_le_bytes());
+		let leftovers = fs::read_dir(dir.path())
+			.unwrap()
+			.filter(|e| e.as_ref().unwrap().file_name().to_string_lossy().contains("_part"))
+			.count();
+		assert_eq!(leftovers, 0, "part files must be removed");
+	}
+
+	#[test]
+	fn every_length_prefix_roundtrips_and_merges_with_others() {
+		let dir = tempdir().unwrap();
+		let widths = [LengthPrefix::U8, LengthPrefix::U16, LengthPrefix::U32];
+		for (seg, prefix) in widths.into_iter().enumerate() {
+			let mut col = Column::open(dir.path(), 0, &StoreOptions::new(100).with_value_prefix(prefix)).unwrap();
+			let longest = prefix.max_len().min(1 << 20);
+			for (i, len) in [0, 1, 255, longest].into_iter().enumerate() {
+				col.insert(vec![seg as u8, i as u8], vec![i as u8; len]).unwrap();
+			}
+			col.flush().unwrap();
+			assert_eq!(col.segments.last().unwrap().prefix, prefix);
+			assert_eq!(col.get(&[seg as u8, 3]).unwrap(), Some(vec![3; longest]));
+			if prefix != LengthPrefix::U32 {
+				assert!(col.insert(b"big".to_vec(), vec![0; prefix.max_len() + 1]).is_err());
+			}
+		}
+		// Segments of all three widths merge into one that uses the column's current width.
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(100).with_value_prefix(LengthPrefix::U32)).unwrap();
+		assert_eq!(col.segments.len(), 3);
+		col.multi_way_merge(&mut |_| {}).unwrap();
+		assert_eq!(col.segments.len(), 1);
+		for (seg, prefix) in widths.into_iter().enumerate() {
+			assert_eq!(col.get(&[seg as u8, 0]).unwrap(), Some(vec![]));
+			assert_eq!(col.get(&[seg as u8, 2]).unwrap(), Some(vec![2; 255]));
+			assert_eq!(col.get(&[seg as u8, 3]).unwrap(), Some(vec![3; prefix.max_len().min(1 << 20)]));
+		}
+	}
+
+	#[test]
+	fn value_offsets_follow_bytes_written_at_the_prefix_boundary() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("boundary.val");
+		let mut writer = ValueWriter::create(&path, LengthPrefix::U8, None).unwrap();
+		let max = LengthPrefix::U8.max_len();
+		let first = writer.append(&vec![1; max]).unwrap();
+		assert!(writer.append(&vec![2; max + 1]).is_err());
+		let second = writer.append(&[3]).unwrap();
+		assert_eq!(first, VALUES_HEADER_BYTES);
+		assert_eq!(second, VALUES_HEADER_BYTES + 1 + max as u64);
+		let file = writer.writer.into_inner().map_err(|e| e.into_error()).unwrap();
+		assert_eq!(file.metadata().unwrap().len(), second + 2);
+		drop(file);
+		assert_eq!(read_value_from_path(&path, LengthPrefix::U8, first).unwrap(), vec![1; max]);
+		assert_eq!(read_value_from_path(&path, LengthPrefix::U8, second).unwrap(), vec![3]);
+	}
+
+	#[test]
+	fn mapped_values_read_like_the_file() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(1_000).with_value_prefix(LengthPrefix::U16).with_mmap_values(1 << 20);
+		let mut col = Column::open(dir.path(), 0, &options).unwrap();
+		for i in 0..200u32 {
+			col.insert(i.to_be_bytes().to_vec(), vec![i as u8; i as usize * 7]).unwrap();
+		}
+		col.flush().unwrap();
+		let seg = &col.segments[0];
+		assert!(seg.values.is_some());
+		let mut stream = seg.map.stream();
+		while let Some((_, offset)) = stream.next() {
+			let mapped = seg.read_value(offset).unwrap();
+			assert_eq!(mapped, read_value_from_path(&seg.values_path, seg.prefix, offset).unwrap());
+		}
+		let end = fs::metadata(&seg.values_path).unwrap().len();
+		assert!(seg.read_value(end).is_err());
+		assert!(read_value_from_path(&seg.values_path, seg.prefix, end).is_err());
+		drop(col);
+
+		// A values file over the limit falls back to reading the file.
+		let col = Column::open(dir.path(), 0, &options.clone().with_mmap_values(64)).unwrap();
+		assert!(col.segments[0].values.is_none());
+		assert_eq!(col.get(&199u32.to_be_bytes()).unwrap(), Some(vec![199; 199 * 7]));
+		let col = Column::open(dir.path(), 0, &options).unwrap();
+		assert!(col.segments[0].values.is_some());
+		assert_eq!(col.get(&199u32.to_be_bytes()).unwrap(), Some(vec![199; 199 * 7]));
+	}
+
+	#[test]
+	fn keys_with_prefix_dedupes_from_segments() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(1)).unwrap();
+		col.insert(b"p1".to_vec(), vec![]).unwrap();
+		col.insert(b"p2".to_vec(), vec![]).unwrap();
+		col.insert(b"p1".to_vec(), vec![]).unwrap(); // newer duplicate
+		col.flush().unwrap();
+		let keys = col.keys_with_prefix(b"p", None).unwrap();
+		assert_eq!(keys, vec![b"p1".to_vec(), b"p2".to_vec()]);
+	}
+
+	#[test]
+	fn keys_with_prefix_limit_keeps_the_smallest_distinct_keys() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(64)).unwrap();
+		let key = |i: u64| [b"k".as_slice(), &i.to_be_bytes()].concat();
+		// 7 is coprime to 500, so segments and the memtable each hold keys from all over the range.
+		for i in (0..500).map(|i| (i * 7) % 500) {
+			col.insert(key(i), vec![]).unwrap();
+		}
+		for i in 0..20 {
+			col.insert(key(i), vec![1]).unwrap();
+			col.insert([b"j".as_slice(), &i.to_be_bytes()].concat(), vec![]).unwrap();
+		}
+		assert!(col.segments.len() > 1 && !col.memtable.is_empty());
+
+		let limited = col.keys_with_prefix(b"k", Some(10)).unwrap();
+		assert_eq!(limited, (0..10).map(key).collect::<Vec<_>>());
+		assert_eq!(col.keys_with_prefix(b"k", Some(1_000)).unwrap().len(), 500);
+		assert_eq!(col.keys_with_prefix(b"k", None).unwrap().len(), 500);
+		assert!(col.keys_with_prefix(b"k", Some(0)).unwrap().is_empty());
+	}
+
+	#[test]
+	fn memtable_kinds_flush_identical_segments() {
+		// 7919 is coprime to 1000, so the random order is a permutation; every key is written
+		// twice so both kinds must keep the last write.
+		let sequential: Vec<u64> = (0..1000).collect();
+		let random: Vec<u64> = (0..1000).map(|i| (i * 7919) % 1000).collect();
+		for order in [sequential, random] {
+			let mut flushed = Vec::new();
+			for kind in [MemtableKind::BTree, MemtableKind::SortedVec] {
+				let dir = tempdir().unwrap();
+				let mut col = Column::open(dir.path(), 0, &StoreOptions::new(10_000).with_memtable(kind)).unwrap();
+				for round in 0..2u8 {
+					for i in &order {
+						col.insert(i.to_be_bytes().to_vec(), vec![round; (*i % 7) as usize]).unwrap();
+					}
+				}
+				assert_eq!(col.get(&5u64.to_be_bytes()).unwrap(), Some(vec![1; 5]));
+				assert_eq!(col.keys_with_prefix(&[0, 0, 0, 0, 0, 0, 0], None).unwrap().len(), 256);
+				col.flush().unwrap();
+				let (fst_path, values_path) = segment_paths(dir.path(), 0, 0, None);
+				flushed.push((fs::read(fst_path).unwrap(), fs::read(values_path).unwrap()));
+			}
+			assert!(flushed[0] == flushed[1], "memtable kinds flushed different segments");
+		}
+	}
+
+	#[test]
+	fn merge_reports_progress_per_batch() {
+		let rows = 2 * MERGE_PROGRESS_ROWS + 100;
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 0, &StoreOptions::new(rows as usize)).unwrap();
+		for i in 0..rows {
+			col.insert(i.to_be_bytes().to_vec(), vec![]).unwrap();
+		}
+		col.flush().unwrap();
+		// Every other key is overwritten in a newer segment; merged rows count it once.
+		for i in (0..rows).step_by(2) {
+			col.insert(i.to_be_bytes().to_vec(), vec![2]).unwrap();
+		}
+		col.flush().unwrap();
+		assert_eq!(col.segments.len(), 2);
+
+		let mut calls = Vec::new();
+		col.multi_way_merge(&mut |n| calls.push(n)).unwrap();
+		assert_eq!(calls, vec![MERGE_PROGRESS_ROWS, MERGE_PROGRESS_ROWS, 100]);
+	}
+
+	#[test]
+	fn insert_rejects_empty_and_oversized_keys() {
+		let dir = tempdir().unwrap();
+		let mut col = Column::open(dir.path(), 3, &StoreOptions::new(1)).unwrap();
+		for key in [Vec::new(), vec![7u8; MAX_KEY_BYTES + 1]] {
+			match col.insert(key, b"v".to_vec()) {
+				Err(StoreError::InvalidInput(msg)) => assert!(msg.starts_with("col3:"), "{msg}"),
+				other => panic!("expected InvalidInput, got {other:?}"),
+			}
+		}
+		assert!(col.segments.is_empty());
+		assert!(col.insert(vec![7u8; MAX_KEY_BYTES], b"v".to_vec()).unwrap());
+		assert_eq!(col.get(&[7u8; MAX_KEY_BYTES]).unwrap(), Some(b"v".to_vec()));
 	}
 }
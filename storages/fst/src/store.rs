@@ -1,9 +1,19 @@
-use core::store_interface::{ProgressTracker, StoreCodec, StoreRead, StoreWrite};
-use std::{fs, io, marker::PhantomData, path::Path, sync::{Arc, RwLock}};
+use core::dyn_store::BenchError;
+use core::inspect::KeyDump;
+use core::store_builder::{BuilderOptions, Durability};
+use core::store_interface::{
+	CommitStats, ConsistencyReport, ExpiringStore, FlushStats, Mismatch, OpenMode, ProgressTracker, Retryable,
+	StoreCodec, StoreRead, StoreWrite, NEVER_EXPIRES, check_columns, check_expiring, check_indexed_value, check_layout,
+	column_id, coalesce_by_encoded_key, composite_key, composite_prefix, decode_exact, expiry_after, is_transient_io,
+	sort_by_encoded_key, sort_by_encoding, split_composite, stored_value, unexpired, unix_millis,
+};
+use std::{fs, io, marker::PhantomData, path::{Path, PathBuf}, sync::{Arc, Mutex, RwLock}, time::{Duration, Instant}};
 
 pub type StoreResult<T> = Result<T, StoreError>;
-use crate::compactor::Compactor;
-use crate::segment::Column;
+use crate::compactor::{CompactionStats, Compactor, SharedCompactionStats};
+use crate::memtable::MemtableKind;
+use crate::segment::{write_manifest, Column, ColumnStats, LengthPrefix, ReadCounters};
+use crate::wal::{Wal, WalOp};
 
 #[derive(Debug)]
 pub enum StoreError {
@@ -11,6 +21,8 @@ pub enum StoreError {
 	Fst(fst::Error),
 	InvalidInput(String),
 	CorruptSegment(String),
+	/// A new segment drew an id whose files already exist; nothing was written over them.
+	SegmentIdInUse(String),
 }
 
 impl std::fmt::Display for StoreError {
@@ -20,12 +32,27 @@ impl std::fmt::Display for StoreError {
 			StoreError::Fst(err) => write!(f, "fst error: {err}"),
 			StoreError::InvalidInput(msg) => write!(f, "invalid input: {msg}"),
 			StoreError::CorruptSegment(msg) => write!(f, "corrupt segment: {msg}"),
+			StoreError::SegmentIdInUse(msg) => write!(f, "segment id in use: {msg}"),
 		}
 	}
 }
 
 impl std::error::Error for StoreError {}
 
+/// Commits take column locks instead of conflicting, so only an interrupted or timed out io call
+/// is worth trying again.
+impl Retryable for StoreError {
+	fn is_retryable(&self) -> bool {
+		matches!(self, StoreError::Io(err) if is_transient_io(err))
+	}
+}
+
+impl From<StoreError> for BenchError {
+	fn from(err: StoreError) -> Self {
+		BenchError::store(err)
+	}
+}
+
 impl From<io::Error> for StoreError {
 	fn from(err: io::Error) -> Self {
 		StoreError::Io(err)
@@ -65,6 +92,16 @@ impl Layout {
 		}
 	}
 
+	/// Name the store's marker records at creation, so a reopen with another layout fails.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Layout::Plain { .. } => "plain",
+			Layout::UniqueIndex { .. } => "unique_index",
+			Layout::Range { .. } => "range",
+			Layout::Dictionary { .. } => "dictionary",
+		}
+	}
+
 	fn column_count(&self) -> usize {
 		match self {
 			Layout::Plain { .. } => 1,
@@ -73,27 +110,289 @@ impl Layout {
 			Layout::Dictionary { .. } => 4,
 		}
 	}
+
+	/// The column ids the layout uses, which name its `col<id>` files.
+	fn columns(&self) -> Vec<u8> {
+		match *self {
+			Layout::Plain { key_to_value } => vec![key_to_value],
+			Layout::UniqueIndex { key_to_value, value_to_key } => vec![key_to_value, value_to_key],
+			Layout::Range { key_to_value, value_key_btree } => vec![key_to_value, value_key_btree],
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
+				vec![key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree]
+			}
+		}
+	}
+}
+
+/// How opening a column treats a segment that cannot be loaded, e.g. one torn by a crash in the
+/// middle of a flush or merge.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecoveryMode {
+	/// The open fails.
+	#[default]
+	Strict,
+	/// Every segment is verified on open and incomplete ones are skipped. Their files stay on disk
+	/// and keep their ids reserved.
+	SkipCorrupt,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct StoreOptions {
 	pub segment_size: usize,
+	pub memtable: MemtableKind,
+	/// fsync segment files (and their directory) when flushing or merging, making `flush` durable.
+	pub sync_on_flush: bool,
+	pub recovery_mode: RecoveryMode,
+	/// Length prefix of values in newly written segments; existing ones keep theirs.
+	pub value_prefix: LengthPrefix,
+	/// Threads for the first pass of a tree merge; 1 merges all segments in a single pass.
+	pub merge_threads: usize,
+	/// Segments a column accumulates before the background compactor merges them into one.
+	pub merge_threshold: usize,
+	/// Size-tiered merging: the compactor only takes the newest segments whose sizes lie within
+	/// this ratio of each other, and waits until `merge_threshold` of them pile up, so a large
+	/// segment is not rewritten for every few small ones. `None` merges all segments at once.
+	pub merge_size_ratio: Option<f64>,
+	/// Build full memtables into segments on a separate thread while commits fill a fresh one.
+	/// `flush` still waits for the segment, so it stays a durability point.
+	pub background_flush: bool,
+	/// Skip writes a later one in the same commit overwrites, so the memtable sees each key once.
+	pub coalesce_duplicates: bool,
+	/// Segment values files up to this size are memory-mapped, so a lookup reads its value without
+	/// a syscall; larger ones are opened per read. 0, the default, maps none.
+	pub mmap_values_max_bytes: u64,
+	/// `Plain` only: keep each value behind its expiry for `ExpiringStore`. A store must always
+	/// be opened with the setting it was created with.
+	pub expiring: bool,
+	/// Log every commit before it reaches the memtables and replay the log on open, so a crash
+	/// loses no commit and never leaves one in some columns only. Without it a crash drops the
+	/// memtables, which columns flush at different points.
+	pub wal: bool,
+	/// Directory of the write-ahead log, e.g. on a faster device; `None` keeps it in the store's.
+	/// Every store needs a directory of its own.
+	pub wal_dir: Option<PathBuf>,
+	/// Count `get`s and the segments they probe into `Store::column_stats`. Off by default, as
+	/// concurrent readers would contend on the counters.
+	pub count_reads: bool,
+	/// Bits per key of the bloom filter built for every new segment, in a `.bloom` file next to
+	/// it; a `get` skips the segments whose filter rules its key out. 10 bits cost 1.25 bytes a
+	/// key for ~1% false positives. `None`, the default, builds none.
+	pub bloom_bits_per_key: Option<f64>,
+	/// Adaptive segment size: every column measures the average key + value size of its inserts
+	/// and moves its flush threshold to the rows of that size fitting this many bytes, within
+	/// `MAX_SEGMENT_SIZE_ADJUST` of `segment_size`. `None`, the default, keeps `segment_size`.
+	pub memtable_budget_bytes: Option<usize>,
+	/// Flush the memtables when the store is dropped, so commits a caller never flushed are not
+	/// lost with them. On by default; the drop then costs a flush of every column, which off
+	/// leaves to `flush` and, for unflushed commits, to replaying the write-ahead log on open.
+	pub flush_on_drop: bool,
+	/// Build a flushed segment's FST while a second thread writes its values file, for memtables
+	/// of at least 4096 rows on more than one core; the files are the same as those of the
+	/// one-thread build. On by default.
+	pub pipelined_flush: bool,
+	/// Index new segments' FSTs by the first this many bytes of each key rather than the whole
+	/// key, and keep the full keys in the values file next to their values. For long keys that
+	/// share few distinct prefixes the FST shrinks to one entry per prefix; a `get` then scans the
+	/// records under its prefix for its key. `None`, the default, indexes whole keys. Segments
+	/// record their setting, so a store reads and merges both kinds whatever it is opened with.
+	pub fst_key_prefix: Option<u8>,
+	/// Put new segment files into `col<id>_shard<n>` subdirectories of this many segment ids each,
+	/// so a column that piles up many small segments before compaction keeps directory listings
+	/// short. `None`, the default, writes them next to each other. Opening scans both layouts, so
+	/// a store can switch either way; shards emptied by merges are left in place.
+	pub segments_per_dir: Option<u64>,
+	/// Segments a column may hold before the compactor reports it fell behind the flushes, in
+	/// `CompactionStats::backlog_warnings` and on stderr. `None`, the default, allows
+	/// `BACKLOG_WARNING_FACTOR` times `merge_threshold`; size-tiered merging keeps more around.
+	pub segment_backlog_warning: Option<usize>,
+	/// Compactor threads. A column merges on one of them at a time, so more than one lets the
+	/// columns of a layout merge side by side; 1, the default, merges them in turn.
+	pub compaction_workers: usize,
+}
+
+/// Named tuning presets for `StoreOptions::profile`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Workload {
+	/// Segments of ~1/8 of the rows, merged once 16 pile up, into a `SortedVec` memtable; no fsync.
+	/// Flushes and merges are rare and big, and appending ascending keys is a push.
+	WriteHeavy,
+	/// The defaults: segments of ~1/32 of the rows, merged once 4 pile up, `BTree` memtable; no fsync.
+	Balanced,
+	/// Segments of ~1/128 of the rows, merged as soon as there are 2, `BTree` memtable, fsync on
+	/// flush. Lookups probe few segments and a flushed write survives a crash.
+	ReadHeavy,
 }
 
 impl Default for StoreOptions {
 	fn default() -> Self {
-		Self { segment_size: MIN_SEGMENT_ROWS }
+		Self::new(MIN_SEGMENT_ROWS)
+	}
+}
+
+impl BuilderOptions for StoreOptions {
+	fn set_coalesce_duplicates(&mut self, coalesce: bool) {
+		self.coalesce_duplicates = coalesce;
+	}
+
+	fn set_segment_size(&mut self, rows: usize) {
+		self.segment_size = rows;
+	}
+
+	fn set_durability(&mut self, durability: Durability) {
+		self.sync_on_flush = durability == Durability::Synced;
+	}
+
+	fn set_wal_dir(&mut self, dir: &Path) {
+		self.wal_dir = Some(dir.to_path_buf());
+	}
+
+	fn set_flush_on_drop(&mut self, flush: bool) {
+		self.flush_on_drop = flush;
 	}
 }
 
 impl StoreOptions {
 	pub fn new(segment_size: usize) -> Self {
-		Self { segment_size }
+		Self {
+			segment_size,
+			memtable: MemtableKind::default(),
+			sync_on_flush: false,
+			recovery_mode: RecoveryMode::default(),
+			value_prefix: LengthPrefix::default(),
+			merge_threads: 1,
+			merge_threshold: DEFAULT_MERGE_THRESHOLD,
+			merge_size_ratio: None,
+			background_flush: false,
+			coalesce_duplicates: false,
+			mmap_values_max_bytes: 0,
+			expiring: false,
+			wal: true,
+			wal_dir: None,
+			count_reads: false,
+			bloom_bits_per_key: None,
+			memtable_budget_bytes: None,
+			flush_on_drop: true,
+			pipelined_flush: true,
+			fst_key_prefix: None,
+			segments_per_dir: None,
+			segment_backlog_warning: None,
+			compaction_workers: 1,
+		}
 	}
 
 	pub fn from_estimates(approx_rows: u64, avg_kv_bytes: usize, mem_budget_bytes: usize) -> Self {
-		let segment_size = compute_segment_size(approx_rows, avg_kv_bytes, mem_budget_bytes);
-		Self { segment_size }
+		Self::new(compute_segment_size(approx_rows, avg_kv_bytes, mem_budget_bytes))
+	}
+
+	/// Preset for `workload`; the segment size assumes `PROFILE_AVG_KV_BYTES` per row against the
+	/// default memtable budget.
+	pub fn profile(workload: Workload, approx_rows: u64) -> Self {
+		let (target_segments, merge_threshold, memtable, sync_on_flush) = match workload {
+			Workload::WriteHeavy => (8, 16, MemtableKind::SortedVec, false),
+			Workload::Balanced => (TARGET_MAX_SEGMENTS, DEFAULT_MERGE_THRESHOLD, MemtableKind::BTree, false),
+			Workload::ReadHeavy => (128, 2, MemtableKind::BTree, true),
+		};
+		let segment_size =
+			segment_size_for(approx_rows, target_segments, PROFILE_AVG_KV_BYTES, DEFAULT_MEMTABLE_BUDGET_BYTES);
+		Self::new(segment_size)
+			.with_merge_threshold(merge_threshold)
+			.with_memtable(memtable)
+			.with_sync_on_flush(sync_on_flush)
+	}
+
+	pub fn with_memtable(self, memtable: MemtableKind) -> Self {
+		Self { memtable, ..self }
+	}
+
+	pub fn with_sync_on_flush(self, sync_on_flush: bool) -> Self {
+		Self { sync_on_flush, ..self }
+	}
+
+	pub fn with_recovery_mode(self, recovery_mode: RecoveryMode) -> Self {
+		Self { recovery_mode, ..self }
+	}
+
+	pub fn with_value_prefix(self, value_prefix: LengthPrefix) -> Self {
+		Self { value_prefix, ..self }
+	}
+
+	pub fn with_merge_threads(self, merge_threads: usize) -> Self {
+		Self { merge_threads: merge_threads.max(1), ..self }
+	}
+
+	pub fn with_background_flush(self, background_flush: bool) -> Self {
+		Self { background_flush, ..self }
+	}
+
+	pub fn with_coalesce_duplicates(self, coalesce_duplicates: bool) -> Self {
+		Self { coalesce_duplicates, ..self }
+	}
+
+	pub fn with_mmap_values(self, mmap_values_max_bytes: u64) -> Self {
+		Self { mmap_values_max_bytes, ..self }
+	}
+
+	pub fn with_expiring(self, expiring: bool) -> Self {
+		Self { expiring, ..self }
+	}
+
+	pub fn with_wal(self, wal: bool) -> Self {
+		Self { wal, ..self }
+	}
+
+	pub fn with_wal_dir(self, wal_dir: impl AsRef<Path>) -> Self {
+		Self { wal_dir: Some(wal_dir.as_ref().to_path_buf()), ..self }
+	}
+
+	pub fn with_count_reads(self, count_reads: bool) -> Self {
+		Self { count_reads, ..self }
+	}
+
+	pub fn with_bloom_bits_per_key(self, bits_per_key: f64) -> Self {
+		Self { bloom_bits_per_key: Some(bits_per_key), ..self }
+	}
+
+	/// Turns on the adaptive segment size against a memtable budget of `mem_budget_bytes` per
+	/// column, for when the average row size is only a guess.
+	pub fn with_adaptive_segment_size(self, mem_budget_bytes: usize) -> Self {
+		Self { memtable_budget_bytes: Some(mem_budget_bytes.max(1)), ..self }
+	}
+
+	pub fn with_flush_on_drop(self, flush_on_drop: bool) -> Self {
+		Self { flush_on_drop, ..self }
+	}
+
+	pub fn with_pipelined_flush(self, pipelined_flush: bool) -> Self {
+		Self { pipelined_flush, ..self }
+	}
+
+	/// At least 1 byte.
+	pub fn with_fst_key_prefix(self, len: u8) -> Self {
+		Self { fst_key_prefix: Some(len.max(1)), ..self }
+	}
+
+	/// At least 1.
+	pub fn with_segments_per_dir(self, segments_per_dir: u64) -> Self {
+		Self { segments_per_dir: Some(segments_per_dir.max(1)), ..self }
+	}
+
+	pub fn with_segment_backlog_warning(self, segments: usize) -> Self {
+		Self { segment_backlog_warning: Some(segments), ..self }
+	}
+
+	/// At least 1.
+	pub fn with_compaction_workers(self, workers: usize) -> Self {
+		Self { compaction_workers: workers.max(1), ..self }
+	}
+
+	/// At least 2, so a merge always combines segments.
+	pub fn with_merge_threshold(self, merge_threshold: usize) -> Self {
+		Self { merge_threshold: merge_threshold.max(2), ..self }
+	}
+
+	/// Ratios below 1 would admit no run at all and are raised to 1.
+	pub fn with_merge_size_ratio(self, ratio: f64) -> Self {
+		Self { merge_size_ratio: Some(ratio.max(1.0)), ..self }
 	}
 }
 
@@ -105,62 +404,198 @@ where
 	layout: Layout,
 	columns: Vec<Arc<RwLock<Column>>>,
 	compactor: Compactor,
-	progress: Option<ProgressTracker>,
+	compaction_stats: SharedCompactionStats,
+	read_only: bool,
+	coalesce_duplicates: bool,
+	expiring: bool,
+	flush_on_drop: bool,
+	/// `None` for read-only stores and with `StoreOptions::wal` off.
+	wal: Option<Mutex<Wal>>,
+	/// Held shared by a commit from logging it until it is in the memtables, and exclusively by a
+	/// flush, so resetting the log never drops a commit that has not reached them yet.
+	commit_gate: RwLock<()>,
+	/// Held by a `Dictionary` commit throughout, so two commits cannot both give one new value a
+	/// birth key.
+	birth_keys: Mutex<()>,
+	progress: Mutex<Option<ProgressTracker>>,
 	_ph: PhantomData<(K, V, KC, VC)>,
 }
 
+/// A `Store` shared across threads: every read takes `&self`, and the store is `Send + Sync` for
+/// `Send + Sync` key and value types. Columns sit behind their own `RwLock`s and `commit` takes
+/// `&self` as well, so writers can share the handle too.
+pub type SharedStore<K, V, KC, VC> = Arc<Store<K, V, KC, VC>>;
+
 impl<K, V, KC, VC> Store<K, V, KC, VC>
 where
 	KC: StoreCodec<K, Error = StoreError>,
 	VC: StoreCodec<V, Error = StoreError>,
 {
-	pub fn open(path: &Path, layout: Layout, options: StoreOptions) -> StoreResult<Self> {
+	/// `open_with_options` with `StoreOptions::default()`, segments of `MIN_SEGMENT_ROWS` rows.
+	pub fn open(path: &Path, layout: Layout) -> StoreResult<Self> {
+		Self::open_with_options(path, layout, StoreOptions::default())
+	}
+
+	pub fn open_with_options(path: &Path, layout: Layout, options: StoreOptions) -> StoreResult<Self> {
 		if options.segment_size == 0 {
 			return Err(StoreError::InvalidInput("segment_size must be > 0".into()))
 		}
+		// Before anything is written, so a rejected open leaves no store behind.
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
 		if !path.exists() {
 			fs::create_dir_all(path)?;
 		}
+		if !check_marker_layout(path, layout)? {
+			fs::write(path.join(STORE_MARKER), layout.name())?;
+		}
+		Self::load(path, layout, options, false)
+	}
+
+	/// `open_with_options` after checking `mode` against whether `path` already holds a store.
+	pub fn open_with_mode(path: &Path, layout: Layout, options: StoreOptions, mode: OpenMode) -> StoreResult<Self> {
+		mode.check(path, store_exists(path)).map_err(StoreError::InvalidInput)?;
+		Self::open_with_options(path, layout, options)
+	}
+
+	/// Loads the existing segments; `commit`, `flush`, `clear`, `multi_way_merge` and `gc` are rejected,
+	/// so nothing under `path` is ever written. The write-ahead log is not replayed either, so only
+	/// flushed commits are visible.
+	pub fn open_read_only(path: &Path, layout: Layout) -> StoreResult<Self> {
+		Self::open_read_only_with_options(path, layout, StoreOptions::default())
+	}
+
+	/// `open_read_only` of a store created with `options`; only `expiring` matters to reads.
+	pub fn open_read_only_with_options(path: &Path, layout: Layout, options: StoreOptions) -> StoreResult<Self> {
+		if !path.is_dir() {
+			return Err(StoreError::InvalidInput(format!("no store at {}", path.display())))
+		}
+		check_marker_layout(path, layout)?;
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
+		Self::load(path, layout, options, true)
+	}
+
+	fn load(path: &Path, layout: Layout, options: StoreOptions, read_only: bool) -> StoreResult<Self> {
 		let mut columns = Vec::new();
 		for idx in 0..layout.column_count() {
-			let col = Column::open(path, idx as u8, options.segment_size)?;
+			let col = Column::open(path, idx as u8, &options)?;
 			columns.push(Arc::new(RwLock::new(col)));
 		}
-		let compactor = Compactor::new(columns.clone());
-		Ok(Self { layout, columns, compactor, progress: None, _ph: PhantomData })
+		let compactor = Compactor::new(columns.clone(), options.compaction_workers);
+		let compaction_stats = compactor.stats();
+		let mut store = Self {
+			layout,
+			columns,
+			compactor,
+			compaction_stats,
+			read_only,
+			coalesce_duplicates: options.coalesce_duplicates,
+			expiring: options.expiring,
+			// Set once the log is replayed, so a failed open does not flush half of it.
+			flush_on_drop: false,
+			wal: None,
+			commit_gate: RwLock::new(()),
+			birth_keys: Mutex::new(()),
+			progress: Mutex::new(None),
+			_ph: PhantomData,
+		};
+		if options.wal && !read_only {
+			let wal_dir = options.wal_dir.as_deref().unwrap_or(path);
+			fs::create_dir_all(wal_dir)?;
+			let wal = Wal::open(wal_dir, |op| store.apply(op))?;
+			store.wal = Some(Mutex::new(wal));
+		}
+		store.flush_on_drop = options.flush_on_drop && !read_only;
+		Ok(store)
+	}
+
+	/// Inserts one write into its column, handing a column that flushed to the compactor.
+	fn apply(&self, (col, key, value): WalOp) -> StoreResult<()> {
+		let column = self.columns.get(col as usize).ok_or_else(|| {
+			StoreError::InvalidInput(format!("write-ahead log writes column {col}, beyond the layout"))
+		})?;
+		if column.write().unwrap().insert(key, value)? {
+			self.compactor.request(col as usize)?;
+		}
+		Ok(())
+	}
+
+	fn ensure_writable(&self) -> StoreResult<()> {
+		if self.read_only {
+			return Err(StoreError::InvalidInput("store is opened read-only".into()))
+		}
+		Ok(())
+	}
+
+	pub fn commit<'a, I>(&self, items: I) -> StoreResult<()>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		self.commit_counted(items).map(|_| ())
+	}
+
+	/// With `coalesce_duplicates` set, a write the batch overwrites later is skipped.
+	pub fn commit_counted<'a, I>(&self, items: I) -> StoreResult<CommitStats>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		self.commit_expiring(items, self.expiring.then_some(NEVER_EXPIRES))
+	}
+
+	/// `commit` of `Plain` entries that `get_value` stops returning once `ttl` has passed.
+	pub fn commit_with_ttl<'a, I>(&self, items: I, ttl: Duration) -> StoreResult<()>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		self.ensure_expiring()?;
+		self.commit_expiring(items, Some(expiry_after(ttl))).map(|_| ())
+	}
+
+	/// `expires_at` is the expiry of every entry, `None` for a store without expiring entries.
+	fn commit_expiring<'a, I>(&self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		if self.coalesce_duplicates {
+			self.write_counted(coalesce_by_encoded_key::<K, V, KC, _>(items), expires_at)
+		} else {
+			self.write_counted(items, expires_at)
+		}
+	}
+
+	fn ensure_expiring(&self) -> StoreResult<()> {
+		if !self.expiring {
+			return Err(StoreError::InvalidInput("store is not opened with expiring entries".into()))
+		}
+		Ok(())
 	}
 
-	pub fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
+	/// Encodes the whole batch into column writes, logs them as one commit, then applies them.
+	/// Commits from several threads encode side by side; `Dictionary` commits, which look up birth
+	/// keys first, take turns. With the write-ahead log on, logging and applying take turns too.
+	fn write_counted<'a, I>(&self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
 	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
 	{
-		let mut processed = 0u64;
+		self.ensure_writable()?;
+		let _birth_keys = matches!(self.layout, Layout::Dictionary { .. }).then(|| self.birth_keys.lock().unwrap());
+		let mut stats = CommitStats::default();
+		let mut ops: Vec<WalOp> = Vec::new();
 		match self.layout {
 			Layout::Plain { key_to_value } => {
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					let flushed = self.columns[key_to_value as usize].write().unwrap().insert(kbytes.as_ref().to_vec(), vbytes.as_ref().to_vec())?;
-					if flushed {
-						self.compactor.request(key_to_value as usize)?;
-					}
-					processed += 1;
+					let stored = stored_value(vbytes.as_ref(), expires_at);
+					stats.record(kbytes.as_ref(), &stored);
+					ops.push((key_to_value, kbytes.as_ref().to_vec(), stored.into_owned()));
 				}
 			},
 			Layout::UniqueIndex { key_to_value, value_to_key } => {
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
 					let kvec = kbytes.as_ref().to_vec();
 					let vvec = vbytes.as_ref().to_vec();
-					let flushed1 = self.columns[key_to_value as usize].write().unwrap().insert(kvec.clone(), vvec.clone())?;
-					let flushed2 = self.columns[value_to_key as usize].write().unwrap().insert(vvec, kvec)?;
-					if flushed1 {
-						self.compactor.request(key_to_value as usize)?;
-					}
-					if flushed2 {
-						self.compactor.request(value_to_key as usize)?;
-					}
-					processed += 2;
+					stats.record(&kvec, &vvec);
+					stats.record(&vvec, &kvec);
+					ops.push((key_to_value, kvec.clone(), vvec.clone()));
+					ops.push((value_to_key, vvec, kvec));
 				}
 			},
 			Layout::Range { key_to_value, value_key_btree } => {
@@ -169,98 +604,305 @@ where
 					let vbytes = VC::encode(v);
 					let kvec = kbytes.as_ref().to_vec();
 					let vvec = vbytes.as_ref().to_vec();
-					let vk = concat(vbytes.as_ref(), kbytes.as_ref());
-					let flushed1 = self.columns[key_to_value as usize].write().unwrap().insert(kvec, vvec)?;
-					let flushed2 = self.columns[value_key_btree as usize].write().unwrap().insert(vk, Vec::new())?;
-					if flushed1 {
-						self.compactor.request(key_to_value as usize)?;
-					}
-					if flushed2 {
-						self.compactor.request(value_key_btree as usize)?;
-					}
-					processed += 2;
+					let vk = composite_key(vbytes.as_ref(), kbytes.as_ref());
+					stats.record(&kvec, &vvec);
+					stats.record(&vk, &[]);
+					ops.push((key_to_value, kvec, vvec));
+					ops.push((value_key_btree, vk, Vec::new()));
 				}
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
 				use std::collections::HashMap;
-				let mut value_cache: HashMap<Vec<u8>, (Vec<u8>, bool)> = HashMap::new();
+				let mut value_cache: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
 					let kvec = kbytes.as_ref().to_vec();
 					let vvec = vbytes.as_ref().to_vec();
-					let (pk, is_new) = if let Some(entry) = value_cache.get(&vvec) {
-						entry.clone()
+					let (pk, is_new) = if let Some(pk) = value_cache.get(&vvec) {
+						(pk.clone(), false)
 					} else if let Some(pk) = self.columns[value_to_birth_key as usize].read().unwrap().get(&vvec)? {
-						value_cache.insert(vvec.clone(), (pk.clone(), false));
+						value_cache.insert(vvec.clone(), pk.clone());
 						(pk, false)
 					} else {
-						value_cache.insert(vvec.clone(), (kvec.clone(), true));
+						value_cache.insert(vvec.clone(), kvec.clone());
 						(kvec.clone(), true)
 					};
 
 					if is_new {
-                        processed += 2;
-						let flushed_v2b = self.columns[value_to_birth_key as usize].write().unwrap().insert(vvec.clone(), pk.clone())?;
-						let flushed_b2v = self.columns[birth_key_to_value as usize].write().unwrap().insert(pk.clone(), vvec.clone())?;
-						if flushed_v2b {
-							self.compactor.request(value_to_birth_key as usize)?;
-						}
-						if flushed_b2v {
-							self.compactor.request(birth_key_to_value as usize)?;
-						}
-					}
-					let flushed_k2b = self.columns[key_to_birth_key as usize].write().unwrap().insert(kvec.clone(), pk.clone())?;
-					if flushed_k2b {
-						self.compactor.request(key_to_birth_key as usize)?;
+						stats.record(&vvec, &pk);
+						stats.record(&pk, &vvec);
+						ops.push((value_to_birth_key, vvec.clone(), pk.clone()));
+						ops.push((birth_key_to_value, pk.clone(), vvec));
 					}
+					stats.record(&kvec, &pk);
+					ops.push((key_to_birth_key, kvec.clone(), pk.clone()));
 
-					let pk_key = concat(&pk, &kvec);
-					let flushed_btree = self.columns[birth_key_key_btree as usize].write().unwrap().insert(pk_key, Vec::new())?;
-					if flushed_btree {
-						self.compactor.request(birth_key_key_btree as usize)?;
-					}
-					processed += 2;
+					let pk_key = composite_key(&pk, &kvec);
+					stats.record(&pk_key, &[]);
+					ops.push((birth_key_key_btree, pk_key, Vec::new()));
 				}
 			},
 		}
-		if let Some(p) = self.progress.as_mut() {
-			p.record(processed);
+		for (col, key, value) in &ops {
+			self.columns[*col as usize].read().unwrap().check_entry(key, value)?;
 		}
-		Ok(())
+		let logged = {
+			let _gate = self.commit_gate.read().unwrap();
+			// Held until the writes are applied, so commits reach the memtables in the order
+			// replaying the log applies them.
+			let mut wal = self.wal.as_ref().map(|wal| wal.lock().unwrap());
+			if let Some(wal) = wal.as_mut() {
+				wal.append(&ops)?;
+			}
+			for op in ops {
+				self.apply(op)?;
+			}
+			wal.map_or(0, |wal| wal.len())
+		};
+		if logged >= WAL_CHECKPOINT_BYTES {
+			self.flush_columns()?;
+		}
+		if let Some(p) = self.progress.lock().unwrap().as_mut() {
+			p.record(stats.rows);
+		}
+		Ok(stats)
+	}
+
+	/// `commit` in encoded-key order with duplicate keys collapsed to the last write. With
+	/// `MemtableKind::SortedVec` every insert of a sorted batch is a plain append.
+	pub fn commit_sorted<'a, I>(&self, items: I) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		let expires_at = self.expiring.then_some(NEVER_EXPIRES);
+		self.write_counted(sort_by_encoded_key::<K, V, KC, _>(items), expires_at).map(|_| ())
 	}
 
 	pub fn flush(&mut self) -> StoreResult<()> {
+		self.flush_reporting().map(|_| ())
+	}
+
+	/// `flush`, summing the segments written across columns; zero when every memtable was empty.
+	/// Every logged commit is then in the segments, so the write-ahead log starts over.
+	pub fn flush_reporting(&mut self) -> StoreResult<FlushStats> {
+		self.ensure_writable()?;
+		self.flush_columns()
+	}
+
+	/// Flushes every column and resets the log once no commit is between the two.
+	fn flush_columns(&self) -> StoreResult<FlushStats> {
+		let _gate = self.commit_gate.write().unwrap();
+		let mut stats = FlushStats::default();
+		for col in &self.columns {
+			stats.add(col.write().unwrap().flush()?);
+		}
+		if let Some(wal) = &self.wal {
+			wal.lock().unwrap().reset()?;
+		}
+		Ok(stats)
+	}
+
+	/// `flush`, then has the compactor merge every column and waits until it is done. Unlike
+	/// `settle_for_reads` the merges follow the merge policy, so a column is left with fewer than
+	/// `merge_threshold` segments rather than a single one.
+	pub fn flush_and_compact(&mut self) -> StoreResult<()> {
+		self.flush()?;
+		for idx in 0..self.columns.len() {
+			self.compactor.request(idx)?;
+		}
+		self.compactor.drain();
+		Ok(())
+	}
+
+	/// Flushes and merges every column into a single segment.
+	pub fn settle_for_reads(&mut self) -> StoreResult<()> {
+		self.flush()?;
+		self.multi_way_merge()
+	}
+
+	pub fn clear(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
 		for col in &self.columns {
-			col.write().unwrap().flush()?;
+			col.write().unwrap().clear()?;
+		}
+		if let Some(wal) = self.wal.as_mut() {
+			wal.get_mut().unwrap().reset()?;
 		}
 		Ok(())
 	}
 
+	/// Merges each column into a single segment; merged rows go to the `set_progress` tracker.
 	pub fn multi_way_merge(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		let progress = self.progress.get_mut().unwrap();
+		let mut report = |rows| {
+			if let Some(p) = progress.as_mut() {
+				p.record(rows);
+			}
+		};
+		for (idx, col) in self.columns.iter().enumerate() {
+			let start = Instant::now();
+			if let Some((rows, reclaimed)) = col.write().unwrap().multi_way_merge(&mut report)? {
+				self.compaction_stats.lock().unwrap()[idx].record(rows, start.elapsed(), reclaimed);
+			}
+		}
+		Ok(())
+	}
+
+	/// Drops shadowed values from every column's segments without merging them, returning the
+	/// bytes reclaimed. Columns with a background merge in flight are skipped.
+	pub fn gc(&mut self) -> StoreResult<u64> {
+		self.ensure_writable()?;
+		let mut reclaimed = 0;
+		for col in &self.columns {
+			reclaimed += col.write().unwrap().gc()?;
+		}
+		Ok(reclaimed)
+	}
+
+	/// Flushes an expiring `Plain` store and rewrites its segments without the expired entries,
+	/// like `gc`; segments have no tombstones to delete them otherwise. A background merge in
+	/// flight is waited out first, as it would put back the segments being rewritten.
+	pub fn purge_expired(&mut self) -> StoreResult<u64> {
+		self.ensure_writable()?;
+		self.ensure_expiring()?;
+		let Layout::Plain { key_to_value } = self.layout else { return Ok(0) };
+		let now = unix_millis();
+		let expired = move |stored: &[u8]| matches!(unexpired(stored, now), Ok(None));
+		let mut col = self.compactor.wait_for_merge(&self.columns[key_to_value as usize]);
+		col.purge(&expired).map(|(_, purged)| purged)
+	}
+
+	/// Snapshot of per-column merge statistics from background compaction and `multi_way_merge`.
+	pub fn compaction_stats(&self) -> Vec<CompactionStats> {
+		self.compaction_stats.lock().unwrap().clone()
+	}
+
+	/// Shared handle to the live stats; stays valid after the store is dropped.
+	pub fn compaction_stats_handle(&self) -> SharedCompactionStats {
+		self.compaction_stats.clone()
+	}
+
+	/// Read amplification per column since open or the last `reset_column_stats`; zeros unless
+	/// opened with `StoreOptions::count_reads`.
+	pub fn column_stats(&self) -> Vec<ColumnStats> {
+		self.read_counters_handle().iter().map(|c| c.stats()).collect()
+	}
+
+	pub fn reset_column_stats(&self) {
+		self.read_counters_handle().iter().for_each(|c| c.reset());
+	}
+
+	/// The counters behind `column_stats`, to read them after the store is gone.
+	pub fn read_counters_handle(&self) -> Vec<Arc<ReadCounters>> {
+		self.columns.iter().map(|col| col.read().unwrap().read_counters.clone()).collect()
+	}
+
+	/// Snapshots the store into the new directory `dest`, which then opens like the original, e.g.
+	/// with `open_read_only`. Memtables are flushed and each column's segments hard-linked (copied
+	/// across filesystems) under its lock, so a merge finishing meanwhile cannot remove them.
+	/// Segments are immutable once installed, so the links stay the snapshot. It is built in a
+	/// sibling `.partial` directory and renamed to `dest`, so `dest` never holds a partial one.
+	pub fn backup(&self, dest: &Path) -> StoreResult<()> {
+		if dest.exists() {
+			return Err(StoreError::InvalidInput(format!("backup destination {} already exists", dest.display())))
+		}
+		let mut staging_name = dest
+			.file_name()
+			.ok_or_else(|| StoreError::InvalidInput(format!("backup destination {} has no name", dest.display())))?
+			.to_os_string();
+		staging_name.push(".partial");
+		let staging = dest.with_file_name(staging_name);
+		if staging.exists() {
+			fs::remove_dir_all(&staging)?;
+		}
+		fs::create_dir_all(&staging)?;
+		if let Err(err) = self.backup_into(&staging) {
+			let _ = fs::remove_dir_all(&staging);
+			return Err(err)
+		}
+		fs::rename(&staging, dest)?;
+		if let Some(parent) = dest.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+			fs::File::open(parent)?.sync_all()?;
+		}
+		Ok(())
+	}
+
+	/// Each column's manifest lists just the segments linked for it, so the backup opens to the
+	/// snapshot even if it shares a directory with leftovers. Commits wait until every column is
+	/// linked, so none lands in some columns' snapshot and not in the others'.
+	fn backup_into(&self, dir: &Path) -> StoreResult<()> {
+		let _gate = self.commit_gate.write().unwrap();
 		for col in &self.columns {
-			col.write().unwrap().multi_way_merge()?;
+			let mut col = col.write().unwrap();
+			// A read-only store has nothing in memory, and must not write.
+			if !self.read_only {
+				col.flush()?;
+			}
+			for seg in &col.segments {
+				for file in [&seg.fst_path, &seg.values_path] {
+					// Sharded segments keep their shard, which is what the manifest lists.
+					let name = file.strip_prefix(&col.dir).expect("segment files live under the store directory");
+					let copy = dir.join(name);
+					let shard = copy.parent().filter(|parent| *parent != dir);
+					if let Some(shard) = shard {
+						fs::create_dir_all(shard)?;
+					}
+					if fs::hard_link(file, &copy).is_err() {
+						fs::copy(file, &copy)?;
+					}
+					fs::File::open(&copy)?.sync_all()?;
+					if let Some(shard) = shard {
+						fs::File::open(shard)?.sync_all()?;
+					}
+				}
+			}
+			write_manifest(dir, col.id, &col.segments, &col.dir, true)?;
 		}
+		let mut marker = fs::File::create(dir.join(STORE_MARKER))?;
+		io::Write::write_all(&mut marker, self.layout.name().as_bytes())?;
+		marker.sync_all()?;
+		fs::File::open(dir)?.sync_all()?;
 		Ok(())
 	}
 
 	pub fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		match self.value_bytes(key)? {
+			Some((col, bytes)) => in_column(col, VC::decode(&bytes).map(Some)),
+			None => Ok(None),
+		}
+	}
+
+	/// `key`'s value as stored, `VC::encode` of what `get_value` returns, without decoding it.
+	pub fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		Ok(self.value_bytes(key)?.map(|(_, bytes)| bytes))
+	}
+
+	/// The encoded value of `key` and the column it was read from, stripped of its expiry.
+	fn value_bytes(&self, key: &K) -> StoreResult<Option<(u8, Vec<u8>)>> {
 		let kbytes = KC::encode(key);
 		match self.layout {
+			Layout::Plain { key_to_value } if self.expiring => {
+				let Some(stored) = self.columns[key_to_value as usize].read().unwrap().get(kbytes.as_ref())? else {
+					return Ok(None)
+				};
+				let value = unexpired(&stored, unix_millis()).map_err(StoreError::InvalidInput);
+				in_column(key_to_value, value).map(|v| v.map(|b| (key_to_value, b.to_vec())))
+			},
 			Layout::Plain { key_to_value }
 			| Layout::UniqueIndex { key_to_value, .. }
 			| Layout::Range { key_to_value, .. } => {
-				self.columns[key_to_value as usize]
-					.read().unwrap()
-					.get(kbytes.as_ref())
-					.map(|opt| opt.map(|b| VC::decode(&b)).transpose())?
+				let value = self.columns[key_to_value as usize].read().unwrap().get(kbytes.as_ref())?;
+				Ok(value.map(|b| (key_to_value, b)))
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, .. } => {
 				if let Some(pk) = self.columns[key_to_birth_key as usize].read().unwrap().get(kbytes.as_ref())? {
-					self.columns[birth_key_to_value as usize]
-						.read().unwrap()
-						.get(&pk)
-						.map(|opt| opt.map(|b| VC::decode(&b)).transpose())?
+					let value = self.columns[birth_key_to_value as usize].read().unwrap().get(&pk)?;
+					Ok(value.map(|b| (birth_key_to_value, b)))
 				} else {
 					Ok(None)
 				}
@@ -268,45 +910,97 @@ where
 		}
 	}
 
+	/// What the store holds for `key`: its encoding and that of its value, printing as hex dumps,
+	/// for a lookup that returns something unexpected.
+	pub fn debug_dump(&self, key: &K) -> StoreResult<KeyDump> {
+		let value = self.get_value(key)?;
+		Ok(KeyDump::new::<K, V, KC, VC>(key, value.as_ref()))
+	}
+
 	pub fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
 		let vbytes = VC::encode(value);
 		match self.layout {
 			Layout::UniqueIndex { value_to_key, .. } => {
-				self.columns[value_to_key as usize]
-					.read().unwrap()
-					.get(vbytes.as_ref())
-					.map(|opt| opt.map(|b| KC::decode(&b)).transpose())?
+				let key = self.columns[value_to_key as usize].read().unwrap().get(vbytes.as_ref())?;
+				let key = key.map(|b| decode_exact::<K, KC>(&b, StoreError::InvalidInput)).transpose();
+				in_column(value_to_key, key)
 			},
+			Layout::Range { .. } | Layout::Dictionary { .. } => Ok(self.keys_for_value(value, Some(1))?.into_iter().next()),
 			_ => Err(StoreError::InvalidInput("get_key_for_value not supported for this layout".into())),
 		}
 	}
 
 	pub fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
+		self.keys_for_value(value, None)
+	}
+
+	/// `StoreRead::get_entries_for_value`. A `Range` store splits each `value||key` entry of one
+	/// btree scan into the pair and drops the entries of overwritten keys by comparing the stored
+	/// bytes of `key_to_value` with the head, without decoding them.
+	pub fn get_entries_for_value(&self, value: &V) -> StoreResult<Vec<(K, V)>>
+	where
+		V: PartialEq,
+	{
+		let Layout::Range { key_to_value, value_key_btree } = self.layout else {
+			let mut entries = Vec::new();
+			for key in self.get_keys_for_value(value)? {
+				if let Some(current) = self.get_value(&key)?
+					&& current == *value
+				{
+					entries.push((key, current));
+				}
+			}
+			return Ok(entries)
+		};
+		let vbytes = VC::encode(value);
+		let prefix = composite_prefix(vbytes.as_ref());
+		let scanned = self.columns[value_key_btree as usize].read().unwrap().keys_with_prefix(&prefix, None)?;
+		let column = self.columns[key_to_value as usize].read().unwrap();
+		let mut entries = Vec::new();
+		for k in scanned {
+			let Some((head, key_bytes)) = split_composite(&k) else { continue };
+			if head != vbytes.as_ref() || column.get(key_bytes)?.is_none_or(|current| current != head) {
+				continue
+			}
+			let key = in_column(value_key_btree, KC::decode(key_bytes))?;
+			entries.push((key, in_column(value_key_btree, VC::decode(head))?));
+		}
+		Ok(entries)
+	}
+
+	/// `get_keys_for_value` capped at the `limit` smallest keys, so a value with very many keys
+	/// cannot make the btree scan collect them all.
+	pub fn get_keys_for_value_limited(&self, value: &V, limit: usize) -> StoreResult<Vec<K>> {
+		self.keys_for_value(value, Some(limit))
+	}
+
+	fn keys_for_value(&self, value: &V, limit: Option<usize>) -> StoreResult<Vec<K>> {
 		let vbytes = VC::encode(value);
 		match self.layout {
 			Layout::Range { value_key_btree, .. } => {
-				let prefix = vbytes.as_ref();
-				let keys = self.columns[value_key_btree as usize].read().unwrap().keys_with_prefix(prefix)?;
+				let prefix = composite_prefix(vbytes.as_ref());
+				let keys = self.columns[value_key_btree as usize].read().unwrap().keys_with_prefix(&prefix, limit)?;
 				let mut out = Vec::new();
 				for k in keys {
-					if k.len() < prefix.len() {
-						continue
+					if let Some((head, key_bytes)) = split_composite(&k)
+						&& head == vbytes.as_ref()
+					{
+						out.push(in_column(value_key_btree, KC::decode(key_bytes))?);
 					}
-					let key_bytes = &k[prefix.len()..];
-					out.push(KC::decode(key_bytes)?);
 				}
 				Ok(out)
 			},
 			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
 				if let Some(pk) = self.columns[value_to_birth_key as usize].read().unwrap().get(vbytes.as_ref())? {
-					let keys = self.columns[birth_key_key_btree as usize].read().unwrap().keys_with_prefix(&pk)?;
+					let prefix = composite_prefix(&pk);
+					let keys = self.columns[birth_key_key_btree as usize].read().unwrap().keys_with_prefix(&prefix, limit)?;
 					let mut out = Vec::new();
 					for k in keys {
-						if k.len() < pk.len() {
-							continue
+						if let Some((head, suffix)) = split_composite(&k)
+							&& head == pk.as_slice()
+						{
+							out.push(in_column(birth_key_key_btree, KC::decode(suffix))?);
 						}
-						let suffix = &k[pk.len()..];
-						out.push(KC::decode(suffix)?);
 					}
 					Ok(out)
 				} else {
@@ -316,69 +1010,369 @@ where
 			_ => Err(StoreError::InvalidInput("get_keys_for_value not supported for this layout".into())),
 		}
 	}
-}
-
-fn concat(a: &[u8], b: &[u8]) -> Vec<u8> {
-	let mut out = Vec::with_capacity(a.len() + b.len());
-	out.extend_from_slice(a);
-	out.extend_from_slice(b);
-	out
-}
-
-impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
-where
-	KC: StoreCodec<K, Error = StoreError>,
-	VC: StoreCodec<V, Error = StoreError>,
-{
-	type Error = StoreError;
-
-	fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
-		Store::get_value(self, key)
-	}
 
-	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
-		Store::get_key_for_value(self, value)
+	/// Holds each column's read lock across the whole batch, so every value sees the same columns.
+	/// Btree scans run in head order; `Dictionary` resolves birth keys in value order first.
+	pub fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		let (btree, heads) = match self.layout {
+			Layout::Range { value_key_btree, .. } => (value_key_btree, sort_by_encoding::<V, VC>(values)),
+			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
+				let v2pk = self.columns[value_to_birth_key as usize].read().unwrap();
+				let mut pks = Vec::with_capacity(values.len());
+				for (idx, vbytes) in sort_by_encoding::<V, VC>(values) {
+					if let Some(pk) = v2pk.get(&vbytes)? {
+						pks.push((idx, pk));
+					}
+				}
+				pks.sort_by(|a, b| a.1.cmp(&b.1));
+				(birth_key_key_btree, pks)
+			},
+			_ => return Err(StoreError::InvalidInput("get_keys_for_values not supported for this layout".into())),
+		};
+		let column = self.columns[btree as usize].read().unwrap();
+		let mut out: Vec<Vec<K>> = values.iter().map(|_| Vec::new()).collect();
+		for (idx, head) in heads {
+			for k in column.keys_with_prefix(&composite_prefix(&head), None)? {
+				if let Some((h, key_bytes)) = split_composite(&k)
+					&& h == head.as_slice()
+				{
+					out[idx].push(in_column(btree, KC::decode(key_bytes))?);
+				}
+			}
+		}
+		Ok(out)
 	}
 
-	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
-		Store::get_keys_for_value(self, value)
+	/// Reads the values up front under the column lock, walking every birth key of the memtables
+	/// and segments.
+	pub fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		match self.layout {
+			Layout::Dictionary { birth_key_to_value, .. } => {
+				let column = self.columns[birth_key_to_value as usize].read().unwrap();
+				let mut values = Vec::new();
+				for pk in column.keys_with_prefix(&[], None)? {
+					if let Some(v) = column.get(&pk)? {
+						values.push(v);
+					}
+				}
+				Ok(values.into_iter().map(move |v| in_column(birth_key_to_value, VC::decode(&v))))
+			},
+			_ => Err(StoreError::InvalidInput("distinct_values not supported for this layout".into())),
+		}
 	}
-}
-
-impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
-where
-	KC: StoreCodec<K, Error = StoreError>,
-	VC: StoreCodec<V, Error = StoreError>,
-{
-	type Options = StoreOptions;
-	type Layout = Layout;
 
-	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
-		Store::open(path, layout, options)
+	pub fn first_key(&self) -> StoreResult<Option<K>> {
+		let col = self.primary_column();
+		let key = self.columns[col as usize].read().unwrap().first_key()?;
+		in_column(col, key.map(|k| KC::decode(&k)).transpose())
 	}
 
-	fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
-	where
-		I: IntoIterator<Item = (&'a K, &'a V)>,
-		K: 'a,
-		V: 'a,
-	{
-		Store::commit(self, items)
+	pub fn last_key(&self) -> StoreResult<Option<K>> {
+		let col = self.primary_column();
+		let key = self.columns[col as usize].read().unwrap().last_key()?;
+		in_column(col, key.map(|k| KC::decode(&k)).transpose())
 	}
 
-	fn flush(&mut self) -> StoreResult<()> {
-		Store::flush(self)
+	/// Rows of the primary column's memtables and segments, without reading either; keys
+	/// overwritten since the last merge count once per segment.
+	pub fn approx_key_count(&self) -> StoreResult<u64> {
+		Ok(self.columns[self.primary_column() as usize].read().unwrap().approx_len())
 	}
 
-	fn set_progress(&mut self, label: &str, total: u64) {
-		self.progress = Some(ProgressTracker::new(label.to_string(), total));
+	/// The column holding every key: `key_to_value`, or `key_to_birth_key` for `Dictionary`.
+	fn primary_column(&self) -> u8 {
+		match self.layout {
+			Layout::Plain { key_to_value }
+			| Layout::UniqueIndex { key_to_value, .. }
+			| Layout::Range { key_to_value, .. } => key_to_value,
+			Layout::Dictionary { key_to_birth_key, .. } => key_to_birth_key,
+		}
 	}
-}
+
+	/// Walks the primary column's keys up front, each column under its own read lock.
+	pub fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		let mut report = ConsistencyReport::default();
+		match self.layout {
+			Layout::Plain { .. } => {},
+			Layout::UniqueIndex { key_to_value, value_to_key } => {
+				let k2v = self.columns[key_to_value as usize].read().unwrap();
+				let v2k = self.columns[value_to_key as usize].read().unwrap();
+				for k in k2v.keys_with_prefix(&[], None)? {
+					let Some(v) = k2v.get(&k)? else { continue };
+					report.checked += 1;
+					if v2k.get(&v)?.as_ref() != Some(&k) {
+						report.mismatches.push(Mismatch::MissingBackEdge { key: k, value: v });
+					}
+				}
+			},
+			Layout::Range { key_to_value, value_key_btree } => {
+				let k2v = self.columns[key_to_value as usize].read().unwrap();
+				let btree = self.columns[value_key_btree as usize].read().unwrap();
+				for k in k2v.keys_with_prefix(&[], None)? {
+					let Some(v) = k2v.get(&k)? else { continue };
+					report.checked += 1;
+					if btree.get(&composite_key(&v, &k))?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: v, key: k });
+					}
+				}
+			},
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, birth_key_key_btree, .. } => {
+				let k2pk = self.columns[key_to_birth_key as usize].read().unwrap();
+				let pk2v = self.columns[birth_key_to_value as usize].read().unwrap();
+				let btree = self.columns[birth_key_key_btree as usize].read().unwrap();
+				for k in k2pk.keys_with_prefix(&[], None)? {
+					let Some(pk) = k2pk.get(&k)? else { continue };
+					report.checked += 1;
+					if pk2v.get(&pk)?.is_none() {
+						report.mismatches.push(Mismatch::DanglingBirthKey { key: k.clone(), birth_key: pk.clone() });
+					}
+					if btree.get(&composite_key(&pk, &k))?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: pk, key: k });
+					}
+				}
+			},
+		}
+		Ok(report)
+	}
+}
+
+/// Names the column a decode failed in, so an error from a misaligned segment points at its files.
+fn in_column<T>(col: u8, decoded: StoreResult<T>) -> StoreResult<T> {
+	decoded.map_err(|err| match err {
+		StoreError::InvalidInput(msg) => StoreError::InvalidInput(format!("{msg} in column col{col}")),
+		other => other,
+	})
+}
+
+/// Created by every writable open, so `OpenMode` recognizes a store that has not flushed yet. It
+/// holds the name of the store's layout.
+const STORE_MARKER: &str = "fst-store";
+
+/// Checks `layout` against the one the store's marker names, returning whether it names one; a
+/// new store has no marker, and one from before markers named the layout an empty one. Without a
+/// name, the column files and shard directories under `path` must be those of the layout's columns.
+fn check_marker_layout(path: &Path, layout: Layout) -> StoreResult<bool> {
+	match fs::read_to_string(path.join(STORE_MARKER)) {
+		Ok(stored) if !stored.is_empty() => {
+			check_layout(&stored, layout.name()).map_err(StoreError::InvalidInput)?;
+			return Ok(true)
+		},
+		Ok(_) => {},
+		Err(err) if err.kind() == io::ErrorKind::NotFound => {},
+		Err(err) => return Err(err.into()),
+	}
+	let mut columns = Vec::new();
+	for entry in fs::read_dir(path)? {
+		columns.extend(column_id(&entry?.file_name().to_string_lossy()));
+	}
+	let expected = layout.columns().into_iter().map(usize::from);
+	check_columns(columns, layout.name(), expected).map_err(StoreError::InvalidInput)?;
+	Ok(false)
+}
+
+/// Whether `path` holds a store: it has the marker or, if written before the marker existed, segments.
+fn store_exists(path: &Path) -> bool {
+	path.join(STORE_MARKER).is_file()
+		|| fs::read_dir(path).is_ok_and(|entries| {
+			entries.flatten().any(|e| e.file_name().to_string_lossy().ends_with(".fst"))
+		})
+}
+
+impl<K, V, KC, VC> Drop for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	/// Flushes every memtable per `StoreOptions::flush_on_drop`; the compactor is joined after,
+	/// when the field drops. Skipped while unwinding, as a panic may have poisoned the columns.
+	fn drop(&mut self) {
+		if self.flush_on_drop
+			&& !std::thread::panicking()
+			&& let Err(e) = self.flush_columns()
+		{
+			eprintln!("flush on drop error: {}", e);
+		}
+	}
+}
+
+impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	type Error = StoreError;
+
+	fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		Store::get_value(self, key)
+	}
+
+	fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		Store::get_value_raw(self, key)
+	}
+
+	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
+		Store::get_key_for_value(self, value)
+	}
+
+	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
+		Store::get_keys_for_value(self, value)
+	}
+
+	fn get_entries_for_value(&self, value: &V) -> StoreResult<Vec<(K, V)>>
+	where
+		V: PartialEq,
+	{
+		Store::get_entries_for_value(self, value)
+	}
+
+	fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		Store::get_keys_for_values(self, values)
+	}
+
+	fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		Store::distinct_values(self)
+	}
+
+	fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		Store::verify_consistency(self)
+	}
+
+	fn first_key(&self) -> StoreResult<Option<K>> {
+		Store::first_key(self)
+	}
+
+	fn last_key(&self) -> StoreResult<Option<K>> {
+		Store::last_key(self)
+	}
+
+	fn approx_key_count(&self) -> StoreResult<u64> {
+		Store::approx_key_count(self)
+	}
+}
+
+impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	type Options = StoreOptions;
+	type Layout = Layout;
+
+	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
+		Store::open_with_options(path, layout, options)
+	}
+
+	fn open_with_mode(path: &Path, layout: Self::Layout, options: Self::Options, mode: OpenMode) -> StoreResult<Self> {
+		Store::open_with_mode(path, layout, options, mode)
+	}
+
+	fn open_read_only(path: &Path, layout: Self::Layout) -> StoreResult<Self> {
+		Store::open_read_only(path, layout)
+	}
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_counted(self, items)
+	}
+
+	fn commit_sorted<'a, I>(&mut self, items: I) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_sorted(self, items)
+	}
+
+	fn flush(&mut self) -> StoreResult<()> {
+		Store::flush(self)
+	}
+
+	fn flush_reporting(&mut self) -> StoreResult<FlushStats> {
+		Store::flush_reporting(self)
+	}
+
+	fn settle_for_reads(&mut self) -> StoreResult<()> {
+		Store::settle_for_reads(self)
+	}
+
+	fn clear(&mut self) -> StoreResult<()> {
+		Store::clear(self)
+	}
+
+	fn set_progress_tracker(&mut self, tracker: ProgressTracker) {
+		*self.progress.get_mut().unwrap() = Some(tracker);
+	}
+}
+
+impl<K, V, KC, VC> ExpiringStore<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_with_ttl(self, items, ttl)
+	}
+
+	fn purge_expired(&mut self) -> StoreResult<u64> {
+		Store::purge_expired(self)
+	}
+}
+
+pub const MIN_SEGMENT_ROWS: usize = 200_000;
+const TARGET_MAX_SEGMENTS: u64 = 32;
+pub const DEFAULT_MEMTABLE_BUDGET_BYTES: usize = 2 * 1024 * 1024 * 1024; // 2GB
+pub const DEFAULT_MERGE_THRESHOLD: usize = 4;
+pub const BACKLOG_WARNING_FACTOR: usize = 4;
+/// Log size at which a commit flushes every column, bounding replay on the next open.
+const WAL_CHECKPOINT_BYTES: u64 = 256 * 1024 * 1024;
+pub const PROFILE_AVG_KV_BYTES: usize = 64;
+
+fn compute_segment_size(approx_rows: u64, avg_kv_bytes: usize, mem_budget_bytes: usize) -> usize {
+	segment_size_for(approx_rows, TARGET_MAX_SEGMENTS, avg_kv_bytes, mem_budget_bytes)
+}
+
+fn segment_size_for(approx_rows: u64, target_segments: u64, avg_kv_bytes: usize, mem_budget_bytes: usize) -> usize {
+	let avg_kv = avg_kv_bytes.max(1);
+	let desired_by_segments = if approx_rows == 0 {
+		MIN_SEGMENT_ROWS as u64
+	} else {
+		let per_seg = approx_rows.div_ceil(target_segments);
+		per_seg.max(MIN_SEGMENT_ROWS as u64)
+	};
+
+	let mem_cap_rows = (mem_budget_bytes.max(1) / avg_kv).max(1);
+	let chosen = desired_by_segments as usize;
+	chosen.min(mem_cap_rows).max(1)
+}
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use core::store_tests::{basic_value_roundtrip, multiple_keys_for_value, reverse_lookup_unique};
+	use core::bench_common::{dir_size, dir_size_by_kind};
+	use core::dyn_store::DynStore;
+	use core::store_tests::{
+		approximate_key_count, assert_send_sync, basic_value_roundtrip, batched_keys_for_values, builder_paths,
+		clear_then_reuse, coalesced_duplicate_keys, consistency_audit, crash_recovery, dictionary_birth_key_stable,
+		dictionary_commit_stats, dictionary_distinct_values, empty_values, expiring_entries, key_bounds,
+		mismatched_layout, multiple_keys_for_value, open_modes, prefix_values_isolated, raw_values,
+		read_only_rejects_writes, reverse_lookup_unique, shared_readers, separate_wal_dir, settle_keeps_contents,
+		sorted_commit_matches_unsorted, untagged_mismatched_layout, variable_length_keys_roundtrip,
+	};
+	use core::bench_codecs::{DecodeError, InvalidInput};
+	use core::hashed_codec::{Blake3, HashedKeyCodec, HASHED_KEY_BYTES};
+	use core::serde_codec::{BincodeError, InvalidValue, SerdeCodec};
+	use core::store_builder::StoreBuilder;
+	use crate::segment::manifest_path;
 	use tempfile::tempdir;
 
 	struct BytesCodec;
@@ -394,43 +1388,486 @@ mod tests {
 		}
 	}
 
+	/// Big-endian u64 whose `decode` panics on short input, so only `EXPECTED_LEN` guards it.
+	struct U64Codec;
+
+	impl StoreCodec<u64> for U64Codec {
+		type Error = StoreError;
+		type Enc<'a> = [u8; 8];
+		const EXPECTED_LEN: Option<usize> = Some(8);
+		fn encode<'a>(value: &'a u64) -> Self::Enc<'a> {
+			value.to_be_bytes()
+		}
+		fn decode(bytes: &[u8]) -> StoreResult<u64> {
+			Ok(u64::from_be_bytes(bytes[..8].try_into().unwrap()))
+		}
+	}
+
+	struct SerdeInvalid;
+
+	impl InvalidValue<StoreError> for SerdeInvalid {
+		fn invalid_value(err: BincodeError) -> StoreError {
+			StoreError::InvalidInput(err.to_string())
+		}
+	}
+
+	struct HashedInvalid;
+
+	impl InvalidInput<StoreError> for HashedInvalid {
+		fn invalid_input(err: DecodeError) -> StoreError {
+			StoreError::InvalidInput(err.to_string())
+		}
+	}
+
+	type HashedBytes = HashedKeyCodec<Blake3, BytesCodec, HashedInvalid>;
+
+	#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+	struct Output {
+		address: String,
+		amount: u64,
+	}
+
+	#[test]
+	fn serde_values_roundtrip_and_reverse_lookup() {
+		type OutputStore = Store<Vec<u8>, Output, BytesCodec, SerdeCodec<StoreError, SerdeInvalid>>;
+		let outputs: Vec<(Vec<u8>, Output)> = (0..20u8)
+			.map(|i| (vec![i], Output { address: format!("bc1q{}", "x".repeat(i as usize % 3)), amount: i as u64 % 3 }))
+			.collect();
+		for layout in [Layout::plain(0), Layout::range(0)] {
+			let dir = tempdir().unwrap();
+			let mut store = OutputStore::open_with_options(dir.path(), layout, StoreOptions::new(8)).unwrap();
+			store.commit(outputs.iter().map(|(k, v)| (k, v))).unwrap();
+			store.flush().unwrap();
+			for (k, v) in &outputs {
+				assert_eq!(store.get_value(k).unwrap().as_ref(), Some(v));
+			}
+			if matches!(layout, Layout::Range { .. }) {
+				let keys = store.get_keys_for_value(&outputs[4].1).unwrap();
+				assert_eq!(keys, (0..20u8).filter(|i| i % 3 == 1).map(|i| vec![i]).collect::<Vec<_>>());
+			}
+		}
+	}
+
+	#[test]
+	fn threads_commit_disjoint_ranges_concurrently() {
+		const THREADS: u32 = 4;
+		const ROWS: u32 = 2_000;
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			let dir = tempdir().unwrap();
+			let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), layout, StoreOptions::new(300))
+				.unwrap();
+			// Every thread commits the same few values, so dictionary commits race for birth keys.
+			let row = |t: u32, i: u32| ((t * ROWS + i).to_be_bytes().to_vec(), vec![(i % 7) as u8; 4]);
+			std::thread::scope(|scope| {
+				for t in 0..THREADS {
+					let store = &store;
+					scope.spawn(move || {
+						for chunk in (0..ROWS).collect::<Vec<_>>().chunks(50) {
+							let rows: Vec<_> = chunk.iter().map(|&i| row(t, i)).collect();
+							store.commit(rows.iter().map(|(k, v)| (k, v))).unwrap();
+						}
+					});
+				}
+			});
+			for t in 0..THREADS {
+				for i in 0..ROWS {
+					let (k, v) = row(t, i);
+					assert_eq!(store.get_value(&k).unwrap(), Some(v));
+				}
+			}
+			assert!(store.verify_consistency().unwrap().is_consistent());
+			if matches!(layout, Layout::Dictionary { .. }) {
+				let keys = store.get_keys_for_value(&vec![0; 4]).unwrap();
+				assert_eq!(keys.len(), (THREADS * ROWS.div_ceil(7)) as usize, "one birth key per value");
+			}
+		}
+	}
+
+	#[test]
+	fn replaying_the_log_of_racing_commits_matches_the_live_store() {
+		const THREADS: u8 = 4;
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(1_000_000).with_wal(true).with_flush_on_drop(false);
+		let keys: Vec<Vec<u8>> = (0..500u16).map(|i| i.to_be_bytes().to_vec()).collect();
+		let live: Vec<Option<Vec<u8>>> = {
+			let store =
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options.clone())
+					.unwrap();
+			// Every thread overwrites the same keys, so the last write of each depends on the order
+			// the commits were applied in.
+			std::thread::scope(|scope| {
+				for t in 0..THREADS {
+					let (store, keys) = (&store, &keys);
+					scope.spawn(move || {
+						for round in 0..30u8 {
+							let value = vec![t, round];
+							store.commit(keys.iter().map(|k| (k, &value))).unwrap();
+						}
+					});
+				}
+			});
+			keys.iter().map(|k| store.get_value(k).unwrap()).collect()
+		};
+		let store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options).unwrap();
+		let replayed: Vec<Option<Vec<u8>>> = keys.iter().map(|k| store.get_value(k).unwrap()).collect();
+		assert_eq!(replayed, live);
+	}
+
+	#[test]
+	fn backups_taken_during_commits_are_consistent_across_columns() {
+		let dir = tempdir().unwrap();
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+			&dir.path().join("store"),
+			Layout::unique_index(0),
+			StoreOptions::new(500),
+		)
+		.unwrap();
+		let done = std::sync::atomic::AtomicBool::new(false);
+		std::thread::scope(|scope| {
+			scope.spawn(|| {
+				for batch in 0..40u32 {
+					let rows: Vec<(Vec<u8>, Vec<u8>)> = (batch * 200..(batch + 1) * 200)
+						.map(|i| (i.to_be_bytes().to_vec(), [b'v'].iter().chain(&i.to_be_bytes()).copied().collect()))
+						.collect();
+					store.commit(rows.iter().map(|(k, v)| (k, v))).unwrap();
+				}
+				done.store(true, std::sync::atomic::Ordering::Release);
+			});
+			let mut taken = 0;
+			while !done.load(std::sync::atomic::Ordering::Acquire) || taken == 0 {
+				let dest = dir.path().join(format!("backup{taken}"));
+				store.backup(&dest).unwrap();
+				let backup =
+					Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&dest, Layout::unique_index(0)).unwrap();
+				// Each column holds the rows of the same whole commits.
+				let rows: Vec<usize> = backup
+					.columns
+					.iter()
+					.map(|col| col.read().unwrap().keys_with_prefix(&[], None).unwrap().len())
+					.collect();
+				assert_eq!(rows[0], rows[1], "backup {taken}");
+				assert_eq!(rows[0] % 200, 0, "backup {taken}");
+				taken += 1;
+			}
+		});
+	}
+
+	#[test]
+	fn commits_racing_background_merges_never_reuse_segment_ids() {
+		const THREADS: u32 = 4;
+		const ROWS: u32 = 1_500;
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(40).with_merge_threshold(2);
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options)
+				.unwrap();
+		let row = |t: u32, i: u32| ((t * ROWS + i).to_be_bytes().to_vec(), i.to_le_bytes().to_vec());
+		// Every few commits flush a segment while the compactor snapshots and merges the earlier ones, so a
+		// flush drawing the id of an in-flight merge fails its commit with `SegmentIdInUse`.
+		std::thread::scope(|scope| {
+			for t in 0..THREADS {
+				let store = &store;
+				scope.spawn(move || {
+					for chunk in (0..ROWS).collect::<Vec<_>>().chunks(15) {
+						let rows: Vec<_> = chunk.iter().map(|&i| row(t, i)).collect();
+						store.commit(rows.iter().map(|(k, v)| (k, v))).unwrap();
+					}
+				});
+			}
+		});
+		store.flush().unwrap();
+		for t in 0..THREADS {
+			for i in 0..ROWS {
+				let (k, v) = row(t, i);
+				assert_eq!(store.get_value(&k).unwrap(), Some(v), "thread {t} row {i}");
+			}
+		}
+		assert!(store.verify_consistency().unwrap().is_consistent());
+		assert!(CompactionStats::total(&store.compaction_stats()).merges > 0);
+	}
+
+	#[test]
+	fn flush_and_compact_leaves_each_column_below_the_merge_threshold() {
+		const MERGE_THRESHOLD: usize = 4;
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(50).with_merge_threshold(MERGE_THRESHOLD);
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::dictionary(0), options)
+				.unwrap();
+		let rows: Vec<_> = (0..2_000u32).map(|i| (i.to_be_bytes().to_vec(), (i % 7).to_le_bytes().to_vec())).collect();
+		for chunk in rows.chunks(30) {
+			store.commit(chunk.iter().map(|(k, v)| (k, v))).unwrap();
+		}
+		store.flush_and_compact().unwrap();
+		for (idx, col) in store.columns.iter().enumerate() {
+			let segments = col.read().unwrap().segments.len();
+			assert!(segments < MERGE_THRESHOLD, "column {idx} has {segments} segments");
+		}
+		assert!(CompactionStats::total(&store.compaction_stats()).merges > 0);
+		for (k, v) in &rows {
+			assert_eq!(store.get_value(k).unwrap().as_ref(), Some(v));
+		}
+		assert_eq!(store.get_keys_for_value(&3u32.to_le_bytes().to_vec()).unwrap().len(), 2_000 / 7 + 1);
+	}
+
+	#[test]
+	fn reverse_lookup_rejects_keys_of_another_codec() {
+		let dir = tempdir().unwrap();
+		let value = 7u64.to_be_bytes().to_vec();
+		{
+			let mut store =
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::unique_index(0), StoreOptions::new(10))
+					.unwrap();
+			store.commit([(&b"short"[..].to_vec(), &value)]).unwrap();
+			store.flush().unwrap();
+		}
+		let store =
+			Store::<u64, Vec<u8>, U64Codec, BytesCodec>::open_with_options(dir.path(), Layout::unique_index(0), StoreOptions::new(10)).unwrap();
+		match store.get_key_for_value(&value) {
+			Err(StoreError::InvalidInput(msg)) => assert!(msg.starts_with("5 bytes where u64 expects 8"), "{msg}"),
+			other => panic!("expected a length error, got {other:?}"),
+		}
+	}
+
+	#[test]
+	fn hashed_blob_keys_roundtrip_but_reject_reverse_lookups() {
+		let dir = tempdir().unwrap();
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, HashedBytes, BytesCodec>::open_with_options(dir.path(), Layout::range(0), StoreOptions::new(10))
+				.unwrap();
+		// Two 4 KiB blobs differing in their last byte only.
+		let blob = vec![3u8; 4096];
+		let other = [vec![3u8; 4095], vec![4]].concat();
+		let (hash, other_hash) = (HashedBytes::encode(&blob), HashedBytes::encode(&other));
+		assert_eq!((hash.len(), other_hash.len()), (HASHED_KEY_BYTES, HASHED_KEY_BYTES));
+		assert_ne!(hash, other_hash);
+		let value = b"same value".to_vec();
+		store.commit([(&blob, &value), (&other, &value)]).unwrap();
+		store.flush().unwrap();
+		assert_eq!(store.get_value(&blob).unwrap(), Some(value.clone()));
+		assert_eq!(store.get_value(&other).unwrap(), Some(value.clone()));
+		assert_eq!(store.get_value(&vec![3u8; 4094]).unwrap(), None);
+
+		// Both keys are under the value, but only as hashes, which cannot be handed back.
+		match store.get_keys_for_value(&value) {
+			Err(StoreError::InvalidInput(msg)) => assert!(msg.starts_with("hashed key cannot be decoded back"), "{msg}"),
+			other => panic!("expected the hashed key to be rejected, got {other:?}"),
+		}
+		assert!(store.get_keys_for_value(&b"absent".to_vec()).unwrap().is_empty());
+	}
+
+	#[test]
+	fn writes_and_reads_from_memtable() {
+		let dir = tempdir().unwrap();
+		let store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(10)).unwrap();
+
+		store.commit([(&b"key"[..].to_vec(), &b"value"[..].to_vec())]).unwrap();
+		let got = store.get_value(&b"key"[..].to_vec()).unwrap();
+		assert_eq!(got, Some(b"value".to_vec()));
+		assert!(store.columns[0].read().unwrap().segments.is_empty(), "no segment should be flushed yet");
+	}
+
+	#[test]
+	fn open_without_options_uses_the_defaults() {
+		let dir = tempdir().unwrap();
+		{
+			let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::unique_index(0)).unwrap();
+			assert!(store.columns.iter().all(|col| col.read().unwrap().segment_size == MIN_SEGMENT_ROWS));
+			store.commit([(&b"k".to_vec(), &b"v".to_vec())]).unwrap();
+		}
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::unique_index(0)).unwrap();
+		assert_eq!(store.get_value(&b"k".to_vec()).unwrap(), Some(b"v".to_vec()));
+		assert_eq!(store.get_key_for_value(&b"v".to_vec()).unwrap(), Some(b"k".to_vec()));
+	}
+
+	#[test]
+	fn flushes_to_segment_and_recovers() {
+		let dir = tempdir().unwrap();
+		{
+			let mut store =
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(2)).unwrap();
+			store.commit([
+				(&b"a"[..].to_vec(), &b"1"[..].to_vec()),
+				(&b"b"[..].to_vec(), &b"2"[..].to_vec()),
+			]).unwrap();
+			store.flush().unwrap();
+			assert_eq!(store.columns[0].read().unwrap().segments.len(), 1);
+		}
+
+		let store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(2)).unwrap();
+		assert_eq!(store.get_value(&b"a"[..].to_vec()).unwrap(), Some(b"1".to_vec()));
+		assert_eq!(store.get_value(&b"b"[..].to_vec()).unwrap(), Some(b"2".to_vec()));
+	}
+
+	#[test]
+	fn flush_reports_pending_rows() {
+		let dir = tempdir().unwrap();
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(100)).unwrap();
+		assert_eq!(store.flush_reporting().unwrap(), FlushStats::default());
+
+		for i in 0..3u8 {
+			store.commit([(&vec![i], &vec![i; 8])]).unwrap();
+		}
+		let stats = store.flush_reporting().unwrap();
+		assert_eq!((stats.segments_written, stats.rows_flushed), (1, 3));
+		assert!(stats.bytes_flushed > 0);
+		assert_eq!(store.flush_reporting().unwrap(), FlushStats::default());
+	}
+
+	#[test]
+	fn background_flush_counts_toward_the_next_flush() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(2).with_background_flush(true);
+		let mut store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options).unwrap();
+		// The second row fills the memtable, which then builds on its own thread; nothing installs
+		// it before the flush.
+		for i in 0..2u8 {
+			store.commit([(&vec![i], &vec![i])]).unwrap();
+		}
+		let stats = store.flush_reporting().unwrap();
+		assert_eq!((stats.segments_written, stats.rows_flushed), (1, 2));
+		assert_eq!(store.flush_reporting().unwrap(), FlushStats::default());
+	}
+
+	#[test]
+	fn synced_flush_recovers_after_reopen() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(2).with_sync_on_flush(true);
+		{
+			let mut store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options.clone()).unwrap();
+			for i in 0..5u8 {
+				store.commit([(&vec![i], &vec![i; 3])]).unwrap();
+			}
+			store.flush().unwrap();
+			store.multi_way_merge().unwrap();
+		}
+
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options).unwrap();
+		assert_eq!(store.columns[0].read().unwrap().segments.len(), 1);
+		for i in 0..5u8 {
+			assert_eq!(store.get_value(&vec![i]).unwrap(), Some(vec![i; 3]));
+		}
+	}
+
+	#[test]
+	fn unflushed_commits_recover_in_every_column() {
+		// Key columns take a row per commit and flush at the fourth; the value columns hold two
+		// rows and never do. Dropping the store without a flush, or one on drop, stands in for a crash.
+		let crash = |options: StoreOptions| {
+			let dir = tempdir().unwrap();
+			{
+				let options = options.clone().with_flush_on_drop(false);
+				let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::dictionary(0), options).unwrap();
+				for i in 0..6u8 {
+					store.commit([(&vec![i], &vec![i % 2; 4])]).unwrap();
+				}
+			}
+			let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::dictionary(0), options).unwrap();
+			(dir, store)
+		};
+
+		let (_dir, store) = crash(StoreOptions::new(4));
+		assert!(store.verify_consistency().unwrap().is_consistent());
+		for i in 0..6u8 {
+			assert_eq!(store.get_value(&vec![i]).unwrap(), Some(vec![i % 2; 4]));
+		}
+
+		let (_dir, store) = crash(StoreOptions::new(4).with_wal(false));
+		assert!(!store.verify_consistency().unwrap().is_consistent(), "flushed keys point at lost values");
+	}
+
 	#[test]
-	fn writes_and_reads_from_memtable() {
+	fn range_entries_in_one_pass_match_keys_and_values() {
 		let dir = tempdir().unwrap();
-		let mut store =
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0), StoreOptions { segment_size: 10 }).unwrap();
+		let options = StoreOptions::new(8);
+		let store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), options)
+				.unwrap();
+		let mut rows: Vec<_> = (0..40u8).map(|i| (vec![i], vec![b'v', i % 3])).collect();
+		for (k, v) in &rows {
+			store.commit([(k, v)]).unwrap();
+		}
+		// Appended, the overwrite leaves the `v0||key` btree entry of key 3 behind.
+		store.commit([(&vec![3], &vec![b'v', 1])]).unwrap();
+		rows[3].1 = vec![b'v', 1];
+		assert!(store.columns[1].read().unwrap().segments.len() > 1);
+		assert!(store.columns[1].read().unwrap().get(&composite_key(b"v\x00", &[3])).unwrap().is_some());
 
-		store.commit([(&b"key"[..].to_vec(), &b"value"[..].to_vec())]).unwrap();
-		let got = store.get_value(&b"key"[..].to_vec()).unwrap();
-		assert_eq!(got, Some(b"value".to_vec()));
-		assert!(store.columns[0].read().unwrap().segments.is_empty(), "no segment should be flushed yet");
+		for value in [vec![b'v', 0], vec![b'v', 1], vec![b'v', 2], vec![b'w']] {
+			let mut two_pass = Vec::new();
+			for key in store.get_keys_for_value(&value).unwrap() {
+				if let Some(current) = store.get_value(&key).unwrap()
+					&& current == value
+				{
+					two_pass.push((key, current));
+				}
+			}
+			let expected: Vec<_> = rows.iter().filter(|(_, v)| *v == value).cloned().collect();
+			assert_eq!(two_pass, expected);
+			assert_eq!(StoreRead::get_entries_for_value(&store, &value).unwrap(), expected);
+		}
 	}
 
 	#[test]
-	fn flushes_to_segment_and_recovers() {
+	fn dropped_store_keeps_unflushed_commits() {
+		// Without the log, only the flush on drop gets the memtables to disk.
+		let reopened = |flush_on_drop: bool| {
+			let dir = tempdir().unwrap();
+			let options = StoreOptions::new(100).with_wal(false).with_flush_on_drop(flush_on_drop);
+			{
+				let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), options.clone()).unwrap();
+				for i in 0..5u8 {
+					store.commit([(&vec![i], &vec![i % 2])]).unwrap();
+				}
+			}
+			let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), options).unwrap();
+			(dir, store)
+		};
+
+		let (_dir, store) = reopened(true);
+		for i in 0..5u8 {
+			assert_eq!(store.get_value(&vec![i]).unwrap(), Some(vec![i % 2]));
+		}
+		assert_eq!(store.get_keys_for_value(&vec![1]).unwrap(), vec![vec![1], vec![3]]);
+
+		let (_dir, store) = reopened(false);
+		assert_eq!(store.get_value(&vec![0]).unwrap(), None);
+	}
+
+	#[test]
+	fn torn_log_tail_rolls_back_only_the_last_commit() {
 		let dir = tempdir().unwrap();
 		{
-			let mut store =
-				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0), StoreOptions { segment_size: 2 }).unwrap();
-			store.commit([
-				(&b"a"[..].to_vec(), &b"1"[..].to_vec()),
-				(&b"b"[..].to_vec(), &b"2"[..].to_vec()),
-			]).unwrap();
-			store.flush().unwrap();
-			assert_eq!(store.columns[0].read().unwrap().segments.len(), 1);
+			let options = StoreOptions::new(100).with_flush_on_drop(false);
+			let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), options).unwrap();
+			for i in 0..3u8 {
+				store.commit([(&vec![i], &vec![7])]).unwrap();
+			}
 		}
+		let wal_path = dir.path().join(crate::wal::WAL_FILE);
+		let logged = fs::metadata(&wal_path).unwrap().len();
+		let mut tail = fs::OpenOptions::new().append(true).open(&wal_path).unwrap();
+		std::io::Write::write_all(&mut tail, &[40, 0, 0, 0, 1, 2, 3, 4, 0, 9]).unwrap();
+		drop(tail);
 
-		let store =
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0), StoreOptions { segment_size: 2 }).unwrap();
-		assert_eq!(store.get_value(&b"a"[..].to_vec()).unwrap(), Some(b"1".to_vec()));
-		assert_eq!(store.get_value(&b"b"[..].to_vec()).unwrap(), Some(b"2".to_vec()));
+		let mut store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), StoreOptions::new(100)).unwrap();
+		assert_eq!(fs::metadata(&wal_path).unwrap().len(), logged);
+		assert_eq!(store.get_keys_for_value(&vec![7]).unwrap(), vec![vec![0], vec![1], vec![2]]);
+		assert!(store.verify_consistency().unwrap().is_consistent());
+
+		store.flush().unwrap();
+		assert_eq!(fs::metadata(&wal_path).unwrap().len(), 0);
 	}
 
 	#[test]
 	fn picks_latest_value_across_segments() {
 		let dir = tempdir().unwrap();
 		let mut store =
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0), StoreOptions { segment_size: 1 }).unwrap();
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(1)).unwrap();
 		store.commit([(&b"k"[..].to_vec(), &b"old"[..].to_vec())]).unwrap();
 		store.flush().unwrap();
 		store.commit([(&b"k"[..].to_vec(), &b"new"[..].to_vec())]).unwrap();
@@ -443,7 +1880,7 @@ mod tests {
 	fn range_lookup_deduplicates() {
 		let dir = tempdir().unwrap();
 		let mut store =
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::range(0), StoreOptions { segment_size: 2 }).unwrap();
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), StoreOptions::new(2)).unwrap();
 		let entries = [
 			(&b"k1"[..].to_vec(), &b"v1"[..].to_vec()),
 			(&b"k2"[..].to_vec(), &b"v1"[..].to_vec()),
@@ -458,37 +1895,617 @@ mod tests {
 		assert_eq!(sorted, vec![b"k1".to_vec(), b"k2".to_vec(), b"k3".to_vec()]);
 	}
 
+	#[test]
+	fn limited_range_lookup_returns_the_smallest_keys() {
+		let dir = tempdir().unwrap();
+		let store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), StoreOptions::new(8)).unwrap();
+		let keys: Vec<Vec<u8>> = (0..40u8).rev().map(|i| vec![b'k', i]).collect();
+		let value = b"v".to_vec();
+		store.commit(keys.iter().map(|k| (k, &value))).unwrap();
+
+		let limited = store.get_keys_for_value_limited(&value, 3).unwrap();
+		assert_eq!(limited, vec![vec![b'k', 0], vec![b'k', 1], vec![b'k', 2]]);
+		assert_eq!(store.get_keys_for_value(&value).unwrap().len(), 40);
+		assert_eq!(store.get_key_for_value(&value).unwrap(), Some(vec![b'k', 0]));
+	}
+
+	#[test]
+	fn backup_keeps_the_snapshot_while_the_original_moves_on() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("store");
+		let dest = dir.path().join("backup");
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::range(0), StoreOptions::new(4)).unwrap();
+		let pairs: Vec<(Vec<u8>, Vec<u8>)> = (0..10u8).map(|i| (vec![i], vec![i % 3])).collect();
+		store.commit(pairs.iter().map(|(k, v)| (k, v))).unwrap();
+		// Rows 8 and 9 are still in the memtables; the backup flushes them.
+		store.backup(&dest).unwrap();
+		assert!(store.backup(&dest).is_err(), "an existing destination is never overwritten");
+
+		let (k, v) = (vec![0], b"changed".to_vec());
+		store.commit([(&k, &v)]).unwrap();
+		store.settle_for_reads().unwrap();
+		store.clear().unwrap();
+		assert_eq!(store.get_value(&vec![1]).unwrap(), None);
+
+		let backup =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&dest, Layout::range(0)).unwrap();
+		for (k, v) in &pairs {
+			assert_eq!(backup.get_value(k).unwrap(), Some(v.clone()));
+		}
+		assert_eq!(backup.get_keys_for_value(&vec![0]).unwrap(), vec![vec![0], vec![3], vec![6], vec![9]]);
+		for col in &backup.columns {
+			let col = col.read().unwrap();
+			let manifest = fs::read_to_string(manifest_path(&dest, col.id)).unwrap();
+			assert_eq!(manifest.lines().count(), col.segments.len());
+		}
+		assert!(!dir.path().join("backup.partial").exists());
+	}
+
 	#[test]
 	fn shared_basic_suite() {
-		let options = StoreOptions { segment_size: 3 };
-		basic_value_roundtrip(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0), options).unwrap()
+		let options = StoreOptions::new(3);
+		basic_value_roundtrip(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), options.clone()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_variable_length_keys_suite() {
+		let options = StoreOptions::new(64);
+		variable_length_keys_roundtrip(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), options.clone()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_clear_suite() {
+		let options = StoreOptions::new(1);
+		clear_then_reuse(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), options.clone()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_read_only_suite() {
+		let dir = tempdir().unwrap();
+		read_only_rejects_writes(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(2)).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::plain(0)).unwrap(),
+		);
+	}
+
+	#[test]
+	fn shared_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::dictionary(0), StoreOptions::new(2)).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(2)),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::range(0)),
+		);
+	}
+
+	#[test]
+	fn shared_untagged_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		untagged_mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::dictionary(0), StoreOptions::new(2)).unwrap(),
+			|| fs::write(dir.path().join(STORE_MARKER), "").unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(2)),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::plain(0)),
+		);
+	}
+
+	#[test]
+	fn shared_crash_recovery_suite() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(64).with_recovery_mode(RecoveryMode::SkipCorrupt);
+		crash_recovery("store::tests::shared_crash_recovery_suite", dir.path(), |path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), options.clone()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_sorted_commit_suite() {
+		let options = StoreOptions::new(64);
+		sorted_commit_matches_unsorted(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), options.clone()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_dictionary_birth_key_suite() {
+		let options = StoreOptions::new(2);
+		dictionary_birth_key_stable(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), options.clone()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_dictionary_commit_stats_suite() {
+		let options = StoreOptions::new(2);
+		dictionary_commit_stats(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), options.clone()).unwrap()
 		});
 	}
 
 	#[test]
 	fn shared_reverse_suite() {
-		let options = StoreOptions { segment_size: 2 };
-		reverse_lookup_unique(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::unique_index(0), options).unwrap()
+		let options = StoreOptions::new(2);
+		reverse_lookup_unique(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::unique_index(0), options.clone()).unwrap()
 		});
 	}
 
 	#[test]
 	fn shared_multiple_keys_suite() {
-		let options = StoreOptions { segment_size: 2 };
-		multiple_keys_for_value(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::range(0), options).unwrap()
+		let options = StoreOptions::new(2);
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			multiple_keys_for_value(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options.clone()).unwrap()
+			});
+		}
+	}
+
+	struct LeU16Codec;
+
+	impl StoreCodec<u16> for LeU16Codec {
+		type Error = StoreError;
+		type Enc<'a> = [u8; 2];
+		fn encode<'a>(value: &'a u16) -> Self::Enc<'a> {
+			value.to_le_bytes()
+		}
+		fn decode(bytes: &[u8]) -> StoreResult<u16> {
+			Ok(u16::from_le_bytes(bytes.try_into().map_err(|_| StoreError::InvalidInput("u16".into()))?))
+		}
+	}
+
+	#[test]
+	fn sorted_keys_for_value_follow_the_decoded_order() {
+		let dir = tempdir().unwrap();
+		let store =
+			Store::<u16, Vec<u8>, LeU16Codec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), StoreOptions::new(2)).unwrap();
+		let v = b"v".to_vec();
+		store.commit([1u16, 256, 2, 513].iter().map(|k| (k, &v))).unwrap();
+		assert_eq!(store.get_keys_for_value(&v).unwrap(), [256, 1, 513, 2]);
+		assert_eq!(StoreRead::get_keys_for_value_sorted(&store, &v).unwrap(), [1, 2, 256, 513]);
+	}
+
+	#[test]
+	fn shared_batched_keys_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			batched_keys_for_values(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(8)).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_readers_suite() {
+		assert_send_sync::<SharedStore<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>();
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			shared_readers(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(8)).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn raw_values_suite() {
+		for layout in [Layout::plain(0), Layout::range(0), Layout::dictionary(0)] {
+			raw_values::<BytesCodec, _, _>(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(8)).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_prefix_values_suite() {
+		let options = StoreOptions::new(2);
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			prefix_values_isolated(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options.clone()).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn fst_key_prefix_suites() {
+		// Values are mapped, so records are also scanned through the mmap, not only the file.
+		let options = StoreOptions::new(2).with_fst_key_prefix(3).with_mmap_values(1 << 20);
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options.clone()).unwrap()
+		};
+		basic_value_roundtrip(|path| open(path, Layout::plain(0)));
+		variable_length_keys_roundtrip(|path| open(path, Layout::plain(0)));
+		for layout in [Layout::plain(0), Layout::range(0), Layout::dictionary(0)] {
+			raw_values::<BytesCodec, _, _>(|path| open(path, layout));
+			key_bounds(|path| open(path, layout));
+		}
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			multiple_keys_for_value(|path| open(path, layout));
+			prefix_values_isolated(|path| open(path, layout));
+		}
+	}
+
+	#[test]
+	fn sharded_segments_suites() {
+		let options = StoreOptions::new(2).with_segments_per_dir(3);
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options.clone()).unwrap()
+		};
+		basic_value_roundtrip(|path| open(path, Layout::plain(0)));
+		variable_length_keys_roundtrip(|path| open(path, Layout::plain(0)));
+		clear_then_reuse(|path| open(path, Layout::plain(0)));
+		for layout in [Layout::plain(0), Layout::range(0), Layout::dictionary(0)] {
+			raw_values::<BytesCodec, _, _>(|path| open(path, layout));
+			key_bounds(|path| open(path, layout));
+		}
+
+		let dir = tempdir().unwrap();
+		let rows: Vec<_> = (0u32..50).map(|i| (i.to_be_bytes().to_vec(), i.to_le_bytes().to_vec())).collect();
+		{
+			let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), options.clone())
+				.unwrap();
+			for chunk in rows.chunks(5) {
+				store.commit(chunk.iter().map(|(k, v)| (k, v))).unwrap();
+			}
+		}
+		assert!(dir.path().join(format!("col0_shard{:020}", 1)).is_dir());
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), options).unwrap();
+		let backup = tempdir().unwrap();
+		store.backup(&backup.path().join("copy")).unwrap();
+		let copy = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&backup.path().join("copy"), Layout::range(0))
+			.unwrap();
+		for store in [&store, &copy] {
+			for (k, v) in &rows {
+				assert_eq!(store.get_value(k).unwrap().as_ref(), Some(v));
+				assert_eq!(store.get_keys_for_value(v).unwrap(), vec![k.clone()]);
+			}
+			assert!(store.verify_consistency().unwrap().is_consistent());
+		}
+	}
+
+	#[test]
+	fn shared_coalesce_suite() {
+		let open = |path: &Path, layout, coalesce| {
+			let options = StoreOptions::new(2).with_coalesce_duplicates(coalesce);
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+		};
+		coalesced_duplicate_keys(
+			|path, c| open(path, Layout::plain(0), c),
+			|path, c| open(path, Layout::unique_index(0), c),
+		);
+	}
+
+	#[test]
+	fn shared_settle_suite() {
+		settle_keeps_contents(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), StoreOptions::new(16))
+				.unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_key_bounds_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			key_bounds(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(2)).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_approximate_key_count_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			approximate_key_count(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(4096)).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_expiring_suite() {
+		// Segments of 4 rows, so the purge rewrites several and drops shadowed versions as well.
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(4).with_expiring(true))
+		};
+		expiring_entries(|path| open(path, Layout::plain(0)).unwrap());
+		assert!(open(tempdir().unwrap().path(), Layout::range(0)).is_err());
+	}
+
+	#[test]
+	fn rejected_options_leave_no_store() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("store");
+		let options = StoreOptions::new(4).with_expiring(true);
+		let opened = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::range(0), options);
+		assert!(matches!(opened, Err(StoreError::InvalidInput(_))));
+		assert!(!path.exists());
+		let mode = OpenMode::CreateNew;
+		Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_mode(&path, Layout::range(0), StoreOptions::new(4), mode)
+			.unwrap();
+	}
+
+	#[test]
+	fn key_bounds_span_segments_and_memtables() {
+		let dir = tempdir().unwrap();
+		let options = StoreOptions::new(4)
+			.with_memtable(MemtableKind::SortedVec)
+			.with_background_flush(true)
+			.with_merge_threshold(1_000);
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options)
+				.unwrap();
+		let key = |i: u32| i.to_be_bytes().to_vec();
+		// Scrambled so the global bounds land in different segments, never the first or the newest.
+		let order: Vec<u32> = (0..64u32).map(|i| (i * 37 + 11) % 64).collect();
+		for &i in &order {
+			store.commit([(&key(i), &key(i))]).unwrap();
+		}
+		assert!(store.columns[0].read().unwrap().segments.len() > 4);
+		assert_eq!(store.first_key().unwrap(), Some(key(0)));
+		assert_eq!(store.last_key().unwrap(), Some(key(63)));
+		// Shorter keys sort before their extensions, and longer ones after.
+		store.commit([(&vec![0u8], &vec![1u8]), (&vec![0xff; 6], &vec![1u8])]).unwrap();
+		assert_eq!(store.first_key().unwrap(), Some(vec![0u8]));
+		assert_eq!(store.last_key().unwrap(), Some(vec![0xff; 6]));
+		store.flush().unwrap();
+		store.multi_way_merge().unwrap();
+		assert_eq!(store.first_key().unwrap(), Some(vec![0u8]));
+		assert_eq!(store.last_key().unwrap(), Some(vec![0xff; 6]));
+	}
+
+	#[test]
+	fn shared_distinct_values_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(2)).unwrap()
+		};
+		dictionary_distinct_values(|path| open(path, Layout::dictionary(0)), |path| open(path, Layout::range(0)));
+	}
+
+	#[test]
+	fn shared_empty_values_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(2)).unwrap()
+		};
+		empty_values(
+			|path| open(path, Layout::plain(0)),
+			|path| open(path, Layout::unique_index(0)),
+			|path| open(path, Layout::range(0)),
+			|path| open(path, Layout::dictionary(0)),
+		);
+	}
+
+	#[test]
+	fn shared_consistency_audit_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, StoreOptions::new(16)).unwrap()
+		};
+		consistency_audit(|path| open(path, Layout::unique_index(0)), |store, k, v| {
+			store.columns[1].write().unwrap().insert(v.to_vec(), b"elsewhere".to_vec()).unwrap();
+			vec![Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() }]
+		});
+		// Columns never delete, so the primary moves to a value the btree does not hold.
+		consistency_audit(|path| open(path, Layout::range(0)), |store, k, _| {
+			store.columns[0].write().unwrap().insert(k.to_vec(), b"moved".to_vec()).unwrap();
+			vec![Mismatch::MissingBtreeEntry { head: b"moved".to_vec(), key: k.to_vec() }]
+		});
+		consistency_audit(|path| open(path, Layout::dictionary(0)), |store, k, _| {
+			store.columns[0].write().unwrap().insert(k.to_vec(), b"ghost".to_vec()).unwrap();
+			vec![
+				Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: b"ghost".to_vec() },
+				Mismatch::MissingBtreeEntry { head: b"ghost".to_vec(), key: k.to_vec() },
+			]
+		});
+	}
+
+	#[test]
+	fn shared_open_modes_suite() {
+		let dir = tempdir().unwrap();
+		open_modes(dir.path(), |path, mode| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_mode(
+				path,
+				Layout::plain(0),
+				StoreOptions::new(2),
+				mode,
+			)
+		});
+	}
+
+	#[test]
+	fn shared_builder_paths_suite() {
+		let dir = tempdir().unwrap();
+		builder_paths(dir.path(), "store.fst", |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain(0))
+				.segment_size(2)
+				.coalesce_duplicates(true)
+		});
+	}
+
+	#[test]
+	fn shared_separate_wal_dir_suite() {
+		let dir = tempdir().unwrap();
+		separate_wal_dir(dir.path(), |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::dictionary(0)).segment_size(64)
 		});
+		assert!(dir.path().join("wal").join(crate::wal::WAL_FILE).is_file());
+		assert!(!dir.path().join("data").join(crate::wal::WAL_FILE).exists());
+	}
+
+	#[test]
+	fn compaction_stats_track_merges() {
+		let dir = tempdir().unwrap();
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(1)).unwrap();
+		for i in 0..8u8 {
+			store.commit([(&vec![i], &vec![i])]).unwrap();
+		}
+		store.commit([(&vec![0u8], &b"overwrite".to_vec())]).unwrap();
+		store.multi_way_merge().unwrap();
+
+		// Background merges may race the explicit one, but together they must account for the work.
+		let stats = CompactionStats::total(&store.compaction_stats());
+		assert!(stats.merges >= 1);
+		assert!(stats.rows_merged >= 4);
+		assert!(stats.bytes_reclaimed > 0);
+		assert_eq!(stats.backlog_warnings, 0, "nine segments stay below the default warning");
+	}
+
+	#[test]
+	fn compaction_falling_behind_flushes_is_reported() {
+		const BIG_ROWS: u32 = 100_000;
+		let dir = tempdir().unwrap();
+		let open = |segment_size| {
+			let options = StoreOptions::new(segment_size).with_merge_threshold(2).with_segment_backlog_warning(8);
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options).unwrap()
+		};
+		// A big segment every background merge has to rewrite, which makes each of them slow.
+		{
+			let mut store = open(BIG_ROWS as usize + 1);
+			let rows: Vec<_> = (0..BIG_ROWS).map(|i| (i.to_be_bytes().to_vec(), vec![0u8; 16])).collect();
+			store.commit(rows.iter().map(|(k, v)| (k, v))).unwrap();
+			store.flush().unwrap();
+		}
+		let store = open(1);
+		// Every single-row commit flushes a segment, far faster than a merge rewrites the big one.
+		let started = Instant::now();
+		let mut commits = 0u32;
+		while CompactionStats::total(&store.compaction_stats()).backlog_warnings == 0 {
+			assert!(started.elapsed() < Duration::from_secs(60), "no backlog warning after {commits} commits");
+			store.commit([(&(BIG_ROWS + commits).to_be_bytes().to_vec(), &vec![1u8])]).unwrap();
+			commits += 1;
+		}
+		let stats = store.compaction_stats()[0];
+		assert!(stats.max_segments > 8, "{stats:?}");
+		assert!(stats.merges >= 1, "the warning is raised once a slow merge hands back");
+	}
+
+	#[test]
+	fn reads_see_latest_writes_during_compaction() {
+		reads_see_latest_writes(StoreOptions::new(16));
+	}
+
+	#[test]
+	fn reads_see_latest_writes_during_background_flush() {
+		reads_see_latest_writes(StoreOptions::new(16).with_background_flush(true));
+	}
+
+	fn reads_see_latest_writes(options: StoreOptions) {
+		use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+		use std::thread;
+
+		const KEYS: u32 = 64;
+		const ROUNDS: u32 = 40;
+		let dir = tempdir().unwrap();
+		let store = Arc::new(RwLock::new(
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options).unwrap(),
+		));
+		// Number of fully committed rounds; a read must never return a round older than the last one.
+		let committed = Arc::new(AtomicU32::new(0));
+		let done = Arc::new(AtomicBool::new(false));
+
+		let readers: Vec<_> = (0..3)
+			.map(|r| {
+				let store = store.clone();
+				let committed = committed.clone();
+				let done = done.clone();
+				thread::spawn(move || {
+					let mut k = r;
+					while !done.load(Ordering::Acquire) {
+						let floor = committed.load(Ordering::Acquire);
+						if floor == 0 {
+							continue
+						}
+						let key = (k % KEYS).to_be_bytes().to_vec();
+						let got = store.read().unwrap().get_value(&key).unwrap().expect("committed key must be readable");
+						let round = u32::from_be_bytes(got.try_into().unwrap());
+						assert!(round + 1 >= floor, "read round {round} older than committed {}", floor - 1);
+						k = k.wrapping_add(7);
+					}
+				})
+			})
+			.collect();
+
+		for round in 0..ROUNDS {
+			let value = round.to_be_bytes().to_vec();
+			let keys: Vec<Vec<u8>> = (0..KEYS).map(|k| k.to_be_bytes().to_vec()).collect();
+			store.write().unwrap().commit(keys.iter().map(|k| (k, &value))).unwrap();
+			committed.store(round + 1, Ordering::Release);
+			if round % 10 == 9 {
+				store.write().unwrap().multi_way_merge().unwrap();
+			}
+		}
+		done.store(true, Ordering::Release);
+		for r in readers {
+			r.join().unwrap();
+		}
+
+		let store = store.read().unwrap();
+		for k in 0..KEYS {
+			let got = store.get_value(&k.to_be_bytes().to_vec()).unwrap();
+			assert_eq!(got, Some((ROUNDS - 1).to_be_bytes().to_vec()));
+		}
+	}
+
+	#[test]
+	fn gc_shrinks_values_of_overwritten_keys() {
+		let dir = tempdir().unwrap();
+		let val_bytes = || -> u64 {
+			fs::read_dir(dir.path())
+				.unwrap()
+				.map(|e| e.unwrap().path())
+				.filter(|p| p.extension().is_some_and(|ext| ext == "val"))
+				.map(|p| fs::metadata(p).unwrap().len())
+				.sum()
+		};
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), StoreOptions::new(1_000)).unwrap();
+		let old: Vec<(Vec<u8>, Vec<u8>)> = (0u32..100).map(|i| (i.to_be_bytes().to_vec(), vec![1u8; 64])).collect();
+		let new: Vec<(Vec<u8>, Vec<u8>)> = (0u32..50).map(|i| (i.to_be_bytes().to_vec(), vec![2u8; 64])).collect();
+		store.commit(old.iter().map(|(k, v)| (k, v))).unwrap();
+		store.flush().unwrap();
+		store.commit(new.iter().map(|(k, v)| (k, v))).unwrap();
+		store.flush().unwrap();
+
+		let before = val_bytes();
+		let reclaimed = store.gc().unwrap();
+		assert!(reclaimed > 0);
+		assert!(val_bytes() < before);
+		for i in 0u32..100 {
+			let expected = if i < 50 { vec![2u8; 64] } else { vec![1u8; 64] };
+			assert_eq!(store.get_value(&i.to_be_bytes().to_vec()).unwrap(), Some(expected));
+		}
+		assert_eq!(store.gc().unwrap(), 0);
+	}
+
+	#[test]
+	fn boxed_as_dyn_store() {
+		let dir = tempdir().unwrap();
+		let mut store: Box<dyn DynStore> = Box::new(
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::unique_index(0), StoreOptions::new(2)).unwrap(),
+		);
+		store.commit(&[(b"k".to_vec(), b"v".to_vec())]).unwrap();
+		store.flush().unwrap();
+		assert_eq!(store.get_value(b"k").unwrap(), Some(b"v".to_vec()));
+		assert_eq!(store.get_key_for_value(b"v").unwrap(), Some(b"k".to_vec()));
+		assert!(matches!(store.get_keys_for_value(b"v"), Err(BenchError::Store(_))));
+	}
+
+	#[test]
+	fn dir_size_grows_with_flushed_segments() {
+		let dir = tempdir().unwrap();
+		let mut store =
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), StoreOptions::new(1_000)).unwrap();
+		let empty = dir_size(dir.path());
+		let items: Vec<(Vec<u8>, Vec<u8>)> = (0u32..100).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8; 16])).collect();
+		store.commit(items.iter().map(|(k, v)| (k, v))).unwrap();
+		store.flush().unwrap();
+		assert!(dir_size(dir.path()) > empty);
+		let kinds = dir_size_by_kind(dir.path());
+		assert!(kinds["fst"] > 0 && kinds["val"] > 0, "{kinds:?}");
+		assert_eq!(kinds.values().sum::<u64>(), dir_size(dir.path()));
 	}
 
 	#[test]
@@ -508,21 +2525,26 @@ mod tests {
 		let size = compute_segment_size(0, 64, DEFAULT_MEMTABLE_BUDGET_BYTES);
 		assert_eq!(size, MIN_SEGMENT_ROWS);
 	}
-}
-pub const MIN_SEGMENT_ROWS: usize = 200_000;
-const TARGET_MAX_SEGMENTS: u64 = 32;
-pub const DEFAULT_MEMTABLE_BUDGET_BYTES: usize = 2 * 1024 * 1024 * 1024; // 2GB
-
-fn compute_segment_size(approx_rows: u64, avg_kv_bytes: usize, mem_budget_bytes: usize) -> usize {
-	let avg_kv = avg_kv_bytes.max(1);
-	let desired_by_segments = if approx_rows == 0 {
-		MIN_SEGMENT_ROWS as u64
-	} else {
-		let per_seg = (approx_rows + TARGET_MAX_SEGMENTS - 1) / TARGET_MAX_SEGMENTS;
-		per_seg.max(MIN_SEGMENT_ROWS as u64)
-	};
 
-	let mem_cap_rows = (mem_budget_bytes.max(1) / avg_kv).max(1);
-	let chosen = desired_by_segments as usize;
-	chosen.min(mem_cap_rows).max(1)
+	#[test]
+	fn workload_profiles_yield_distinct_options() {
+		let rows = 100_000_000;
+		let write = StoreOptions::profile(Workload::WriteHeavy, rows);
+		let balanced = StoreOptions::profile(Workload::Balanced, rows);
+		let read = StoreOptions::profile(Workload::ReadHeavy, rows);
+		assert_eq!(
+			[write.segment_size, balanced.segment_size, read.segment_size],
+			[12_500_000, 3_125_000, 781_250]
+		);
+		assert_eq!([write.merge_threshold, balanced.merge_threshold, read.merge_threshold], [16, 4, 2]);
+		assert_eq!(
+			[write.memtable, balanced.memtable, read.memtable],
+			[MemtableKind::SortedVec, MemtableKind::BTree, MemtableKind::BTree]
+		);
+		assert_eq!([write.sync_on_flush, balanced.sync_on_flush, read.sync_on_flush], [false, false, true]);
+		let defaults = StoreOptions::from_estimates(rows, PROFILE_AVG_KV_BYTES, DEFAULT_MEMTABLE_BUDGET_BYTES);
+		assert_eq!((balanced.segment_size, balanced.merge_threshold), (defaults.segment_size, defaults.merge_threshold));
+		// Small estimates fall back to the minimum segment size whatever the profile.
+		assert_eq!(StoreOptions::profile(Workload::ReadHeavy, 1_000).segment_size, MIN_SEGMENT_ROWS);
+	}
 }
@@ -0,0 +1,135 @@
+use std::{
+	fs::{File, OpenOptions},
+	io::{self, BufReader, Read, Seek, SeekFrom, Write},
+	path::Path,
+};
+
+use crate::store::StoreResult;
+
+pub(crate) const WAL_FILE: &str = "wal.log";
+
+/// Bytes of the length and CRC32 in front of every record.
+const RECORD_HEADER_BYTES: usize = 8;
+
+/// One write of a commit: `key` -> `value` into the column with that index.
+pub(crate) type WalOp = (u8, Vec<u8>, Vec<u8>);
+
+/// Append-only log of the commits since the last full flush, one record per commit, so a crash
+/// cannot leave a commit in some columns' segments but not in others: reopening replays every
+/// logged commit into all of its columns. A record is its payload length and CRC32 (u32 LE each),
+/// then per op the column byte, the u32 LE key length, the key, the u32 LE value length and the
+/// value. Appends reach the OS before the columns see the commit, so they survive the process.
+pub(crate) struct Wal {
+	file: File,
+	len: u64,
+}
+
+impl Wal {
+	/// Opens the log under `dir` and hands every op of its intact records to `replay`, in commit
+	/// order. A torn last record, left by a crash in the middle of an append, never reached the
+	/// columns; it is cut off, rolling its commit back.
+	pub(crate) fn open(dir: &Path, mut replay: impl FnMut(WalOp) -> StoreResult<()>) -> StoreResult<Self> {
+		let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(dir.join(WAL_FILE))?;
+		let mut reader = BufReader::new(&mut file);
+		let mut len = 0u64;
+		while let Some(ops) = read_record(&mut reader)? {
+			len += (RECORD_HEADER_BYTES + ops.len()) as u64;
+			for op in decode_ops(&ops) {
+				replay(op)?;
+			}
+		}
+		file.set_len(len)?;
+		file.seek(SeekFrom::Start(len))?;
+		Ok(Self { file, len })
+	}
+
+	/// Logs one commit as a single record.
+	pub(crate) fn append(&mut self, ops: &[WalOp]) -> StoreResult<()> {
+		let payload_len: usize = ops.iter().map(|(_, k, v)| 1 + 4 + k.len() + 4 + v.len()).sum();
+		let mut record = Vec::with_capacity(RECORD_HEADER_BYTES + payload_len);
+		record.extend_from_slice(&[0; RECORD_HEADER_BYTES]);
+		for (col, key, value) in ops {
+			record.push(*col);
+			record.extend_from_slice(&(key.len() as u32).to_le_bytes());
+			record.extend_from_slice(key);
+			record.extend_from_slice(&(value.len() as u32).to_le_bytes());
+			record.extend_from_slice(value);
+		}
+		let crc = crc32fast::hash(&record[RECORD_HEADER_BYTES..]);
+		record[..4].copy_from_slice(&(payload_len as u32).to_le_bytes());
+		record[4..RECORD_HEADER_BYTES].copy_from_slice(&crc.to_le_bytes());
+		self.file.write_all(&record)?;
+		self.len += record.len() as u64;
+		Ok(())
+	}
+
+	/// Bytes logged since the last `reset`.
+	pub(crate) fn len(&self) -> u64 {
+		self.len
+	}
+
+	/// Empties the log once every logged commit is in the segments.
+	pub(crate) fn reset(&mut self) -> StoreResult<()> {
+		self.file.set_len(0)?;
+		self.file.seek(SeekFrom::Start(0))?;
+		self.len = 0;
+		Ok(())
+	}
+}
+
+/// The payload of the next record, `None` at the end of the log or at a torn or corrupt record.
+fn read_record(reader: &mut impl Read) -> StoreResult<Option<Vec<u8>>> {
+	let mut header = [0u8; RECORD_HEADER_BYTES];
+	if !read_full(reader, &mut header)? {
+		return Ok(None)
+	}
+	let len = u32::from_le_bytes(header[..4].try_into().expect("4 bytes")) as usize;
+	let crc = u32::from_le_bytes(header[4..].try_into().expect("4 bytes"));
+	let mut payload = vec![0u8; len];
+	if !read_full(reader, &mut payload)? || crc32fast::hash(&payload) != crc || !ops_fit(&payload) {
+		return Ok(None)
+	}
+	Ok(Some(payload))
+}
+
+/// Fills `buf`, returning false if the log ends first.
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+	match reader.read_exact(buf) {
+		Ok(()) => Ok(true),
+		Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+		Err(err) => Err(err),
+	}
+}
+
+/// Whether `payload` splits into whole ops, so `decode_ops` cannot run past its end.
+fn ops_fit(payload: &[u8]) -> bool {
+	let mut rest = payload;
+	while !rest.is_empty() {
+		let Some(after_key) = skip_field(&rest[1..]) else { return false };
+		let Some(after_value) = skip_field(after_key) else { return false };
+		rest = after_value;
+	}
+	true
+}
+
+/// `bytes` past one length-prefixed field, `None` if it does not fit.
+fn skip_field(bytes: &[u8]) -> Option<&[u8]> {
+	let len = u32::from_le_bytes(bytes.get(..4)?.try_into().ok()?) as usize;
+	bytes.get(4..)?.get(len..)
+}
+
+fn decode_ops(payload: &[u8]) -> impl Iterator<Item = WalOp> + '_ {
+	let mut rest = payload;
+	std::iter::from_fn(move || {
+		let (&col, after_col) = rest.split_first()?;
+		let (key, after_key) = read_field(after_col);
+		let (value, after_value) = read_field(after_key);
+		rest = after_value;
+		Some((col, key.to_vec(), value.to_vec()))
+	})
+}
+
+fn read_field(bytes: &[u8]) -> (&[u8], &[u8]) {
+	let len = u32::from_le_bytes(bytes[..4].try_into().expect("checked by ops_fit")) as usize;
+	bytes[4..].split_at(len)
+}
@@ -1,9 +1,25 @@
-use core::store_interface::{ProgressTracker, StoreRead, StoreWrite, StoreCodec};
+use core::dyn_store::BenchError;
+use core::inspect::KeyDump;
+use core::store_builder::{self, BuilderOptions};
+use core::store_interface::{
+	CommitStats, ConsistencyReport, ExpiringStore, Mismatch, OpenMode, ProgressTracker, Retryable, StoreRead,
+	StoreWrite, StoreCodec, NEVER_EXPIRES, check_columns, check_expiring, check_indexed_value, check_layout,
+	coalesce_by_encoded_key, column_id, composite_key, composite_prefix, decode_exact, expiry_after, is_transient_io,
+	sort_by_encoded_key, sort_by_encoding, split_composite, stored_value, unexpired, unix_millis,
+};
 use libmdbx::{
 	Database, DatabaseOptions, Mode, NoWriteMap, ReadWriteOptions, RO, RW, SyncMode, Table, TableFlags, Transaction,
-	WriteFlags,
+	TransactionKind, WriteFlags,
+};
+use std::{
+	collections::HashMap,
+	marker::PhantomData,
+	ops::Deref,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	thread::{self, ThreadId},
+	time::Duration,
 };
-use std::{ffi::OsStr, fs, marker::PhantomData, path::{Path, PathBuf}};
 
 #[derive(Debug)]
 pub enum StoreError {
@@ -22,6 +38,19 @@ impl std::fmt::Display for StoreError {
 
 impl std::error::Error for StoreError {}
 
+/// `Busy` and a full reader table clear up once the other transactions finish.
+impl Retryable for StoreError {
+	fn is_retryable(&self) -> bool {
+		matches!(self, StoreError::Mdbx(libmdbx::Error::Busy | libmdbx::Error::ReadersFull))
+	}
+}
+
+impl From<StoreError> for BenchError {
+	fn from(err: StoreError) -> Self {
+		BenchError::store(err)
+	}
+}
+
 impl From<libmdbx::Error> for StoreError {
 	fn from(err: libmdbx::Error) -> Self {
 		StoreError::Mdbx(err)
@@ -36,6 +65,21 @@ impl From<std::io::Error> for StoreError {
 
 pub type StoreResult<T> = Result<T, StoreError>;
 
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MdbxOptions {
+	/// Skip writes a later one in the same commit overwrites; see `coalesce_by_encoded_key`.
+	pub coalesce_duplicates: bool,
+	/// `Plain` only: keep each value behind its expiry for `ExpiringStore`. A store must always
+	/// be opened with the setting it was created with.
+	pub expiring: bool,
+}
+
+impl BuilderOptions for MdbxOptions {
+	fn set_coalesce_duplicates(&mut self, coalesce: bool) {
+		self.coalesce_duplicates = coalesce;
+	}
+}
+
 #[derive(Clone, Copy)]
 pub enum Layout {
 	Plain { key_to_value: usize },
@@ -63,6 +107,16 @@ impl Layout {
 		}
 	}
 
+	/// Name the `LAYOUT_TABLE` records at creation, so a reopen with another layout fails.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Layout::Plain { .. } => "plain",
+			Layout::UniqueIndex { .. } => "unique_index",
+			Layout::Range { .. } => "range",
+			Layout::Dictionary { .. } => "dictionary",
+		}
+	}
+
 	fn table_count(&self) -> usize {
 		match self {
 			Layout::Plain { .. } => 1,
@@ -71,6 +125,18 @@ impl Layout {
 			Layout::Dictionary { .. } => 4,
 		}
 	}
+
+	/// The column ids the layout uses, which name its `col<id>` tables.
+	fn columns(&self) -> Vec<usize> {
+		match *self {
+			Layout::Plain { key_to_value } => vec![key_to_value],
+			Layout::UniqueIndex { key_to_value, value_to_key } => vec![key_to_value, value_to_key],
+			Layout::Range { key_to_value, value_key_btree } => vec![key_to_value, value_key_btree],
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
+				vec![key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree]
+			}
+		}
+	}
 }
 
 pub struct Store<K, V, KC, VC>
@@ -78,35 +144,171 @@ where
 	KC: StoreCodec<K, Error = StoreError>,
 	VC: StoreCodec<V, Error = StoreError>,
 {
-	db: Database<NoWriteMap>,
+	// The pooled transactions claim `'static` but borrow the `Database` in `db`. That holds while:
+	// - `read_txns` is declared before `db`, so it is dropped first;
+	// - `db` is boxed and never replaced, so the `Database` stays put when the store moves;
+	// - `into_database` empties `read_txns` before it moves the `Database` out of the box.
+	// libmdbx has no `renew` for a pooled transaction to pick up a commit, so every write through
+	// the store drops them all (`drop_read_txns`); it takes `&mut self`, so no `PooledTxn` is out.
+	read_txns: Mutex<HashMap<ThreadId, IdleTxn>>,
+	db: Box<Database<NoWriteMap>>,
 	layout: Layout,
+	read_only: bool,
+	coalesce_duplicates: bool,
+	expiring: bool,
 	progress: Option<ProgressTracker>,
 	_ph: PhantomData<(K, V, KC, VC)>,
 }
 
+/// A `Store` shared across threads: every read takes `&self`, and the store is `Send + Sync` for
+/// `Send + Sync` key and value types. Every thread reads through a read transaction of its own from
+/// the pool, so readers do not block one another; commits take `&mut self` and need a lock of the
+/// caller's.
+pub type SharedStore<K, V, KC, VC> = Arc<Store<K, V, KC, VC>>;
+
 impl<K, V, KC, VC> Store<K, V, KC, VC>
 where
 	KC: StoreCodec<K, Error = StoreError>,
 	VC: StoreCodec<V, Error = StoreError>,
 {
 	pub fn open(path: &Path, layout: Layout) -> StoreResult<Self> {
-		Self::open_with_options(path, layout, ())
+		Self::open_with_options(path, layout, MdbxOptions::default())
 	}
 
-	pub fn open_with_options(path: &Path, layout: Layout, _options: ()) -> StoreResult<Self> {
+	pub fn open_with_options(path: &Path, layout: Layout, options: MdbxOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
 		let db_path = db_file_path(path)?;
 		let rw_opts = ReadWriteOptions { sync_mode: SyncMode::UtterlyNoSync, ..Default::default() };
-		let opts = DatabaseOptions { max_tables: Some(layout.table_count() as u64), mode: Mode::ReadWrite(rw_opts), ..Default::default() };
-		let db = Database::open_with_options(&db_path, opts)?;
+		let max_tables = Some(layout.table_count() as u64 + 1);
+		let opts = DatabaseOptions { max_tables, mode: Mode::ReadWrite(rw_opts), ..Default::default() };
+		Self::from_database_with_options(Database::open_with_options(&db_path, opts)?, layout, options)
+	}
+
+	/// Builds the layout on a read-write environment the caller already opened, e.g. one holding
+	/// tables of its own, creating the layout's tables if missing; its `max_tables` must leave room
+	/// for them and `LAYOUT_TABLE`. `into_database` hands the environment back.
+	pub fn from_database(db: Database<NoWriteMap>, layout: Layout) -> StoreResult<Self> {
+		Self::from_database_with_options(db, layout, MdbxOptions::default())
+	}
+
+	pub fn from_database_with_options(db: Database<NoWriteMap>, layout: Layout, options: MdbxOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
 		{
 			let tx = db.begin_rw_txn()?;
-			for idx in 0..layout.table_count() {
+			let tag = tx.create_table(Some(LAYOUT_TABLE), TableFlags::empty())?;
+			match tx.get::<Vec<u8>>(&tag, LAYOUT_TABLE.as_bytes())? {
+				Some(stored) => {
+					check_layout(&String::from_utf8_lossy(&stored), layout.name()).map_err(StoreError::InvalidInput)?
+				},
+				// A new store, or one from before the layout was recorded.
+				None => {
+					check_columns(stored_table_ids(&tx)?, layout.name(), layout.columns())
+						.map_err(StoreError::InvalidInput)?;
+					tx.put(&tag, LAYOUT_TABLE.as_bytes(), layout.name().as_bytes(), WriteFlags::empty())?
+				},
+			}
+			for idx in layout.columns() {
 				let name = table_name(idx);
 				tx.create_table(Some(&name), TableFlags::empty())?;
 			}
 			tx.commit()?;
 		}
-		Ok(Self { db, layout, progress: None, _ph: PhantomData })
+		Ok(Self {
+			read_txns: Mutex::default(),
+			db: Box::new(db),
+			layout,
+			read_only: false,
+			coalesce_duplicates: options.coalesce_duplicates,
+			expiring: options.expiring,
+			progress: None,
+			_ph: PhantomData,
+		})
+	}
+
+	/// `open_with_options` after checking `mode` against whether `path` already holds a store.
+	pub fn open_with_mode(path: &Path, layout: Layout, options: MdbxOptions, mode: OpenMode) -> StoreResult<Self> {
+		mode.check(path, store_exists(path)).map_err(StoreError::InvalidInput)?;
+		Self::open_with_options(path, layout, options)
+	}
+
+	/// Opens without creating tables, so every table must already exist.
+	pub fn open_read_only(path: &Path, layout: Layout) -> StoreResult<Self> {
+		Self::open_read_only_with_options(path, layout, MdbxOptions::default())
+	}
+
+	/// `open_read_only` of a store created with `options`; only `expiring` matters to reads.
+	pub fn open_read_only_with_options(path: &Path, layout: Layout, options: MdbxOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
+		let db_path = db_file_path(path)?;
+		let max_tables = Some(layout.table_count() as u64 + 1);
+		let opts = DatabaseOptions { max_tables, mode: Mode::ReadOnly, ..Default::default() };
+		let db = Database::open_with_options(&db_path, opts)?;
+		{
+			let tx = db.begin_ro_txn()?;
+			let stored = match tx.open_table(Some(LAYOUT_TABLE)) {
+				Ok(tag) => tx.get::<Vec<u8>>(&tag, LAYOUT_TABLE.as_bytes())?,
+				Err(libmdbx::Error::NotFound) => None,
+				Err(err) => return Err(err.into()),
+			};
+			match stored {
+				Some(stored) => {
+					check_layout(&String::from_utf8_lossy(&stored), layout.name()).map_err(StoreError::InvalidInput)?
+				},
+				None => check_columns(stored_table_ids(&tx)?, layout.name(), layout.columns())
+					.map_err(StoreError::InvalidInput)?,
+			}
+		}
+		Ok(Self {
+			read_txns: Mutex::default(),
+			db: Box::new(db),
+			layout,
+			read_only: true,
+			coalesce_duplicates: false,
+			expiring: options.expiring,
+			progress: None,
+			_ph: PhantomData,
+		})
+	}
+
+	/// The environment under the store, once its pooled read transactions are gone.
+	pub fn into_database(self) -> Database<NoWriteMap> {
+		let Self { read_txns, db, .. } = self;
+		drop(read_txns);
+		*db
+	}
+
+	fn ensure_writable(&self) -> StoreResult<()> {
+		if self.read_only {
+			return Err(StoreError::InvalidInput("store is opened read-only".into()))
+		}
+		Ok(())
+	}
+
+	/// A read transaction of this thread's, reused across lookups until a commit or `clear`
+	/// through this store drops it; until then it keeps reading the snapshot it began on.
+	fn read_txn(&self) -> StoreResult<PooledTxn<'_>> {
+		let idle = self.read_txns.lock().unwrap().remove(&thread::current().id());
+		let txn = match idle {
+			Some(IdleTxn(txn)) => txn,
+			// SAFETY: only the lifetime is extended. The transaction borrows the `Database` in
+			// `db`'s box, which outlives everything in `read_txns` (see the invariants on the
+			// field). Out of the pool it is a `PooledTxn` borrowing the store, which hands it out
+			// only as a `Transaction<'_>` of that borrow, so no table or cursor opened through it
+			// outlives the store either.
+			None => unsafe {
+				std::mem::transmute::<Transaction<'_, RO, NoWriteMap>, Transaction<'static, RO, NoWriteMap>>(
+					self.db.begin_ro_txn()?,
+				)
+			},
+		};
+		Ok(PooledTxn { pool: &self.read_txns, txn: Some(txn) })
+	}
+
+	/// Drops the pooled read transactions of every thread, which do not see what was just written
+	/// and would pin their snapshot's pages against reuse; each thread begins a new one on its next
+	/// read.
+	fn drop_read_txns(&mut self) {
+		self.read_txns.get_mut().unwrap().clear();
 	}
 
 	pub fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
@@ -115,7 +317,59 @@ where
 		K: 'a,
 		V: 'a,
 	{
-		let mut processed = 0u64;
+		self.commit_counted(items).map(|_| ())
+	}
+
+	/// With `coalesce_duplicates` set, a write the batch overwrites later is skipped.
+	pub fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.commit_expiring(items, self.expiring.then_some(NEVER_EXPIRES))
+	}
+
+	/// `commit` of `Plain` entries that `get_value` stops returning once `ttl` has passed.
+	pub fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.ensure_expiring()?;
+		self.commit_expiring(items, Some(expiry_after(ttl))).map(|_| ())
+	}
+
+	/// `expires_at` is the expiry of every entry, `None` for a store without expiring entries.
+	fn commit_expiring<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		if self.coalesce_duplicates {
+			self.write_counted(coalesce_by_encoded_key::<K, V, KC, _>(items), expires_at)
+		} else {
+			self.write_counted(items, expires_at)
+		}
+	}
+
+	fn ensure_expiring(&self) -> StoreResult<()> {
+		if !self.expiring {
+			return Err(StoreError::InvalidInput("store is not opened with expiring entries".into()))
+		}
+		Ok(())
+	}
+
+	fn write_counted<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.ensure_writable()?;
+		let mut stats = CommitStats::default();
 		let txn = self.db.begin_rw_txn()?;
 		match self.layout {
 			Layout::Plain { key_to_value } => {
@@ -123,8 +377,9 @@ where
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					txn.put(&table, kbytes.as_ref(), vbytes.as_ref(), WriteFlags::empty())?;
-					processed += 1;
+					let stored = stored_value(vbytes.as_ref(), expires_at);
+					txn.put(&table, kbytes.as_ref(), stored.as_ref(), WriteFlags::empty())?;
+					stats.record(kbytes.as_ref(), &stored);
 				}
 			},
 			Layout::UniqueIndex { key_to_value, value_to_key } => {
@@ -133,9 +388,11 @@ where
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
 					txn.put(&t_k2v, kbytes.as_ref(), vbytes.as_ref(), WriteFlags::empty())?;
 					txn.put(&t_v2k, vbytes.as_ref(), kbytes.as_ref(), WriteFlags::empty())?;
-					processed += 2;
+					stats.record(kbytes.as_ref(), vbytes.as_ref());
+					stats.record(vbytes.as_ref(), kbytes.as_ref());
 				}
 			},
 			Layout::Range { key_to_value, value_key_btree } => {
@@ -145,9 +402,10 @@ where
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
 					txn.put(&t_k2v, kbytes.as_ref(), vbytes.as_ref(), WriteFlags::empty())?;
-					let vk = concat(vbytes.as_ref(), kbytes.as_ref());
+					let vk = composite_key(vbytes.as_ref(), kbytes.as_ref());
 					txn.put(&t_vkb, vk.as_slice(), &[], WriteFlags::empty())?;
-					processed += 2;
+					stats.record(kbytes.as_ref(), vbytes.as_ref());
+					stats.record(&vk, &[]);
 				}
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
@@ -156,56 +414,76 @@ where
 				let t_pk2v = open_table(&txn, birth_key_to_value)?;
 				let t_v2pk = open_table(&txn, value_to_birth_key)?;
 				let t_pk_k = open_table(&txn, birth_key_key_btree)?;
-				let mut cache: HashMap<Vec<u8>, (Vec<u8>, bool)> = HashMap::new();
+				let mut cache: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					let (pk, is_new) = if let Some(entry) = cache.get(vbytes.as_ref()) {
-						entry.clone()
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
+					let (pk, is_new) = if let Some(pk) = cache.get(vbytes.as_ref()) {
+						(pk.clone(), false)
 					} else if let Some(pk) = txn.get::<Vec<u8>>(&t_v2pk, vbytes.as_ref())? {
 						let pk_vec = pk;
-						cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), false));
+						cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, false)
 					} else {
 						let pk_vec = kbytes.as_ref().to_vec();
-						cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), true));
+						cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, true)
 					};
 
 					if is_new {
 						txn.put(&t_v2pk, vbytes.as_ref(), pk.as_slice(), WriteFlags::empty())?;
 						txn.put(&t_pk2v, pk.as_slice(), vbytes.as_ref(), WriteFlags::empty())?;
-						processed += 2;
+						stats.record(vbytes.as_ref(), &pk);
+						stats.record(&pk, vbytes.as_ref());
 					}
 					txn.put(&t_k2pk, kbytes.as_ref(), pk.as_slice(), WriteFlags::empty())?;
-					let pk_key = concat(&pk, kbytes.as_ref());
+					let pk_key = composite_key(&pk, kbytes.as_ref());
 					txn.put(&t_pk_k, pk_key.as_slice(), &[], WriteFlags::empty())?;
-					processed += 2;
+					stats.record(kbytes.as_ref(), &pk);
+					stats.record(&pk_key, &[]);
 				}
 			},
 		}
 		txn.commit()?;
+		self.drop_read_txns();
 		if let Some(p) = self.progress.as_mut() {
-			p.record(processed);
+			p.record(stats.rows);
 		}
-		Ok(())
+		Ok(stats)
 	}
 
 	pub fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		self.read_value(key, VC::decode)
+	}
+
+	/// `key`'s value as stored, `VC::encode` of what `get_value` returns, without decoding it.
+	pub fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		self.read_value(key, |bytes| Ok(bytes.to_vec()))
+	}
+
+	/// Passes the encoded value of `key`, stripped of its expiry, to `read`.
+	fn read_value<T>(&self, key: &K, read: impl FnOnce(&[u8]) -> StoreResult<T>) -> StoreResult<Option<T>> {
 		let kbytes = KC::encode(key);
-		let txn = self.db.begin_ro_txn()?;
+		let txn = self.read_txn()?;
 		match self.layout {
+			Layout::Plain { key_to_value } if self.expiring => {
+				let table = open_table_ro(&txn, key_to_value)?;
+				let Some(stored) = txn.get::<Vec<u8>>(&table, kbytes.as_ref())? else { return Ok(None) };
+				let value = unexpired(&stored, unix_millis()).map_err(StoreError::InvalidInput)?;
+				value.map(read).transpose()
+			},
 			Layout::Plain { key_to_value }
 			| Layout::UniqueIndex { key_to_value, .. }
 			| Layout::Range { key_to_value, .. } => {
 				let table = open_table_ro(&txn, key_to_value)?;
-				txn.get::<Vec<u8>>(&table, kbytes.as_ref())?.map(|v| VC::decode(&v)).transpose()
+				txn.get::<Vec<u8>>(&table, kbytes.as_ref())?.map(|v| read(&v)).transpose()
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, .. } => {
 				let t_k2pk = open_table_ro(&txn, key_to_birth_key)?;
 				let t_pk2v = open_table_ro(&txn, birth_key_to_value)?;
 				if let Some(pk) = txn.get::<Vec<u8>>(&t_k2pk, kbytes.as_ref())? {
-					txn.get::<Vec<u8>>(&t_pk2v, pk.as_slice())?.map(|v| VC::decode(&v)).transpose()
+					txn.get::<Vec<u8>>(&t_pk2v, pk.as_slice())?.map(|v| read(&v)).transpose()
 				} else {
 					Ok(None)
 				}
@@ -213,32 +491,42 @@ where
 		}
 	}
 
+	/// What the store holds for `key`: its encoding and that of its value, printing as hex dumps,
+	/// for a lookup that returns something unexpected.
+	pub fn debug_dump(&self, key: &K) -> StoreResult<KeyDump> {
+		let value = self.get_value(key)?;
+		Ok(KeyDump::new::<K, V, KC, VC>(key, value.as_ref()))
+	}
+
 	pub fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
 		let vbytes = VC::encode(value);
-		let txn = self.db.begin_ro_txn()?;
 		match self.layout {
 			Layout::UniqueIndex { value_to_key, .. } => {
+				let txn = self.read_txn()?;
 				let t_v2k = open_table_ro(&txn, value_to_key)?;
-				txn.get::<Vec<u8>>(&t_v2k, vbytes.as_ref())?.map(|k| KC::decode(&k)).transpose()
+				txn.get::<Vec<u8>>(&t_v2k, vbytes.as_ref())?
+					.map(|k| decode_exact::<K, KC>(&k, StoreError::InvalidInput))
+					.transpose()
 			},
+			Layout::Range { .. } | Layout::Dictionary { .. } => Ok(self.get_keys_for_value(value)?.into_iter().next()),
 			_ => Err(StoreError::InvalidInput("get_key_for_value not supported for this layout".into())),
 		}
 	}
 
 	pub fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
 		let vbytes = VC::encode(value);
-		let txn = self.db.begin_ro_txn()?;
+		let txn = self.read_txn()?;
 		match self.layout {
 			Layout::Range { value_key_btree, .. } => {
 				let table = open_table_ro(&txn, value_key_btree)?;
 				let mut out = Vec::new();
-				let mut iter = txn.cursor(&table)?.into_iter_from::<Vec<u8>, Vec<u8>>(vbytes.as_ref());
+				let prefix = composite_prefix(vbytes.as_ref());
+				let mut iter = txn.cursor(&table)?.into_iter_from::<Vec<u8>, Vec<u8>>(prefix.as_slice());
 				while let Some(Ok((k, _))) = iter.next() {
-					if !k.starts_with(vbytes.as_ref()) {
-						break
+					match split_composite(&k) {
+						Some((head, key_bytes)) if head == vbytes.as_ref() => out.push(KC::decode(key_bytes)?),
+						_ => break,
 					}
-					let key_bytes = &k[vbytes.as_ref().len()..];
-					out.push(KC::decode(key_bytes)?);
 				}
 				Ok(out)
 			},
@@ -247,14 +535,14 @@ where
 				let t_pk_k = open_table_ro(&txn, birth_key_key_btree)?;
 				if let Some(pk) = txn.get::<Vec<u8>>(&t_v2pk, vbytes.as_ref())? {
 					let mut out = Vec::new();
+					let prefix = composite_prefix(&pk);
 					let cursor = txn.cursor(&t_pk_k)?;
-					let mut iter = cursor.into_iter_from::<Vec<u8>, Vec<u8>>(pk.as_slice());
+					let mut iter = cursor.into_iter_from::<Vec<u8>, Vec<u8>>(prefix.as_slice());
 					while let Some(Ok((k, _))) = iter.next() {
-						if !k.starts_with(pk.as_slice()) {
-							break
+						match split_composite(&k) {
+							Some((head, key_bytes)) if head == pk.as_slice() => out.push(KC::decode(key_bytes)?),
+							_ => break,
 						}
-						let key_bytes = &k[pk.len()..];
-						out.push(KC::decode(key_bytes)?);
 					}
 					Ok(out)
 				} else {
@@ -265,15 +553,344 @@ where
 		}
 	}
 
+	/// One read transaction and one btree cursor for the whole batch. Scans run in head order, so
+	/// the cursor moves forward through the btree; `Dictionary` resolves birth keys in value order first.
+	pub fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		let txn = self.read_txn()?;
+		let (btree, heads) = match self.layout {
+			Layout::Range { value_key_btree, .. } => (value_key_btree, sort_by_encoding::<V, VC>(values)),
+			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
+				let t_v2pk = open_table_ro(&txn, value_to_birth_key)?;
+				let mut pks = Vec::with_capacity(values.len());
+				for (idx, vbytes) in sort_by_encoding::<V, VC>(values) {
+					if let Some(pk) = txn.get::<Vec<u8>>(&t_v2pk, &vbytes)? {
+						pks.push((idx, pk));
+					}
+				}
+				pks.sort_by(|a, b| a.1.cmp(&b.1));
+				(birth_key_key_btree, pks)
+			},
+			_ => return Err(StoreError::InvalidInput("get_keys_for_values not supported for this layout".into())),
+		};
+		let table = open_table_ro(&txn, btree)?;
+		let mut cursor = txn.cursor(&table)?;
+		let mut out: Vec<Vec<K>> = values.iter().map(|_| Vec::new()).collect();
+		for (idx, head) in heads {
+			let prefix = composite_prefix(&head);
+			for kv in cursor.iter_from::<Vec<u8>, Vec<u8>>(prefix.as_slice()) {
+				let (k, _) = kv?;
+				match split_composite(&k) {
+					Some((h, key_bytes)) if h == head.as_slice() => out[idx].push(KC::decode(key_bytes)?),
+					_ => break,
+				}
+			}
+		}
+		Ok(out)
+	}
+
+	/// Reads the values up front: the cursor borrows the read transaction, which ends here.
+	pub fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		match self.layout {
+			Layout::Dictionary { birth_key_to_value, .. } => {
+				let txn = self.read_txn()?;
+				let table = open_table_ro(&txn, birth_key_to_value)?;
+				let values = txn
+					.cursor(&table)?
+					.into_iter_start::<Vec<u8>, Vec<u8>>()
+					.map(|kv| kv.map(|(_, v)| v))
+					.collect::<Result<Vec<_>, _>>()?;
+				Ok(values.into_iter().map(|v| VC::decode(&v)))
+			},
+			_ => Err(StoreError::InvalidInput("distinct_values not supported for this layout".into())),
+		}
+	}
+
+	/// The first key in the primary table, in encoded key order.
+	pub fn first_key(&self) -> StoreResult<Option<K>> {
+		let txn = self.read_txn()?;
+		let table = open_table_ro(&txn, self.primary_column())?;
+		txn.cursor(&table)?.first::<Vec<u8>, ()>()?.map(|(k, _)| KC::decode(&k)).transpose()
+	}
+
+	/// The last key in the primary table, in encoded key order.
+	pub fn last_key(&self) -> StoreResult<Option<K>> {
+		let txn = self.read_txn()?;
+		let table = open_table_ro(&txn, self.primary_column())?;
+		txn.cursor(&table)?.last::<Vec<u8>, ()>()?.map(|(k, _)| KC::decode(&k)).transpose()
+	}
+
+	/// The entry count MDBX keeps in the primary table's stats, exact and without a scan.
+	pub fn approx_key_count(&self) -> StoreResult<u64> {
+		let txn = self.read_txn()?;
+		let table = open_table_ro(&txn, self.primary_column())?;
+		Ok(txn.table_stat(&table)?.entries() as u64)
+	}
+
+	/// The table holding every key: `key_to_value`, or `key_to_birth_key` for `Dictionary`.
+	fn primary_column(&self) -> usize {
+		match self.layout {
+			Layout::Plain { key_to_value }
+			| Layout::UniqueIndex { key_to_value, .. }
+			| Layout::Range { key_to_value, .. } => key_to_value,
+			Layout::Dictionary { key_to_birth_key, .. } => key_to_birth_key,
+		}
+	}
+
+	/// Checks the columns against one read transaction.
+	pub fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		let mut report = ConsistencyReport::default();
+		let txn = self.read_txn()?;
+		match self.layout {
+			Layout::Plain { .. } => {},
+			Layout::UniqueIndex { key_to_value, value_to_key } => {
+				let t_k2v = open_table_ro(&txn, key_to_value)?;
+				let t_v2k = open_table_ro(&txn, value_to_key)?;
+				for kv in txn.cursor(&t_k2v)?.into_iter_start::<Vec<u8>, Vec<u8>>() {
+					let (k, v) = kv?;
+					report.checked += 1;
+					if txn.get::<Vec<u8>>(&t_v2k, &v)?.as_ref() != Some(&k) {
+						report.mismatches.push(Mismatch::MissingBackEdge { key: k, value: v });
+					}
+				}
+			},
+			Layout::Range { key_to_value, value_key_btree } => {
+				let t_k2v = open_table_ro(&txn, key_to_value)?;
+				let t_vkb = open_table_ro(&txn, value_key_btree)?;
+				for kv in txn.cursor(&t_k2v)?.into_iter_start::<Vec<u8>, Vec<u8>>() {
+					let (k, v) = kv?;
+					report.checked += 1;
+					if txn.get::<Vec<u8>>(&t_vkb, &composite_key(&v, &k))?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: v, key: k });
+					}
+				}
+			},
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, birth_key_key_btree, .. } => {
+				let t_k2pk = open_table_ro(&txn, key_to_birth_key)?;
+				let t_pk2v = open_table_ro(&txn, birth_key_to_value)?;
+				let t_pk_k = open_table_ro(&txn, birth_key_key_btree)?;
+				for kv in txn.cursor(&t_k2pk)?.into_iter_start::<Vec<u8>, Vec<u8>>() {
+					let (k, pk) = kv?;
+					report.checked += 1;
+					if txn.get::<Vec<u8>>(&t_pk2v, &pk)?.is_none() {
+						report.mismatches.push(Mismatch::DanglingBirthKey { key: k.clone(), birth_key: pk.clone() });
+					}
+					if txn.get::<Vec<u8>>(&t_pk_k, &composite_key(&pk, &k))?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: pk, key: k });
+					}
+				}
+			},
+		}
+		Ok(report)
+	}
+
+	/// `commit` in encoded-key order with duplicate keys collapsed to the last write; MDBX appends
+	/// to the rightmost page when keys arrive ascending.
+	pub fn commit_sorted<'a, I>(&mut self, items: I) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		let expires_at = self.expiring.then_some(NEVER_EXPIRES);
+		self.write_counted(sort_by_encoded_key::<K, V, KC, _>(items), expires_at).map(|_| ())
+	}
+
 	pub fn flush(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		Ok(())
+	}
+
+	/// MDBX's B+tree has no compaction step; a forced sync is the checkpoint that writes out what
+	/// `UtterlyNoSync` commits left in the page cache.
+	pub fn settle_for_reads(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		self.db.sync(true)?;
+		Ok(())
+	}
+
+	/// Deletes the expired entries of an expiring `Plain` store in one write transaction.
+	pub fn purge_expired(&mut self) -> StoreResult<u64> {
+		self.ensure_writable()?;
+		self.ensure_expiring()?;
+		let Layout::Plain { key_to_value } = self.layout else { return Ok(0) };
+		let now = unix_millis();
+		let mut purged = 0;
+		let txn = self.db.begin_rw_txn()?;
+		{
+			let table = open_table(&txn, key_to_value)?;
+			let mut expired = Vec::new();
+			for kv in txn.cursor(&table)?.into_iter_start::<Vec<u8>, Vec<u8>>() {
+				let (k, stored) = kv?;
+				if matches!(unexpired(&stored, now), Ok(None)) {
+					expired.push(k);
+				}
+			}
+			for k in &expired {
+				purged += txn.del(&table, k, None)? as u64;
+			}
+		}
+		txn.commit()?;
+		self.drop_read_txns();
+		Ok(purged)
+	}
+
+	/// Removes `keys` with their entries in the layout's other tables in one write transaction,
+	/// returning how many were present. A `Dictionary` birth key's `birth_key_to_value` and
+	/// `value_to_birth_key` entries go with the last key holding it, found by scanning its
+	/// `birth_key||key` prefix in `birth_key_key_btree`; entries there of keys since overwritten
+	/// with another value are stale and dropped on the way.
+	pub fn delete<'a, I>(&mut self, keys: I) -> StoreResult<u64>
+	where
+		I: IntoIterator<Item = &'a K>,
+		K: 'a,
+	{
+		self.ensure_writable()?;
+		let mut deleted = 0;
+		let txn = self.db.begin_rw_txn()?;
+		match self.layout {
+			Layout::Plain { key_to_value } => {
+				let table = open_table(&txn, key_to_value)?;
+				for k in keys {
+					deleted += txn.del(&table, KC::encode(k).as_ref(), None)? as u64;
+				}
+			},
+			Layout::UniqueIndex { key_to_value, value_to_key } => {
+				let t_k2v = open_table(&txn, key_to_value)?;
+				let t_v2k = open_table(&txn, value_to_key)?;
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(v) = txn.get::<Vec<u8>>(&t_k2v, kbytes.as_ref())? else { continue };
+					txn.del(&t_k2v, kbytes.as_ref(), None)?;
+					deleted += 1;
+					// The value may have moved to another key since.
+					if txn.get::<Vec<u8>>(&t_v2k, &v)?.is_some_and(|owner| owner == kbytes.as_ref()) {
+						txn.del(&t_v2k, &v, None)?;
+					}
+				}
+			},
+			Layout::Range { key_to_value, value_key_btree } => {
+				let t_k2v = open_table(&txn, key_to_value)?;
+				let t_vkb = open_table(&txn, value_key_btree)?;
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(v) = txn.get::<Vec<u8>>(&t_k2v, kbytes.as_ref())? else { continue };
+					txn.del(&t_k2v, kbytes.as_ref(), None)?;
+					deleted += 1;
+					txn.del(&t_vkb, composite_key(&v, kbytes.as_ref()), None)?;
+				}
+			},
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
+				let t_k2pk = open_table(&txn, key_to_birth_key)?;
+				let t_pk2v = open_table(&txn, birth_key_to_value)?;
+				let t_v2pk = open_table(&txn, value_to_birth_key)?;
+				let t_pk_k = open_table(&txn, birth_key_key_btree)?;
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(pk) = txn.get::<Vec<u8>>(&t_k2pk, kbytes.as_ref())? else { continue };
+					txn.del(&t_k2pk, kbytes.as_ref(), None)?;
+					deleted += 1;
+					txn.del(&t_pk_k, composite_key(&pk, kbytes.as_ref()), None)?;
+					let (mut live, mut stale) = (false, Vec::new());
+					let prefix = composite_prefix(&pk);
+					for entry in txn.cursor(&t_pk_k)?.into_iter_from::<Vec<u8>, ()>(prefix.as_slice()) {
+						let (entry, ()) = entry?;
+						match split_composite(&entry) {
+							Some((head, key)) if head == pk.as_slice() => {
+								if txn.get::<Vec<u8>>(&t_k2pk, key)?.is_some_and(|owner| owner == pk) {
+									live = true;
+								} else {
+									stale.push(entry);
+								}
+							},
+							_ => break,
+						}
+					}
+					for entry in &stale {
+						txn.del(&t_pk_k, entry, None)?;
+					}
+					if live {
+						continue
+					}
+					if let Some(v) = txn.get::<Vec<u8>>(&t_pk2v, &pk)? {
+						txn.del(&t_pk2v, &pk, None)?;
+						if txn.get::<Vec<u8>>(&t_v2pk, &v)?.is_some_and(|owner| owner == pk) {
+							txn.del(&t_v2pk, &v, None)?;
+						}
+					}
+				}
+			},
+		}
+		txn.commit()?;
+		self.drop_read_txns();
+		Ok(deleted)
+	}
+
+	pub fn clear(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		let txn = self.db.begin_rw_txn()?;
+		for idx in self.layout.columns() {
+			let table = open_table(&txn, idx)?;
+			txn.clear_table(&table)?;
+		}
+		txn.commit()?;
+		self.drop_read_txns();
 		Ok(())
 	}
 }
 
+/// A pooled read transaction; only ever taken out of the pool by the thread that began it.
+struct IdleTxn(Transaction<'static, RO, NoWriteMap>);
+
+// SAFETY: libmdbx opens every environment with `MDBX_NOTLS`, so a read transaction is not bound
+// to the thread that began it and may be aborted from another: `drop_read_txns` aborts every
+// thread's on the writing thread, and dropping the store aborts them on whichever thread drops it.
+// Only its use is confined: the pool hands a transaction back only to the thread it is keyed
+// under, which takes it out of the pool first, so no two threads ever use one at once.
+unsafe impl Send for IdleTxn {}
+
+/// A read transaction out of the pool, returned to it for this thread on drop.
+struct PooledTxn<'s> {
+	pool: &'s Mutex<HashMap<ThreadId, IdleTxn>>,
+	txn: Option<Transaction<'static, RO, NoWriteMap>>,
+}
+
+// Narrows the `'static` back to the store borrow, so what is opened through the transaction
+// cannot be kept past it.
+impl<'s> Deref for PooledTxn<'s> {
+	type Target = Transaction<'s, RO, NoWriteMap>;
+
+	fn deref(&self) -> &Self::Target {
+		self.txn.as_ref().expect("taken only on drop")
+	}
+}
+
+impl Drop for PooledTxn<'_> {
+	fn drop(&mut self) {
+		if let Some(txn) = self.txn.take() {
+			self.pool.lock().unwrap().insert(thread::current().id(), IdleTxn(txn));
+		}
+	}
+}
+
+/// Holds the name of the store's layout under its own name as the key.
+const LAYOUT_TABLE: &str = "layout";
+
 fn table_name(idx: usize) -> String {
 	format!("col{idx}")
 }
 
+/// The ids of the `col<id>` tables the environment holds, for `check_columns`; a store from before
+/// `LAYOUT_TABLE` has no other record of its layout.
+fn stored_table_ids<K: TransactionKind>(txn: &Transaction<'_, K, NoWriteMap>) -> StoreResult<Vec<usize>> {
+	let main = txn.open_table(None)?;
+	let mut ids = Vec::new();
+	for entry in txn.cursor(&main)?.into_iter_start::<Vec<u8>, ()>() {
+		let (name, ()) = entry?;
+		ids.extend(column_id(&String::from_utf8_lossy(&name)));
+	}
+	Ok(ids)
+}
+
 fn open_table<'txn>(txn: &'txn Transaction<'txn, RW, NoWriteMap>, idx: usize) -> StoreResult<Table<'txn>> {
 	let name = table_name(idx);
 	let flags = TableFlags::empty();
@@ -287,25 +904,154 @@ fn open_table_ro<'txn>(txn: &'txn Transaction<'txn, RO, NoWriteMap>, idx: usize)
 	Ok(table)
 }
 
-fn concat(a: &[u8], b: &[u8]) -> Vec<u8> {
-	let mut out = Vec::with_capacity(a.len() + b.len());
-	out.extend_from_slice(a);
-	out.extend_from_slice(b);
-	out
+/// The database file of a store at `path`; see `store_builder::db_file`.
+fn db_file(path: &Path) -> PathBuf {
+	store_builder::db_file(path, "mdbx")
 }
 
 fn db_file_path(path: &Path) -> StoreResult<PathBuf> {
-	if path.extension() == Some(OsStr::new("mdbx")) {
-		return Ok(path.to_path_buf())
+	Ok(store_builder::db_file_path(path, "mdbx")?)
+}
+
+/// Whether `path` holds a store; libmdbx makes `db_file` a file or, by default, a directory.
+fn store_exists(path: &Path) -> bool {
+	db_file(path).exists()
+}
+
+impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	type Error = StoreError;
+
+	fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		Store::get_value(self, key)
+	}
+
+	fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		Store::get_value_raw(self, key)
+	}
+
+	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
+		Store::get_key_for_value(self, value)
+	}
+
+	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
+		Store::get_keys_for_value(self, value)
+	}
+
+	fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		Store::get_keys_for_values(self, values)
+	}
+
+	fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		Store::distinct_values(self)
+	}
+
+	fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		Store::verify_consistency(self)
+	}
+
+	fn first_key(&self) -> StoreResult<Option<K>> {
+		Store::first_key(self)
+	}
+
+	fn last_key(&self) -> StoreResult<Option<K>> {
+		Store::last_key(self)
+	}
+
+	fn approx_key_count(&self) -> StoreResult<u64> {
+		Store::approx_key_count(self)
+	}
+}
+
+impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	type Options = MdbxOptions;
+	type Layout = Layout;
+
+	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
+		Store::open_with_options(path, layout, options)
+	}
+
+	fn open_with_mode(path: &Path, layout: Self::Layout, options: Self::Options, mode: OpenMode) -> StoreResult<Self> {
+		Store::open_with_mode(path, layout, options, mode)
+	}
+
+	fn open_read_only(path: &Path, layout: Self::Layout) -> StoreResult<Self> {
+		Store::open_read_only(path, layout)
+	}
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_counted(self, items)
+	}
+
+	fn commit_sorted<'a, I>(&mut self, items: I) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_sorted(self, items)
+	}
+
+	fn flush(&mut self) -> StoreResult<()> {
+		Store::flush(self)
+	}
+
+	fn settle_for_reads(&mut self) -> StoreResult<()> {
+		Store::settle_for_reads(self)
+	}
+
+	fn clear(&mut self) -> StoreResult<()> {
+		Store::clear(self)
+	}
+
+	fn set_progress_tracker(&mut self, tracker: ProgressTracker) {
+		self.progress = Some(tracker);
+	}
+}
+
+impl<K, V, KC, VC> ExpiringStore<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_with_ttl(self, items, ttl)
+	}
+
+	fn purge_expired(&mut self) -> StoreResult<u64> {
+		Store::purge_expired(self)
 	}
-	fs::create_dir_all(path)?;
-	Ok(path.join("db.mdbx"))
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use core::store_tests::{basic_value_roundtrip, multiple_keys_for_value, reverse_lookup_unique};
+	use core::store_tests::{
+		approximate_key_count, assert_send_sync, basic_value_roundtrip, batched_keys_for_values, builder_paths,
+		clear_then_reuse, coalesced_duplicate_keys, consistency_audit, dictionary_birth_key_stable,
+		dictionary_commit_stats, dictionary_delete, dictionary_distinct_values, empty_values, expiring_entries,
+		key_bounds, mismatched_layout, multiple_keys_for_value, open_modes, prefix_values_isolated, raw_values,
+		read_only_rejects_writes, reverse_lookup_unique, shared_readers, settle_keeps_contents,
+		sorted_commit_matches_unsorted, untagged_mismatched_layout, variable_length_keys_roundtrip,
+	};
+	use core::store_builder::StoreBuilder;
 	use tempfile::tempdir;
 
 	struct BytesCodec;
@@ -323,81 +1069,402 @@ mod tests {
 
 	#[test]
 	fn shared_basic_suite() {
-		basic_value_roundtrip(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().join("db.mdbx");
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::plain(0), ()).unwrap()
+		basic_value_roundtrip(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0)).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_variable_length_keys_suite() {
+		variable_length_keys_roundtrip(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0)).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_clear_suite() {
+		clear_then_reuse(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary(0)).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_sorted_commit_suite() {
+		sorted_commit_matches_unsorted(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0)).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_dictionary_birth_key_suite() {
+		dictionary_birth_key_stable(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary(0)).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_dictionary_commit_stats_suite() {
+		dictionary_commit_stats(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary(0)).unwrap()
 		});
 	}
 
 	#[test]
 	fn shared_reverse_suite() {
-		reverse_lookup_unique(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().join("db.mdbx");
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::unique_index(0), ()).unwrap()
+		reverse_lookup_unique(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::unique_index(0)).unwrap()
 		});
 	}
 
 	#[test]
 	fn shared_multiple_keys_suite() {
-		multiple_keys_for_value(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().join("db.mdbx");
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::range(0), ()).unwrap()
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			multiple_keys_for_value(|path| {
+				let path = path.join("db.mdbx");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_batched_keys_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			batched_keys_for_values(|path| {
+				let path = path.join("db.mdbx");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_readers_suite() {
+		assert_send_sync::<SharedStore<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>();
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			shared_readers(|path| {
+				let path = path.join("db.mdbx");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn raw_values_suite() {
+		for layout in [Layout::plain(0), Layout::range(0), Layout::dictionary(0)] {
+			raw_values::<BytesCodec, _, _>(|path| {
+				let path = path.join("db.mdbx");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_prefix_values_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			prefix_values_isolated(|path| {
+				let path = path.join("db.mdbx");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_coalesce_suite() {
+		let open = |path: &Path, layout, coalesce_duplicates| {
+			let path = path.join("db.mdbx");
+			let options = MdbxOptions { coalesce_duplicates, ..MdbxOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, layout, options).unwrap()
+		};
+		coalesced_duplicate_keys(
+			|path, c| open(path, Layout::plain(0), c),
+			|path, c| open(path, Layout::unique_index(0), c),
+		);
+	}
+
+	#[test]
+	fn shared_settle_suite() {
+		settle_keeps_contents(|path| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0)).unwrap()
 		});
 	}
-}
 
-impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
-where
-	KC: StoreCodec<K, Error = StoreError>,
-	VC: StoreCodec<V, Error = StoreError>,
-{
-	type Error = StoreError;
+	#[test]
+	fn shared_key_bounds_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			key_bounds(|path| {
+				let path = path.join("db.mdbx");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
 
-	fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
-		Store::get_value(self, key)
+	#[test]
+	fn shared_approximate_key_count_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			approximate_key_count(|path| {
+				let path = path.join("db.mdbx");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
 	}
 
-	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
-		Store::get_key_for_value(self, value)
+	#[test]
+	fn shared_expiring_suite() {
+		let open = |path: &Path, layout| {
+			let path = path.join("db.mdbx");
+			let options = MdbxOptions { expiring: true, ..MdbxOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, layout, options)
+		};
+		expiring_entries(|path| open(path, Layout::plain(0)).unwrap());
+		assert!(open(tempdir().unwrap().path(), Layout::range(0)).is_err());
 	}
 
-	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
-		Store::get_keys_for_value(self, value)
+	#[test]
+	fn shared_distinct_values_suite() {
+		let open = |path: &Path, layout| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+		};
+		dictionary_distinct_values(|path| open(path, Layout::dictionary(0)), |path| open(path, Layout::range(0)));
 	}
-}
 
-impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
-where
-	KC: StoreCodec<K, Error = StoreError>,
-	VC: StoreCodec<V, Error = StoreError>,
-{
-	type Options = ();
-	type Layout = Layout;
+	#[test]
+	fn shared_dictionary_delete_suite() {
+		let dir = tempdir().unwrap();
+		dictionary_delete(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::dictionary(0)).unwrap(),
+			|store, keys| store.delete(keys),
+			|store, value| {
+				let txn = store.read_txn().unwrap();
+				let v2pk = open_table_ro(&txn, 2).unwrap();
+				let pk2v = open_table_ro(&txn, 1).unwrap();
+				txn.get::<Vec<u8>>(&v2pk, value).unwrap().is_some()
+					|| txn
+						.cursor(&pk2v)
+						.unwrap()
+						.into_iter_start::<Vec<u8>, Vec<u8>>()
+						.any(|entry| entry.unwrap().1 == value)
+			},
+		);
+	}
 
-	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
-		Store::open_with_options(path, layout, options)
+	#[test]
+	fn shared_empty_values_suite() {
+		let open = |path: &Path, layout| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+		};
+		empty_values(
+			|path| open(path, Layout::plain(0)),
+			|path| open(path, Layout::unique_index(0)),
+			|path| open(path, Layout::range(0)),
+			|path| open(path, Layout::dictionary(0)),
+		);
 	}
 
-	fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
-	where
-		I: IntoIterator<Item = (&'a K, &'a V)>,
-		K: 'a,
-		V: 'a,
-	{
-		Store::commit(self, items)
+	#[test]
+	fn shared_consistency_audit_suite() {
+		let open = |path: &Path, layout| {
+			let path = path.join("db.mdbx");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+		};
+		consistency_audit(|path| open(path, Layout::unique_index(0)), |store, k, v| {
+			let txn = store.db.begin_rw_txn().unwrap();
+			txn.put(&open_table(&txn, 1).unwrap(), v, b"elsewhere", WriteFlags::empty()).unwrap();
+			txn.commit().unwrap();
+			vec![Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() }]
+		});
+		consistency_audit(|path| open(path, Layout::range(0)), |store, k, v| {
+			let txn = store.db.begin_rw_txn().unwrap();
+			txn.del(&open_table(&txn, 1).unwrap(), composite_key(v, k), None).unwrap();
+			txn.commit().unwrap();
+			vec![Mismatch::MissingBtreeEntry { head: v.to_vec(), key: k.to_vec() }]
+		});
+		// Every row has its own value, so each key is its own birth key.
+		consistency_audit(|path| open(path, Layout::dictionary(0)), |store, k, _| {
+			let txn = store.db.begin_rw_txn().unwrap();
+			txn.del(&open_table(&txn, 1).unwrap(), k, None).unwrap();
+			txn.del(&open_table(&txn, 3).unwrap(), composite_key(k, k), None).unwrap();
+			txn.commit().unwrap();
+			vec![
+				Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: k.to_vec() },
+				Mismatch::MissingBtreeEntry { head: k.to_vec(), key: k.to_vec() },
+			]
+		});
 	}
 
-	fn flush(&mut self) -> StoreResult<()> {
-		Store::flush(self)
+	#[test]
+	fn shared_open_modes_suite() {
+		let dir = tempdir().unwrap();
+		open_modes(dir.path(), |path, mode| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_mode(
+				path,
+				Layout::plain(0),
+				MdbxOptions::default(),
+				mode,
+			)
+		});
+	}
+
+	#[test]
+	fn pooled_read_txns_are_dropped_by_commits() {
+		let dir = tempdir().unwrap();
+		let mut store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0)).unwrap();
+		let (k, v1, v2) = (b"k".to_vec(), b"v1".to_vec(), b"v2".to_vec());
+		store.commit([(&k, &v1)]).unwrap();
+		assert_eq!(store.get_value(&k).unwrap(), Some(v1.clone()));
+		assert_eq!(store.get_value(&k).unwrap(), Some(v1));
+		assert_eq!(store.read_txns.lock().unwrap().len(), 1);
+
+		store.commit([(&k, &v2)]).unwrap();
+		assert!(store.read_txns.lock().unwrap().is_empty());
+		assert_eq!(store.get_value(&k).unwrap(), Some(v2.clone()));
+		let other = thread::scope(|s| s.spawn(|| store.get_value(&k).unwrap()).join().unwrap());
+		assert_eq!(other, Some(v2));
+		assert_eq!(store.read_txns.lock().unwrap().len(), 2);
+
+		store.clear().unwrap();
+		assert_eq!(store.get_value(&k).unwrap(), None);
+	}
+
+	#[test]
+	fn pooled_read_txns_of_every_thread_see_a_commit() {
+		const READERS: usize = 4;
+		let dir = tempdir().unwrap();
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0)).unwrap();
+		let store = std::sync::RwLock::new(store);
+		let k = b"k".to_vec();
+		store.write().unwrap().commit([(&k, &b"v0".to_vec())]).unwrap();
+		let (pooled, committed) = (std::sync::Barrier::new(READERS + 1), std::sync::Barrier::new(READERS + 1));
+		thread::scope(|s| {
+			for _ in 0..READERS {
+				s.spawn(|| {
+					for round in 0..3u8 {
+						// Twice, so the second read goes through this thread's pooled transaction.
+						for _ in 0..2 {
+							let expected = format!("v{round}").into_bytes();
+							assert_eq!(store.read().unwrap().get_value(&k).unwrap(), Some(expected));
+						}
+						pooled.wait();
+						committed.wait();
+					}
+				});
+			}
+			for round in 1..=3u8 {
+				pooled.wait();
+				let mut store = store.write().unwrap();
+				assert_eq!(store.read_txns.lock().unwrap().len(), READERS);
+				// Aborts the readers' transactions on this thread while they wait to read again.
+				store.commit([(&k, &format!("v{round}").into_bytes())]).unwrap();
+				assert!(store.read_txns.lock().unwrap().is_empty());
+				drop(store);
+				committed.wait();
+			}
+		});
+		assert_eq!(store.into_inner().unwrap().get_value(&k).unwrap(), Some(b"v3".to_vec()));
+	}
+
+	#[test]
+	fn shared_builder_paths_suite() {
+		let dir = tempdir().unwrap();
+		builder_paths(dir.path(), "store.mdbx", |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain(0))
+				.coalesce_duplicates(true)
+		});
+		assert!(dir.path().join("dir/db.mdbx").exists());
+		assert!(dir.path().join("nested/store.mdbx").exists());
+		assert!(!dir.path().join("nested/store.mdbx/db.mdbx").exists());
+	}
+
+	#[test]
+	fn shared_read_only_suite() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.mdbx");
+		read_only_rejects_writes(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0)).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&path, Layout::plain(0)).unwrap(),
+		);
 	}
 
-	fn set_progress(&mut self, label: &str, total: u64) {
-		self.progress = Some(ProgressTracker::new(label.to_string(), total));
+	#[test]
+	fn shared_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.mdbx");
+		mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary(0)).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0)),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&path, Layout::range(0)),
+		);
+	}
+
+	#[test]
+	fn shared_untagged_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.mdbx");
+		untagged_mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary(0)).unwrap(),
+			|| {
+				let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary(0)).unwrap();
+				let db = store.into_database();
+				let tx = db.begin_rw_txn().unwrap();
+				tx.del(&tx.open_table(Some(LAYOUT_TABLE)).unwrap(), LAYOUT_TABLE.as_bytes(), None).unwrap();
+				tx.commit().unwrap();
+			},
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain(0)),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&path, Layout::plain(0)),
+		);
+	}
+
+	#[test]
+	fn untagged_store_from_a_nonzero_base_reopens_with_its_layout() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.mdbx");
+		let open = |layout| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout);
+		let (k, v) = (b"key".to_vec(), b"value".to_vec());
+		let mut store = open(Layout::range(2)).unwrap();
+		store.commit([(&k, &v)]).unwrap();
+		let db = store.into_database();
+		let tx = db.begin_rw_txn().unwrap();
+		tx.del(&tx.open_table(Some(LAYOUT_TABLE)).unwrap(), LAYOUT_TABLE.as_bytes(), None).unwrap();
+		tx.commit().unwrap();
+		drop(db);
+
+		let err = open(Layout::range(0)).err().expect("reopened untagged from another base");
+		assert!(format!("{err:?}").contains("columns"), "{err:?}");
+		assert_eq!(open(Layout::range(2)).unwrap().get_value(&k).unwrap(), Some(v));
+	}
+
+	#[test]
+	fn wraps_an_open_database_beside_its_own_tables() {
+		let dir = tempdir().unwrap();
+		let opts = DatabaseOptions { max_tables: Some(4), ..Default::default() };
+		let db = Database::<NoWriteMap>::open_with_options(dir.path().join("app.mdbx"), opts).unwrap();
+		let tx = db.begin_rw_txn().unwrap();
+		{
+			let app = tx.create_table(Some("app"), TableFlags::empty()).unwrap();
+			tx.put(&app, b"height", b"7", WriteFlags::empty()).unwrap();
+		}
+		tx.commit().unwrap();
+
+		let mut store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::from_database(db, Layout::unique_index(0)).unwrap();
+		store.commit([(&vec![1], &vec![10]), (&vec![2], &vec![20])]).unwrap();
+		assert_eq!(store.get_value(&vec![1]).unwrap(), Some(vec![10]));
+		assert_eq!(store.get_key_for_value(&vec![20]).unwrap(), Some(vec![2]));
+
+		let db = store.into_database();
+		{
+			let tx = db.begin_ro_txn().unwrap();
+			let app = tx.open_table(Some("app")).unwrap();
+			assert_eq!(tx.get::<Vec<u8>>(&app, b"height").unwrap(), Some(b"7".to_vec()));
+		}
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::from_database(db, Layout::unique_index(0)).unwrap();
+		assert_eq!(store.get_value(&vec![2]).unwrap(), Some(vec![20]));
 	}
 }
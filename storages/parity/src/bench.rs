@@ -1,19 +1,24 @@
-mod store;
-
 use std::path::{Path, PathBuf};
 
 use core::{
-	bench_codecs::{AddressCodec, AmountCodec, InvalidInput, KeyCodec, TimestampCodec, TxCodec},
-	bench_common::{run_all_parallel, run_dictionary, run_index, run_plain, run_range, Address, Amount, Key, NamedJob, Timestamp, TxHash},
+	bench_codecs::{
+		AddressCodec, AmountCodec, DecodeError, InvalidInput, KeyCodec, TimestampCodec, TxCodec, VarKeyCodec,
+	},
+	bench_common::{
+		parse_duration, run_all_parallel, run_dictionary, run_dictionary_reads, run_index, run_mixed, run_plain,
+		run_plain_varkey, run_range, run_reads, run_settle, Address, Amount, BenchLayout, Key, KeyDistribution,
+		KeyLengths, LayoutSet, MixedLoad, NamedJob, ReadLoad, RunLength, Timestamp, TxHash, VarKey,
+	},
+	store_builder::StoreBuilder,
 };
-use store::{Layout, Store, StoreResult};
-use parity_db::Error as PError;
+use parity_bench::store::{Layout, ParityOptions, Store, StoreResult};
+use parity_db::{CompressionType, Error as PError};
 
 struct ParityInvalid;
 
 impl InvalidInput<parity_db::Error> for ParityInvalid {
-	fn invalid_input(msg: &'static str) -> parity_db::Error {
-		parity_db::Error::InvalidInput(msg.into())
+	fn invalid_input(err: DecodeError) -> parity_db::Error {
+		parity_db::Error::InvalidInput(err.to_string())
 	}
 }
 
@@ -22,12 +27,19 @@ type PAmountCodec = AmountCodec<parity_db::Error, ParityInvalid>;
 type PTimestampCodec = TimestampCodec<parity_db::Error, ParityInvalid>;
 type PTxCodec = TxCodec<parity_db::Error, ParityInvalid>;
 type PAddressCodec = AddressCodec<parity_db::Error>;
+type PVarKeyCodec = VarKeyCodec<parity_db::Error>;
 
 fn main() -> StoreResult<()> {
 	let mut args = std::env::args().skip(1);
 	let mut total = 10_000_000u64;
+	let mut duration = None;
+	let mut resume = false;
 	let mut base: Option<PathBuf> = None;
-	let mut benches: Option<Vec<String>> = None;
+	let mut layouts = LayoutSet::default();
+	let mut read_load = ReadLoad::default();
+	let mut mixed_load = None;
+	let mut key_lengths = KeyLengths::default();
+	let mut options = ParityOptions::default();
 
 	while let Some(arg) = args.next() {
 		match arg.as_str() {
@@ -36,14 +48,54 @@ fn main() -> StoreResult<()> {
 					total = v;
 				}
 			},
+			"--resume" => resume = true,
+			"--duration" => {
+				if let Some(d) = args.next().and_then(|s| parse_duration(&s)) {
+					duration = Some(d);
+				}
+			},
 			"--dir" => {
 				if let Some(p) = args.next() {
 					base = Some(PathBuf::from(p));
 				}
 			},
-			"--benches" => {
+			"--key-lengths" => {
+				if let Some((min, max)) = args.next().as_deref().and_then(|s| s.split_once(':'))
+					&& let (Ok(min), Ok(max)) = (min.parse(), max.parse())
+				{
+					key_lengths = KeyLengths { min, max };
+				}
+			},
+			"--compression" => match args.next().as_deref() {
+				Some("none") => options.compression = CompressionType::NoCompression,
+				Some("lz4") => options.compression = CompressionType::Lz4,
+				Some("snappy") => options.compression = CompressionType::Snappy,
+				_ => {},
+			},
+			"--uniform-values" => options.uniform_values = true,
+			"--reads" => {
+				if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+					read_load.reads = v;
+				}
+			},
+			"--distribution" => {
+				if let Some(d) = args.next().and_then(|s| KeyDistribution::parse(&s)) {
+					read_load.distribution = d;
+				}
+			},
+			"--theta" => {
+				if let Some(v) = args.next().and_then(|s| s.parse::<f64>().ok()).filter(|t| *t > 0.0 && *t < 1.0) {
+					read_load.theta = v;
+				}
+			},
+			"--mixed" => {
+				if let Some(load) = args.next().and_then(|s| MixedLoad::parse(&s)) {
+					mixed_load = Some(load);
+				}
+			},
+			"--layouts" | "--benches" => {
 				if let Some(list) = args.next() {
-					benches = Some(list.split(',').map(|s| s.to_string()).collect());
+					layouts = LayoutSet::parse(&list).unwrap_or_else(|err| panic!("--layouts: {err}"));
 				}
 			},
 			_ => {},
@@ -51,45 +103,170 @@ fn main() -> StoreResult<()> {
 	}
 
 	let base = base.unwrap_or_else(|| std::env::temp_dir().join(Path::new("parity_bench")));
+	// `--resume` keeps the stores of an interrupted run and continues each ingest from its checkpoint.
+	let length = if resume { RunLength::Resumable(total) } else { RunLength::new(total, duration) };
 
-	core::bench_common::cleanup_dirs(&base, &["plain", "index", "range", "dictionary"]);
+	if !resume {
+		core::bench_common::cleanup_dirs(&base, &layouts);
+	}
 
 	let jobs: Vec<NamedJob<PError>> = vec![
 		{
 			let base = base.clone();
-			NamedJob::new("plain", Box::new(move || run_plain(&base, total, parity_plain_factory)))
+			NamedJob::with_progress(
+				BenchLayout::Plain,
+				Box::new(move |board| run_plain(&base, length, board, move |path| parity_plain_factory(path, options))),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::with_progress(
+				BenchLayout::PlainVarkey,
+				Box::new(move |board| {
+					run_plain_varkey(&base, length, key_lengths, board, move |path| {
+						parity_plain_varkey_factory(path, options)
+					})
+				}),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::with_progress(
+				BenchLayout::Index,
+				Box::new(move |board| run_index(&base, length, board, move |path| parity_index_factory(path, options))),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::with_progress(
+				BenchLayout::Range,
+				Box::new(move |board| run_range(&base, length, board, move |path| parity_range_factory(path, options))),
+			)
 		},
 		{
 			let base = base.clone();
-			NamedJob::new("index", Box::new(move || run_index(&base, total, parity_index_factory)))
+			NamedJob::with_progress(
+				BenchLayout::Dictionary,
+				Box::new(move |board| {
+					run_dictionary(&base, length, board, move |path| parity_dictionary_factory(path, options))
+				}),
+			)
 		},
+	];
+
+	run_all_parallel(jobs, &layouts)?;
+
+	// Reopen each store and settle it, so reads of every backend start from its read-optimized layout.
+	let settles: Vec<NamedJob<PError>> = vec![
 		{
 			let base = base.clone();
-			NamedJob::new("range", Box::new(move || run_range(&base, total, parity_range_factory)))
+			NamedJob::new(
+				BenchLayout::Plain,
+				Box::new(move || run_settle(&base, "plain", move |path| parity_plain_factory(path, options))),
+			)
 		},
 		{
 			let base = base.clone();
-			NamedJob::new("dictionary", Box::new(move || run_dictionary(&base, total, parity_dictionary_factory)))
+			NamedJob::new(
+				BenchLayout::PlainVarkey,
+				Box::new(move || {
+					run_settle(&base, "plain_varkey", move |path| parity_plain_varkey_factory(path, options))
+				}),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Index,
+				Box::new(move || run_settle(&base, "index", move |path| parity_index_factory(path, options))),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Range,
+				Box::new(move || run_settle(&base, "range", move |path| parity_range_factory(path, options))),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Dictionary,
+				Box::new(move || run_settle(&base, "dictionary", move |path| parity_dictionary_factory(path, options))),
+			)
 		},
 	];
 
-	run_all_parallel(jobs, benches.as_deref().unwrap_or(&[]))?;
+	run_all_parallel(settles, &layouts)?;
+
+	// Time lookups of the settled plain and dictionary stores, keys drawn per `--distribution`.
+	let reads: Vec<NamedJob<PError>> = vec![
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Plain,
+				Box::new(move || run_reads(&base, length, read_load, move |path| parity_plain_factory(path, options))),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Dictionary,
+				Box::new(move || {
+					run_dictionary_reads(&base, length, read_load, move |path| parity_dictionary_factory(path, options))
+						.map(drop)
+				}),
+			)
+		},
+	];
+	run_all_parallel(reads, &layouts)?;
+
+	// Write a fresh plain store while reading it, per `--mixed <writers>:<readers>`.
+	if let Some(load) = mixed_load {
+		let mixed: Vec<NamedJob<PError>> = vec![{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Plain,
+				Box::new(move || run_mixed(&base, length, load, move |path| parity_plain_factory(path, options)).map(drop)),
+			)
+		}];
+		run_all_parallel(mixed, &layouts)?;
+	}
 
 	Ok(())
 }
 
-fn parity_plain_factory(path: &Path) -> StoreResult<Store<Key, Amount, PKeyCodec, PAmountCodec>> {
-	Store::open_with_options(path, Layout::plain(0), ())
+fn parity_plain_factory(
+	path: &Path,
+	options: ParityOptions,
+) -> StoreResult<Store<Key, Amount, PKeyCodec, PAmountCodec>> {
+	StoreBuilder::new(path, Layout::plain(0)).options(options.without_uniform_values()).open()
+}
+
+fn parity_plain_varkey_factory(
+	path: &Path,
+	options: ParityOptions,
+) -> StoreResult<Store<VarKey, Amount, PVarKeyCodec, PAmountCodec>> {
+	StoreBuilder::new(path, Layout::plain(0)).options(options.without_uniform_values()).open()
 }
 
-fn parity_index_factory(path: &Path) -> StoreResult<Store<Key, TxHash, PKeyCodec, PTxCodec>> {
-	Store::open_with_options(path, Layout::unique_index(0), ())
+fn parity_index_factory(
+	path: &Path,
+	options: ParityOptions,
+) -> StoreResult<Store<Key, TxHash, PKeyCodec, PTxCodec>> {
+	StoreBuilder::new(path, Layout::unique_index(0)).options(options).open()
 }
 
-fn parity_range_factory(path: &Path) -> StoreResult<Store<Key, Timestamp, PKeyCodec, PTimestampCodec>> {
-	Store::open_with_options(path, Layout::range(0), ())
+fn parity_range_factory(
+	path: &Path,
+	options: ParityOptions,
+) -> StoreResult<Store<Key, Timestamp, PKeyCodec, PTimestampCodec>> {
+	StoreBuilder::new(path, Layout::range(0)).options(options.without_uniform_values()).open()
 }
 
-fn parity_dictionary_factory(path: &Path) -> StoreResult<Store<Key, Address, PKeyCodec, PAddressCodec>> {
-	Store::open_with_options(path, Layout::dictionary(0), ())
+fn parity_dictionary_factory(
+	path: &Path,
+	options: ParityOptions,
+) -> StoreResult<Store<Key, Address, PKeyCodec, PAddressCodec>> {
+	StoreBuilder::new(path, Layout::dictionary(0)).options(options.without_uniform_values()).open()
 }
@@ -1,9 +1,24 @@
 use parity_db::{ColId, CompressionType, Db, Error, Options, Result};
-use std::{marker::PhantomData, path::Path};
-use core::store_interface::{ProgressTracker, StoreCodec, StoreRead, StoreWrite};
+use std::{marker::PhantomData, path::{Path, PathBuf}, sync::Arc, time::Duration};
+use core::inspect::KeyDump;
+use core::store_builder::BuilderOptions;
+use core::store_interface::{
+	CommitStats, ConsistencyReport, ExpiringStore, OpenMode, ProgressTracker, Retryable, StoreCodec, StoreRead,
+	StoreWrite, NEVER_EXPIRES, check_expiring, check_indexed_value, check_layout, coalesce_by_encoded_key, composite_key,
+	composite_prefix, decode_exact, expiry_after, is_transient_io, sort_by_encoded_key, sort_by_encoding,
+	split_composite, stored_value, unexpired, unix_millis,
+};
 
 pub type StoreResult<T> = Result<T>;
 
+/// parity-db commits through its own queue and never reports a conflict, so only an interrupted
+/// or timed out io call is worth trying again.
+impl Retryable for Error {
+	fn is_retryable(&self) -> bool {
+		matches!(self, Error::Io(err) if is_transient_io(err))
+	}
+}
+
 /// Storage layouts supported by the generic store.
 #[derive(Clone, Copy)]
 pub enum Layout {
@@ -31,92 +46,252 @@ impl Layout {
 			birth_key_key_btree: from + 3,
 		}
 	}
+
+	/// Name of the layout the columns of an existing store are checked against on open.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Layout::Plain { .. } => "plain",
+			Layout::UniqueIndex { .. } => "unique_index",
+			Layout::Range { .. } => "range",
+			Layout::Dictionary { .. } => "dictionary",
+		}
+	}
+}
+
+/// Column tuning for benchmarking parity-db variants; the default is the untuned setup.
+#[derive(Clone, Copy, Debug)]
+pub struct ParityOptions {
+	/// Applied to every column.
+	pub compression: CompressionType,
+	/// Hash columns hold values that are preimages of their keys.
+	pub preimage: bool,
+	/// Hash columns keyed by `K` (and birth keys) skip key hashing; keys must be at least 32
+	/// uniformly distributed bytes, shorter ones are rejected.
+	pub uniform_keys: bool,
+	/// Same for the hash columns keyed by `V` (`value_to_key`, `value_to_birth_key`), e.g. tx hashes.
+	pub uniform_values: bool,
+	/// Skip writes a later one in the same commit overwrites; see `coalesce_by_encoded_key`.
+	pub coalesce_duplicates: bool,
+	/// `Dictionary` only: never write `birth_key_key_btree` and keep it a hash column, for
+	/// write-only runs; reverse lookups and `distinct_values` then fail. Must match across opens.
+	pub skip_reverse_index: bool,
+	/// `Plain` only: keep each value behind its expiry for `ExpiringStore`. The column becomes a
+	/// btree so `purge_expired` can walk its keys; read-only opens tell it from the metadata.
+	pub expiring: bool,
+	/// Commit a large `commit` in parity-db commits of about this many column changes, so its
+	/// changes are never all held in memory at once. Each chunk is atomic, the whole call is not:
+	/// a failure leaves the earlier chunks written. `None`, the default, commits it all at once.
+	pub commit_chunk_ops: Option<usize>,
+}
+
+impl Default for ParityOptions {
+	fn default() -> Self {
+		Self {
+			compression: CompressionType::NoCompression,
+			preimage: false,
+			uniform_keys: false,
+			uniform_values: false,
+			coalesce_duplicates: false,
+			skip_reverse_index: false,
+			expiring: false,
+			commit_chunk_ops: None,
+		}
+	}
+}
+
+impl ParityOptions {
+	/// For layouts whose values are not fixed-size hashes.
+	pub fn without_uniform_values(self) -> Self {
+		Self { uniform_values: false, ..self }
+	}
+
+	/// At least 1 change per chunk.
+	pub fn with_commit_chunk_ops(self, ops: usize) -> Self {
+		Self { commit_chunk_ops: Some(ops.max(1)), ..self }
+	}
+
+	/// For write-only `Dictionary` runs that never look keys up by value.
+	pub fn without_reverse_index(self) -> Self {
+		Self { skip_reverse_index: true, ..self }
+	}
+}
+
+impl BuilderOptions for ParityOptions {
+	fn set_coalesce_duplicates(&mut self, coalesce: bool) {
+		self.coalesce_duplicates = coalesce;
+	}
 }
 
+/// parity-db uses the first 32 key bytes of a uniform column as the hash.
+const UNIFORM_KEY_BYTES: usize = 32;
+
 /// Generic store operating on a chosen layout and codecs.
 pub struct Store<K, V, KC, VC>
 where
 	KC: StoreCodec<K, Error = Error>,
 	VC: StoreCodec<V, Error = Error>,
 {
-	db: Db,
+	// Only `None` while `clear` has the database closed to reset its columns.
+	db: Option<Db>,
+	path: PathBuf,
 	layout: Layout,
+	options: ParityOptions,
+	// Per column; parity-db panics on uniform keys shorter than `UNIFORM_KEY_BYTES`.
+	uniform: Vec<bool>,
+	read_only: bool,
     progress: Option<ProgressTracker>,
 	_ph: PhantomData<(K, V, KC, VC)>,
 }
 
+/// A `Store` shared across threads: every read takes `&self`, and the store is `Send + Sync` for
+/// `Send + Sync` key and value types. parity-db serves reads from any thread alongside its
+/// background commit queue; commits take `&mut self` and need a lock of the caller's.
+pub type SharedStore<K, V, KC, VC> = Arc<Store<K, V, KC, VC>>;
+
 impl<K, V, KC, VC> Store<K, V, KC, VC>
 where
     KC: StoreCodec<K, Error = Error>,
     VC: StoreCodec<V, Error = Error>,
 {
     pub fn open(path: &Path, layout: Layout) -> Result<Self> {
-        Self::open_with_options(path, layout, ())
+        Self::open_with_options(path, layout, ParityOptions::default())
     }
 
-	pub fn open_with_options(path: &Path, layout: Layout, _options: ()) -> Result<Self> {
-		let options = build_options(path, &layout);
-		let db = Db::open_or_create(&options)?;
-		Ok(Self { db, progress: None, layout, _ph: PhantomData })
+	pub fn open_with_options(path: &Path, layout: Layout, options: ParityOptions) -> Result<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(Error::InvalidInput)?;
+		check_stored_layout(path, &layout)?;
+		let db_options = build_options(path, &layout, options);
+		let uniform = db_options.columns.iter().map(|c| c.uniform).collect();
+		let db = Db::open_or_create(&db_options)?;
+		Ok(Self {
+			db: Some(db),
+			path: path.to_path_buf(),
+			progress: None,
+			layout,
+			options,
+			uniform,
+			read_only: false,
+			_ph: PhantomData,
+		})
+	}
+
+	/// `open_with_options` after checking `mode` against whether `path` already holds a store.
+	pub fn open_with_mode(path: &Path, layout: Layout, options: ParityOptions, mode: OpenMode) -> Result<Self> {
+		mode.check(path, store_exists(path)).map_err(Error::InvalidInput)?;
+		Self::open_with_options(path, layout, options)
+	}
+
+	/// Column options are taken from the database metadata, so a store written with any
+	/// `ParityOptions` opens without repeating them.
+	pub fn open_read_only(path: &Path, layout: Layout) -> Result<Self> {
+		check_stored_layout(path, &layout)?;
+		let mut db_options = build_options(path, &layout, ParityOptions::default());
+		if let Some(meta) = Options::load_metadata(path)? {
+			db_options.columns = meta.columns;
+		}
+		let uniform = db_options.columns.iter().map(|c| c.uniform).collect();
+		let expiring = match layout {
+			Layout::Plain { key_to_value } => db_options.columns[key_to_value as usize].btree_index,
+			_ => false,
+		};
+		let db = Db::open_read_only(&db_options)?;
+		Ok(Self {
+			db: Some(db),
+			path: path.to_path_buf(),
+			progress: None,
+			layout,
+			options: ParityOptions { expiring, ..ParityOptions::default() },
+			uniform,
+			read_only: true,
+			_ph: PhantomData,
+		})
 	}
 
 	pub fn commit<'a, I>(&mut self, items: I) -> Result<()>
 	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
 	{
-        let mut processed = 0u64;
+		self.commit_counted(items).map(|_| ())
+	}
+
+	/// With `coalesce_duplicates` set, a write the batch overwrites later is skipped.
+	pub fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		self.commit_expiring(items, self.options.expiring.then_some(NEVER_EXPIRES))
+	}
+
+	/// `commit` of `Plain` entries that `get_value` stops returning once `ttl` has passed.
+	pub fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> Result<()>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		self.ensure_expiring()?;
+		self.commit_expiring(items, Some(expiry_after(ttl))).map(|_| ())
+	}
+
+	/// `expires_at` is the expiry of every entry, `None` for a store without expiring entries.
+	fn commit_expiring<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> Result<CommitStats>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		if self.options.coalesce_duplicates {
+			self.write_counted(coalesce_by_encoded_key::<K, V, KC, _>(items), expires_at)
+		} else {
+			self.write_counted(items, expires_at)
+		}
+	}
+
+	fn write_counted<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> Result<CommitStats>
+	where I: IntoIterator<Item = (&'a K, &'a V)>, K: 'a, V: 'a,
+	{
+		self.ensure_writable()?;
+		let mut stats = CommitStats::default();
+		let mut changes = Vec::new();
 		match self.layout {
 			Layout::Plain { key_to_value } => {
-				let changes = items
-					.into_iter()
-					.map(|(k, v)| {
-						let kbytes = KC::encode(k);
-						let vbytes = VC::encode(v);
-						(key_to_value, kbytes.as_ref().to_vec(), Some(vbytes.as_ref().to_vec()))
-					})
-					.collect::<Vec<_>>();
-                processed += changes.len() as u64;
-				self.db.commit(changes)?
+				for (k, v) in items {
+					let kbytes = KC::encode(k);
+					let vbytes = VC::encode(v);
+					let stored = stored_value(vbytes.as_ref(), expires_at).into_owned();
+					changes.push((key_to_value, kbytes.as_ref().to_vec(), Some(stored)));
+					self.commit_full_chunk(&mut changes, &mut stats)?;
+				}
 			},
 			Layout::UniqueIndex { key_to_value, value_to_key } => {
-				let mut changes = Vec::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
+					check_indexed_value(vbytes.as_ref()).map_err(Error::InvalidInput)?;
 					changes.push((key_to_value, kbytes.as_ref().to_vec(), Some(vbytes.as_ref().to_vec())));
 					changes.push((value_to_key, vbytes.as_ref().to_vec(), Some(kbytes.as_ref().to_vec())));
+					self.commit_full_chunk(&mut changes, &mut stats)?;
 				}
-                processed += changes.len() as u64;
-				self.db.commit(changes)?
 			},
 			Layout::Range { key_to_value, value_key_btree } => {
-				let mut changes = Vec::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
 					let kslice = kbytes.as_ref();
 					changes.push((key_to_value, kslice.to_vec(), Some(vbytes.as_ref().to_vec())));
-					let vk = concat(vbytes.as_ref(), kslice);
+					let vk = composite_key(vbytes.as_ref(), kslice);
 					changes.push((value_key_btree, vk, Some(Vec::new())));
+					self.commit_full_chunk(&mut changes, &mut stats)?;
 				}
-                processed += changes.len() as u64;
-				self.db.commit(changes)?
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
 				use std::collections::HashMap;
-				let mut changes = Vec::new();
-				// Cache: value bytes -> (birth_key bytes, is_new_birth)
-				let mut value_cache: HashMap<Vec<u8>, (Vec<u8>, bool)> = HashMap::new();
+				// Cache: value bytes -> birth_key bytes; only a value's first occurrence is new
+				let mut value_cache: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					let (pk, is_new) = if let Some(entry) = value_cache.get(vbytes.as_ref()) {
-						entry.clone()
-					} else if let Some(pk) = self.db.get(value_to_birth_key, vbytes.as_ref())? {
-						value_cache.insert(vbytes.as_ref().to_vec(), (pk.clone(), false));
+					check_indexed_value(vbytes.as_ref()).map_err(Error::InvalidInput)?;
+					let (pk, is_new) = if let Some(pk) = value_cache.get(vbytes.as_ref()) {
+						(pk.clone(), false)
+					} else if let Some(pk) = self.get(value_to_birth_key, vbytes.as_ref())? {
+						value_cache.insert(vbytes.as_ref().to_vec(), pk.clone());
 						(pk, false)
 					} else {
 						let pk_bytes = kbytes.as_ref().to_vec();
-						value_cache.insert(vbytes.as_ref().to_vec(), (pk_bytes.clone(), true));
+						value_cache.insert(vbytes.as_ref().to_vec(), pk_bytes.clone());
 						(pk_bytes, true)
 					};
 
@@ -126,32 +301,62 @@ where
 					}
 					changes.push((key_to_birth_key, kbytes.as_ref().to_vec(), Some(pk.clone())));
 
-					let pk_key = concat(&pk, kbytes.as_ref());
-					changes.push((birth_key_key_btree, pk_key, Some(Vec::new())));
-				}
-				if !changes.is_empty() {
-                    processed += changes.len() as u64;
-					self.db.commit(changes)?;
+					if !self.options.skip_reverse_index {
+						let pk_key = composite_key(&pk, kbytes.as_ref());
+						changes.push((birth_key_key_btree, pk_key, Some(Vec::new())));
+					}
+					self.commit_full_chunk(&mut changes, &mut stats)?;
 				}
 			},
 		}
+		if !changes.is_empty() {
+			stats.add(self.commit_changes(changes)?);
+		}
         if let Some(p) = self.progress.as_mut() {
-            p.record(processed);
+            p.record(stats.rows);
         }
-        Ok(())
+        Ok(stats)
+	}
+
+	/// Commits `changes` once they reach `ParityOptions::commit_chunk_ops`, between two items so
+	/// an item's changes always land together.
+	fn commit_full_chunk(
+		&self,
+		changes: &mut Vec<(ColId, Vec<u8>, Option<Vec<u8>>)>,
+		stats: &mut CommitStats,
+	) -> Result<()> {
+		if self.options.commit_chunk_ops.is_some_and(|ops| changes.len() >= ops) {
+			stats.add(self.commit_changes(std::mem::take(changes))?);
+		}
+		Ok(())
 	}
 
 	pub fn get_value(&self, key: &K) -> Result<Option<V>> {
+		self.read_value(key, VC::decode)
+	}
+
+	/// `key`'s value as stored, `VC::encode` of what `get_value` returns, without decoding it.
+	pub fn get_value_raw(&self, key: &K) -> Result<Option<Vec<u8>>> {
+		self.read_value(key, |bytes| Ok(bytes.to_vec()))
+	}
+
+	/// Passes the encoded value of `key`, stripped of its expiry, to `read`.
+	fn read_value<T>(&self, key: &K, read: impl FnOnce(&[u8]) -> Result<T>) -> Result<Option<T>> {
 		let kbytes = KC::encode(key);
 		match self.layout {
+			Layout::Plain { key_to_value } if self.options.expiring => {
+				let Some(stored) = self.get(key_to_value, kbytes.as_ref())? else { return Ok(None) };
+				let value = unexpired(&stored, unix_millis()).map_err(Error::InvalidInput)?;
+				value.map(read).transpose()
+			},
 			Layout::Plain { key_to_value }
 			| Layout::UniqueIndex { key_to_value, .. }
 			| Layout::Range { key_to_value, .. } => {
-				self.db.get(key_to_value, kbytes.as_ref())?.map(|b| VC::decode(&b)).transpose()
+				self.get(key_to_value, kbytes.as_ref())?.map(|b| read(&b)).transpose()
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, .. } => {
-				if let Some(pk) = self.db.get(key_to_birth_key, kbytes.as_ref())? {
-					self.db.get(birth_key_to_value, &pk)?.map(|b| VC::decode(&b)).transpose()
+				if let Some(pk) = self.get(key_to_birth_key, kbytes.as_ref())? {
+					self.get(birth_key_to_value, &pk)?.map(|b| read(&b)).transpose()
 				} else {
 					Ok(None)
 				}
@@ -159,12 +364,22 @@ where
 		}
 	}
 
+	/// What the store holds for `key`: its encoding and that of its value, printing as hex dumps,
+	/// for a lookup that returns something unexpected.
+	pub fn debug_dump(&self, key: &K) -> Result<KeyDump> {
+		let value = self.get_value(key)?;
+		Ok(KeyDump::new::<K, V, KC, VC>(key, value.as_ref()))
+	}
+
 	pub fn get_key_for_value(&self, value: &V) -> Result<Option<K>> {
 		let vbytes = VC::encode(value);
 		match self.layout {
 			Layout::UniqueIndex { value_to_key, .. } => {
-				self.db.get(value_to_key, vbytes.as_ref())?.map(|b| KC::decode(&b)).transpose()
+				self.get(value_to_key, vbytes.as_ref())?
+					.map(|b| decode_exact::<K, KC>(&b, Error::InvalidInput))
+					.transpose()
 			},
+			Layout::Range { .. } | Layout::Dictionary { .. } => Ok(self.get_keys_for_value(value)?.into_iter().next()),
 			_ => Err(Error::InvalidInput("get_key_for_value not supported for this layout".into())),
 		}
 	}
@@ -173,30 +388,28 @@ where
 		let vbytes = VC::encode(value);
 		match self.layout {
 			Layout::Range { value_key_btree, .. } => {
-				let prefix = vbytes.as_ref();
 				let mut out = Vec::new();
-				let mut iter = self.db.iter(value_key_btree)?;
-				iter.seek(prefix)?;
+				let mut iter = self.db()?.iter(value_key_btree)?;
+				iter.seek(&composite_prefix(vbytes.as_ref()))?;
 				while let Some((k, _)) = iter.next()? {
-					if k.len() < prefix.len() || &k[..prefix.len()] != prefix {
-						break
+					match split_composite(&k) {
+						Some((head, key_bytes)) if head == vbytes.as_ref() => out.push(KC::decode(key_bytes)?),
+						_ => break,
 					}
-					let key_bytes = &k[prefix.len()..];
-					out.push(KC::decode(key_bytes)?);
 				}
 				Ok(out)
 			},
 			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
-				if let Some(pk) = self.db.get(value_to_birth_key, vbytes.as_ref())? {
-					let mut iter = self.db.iter(birth_key_key_btree)?;
-					iter.seek(&pk)?;
+				self.ensure_reverse_index()?;
+				if let Some(pk) = self.get(value_to_birth_key, vbytes.as_ref())? {
+					let mut iter = self.db()?.iter(birth_key_key_btree)?;
+					iter.seek(&composite_prefix(&pk))?;
 					let mut out = Vec::new();
 					while let Some((k, _)) = iter.next()? {
-						if k.len() < pk.len() || k[..pk.len()] != pk[..] {
-							break
+						match split_composite(&k) {
+							Some((head, key_bytes)) if head == pk.as_slice() => out.push(KC::decode(key_bytes)?),
+							_ => break,
 						}
-						let key_bytes = &k[pk.len()..];
-						out.push(KC::decode(key_bytes)?);
 					}
 					Ok(out)
 				} else {
@@ -207,12 +420,200 @@ where
 		}
 	}
 
+	/// One btree iterator for the whole batch. Seeks run in head order, so the iterator moves
+	/// forward through the btree; `Dictionary` resolves birth keys in value order first.
+	pub fn get_keys_for_values(&self, values: &[V]) -> Result<Vec<Vec<K>>> {
+		let (btree, heads) = match self.layout {
+			Layout::Range { value_key_btree, .. } => (value_key_btree, sort_by_encoding::<V, VC>(values)),
+			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
+				self.ensure_reverse_index()?;
+				let mut pks = Vec::with_capacity(values.len());
+				for (idx, vbytes) in sort_by_encoding::<V, VC>(values) {
+					if let Some(pk) = self.get(value_to_birth_key, &vbytes)? {
+						pks.push((idx, pk));
+					}
+				}
+				pks.sort_by(|a, b| a.1.cmp(&b.1));
+				(birth_key_key_btree, pks)
+			},
+			_ => return Err(Error::InvalidInput("get_keys_for_values not supported for this layout".into())),
+		};
+		let mut iter = self.db()?.iter(btree)?;
+		let mut out: Vec<Vec<K>> = values.iter().map(|_| Vec::new()).collect();
+		for (idx, head) in heads {
+			iter.seek(&composite_prefix(&head))?;
+			while let Some((k, _)) = iter.next()? {
+				match split_composite(&k) {
+					Some((h, key_bytes)) if h == head.as_slice() => out[idx].push(KC::decode(key_bytes)?),
+					_ => break,
+				}
+			}
+		}
+		Ok(out)
+	}
+
+	/// Hash columns cannot be walked in order, so this skips through `birth_key_key_btree` one
+	/// birth key at a time and looks each value up; the values are read up front.
+	pub fn distinct_values(&self) -> Result<impl Iterator<Item = Result<V>>> {
+		match self.layout {
+			Layout::Dictionary { birth_key_to_value, birth_key_key_btree, .. } => {
+				self.ensure_reverse_index()?;
+				let mut values = Vec::new();
+				let mut iter = self.db()?.iter(birth_key_key_btree)?;
+				iter.seek_to_first()?;
+				while let Some((k, _)) = iter.next()? {
+					let Some((pk, _)) = split_composite(&k) else { break };
+					if let Some(v) = self.get(birth_key_to_value, pk)? {
+						values.push(v);
+					}
+					match prefix_upper_bound(&composite_prefix(pk)) {
+						Some(next) => iter.seek(&next)?,
+						None => break,
+					}
+				}
+				Ok(values.into_iter().map(|v| VC::decode(&v)))
+			},
+			_ => Err(Error::InvalidInput("distinct_values not supported for this layout".into())),
+		}
+	}
+
+	/// The audit walks the primary column, and parity-db hash columns only yield hashed keys, so
+	/// the indexed layouts cannot be checked; `Plain` has nothing to check.
+	pub fn verify_consistency(&self) -> Result<ConsistencyReport> {
+		match self.layout {
+			Layout::Plain { .. } => Ok(ConsistencyReport::default()),
+			_ => Err(Error::InvalidInput("verify_consistency cannot walk the keys of hash columns".into())),
+		}
+	}
+
+	/// Every layout keeps its primary column hashed, so there is no key order to take bounds from.
+	pub fn first_key(&self) -> Result<Option<K>> {
+		Err(Error::InvalidInput("first_key cannot order the keys of hash columns".into()))
+	}
+
+	pub fn last_key(&self) -> Result<Option<K>> {
+		Err(Error::InvalidInput("last_key cannot order the keys of hash columns".into()))
+	}
+
+	/// parity-db keeps no key count a read can take without walking the column.
+	pub fn approx_key_count(&self) -> Result<u64> {
+		Err(Error::InvalidInput("approx_key_count is not tracked by parity-db columns".into()))
+	}
+
+	/// `commit` in encoded-key order with duplicate keys collapsed to the last write, which keeps
+	/// btree column inserts local.
+	pub fn commit_sorted<'a, I>(&mut self, items: I) -> Result<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		let expires_at = self.options.expiring.then_some(NEVER_EXPIRES);
+		self.write_counted(sort_by_encoded_key::<K, V, KC, _>(items), expires_at).map(|_| ())
+	}
+
 	pub fn flush(&mut self) -> Result<()> {
+		self.ensure_writable()?;
+		Ok(())
+	}
+
+	/// Reopens the database: closing waits for the commit queue and enacts the write-ahead logs
+	/// into the column files, so reads no longer consult the log overlay.
+	pub fn settle_for_reads(&mut self) -> Result<()> {
+		self.ensure_writable()?;
+		let options = build_options(&self.path, &self.layout, self.options);
+		drop(self.db.take());
+		self.db = Some(Db::open_or_create(&options)?);
+		Ok(())
+	}
+
+	/// Walks the btree `Plain` column of an expiring store and deletes the expired entries in one
+	/// commit.
+	pub fn purge_expired(&mut self) -> Result<u64> {
+		self.ensure_writable()?;
+		self.ensure_expiring()?;
+		let Layout::Plain { key_to_value } = self.layout else { return Ok(0) };
+		let now = unix_millis();
+		let mut expired = Vec::new();
+		let mut iter = self.db()?.iter(key_to_value)?;
+		iter.seek_to_first()?;
+		while let Some((k, stored)) = iter.next()? {
+			if matches!(unexpired(&stored, now), Ok(None)) {
+				expired.push((key_to_value, k, None));
+			}
+		}
+		let purged = expired.len() as u64;
+		if purged > 0 {
+			self.db()?.commit(expired)?;
+		}
+		Ok(purged)
+	}
+
+	/// parity-db can only truncate columns of a closed database, so the handle is dropped,
+	/// every column reset and the database reopened.
+	pub fn clear(&mut self) -> Result<()> {
+		self.ensure_writable()?;
+		let mut options = build_options(&self.path, &self.layout, self.options);
+		drop(self.db.take());
+		for col in 0..options.columns.len() as ColId {
+			Db::reset_column(&mut options, col, None)?;
+		}
+		self.db = Some(Db::open_or_create(&options)?);
+		Ok(())
+	}
+
+	fn db(&self) -> Result<&Db> {
+		self.db.as_ref().ok_or_else(|| Error::InvalidInput("database closed by a failed clear".into()))
+	}
+
+	fn check_key(&self, col: ColId, key: &[u8]) -> Result<()> {
+		if self.uniform.get(col as usize).copied().unwrap_or(false) && key.len() < UNIFORM_KEY_BYTES {
+			return Err(Error::InvalidInput(format!(
+				"column {col} uses uniform keys of at least {UNIFORM_KEY_BYTES} bytes, got {}",
+				key.len()
+			)))
+		}
+		Ok(())
+	}
+
+	fn get(&self, col: ColId, key: &[u8]) -> Result<Option<Vec<u8>>> {
+		self.check_key(col, key)?;
+		self.db()?.get(col, key)
+	}
+
+	fn commit_changes(&self, changes: Vec<(ColId, Vec<u8>, Option<Vec<u8>>)>) -> Result<CommitStats> {
+		let mut stats = CommitStats::default();
+		for (col, key, value) in &changes {
+			self.check_key(*col, key)?;
+			stats.record(key, value.as_deref().unwrap_or_default());
+		}
+		self.db()?.commit(changes)?;
+		Ok(stats)
+	}
+
+	fn ensure_reverse_index(&self) -> Result<()> {
+		if self.options.skip_reverse_index {
+			return Err(Error::InvalidInput("reverse index disabled by skip_reverse_index".into()))
+		}
+		Ok(())
+	}
+
+	fn ensure_expiring(&self) -> Result<()> {
+		if !self.options.expiring {
+			return Err(Error::InvalidInput("store is not opened with expiring entries".into()))
+		}
+		Ok(())
+	}
+
+	fn ensure_writable(&self) -> Result<()> {
+		if self.read_only {
+			return Err(Error::InvalidInput("store is opened read-only".into()))
+		}
 		Ok(())
 	}
 }
 
-fn build_options(path: &Path, layout: &Layout) -> Options {
+fn build_options(path: &Path, layout: &Layout, options: ParityOptions) -> Options {
 	let columns = match layout {
 		Layout::Plain { .. } => 1,
 		Layout::UniqueIndex { .. } => 2,
@@ -223,72 +624,66 @@ fn build_options(path: &Path, layout: &Layout) -> Options {
 	for col in opts.columns.iter_mut() {
 		col.uniform = false;
 		col.preimage = false;
-		col.compression = CompressionType::NoCompression;
+		col.compression = options.compression;
 	}
 	if let Layout::Range { value_key_btree, .. } = layout {
 		opts.columns[*value_key_btree as usize].btree_index = true;
 	}
 	if let Layout::Dictionary { birth_key_key_btree, .. } = layout {
-		opts.columns[*birth_key_key_btree as usize].btree_index = true;
+		opts.columns[*birth_key_key_btree as usize].btree_index = !options.skip_reverse_index;
+	}
+	if let Layout::Plain { key_to_value } = layout {
+		opts.columns[*key_to_value as usize].btree_index = options.expiring;
+	}
+	let (key_cols, value_cols): (&[ColId], &[ColId]) = match layout {
+		Layout::Plain { .. } if options.expiring => (&[], &[]),
+		Layout::Plain { key_to_value } | Layout::Range { key_to_value, .. } => (&[*key_to_value], &[]),
+		Layout::UniqueIndex { key_to_value, value_to_key } => (&[*key_to_value], &[*value_to_key]),
+		Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, .. } => {
+			(&[*key_to_birth_key, *birth_key_to_value], &[*value_to_birth_key])
+		},
+	};
+	for col in opts.columns.iter_mut().filter(|c| !c.btree_index) {
+		col.preimage = options.preimage;
+	}
+	for &col in key_cols {
+		opts.columns[col as usize].uniform = options.uniform_keys;
+	}
+	for &col in value_cols {
+		opts.columns[col as usize].uniform = options.uniform_values;
 	}
 	opts
 }
 
-fn concat(a: &[u8], b: &[u8]) -> Vec<u8> {
-	let mut out = Vec::with_capacity(a.len() + b.len());
-	out.extend_from_slice(a);
-	out.extend_from_slice(b);
-	out
+/// Checks `layout` against the store at `path`, if any. parity-db records no layout, but its
+/// metadata lists the columns, and which of them is a btree, and those tell the layouts apart.
+fn check_stored_layout(path: &Path, layout: &Layout) -> Result<()> {
+	let Some(meta) = Options::load_metadata(path)? else { return Ok(()) };
+	let stored = match meta.columns.as_slice() {
+		[_] => "plain",
+		[_, btree] if btree.btree_index => "range",
+		[_, _] => "unique_index",
+		[_, _, _, _] => "dictionary",
+		columns => return Err(Error::InvalidInput(format!("store has {} columns, no layout has", columns.len()))),
+	};
+	check_layout(stored, layout.name()).map_err(Error::InvalidInput)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use core::store_tests::{basic_value_roundtrip, multiple_keys_for_value, reverse_lookup_unique};
-    use tempfile::tempdir;
-
-    struct BytesCodec;
-
-    impl StoreCodec<Vec<u8>> for BytesCodec {
-        type Error = Error;
-        type Enc<'a> = &'a [u8];
-        fn encode<'a>(value: &'a Vec<u8>) -> Self::Enc<'a> {
-            value.as_slice()
-        }
-        fn decode(bytes: &[u8]) -> Result<Vec<u8>> {
-            Ok(bytes.to_vec())
-        }
-    }
-
-    #[test]
-    fn shared_basic_suite() {
-        basic_value_roundtrip(|| {
-            let dir = tempdir().unwrap();
-            let path = dir.path().to_path_buf();
-            std::mem::forget(dir);
-            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::plain(0), ()).unwrap()
-        });
-    }
-
-    #[test]
-    fn shared_reverse_suite() {
-        reverse_lookup_unique(|| {
-            let dir = tempdir().unwrap();
-            let path = dir.path().to_path_buf();
-            std::mem::forget(dir);
-            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::unique_index(0), ()).unwrap()
-        });
-    }
+/// Whether `path` holds a store; parity-db writes its column `metadata` on creation.
+fn store_exists(path: &Path) -> bool {
+	path.join("metadata").is_file()
+}
 
-    #[test]
-    fn shared_multiple_keys_suite() {
-        multiple_keys_for_value(|| {
-            let dir = tempdir().unwrap();
-            let path = dir.path().to_path_buf();
-            std::mem::forget(dir);
-            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::range(0), ()).unwrap()
-        });
-    }
+/// Smallest key above every key starting with `prefix`, or `None` if there is none.
+fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+	let mut out = prefix.to_vec();
+	while let Some(last) = out.pop() {
+		if last != u8::MAX {
+			out.push(last + 1);
+			return Some(out)
+		}
+	}
+	None
 }
 
 impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
@@ -302,6 +697,10 @@ where
 		Store::get_value(self, key)
 	}
 
+	fn get_value_raw(&self, key: &K) -> Result<Option<Vec<u8>>> {
+		Store::get_value_raw(self, key)
+	}
+
 	fn get_key_for_value(&self, value: &V) -> Result<Option<K>> {
 		Store::get_key_for_value(self, value)
 	}
@@ -309,6 +708,34 @@ where
 	fn get_keys_for_value(&self, value: &V) -> Result<Vec<K>> {
 		Store::get_keys_for_value(self, value)
 	}
+
+	fn get_keys_for_values(&self, values: &[V]) -> Result<Vec<Vec<K>>> {
+		Store::get_keys_for_values(self, values)
+	}
+
+	fn distinct_values(&self) -> Result<impl Iterator<Item = Result<V>>> {
+		Store::distinct_values(self)
+	}
+
+	fn verify_consistency(&self) -> Result<ConsistencyReport> {
+		Store::verify_consistency(self)
+	}
+
+	fn first_key(&self) -> Result<Option<K>> {
+		Store::first_key(self)
+	}
+
+	fn last_key(&self) -> Result<Option<K>> {
+		Store::last_key(self)
+	}
+
+	fn orders_keys(&self) -> bool {
+		false
+	}
+
+	fn approx_key_count(&self) -> Result<u64> {
+		Store::approx_key_count(self)
+	}
 }
 
 impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
@@ -316,27 +743,383 @@ where
     KC: StoreCodec<K, Error = Error>,
     VC: StoreCodec<V, Error = Error>,
 {
-	type Options = ();
+	type Options = ParityOptions;
 	type Layout = Layout;
 
 	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> Result<Self> {
 		Store::open_with_options(path, layout, options)
 	}
 
-	fn commit<'a, I>(&mut self, items: I) -> Result<()>
+	fn open_with_mode(path: &Path, layout: Self::Layout, options: Self::Options, mode: OpenMode) -> Result<Self> {
+		Store::open_with_mode(path, layout, options, mode)
+	}
+
+	fn open_read_only(path: &Path, layout: Self::Layout) -> Result<Self> {
+		Store::open_read_only(path, layout)
+	}
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> Result<CommitStats>
 	where
 		I: IntoIterator<Item = (&'a K, &'a V)>,
 		K: 'a,
 		V: 'a,
 	{
-		Store::commit(self, items)
+		Store::commit_counted(self, items)
+	}
+
+	fn commit_sorted<'a, I>(&mut self, items: I) -> Result<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_sorted(self, items)
 	}
 
 	fn flush(&mut self) -> Result<()> {
 		Store::flush(self)
 	}
 
-    fn set_progress(&mut self, label: &str, total: u64) {
-        self.progress = Some(ProgressTracker::new(label.to_string(), total));
+	fn settle_for_reads(&mut self) -> Result<()> {
+		Store::settle_for_reads(self)
+	}
+
+	fn clear(&mut self) -> Result<()> {
+		Store::clear(self)
+	}
+
+    fn set_progress_tracker(&mut self, tracker: ProgressTracker) {
+        self.progress = Some(tracker);
+    }
+}
+
+impl<K, V, KC, VC> ExpiringStore<K, V> for Store<K, V, KC, VC>
+where
+    KC: StoreCodec<K, Error = Error>,
+    VC: StoreCodec<V, Error = Error>,
+{
+	fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> Result<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_with_ttl(self, items, ttl)
+	}
+
+	fn purge_expired(&mut self) -> Result<u64> {
+		Store::purge_expired(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::store_tests::{
+        assert_send_sync, basic_value_roundtrip, batched_keys_for_values, builder_paths, clear_then_reuse,
+        coalesced_duplicate_keys, dictionary_birth_key_stable, dictionary_commit_stats, dictionary_distinct_values,
+        empty_values, expiring_entries, mismatched_layout, multiple_keys_for_value, open_modes, prefix_values_isolated,
+        raw_values, read_only_rejects_writes, reverse_lookup_unique, scratch_store, shared_readers,
+        settle_keeps_contents, sorted_commit_matches_unsorted, variable_length_keys_roundtrip,
+    };
+    use core::store_builder::StoreBuilder;
+    use tempfile::tempdir;
+
+    struct BytesCodec;
+
+    impl StoreCodec<Vec<u8>> for BytesCodec {
+        type Error = Error;
+        type Enc<'a> = &'a [u8];
+        fn encode<'a>(value: &'a Vec<u8>) -> Self::Enc<'a> {
+            value.as_slice()
+        }
+        fn decode(bytes: &[u8]) -> Result<Vec<u8>> {
+            Ok(bytes.to_vec())
+        }
+    }
+
+    #[test]
+    fn shared_basic_suite() {
+        basic_value_roundtrip(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_variable_length_keys_suite() {
+        variable_length_keys_roundtrip(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_clear_suite() {
+        clear_then_reuse(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_sorted_commit_suite() {
+        sorted_commit_matches_unsorted(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_dictionary_birth_key_suite() {
+        dictionary_birth_key_stable(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_dictionary_commit_stats_suite() {
+        dictionary_commit_stats(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_reverse_suite() {
+        reverse_lookup_unique(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::unique_index(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_multiple_keys_suite() {
+        for layout in [Layout::range(0), Layout::dictionary(0)] {
+            multiple_keys_for_value(|path| {
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default()).unwrap()
+            });
+        }
+    }
+
+    #[test]
+    fn shared_batched_keys_suite() {
+        for layout in [Layout::range(0), Layout::dictionary(0)] {
+            batched_keys_for_values(|path| {
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default())
+                    .unwrap()
+            });
+        }
+    }
+
+    #[test]
+    fn shared_readers_suite() {
+        assert_send_sync::<SharedStore<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>();
+        for layout in [Layout::range(0), Layout::dictionary(0)] {
+            shared_readers(|path| {
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default())
+                    .unwrap()
+            });
+        }
+    }
+
+    #[test]
+    fn raw_values_suite() {
+        for layout in [Layout::plain(0), Layout::range(0), Layout::dictionary(0)] {
+            raw_values::<BytesCodec, _, _>(|path| {
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default())
+                    .unwrap()
+            });
+        }
+    }
+
+    #[test]
+    fn shared_prefix_values_suite() {
+        for layout in [Layout::range(0), Layout::dictionary(0)] {
+            prefix_values_isolated(|path| {
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default()).unwrap()
+            });
+        }
+    }
+
+    #[test]
+    fn shared_expiring_suite() {
+        let options = ParityOptions { expiring: true, ..ParityOptions::default() };
+        expiring_entries(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), options).unwrap()
+        });
+        let dir = tempdir().unwrap();
+        assert!(Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::range(0), options).is_err());
+        let (key, value) = (b"k".to_vec(), b"v".to_vec());
+        {
+            let mut store =
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), options).unwrap();
+            store.commit_with_ttl([(&key, &value)], Duration::from_secs(3600)).unwrap();
+        }
+        let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::plain(0)).unwrap();
+        assert_eq!(store.get_value(&key).unwrap(), Some(value));
+    }
+
+    #[test]
+    fn shared_coalesce_suite() {
+        let open = |path: &Path, layout, coalesce_duplicates| {
+            let options = ParityOptions { coalesce_duplicates, ..ParityOptions::default() };
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+        };
+        coalesced_duplicate_keys(
+            |path, c| open(path, Layout::plain(0), c),
+            |path, c| open(path, Layout::unique_index(0), c),
+        );
+    }
+
+    #[test]
+    fn shared_settle_suite() {
+        settle_keeps_contents(|path| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), ParityOptions::default()).unwrap()
+        });
+    }
+
+    #[test]
+    fn shared_distinct_values_suite() {
+        let open = |path: &Path, layout| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default()).unwrap()
+        };
+        dictionary_distinct_values(|path| open(path, Layout::dictionary(0)), |path| open(path, Layout::range(0)));
+    }
+
+    #[test]
+    fn shared_empty_values_suite() {
+        let open = |path: &Path, layout| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default()).unwrap()
+        };
+        empty_values(
+            |path| open(path, Layout::plain(0)),
+            |path| open(path, Layout::unique_index(0)),
+            |path| open(path, Layout::range(0)),
+            |path| open(path, Layout::dictionary(0)),
+        );
+    }
+
+    #[test]
+    fn verify_consistency_needs_an_ordered_primary() {
+        let open = |layout| {
+            scratch_store(|path| {
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, ParityOptions::default()).unwrap()
+            })
+        };
+        let (_dir, store) = open(Layout::plain(0));
+        assert!(store.verify_consistency().unwrap().is_consistent());
+        for layout in [Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)] {
+            let (_dir, store) = open(layout);
+            assert!(store.verify_consistency().is_err());
+        }
+    }
+
+    #[test]
+    fn key_bounds_need_an_ordered_primary() {
+        let dir = tempdir().unwrap();
+        let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), ParityOptions::default()).unwrap();
+        assert!(store.first_key().is_err());
+        assert!(store.last_key().is_err());
+    }
+
+    #[test]
+    fn shared_open_modes_suite() {
+        let dir = tempdir().unwrap();
+        open_modes(dir.path(), |path, mode| {
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_mode(path, Layout::plain(0), ParityOptions::default(), mode)
+        });
+    }
+    #[test]
+    fn shared_builder_paths_suite() {
+        let dir = tempdir().unwrap();
+        builder_paths(dir.path(), "store.parity", |path| {
+            StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain(0))
+                .coalesce_duplicates(true)
+        });
+    }
+
+
+    #[test]
+    fn shared_read_only_suite() {
+        let dir = tempdir().unwrap();
+        read_only_rejects_writes(
+            || Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), ParityOptions::default()).unwrap(),
+            || Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::plain(0)).unwrap(),
+        );
+    }
+
+    #[test]
+    fn shared_mismatched_layout_suite() {
+        let dir = tempdir().unwrap();
+        mismatched_layout(
+            || Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::dictionary(0)).unwrap(),
+            || Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0)),
+            || Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::range(0)),
+        );
+    }
+
+    #[test]
+    fn write_only_dictionary_skips_the_reverse_index() {
+        let layout = Layout::dictionary(0);
+        let options = ParityOptions::default().without_reverse_index();
+        let dir = tempdir().unwrap();
+        assert!(!build_options(dir.path(), &layout, options).columns[3].btree_index);
+        assert!(build_options(dir.path(), &layout, ParityOptions::default()).columns[3].btree_index);
+        let items: Vec<(Vec<u8>, Vec<u8>)> = (0..10u8).map(|i| (vec![i], vec![i % 2])).collect();
+        let mut store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), layout, options).unwrap();
+        let stats = store.commit_counted(items.iter().map(|(k, v)| (k, v))).unwrap();
+        // Two new values write two rows each and every key its birth key, with no btree entries.
+        assert_eq!(stats.rows, 2 * 2 + 10);
+        for (k, v) in &items {
+            assert_eq!(store.get_value(k).unwrap(), Some(v.clone()));
+        }
+        for err in [
+            store.get_keys_for_value(&vec![0]).unwrap_err(),
+            store.get_key_for_value(&vec![0]).unwrap_err(),
+            store.get_keys_for_values(&[vec![0]]).unwrap_err(),
+        ] {
+            assert!(err.to_string().contains("reverse index disabled"), "{err}");
+        }
+        assert!(store.distinct_values().is_err());
+    }
+
+    #[test]
+    fn uniform_columns_roundtrip_and_reject_short_keys() {
+        let dir = tempdir().unwrap();
+        let options = ParityOptions {
+            compression: CompressionType::Lz4,
+            uniform_keys: true,
+            uniform_values: true,
+            ..ParityOptions::default()
+        };
+        let hash = |i: u8| -> Vec<u8> { (0..32u8).map(|b| b.wrapping_mul(31).wrapping_add(i).rotate_left(3)).collect() };
+        let items: Vec<(Vec<u8>, Vec<u8>)> = (0..50u8).map(|i| (hash(i), hash(i + 100))).collect();
+        {
+            let mut store =
+                Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::unique_index(0), options)
+                    .unwrap();
+            store.commit(items.iter().map(|(k, v)| (k, v))).unwrap();
+            for (k, v) in &items {
+                assert_eq!(store.get_value(k).unwrap(), Some(v.clone()));
+                assert_eq!(store.get_key_for_value(v).unwrap(), Some(k.clone()));
+            }
+            let short = b"short".to_vec();
+            assert!(matches!(store.commit([(&short, &items[0].1)]), Err(Error::InvalidInput(_))));
+            assert!(matches!(store.get_value(&short), Err(Error::InvalidInput(_))));
+        }
+        let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::unique_index(0)).unwrap();
+        assert_eq!(store.get_value(&items[7].0).unwrap(), Some(items[7].1.clone()));
+    }
+
+    #[test]
+    fn chunked_commit_stores_every_row() {
+        let dir = tempdir().unwrap();
+        let options = ParityOptions::default().with_commit_chunk_ops(7);
+        let mut store =
+            Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::dictionary(0), options)
+                .unwrap();
+        let items: Vec<(Vec<u8>, Vec<u8>)> =
+            (0..1_000u32).map(|i| (i.to_be_bytes().to_vec(), (i % 10).to_be_bytes().to_vec())).collect();
+        store.commit(items.iter().map(|(k, v)| (k, v))).unwrap();
+        for (k, v) in &items {
+            assert_eq!(store.get_value(k).unwrap(), Some(v.clone()));
+        }
+        let value = 3u32.to_be_bytes().to_vec();
+        assert_eq!(store.get_keys_for_value(&value).unwrap().len(), 100);
     }
 }
@@ -1,18 +1,19 @@
-mod store;
-
 use std::path::{Path, PathBuf};
 
-use core::bench_codecs::{AddressCodec, AmountCodec, InvalidInput, KeyCodec, TimestampCodec, TxCodec};
+use core::bench_codecs::{AddressCodec, AmountCodec, DecodeError, InvalidInput, KeyCodec, TimestampCodec, TxCodec};
 use core::bench_common::{
-	self, run_all_parallel, run_dictionary, run_index, run_plain, run_range, Address, Amount, Key, NamedJob, Timestamp, TxHash,
+	self, parse_duration, run_all_parallel, run_dictionary, run_dictionary_reads, run_index, run_mixed, run_plain,
+	run_range, run_reads, run_settle, Address, Amount, BenchLayout, Key, KeyDistribution, LayoutSet, MixedLoad,
+	NamedJob, ReadLoad, RunLength, Timestamp, TxHash,
 };
-use crate::store::{Layout, Store, StoreError, StoreResult};
+use core::store_builder::StoreBuilder;
+use redb_bench::store::{Layout, Store, StoreError, StoreResult};
 
 struct RedbInvalid;
 
 impl InvalidInput<StoreError> for RedbInvalid {
-	fn invalid_input(msg: &'static str) -> StoreError {
-		StoreError::InvalidInput(msg.into())
+	fn invalid_input(err: DecodeError) -> StoreError {
+		StoreError::InvalidInput(err.to_string())
 	}
 }
 
@@ -25,8 +26,12 @@ type RAddressCodec = AddressCodec<StoreError>;
 fn main() -> StoreResult<()> {
     let mut args = std::env::args().skip(1);
     let mut total = 10_000_000u64;
+    let mut duration = None;
+    let mut resume = false;
     let mut base: Option<PathBuf> = None;
-    let mut benches: Option<Vec<String>> = None;
+    let mut layouts = LayoutSet::default();
+    let mut read_load = ReadLoad::default();
+    let mut mixed_load = None;
 
 	while let Some(arg) = args.next() {
 		match arg.as_str() {
@@ -35,14 +40,40 @@ fn main() -> StoreResult<()> {
 					total = v;
 				}
 			},
+			"--resume" => resume = true,
+			"--duration" => {
+				if let Some(d) = args.next().and_then(|s| parse_duration(&s)) {
+					duration = Some(d);
+				}
+			},
             "--dir" => {
                 if let Some(p) = args.next() {
                     base = Some(PathBuf::from(p));
                 }
             },
-            "--benches" => {
+            "--reads" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+                    read_load.reads = v;
+                }
+            },
+            "--distribution" => {
+                if let Some(d) = args.next().and_then(|s| KeyDistribution::parse(&s)) {
+                    read_load.distribution = d;
+                }
+            },
+            "--theta" => {
+                if let Some(v) = args.next().and_then(|s| s.parse::<f64>().ok()).filter(|t| *t > 0.0 && *t < 1.0) {
+                    read_load.theta = v;
+                }
+            },
+            "--mixed" => {
+                if let Some(load) = args.next().and_then(|s| MixedLoad::parse(&s)) {
+                    mixed_load = Some(load);
+                }
+            },
+            "--layouts" | "--benches" => {
                 if let Some(list) = args.next() {
-                    benches = Some(list.split(',').map(|s| s.to_string()).collect());
+                    layouts = LayoutSet::parse(&list).unwrap_or_else(|err| panic!("--layouts: {err}"));
                 }
             },
             _ => {},
@@ -50,45 +81,114 @@ fn main() -> StoreResult<()> {
     }
 
 	let base = base.unwrap_or_else(|| std::env::temp_dir().join(Path::new("redb_bench")));
+	// `--resume` keeps the stores of an interrupted run and continues each ingest from its checkpoint.
+	let length = if resume { RunLength::Resumable(total) } else { RunLength::new(total, duration) };
 
-    bench_common::cleanup_dirs(&base, &["plain", "index", "range", "dictionary"]);
+	if !resume {
+		bench_common::cleanup_dirs(&base, &layouts);
+	}
 
     let jobs: Vec<NamedJob<StoreError>> = vec![
         {
             let base = base.clone();
-            NamedJob::new("plain", Box::new(move || run_plain(&base, total, redb_plain_factory)))
+            NamedJob::with_progress(
+                BenchLayout::Plain,
+                Box::new(move |board| run_plain(&base, length, board, redb_plain_factory)),
+            )
+        },
+        {
+            let base = base.clone();
+            NamedJob::with_progress(
+                BenchLayout::Index,
+                Box::new(move |board| run_index(&base, length, board, redb_index_factory)),
+            )
+        },
+        {
+            let base = base.clone();
+            NamedJob::with_progress(
+                BenchLayout::Range,
+                Box::new(move |board| run_range(&base, length, board, redb_range_factory)),
+            )
+        },
+        {
+            let base = base.clone();
+            NamedJob::with_progress(
+                BenchLayout::Dictionary,
+                Box::new(move |board| run_dictionary(&base, length, board, redb_dictionary_factory)),
+            )
         },
+    ];
+
+    run_all_parallel(jobs, &layouts)?;
+
+    // Reopen each store and settle it, so reads of every backend start from its read-optimized layout.
+    let settles: Vec<NamedJob<StoreError>> = vec![
         {
             let base = base.clone();
-            NamedJob::new("index", Box::new(move || run_index(&base, total, redb_index_factory)))
+            NamedJob::new(BenchLayout::Plain, Box::new(move || run_settle(&base, "plain", redb_plain_factory)))
         },
         {
             let base = base.clone();
-            NamedJob::new("range", Box::new(move || run_range(&base, total, redb_range_factory)))
+            NamedJob::new(BenchLayout::Index, Box::new(move || run_settle(&base, "index", redb_index_factory)))
         },
         {
             let base = base.clone();
-            NamedJob::new("dictionary", Box::new(move || run_dictionary(&base, total, redb_dictionary_factory)))
+            NamedJob::new(BenchLayout::Range, Box::new(move || run_settle(&base, "range", redb_range_factory)))
+        },
+        {
+            let base = base.clone();
+            NamedJob::new(
+                BenchLayout::Dictionary,
+                Box::new(move || run_settle(&base, "dictionary", redb_dictionary_factory)),
+            )
         },
     ];
 
-    run_all_parallel(jobs, benches.as_deref().unwrap_or(&[]))?;
+    run_all_parallel(settles, &layouts)?;
+
+	// Time lookups of the settled plain and dictionary stores, keys drawn per `--distribution`.
+	let reads: Vec<NamedJob<StoreError>> = vec![
+		{
+			let base = base.clone();
+			NamedJob::new(BenchLayout::Plain, Box::new(move || run_reads(&base, length, read_load, redb_plain_factory)))
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Dictionary,
+				Box::new(move || run_dictionary_reads(&base, length, read_load, redb_dictionary_factory).map(drop)),
+			)
+		},
+	];
+	run_all_parallel(reads, &layouts)?;
+
+	// Write a fresh plain store while reading it, per `--mixed <writers>:<readers>`.
+	if let Some(load) = mixed_load {
+		let mixed: Vec<NamedJob<StoreError>> = vec![{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Plain,
+				Box::new(move || run_mixed(&base, length, load, redb_plain_factory).map(drop)),
+			)
+		}];
+		run_all_parallel(mixed, &layouts)?;
+	}
 
 	Ok(())
 }
 
 fn redb_plain_factory(path: &Path) -> StoreResult<Store<Key, Amount, RKeyCodec, RAmountCodec>> {
-	Store::open_with_options(path, Layout::plain(), ())
+	StoreBuilder::new(path, Layout::plain()).open()
 }
 
 fn redb_index_factory(path: &Path) -> StoreResult<Store<Key, TxHash, RKeyCodec, RTxCodec>> {
-	Store::open_with_options(path, Layout::unique_index(), ())
+	StoreBuilder::new(path, Layout::unique_index()).open()
 }
 
 fn redb_range_factory(path: &Path) -> StoreResult<Store<Key, Timestamp, RKeyCodec, RTimestampCodec>> {
-	Store::open_with_options(path, Layout::range(), ())
+	StoreBuilder::new(path, Layout::range()).open()
 }
 
 fn redb_dictionary_factory(path: &Path) -> StoreResult<Store<Key, Address, RKeyCodec, RAddressCodec>> {
-	Store::open_with_options(path, Layout::dictionary(), ())
+	StoreBuilder::new(path, Layout::dictionary()).open()
 }
@@ -1,9 +1,18 @@
-use core::store_interface::{ProgressTracker, StoreCodec, StoreRead, StoreWrite};
+use core::dyn_store::BenchError;
+use core::inspect::KeyDump;
+use core::store_builder::{self, BuilderOptions};
+use core::store_interface::{
+	CommitStats, ConsistencyReport, ExpiringStore, Mismatch, OpenMode, ProgressTracker, Retryable, StoreCodec,
+	StoreRead, StoreWrite, NEVER_EXPIRES, check_expiring, check_indexed_value, check_layout, coalesce_by_encoded_key,
+	composite_key, composite_prefix, decode_exact, expiry_after, is_transient_io, sort_by_encoded_key, sort_by_encoding,
+	split_composite, stored_value, unexpired, unix_millis,
+};
 use redb::{
-	CommitError, Database, DatabaseError, Durability, ReadableDatabase, ReadableTable, SetDurabilityError,
-	StorageError, TableDefinition, TableError, TransactionError,
+	CommitError, CompactionError, Database, DatabaseError, Durability, ReadOnlyDatabase, ReadOnlyTable, ReadTransaction,
+	ReadableDatabase, ReadableTable, ReadableTableMetadata, SetDurabilityError, StorageError, TableDefinition,
+	TableError, TableHandle, TransactionError, UntypedTableHandle,
 };
-use std::{ffi::OsStr, fs, marker::PhantomData, path::{Path, PathBuf}};
+use std::{marker::PhantomData, path::{Path, PathBuf}, sync::Arc, time::Duration};
 
 #[derive(Debug)]
 pub enum StoreError {
@@ -14,6 +23,7 @@ pub enum StoreError {
 	Storage(StorageError),
 	SetDurability(SetDurabilityError),
 	Commit(CommitError),
+	Compaction(CompactionError),
 	InvalidInput(String),
 }
 
@@ -27,6 +37,7 @@ impl std::fmt::Display for StoreError {
 			StoreError::Storage(err) => write!(f, "redb storage error: {err}"),
 			StoreError::SetDurability(err) => write!(f, "redb durability error: {err}"),
 			StoreError::Commit(err) => write!(f, "redb commit error: {err}"),
+			StoreError::Compaction(err) => write!(f, "redb compaction error: {err}"),
 			StoreError::InvalidInput(msg) => write!(f, "invalid input: {msg}"),
 		}
 	}
@@ -34,6 +45,28 @@ impl std::fmt::Display for StoreError {
 
 impl std::error::Error for StoreError {}
 
+/// redb queues write transactions rather than failing them on contention, so only an interrupted
+/// or timed out io call is worth trying again.
+impl Retryable for StoreError {
+	fn is_retryable(&self) -> bool {
+		let storage = match self {
+			StoreError::Redb(redb::Error::Io(err)) => return is_transient_io(err),
+			StoreError::Storage(err)
+			| StoreError::Tx(TransactionError::Storage(err))
+			| StoreError::Table(TableError::Storage(err))
+			| StoreError::Commit(CommitError::Storage(err)) => err,
+			_ => return false,
+		};
+		matches!(storage, StorageError::Io(err) if is_transient_io(err))
+	}
+}
+
+impl From<StoreError> for BenchError {
+	fn from(err: StoreError) -> Self {
+		BenchError::store(err)
+	}
+}
+
 impl From<redb::Error> for StoreError {
 	fn from(err: redb::Error) -> Self {
 		StoreError::Redb(err)
@@ -64,6 +97,12 @@ impl From<CommitError> for StoreError {
 	}
 }
 
+impl From<CompactionError> for StoreError {
+	fn from(err: CompactionError) -> Self {
+		StoreError::Compaction(err)
+	}
+}
+
 impl From<TableError> for StoreError {
 	fn from(err: TableError) -> Self {
 		StoreError::Table(err)
@@ -82,13 +121,22 @@ impl From<std::io::Error> for StoreError {
 	}
 }
 
-impl StoreError {
-	fn other<E: std::fmt::Display>(err: E) -> Self {
-		StoreError::InvalidInput(err.to_string())
-	}
+pub type StoreResult<T> = Result<T, StoreError>;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RedbOptions {
+	/// Skip writes a later one in the same commit overwrites; see `coalesce_by_encoded_key`.
+	pub coalesce_duplicates: bool,
+	/// `Plain` only: keep each value behind its expiry for `ExpiringStore`. A store must always
+	/// be opened with the setting it was created with.
+	pub expiring: bool,
 }
 
-pub type StoreResult<T> = Result<T, StoreError>;
+impl BuilderOptions for RedbOptions {
+	fn set_coalesce_duplicates(&mut self, coalesce: bool) {
+		self.coalesce_duplicates = coalesce;
+	}
+}
 
 /// Storage layouts supported by the generic store.
 #[derive(Clone, Copy)]
@@ -112,6 +160,46 @@ impl Layout {
 	pub fn dictionary() -> Self {
 		Layout::Dictionary
 	}
+
+	/// Name the `LAYOUT` table records at creation, so a reopen with another layout fails.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Layout::Plain => "plain",
+			Layout::UniqueIndex => "unique_index",
+			Layout::Range => "range",
+			Layout::Dictionary => "dictionary",
+		}
+	}
+
+	fn tables(&self) -> &'static [TableDefinition<'static, &'static [u8], &'static [u8]>] {
+		match self {
+			Layout::Plain => &[KEY_TO_VALUE],
+			Layout::UniqueIndex => &[KEY_TO_VALUE, VALUE_TO_KEY],
+			Layout::Range => &[KEY_TO_VALUE, VALUE_KEY_BTREE],
+			Layout::Dictionary => &[KEY_TO_BIRTH_KEY, BIRTH_KEY_TO_VALUE, VALUE_TO_BIRTH_KEY, BIRTH_KEY_KEY_BTREE],
+		}
+	}
+}
+
+enum Db {
+	Writable(Database),
+	ReadOnly(ReadOnlyDatabase),
+}
+
+impl Db {
+	fn begin_read(&self) -> Result<ReadTransaction, TransactionError> {
+		match self {
+			Db::Writable(db) => db.begin_read(),
+			Db::ReadOnly(db) => db.begin_read(),
+		}
+	}
+
+	fn writable(&self) -> StoreResult<&Database> {
+		match self {
+			Db::Writable(db) => Ok(db),
+			Db::ReadOnly(_) => Err(StoreError::InvalidInput("store is opened read-only".into())),
+		}
+	}
 }
 
 /// Generic store operating on a chosen layout and codecs.
@@ -120,12 +208,19 @@ where
 	KC: StoreCodec<K, Error = StoreError>,
 	VC: StoreCodec<V, Error = StoreError>,
 {
-	db: Database,
+	db: Db,
 	layout: Layout,
+	coalesce_duplicates: bool,
+	expiring: bool,
 	progress: Option<ProgressTracker>,
 	_ph: PhantomData<(K, V, KC, VC)>,
 }
 
+/// A `Store` shared across threads: every read takes `&self`, and the store is `Send + Sync` for
+/// `Send + Sync` key and value types. Every read begins its own read transaction, which never waits
+/// for a commit; commits take `&mut self` and need a lock of the caller's.
+pub type SharedStore<K, V, KC, VC> = Arc<Store<K, V, KC, VC>>;
+
 const KEY_TO_VALUE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("k2v");
 const VALUE_TO_KEY: TableDefinition<&[u8], &[u8]> = TableDefinition::new("v2k");
 const VALUE_KEY_BTREE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("vkb");
@@ -133,6 +228,9 @@ const KEY_TO_BIRTH_KEY: TableDefinition<&[u8], &[u8]> = TableDefinition::new("k2
 const BIRTH_KEY_TO_VALUE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("pk2v");
 const VALUE_TO_BIRTH_KEY: TableDefinition<&[u8], &[u8]> = TableDefinition::new("v2pk");
 const BIRTH_KEY_KEY_BTREE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("pkkb");
+/// Holds the name of the store's layout under `LAYOUT_KEY`.
+const LAYOUT: TableDefinition<&str, &str> = TableDefinition::new("layout");
+const LAYOUT_KEY: &str = "layout";
 
 impl<K, V, KC, VC> Store<K, V, KC, VC>
 where
@@ -140,14 +238,36 @@ where
 	VC: StoreCodec<V, Error = StoreError>,
 {
 	pub fn open(path: &Path, layout: Layout) -> StoreResult<Self> {
-		Self::open_with_options(path, layout, ())
+		Self::open_with_options(path, layout, RedbOptions::default())
 	}
 
-	pub fn open_with_options(path: &Path, layout: Layout, _options: ()) -> StoreResult<Self> {
+	pub fn open_with_options(path: &Path, layout: Layout, options: RedbOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain)).map_err(StoreError::InvalidInput)?;
 		let db_path = db_file_path(path)?;
-		let db = Database::create(db_path)?;
+		Self::from_database_with_options(Database::create(db_path)?, layout, options)
+	}
+
+	/// Builds the layout on a database the caller already opened, e.g. one holding tables of its
+	/// own, creating the layout's tables if missing. redb locks its file, so a second handle to it
+	/// could not be opened; `into_database` hands this one back.
+	pub fn from_database(db: Database, layout: Layout) -> StoreResult<Self> {
+		Self::from_database_with_options(db, layout, RedbOptions::default())
+	}
+
+	pub fn from_database_with_options(db: Database, layout: Layout, options: RedbOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain)).map_err(StoreError::InvalidInput)?;
 		{
 			let tx = db.begin_write()?;
+			let tables = table_names(tx.list_tables()?);
+			let mut tag = tx.open_table(LAYOUT)?;
+			let recorded = tag.get(LAYOUT_KEY)?.map(|name| name.value().to_string());
+			if let Some(stored) = recorded.as_deref().or_else(|| layout_of_tables(&tables)) {
+				check_layout(stored, layout.name()).map_err(StoreError::InvalidInput)?;
+			}
+			if recorded.is_none() {
+				tag.insert(LAYOUT_KEY, layout.name())?;
+			}
+			drop(tag);
 			match layout {
 				Layout::Plain => {
 					tx.open_table(KEY_TO_VALUE)?;
@@ -169,7 +289,58 @@ where
 			}
 			tx.commit()?;
 		}
-		Ok(Self { db, layout, progress: None, _ph: PhantomData })
+		Ok(Self {
+			db: Db::Writable(db),
+			layout,
+			coalesce_duplicates: options.coalesce_duplicates,
+			expiring: options.expiring,
+			progress: None,
+			_ph: PhantomData,
+		})
+	}
+
+	/// `open_with_options` after checking `mode` against whether `path` already holds a store.
+	pub fn open_with_mode(path: &Path, layout: Layout, options: RedbOptions, mode: OpenMode) -> StoreResult<Self> {
+		mode.check(path, store_exists(path)).map_err(StoreError::InvalidInput)?;
+		Self::open_with_options(path, layout, options)
+	}
+
+	/// Shares the file with other read-only handles; tables must already exist.
+	pub fn open_read_only(path: &Path, layout: Layout) -> StoreResult<Self> {
+		Self::open_read_only_with_options(path, layout, RedbOptions::default())
+	}
+
+	/// `open_read_only` of a store created with `options`; only `expiring` matters to reads.
+	pub fn open_read_only_with_options(path: &Path, layout: Layout, options: RedbOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain)).map_err(StoreError::InvalidInput)?;
+		let db = ReadOnlyDatabase::open(db_file(path))?;
+		let tx = db.begin_read()?;
+		let recorded = match tx.open_table(LAYOUT) {
+			Ok(tag) => tag.get(LAYOUT_KEY)?.map(|name| name.value().to_string()),
+			Err(TableError::TableDoesNotExist(_)) => None,
+			Err(err) => return Err(err.into()),
+		};
+		let tables = table_names(tx.list_tables()?);
+		if let Some(stored) = recorded.as_deref().or_else(|| layout_of_tables(&tables)) {
+			check_layout(stored, layout.name()).map_err(StoreError::InvalidInput)?;
+		}
+		drop(tx);
+		Ok(Self {
+			db: Db::ReadOnly(db),
+			layout,
+			coalesce_duplicates: false,
+			expiring: options.expiring,
+			progress: None,
+			_ph: PhantomData,
+		})
+	}
+
+	/// The database under the store, `None` for a read-only one.
+	pub fn into_database(self) -> Option<Database> {
+		match self.db {
+			Db::Writable(db) => Some(db),
+			Db::ReadOnly(_) => None,
+		}
 	}
 
 	pub fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
@@ -178,16 +349,68 @@ where
 		K: 'a,
 		V: 'a,
 	{
-		let mut processed = 0u64;
-		let mut write_tx = self.db.begin_write()?;
+		self.commit_counted(items).map(|_| ())
+	}
+
+	/// With `coalesce_duplicates` set, a write the batch overwrites later is skipped.
+	pub fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.commit_expiring(items, self.expiring.then_some(NEVER_EXPIRES))
+	}
+
+	/// `commit` of `Plain` entries that `get_value` stops returning once `ttl` has passed.
+	pub fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.ensure_expiring()?;
+		self.commit_expiring(items, Some(expiry_after(ttl))).map(|_| ())
+	}
+
+	/// `expires_at` is the expiry of every entry, `None` for a store without expiring entries.
+	fn commit_expiring<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		if self.coalesce_duplicates {
+			self.write_counted(coalesce_by_encoded_key::<K, V, KC, _>(items), expires_at)
+		} else {
+			self.write_counted(items, expires_at)
+		}
+	}
+
+	fn ensure_expiring(&self) -> StoreResult<()> {
+		if !self.expiring {
+			return Err(StoreError::InvalidInput("store is not opened with expiring entries".into()))
+		}
+		Ok(())
+	}
+
+	fn write_counted<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		let mut stats = CommitStats::default();
+		let mut write_tx = self.db.writable()?.begin_write()?;
 		match self.layout {
 			Layout::Plain => {
 				let mut k2v = write_tx.open_table(KEY_TO_VALUE)?;
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					k2v.insert(kbytes.as_ref(), vbytes.as_ref())?;
-					processed += 1;
+					let stored = stored_value(vbytes.as_ref(), expires_at);
+					k2v.insert(kbytes.as_ref(), stored.as_ref())?;
+					stats.record(kbytes.as_ref(), &stored);
 				}
 			},
 			Layout::UniqueIndex => {
@@ -196,9 +419,11 @@ where
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
 					k2v.insert(kbytes.as_ref(), vbytes.as_ref())?;
 					v2k.insert(vbytes.as_ref(), kbytes.as_ref())?;
-					processed += 2;
+					stats.record(kbytes.as_ref(), vbytes.as_ref());
+					stats.record(vbytes.as_ref(), kbytes.as_ref());
 				}
 			},
 			Layout::Range => {
@@ -208,9 +433,10 @@ where
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
 					k2v.insert(kbytes.as_ref(), vbytes.as_ref())?;
-					let vk = concat(vbytes.as_ref(), kbytes.as_ref());
+					let vk = composite_key(vbytes.as_ref(), kbytes.as_ref());
 					vkb.insert(vk.as_slice(), &[] as &[u8])?;
-					processed += 2;
+					stats.record(kbytes.as_ref(), vbytes.as_ref());
+					stats.record(&vk, &[]);
 				}
 			},
 			Layout::Dictionary => {
@@ -219,58 +445,77 @@ where
 				let mut pk2v = write_tx.open_table(BIRTH_KEY_TO_VALUE)?;
 				let mut v2pk = write_tx.open_table(VALUE_TO_BIRTH_KEY)?;
 				let mut pk_k_btree = write_tx.open_table(BIRTH_KEY_KEY_BTREE)?;
-				let mut cache: HashMap<Vec<u8>, (Vec<u8>, bool)> = HashMap::new();
+				let mut cache: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					let (pk, is_new) = if let Some(entry) = cache.get(vbytes.as_ref()) {
-						entry.clone()
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
+					let (pk, is_new) = if let Some(pk) = cache.get(vbytes.as_ref()) {
+						(pk.clone(), false)
 					} else if let Ok(Some(pk)) = v2pk.get(vbytes.as_ref()) {
 						let pk_vec = pk.value().to_vec();
-						cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), false));
+						cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, false)
 					} else {
 						let pk_vec = kbytes.as_ref().to_vec();
-						cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), true));
+						cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, true)
 					};
 
 					if is_new {
 						v2pk.insert(vbytes.as_ref(), pk.as_slice())?;
 						pk2v.insert(pk.as_slice(), vbytes.as_ref())?;
-						processed += 2;
+						stats.record(vbytes.as_ref(), &pk);
+						stats.record(&pk, vbytes.as_ref());
 					}
 					k2pk.insert(kbytes.as_ref(), pk.as_slice())?;
-					let pk_key = concat(&pk, kbytes.as_ref());
+					let pk_key = composite_key(&pk, kbytes.as_ref());
 					pk_k_btree.insert(pk_key.as_slice(), &[] as &[u8])?;
-					processed += 2;
+					stats.record(kbytes.as_ref(), &pk);
+					stats.record(&pk_key, &[]);
 				}
 			},
 		}
 		write_tx.set_durability(Durability::None)?;
 		write_tx.commit()?;
 		if let Some(p) = self.progress.as_mut() {
-			p.record(processed);
+			p.record(stats.rows);
 		}
-		Ok(())
+		Ok(stats)
 	}
 
 	pub fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		self.read_value(key, VC::decode)
+	}
+
+	/// `key`'s value as stored, `VC::encode` of what `get_value` returns, without decoding it.
+	pub fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		self.read_value(key, |bytes| Ok(bytes.to_vec()))
+	}
+
+	/// Passes the encoded value of `key`, stripped of its expiry, to `read`.
+	fn read_value<T>(&self, key: &K, read: impl FnOnce(&[u8]) -> StoreResult<T>) -> StoreResult<Option<T>> {
 		let kbytes = KC::encode(key);
-		let read_tx = self.db.begin_read().map_err(StoreError::other)?;
+		let read_tx = self.db.begin_read()?;
 		match self.layout {
+			Layout::Plain if self.expiring => {
+				let k2v = read_tx.open_table(KEY_TO_VALUE)?;
+				let Some(stored) = k2v.get(kbytes.as_ref())? else { return Ok(None) };
+				let value = unexpired(stored.value(), unix_millis()).map_err(StoreError::InvalidInput)?;
+				value.map(read).transpose()
+			},
 			Layout::Plain | Layout::UniqueIndex | Layout::Range => {
-				let k2v = read_tx.open_table(KEY_TO_VALUE).map_err(StoreError::other)?;
+				let k2v = read_tx.open_table(KEY_TO_VALUE)?;
 				k2v.get(kbytes.as_ref())?
-					.map(|v| VC::decode(v.value()))
+					.map(|v| read(v.value()))
 					.transpose()
 			},
 			Layout::Dictionary => {
-				let k2pk = read_tx.open_table(KEY_TO_BIRTH_KEY).map_err(StoreError::other)?;
-				let pk2v = read_tx.open_table(BIRTH_KEY_TO_VALUE).map_err(StoreError::other)?;
+				let k2pk = read_tx.open_table(KEY_TO_BIRTH_KEY)?;
+				let pk2v = read_tx.open_table(BIRTH_KEY_TO_VALUE)?;
 				if let Some(pk) = k2pk.get(kbytes.as_ref())? {
 					pk2v.get(pk.value())?
-						.map(|v| VC::decode(v.value()))
+						.map(|v| read(v.value()))
 						.transpose()
 				} else {
 					Ok(None)
@@ -279,52 +524,41 @@ where
 		}
 	}
 
+	/// What the store holds for `key`: its encoding and that of its value, printing as hex dumps,
+	/// for a lookup that returns something unexpected.
+	pub fn debug_dump(&self, key: &K) -> StoreResult<KeyDump> {
+		let value = self.get_value(key)?;
+		Ok(KeyDump::new::<K, V, KC, VC>(key, value.as_ref()))
+	}
+
 	pub fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
 		let vbytes = VC::encode(value);
-		let read_tx = self.db.begin_read().map_err(StoreError::other)?;
+		let read_tx = self.db.begin_read()?;
 		match self.layout {
 			Layout::UniqueIndex => {
-				let v2k = read_tx.open_table(VALUE_TO_KEY).map_err(StoreError::other)?;
-				v2k.get(vbytes.as_ref())?.map(|k| KC::decode(k.value())).transpose()
+				let v2k = read_tx.open_table(VALUE_TO_KEY)?;
+				v2k.get(vbytes.as_ref())?
+					.map(|k| decode_exact::<K, KC>(k.value(), StoreError::InvalidInput))
+					.transpose()
 			},
+			Layout::Range | Layout::Dictionary => Ok(self.get_keys_for_value(value)?.into_iter().next()),
 			_ => Err(StoreError::InvalidInput("get_key_for_value not supported for this layout".into())),
 		}
 	}
 
 	pub fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
 		let vbytes = VC::encode(value);
-		let read_tx = self.db.begin_read().map_err(StoreError::other)?;
+		let read_tx = self.db.begin_read()?;
 		match self.layout {
 			Layout::Range => {
-				let vkb = read_tx.open_table(VALUE_KEY_BTREE).map_err(StoreError::other)?;
-				let mut out = Vec::new();
-				let mut cursor = vkb.range(vbytes.as_ref()..)?;
-				while let Some(Ok((k, _))) = cursor.next() {
-					let kslice = k.value();
-					if !kslice.starts_with(vbytes.as_ref()) {
-						break
-					}
-					let key_bytes = &kslice[vbytes.as_ref().len()..];
-					out.push(KC::decode(key_bytes)?);
-				}
-				Ok(out)
+				let vkb = read_tx.open_table(VALUE_KEY_BTREE)?;
+				btree_keys::<K, KC>(&vkb, vbytes.as_ref())
 			},
 			Layout::Dictionary => {
-				let v2pk = read_tx.open_table(VALUE_TO_BIRTH_KEY).map_err(StoreError::other)?;
-				let pk_k_btree = read_tx.open_table(BIRTH_KEY_KEY_BTREE).map_err(StoreError::other)?;
+				let v2pk = read_tx.open_table(VALUE_TO_BIRTH_KEY)?;
+				let pk_k_btree = read_tx.open_table(BIRTH_KEY_KEY_BTREE)?;
 				if let Some(pk) = v2pk.get(vbytes.as_ref())? {
-					let pk = pk.value();
-					let mut out = Vec::new();
-					let mut cursor = pk_k_btree.range(pk..)?;
-					while let Some(Ok((k, _))) = cursor.next() {
-						let kslice = k.value();
-						if !kslice.starts_with(pk) {
-							break
-						}
-						let key_bytes = &kslice[pk.len()..];
-						out.push(KC::decode(key_bytes)?);
-					}
-					Ok(out)
+					btree_keys::<K, KC>(&pk_k_btree, pk.value())
 				} else {
 					Ok(Vec::new())
 				}
@@ -333,74 +567,316 @@ where
 		}
 	}
 
-	pub fn flush(&mut self) -> StoreResult<()> {
-		Ok(())
+	/// One read transaction for the whole batch. Btree scans run in head order, so consecutive
+	/// scans read neighbouring pages; `Dictionary` resolves birth keys in value order first.
+	pub fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		let read_tx = self.db.begin_read()?;
+		let mut out: Vec<Vec<K>> = values.iter().map(|_| Vec::new()).collect();
+		match self.layout {
+			Layout::Range => {
+				let vkb = read_tx.open_table(VALUE_KEY_BTREE)?;
+				for (idx, vbytes) in sort_by_encoding::<V, VC>(values) {
+					out[idx] = btree_keys::<K, KC>(&vkb, &vbytes)?;
+				}
+			},
+			Layout::Dictionary => {
+				let v2pk = read_tx.open_table(VALUE_TO_BIRTH_KEY)?;
+				let pk_k_btree = read_tx.open_table(BIRTH_KEY_KEY_BTREE)?;
+				let mut pks = Vec::with_capacity(values.len());
+				for (idx, vbytes) in sort_by_encoding::<V, VC>(values) {
+					if let Some(pk) = v2pk.get(vbytes.as_slice())? {
+						pks.push((idx, pk.value().to_vec()));
+					}
+				}
+				pks.sort_by(|a, b| a.1.cmp(&b.1));
+				for (idx, pk) in pks {
+					out[idx] = btree_keys::<K, KC>(&pk_k_btree, &pk)?;
+				}
+			},
+			_ => return Err(StoreError::InvalidInput("get_keys_for_values not supported for this layout".into())),
+		}
+		Ok(out)
 	}
-}
 
-fn db_file_path(path: &Path) -> StoreResult<PathBuf> {
-	if path.extension() == Some(OsStr::new("redb")) {
-		return Ok(path.to_path_buf())
+	/// Lazy over a read snapshot taken now; commits made while iterating are not seen.
+	pub fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		match self.layout {
+			Layout::Dictionary => {
+				let read_tx = self.db.begin_read()?;
+				let pk2v = read_tx.open_table(BIRTH_KEY_TO_VALUE)?;
+				Ok(pk2v.range::<&[u8]>(..)?.map(|kv| VC::decode(kv?.1.value())))
+			},
+			_ => Err(StoreError::InvalidInput("distinct_values not supported for this layout".into())),
+		}
 	}
-	fs::create_dir_all(path)?;
-	Ok(path.join("db.redb"))
-}
 
-fn concat(a: &[u8], b: &[u8]) -> Vec<u8> {
-	let mut out = Vec::with_capacity(a.len() + b.len());
-	out.extend_from_slice(a);
-	out.extend_from_slice(b);
-	out
-}
+	/// The first key in the primary table, in encoded key order.
+	pub fn first_key(&self) -> StoreResult<Option<K>> {
+		let read_tx = self.db.begin_read()?;
+		let table = read_tx.open_table(self.primary_table())?;
+		table.first()?.map(|(k, _)| KC::decode(k.value())).transpose()
+	}
 
-#[cfg(test)]
-mod tests {
-	use super::*;
-	use core::store_tests::{basic_value_roundtrip, multiple_keys_for_value, reverse_lookup_unique};
-	use tempfile::tempdir;
+	/// The last key in the primary table, in encoded key order.
+	pub fn last_key(&self) -> StoreResult<Option<K>> {
+		let read_tx = self.db.begin_read()?;
+		let table = read_tx.open_table(self.primary_table())?;
+		table.last()?.map(|(k, _)| KC::decode(k.value())).transpose()
+	}
 
-	struct BytesCodec;
+	/// The length redb keeps in the primary table's root, exact and without a scan.
+	pub fn approx_key_count(&self) -> StoreResult<u64> {
+		let read_tx = self.db.begin_read()?;
+		Ok(read_tx.open_table(self.primary_table())?.len()?)
+	}
 
-	impl StoreCodec<Vec<u8>> for BytesCodec {
-		type Error = StoreError;
-		type Enc<'a> = &'a [u8] where Self: 'a, Vec<u8>: 'a;
-		fn encode<'a>(value: &'a Vec<u8>) -> Self::Enc<'a> {
-			value.as_slice()
+	/// The table holding every key: `key_to_value`, or `key_to_birth_key` for `Dictionary`.
+	fn primary_table(&self) -> TableDefinition<'static, &'static [u8], &'static [u8]> {
+		match self.layout {
+			Layout::Plain | Layout::UniqueIndex | Layout::Range => KEY_TO_VALUE,
+			Layout::Dictionary => KEY_TO_BIRTH_KEY,
 		}
-		fn decode(bytes: &[u8]) -> StoreResult<Vec<u8>> {
-			Ok(bytes.to_vec())
+	}
+
+	/// Checks the columns against one read snapshot.
+	pub fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		let mut report = ConsistencyReport::default();
+		let read_tx = self.db.begin_read()?;
+		match self.layout {
+			Layout::Plain => {},
+			Layout::UniqueIndex => {
+				let k2v = read_tx.open_table(KEY_TO_VALUE)?;
+				let v2k = read_tx.open_table(VALUE_TO_KEY)?;
+				for kv in k2v.range::<&[u8]>(..)? {
+					let (k, v) = kv?;
+					let (k, v) = (k.value(), v.value());
+					report.checked += 1;
+					if v2k.get(v)?.is_none_or(|back| back.value() != k) {
+						report.mismatches.push(Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() });
+					}
+				}
+			},
+			Layout::Range => {
+				let k2v = read_tx.open_table(KEY_TO_VALUE)?;
+				let vkb = read_tx.open_table(VALUE_KEY_BTREE)?;
+				for kv in k2v.range::<&[u8]>(..)? {
+					let (k, v) = kv?;
+					let (k, v) = (k.value(), v.value());
+					report.checked += 1;
+					if vkb.get(composite_key(v, k).as_slice())?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: v.to_vec(), key: k.to_vec() });
+					}
+				}
+			},
+			Layout::Dictionary => {
+				let k2pk = read_tx.open_table(KEY_TO_BIRTH_KEY)?;
+				let pk2v = read_tx.open_table(BIRTH_KEY_TO_VALUE)?;
+				let pk_k_btree = read_tx.open_table(BIRTH_KEY_KEY_BTREE)?;
+				for kv in k2pk.range::<&[u8]>(..)? {
+					let (k, pk) = kv?;
+					let (k, pk) = (k.value(), pk.value());
+					report.checked += 1;
+					if pk2v.get(pk)?.is_none() {
+						report.mismatches.push(Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: pk.to_vec() });
+					}
+					if pk_k_btree.get(composite_key(pk, k).as_slice())?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: pk.to_vec(), key: k.to_vec() });
+					}
+				}
+			},
 		}
+		Ok(report)
 	}
 
-	#[test]
-	fn shared_basic_suite() {
-		basic_value_roundtrip(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().join("db.redb");
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::plain(), ()).unwrap()
-		});
+	/// `commit` in encoded-key order with duplicate keys collapsed to the last write, so B-tree
+	/// inserts land on neighbouring pages instead of splitting pages all over the tree.
+	pub fn commit_sorted<'a, I>(&mut self, items: I) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		let expires_at = self.expiring.then_some(NEVER_EXPIRES);
+		self.write_counted(sort_by_encoded_key::<K, V, KC, _>(items), expires_at).map(|_| ())
 	}
 
-	#[test]
-	fn shared_reverse_suite() {
-		reverse_lookup_unique(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().join("db.redb");
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::unique_index(), ()).unwrap()
-		});
+	pub fn flush(&mut self) -> StoreResult<()> {
+		self.db.writable()?;
+		Ok(())
 	}
 
-	#[test]
-	fn shared_multiple_keys_suite() {
-		multiple_keys_for_value(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().join("db.redb");
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::range(), ()).unwrap()
-		});
+	/// Compacts the file, releasing the pages freed by copy-on-write commits. Commits here are
+	/// non-durable and keep the last durable state pinned, which `compact` refuses to run under,
+	/// so an empty durable commit comes first.
+	pub fn settle_for_reads(&mut self) -> StoreResult<()> {
+		let Db::Writable(db) = &mut self.db else {
+			return Err(StoreError::InvalidInput("store is opened read-only".into()))
+		};
+		db.begin_write()?.commit()?;
+		db.compact()?;
+		Ok(())
+	}
+
+	/// Deletes the expired entries of an expiring `Plain` store in one write transaction.
+	pub fn purge_expired(&mut self) -> StoreResult<u64> {
+		self.ensure_expiring()?;
+		let now = unix_millis();
+		let mut purged = 0;
+		let write_tx = self.db.writable()?.begin_write()?;
+		{
+			let mut k2v = write_tx.open_table(KEY_TO_VALUE)?;
+			k2v.retain(|_, stored| {
+				let expired = matches!(unexpired(stored, now), Ok(None));
+				purged += expired as u64;
+				!expired
+			})?;
+		}
+		write_tx.commit()?;
+		Ok(purged)
 	}
+
+	/// Removes `keys` with their entries in the layout's other tables, returning how many were
+	/// present. A `Dictionary` birth key's `pk2v` and `v2pk` entries go with the last key holding
+	/// it, found by scanning its `birth_key||key` prefix in `pkkb`; entries there of keys since
+	/// overwritten with another value are stale and dropped on the way.
+	pub fn delete<'a, I>(&mut self, keys: I) -> StoreResult<u64>
+	where
+		I: IntoIterator<Item = &'a K>,
+		K: 'a,
+	{
+		let mut deleted = 0;
+		let mut write_tx = self.db.writable()?.begin_write()?;
+		match self.layout {
+			Layout::Plain => {
+				let mut k2v = write_tx.open_table(KEY_TO_VALUE)?;
+				for k in keys {
+					deleted += k2v.remove(KC::encode(k).as_ref())?.is_some() as u64;
+				}
+			},
+			Layout::UniqueIndex => {
+				let mut k2v = write_tx.open_table(KEY_TO_VALUE)?;
+				let mut v2k = write_tx.open_table(VALUE_TO_KEY)?;
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(v) = k2v.remove(kbytes.as_ref())?.map(|v| v.value().to_vec()) else { continue };
+					deleted += 1;
+					// The value may have moved to another key since.
+					if v2k.get(v.as_slice())?.is_some_and(|owner| owner.value() == kbytes.as_ref()) {
+						v2k.remove(v.as_slice())?;
+					}
+				}
+			},
+			Layout::Range => {
+				let mut k2v = write_tx.open_table(KEY_TO_VALUE)?;
+				let mut vkb = write_tx.open_table(VALUE_KEY_BTREE)?;
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(v) = k2v.remove(kbytes.as_ref())?.map(|v| v.value().to_vec()) else { continue };
+					deleted += 1;
+					vkb.remove(composite_key(&v, kbytes.as_ref()).as_slice())?;
+				}
+			},
+			Layout::Dictionary => {
+				let mut k2pk = write_tx.open_table(KEY_TO_BIRTH_KEY)?;
+				let mut pk2v = write_tx.open_table(BIRTH_KEY_TO_VALUE)?;
+				let mut v2pk = write_tx.open_table(VALUE_TO_BIRTH_KEY)?;
+				let mut pk_k_btree = write_tx.open_table(BIRTH_KEY_KEY_BTREE)?;
+				for k in keys {
+					let kbytes = KC::encode(k);
+					let Some(pk) = k2pk.remove(kbytes.as_ref())?.map(|pk| pk.value().to_vec()) else { continue };
+					deleted += 1;
+					pk_k_btree.remove(composite_key(&pk, kbytes.as_ref()).as_slice())?;
+					let (mut live, mut stale) = (false, Vec::new());
+					for entry in pk_k_btree.range(composite_prefix(&pk).as_slice()..)? {
+						let (entry, _) = entry?;
+						match split_composite(entry.value()) {
+							Some((head, key)) if head == pk.as_slice() => {
+								if k2pk.get(key)?.is_some_and(|owner| owner.value() == pk.as_slice()) {
+									live = true;
+								} else {
+									stale.push(entry.value().to_vec());
+								}
+							},
+							_ => break,
+						}
+					}
+					for entry in stale {
+						pk_k_btree.remove(entry.as_slice())?;
+					}
+					if live {
+						continue
+					}
+					if let Some(v) = pk2v.remove(pk.as_slice())?.map(|v| v.value().to_vec())
+						&& v2pk.get(v.as_slice())?.is_some_and(|owner| owner.value() == pk.as_slice())
+					{
+						v2pk.remove(v.as_slice())?;
+					}
+				}
+			},
+		}
+		write_tx.set_durability(Durability::None)?;
+		write_tx.commit()?;
+		Ok(deleted)
+	}
+
+	pub fn clear(&mut self) -> StoreResult<()> {
+		let write_tx = self.db.writable()?.begin_write()?;
+		for table in self.layout.tables() {
+			write_tx.delete_table(*table)?;
+			write_tx.open_table(*table)?;
+		}
+		write_tx.commit()?;
+		Ok(())
+	}
+}
+
+/// Keys of the btree entries whose composite key starts with `head`.
+fn btree_keys<K, KC>(btree: &ReadOnlyTable<&'static [u8], &'static [u8]>, head: &[u8]) -> StoreResult<Vec<K>>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+{
+	let mut out = Vec::new();
+	for entry in btree.range(composite_prefix(head).as_slice()..)? {
+		let (k, _) = entry?;
+		match split_composite(k.value()) {
+			Some((h, key_bytes)) if h == head => out.push(KC::decode(key_bytes)?),
+			_ => break,
+		}
+	}
+	Ok(out)
+}
+
+/// The database file of a store at `path`; see `store_builder::db_file`.
+fn db_file(path: &Path) -> PathBuf {
+	store_builder::db_file(path, "redb")
+}
+
+fn db_file_path(path: &Path) -> StoreResult<PathBuf> {
+	Ok(store_builder::db_file_path(path, "redb")?)
+}
+
+fn table_names(tables: impl Iterator<Item = UntypedTableHandle>) -> Vec<String> {
+	tables.map(|table| table.name().to_string()).collect()
+}
+
+/// The layout whose tables a store from before `LAYOUT` recorded it holds, `None` for a new one;
+/// tables of the caller's own are not taken for any layout's.
+fn layout_of_tables(tables: &[String]) -> Option<&'static str> {
+	let has = |table: TableDefinition<&[u8], &[u8]>| tables.iter().any(|name| name == table.name());
+	[
+		(KEY_TO_BIRTH_KEY, Layout::Dictionary),
+		(VALUE_KEY_BTREE, Layout::Range),
+		(VALUE_TO_KEY, Layout::UniqueIndex),
+		(KEY_TO_VALUE, Layout::Plain),
+	]
+	.into_iter()
+	.find(|&(table, _)| has(table))
+	.map(|(_, layout)| layout.name())
+}
+
+/// Whether `path` holds a store, i.e. its database file exists.
+fn store_exists(path: &Path) -> bool {
+	db_file(path).is_file()
 }
 
 impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
@@ -414,6 +890,10 @@ where
 		Store::get_value(self, key)
 	}
 
+	fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		Store::get_value_raw(self, key)
+	}
+
 	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
 		Store::get_key_for_value(self, value)
 	}
@@ -421,6 +901,30 @@ where
 	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
 		Store::get_keys_for_value(self, value)
 	}
+
+	fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		Store::get_keys_for_values(self, values)
+	}
+
+	fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		Store::distinct_values(self)
+	}
+
+	fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		Store::verify_consistency(self)
+	}
+
+	fn first_key(&self) -> StoreResult<Option<K>> {
+		Store::first_key(self)
+	}
+
+	fn last_key(&self) -> StoreResult<Option<K>> {
+		Store::last_key(self)
+	}
+
+	fn approx_key_count(&self) -> StoreResult<u64> {
+		Store::approx_key_count(self)
+	}
 }
 
 impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
@@ -428,27 +932,440 @@ where
 	KC: StoreCodec<K, Error = StoreError>,
 	VC: StoreCodec<V, Error = StoreError>,
 {
-	type Options = ();
+	type Options = RedbOptions;
 	type Layout = Layout;
 
 	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
 		Store::open_with_options(path, layout, options)
 	}
 
-	fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
+	fn open_with_mode(path: &Path, layout: Self::Layout, options: Self::Options, mode: OpenMode) -> StoreResult<Self> {
+		Store::open_with_mode(path, layout, options, mode)
+	}
+
+	fn open_read_only(path: &Path, layout: Self::Layout) -> StoreResult<Self> {
+		Store::open_read_only(path, layout)
+	}
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
 	where
 		I: IntoIterator<Item = (&'a K, &'a V)>,
 		K: 'a,
 		V: 'a,
 	{
-		Store::commit(self, items)
+		Store::commit_counted(self, items)
+	}
+
+	fn commit_sorted<'a, I>(&mut self, items: I) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_sorted(self, items)
 	}
 
 	fn flush(&mut self) -> StoreResult<()> {
 		Store::flush(self)
 	}
 
-	fn set_progress(&mut self, label: &str, total: u64) {
-		self.progress = Some(ProgressTracker::new(label.to_string(), total));
+	fn settle_for_reads(&mut self) -> StoreResult<()> {
+		Store::settle_for_reads(self)
+	}
+
+	fn clear(&mut self) -> StoreResult<()> {
+		Store::clear(self)
+	}
+
+	fn set_progress_tracker(&mut self, tracker: ProgressTracker) {
+		self.progress = Some(tracker);
+	}
+}
+
+impl<K, V, KC, VC> ExpiringStore<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_with_ttl(self, items, ttl)
+	}
+
+	fn purge_expired(&mut self) -> StoreResult<u64> {
+		Store::purge_expired(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::store_tests::{
+		approximate_key_count, assert_send_sync, basic_value_roundtrip, batched_keys_for_values, builder_paths,
+		clear_then_reuse, coalesced_duplicate_keys, consistency_audit, dictionary_birth_key_stable,
+		dictionary_commit_stats, dictionary_delete, dictionary_distinct_values, empty_values, expiring_entries,
+		key_bounds, mismatched_layout, multiple_keys_for_value, open_modes, prefix_values_isolated, raw_values,
+		read_only_rejects_writes, reverse_lookup_unique, shared_readers, settle_keeps_contents,
+		sorted_commit_matches_unsorted, variable_length_keys_roundtrip,
+	};
+	use core::store_builder::StoreBuilder;
+	use tempfile::tempdir;
+
+	struct BytesCodec;
+
+	impl StoreCodec<Vec<u8>> for BytesCodec {
+		type Error = StoreError;
+		type Enc<'a> = &'a [u8] where Self: 'a, Vec<u8>: 'a;
+		fn encode<'a>(value: &'a Vec<u8>) -> Self::Enc<'a> {
+			value.as_slice()
+		}
+		fn decode(bytes: &[u8]) -> StoreResult<Vec<u8>> {
+			Ok(bytes.to_vec())
+		}
+	}
+
+	#[test]
+	fn shared_basic_suite() {
+		basic_value_roundtrip(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_variable_length_keys_suite() {
+		variable_length_keys_roundtrip(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_clear_suite() {
+		clear_then_reuse(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_sorted_commit_suite() {
+		sorted_commit_matches_unsorted(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_dictionary_birth_key_suite() {
+		dictionary_birth_key_stable(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_dictionary_commit_stats_suite() {
+		dictionary_commit_stats(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_reverse_suite() {
+		reverse_lookup_unique(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::unique_index()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_multiple_keys_suite() {
+		for layout in [Layout::range(), Layout::dictionary()] {
+			multiple_keys_for_value(|path| {
+				let path = path.join("db.redb");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_batched_keys_suite() {
+		for layout in [Layout::range(), Layout::dictionary()] {
+			batched_keys_for_values(|path| {
+				let path = path.join("db.redb");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_readers_suite() {
+		assert_send_sync::<SharedStore<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>();
+		for layout in [Layout::range(), Layout::dictionary()] {
+			shared_readers(|path| {
+				let path = path.join("db.redb");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn raw_values_suite() {
+		for layout in [Layout::plain(), Layout::range(), Layout::dictionary()] {
+			raw_values::<BytesCodec, _, _>(|path| {
+				let path = path.join("db.redb");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_prefix_values_suite() {
+		for layout in [Layout::range(), Layout::dictionary()] {
+			prefix_values_isolated(|path| {
+				let path = path.join("db.redb");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_coalesce_suite() {
+		let open = |path: &Path, layout, coalesce_duplicates| {
+			let path = path.join("db.redb");
+			let options = RedbOptions { coalesce_duplicates, ..RedbOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, layout, options).unwrap()
+		};
+		coalesced_duplicate_keys(
+			|path, c| open(path, Layout::plain(), c),
+			|path, c| open(path, Layout::unique_index(), c),
+		);
+	}
+
+	#[test]
+	fn shared_settle_suite() {
+		settle_keeps_contents(|path| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_key_bounds_suite() {
+		for layout in [Layout::plain(), Layout::dictionary()] {
+			key_bounds(|path| {
+				let path = path.join("db.redb");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_approximate_key_count_suite() {
+		for layout in [Layout::plain(), Layout::dictionary()] {
+			approximate_key_count(|path| {
+				let path = path.join("db.redb");
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_expiring_suite() {
+		let open = |path: &Path, layout| {
+			let path = path.join("db.redb");
+			let options = RedbOptions { expiring: true, ..RedbOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, layout, options)
+		};
+		expiring_entries(|path| open(path, Layout::plain()).unwrap());
+		assert!(open(tempdir().unwrap().path(), Layout::range()).is_err());
+	}
+
+	#[test]
+	fn shared_distinct_values_suite() {
+		let open = |path: &Path, layout| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+		};
+		dictionary_distinct_values(|path| open(path, Layout::dictionary()), |path| open(path, Layout::range()));
+	}
+
+	#[test]
+	fn shared_dictionary_delete_suite() {
+		let dir = tempdir().unwrap();
+		dictionary_delete(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&dir.path().join("db.redb"), Layout::dictionary()).unwrap(),
+			|store, keys| store.delete(keys),
+			|store, value| {
+				let read_tx = store.db.begin_read().unwrap();
+				let v2pk = read_tx.open_table(VALUE_TO_BIRTH_KEY).unwrap();
+				let pk2v = read_tx.open_table(BIRTH_KEY_TO_VALUE).unwrap();
+				v2pk.get(value).unwrap().is_some() || pk2v.iter().unwrap().any(|entry| entry.unwrap().1.value() == value)
+			},
+		);
+	}
+
+	#[test]
+	fn shared_empty_values_suite() {
+		let open = |path: &Path, layout| {
+			let path = path.join("db.redb");
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, layout).unwrap()
+		};
+		empty_values(
+			|path| open(path, Layout::plain()),
+			|path| open(path, Layout::unique_index()),
+			|path| open(path, Layout::range()),
+			|path| open(path, Layout::dictionary()),
+		);
+	}
+
+	#[test]
+	fn shared_consistency_audit_suite() {
+		type BytesStore = Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>;
+		let open = |path: &Path, layout| {
+			let path = path.join("db.redb");
+			BytesStore::open(&path, layout).unwrap()
+		};
+		let corrupt = |store: &mut BytesStore, damage: &dyn Fn(&redb::WriteTransaction)| {
+			let write_tx = store.db.writable().unwrap().begin_write().unwrap();
+			damage(&write_tx);
+			write_tx.commit().unwrap();
+		};
+		consistency_audit(|path| open(path, Layout::unique_index()), |store, k, v| {
+			corrupt(store, &|tx| {
+				tx.open_table(VALUE_TO_KEY).unwrap().insert(v, b"elsewhere".as_slice()).unwrap();
+			});
+			vec![Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() }]
+		});
+		consistency_audit(|path| open(path, Layout::range()), |store, k, v| {
+			corrupt(store, &|tx| {
+				tx.open_table(VALUE_KEY_BTREE).unwrap().remove(composite_key(v, k).as_slice()).unwrap();
+			});
+			vec![Mismatch::MissingBtreeEntry { head: v.to_vec(), key: k.to_vec() }]
+		});
+		// Every row has its own value, so each key is its own birth key.
+		consistency_audit(|path| open(path, Layout::dictionary()), |store, k, _| {
+			corrupt(store, &|tx| {
+				tx.open_table(BIRTH_KEY_TO_VALUE).unwrap().remove(k).unwrap();
+				tx.open_table(BIRTH_KEY_KEY_BTREE).unwrap().remove(composite_key(k, k).as_slice()).unwrap();
+			});
+			vec![
+				Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: k.to_vec() },
+				Mismatch::MissingBtreeEntry { head: k.to_vec(), key: k.to_vec() },
+			]
+		});
+	}
+
+	#[test]
+	fn shared_open_modes_suite() {
+		let dir = tempdir().unwrap();
+		open_modes(dir.path(), |path, mode| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_mode(
+				path,
+				Layout::plain(),
+				RedbOptions::default(),
+				mode,
+			)
+		});
+	}
+
+	#[test]
+	fn shared_builder_paths_suite() {
+		let dir = tempdir().unwrap();
+		builder_paths(dir.path(), "store.redb", |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain())
+				.coalesce_duplicates(true)
+		});
+		assert!(dir.path().join("dir/db.redb").is_file());
+		assert!(dir.path().join("nested/store.redb").is_file());
+		assert!(!dir.path().join("nested/store.redb/db.redb").exists());
+	}
+
+	#[test]
+	fn shared_read_only_suite() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.redb");
+		read_only_rejects_writes(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain()).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&path, Layout::plain()).unwrap(),
+		);
+	}
+
+	#[test]
+	fn shared_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.redb");
+		mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::dictionary()).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::plain()),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&path, Layout::range()),
+		);
+	}
+
+	#[test]
+	fn store_without_a_layout_tag_is_told_by_its_tables() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.redb");
+		{
+			let _store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::range()).unwrap();
+		}
+		{
+			// As written before the layout was recorded.
+			let db = Database::create(&path).unwrap();
+			let tx = db.begin_write().unwrap();
+			tx.delete_table(LAYOUT).unwrap();
+			tx.commit().unwrap();
+		}
+		let err = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&path, Layout::plain()).err().unwrap();
+		assert!(matches!(err, StoreError::InvalidInput(ref msg) if msg.contains("range layout")), "{err:?}");
+		Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(&path, Layout::range()).unwrap();
+		let tx = Database::create(&path).unwrap().begin_read().unwrap();
+		assert_eq!(tx.open_table(LAYOUT).unwrap().get(LAYOUT_KEY).unwrap().unwrap().value(), "range");
+	}
+
+	#[test]
+	fn missing_table_is_a_table_error() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("db.redb");
+		{
+			// A unique index whose `value_to_key` table went missing.
+			let db = Database::create(&path).unwrap();
+			let tx = db.begin_write().unwrap();
+			tx.open_table(KEY_TO_VALUE).unwrap().insert([1u8].as_slice(), [2u8].as_slice()).unwrap();
+			tx.open_table(LAYOUT).unwrap().insert(LAYOUT_KEY, Layout::unique_index().name()).unwrap();
+			tx.commit().unwrap();
+		}
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(&path, Layout::unique_index()).unwrap();
+		let err = store.get_key_for_value(&vec![2]).unwrap_err();
+		assert!(matches!(err, StoreError::Table(TableError::TableDoesNotExist(_))), "got {err:?}");
+		assert!(matches!(store.verify_consistency(), Err(StoreError::Table(TableError::TableDoesNotExist(_)))));
+		assert!(matches!(store.get_keys_for_value(&vec![2]), Err(StoreError::InvalidInput(_))));
+	}
+
+	#[test]
+	fn wraps_an_open_database_beside_its_own_tables() {
+		const APP: TableDefinition<&str, u64> = TableDefinition::new("app");
+		let dir = tempdir().unwrap();
+		let db = Database::create(dir.path().join("app.redb")).unwrap();
+		let tx = db.begin_write().unwrap();
+		tx.open_table(APP).unwrap().insert("height", 7).unwrap();
+		tx.commit().unwrap();
+
+		let mut store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::from_database(db, Layout::unique_index()).unwrap();
+		store.commit([(&vec![1], &vec![10]), (&vec![2], &vec![20])]).unwrap();
+		assert_eq!(store.get_value(&vec![1]).unwrap(), Some(vec![10]));
+		assert_eq!(store.get_key_for_value(&vec![20]).unwrap(), Some(vec![2]));
+
+		let db = store.into_database().unwrap();
+		let tx = db.begin_read().unwrap();
+		assert_eq!(tx.open_table(APP).unwrap().get("height").unwrap().unwrap().value(), 7);
+		let store = Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::from_database(db, Layout::unique_index()).unwrap();
+		assert_eq!(store.get_value(&vec![2]).unwrap(), Some(vec![20]));
 	}
 }
@@ -1,17 +1,21 @@
-mod store;
-
-use core::bench_codecs::{AddressCodec, AmountCodec, InvalidInput, KeyCodec, TimestampCodec, TxCodec};
+use core::bench_codecs::{AddressCodec, AmountCodec, DecodeError, InvalidInput, KeyCodec, TimestampCodec, TxCodec};
 use core::bench_common::{
-	self, run_all_parallel, run_dictionary, run_index, run_plain, run_range, Address, Amount, Key, NamedJob, Timestamp, TxHash,
+	self, parse_duration, run_all_parallel, run_dictionary, run_dictionary_reads, run_index, run_mixed, run_plain,
+	run_range, run_reads, run_settle, store_wal_dir, Address, Amount, BenchLayout, Key, KeyDistribution, LayoutSet,
+	MixedLoad, NamedJob, ReadLoad, RunLength, Timestamp, TxHash,
+};
+use core::store_builder::StoreBuilder;
+use rocksdb_bench::store::{encoded_len, Layout, RocksOptions, Store, StoreError, StoreResult};
+use std::{
+	path::{Path, PathBuf},
+	sync::OnceLock,
 };
-use crate::store::{Layout, Store, StoreError, StoreResult};
-use std::path::{Path, PathBuf};
 
 struct RocksInvalid;
 
 impl InvalidInput<StoreError> for RocksInvalid {
-	fn invalid_input(msg: &'static str) -> StoreError {
-		StoreError::InvalidInput(msg.into())
+	fn invalid_input(err: DecodeError) -> StoreError {
+		StoreError::InvalidInput(err.to_string())
 	}
 }
 
@@ -21,11 +25,19 @@ type RTimestampCodec = TimestampCodec<StoreError, RocksInvalid>;
 type RTxCodec = TxCodec<StoreError, RocksInvalid>;
 type RAddressCodec = AddressCodec<StoreError>;
 
+/// Base directory of the write-ahead logs, per `--wal-dir`; unset keeps them in each store's directory.
+static WAL_DIR: OnceLock<PathBuf> = OnceLock::new();
+
 fn main() -> StoreResult<()> {
 	let mut args = std::env::args().skip(1);
 	let mut total = 10_000_000u64;
+	let mut duration = None;
+	let mut resume = false;
 	let mut base: Option<PathBuf> = None;
-	let mut benches: Option<Vec<String>> = None;
+	let mut layouts = LayoutSet::default();
+	let mut read_load = ReadLoad::default();
+	let mut mixed_load = None;
+	let mut prefix_bloom = false;
 
 	while let Some(arg) = args.next() {
 		match arg.as_str() {
@@ -34,14 +46,46 @@ fn main() -> StoreResult<()> {
 					total = v;
 				}
 			},
+			"--resume" => resume = true,
+			"--duration" => {
+				if let Some(d) = args.next().and_then(|s| parse_duration(&s)) {
+					duration = Some(d);
+				}
+			},
 			"--dir" => {
 				if let Some(p) = args.next() {
 					base = Some(PathBuf::from(p));
 				}
 			},
-			"--benches" => {
+			"--prefix-bloom" => prefix_bloom = true,
+			"--reads" => {
+				if let Some(v) = args.next().and_then(|s| s.parse::<u64>().ok()) {
+					read_load.reads = v;
+				}
+			},
+			"--distribution" => {
+				if let Some(d) = args.next().and_then(|s| KeyDistribution::parse(&s)) {
+					read_load.distribution = d;
+				}
+			},
+			"--theta" => {
+				if let Some(v) = args.next().and_then(|s| s.parse::<f64>().ok()).filter(|t| *t > 0.0 && *t < 1.0) {
+					read_load.theta = v;
+				}
+			},
+			"--wal-dir" => {
+				if let Some(p) = args.next() {
+					WAL_DIR.set(PathBuf::from(p)).ok();
+				}
+			},
+			"--mixed" => {
+				if let Some(load) = args.next().and_then(|s| MixedLoad::parse(&s)) {
+					mixed_load = Some(load);
+				}
+			},
+			"--layouts" | "--benches" => {
 				if let Some(list) = args.next() {
-					benches = Some(list.split(',').map(|s| s.to_string()).collect());
+					layouts = LayoutSet::parse(&list).unwrap_or_else(|err| panic!("--layouts: {err}"));
 				}
 			},
 			_ => {},
@@ -49,45 +93,139 @@ fn main() -> StoreResult<()> {
 	}
 
 	let base = base.unwrap_or_else(|| std::env::temp_dir().join(Path::new("rocksdb_bench")));
+	// `--resume` keeps the stores of an interrupted run and continues each ingest from its checkpoint.
+	let length = if resume { RunLength::Resumable(total) } else { RunLength::new(total, duration) };
 
-	bench_common::cleanup_dirs(&base, &["plain", "index", "range", "dictionary"]);
+	if !resume {
+		bench_common::cleanup_dirs(&base, &layouts);
+		if let Some(wal_dir) = WAL_DIR.get() {
+			bench_common::cleanup_wal_dirs(wal_dir, &layouts);
+		}
+	}
 
 	let jobs: Vec<NamedJob<StoreError>> = vec![
 		{
 			let base = base.clone();
-			NamedJob::new("plain", Box::new(move || run_plain(&base, total, rocks_plain_factory)))
+			NamedJob::with_progress(
+				BenchLayout::Plain,
+				Box::new(move |board| run_plain(&base, length, board, rocks_plain_factory)),
+			)
 		},
 		{
 			let base = base.clone();
-			NamedJob::new("index", Box::new(move || run_index(&base, total, rocks_index_factory)))
+			NamedJob::with_progress(
+				BenchLayout::Index,
+				Box::new(move |board| run_index(&base, length, board, rocks_index_factory)),
+			)
 		},
 		{
 			let base = base.clone();
-			NamedJob::new("range", Box::new(move || run_range(&base, total, rocks_range_factory)))
+			NamedJob::with_progress(
+				BenchLayout::Range,
+				Box::new(move |board| run_range(&base, length, board, |p| rocks_range_factory(p, prefix_bloom))),
+			)
 		},
 		{
 			let base = base.clone();
-			NamedJob::new("dictionary", Box::new(move || run_dictionary(&base, total, rocks_dictionary_factory)))
+			NamedJob::with_progress(
+				BenchLayout::Dictionary,
+				Box::new(move |board| {
+					run_dictionary(&base, length, board, |p| rocks_dictionary_factory(p, prefix_bloom))
+				}),
+			)
 		},
 	];
 
-	run_all_parallel(jobs, benches.as_deref().unwrap_or(&[]))?;
+	run_all_parallel(jobs, &layouts)?;
+
+	// Reopen each store and settle it, so reads of every backend start from its read-optimized layout.
+	let settles: Vec<NamedJob<StoreError>> = vec![
+		{
+			let base = base.clone();
+			NamedJob::new(BenchLayout::Plain, Box::new(move || run_settle(&base, "plain", rocks_plain_factory)))
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(BenchLayout::Index, Box::new(move || run_settle(&base, "index", rocks_index_factory)))
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Range,
+				Box::new(move || run_settle(&base, "range", |p| rocks_range_factory(p, prefix_bloom))),
+			)
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Dictionary,
+				Box::new(move || run_settle(&base, "dictionary", |p| rocks_dictionary_factory(p, prefix_bloom))),
+			)
+		},
+	];
+
+	run_all_parallel(settles, &layouts)?;
+
+	// Time lookups of the settled plain and dictionary stores, keys drawn per `--distribution`.
+	let reads: Vec<NamedJob<StoreError>> = vec![
+		{
+			let base = base.clone();
+			NamedJob::new(BenchLayout::Plain, Box::new(move || run_reads(&base, length, read_load, rocks_plain_factory)))
+		},
+		{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Dictionary,
+				Box::new(move || run_dictionary_reads(&base, length, read_load, |p| rocks_dictionary_factory(p, prefix_bloom)).map(drop)),
+			)
+		},
+	];
+	run_all_parallel(reads, &layouts)?;
+
+	// Write a fresh plain store while reading it, per `--mixed <writers>:<readers>`.
+	if let Some(load) = mixed_load {
+		let mixed: Vec<NamedJob<StoreError>> = vec![{
+			let base = base.clone();
+			NamedJob::new(
+				BenchLayout::Plain,
+				Box::new(move || run_mixed(&base, length, load, rocks_plain_factory).map(drop)),
+			)
+		}];
+		run_all_parallel(mixed, &layouts)?;
+	}
 
 	Ok(())
 }
 
 fn rocks_plain_factory(path: &Path) -> StoreResult<Store<Key, Amount, RKeyCodec, RAmountCodec>> {
-	Store::open_with_options(path, Layout::plain(0), ())
+	StoreBuilder::new(path, Layout::plain(0)).configure(with_wal_dir(path)).open()
 }
 
 fn rocks_index_factory(path: &Path) -> StoreResult<Store<Key, TxHash, RKeyCodec, RTxCodec>> {
-	Store::open_with_options(path, Layout::unique_index(0), ())
+	StoreBuilder::new(path, Layout::unique_index(0)).configure(with_wal_dir(path)).open()
+}
+
+/// Range btree keys are prefixed by the encoded timestamp.
+fn rocks_range_factory(path: &Path, prefix_bloom: bool) -> StoreResult<Store<Key, Timestamp, RKeyCodec, RTimestampCodec>> {
+	let options = if prefix_bloom {
+		RocksOptions::with_btree_prefix_len(encoded_len::<Timestamp, RTimestampCodec>(&Timestamp(0)))
+	} else {
+		RocksOptions::default()
+	};
+	StoreBuilder::new(path, Layout::range(0)).options(options).configure(with_wal_dir(path)).open()
 }
 
-fn rocks_range_factory(path: &Path) -> StoreResult<Store<Key, Timestamp, RKeyCodec, RTimestampCodec>> {
-	Store::open_with_options(path, Layout::range(0), ())
+/// Dictionary btree keys are prefixed by the encoded birth key, not the variable-length address.
+fn rocks_dictionary_factory(path: &Path, prefix_bloom: bool) -> StoreResult<Store<Key, Address, RKeyCodec, RAddressCodec>> {
+	let options = if prefix_bloom {
+		RocksOptions::with_btree_prefix_len(encoded_len::<Key, RKeyCodec>(&Key(0)))
+	} else {
+		RocksOptions::default()
+	};
+	StoreBuilder::new(path, Layout::dictionary(0)).options(options).configure(with_wal_dir(path)).open()
 }
 
-fn rocks_dictionary_factory(path: &Path) -> StoreResult<Store<Key, Address, RKeyCodec, RAddressCodec>> {
-	Store::open_with_options(path, Layout::dictionary(0), ())
+/// Points the store at `path` to its own log directory under `--wal-dir`, if given.
+fn with_wal_dir(path: &Path) -> impl FnOnce(&mut RocksOptions) + '_ {
+	move |options| options.wal_dir = WAL_DIR.get().map(|base| store_wal_dir(base, path))
 }
@@ -1,6 +1,24 @@
-use core::store_interface::{ProgressTracker, StoreCodec, StoreRead, StoreWrite};
-use rocksdb::{Direction, IteratorMode, Options, WriteBatch, WriteOptions, DBWithThreadMode, MultiThreaded};
-use std::{marker::PhantomData, path::Path, sync::Arc};
+use core::dyn_store::BenchError;
+use core::inspect::KeyDump;
+use core::store_builder::BuilderOptions;
+use core::store_interface::{
+	CommitStats, ConsistencyReport, ExpiringStore, Mismatch, OpenMode, ProgressTracker, Retryable, StoreCodec,
+	StoreRead, StoreWrite, NEVER_EXPIRES, check_columns, check_expiring, check_indexed_value, check_layout,
+	coalesce_by_encoded_key, column_id, composite_key, composite_prefix, decode_exact, expiry_after, is_transient_io,
+	sort_by_encoding, split_composite, stored_value, unexpired, unix_millis,
+};
+use rocksdb::{
+	BlockBasedOptions, BottommostLevelCompaction, ColumnFamilyDescriptor, CompactOptions, CompactionDecision, Direction,
+	ErrorKind, IteratorMode, Options, ReadOptions, SliceTransform, WriteBatch, WriteOptions, DBWithThreadMode,
+	MultiThreaded,
+};
+use std::{
+	cmp::Ordering,
+	marker::PhantomData,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
 
 #[derive(Debug)]
 pub enum StoreError {
@@ -19,6 +37,22 @@ impl std::fmt::Display for StoreError {
 
 impl std::error::Error for StoreError {}
 
+/// RocksDB reports contention as `Busy` or `TryAgain`, and a lock wait that ran out as `TimedOut`.
+impl Retryable for StoreError {
+	fn is_retryable(&self) -> bool {
+		match self {
+			StoreError::Rocks(err) => matches!(err.kind(), ErrorKind::Busy | ErrorKind::TryAgain | ErrorKind::TimedOut),
+			StoreError::InvalidInput(_) => false,
+		}
+	}
+}
+
+impl From<StoreError> for BenchError {
+	fn from(err: StoreError) -> Self {
+		BenchError::store(err)
+	}
+}
+
 impl From<rocksdb::Error> for StoreError {
 	fn from(err: rocksdb::Error) -> Self {
 		StoreError::Rocks(err)
@@ -27,6 +61,118 @@ impl From<rocksdb::Error> for StoreError {
 
 pub type StoreResult<T> = Result<T, StoreError>;
 
+#[derive(Clone, Default)]
+pub struct RocksOptions {
+	/// Fixed prefix extractor on the btree column (`Range`: value, `Dictionary`: birth key) enabling
+	/// prefix bloom filters for `get_keys_for_value`. Must equal the length of that prefix in the
+	/// btree key, length varint included, so only fixed-width codecs qualify; see `encoded_len`.
+	pub btree_prefix_len: Option<usize>,
+	/// Skip writes a later one in the same commit overwrites; see `coalesce_by_encoded_key`.
+	pub coalesce_duplicates: bool,
+	/// Tuning of the columns read by point lookups, every column but the btree one.
+	pub point_lookup: ColumnConfig,
+	/// Tuning of the btree column read by range scans; its prefix comes from `btree_prefix_len`.
+	pub range_scan: ColumnConfig,
+	/// Order of the primary column, `key_to_value` or `key_to_birth_key`. The other columns are
+	/// keyed by values or composites and stay bytewise.
+	pub key_order: KeyOrder,
+	/// `Plain` only: keep each value behind its expiry for `ExpiringStore`, and let compaction drop
+	/// expired entries. RocksDB's own TTL columns fix one TTL per column at open, so they cannot
+	/// take a TTL per commit.
+	pub expiring: bool,
+	/// Directory of the write-ahead log, e.g. on a faster device; `None` keeps it in the store's.
+	pub wal_dir: Option<PathBuf>,
+	/// Write a large `commit` in `WriteBatch`es of about this many operations, bounding the memory
+	/// a batch holds. Each batch is atomic, the whole call is not: a failure leaves the earlier
+	/// batches written. `None`, the default, writes it as one batch.
+	pub commit_chunk_ops: Option<usize>,
+}
+
+impl RocksOptions {
+	pub fn with_btree_prefix_len(len: usize) -> Self {
+		Self { btree_prefix_len: Some(len), ..Self::default() }
+	}
+}
+
+impl BuilderOptions for RocksOptions {
+	fn set_coalesce_duplicates(&mut self, coalesce: bool) {
+		self.coalesce_duplicates = coalesce;
+	}
+
+	fn set_wal_dir(&mut self, dir: &Path) {
+		self.wal_dir = Some(dir.to_path_buf());
+	}
+}
+
+/// Table and memtable tuning of one column family; the default leaves RocksDB's own defaults.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ColumnConfig {
+	/// Fixed prefix extractor with prefix bloom filters in the SSTs and the memtable.
+	pub prefix_len: Option<usize>,
+	/// Bits per key of a whole-key bloom filter in the SSTs and the memtable.
+	pub bloom_bits_per_key: Option<f64>,
+	/// Data block size in bytes; larger blocks favour scans over point lookups.
+	pub block_size: Option<usize>,
+}
+
+/// Comparator of a column family. RocksDB records its name and refuses to open the column under
+/// another one, so a store must always be opened with the order it was created with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyOrder {
+	#[default]
+	Bytewise,
+	/// The first 8 bytes as a little-endian `u64`, so keys of a little-endian codec iterate in
+	/// numeric order; ties break on the head length, then bytewise on the rest.
+	LittleEndianU64,
+}
+
+impl KeyOrder {
+	pub fn compare(self, a: &[u8], b: &[u8]) -> Ordering {
+		match self {
+			KeyOrder::Bytewise => a.cmp(b),
+			KeyOrder::LittleEndianU64 => {
+				let (a_head, a_rest) = a.split_at(a.len().min(8));
+				let (b_head, b_rest) = b.split_at(b.len().min(8));
+				le_u64(a_head)
+					.cmp(&le_u64(b_head))
+					.then(a_head.len().cmp(&b_head.len()))
+					.then_with(|| a_rest.cmp(b_rest))
+			},
+		}
+	}
+
+	fn name(self) -> Option<&'static str> {
+		match self {
+			KeyOrder::Bytewise => None,
+			KeyOrder::LittleEndianU64 => Some("blockchain-benches.le-u64"),
+		}
+	}
+}
+
+/// `head` of at most 8 bytes, zero-extended.
+fn le_u64(head: &[u8]) -> u64 {
+	let mut bytes = [0u8; 8];
+	bytes[..head.len()].copy_from_slice(head);
+	u64::from_le_bytes(bytes)
+}
+
+impl ColumnConfig {
+	/// Whole-key bloom filters, so lookups of missing keys skip the SSTs.
+	pub fn point_lookup() -> Self {
+		Self { bloom_bits_per_key: Some(10.0), ..Self::default() }
+	}
+
+	/// Larger blocks, so scans read fewer and longer runs of each SST.
+	pub fn range_scan() -> Self {
+		Self { block_size: Some(64 * 1024), ..Self::default() }
+	}
+}
+
+/// Length `sample` takes as a btree key prefix, for deriving `btree_prefix_len` from a fixed-width codec.
+pub fn encoded_len<T, C: StoreCodec<T>>(sample: &T) -> usize {
+	composite_prefix(C::encode(sample).as_ref()).len()
+}
+
 #[derive(Clone, Copy)]
 pub enum Layout {
 	Plain { key_to_value: usize },
@@ -54,6 +200,17 @@ impl Layout {
 		}
 	}
 
+	/// Name the store records at creation, in a `LAYOUT_CF_PREFIX` column family, so a reopen with
+	/// another layout fails.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Layout::Plain { .. } => "plain",
+			Layout::UniqueIndex { .. } => "unique_index",
+			Layout::Range { .. } => "range",
+			Layout::Dictionary { .. } => "dictionary",
+		}
+	}
+
 	fn column_count(&self) -> usize {
 		match self {
 			Layout::Plain { .. } => 1,
@@ -62,6 +219,36 @@ impl Layout {
 			Layout::Dictionary { .. } => 4,
 		}
 	}
+
+	/// The column ids the layout uses, which name its `col<id>` column families.
+	fn columns(&self) -> Vec<usize> {
+		match *self {
+			Layout::Plain { key_to_value } => vec![key_to_value],
+			Layout::UniqueIndex { key_to_value, value_to_key } => vec![key_to_value, value_to_key],
+			Layout::Range { key_to_value, value_key_btree } => vec![key_to_value, value_key_btree],
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
+				vec![key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree]
+			}
+		}
+	}
+
+	/// The column holding every key: `key_to_value`, or `key_to_birth_key` for `Dictionary`.
+	fn primary_column(&self) -> usize {
+		match *self {
+			Layout::Plain { key_to_value }
+			| Layout::UniqueIndex { key_to_value, .. }
+			| Layout::Range { key_to_value, .. } => key_to_value,
+			Layout::Dictionary { key_to_birth_key, .. } => key_to_birth_key,
+		}
+	}
+
+	fn btree_column(&self) -> Option<usize> {
+		match self {
+			Layout::Range { value_key_btree, .. } => Some(*value_key_btree),
+			Layout::Dictionary { birth_key_key_btree, .. } => Some(*birth_key_key_btree),
+			_ => None,
+		}
+	}
 }
 
 pub struct Store<K, V, KC, VC>
@@ -71,11 +258,19 @@ where
 {
 	db: DBWithThreadMode<MultiThreaded>,
 	cf_names: Vec<String>,
+	cf_opts: Vec<Options>,
+	options: RocksOptions,
 	layout: Layout,
+	read_only: bool,
 	progress: Option<ProgressTracker>,
 	_ph: PhantomData<(K, V, KC, VC)>,
 }
 
+/// A `Store` shared across threads: every read takes `&self`, and the store is `Send + Sync` for
+/// `Send + Sync` key and value types. The database is opened `MultiThreaded`, so reads from several
+/// threads run in parallel; commits take `&mut self` and need a lock of the caller's.
+pub type SharedStore<K, V, KC, VC> = Arc<Store<K, V, KC, VC>>;
+
 impl<K, V, KC, VC> Store<K, V, KC, VC>
 where
 	KC: StoreCodec<K, Error = StoreError>,
@@ -85,15 +280,15 @@ where
 		let name = &self.cf_names[idx];
 		self.db
 			.cf_handle(name)
-			.map(|cf| cf.clone())
 			.ok_or_else(|| StoreError::InvalidInput(format!("missing column family {name}")))
 	}
 
 	pub fn open(path: &Path, layout: Layout) -> StoreResult<Self> {
-		Self::open_with_options(path, layout, ())
+		Self::open_with_options(path, layout, RocksOptions::default())
 	}
 
-	pub fn open_with_options(path: &Path, layout: Layout, _options: ()) -> StoreResult<Self> {
+	pub fn open_with_options(path: &Path, layout: Layout, options: RocksOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
 		let mut opts = Options::default();
 		// Keep the per-DB file descriptor footprint in check so we don't hit OS ulimit
 		// when many SSTs are present during the benchmark.
@@ -101,9 +296,57 @@ where
 		opts.create_if_missing(true);
 		opts.create_missing_column_families(true);
 		let cf_names: Vec<String> = (0..layout.column_count()).map(|i| format!("col{i}")).collect();
-		let cf_strs: Vec<&str> = cf_names.iter().map(|s| s.as_str()).collect();
-		let db = DBWithThreadMode::<MultiThreaded>::open_cf(&opts, path, cf_strs.clone())?;
-		Ok(Self { db, cf_names: cf_names.into_iter().collect(), layout, progress: None, _ph: PhantomData })
+		if let Some(dir) = &options.wal_dir {
+			opts.set_wal_dir(dir);
+		}
+		check_stored_layout(path, &opts, layout)?;
+		let cf_opts = cf_options(&opts, layout, &options);
+		let descriptors = cf_names
+			.iter()
+			.zip(&cf_opts)
+			.map(|(name, o)| ColumnFamilyDescriptor::new(name, o.clone()))
+			.chain([ColumnFamilyDescriptor::new(layout_cf(layout), Options::default())]);
+		let db = DBWithThreadMode::<MultiThreaded>::open_cf_descriptors(&opts, path, descriptors)?;
+		Ok(Self { db, cf_names, cf_opts, options, layout, read_only: false, progress: None, _ph: PhantomData })
+	}
+
+	/// `open_with_options` after checking `mode` against whether `path` already holds a store.
+	pub fn open_with_mode(path: &Path, layout: Layout, options: RocksOptions, mode: OpenMode) -> StoreResult<Self> {
+		mode.check(path, store_exists(path)).map_err(StoreError::InvalidInput)?;
+		Self::open_with_options(path, layout, options)
+	}
+
+	pub fn open_read_only(path: &Path, layout: Layout) -> StoreResult<Self> {
+		Self::open_read_only_with_options(path, layout, RocksOptions::default())
+	}
+
+	/// Needed for a store created with a non-default `key_order`, which RocksDB checks on open, or
+	/// with expiring entries.
+	pub fn open_read_only_with_options(path: &Path, layout: Layout, options: RocksOptions) -> StoreResult<Self> {
+		check_expiring(options.expiring, matches!(layout, Layout::Plain { .. })).map_err(StoreError::InvalidInput)?;
+		let mut opts = Options::default();
+		opts.set_max_open_files(2048);
+		let cf_names: Vec<String> = (0..layout.column_count()).map(|i| format!("col{i}")).collect();
+		if let Some(dir) = &options.wal_dir {
+			opts.set_wal_dir(dir);
+		}
+		// A store from before the layout was recorded has no column family for it.
+		let tagged = check_stored_layout(path, &opts, layout)?;
+		let cf_opts = cf_options(&opts, layout, &options);
+		let descriptors = cf_names
+			.iter()
+			.zip(&cf_opts)
+			.map(|(name, o)| ColumnFamilyDescriptor::new(name, o.clone()))
+			.chain(tagged.then(|| ColumnFamilyDescriptor::new(layout_cf(layout), Options::default())));
+		let db = DBWithThreadMode::<MultiThreaded>::open_cf_descriptors_read_only(&opts, path, descriptors, false)?;
+		Ok(Self { db, cf_names, cf_opts, options, layout, read_only: true, progress: None, _ph: PhantomData })
+	}
+
+	fn ensure_writable(&self) -> StoreResult<()> {
+		if self.read_only {
+			return Err(StoreError::InvalidInput("store is opened read-only".into()))
+		}
+		Ok(())
 	}
 
 	pub fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
@@ -112,7 +355,59 @@ where
 		K: 'a,
 		V: 'a,
 	{
-		let mut processed = 0u64;
+		self.commit_counted(items).map(|_| ())
+	}
+
+	/// With `coalesce_duplicates` set, a write the batch overwrites later is skipped.
+	pub fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.commit_expiring(items, self.options.expiring.then_some(NEVER_EXPIRES))
+	}
+
+	/// `commit` of `Plain` entries that `get_value` stops returning once `ttl` has passed.
+	pub fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.ensure_expiring()?;
+		self.commit_expiring(items, Some(expiry_after(ttl))).map(|_| ())
+	}
+
+	/// `expires_at` is the expiry of every entry, `None` for a store without expiring entries.
+	fn commit_expiring<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		if self.options.coalesce_duplicates {
+			self.write_counted(coalesce_by_encoded_key::<K, V, KC, _>(items), expires_at)
+		} else {
+			self.write_counted(items, expires_at)
+		}
+	}
+
+	fn ensure_expiring(&self) -> StoreResult<()> {
+		if !self.options.expiring {
+			return Err(StoreError::InvalidInput("store is not opened with expiring entries".into()))
+		}
+		Ok(())
+	}
+
+	fn write_counted<'a, I>(&mut self, items: I, expires_at: Option<u64>) -> StoreResult<CommitStats>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		self.ensure_writable()?;
+		let mut stats = CommitStats::default();
 		let mut batch = WriteBatch::default();
 		let opts = WriteOptions::default();
 		match self.layout {
@@ -121,8 +416,10 @@ where
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					batch.put_cf(&cf, kbytes.as_ref(), vbytes.as_ref());
-					processed += 1;
+					let stored = stored_value(vbytes.as_ref(), expires_at);
+					batch.put_cf(&cf, kbytes.as_ref(), &stored);
+					stats.record(kbytes.as_ref(), &stored);
+					self.write_full_chunk(&mut batch, &opts)?;
 				}
 			},
 			Layout::UniqueIndex { key_to_value, value_to_key } => {
@@ -131,9 +428,12 @@ where
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
 					batch.put_cf(&cf_k2v, kbytes.as_ref(), vbytes.as_ref());
 					batch.put_cf(&cf_v2k, vbytes.as_ref(), kbytes.as_ref());
-					processed += 2;
+					stats.record(kbytes.as_ref(), vbytes.as_ref());
+					stats.record(vbytes.as_ref(), kbytes.as_ref());
+					self.write_full_chunk(&mut batch, &opts)?;
 				}
 			},
 			Layout::Range { key_to_value, value_key_btree } => {
@@ -144,9 +444,11 @@ where
 					let vbytes = VC::encode(v);
 					let kslice = kbytes.as_ref();
 					batch.put_cf(&cf_k2v, kslice, vbytes.as_ref());
-					let vk = concat(vbytes.as_ref(), kslice);
-					batch.put_cf(&cf_vkb, vk.as_slice(), &[]);
-					processed += 2;
+					let vk = composite_key(vbytes.as_ref(), kslice);
+					batch.put_cf(&cf_vkb, vk.as_slice(), []);
+					stats.record(kbytes.as_ref(), vbytes.as_ref());
+					stats.record(&vk, &[]);
+					self.write_full_chunk(&mut batch, &opts)?;
 				}
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
@@ -155,55 +457,85 @@ where
 				let cf_pk2v = self.cf(birth_key_to_value)?;
 				let cf_v2pk = self.cf(value_to_birth_key)?;
 				let cf_pk_k = self.cf(birth_key_key_btree)?;
-				let mut cache: HashMap<Vec<u8>, (Vec<u8>, bool)> = HashMap::new();
+				let mut cache: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
 				for (k, v) in items {
 					let kbytes = KC::encode(k);
 					let vbytes = VC::encode(v);
-					let (pk, is_new) = if let Some(entry) = cache.get(vbytes.as_ref()) {
-						entry.clone()
+					check_indexed_value(vbytes.as_ref()).map_err(StoreError::InvalidInput)?;
+					let (pk, is_new) = if let Some(pk) = cache.get(vbytes.as_ref()) {
+						(pk.clone(), false)
 					} else if let Some(pk) = self.db.get_cf(&cf_v2pk, vbytes.as_ref())? {
 						let pk_vec = pk.to_vec();
-						cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), false));
+						cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, false)
 					} else {
 						let pk_vec = kbytes.as_ref().to_vec();
-						cache.insert(vbytes.as_ref().to_vec(), (pk_vec.clone(), true));
+						cache.insert(vbytes.as_ref().to_vec(), pk_vec.clone());
 						(pk_vec, true)
 					};
 
 					if is_new {
 						batch.put_cf(&cf_v2pk, vbytes.as_ref(), pk.as_slice());
 						batch.put_cf(&cf_pk2v, pk.as_slice(), vbytes.as_ref());
-						processed += 2;
+						stats.record(vbytes.as_ref(), &pk);
+						stats.record(&pk, vbytes.as_ref());
 					}
 					batch.put_cf(&cf_k2pk, kbytes.as_ref(), pk.as_slice());
-					let pk_key = concat(&pk, kbytes.as_ref());
-					batch.put_cf(&cf_pk_k, pk_key.as_slice(), &[]);
-					processed += 2;
+					let pk_key = composite_key(&pk, kbytes.as_ref());
+					batch.put_cf(&cf_pk_k, pk_key.as_slice(), []);
+					stats.record(kbytes.as_ref(), &pk);
+					stats.record(&pk_key, &[]);
+					self.write_full_chunk(&mut batch, &opts)?;
 				}
 			},
 		}
-		self.db.write_opt(batch, &opts)?;
+		if !batch.is_empty() {
+			self.db.write_opt(batch, &opts)?;
+		}
 		if let Some(p) = self.progress.as_mut() {
-			p.record(processed);
+			p.record(stats.rows);
+		}
+		Ok(stats)
+	}
+
+	/// Writes `batch` once it reaches `RocksOptions::commit_chunk_ops`, between two items so an
+	/// item's operations always land together.
+	fn write_full_chunk(&self, batch: &mut WriteBatch, opts: &WriteOptions) -> StoreResult<()> {
+		if self.options.commit_chunk_ops.is_some_and(|ops| batch.len() >= ops) {
+			self.db.write_opt(std::mem::take(batch), opts)?;
 		}
 		Ok(())
 	}
 
 	pub fn get_value(&self, key: &K) -> StoreResult<Option<V>> {
+		self.read_value(key, VC::decode)
+	}
+
+	/// `key`'s value as stored, `VC::encode` of what `get_value` returns, without decoding it.
+	pub fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		self.read_value(key, |bytes| Ok(bytes.to_vec()))
+	}
+
+	/// Passes the encoded value of `key`, stripped of its expiry, to `read`.
+	fn read_value<T>(&self, key: &K, read: impl FnOnce(&[u8]) -> StoreResult<T>) -> StoreResult<Option<T>> {
 		let kbytes = KC::encode(key);
 		match self.layout {
+			Layout::Plain { key_to_value } if self.options.expiring => {
+				let Some(stored) = self.db.get_pinned_cf(&self.cf(key_to_value)?, kbytes.as_ref())? else { return Ok(None) };
+				let value = unexpired(&stored, unix_millis()).map_err(StoreError::InvalidInput)?;
+				value.map(read).transpose()
+			},
 			Layout::Plain { key_to_value }
 			| Layout::UniqueIndex { key_to_value, .. }
 			| Layout::Range { key_to_value, .. } => {
 				let cf = self.cf(key_to_value)?;
-				self.db.get_cf(&cf, kbytes.as_ref())?.map(|v| VC::decode(&v)).transpose()
+				self.db.get_cf(&cf, kbytes.as_ref())?.map(|v| read(&v)).transpose()
 			},
 			Layout::Dictionary { key_to_birth_key, birth_key_to_value, .. } => {
 				let cf_k2pk = self.cf(key_to_birth_key)?;
 				let cf_pk2v = self.cf(birth_key_to_value)?;
 				if let Some(pk) = self.db.get_cf(&cf_k2pk, kbytes.as_ref())? {
-					self.db.get_cf(&cf_pk2v, &pk)?.map(|v| VC::decode(&v)).transpose()
+					self.db.get_cf(&cf_pk2v, &pk)?.map(|v| read(&v)).transpose()
 				} else {
 					Ok(None)
 				}
@@ -211,13 +543,24 @@ where
 		}
 	}
 
+	/// What the store holds for `key`: its encoding and that of its value, printing as hex dumps,
+	/// for a lookup that returns something unexpected.
+	pub fn debug_dump(&self, key: &K) -> StoreResult<KeyDump> {
+		let value = self.get_value(key)?;
+		Ok(KeyDump::new::<K, V, KC, VC>(key, value.as_ref()))
+	}
+
 	pub fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
 		let vbytes = VC::encode(value);
 		match self.layout {
 			Layout::UniqueIndex { value_to_key, .. } => {
 				let cf = self.cf(value_to_key)?;
-				self.db.get_cf(&cf, vbytes.as_ref())?.map(|k| KC::decode(&k)).transpose()
+				self.db
+					.get_cf(&cf, vbytes.as_ref())?
+					.map(|k| decode_exact::<K, KC>(&k, StoreError::InvalidInput))
+					.transpose()
 			},
+			Layout::Range { .. } | Layout::Dictionary { .. } => Ok(self.get_keys_for_value(value)?.into_iter().next()),
 			_ => Err(StoreError::InvalidInput("get_key_for_value not supported for this layout".into())),
 		}
 	}
@@ -228,15 +571,17 @@ where
 			Layout::Range { value_key_btree, .. } => {
 				let cf = self.cf(value_key_btree)?;
 				let mut out = Vec::new();
-				let mut iter = self
-					.db
-					.iterator_cf(&cf, IteratorMode::From(vbytes.as_ref(), Direction::Forward));
+				let prefix = composite_prefix(vbytes.as_ref());
+				let mut iter = self.db.iterator_cf_opt(
+					&cf,
+					self.prefix_read_options(),
+					IteratorMode::From(&prefix, Direction::Forward),
+				);
 				while let Some(Ok((k, _))) = iter.next() {
-					if !k.starts_with(vbytes.as_ref()) {
-						break
+					match split_composite(&k) {
+						Some((head, key_bytes)) if head == vbytes.as_ref() => out.push(KC::decode(key_bytes)?),
+						_ => break,
 					}
-					let key_bytes = &k[vbytes.as_ref().len()..];
-					out.push(KC::decode(key_bytes)?);
 				}
 				Ok(out)
 			},
@@ -245,13 +590,17 @@ where
 				let cf_pk_k = self.cf(birth_key_key_btree)?;
 				if let Some(pk) = self.db.get_cf(&cf_v2pk, vbytes.as_ref())? {
 					let mut out = Vec::new();
-					let mut iter = self.db.iterator_cf(&cf_pk_k, IteratorMode::From(pk.as_ref(), Direction::Forward));
+					let prefix = composite_prefix(&pk);
+					let mut iter = self.db.iterator_cf_opt(
+						&cf_pk_k,
+						self.prefix_read_options(),
+						IteratorMode::From(&prefix, Direction::Forward),
+					);
 					while let Some(Ok((k, _))) = iter.next() {
-						if !k.starts_with(pk.as_ref()) {
-							break
+						match split_composite(&k) {
+							Some((head, key_bytes)) if head == pk.as_slice() => out.push(KC::decode(key_bytes)?),
+							_ => break,
 						}
-						let key_bytes = &k[pk.len()..];
-						out.push(KC::decode(key_bytes)?);
 					}
 					Ok(out)
 				} else {
@@ -262,68 +611,349 @@ where
 		}
 	}
 
+	/// One snapshot and one raw btree iterator for the whole batch. Seeks run in head order, so the
+	/// iterator moves forward through the btree; `Dictionary` resolves birth keys in value order first.
+	pub fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		let snapshot = self.db.snapshot();
+		let (btree, heads) = match self.layout {
+			Layout::Range { value_key_btree, .. } => (value_key_btree, sort_by_encoding::<V, VC>(values)),
+			Layout::Dictionary { value_to_birth_key, birth_key_key_btree, .. } => {
+				let cf_v2pk = self.cf(value_to_birth_key)?;
+				let mut pks = Vec::with_capacity(values.len());
+				for (idx, vbytes) in sort_by_encoding::<V, VC>(values) {
+					if let Some(pk) = snapshot.get_cf(&cf_v2pk, &vbytes)? {
+						pks.push((idx, pk));
+					}
+				}
+				pks.sort_by(|a, b| a.1.cmp(&b.1));
+				(birth_key_key_btree, pks)
+			},
+			_ => return Err(StoreError::InvalidInput("get_keys_for_values not supported for this layout".into())),
+		};
+		let cf = self.cf(btree)?;
+		let mut iter = snapshot.raw_iterator_cf_opt(&cf, self.prefix_read_options());
+		let mut out: Vec<Vec<K>> = values.iter().map(|_| Vec::new()).collect();
+		for (idx, head) in heads {
+			iter.seek(composite_prefix(&head));
+			while let Some(k) = iter.key() {
+				match split_composite(k) {
+					Some((h, key_bytes)) if h == head.as_slice() => out[idx].push(KC::decode(key_bytes)?),
+					_ => break,
+				}
+				iter.next();
+			}
+			iter.status()?;
+		}
+		Ok(out)
+	}
+
+	pub fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		match self.layout {
+			Layout::Dictionary { birth_key_to_value, .. } => {
+				let cf = self.cf(birth_key_to_value)?;
+				Ok(self.db.iterator_cf(&cf, IteratorMode::Start).map(|kv| VC::decode(&kv?.1)))
+			},
+			_ => Err(StoreError::InvalidInput("distinct_values not supported for this layout".into())),
+		}
+	}
+
+	/// The first key in the primary column's `key_order`.
+	pub fn first_key(&self) -> StoreResult<Option<K>> {
+		self.edge_key(IteratorMode::Start)
+	}
+
+	/// The last key in the primary column's `key_order`.
+	pub fn last_key(&self) -> StoreResult<Option<K>> {
+		self.edge_key(IteratorMode::End)
+	}
+
+	/// RocksDB's `estimate-num-keys` of the primary column family, which counts overwrites and
+	/// deletes not yet compacted away.
+	pub fn approx_key_count(&self) -> StoreResult<u64> {
+		let cf = self.cf(self.layout.primary_column())?;
+		Ok(self.db.property_int_value_cf(&cf, "rocksdb.estimate-num-keys")?.unwrap_or(0))
+	}
+
+	/// The first key a cursor over the primary column yields from `mode`.
+	fn edge_key(&self, mode: IteratorMode<'_>) -> StoreResult<Option<K>> {
+		match self.db.iterator_cf(&self.cf(self.layout.primary_column())?, mode).next() {
+			Some(kv) => KC::decode(&kv?.0).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	pub fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		let mut report = ConsistencyReport::default();
+		match self.layout {
+			Layout::Plain { .. } => {},
+			Layout::UniqueIndex { key_to_value, value_to_key } => {
+				let cf_v2k = self.cf(value_to_key)?;
+				for kv in self.db.iterator_cf(&self.cf(key_to_value)?, IteratorMode::Start) {
+					let (k, v) = kv?;
+					report.checked += 1;
+					if self.db.get_cf(&cf_v2k, &v)?.as_deref() != Some(k.as_ref()) {
+						report.mismatches.push(Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() });
+					}
+				}
+			},
+			Layout::Range { key_to_value, value_key_btree } => {
+				let cf_vkb = self.cf(value_key_btree)?;
+				for kv in self.db.iterator_cf(&self.cf(key_to_value)?, IteratorMode::Start) {
+					let (k, v) = kv?;
+					report.checked += 1;
+					if self.db.get_cf(&cf_vkb, composite_key(&v, &k))?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: v.to_vec(), key: k.to_vec() });
+					}
+				}
+			},
+			Layout::Dictionary { key_to_birth_key, birth_key_to_value, birth_key_key_btree, .. } => {
+				let cf_pk2v = self.cf(birth_key_to_value)?;
+				let cf_pk_k = self.cf(birth_key_key_btree)?;
+				for kv in self.db.iterator_cf(&self.cf(key_to_birth_key)?, IteratorMode::Start) {
+					let (k, pk) = kv?;
+					report.checked += 1;
+					if self.db.get_cf(&cf_pk2v, &pk)?.is_none() {
+						report.mismatches.push(Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: pk.to_vec() });
+					}
+					if self.db.get_cf(&cf_pk_k, composite_key(&pk, &k))?.is_none() {
+						report.mismatches.push(Mismatch::MissingBtreeEntry { head: pk.to_vec(), key: k.to_vec() });
+					}
+				}
+			},
+		}
+		Ok(report)
+	}
+
+	/// Bounds btree scans to the seek prefix so RocksDB can consult the prefix bloom filters.
+	fn prefix_read_options(&self) -> ReadOptions {
+		let mut read_opts = ReadOptions::default();
+		if self.options.btree_prefix_len.is_some() {
+			read_opts.set_prefix_same_as_start(true);
+		}
+		read_opts
+	}
+
 	pub fn flush(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
 		self.db.flush()?;
 		Ok(())
 	}
-}
-
-fn concat(a: &[u8], b: &[u8]) -> Vec<u8> {
-	let mut out = Vec::with_capacity(a.len() + b.len());
-	out.extend_from_slice(a);
-	out.extend_from_slice(b);
-	out
-}
 
-#[cfg(test)]
-mod tests {
-	use super::*;
-	use core::store_tests::{basic_value_roundtrip, multiple_keys_for_value, reverse_lookup_unique};
-	use tempfile::tempdir;
+	/// Flushes each column family and compacts its whole key range down to the bottommost level,
+	/// so reads no longer probe level-0 files or overlapping runs.
+	pub fn settle_for_reads(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		let mut compact = CompactOptions::default();
+		compact.set_bottommost_level_compaction(BottommostLevelCompaction::Force);
+		for idx in 0..self.cf_names.len() {
+			let cf = self.cf(idx)?;
+			self.db.flush_cf(&cf)?;
+			self.db.compact_range_cf_opt(&cf, None::<&[u8]>, None::<&[u8]>, &compact);
+		}
+		Ok(())
+	}
 
-	struct BytesCodec;
+	/// Deletes the expired entries of an expiring `Plain` store in one batch; compaction drops the
+	/// ones it reaches first.
+	pub fn purge_expired(&mut self) -> StoreResult<u64> {
+		self.ensure_writable()?;
+		self.ensure_expiring()?;
+		let cf = self.cf(self.layout.primary_column())?;
+		let now = unix_millis();
+		let mut batch = WriteBatch::default();
+		for kv in self.db.iterator_cf(&cf, IteratorMode::Start) {
+			let (k, stored) = kv?;
+			if matches!(unexpired(&stored, now), Ok(None)) {
+				batch.delete_cf(&cf, k);
+			}
+		}
+		let purged = batch.len() as u64;
+		self.db.write(batch)?;
+		Ok(purged)
+	}
 
-	impl StoreCodec<Vec<u8>> for BytesCodec {
-		type Error = StoreError;
-		type Enc<'a> = &'a [u8] where Self: 'a, Vec<u8>: 'a;
-		fn encode<'a>(value: &'a Vec<u8>) -> Self::Enc<'a> {
-			value.as_slice()
+	/// Removes `keys` with their entries in the layout's other columns, returning how many were
+	/// present; each key's deletes land in one batch. A `Dictionary` birth key's
+	/// `birth_key_to_value` and `value_to_birth_key` entries go with the last key holding it, found
+	/// by scanning its `birth_key||key` prefix in `birth_key_key_btree`; entries there of keys since
+	/// overwritten with another value are stale and dropped on the way.
+	pub fn delete<'a, I>(&mut self, keys: I) -> StoreResult<u64>
+	where
+		I: IntoIterator<Item = &'a K>,
+		K: 'a,
+	{
+		self.ensure_writable()?;
+		let mut deleted = 0;
+		let opts = WriteOptions::default();
+		for k in keys {
+			let kbytes = KC::encode(k);
+			let kslice = kbytes.as_ref();
+			let mut batch = WriteBatch::default();
+			match self.layout {
+				Layout::Plain { key_to_value } => {
+					let cf = self.cf(key_to_value)?;
+					if self.db.get_pinned_cf(&cf, kslice)?.is_none() {
+						continue
+					}
+					batch.delete_cf(&cf, kslice);
+				},
+				Layout::UniqueIndex { key_to_value, value_to_key } => {
+					let cf_k2v = self.cf(key_to_value)?;
+					let cf_v2k = self.cf(value_to_key)?;
+					let Some(v) = self.db.get_cf(&cf_k2v, kslice)? else { continue };
+					batch.delete_cf(&cf_k2v, kslice);
+					// The value may have moved to another key since.
+					if self.db.get_cf(&cf_v2k, &v)?.is_some_and(|owner| owner == kslice) {
+						batch.delete_cf(&cf_v2k, &v);
+					}
+				},
+				Layout::Range { key_to_value, value_key_btree } => {
+					let cf_k2v = self.cf(key_to_value)?;
+					let cf_vkb = self.cf(value_key_btree)?;
+					let Some(v) = self.db.get_cf(&cf_k2v, kslice)? else { continue };
+					batch.delete_cf(&cf_k2v, kslice);
+					batch.delete_cf(&cf_vkb, composite_key(&v, kslice));
+				},
+				Layout::Dictionary { key_to_birth_key, birth_key_to_value, value_to_birth_key, birth_key_key_btree } => {
+					let cf_k2pk = self.cf(key_to_birth_key)?;
+					let cf_pk2v = self.cf(birth_key_to_value)?;
+					let cf_v2pk = self.cf(value_to_birth_key)?;
+					let cf_pk_k = self.cf(birth_key_key_btree)?;
+					let Some(pk) = self.db.get_cf(&cf_k2pk, kslice)? else { continue };
+					batch.delete_cf(&cf_k2pk, kslice);
+					let mut live = false;
+					let prefix = composite_prefix(&pk);
+					let iter = self.db.iterator_cf_opt(
+						&cf_pk_k,
+						self.prefix_read_options(),
+						IteratorMode::From(&prefix, Direction::Forward),
+					);
+					for entry in iter {
+						let (entry, _) = entry?;
+						let key = match split_composite(&entry) {
+							Some((head, key)) if head == pk.as_slice() => key,
+							_ => break,
+						};
+						if key != kslice && self.db.get_cf(&cf_k2pk, key)?.is_some_and(|owner| owner == pk) {
+							live = true;
+						} else {
+							batch.delete_cf(&cf_pk_k, &entry);
+						}
+					}
+					if !live && let Some(v) = self.db.get_cf(&cf_pk2v, &pk)? {
+						batch.delete_cf(&cf_pk2v, &pk);
+						if self.db.get_cf(&cf_v2pk, &v)?.is_some_and(|owner| owner == pk) {
+							batch.delete_cf(&cf_v2pk, &v);
+						}
+					}
+				},
+			}
+			self.db.write_opt(batch, &opts)?;
+			deleted += 1;
 		}
-		fn decode(bytes: &[u8]) -> StoreResult<Vec<u8>> {
-			Ok(bytes.to_vec())
+		Ok(deleted)
+	}
+
+	/// Drops and recreates every column family; cheaper than a full-range delete and leaves no tombstones.
+	pub fn clear(&mut self) -> StoreResult<()> {
+		self.ensure_writable()?;
+		for (name, opts) in self.cf_names.iter().zip(&self.cf_opts) {
+			self.db.drop_cf(name)?;
+			self.db.create_cf(name, opts)?;
 		}
+		Ok(())
 	}
+}
 
-	#[test]
-	fn shared_basic_suite() {
-		basic_value_roundtrip(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::plain(0), ()).unwrap()
-		});
+/// One config per column family in column order: the btree column gets `range_scan` with the
+/// `btree_prefix_len` extractor, every other column `point_lookup`.
+fn column_configs(layout: Layout, options: &RocksOptions) -> Vec<ColumnConfig> {
+	(0..layout.column_count())
+		.map(|idx| {
+			if layout.btree_column() == Some(idx) {
+				ColumnConfig { prefix_len: options.btree_prefix_len, ..options.range_scan }
+			} else {
+				options.point_lookup
+			}
+		})
+		.collect()
+}
+
+/// Options of each column family of `layout` in column order, `key_order` and, for expiring
+/// entries, the expiry filter on the primary one.
+fn cf_options(base: &Options, layout: Layout, options: &RocksOptions) -> Vec<Options> {
+	let mut cf_opts: Vec<Options> = column_configs(layout, options).iter().map(|c| column_options(base, c)).collect();
+	if let Some(name) = options.key_order.name() {
+		let order = options.key_order;
+		cf_opts[layout.primary_column()].set_comparator(name, Box::new(move |a: &[u8], b: &[u8]| order.compare(a, b)));
+	}
+	if options.expiring {
+		cf_opts[layout.primary_column()].set_compaction_filter("blockchain-benches.expiry", drop_expired);
 	}
+	cf_opts
+}
 
-	#[test]
-	fn shared_reverse_suite() {
-		reverse_lookup_unique(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::unique_index(0), ())
-				.unwrap()
-		});
+/// Compaction filter of expiring entries; a value too short to hold an expiry is kept for
+/// `get_value` to report.
+fn drop_expired(_level: u32, _key: &[u8], stored: &[u8]) -> CompactionDecision {
+	match unexpired(stored, unix_millis()) {
+		Ok(None) => CompactionDecision::Remove,
+		_ => CompactionDecision::Keep,
 	}
+}
 
-	#[test]
-	fn shared_multiple_keys_suite() {
-		multiple_keys_for_value(|| {
-			let dir = tempdir().unwrap();
-			let path = dir.path().to_path_buf();
-			std::mem::forget(dir);
-			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(&path, Layout::range(0), ()).unwrap()
-		});
+fn column_options(base: &Options, config: &ColumnConfig) -> Options {
+	let mut opts = base.clone();
+	if *config == ColumnConfig::default() {
+		return opts
+	}
+	let mut table = BlockBasedOptions::default();
+	if let Some(len) = config.prefix_len {
+		opts.set_prefix_extractor(SliceTransform::create_fixed_prefix(len));
+		opts.set_memtable_prefix_bloom_ratio(0.1);
+		table.set_bloom_filter(10.0, false);
+		table.set_whole_key_filtering(false);
+	}
+	if let Some(bits) = config.bloom_bits_per_key {
+		opts.set_memtable_prefix_bloom_ratio(0.1);
+		opts.set_memtable_whole_key_filtering(true);
+		table.set_bloom_filter(bits, false);
+		table.set_whole_key_filtering(true);
+	}
+	if let Some(size) = config.block_size {
+		table.set_block_size(size);
+	}
+	opts.set_block_based_table_factory(&table);
+	opts
+}
+
+/// Prefix of the empty column family whose name records the store's layout, e.g. `layout:range`;
+/// reading a name needs no open, which already fails on column families it does not list.
+const LAYOUT_CF_PREFIX: &str = "layout:";
+
+fn layout_cf(layout: Layout) -> String {
+	format!("{LAYOUT_CF_PREFIX}{}", layout.name())
+}
+
+/// Checks `layout` against the one the store at `path` records, returning whether it records
+/// one. A store from before the layout was recorded must have the layout's columns and no others.
+fn check_stored_layout(path: &Path, opts: &Options, layout: Layout) -> StoreResult<bool> {
+	if !store_exists(path) {
+		return Ok(false)
 	}
+	let names = DBWithThreadMode::<MultiThreaded>::list_cf(opts, path)?;
+	if let Some(stored) = names.iter().find_map(|name| name.strip_prefix(LAYOUT_CF_PREFIX)) {
+		check_layout(stored, layout.name()).map_err(StoreError::InvalidInput)?;
+		return Ok(true)
+	}
+	let columns = names.iter().filter_map(|name| column_id(name));
+	check_columns(columns, layout.name(), layout.columns()).map_err(StoreError::InvalidInput)?;
+	Ok(false)
+}
+
+/// Whether `path` holds a store; RocksDB keeps the name of the live manifest in `CURRENT`.
+fn store_exists(path: &Path) -> bool {
+	path.join("CURRENT").is_file()
 }
 
 impl<K, V, KC, VC> StoreRead<K, V> for Store<K, V, KC, VC>
@@ -337,6 +967,10 @@ where
 		Store::get_value(self, key)
 	}
 
+	fn get_value_raw(&self, key: &K) -> StoreResult<Option<Vec<u8>>> {
+		Store::get_value_raw(self, key)
+	}
+
 	fn get_key_for_value(&self, value: &V) -> StoreResult<Option<K>> {
 		Store::get_key_for_value(self, value)
 	}
@@ -344,6 +978,30 @@ where
 	fn get_keys_for_value(&self, value: &V) -> StoreResult<Vec<K>> {
 		Store::get_keys_for_value(self, value)
 	}
+
+	fn get_keys_for_values(&self, values: &[V]) -> StoreResult<Vec<Vec<K>>> {
+		Store::get_keys_for_values(self, values)
+	}
+
+	fn distinct_values(&self) -> StoreResult<impl Iterator<Item = StoreResult<V>>> {
+		Store::distinct_values(self)
+	}
+
+	fn verify_consistency(&self) -> StoreResult<ConsistencyReport> {
+		Store::verify_consistency(self)
+	}
+
+	fn first_key(&self) -> StoreResult<Option<K>> {
+		Store::first_key(self)
+	}
+
+	fn last_key(&self) -> StoreResult<Option<K>> {
+		Store::last_key(self)
+	}
+
+	fn approx_key_count(&self) -> StoreResult<u64> {
+		Store::approx_key_count(self)
+	}
 }
 
 impl<K, V, KC, VC> StoreWrite<K, V> for Store<K, V, KC, VC>
@@ -351,27 +1009,520 @@ where
 	KC: StoreCodec<K, Error = StoreError>,
 	VC: StoreCodec<V, Error = StoreError>,
 {
-	type Options = ();
+	type Options = RocksOptions;
 	type Layout = Layout;
 
 	fn open_with_options(path: &Path, layout: Self::Layout, options: Self::Options) -> StoreResult<Self> {
 		Store::open_with_options(path, layout, options)
 	}
 
-	fn commit<'a, I>(&mut self, items: I) -> StoreResult<()>
+	fn open_with_mode(path: &Path, layout: Self::Layout, options: Self::Options, mode: OpenMode) -> StoreResult<Self> {
+		Store::open_with_mode(path, layout, options, mode)
+	}
+
+	fn open_read_only(path: &Path, layout: Self::Layout) -> StoreResult<Self> {
+		Store::open_read_only(path, layout)
+	}
+
+	fn commit_counted<'a, I>(&mut self, items: I) -> StoreResult<CommitStats>
 	where
 		I: IntoIterator<Item = (&'a K, &'a V)>,
 		K: 'a,
 		V: 'a,
 	{
-		Store::commit(self, items)
+		Store::commit_counted(self, items)
 	}
 
 	fn flush(&mut self) -> StoreResult<()> {
 		Store::flush(self)
 	}
 
-	fn set_progress(&mut self, label: &str, total: u64) {
-		self.progress = Some(ProgressTracker::new(label.to_string(), total));
+	fn settle_for_reads(&mut self) -> StoreResult<()> {
+		Store::settle_for_reads(self)
+	}
+
+	fn clear(&mut self) -> StoreResult<()> {
+		Store::clear(self)
+	}
+
+	fn set_progress_tracker(&mut self, tracker: ProgressTracker) {
+		self.progress = Some(tracker);
+	}
+}
+
+impl<K, V, KC, VC> ExpiringStore<K, V> for Store<K, V, KC, VC>
+where
+	KC: StoreCodec<K, Error = StoreError>,
+	VC: StoreCodec<V, Error = StoreError>,
+{
+	fn commit_with_ttl<'a, I>(&mut self, items: I, ttl: Duration) -> StoreResult<()>
+	where
+		I: IntoIterator<Item = (&'a K, &'a V)>,
+		K: 'a,
+		V: 'a,
+	{
+		Store::commit_with_ttl(self, items, ttl)
+	}
+
+	fn purge_expired(&mut self) -> StoreResult<u64> {
+		Store::purge_expired(self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::store_tests::{
+		approximate_key_count, assert_send_sync, basic_value_roundtrip, batched_keys_for_values, builder_paths,
+		clear_then_reuse, coalesced_duplicate_keys, consistency_audit, dictionary_birth_key_stable,
+		dictionary_commit_stats, dictionary_delete, dictionary_distinct_values, empty_values, expiring_entries,
+		key_bounds, mismatched_layout, multiple_keys_for_value, open_modes, prefix_values_isolated, raw_values,
+		read_only_rejects_writes, reverse_lookup_unique, shared_readers, separate_wal_dir, settle_keeps_contents,
+		variable_length_keys_roundtrip,
+	};
+	use core::store_builder::StoreBuilder;
+	use tempfile::tempdir;
+
+	struct BytesCodec;
+
+	impl StoreCodec<Vec<u8>> for BytesCodec {
+		type Error = StoreError;
+		type Enc<'a> = &'a [u8] where Self: 'a, Vec<u8>: 'a;
+		fn encode<'a>(value: &'a Vec<u8>) -> Self::Enc<'a> {
+			value.as_slice()
+		}
+		fn decode(bytes: &[u8]) -> StoreResult<Vec<u8>> {
+			Ok(bytes.to_vec())
+		}
+	}
+
+	#[test]
+	fn shared_basic_suite() {
+		basic_value_roundtrip(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), RocksOptions::default()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_variable_length_keys_suite() {
+		variable_length_keys_roundtrip(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), RocksOptions::default()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_clear_suite() {
+		clear_then_reuse(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), RocksOptions::default()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_read_only_suite() {
+		let dir = tempdir().unwrap();
+		read_only_rejects_writes(
+			|| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(dir.path(), Layout::plain(0), RocksOptions::default())
+					.unwrap()
+			},
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::plain(0)).unwrap(),
+		);
+	}
+
+	#[test]
+	fn shared_dictionary_birth_key_suite() {
+		dictionary_birth_key_stable(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), RocksOptions::default()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_dictionary_commit_stats_suite() {
+		dictionary_commit_stats(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::dictionary(0), RocksOptions::default()).unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_reverse_suite() {
+		reverse_lookup_unique(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::unique_index(0), RocksOptions::default())
+				.unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_multiple_keys_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			multiple_keys_for_value(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, RocksOptions::default()).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_batched_keys_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			batched_keys_for_values(|path| {
+				let options = RocksOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_readers_suite() {
+		assert_send_sync::<SharedStore<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>();
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			shared_readers(|path| {
+				let options = RocksOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn raw_values_suite() {
+		for layout in [Layout::plain(0), Layout::range(0), Layout::dictionary(0)] {
+			raw_values::<BytesCodec, _, _>(|path| {
+				let options = RocksOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_prefix_values_suite() {
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			prefix_values_isolated(|path| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, RocksOptions::default()).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_coalesce_suite() {
+		let open = |path: &Path, layout, coalesce_duplicates| {
+			let options = RocksOptions { coalesce_duplicates, ..RocksOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+		};
+		coalesced_duplicate_keys(
+			|path, c| open(path, Layout::plain(0), c),
+			|path, c| open(path, Layout::unique_index(0), c),
+		);
+	}
+
+	#[test]
+	fn shared_settle_suite() {
+		settle_keeps_contents(|path| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, Layout::plain(0), RocksOptions::default())
+				.unwrap()
+		});
+	}
+
+	#[test]
+	fn shared_key_bounds_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			key_bounds(|path| {
+				let options = RocksOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_approximate_key_count_suite() {
+		for layout in [Layout::plain(0), Layout::dictionary(0)] {
+			approximate_key_count(|path| {
+				let options = RocksOptions::default();
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options).unwrap()
+			});
+		}
+	}
+
+	#[test]
+	fn shared_expiring_suite() {
+		let open = |path: &Path, layout| {
+			let options = RocksOptions { expiring: true, ..RocksOptions::default() };
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, options)
+		};
+		expiring_entries(|path| open(path, Layout::plain(0)).unwrap());
+		assert!(open(tempdir().unwrap().path(), Layout::range(0)).is_err());
+	}
+
+	#[test]
+	fn compaction_drops_expired_entries() {
+		type BytesStore = Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>;
+		let dir = tempdir().unwrap();
+		let options = RocksOptions { expiring: true, ..RocksOptions::default() };
+		let items: Vec<(Vec<u8>, Vec<u8>)> = (0..10u32).map(|i| (i.to_be_bytes().to_vec(), vec![i as u8; 8])).collect();
+		{
+			let mut store = BytesStore::open_with_options(dir.path(), Layout::plain(0), options.clone()).unwrap();
+			store.commit_with_ttl(items[..6].iter().map(|(k, v)| (k, v)), Duration::ZERO).unwrap();
+			store.commit(items[6..].iter().map(|(k, v)| (k, v))).unwrap();
+			store.settle_for_reads().unwrap();
+			assert_eq!(store.purge_expired().unwrap(), 0, "compaction already dropped them");
+		}
+		let store = BytesStore::open_read_only_with_options(dir.path(), Layout::plain(0), options).unwrap();
+		let cf = store.cf(0).unwrap();
+		assert_eq!(store.db.iterator_cf(&cf, IteratorMode::Start).count(), 4);
+		assert_eq!(store.get_value(&items[7].0).unwrap(), Some(items[7].1.clone()));
+	}
+
+	#[test]
+	fn shared_distinct_values_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, RocksOptions::default())
+				.unwrap()
+		};
+		dictionary_distinct_values(|path| open(path, Layout::dictionary(0)), |path| open(path, Layout::range(0)));
+	}
+
+	#[test]
+	fn shared_dictionary_delete_suite() {
+		let dir = tempdir().unwrap();
+		dictionary_delete(
+			|| {
+				Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(
+					dir.path(),
+					Layout::dictionary(0),
+					RocksOptions::default(),
+				)
+				.unwrap()
+			},
+			|store, keys| store.delete(keys),
+			|store, value| {
+				store.db.get_cf(&store.cf(2).unwrap(), value).unwrap().is_some()
+					|| store
+						.db
+						.iterator_cf(&store.cf(1).unwrap(), IteratorMode::Start)
+						.any(|entry| entry.unwrap().1.as_ref() == value)
+			},
+		);
+	}
+
+	#[test]
+	fn shared_empty_values_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, RocksOptions::default())
+				.unwrap()
+		};
+		empty_values(
+			|path| open(path, Layout::plain(0)),
+			|path| open(path, Layout::unique_index(0)),
+			|path| open(path, Layout::range(0)),
+			|path| open(path, Layout::dictionary(0)),
+		);
+	}
+
+	#[test]
+	fn shared_consistency_audit_suite() {
+		let open = |path: &Path, layout| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_options(path, layout, RocksOptions::default())
+				.unwrap()
+		};
+		consistency_audit(|path| open(path, Layout::unique_index(0)), |store, k, v| {
+			store.db.put_cf(&store.cf(1).unwrap(), v, b"elsewhere").unwrap();
+			vec![Mismatch::MissingBackEdge { key: k.to_vec(), value: v.to_vec() }]
+		});
+		consistency_audit(|path| open(path, Layout::range(0)), |store, k, v| {
+			store.db.delete_cf(&store.cf(1).unwrap(), composite_key(v, k)).unwrap();
+			vec![Mismatch::MissingBtreeEntry { head: v.to_vec(), key: k.to_vec() }]
+		});
+		// Every row has its own value, so each key is its own birth key.
+		consistency_audit(|path| open(path, Layout::dictionary(0)), |store, k, _| {
+			store.db.delete_cf(&store.cf(1).unwrap(), k).unwrap();
+			store.db.delete_cf(&store.cf(3).unwrap(), composite_key(k, k)).unwrap();
+			vec![
+				Mismatch::DanglingBirthKey { key: k.to_vec(), birth_key: k.to_vec() },
+				Mismatch::MissingBtreeEntry { head: k.to_vec(), key: k.to_vec() },
+			]
+		});
+	}
+
+	#[test]
+	fn shared_open_modes_suite() {
+		let dir = tempdir().unwrap();
+		open_modes(dir.path(), |path, mode| {
+			Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_with_mode(
+				path,
+				Layout::plain(0),
+				RocksOptions::default(),
+				mode,
+			)
+		});
+	}
+
+	#[test]
+	fn shared_separate_wal_dir_suite() {
+		let dir = tempdir().unwrap();
+		separate_wal_dir(dir.path(), |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain(0))
+		});
+		let logs = |sub: &str| {
+			std::fs::read_dir(dir.path().join(sub))
+				.unwrap()
+				.filter(|entry| entry.as_ref().unwrap().path().extension() == Some("log".as_ref()))
+				.count()
+		};
+		assert!(logs("wal") > 0);
+		assert_eq!(logs("data"), 0);
+	}
+
+	#[test]
+	fn shared_builder_paths_suite() {
+		let dir = tempdir().unwrap();
+		builder_paths(dir.path(), "store.rocksdb", |path| {
+			StoreBuilder::<_, _, Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>>::new(path, Layout::plain(0))
+				.coalesce_duplicates(true)
+		});
+	}
+
+	#[test]
+	fn prefix_extractor_keeps_reverse_lookups() {
+		type BytesStore = Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>;
+		let values: Vec<Vec<u8>> = (0..16u64).map(|v| v.to_be_bytes().to_vec()).collect();
+		let items: Vec<(Vec<u8>, Vec<u8>)> =
+			(0..400u64).map(|k| (k.to_be_bytes().to_vec(), values[(k % 16) as usize].clone())).collect();
+		for layout in [Layout::range(0), Layout::dictionary(0)] {
+			let plain_dir = tempdir().unwrap();
+			let prefix_dir = tempdir().unwrap();
+			let mut plain = BytesStore::open_with_options(plain_dir.path(), layout, RocksOptions::default()).unwrap();
+			let options = RocksOptions::with_btree_prefix_len(encoded_len::<_, BytesCodec>(&values[0]));
+			let mut prefixed = BytesStore::open_with_options(prefix_dir.path(), layout, options).unwrap();
+			for chunk in items.chunks(100) {
+				plain.commit(chunk.iter().map(|(k, v)| (k, v))).unwrap();
+				prefixed.commit(chunk.iter().map(|(k, v)| (k, v))).unwrap();
+				prefixed.flush().unwrap();
+			}
+			for v in &values {
+				let expected = plain.get_keys_for_value(v).unwrap();
+				assert_eq!(expected.len(), 25);
+				assert_eq!(prefixed.get_keys_for_value(v).unwrap(), expected);
+			}
+			assert!(prefixed.get_keys_for_value(&99u64.to_be_bytes().to_vec()).unwrap().is_empty());
+		}
+	}
+
+	#[test]
+	fn little_endian_order_compares_numerically() {
+		let order = KeyOrder::LittleEndianU64;
+		let mut keys: Vec<Vec<u8>> =
+			[256u64, 1, u64::MAX, 255, 65_536, 0].iter().map(|k| k.to_le_bytes().to_vec()).collect();
+		keys.push([&7u64.to_le_bytes()[..], b"suffix"].concat());
+		keys.push(vec![7]);
+		keys.sort_by(|a, b| order.compare(a, b));
+		let expected: Vec<Vec<u8>> = vec![
+			0u64.to_le_bytes().to_vec(),
+			1u64.to_le_bytes().to_vec(),
+			vec![7],
+			[&7u64.to_le_bytes()[..], b"suffix"].concat(),
+			255u64.to_le_bytes().to_vec(),
+			256u64.to_le_bytes().to_vec(),
+			65_536u64.to_le_bytes().to_vec(),
+			u64::MAX.to_le_bytes().to_vec(),
+		];
+		assert_eq!(keys, expected);
+		assert_eq!(KeyOrder::Bytewise.compare(&[1, 0], &[0, 1]), Ordering::Greater);
+	}
+
+	#[test]
+	fn key_order_iterates_the_primary_column_numerically() {
+		type BytesStore = Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>;
+		let dir = tempdir().unwrap();
+		let options = RocksOptions { key_order: KeyOrder::LittleEndianU64, ..RocksOptions::default() };
+		let ids = [300u64, 2, 70_000, 1, 256, 255];
+		{
+			let mut store = BytesStore::open_with_options(dir.path(), Layout::plain(0), options.clone()).unwrap();
+			for (i, id) in ids.iter().enumerate() {
+				let (k, v) = (id.to_le_bytes().to_vec(), id.to_be_bytes().to_vec());
+				store.commit([(&k, &v)]).unwrap();
+				if i % 2 == 1 {
+					store.flush().unwrap();
+				}
+			}
+			assert_eq!(store.first_key().unwrap(), Some(1u64.to_le_bytes().to_vec()));
+			assert_eq!(store.last_key().unwrap(), Some(70_000u64.to_le_bytes().to_vec()));
+		}
+		let store = BytesStore::open_read_only_with_options(dir.path(), Layout::plain(0), options).unwrap();
+		let cf = store.cf(0).unwrap();
+		let scanned: Vec<u64> = store
+			.db
+			.iterator_cf(&cf, IteratorMode::Start)
+			.map(|kv| u64::from_le_bytes(kv.unwrap().0.as_ref().try_into().unwrap()))
+			.collect();
+		let mut sorted = ids.to_vec();
+		sorted.sort();
+		assert_eq!(scanned, sorted);
+		assert_eq!(store.get_value(&256u64.to_le_bytes().to_vec()).unwrap(), Some(256u64.to_be_bytes().to_vec()));
+	}
+
+	#[test]
+	fn differentiated_column_configs_match_defaults_across_layouts() {
+		type BytesStore = Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>;
+		let values: Vec<Vec<u8>> = (0..16u64).map(|v| v.to_be_bytes().to_vec()).collect();
+		let items: Vec<(Vec<u8>, Vec<u8>)> =
+			(0..400u64).map(|k| (k.to_be_bytes().to_vec(), values[(k % 16) as usize].clone())).collect();
+		for layout in [Layout::plain(0), Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)] {
+			let default_dir = tempdir().unwrap();
+			let tuned_dir = tempdir().unwrap();
+			let mut default =
+				BytesStore::open_with_options(default_dir.path(), layout, RocksOptions::default()).unwrap();
+			let options = RocksOptions {
+				btree_prefix_len: layout.btree_column().map(|_| encoded_len::<_, BytesCodec>(&values[0])),
+				point_lookup: ColumnConfig::point_lookup(),
+				range_scan: ColumnConfig::range_scan(),
+				..RocksOptions::default()
+			};
+			let configs = column_configs(layout, &options);
+			assert_eq!(configs.len(), layout.column_count());
+			for (idx, config) in configs.iter().enumerate() {
+				assert_eq!(config.block_size.is_some(), layout.btree_column() == Some(idx));
+				assert_eq!(config.bloom_bits_per_key.is_some(), layout.btree_column() != Some(idx));
+			}
+			let mut tuned = BytesStore::open_with_options(tuned_dir.path(), layout, options).unwrap();
+			for chunk in items.chunks(100) {
+				default.commit(chunk.iter().map(|(k, v)| (k, v))).unwrap();
+				tuned.commit(chunk.iter().map(|(k, v)| (k, v))).unwrap();
+				tuned.flush().unwrap();
+			}
+			for (k, v) in &items {
+				assert_eq!(tuned.get_value(k).unwrap(), Some(v.clone()));
+			}
+			assert_eq!(tuned.get_value(&999u64.to_be_bytes().to_vec()).unwrap(), None);
+			for v in &values {
+				assert_eq!(tuned.get_key_for_value(v).ok(), default.get_key_for_value(v).ok());
+				assert_eq!(tuned.get_keys_for_value(v).ok(), default.get_keys_for_value(v).ok());
+			}
+		}
+	}
+
+	#[test]
+	fn shared_mismatched_layout_suite() {
+		let dir = tempdir().unwrap();
+		mismatched_layout(
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::dictionary(0)).unwrap(),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open(dir.path(), Layout::plain(0)),
+			|| Store::<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>::open_read_only(dir.path(), Layout::range(0)),
+		);
+	}
+
+	#[test]
+	fn chunked_commit_stores_every_row() {
+		type BytesStore = Store<Vec<u8>, Vec<u8>, BytesCodec, BytesCodec>;
+		let items: Vec<(Vec<u8>, Vec<u8>)> =
+			(0..1_000u64).map(|k| (k.to_be_bytes().to_vec(), (k % 10).to_be_bytes().to_vec())).collect();
+		for layout in [Layout::plain(0), Layout::unique_index(0), Layout::range(0), Layout::dictionary(0)] {
+			let dir = tempdir().unwrap();
+			let options = RocksOptions { commit_chunk_ops: Some(7), ..RocksOptions::default() };
+			let mut store = BytesStore::open_with_options(dir.path(), layout, options).unwrap();
+			store.commit(items.iter().map(|(k, v)| (k, v))).unwrap();
+			for (k, v) in &items {
+				assert_eq!(store.get_value(k).unwrap(), Some(v.clone()));
+			}
+			if matches!(layout, Layout::Range { .. } | Layout::Dictionary { .. }) {
+				assert_eq!(store.get_keys_for_value(&3u64.to_be_bytes().to_vec()).unwrap().len(), 100);
+			}
+		}
 	}
 }